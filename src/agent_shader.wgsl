@@ -0,0 +1,70 @@
+// Agents, drawn as camera facing capsules standing on the ground.
+
+//
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) //
+var<uniform> camera: CameraUniform;
+
+struct AgentUniform {
+    // xyz: the camera's right, flat, so the agents stay upright.
+    right: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> params: AgentUniform;
+
+struct AgentInput {
+    // feet of the agent.
+    @location(0) position: vec3<f32>,
+    @location(1) radius: f32,
+    @location(2) color: vec3<f32>,
+    @location(3) height: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    // x: -radius..radius, y: 0..height, in world units.
+    @location(1) local: vec2<f32>,
+    // x: radius, y: height.
+    @location(2) size: vec2<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    agent: AgentInput,
+) -> VertexOutput {
+    // two triangles per agent, counter clockwise.
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let local = vec2<f32>(corner.x * agent.radius, corner.y * agent.height);
+    let world = agent.position + params.right.xyz * local.x + vec3<f32>(0.0, local.y, 0.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world, 1.0);
+    out.color = agent.color;
+    out.local = local;
+    out.size = vec2<f32>(agent.radius, agent.height);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // distance to the line through the middle of the capsule, its ends are round.
+    let radius = in.size.x;
+    let middle = clamp(in.local.y, radius, max(in.size.y - radius, radius));
+    let distance = length(in.local - vec2<f32>(0.0, middle)) / max(radius, 0.0001);
+    if (distance > 1.0) {
+        discard;
+    }
+    // a bit of roundness, darker towards the sides.
+    let shade = 0.55 + 0.45 * sqrt(1.0 - distance * distance);
+    return vec4<f32>(in.color * shade, 1.0);
+}
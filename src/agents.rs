@@ -0,0 +1,491 @@
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::navmesh::NavMesh;
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+use crate::texture::Texture;
+
+/*
+    Agents walking around on a navmesh, to try out gameplay ideas on
+    the loaded terrain.
+
+    Every agent either wanders (walks to a random reachable spot
+    nearby, waits a moment, picks the next one) or walks to a goal
+    and stays there. The ways are found with NavMesh::find_path().
+
+    The agents are moved in fixed steps of FIXED_STEP seconds, however
+    long the frames take: update() runs as many steps as the frame's time
+    step (PluginContext::time_step) holds, so they walk the same ways at
+    30 and at 144 fps, stand still while the application pauses and walk
+    the recorded ways again in a replay. A long hitch only runs up to
+    MAX_STEPS, the agents are slowed down instead of the frame taking
+    even longer.
+
+    There is no entity system in the renderer, the agents are a plain
+    list owned by the plugin.
+
+    They are drawn as upright capsules facing the camera, all of them
+    in one instanced draw call (agent_shader.wgsl), depth tested against
+    the scene when the frame shares its depth buffer (FrameTargets::depth),
+    otherwise on top of it.
+
+    usage:
+        let navmesh = NavMesh::build(renderer.models(), &NavSettings::default(), |_, _| true);
+        let mut agents = Agents::new(navmesh);
+        agents.add(Agent::new([4.0, 0.0, 2.0], Behavior::Wander));
+        renderer.add_plugin(agents);
+        ...
+        renderer.plugin_mut::<Agents>().unwrap().send_to(0, [10.0, 0.0, 3.0]);
+*/
+
+// seconds of one simulation step.
+pub const FIXED_STEP: f32 = 1.0 / 30.0;
+// steps per frame at most.
+const MAX_STEPS: u32 = 8;
+// how far a wandering agent looks for its next spot, and how often it tries.
+const WANDER_RADIUS: f32 = 12.0;
+const WANDER_TRIES: usize = 8;
+// seconds a wandering agent waits at a spot.
+const WANDER_WAIT: f32 = 1.5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Behavior {
+    Wander,
+    // walks to the point and stays there.
+    Goal([f32; 3]),
+    Idle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Agent {
+    // feet, on the ground.
+    pub position: [f32; 3],
+    pub behavior: Behavior,
+    // units per second.
+    pub speed: f32,
+    pub radius: f32,
+    pub height: f32,
+    pub color: [f32; 3],
+    // what's left of the way, the next point first.
+    path: Vec<[f32; 3]>,
+    // seconds until a wandering agent moves on.
+    wait: f32,
+}
+
+impl Agent {
+    pub fn new(position: [f32; 3], behavior: Behavior) -> Self {
+        Self {
+            position,
+            behavior,
+            speed: 2.0,
+            radius: 0.3,
+            height: 1.8,
+            color: [0.9, 0.5, 0.2],
+            path: Vec::new(),
+            wait: 0.0,
+        }
+    }
+
+    // the rest of the way the agent is walking.
+    pub fn path(&self) -> &[[f32; 3]] {
+        &self.path
+    }
+}
+
+// per agent, instance step mode, see agent_shader.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct AgentInstance {
+    position: [f32; 3],
+    radius: f32,
+    color: [f32; 3],
+    height: f32,
+}
+
+impl AgentInstance {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<AgentInstance>() as wgpu::BufferAddress,
+            // the quad corners come from the vertex index.
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3, // position.
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32, // radius.
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3, // color.
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32, // height.
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct AgentUniform {
+    right: [f32; 4],
+}
+
+struct GpuState {
+    pipeline: wgpu::RenderPipeline,
+    // the same, depth tested, for frames with FrameTargets::depth.
+    depth_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    // agents the instance buffer has room for.
+    capacity: usize,
+}
+
+pub struct Agents {
+    navmesh: NavMesh,
+    agents: Vec<Agent>,
+    // simulated time not yet stepped through.
+    accumulator: f32,
+    // xorshift state, for the wandering.
+    seed: u32,
+    gpu: Option<GpuState>,
+}
+
+impl Agents {
+    pub fn new(navmesh: NavMesh) -> Self {
+        Self {
+            navmesh,
+            agents: Vec::new(),
+            accumulator: 0.0,
+            seed: 0x9e37_79b9,
+            gpu: None,
+        }
+    }
+
+    pub fn navmesh(&self) -> &NavMesh {
+        &self.navmesh
+    }
+
+    // e.g. after the terrain changed. The agents look for new ways.
+    pub fn set_navmesh(&mut self, navmesh: NavMesh) {
+        self.navmesh = navmesh;
+        for agent in &mut self.agents {
+            agent.path.clear();
+        }
+    }
+
+    // returns the index of the agent.
+    pub fn add(&mut self, agent: Agent) -> usize {
+        self.agents.push(agent);
+        self.agents.len() - 1
+    }
+
+    // `count` wandering agents on random walkable spots, in random colors.
+    // Returns how many were added, none without walkable ground.
+    pub fn spawn(&mut self, count: usize) -> usize {
+        let points = self.navmesh.walkable_points();
+        if points.is_empty() {
+            return 0;
+        }
+        for _ in 0..count {
+            let point = points[((self.random() * points.len() as f32) as usize).min(points.len() - 1)];
+            let mut agent = Agent::new(point, Behavior::Wander);
+            agent.color = [0.3 + self.random() * 0.7, 0.3 + self.random() * 0.7, 0.3 + self.random() * 0.7];
+            agent.speed = 1.5 + self.random();
+            self.add(agent);
+        }
+        count
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Agent> {
+        if index < self.agents.len() {
+            Some(self.agents.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.agents.clear();
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    // sends the agent at `index` to `goal`. False when there's no way there.
+    pub fn send_to(&mut self, index: usize, goal: [f32; 3]) -> bool {
+        let agent = match self.agents.get_mut(index) {
+            Some(agent) => agent,
+            None => return false,
+        };
+        match self.navmesh.find_path(agent.position, goal) {
+            Some(path) => {
+                agent.behavior = Behavior::Goal(goal);
+                agent.path = path;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // moves every agent by one FIXED_STEP.
+    pub fn step(&mut self) {
+        for index in 0..self.agents.len() {
+            if self.agents[index].path.is_empty() {
+                self.plan(index);
+            }
+            let navmesh = &self.navmesh;
+            let agent = &mut self.agents[index];
+            let mut distance = agent.speed * FIXED_STEP;
+            while distance > 0.0 && !agent.path.is_empty() {
+                let position = Vector3::from(agent.position);
+                let next = Vector3::from(agent.path[0]);
+                let to_next = next - position;
+                let length = to_next.magnitude();
+                if length <= distance {
+                    agent.position = next.into();
+                    agent.path.remove(0);
+                    distance -= length;
+                } else {
+                    agent.position = (position + to_next * (distance / length)).into();
+                    distance = 0.0;
+                }
+            }
+            // stays on the ground between the path points.
+            if let Some(height) = navmesh.height_at(agent.position[0], agent.position[2]) {
+                agent.position[1] = height;
+            }
+        }
+    }
+
+    // the next way of an agent that arrived.
+    fn plan(&mut self, index: usize) {
+        match self.agents[index].behavior {
+            Behavior::Wander => {
+                if self.agents[index].wait > 0.0 {
+                    self.agents[index].wait -= FIXED_STEP;
+                    return;
+                }
+                let position = self.agents[index].position;
+                for _ in 0..WANDER_TRIES {
+                    let angle = self.random() * std::f32::consts::TAU;
+                    let distance = self.random() * WANDER_RADIUS;
+                    let x = position[0] + angle.cos() * distance;
+                    let z = position[2] + angle.sin() * distance;
+                    let goal = match self.navmesh.height_at(x, z) {
+                        Some(height) => [x, height, z],
+                        None => continue,
+                    };
+                    if let Some(path) = self.navmesh.find_path(position, goal) {
+                        let wait = WANDER_WAIT * (0.5 + self.random());
+                        let agent = &mut self.agents[index];
+                        agent.path = path;
+                        agent.wait = wait;
+                        return;
+                    }
+                }
+            }
+            // arrived, or the goal can't be reached.
+            Behavior::Goal(_) => self.agents[index].behavior = Behavior::Idle,
+            Behavior::Idle => {}
+        }
+    }
+
+    // 0..1
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn instances(&self) -> Vec<AgentInstance> {
+        self.agents.iter()
+            .map(|agent| AgentInstance {
+                position: agent.position,
+                radius: agent.radius,
+                color: agent.color,
+                height: agent.height,
+            })
+            .collect()
+    }
+}
+
+impl RenderPlugin for Agents {
+    fn name(&self) -> &str {
+        "Agents"
+    }
+
+    fn setup(&mut self, ctx: &PluginContext) {
+        let device = ctx.device;
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Agent Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[AgentUniform { right: [1.0, 0.0, 0.0, 0.0] }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("agent_uniform_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("agent_uniform_bind_group"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Agent Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("agent_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Agent Pipeline Layout"),
+            bind_group_layouts: &[ctx.camera_bind_group_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        // plugins draw into the resolved surface texture -> no msaa.
+        let pipeline = crate::renderer::create_render_pipeline(
+            device,
+            &layout,
+            ctx.config.format,
+            1,
+            None,
+            &[AgentInstance::desc()],
+            &shader,
+        );
+        let depth_pipeline = crate::renderer::create_render_pipeline(
+            device,
+            &layout,
+            ctx.config.format,
+            1,
+            Some(Texture::DEPTH_FORMAT),
+            &[AgentInstance::desc()],
+            &shader,
+        );
+
+        let capacity = self.agents.len().max(64);
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Agent Instance Buffer"),
+            size: (capacity * std::mem::size_of::<AgentInstance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        self.gpu = Some(GpuState { pipeline, depth_pipeline, uniform_buffer, uniform_bind_group, instance_buffer, capacity });
+    }
+
+    fn update(&mut self, ctx: &PluginContext) {
+        self.accumulator += ctx.time_step;
+
+        let mut steps = 0;
+        while self.accumulator >= FIXED_STEP && steps < MAX_STEPS {
+            self.step();
+            self.accumulator -= FIXED_STEP;
+            steps += 1;
+        }
+        // the time that didn't fit is dropped.
+        if steps == MAX_STEPS {
+            self.accumulator = 0.0;
+        }
+
+        let instances = self.instances();
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        if instances.len() > gpu.capacity {
+            gpu.capacity = instances.len().next_power_of_two();
+            gpu.instance_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Agent Instance Buffer"),
+                size: (gpu.capacity * std::mem::size_of::<AgentInstance>()) as u64,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        if !instances.is_empty() {
+            ctx.queue.write_buffer(&gpu.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+
+        // the capsules turn with the camera, but stay upright.
+        let forward = ctx.camera.target() - ctx.camera.eye();
+        let right = forward.cross(Vector3::unit_y());
+        let right = if right.magnitude2() > f32::EPSILON { right.normalize() } else { Vector3::unit_x() };
+        ctx.queue.write_buffer(
+            &gpu.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[AgentUniform { right: [right.x, right.y, right.z, 0.0] }]),
+        );
+    }
+
+    fn encode(
+        &mut self,
+        _ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    ) {
+        let gpu = match &self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        if self.agents.is_empty() {
+            return;
+        }
+
+        // behind walls the capsules are hidden, where the scene's depth is there.
+        let (pipeline, depth_stencil_attachment) = match targets.depth {
+            Some(depth) => (
+                &gpu.depth_pipeline,
+                Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            ),
+            None => (&gpu.pipeline, None),
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Agent Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: targets.color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, targets.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &gpu.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, gpu.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.agents.len() as u32);
+    }
+}
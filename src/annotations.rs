@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+
+use crate::overlay::Overlay;
+use crate::picking::{self, Ray};
+use crate::renderer::Renderer;
+
+/*
+    Annotations: labels pinned to points in the scene, e.g. notes on a
+    model in a review.
+
+    Every annotation is a marker and a text in the overlay, anchored to
+    its world position, so they move with the camera and keep their size
+    in pixels. They are hidden while the point is behind the camera or
+    off screen, and while something is in front of it.
+
+    The scene's depth buffer is only on the gpu, and not shared at all
+    with msaa, dynamic resolution or stereo (see FrameTargets::depth),
+    so whether something covers an annotation is found out with picking:
+    a ray from the eye to the point, if it hits a visible mesh clearly
+    before the point, the point is covered. The rays are only cast again when the
+    camera moved or the annotations changed (and every RECHECK_FRAMES
+    frames, for meshes that moved on their own).
+
+    usage:
+        let mut annotations = Annotations::new();
+        let id = annotations.add([1.0, 2.0, 0.0], "check this joint");
+        // or where the cursor points:
+        annotations.add_at_cursor(&mut renderer, x, y, "too dark");
+
+        // every frame:
+        annotations.update(&renderer);
+        annotations.draw(renderer.plugin_mut::<Overlay>().unwrap());
+*/
+
+// the overlay layer the annotations are drawn into.
+pub const OVERLAY_LAYER: &str = "annotations";
+pub const DEFAULT_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+// in pixels.
+const MARKER_SIZE: f32 = 8.0;
+// a hit this close in front of the point (as a part of its distance from
+// the eye) is the surface the annotation sits on, not something covering it.
+const OCCLUSION_BIAS: f32 = 0.01;
+const RECHECK_FRAMES: u32 = 30;
+// how far from its marker, in pixels, pick() still finds an annotation.
+const PICK_RADIUS: f32 = 12.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub position: [f32; 3],
+    pub text: String,
+    pub color: [f32; 4],
+}
+
+struct Entry {
+    annotation: Annotation,
+    visible: bool,
+}
+
+pub struct Annotations {
+    entries: BTreeMap<usize, Entry>,
+    next_id: usize,
+    occlusion: bool,
+    // what the visibility was last checked with.
+    view_proj: Option<Matrix4<f32>>,
+    size: (u32, u32),
+    // frames since the last check.
+    frames: u32,
+    dirty: bool,
+}
+
+impl Default for Annotations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Annotations {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            next_id: 0,
+            occlusion: true,
+            view_proj: None,
+            size: (0, 0),
+            frames: 0,
+            dirty: true,
+        }
+    }
+
+    // the id stays the same while the annotation exists.
+    pub fn add(&mut self, position: [f32; 3], text: &str) -> usize {
+        self.insert(Annotation { position, text: text.to_string(), color: DEFAULT_COLOR })
+    }
+
+    pub fn insert(&mut self, annotation: Annotation) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(id, Entry { annotation, visible: false });
+        self.dirty = true;
+        id
+    }
+
+    // an annotation on the mesh under the window position `x`/`y`, None when there is none.
+    pub fn add_at_cursor(&mut self, renderer: &mut Renderer, x: f32, y: f32, text: &str) -> Option<usize> {
+        let hit = renderer.pick(x, y)?;
+        Some(self.add(hit.position, text))
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Annotation> {
+        self.dirty = true;
+        self.entries.remove(&id).map(|entry| entry.annotation)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = true;
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Annotation> {
+        self.entries.get(&id).map(|entry| &entry.annotation)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Annotation> {
+        self.dirty = true;
+        self.entries.get_mut(&id).map(|entry| &mut entry.annotation)
+    }
+
+    // in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Annotation)> {
+        self.entries.iter().map(|(id, entry)| (*id, &entry.annotation))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // whether the annotation was on screen and uncovered at the last update().
+    pub fn visible(&self, id: usize) -> bool {
+        self.entries.get(&id).is_some_and(|entry| entry.visible)
+    }
+
+    // off, annotations behind meshes stay visible (and no rays are cast).
+    pub fn set_occlusion(&mut self, occlusion: bool) {
+        self.occlusion = occlusion;
+        self.dirty = true;
+    }
+
+    pub fn occlusion(&self) -> bool {
+        self.occlusion
+    }
+
+    // checks which annotations can be seen from the current camera.
+    pub fn update(&mut self, renderer: &Renderer) {
+        let camera = renderer.camera();
+        let view_proj = camera.build_view_projection_matrix();
+        let size = renderer.size();
+        let size = (size.width, size.height);
+        self.frames += 1;
+        if !self.dirty && self.view_proj == Some(view_proj) && self.size == size && self.frames < RECHECK_FRAMES {
+            return;
+        }
+        self.view_proj = Some(view_proj);
+        self.size = size;
+        self.frames = 0;
+        self.dirty = false;
+
+        let eye = camera.eye();
+        let eye = Vector3::new(eye.x, eye.y, eye.z);
+        for entry in self.entries.values_mut() {
+            let position = Vector3::from(entry.annotation.position);
+            entry.visible = project(view_proj, position, size).is_some()
+                && !(self.occlusion && covered(renderer, eye, position));
+        }
+    }
+
+    // redraws the overlay layer with the visible annotations.
+    pub fn draw(&self, overlay: &mut Overlay) {
+        let layer = overlay.layer(OVERLAY_LAYER);
+        layer.clear();
+        for entry in self.entries.values().filter(|entry| entry.visible) {
+            let annotation = &entry.annotation;
+            layer.marker_at(annotation.position, MARKER_SIZE, annotation.color);
+            layer.text_at(annotation.position, &annotation.text, annotation.color);
+        }
+    }
+
+    // the visible annotation whose marker is closest to the window position `x`/`y`.
+    pub fn pick(&self, x: f32, y: f32) -> Option<usize> {
+        let view_proj = self.view_proj?;
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.visible)
+            .filter_map(|(id, entry)| {
+                let (px, py) = project(view_proj, Vector3::from(entry.annotation.position), self.size)?;
+                let distance = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+                (distance <= PICK_RADIUS).then_some((*id, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+}
+
+// window position of `position` in pixels, None behind the camera or off screen.
+fn project(view_proj: Matrix4<f32>, position: Vector3<f32>, size: (u32, u32)) -> Option<(f32, f32)> {
+    let clip = view_proj * Vector4::new(position.x, position.y, position.z, 1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+    if x.abs() > 1.0 || y.abs() > 1.0 {
+        return None;
+    }
+    Some(((x * 0.5 + 0.5) * size.0 as f32, (0.5 - y * 0.5) * size.1 as f32))
+}
+
+// whether a visible mesh is between the eye and `position`.
+fn covered(renderer: &Renderer, eye: Vector3<f32>, position: Vector3<f32>) -> bool {
+    let distance = (position - eye).magnitude();
+    if distance <= f32::EPSILON {
+        return false;
+    }
+    let ray = Ray::new(eye, position - eye);
+    picking::pick_filtered(renderer.models(), &ray, |model, mesh| renderer.mesh_visible(model, mesh))
+        .is_some_and(|hit| hit.distance < distance * (1.0 - OCCLUSION_BIAS))
+}
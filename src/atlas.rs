@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::texture;
+
+/*
+    Texture atlas: many small images (glyphs, icons, markers) packed into
+    one texture, so everything drawn out of it shares a single bind group
+    and can go into a single draw call.
+
+    The packing is a skyline: the top edge of what is packed so far is
+    kept as a list of horizontal segments, every image goes where its
+    bottom ends up lowest. The images are packed from the tallest to the
+    flattest, which keeps the skyline even. The atlas is square, a power
+    of two, and grows until everything fits (or max_size is reached).
+
+    Every image gets a border of PADDING pixels repeating its edge, so
+    linear filtering doesn't pull in the neighbours.
+
+    usage:
+        let mut builder = AtlasBuilder::new(2048);
+        builder.add("pin", image::open("pin.png")?.to_rgba8());
+        builder.add("flag", image::open("flag.png")?.to_rgba8());
+        let atlas = builder.build()?;
+        let pin = atlas.rect("pin").unwrap(); // pin.uv_min .. pin.uv_max
+        let texture = atlas.upload(&device, &queue, "sprites")?;
+*/
+
+const PADDING: u32 = 1;
+// the smallest atlas that is tried.
+const MIN_SIZE: u32 = 64;
+
+// Where an image ended up, in pixels and in uvs of the atlas texture.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+// a segment of the skyline, `y` is the height of what's packed below it.
+#[derive(Debug, Copy, Clone)]
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+// Skyline bin packing of rectangles into a `width` x `height` area.
+pub struct Skyline {
+    width: u32,
+    height: u32,
+    segments: Vec<Segment>,
+}
+
+impl Skyline {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            segments: vec![Segment { x: 0, y: 0, width }],
+        }
+    }
+
+    // top left corner for a `width` x `height` rectangle, None when it doesn't fit anymore.
+    pub fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        // lowest bottom first, then the leftmost.
+        let (index, y) = (0..self.segments.len())
+            .filter_map(|index| self.fit(index, width, height).map(|y| (index, y)))
+            .min_by_key(|(index, y)| (*y, self.segments[*index].x))?;
+        let x = self.segments[index].x;
+
+        self.segments.insert(index, Segment { x, y: y + height, width });
+        // the new segment covers the start of the ones after it.
+        let next = index + 1;
+        while next < self.segments.len() {
+            let end = x + width;
+            let segment = &mut self.segments[next];
+            if segment.x >= end {
+                break;
+            }
+            let covered = end - segment.x;
+            if segment.width <= covered {
+                self.segments.remove(next);
+            } else {
+                segment.x += covered;
+                segment.width -= covered;
+                break;
+            }
+        }
+        // neighbours at the same height become one.
+        let mut index = 0;
+        while index + 1 < self.segments.len() {
+            if self.segments[index].y == self.segments[index + 1].y {
+                self.segments[index].width += self.segments[index + 1].width;
+                self.segments.remove(index + 1);
+            } else {
+                index += 1;
+            }
+        }
+        Some((x, y))
+    }
+
+    // the height the rectangle sits at when it starts at segment `index`.
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.segments[index].x;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut covered = 0;
+        for segment in &self.segments[index..] {
+            if covered >= width {
+                break;
+            }
+            y = y.max(segment.y);
+            covered += segment.width;
+        }
+        if y + height > self.height {
+            return None;
+        }
+        Some(y)
+    }
+}
+
+// Collects named images and packs them into an Atlas.
+pub struct AtlasBuilder {
+    max_size: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    // the atlas won't grow beyond `max_size` x `max_size` pixels.
+    pub fn new(max_size: u32) -> Self {
+        Self { max_size, images: Vec::new() }
+    }
+
+    // an image added again under the same name replaces the first one.
+    pub fn add(&mut self, name: &str, image: image::RgbaImage) {
+        self.images.retain(|(other, _)| other != name);
+        self.images.push((name.to_string(), image));
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn build(&self) -> Result<Atlas> {
+        if let Some((name, _)) = self.images.iter().find(|(_, image)| image.width() == 0 || image.height() == 0) {
+            bail!("Image {:?} of the atlas is empty.", name);
+        }
+        let padded = |image: &image::RgbaImage| (image.width() + 2 * PADDING, image.height() + 2 * PADDING);
+
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|index| {
+            let (width, height) = padded(&self.images[*index].1);
+            (std::cmp::Reverse(height), std::cmp::Reverse(width))
+        });
+
+        // start at the size the images would fill without gaps.
+        let area: u64 = self.images.iter()
+            .map(|(_, image)| {
+                let (width, height) = padded(image);
+                width as u64 * height as u64
+            })
+            .sum();
+        let widest = self.images.iter()
+            .map(|(_, image)| {
+                let (width, height) = padded(image);
+                width.max(height)
+            })
+            .max()
+            .unwrap_or(0);
+        let mut size = ((area as f64).sqrt().ceil() as u32).max(widest).max(MIN_SIZE).next_power_of_two();
+
+        let positions = loop {
+            if size > self.max_size {
+                bail!("{} images don't fit into a {} pixel atlas.", self.images.len(), self.max_size);
+            }
+            let mut skyline = Skyline::new(size, size);
+            let positions: Option<Vec<(usize, (u32, u32))>> = order.iter()
+                .map(|index| {
+                    let (width, height) = padded(&self.images[*index].1);
+                    skyline.insert(width, height).map(|position| (*index, position))
+                })
+                .collect();
+            match positions {
+                Some(positions) => break positions,
+                None => size *= 2,
+            }
+        };
+
+        let mut image = image::RgbaImage::new(size, size);
+        let mut rects = BTreeMap::new();
+        for (index, (x, y)) in positions {
+            let (name, source) = &self.images[index];
+            let (width, height) = source.dimensions();
+            let (padded_width, padded_height) = padded(source);
+            for py in 0..padded_height {
+                for px in 0..padded_width {
+                    // the border repeats the edge of the image.
+                    let sx = px.saturating_sub(PADDING).min(width - 1);
+                    let sy = py.saturating_sub(PADDING).min(height - 1);
+                    image.put_pixel(x + px, y + py, *source.get_pixel(sx, sy));
+                }
+            }
+
+            let (x, y) = (x + PADDING, y + PADDING);
+            rects.insert(name.clone(), AtlasRect {
+                x,
+                y,
+                width,
+                height,
+                uv_min: [x as f32 / size as f32, y as f32 / size as f32],
+                uv_max: [(x + width) as f32 / size as f32, (y + height) as f32 / size as f32],
+            });
+        }
+        Ok(Atlas { image, rects })
+    }
+}
+
+// The packed images and where each of them is.
+pub struct Atlas {
+    image: image::RgbaImage,
+    rects: BTreeMap<String, AtlasRect>,
+}
+
+impl Atlas {
+    // width and height, the atlas is square.
+    pub fn size(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn image(&self) -> &image::RgbaImage {
+        &self.image
+    }
+
+    pub fn rect(&self, name: &str) -> Option<&AtlasRect> {
+        self.rects.get(name)
+    }
+
+    pub fn rects(&self) -> impl Iterator<Item = (&str, &AtlasRect)> {
+        self.rects.iter().map(|(name, rect)| (name.as_str(), rect))
+    }
+
+    pub fn upload(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Result<texture::Texture> {
+        texture::Texture::from_image(device, queue, &image::DynamicImage::ImageRgba8(self.image.clone()), Some(label))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlap(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    fn filled(width: u32, height: u32, value: u8) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn skyline_packs_without_overlap() {
+        let mut skyline = Skyline::new(64, 64);
+        let sizes = [(30, 20), (20, 20), (14, 10), (64, 8), (10, 30), (33, 5), (7, 7)];
+        let mut placed: Vec<(u32, u32, u32, u32)> = Vec::new();
+        for (width, height) in sizes {
+            let (x, y) = skyline.insert(width, height).unwrap();
+            let rect = (x, y, width, height);
+            assert!(x + width <= 64 && y + height <= 64);
+            assert!(placed.iter().all(|other| !overlap(rect, *other)), "{:?} overlaps {:?}", rect, placed);
+            placed.push(rect);
+        }
+        // the first lands in the corner, a full row goes on top of everything.
+        assert_eq!((placed[0].0, placed[0].1), (0, 0));
+        assert_eq!(placed[3].1, 20);
+    }
+
+    #[test]
+    fn skyline_is_full_at_some_point() {
+        let mut skyline = Skyline::new(32, 32);
+        for _ in 0..4 {
+            assert!(skyline.insert(16, 16).is_some());
+        }
+        assert!(skyline.insert(1, 1).is_none());
+        assert!(Skyline::new(8, 8).insert(9, 1).is_none());
+    }
+
+    #[test]
+    fn atlas_keeps_the_pixels_and_pads_them() {
+        let mut builder = AtlasBuilder::new(256);
+        builder.add("a", filled(10, 4, 10));
+        builder.add("b", filled(3, 30, 20));
+        builder.add("a", filled(5, 5, 30));
+        assert_eq!(builder.len(), 2);
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.size(), MIN_SIZE);
+
+        for (name, value) in [("a", 30), ("b", 20)] {
+            let rect = atlas.rect(name).unwrap();
+            assert_eq!(rect.uv_min, [rect.x as f32 / 64.0, rect.y as f32 / 64.0]);
+            // the image and its border.
+            for y in rect.y - PADDING..rect.y + rect.height + PADDING {
+                for x in rect.x - PADDING..rect.x + rect.width + PADDING {
+                    assert_eq!(atlas.image().get_pixel(x, y)[0], value);
+                }
+            }
+        }
+        assert_eq!((atlas.rect("a").unwrap().width, atlas.rect("a").unwrap().height), (5, 5));
+        assert!(atlas.rect("c").is_none());
+    }
+
+    #[test]
+    fn atlas_grows_up_to_max_size() {
+        let mut builder = AtlasBuilder::new(256);
+        for index in 0..5 {
+            builder.add(&index.to_string(), filled(60, 60, 0));
+        }
+        assert_eq!(builder.build().unwrap().size(), 256);
+
+        // too wide with its border.
+        builder.add("big", filled(255, 60, 0));
+        assert!(builder.build().is_err());
+        builder.add("big", filled(0, 60, 0));
+        assert!(builder.build().is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::model::{Displacement, LightmapMode, MaterialData, MeshData, ModelData, VertexColors};
+use crate::vertex::MVertex;
+
+/*
+    Preprocessed ("baked") model format.
+
+    Stores the vertices and indices exactly as they are uploaded,
+    so loading is a couple of reads instead of parsing an obj.
+
+    layout (all integers little endian u32):
+        magic "ZXMB", version, source hash (u64)
+        material count
+            name, diffuse texture path (empty = none),
+            diffuse color (3 x f32), vertex color mode,
+            lightmap path (empty = none), lightmap mode,
+            displacement map path (empty = none), base, scale (f32),
+            normal map path (empty = none)
+        mesh count
+            name, material index,
+            vertex count, vertices (MVertex as raw bytes),
+            index count, indices
+
+    strings are written as byte length + utf8 bytes.
+    Texture paths are relative to the folder of the baked file,
+    same as in the mtl file the model came from.
+
+    The source hash covers the obj and its mtl files, it's what
+    load_cached() uses to tell if the cache is still up to date.
+*/
+
+const MAGIC: &[u8; 4] = b"ZXMB";
+const VERSION: u32 = 8;
+
+// extension of the cache file written next to the obj.
+pub const CACHE_EXTENSION: &str = "zxmb";
+
+// Loads the model at `path` through its cache file:
+// if the cache exists and was made from the same content it's used,
+// otherwise the obj is parsed and the cache (re)written.
+//
+// The cache holds the geometry as imported, displacement maps are
+// applied afterwards so editing one doesn't need a rebake.
+pub fn load_cached<P: AsRef<Path>>(path: P) -> Result<ModelData> {
+    // urls are downloaded first and then cached like local files, see fetch.rs.
+    let path = crate::fetch::local_model(path.as_ref())?;
+    let mut data = load_cached_source(&path)?;
+    data.apply_displacement()?;
+    Ok(data)
+}
+
+fn load_cached_source(path: &Path) -> Result<ModelData> {
+    let hash = source_hash(path)?;
+    let cache_path = cache_path(path);
+
+    if let Ok(cached_hash) = read_hash(&cache_path) {
+        if cached_hash == hash {
+            match read(&cache_path) {
+                Ok(data) => return Ok(data),
+                Err(e) => log::warn!("Ignoring broken model cache {:?}: {}", cache_path, e),
+            }
+        }
+    }
+
+    let data = ModelData::load(path)?;
+    // not being able to cache is no reason to fail the load.
+    if let Err(e) = write(&data, hash, &cache_path) {
+        log::warn!("Unable to write model cache {:?}: {}", cache_path, e);
+    }
+    Ok(data)
+}
+
+// model.obj -> model.obj.zxmb
+pub fn cache_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(CACHE_EXTENSION);
+    path.with_file_name(file_name)
+}
+
+// FNV-1a over the model file and, for obj, the mtl files it references.
+pub fn source_hash(path: &Path) -> Result<u64> {
+    let obj = std::fs::read(path)
+        .with_context(|| format!("Unable to read {:?}.", path))?;
+
+    let mut hash = fnv1a(0xcbf2_9ce4_8422_2325, &obj);
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    for line in String::from_utf8_lossy(&obj).lines() {
+        if let Some(mtl) = line.trim().strip_prefix("mtllib") {
+            // a missing mtl still has to change the hash, so hash the name as well.
+            hash = fnv1a(hash, mtl.trim().as_bytes());
+            if let Ok(bytes) = std::fs::read(directory.join(mtl.trim())) {
+                hash = fnv1a(hash, &bytes);
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+pub fn write<P: AsRef<Path>>(data: &ModelData, source_hash: u64, path: P) -> Result<()> {
+    let file = File::create(path.as_ref())
+        .with_context(|| format!("Unable to create {:?}.", path.as_ref()))?;
+    let mut writer = BufWriter::new(file);
+
+    writer.write_all(MAGIC)?;
+    write_u32(&mut writer, VERSION)?;
+    writer.write_all(&source_hash.to_le_bytes())?;
+
+    write_u32(&mut writer, data.materials.len() as u32)?;
+    for material in &data.materials {
+        write_str(&mut writer, &material.name)?;
+        let diffuse = material.diffuse_texture.as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        write_str(&mut writer, &diffuse)?;
+        for channel in &material.diffuse_color {
+            writer.write_all(&channel.to_le_bytes())?;
+        }
+        write_u32(&mut writer, material.vertex_colors.as_u32())?;
+        let lightmap = material.lightmap_texture.as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        write_str(&mut writer, &lightmap)?;
+        write_u32(&mut writer, material.lightmap_mode.as_u32())?;
+        match &material.displacement {
+            Some(displacement) => {
+                write_str(&mut writer, &displacement.texture.to_string_lossy())?;
+                write_u32(&mut writer, displacement.base.to_bits())?;
+                write_u32(&mut writer, displacement.scale.to_bits())?;
+            }
+            None => {
+                write_str(&mut writer, "")?;
+                write_u32(&mut writer, 0.0f32.to_bits())?;
+                write_u32(&mut writer, 0.0f32.to_bits())?;
+            }
+        }
+        let normal = material.normal_texture.as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        write_str(&mut writer, &normal)?;
+    }
+
+    write_u32(&mut writer, data.meshes.len() as u32)?;
+    for mesh in &data.meshes {
+        write_str(&mut writer, &mesh.name)?;
+        write_u32(&mut writer, mesh.material as u32)?;
+
+        write_u32(&mut writer, mesh.vertices.len() as u32)?;
+        writer.write_all(bytemuck::cast_slice(&mesh.vertices))?;
+
+        write_u32(&mut writer, mesh.indices.len() as u32)?;
+        for index in &mesh.indices {
+            write_u32(&mut writer, *index)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// only reads the header, to check a cache without loading it.
+pub fn read_hash<P: AsRef<Path>>(path: P) -> Result<u64> {
+    let file = File::open(path.as_ref())
+        .with_context(|| format!("Unable to open {:?}.", path.as_ref()))?;
+    read_header(&mut BufReader::new(file), path.as_ref())
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<ModelData> {
+    let file = File::open(path.as_ref())
+        .with_context(|| format!("Unable to open {:?}.", path.as_ref()))?;
+    // the counts can't promise more than the file holds.
+    let len = file.metadata()?.len() as usize;
+    let mut reader = BufReader::new(file);
+
+    read_header(&mut reader, path.as_ref())?;
+
+    let num_materials = read_u32(&mut reader)?;
+    let mut materials = Vec::with_capacity(num_materials as usize);
+    for _ in 0..num_materials {
+        let name = read_str(&mut reader)?;
+        let diffuse = read_str(&mut reader)?;
+        let mut diffuse_color = [0.0f32; 3];
+        for channel in diffuse_color.iter_mut() {
+            *channel = f32::from_bits(read_u32(&mut reader)?);
+        }
+        let vertex_colors = VertexColors::from_u32(read_u32(&mut reader)?);
+        let lightmap = read_str(&mut reader)?;
+        let lightmap_mode = LightmapMode::from_u32(read_u32(&mut reader)?);
+        let displacement = read_str(&mut reader)?;
+        let base = f32::from_bits(read_u32(&mut reader)?);
+        let scale = f32::from_bits(read_u32(&mut reader)?);
+        let normal = read_str(&mut reader)?;
+        materials.push(MaterialData {
+            name,
+            diffuse_texture: if diffuse.is_empty() { None } else { Some(PathBuf::from(diffuse)) },
+            diffuse_color,
+            vertex_colors,
+            lightmap_texture: if lightmap.is_empty() { None } else { Some(PathBuf::from(lightmap)) },
+            lightmap_mode,
+            displacement: if displacement.is_empty() {
+                None
+            } else {
+                Some(Displacement { texture: PathBuf::from(displacement), base, scale })
+            },
+            normal_texture: if normal.is_empty() { None } else { Some(PathBuf::from(normal)) },
+        });
+    }
+
+    let num_meshes = read_u32(&mut reader)?;
+    let mut meshes = Vec::with_capacity(num_meshes as usize);
+    for _ in 0..num_meshes {
+        let name = read_str(&mut reader)?;
+        let material = read_u32(&mut reader)? as usize;
+        // a broken cache has to fail here, where the loader falls back to
+        // the source file, not later in the upload.
+        if material >= materials.len() {
+            bail!("Mesh {:?} has material {} of {}.", name, material, materials.len());
+        }
+
+        let num_vertices = read_u32(&mut reader)? as usize;
+        if num_vertices * std::mem::size_of::<MVertex>() > len {
+            bail!("Mesh {:?} has more vertices than the file holds.", name);
+        }
+        let mut vertices = vec![bytemuck::Zeroable::zeroed(); num_vertices];
+        reader.read_exact(bytemuck::cast_slice_mut::<MVertex, u8>(&mut vertices))?;
+
+        let num_indices = read_u32(&mut reader)? as usize;
+        if num_indices * 4 > len {
+            bail!("Mesh {:?} has more indices than the file holds.", name);
+        }
+        let mut indices = Vec::with_capacity(num_indices);
+        for _ in 0..num_indices {
+            let index = read_u32(&mut reader)?;
+            if index as usize >= num_vertices {
+                bail!("Mesh {:?} has index {} of {} vertices.", name, index, num_vertices);
+            }
+            indices.push(index);
+        }
+
+        meshes.push(MeshData::new(name, vertices, indices, material));
+    }
+
+    let directory = path.as_ref().parent()
+        .context("Directory has no parent")?
+        .to_path_buf();
+
+    Ok(ModelData::new(meshes, materials, directory))
+}
+
+// checks magic and version, returns the source hash.
+fn read_header<R: Read>(reader: &mut R, path: &Path) -> Result<u64> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("{:?} is not a baked model.", path);
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        bail!("{:?} has version {}, expected {}.", path, version, VERSION);
+    }
+
+    let mut hash = [0u8; 8];
+    reader.read_exact(&mut hash)?;
+    Ok(u64::from_le_bytes(hash))
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_str<R: Read>(reader: &mut R) -> Result<String> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(material: usize, indices: Vec<u32>) -> ModelData {
+        let material_data = MaterialData {
+            name: "material".to_string(),
+            diffuse_texture: None,
+            diffuse_color: [1.0; 3],
+            vertex_colors: VertexColors::from_u32(0),
+            lightmap_texture: None,
+            lightmap_mode: LightmapMode::from_u32(0),
+            displacement: None,
+            normal_texture: None,
+        };
+        let vertices = vec![bytemuck::Zeroable::zeroed(); 3];
+        let mesh = MeshData::new("mesh".to_string(), vertices, indices, material);
+        ModelData::new(vec![mesh], vec![material_data], PathBuf::new())
+    }
+
+    // writes `data`, lets `corrupt` change the bytes and reads it back.
+    fn round_trip(name: &str, data: &ModelData, corrupt: impl FnOnce(&mut Vec<u8>)) -> Result<ModelData> {
+        let path = std::env::temp_dir().join(format!("zhneeshyx-{}-{}.{}", name, std::process::id(), CACHE_EXTENSION));
+        write(data, 42, &path)?;
+        let mut bytes = std::fs::read(&path)?;
+        corrupt(&mut bytes);
+        std::fs::write(&path, bytes)?;
+        let read_back = read(&path);
+        std::fs::remove_file(&path)?;
+        read_back
+    }
+
+    #[test]
+    fn models_read_back_as_written() {
+        let data = round_trip("valid", &model(0, vec![0, 1, 2]), |_| {}).unwrap();
+        assert_eq!(data.meshes[0].indices, vec![0, 1, 2]);
+        assert_eq!(data.meshes[0].vertices.len(), 3);
+        assert_eq!(data.materials[0].name, "material");
+    }
+
+    #[test]
+    fn broken_caches_are_errors() {
+        assert!(round_trip("material", &model(1, vec![0, 1, 2]), |_| {}).is_err());
+        assert!(round_trip("index", &model(0, vec![0, 1, 3]), |_| {}).is_err());
+        // the index count, the last u32 before the three indices.
+        let huge_count = |bytes: &mut Vec<u8>| {
+            let count = bytes.len() - 16;
+            bytes[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        };
+        assert!(round_trip("count", &model(0, vec![0, 1, 2]), huge_count).is_err());
+    }
+}
@@ -1,48 +1,604 @@
 // Vertex shader
 
-[[block]] //
+//
 struct CameraUniform {
-    view_proj: mat4x4<f32>;
-};
+    view_proj: mat4x4<f32>,
+}
 
-[[group(1), binding(0)]] //
+@group(1) @binding(0) //
 var<uniform> camera: CameraUniform;
 
+// see frame.rs.
+struct FrameUniform {
+    // seconds since the renderer started.
+    time: f32,
+    delta_time: f32,
+    frame: u32,
+    // width, height, 1 / width, 1 / height in pixels.
+    screen_size: vec4<f32>,
+    camera_position: vec4<f32>,
+    // direction times speed, w: speed. See wind.rs.
+    wind: vec4<f32>,
+}
+
+@group(1) @binding(1)
+var<uniform> frame: FrameUniform;
+
 struct VertexInput {
-    [[location(0)]] position: vec3<f32>;
-    [[location(1)]] uv: vec2<f32>;
-    [[location(2)]] norm: vec3<f32>;
-};
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) norm: vec3<f32>,
+    @location(3) color: vec3<f32>,
+    @location(4) uv2: vec2<f32>,
+    // per mesh, see MeshOffset.
+    @location(5) offset: vec3<f32>,
+    // per copy of the model, see InstanceRaw. A single identity one
+    // for models without instances.
+    @location(6) model_0: vec4<f32>,
+    @location(7) model_1: vec4<f32>,
+    @location(8) model_2: vec4<f32>,
+    @location(9) model_3: vec4<f32>,
+    @location(10) normal_0: vec3<f32>,
+    @location(11) normal_1: vec3<f32>,
+    @location(12) normal_2: vec3<f32>,
+    // for normal mapping, see MeshData::compute_tangents().
+    @location(13) tangent: vec3<f32>,
+    @location(14) bitangent: vec3<f32>,
+}
 
 struct VertexOutput {
-    [[builtin(position)]] clip_position: vec4<f32>;
-    [[location(0)]] uv: vec2<f32>;
-};
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) uv2: vec2<f32>,
+    @location(3) world_position: vec3<f32>,
+    @location(4) norm: vec3<f32>,
+    // distance along the view direction, for the half rate light buffer.
+    @location(5) view_depth: f32,
+    @location(6) tangent: vec3<f32>,
+    @location(7) bitangent: vec3<f32>,
+}
 
-[[stage(vertex)]]
-fn main(
+// where the vertex animation moves `vertex` to at the current time, see vat.rs.
+fn vat_offset(vertex: u32) -> vec3<f32> {
+    if (material.vat_frames == 0u) {
+        return vec3<f32>(0.0);
+    }
+    let last = f32(material.vat_frames - 1u);
+    var time = max((frame.time - material.vat_start) * material.vat_fps, 0.0);
+    if (material.vat_loop == 1u) {
+        time = time % f32(material.vat_frames);
+    } else {
+        time = min(time, last);
+    }
+    let first = u32(time);
+    // looping blends the last frame into the first one.
+    let next = select(min(first + 1u, material.vat_frames - 1u), (first + 1u) % material.vat_frames, material.vat_loop == 1u);
+    let width = textureDimensions(tex_vat).x;
+    let texel = vec2<u32>(vertex % width, vertex / width);
+    let a = textureLoad(tex_vat, texel + vec2<u32>(0u, first * material.vat_rows), 0).xyz;
+    let b = textureLoad(tex_vat, texel + vec2<u32>(0u, next * material.vat_rows), 0).xyz;
+    return mix(a, b, fract(time));
+}
+
+// how far the wind moves a vertex at `position` (world space), see wind.rs.
+fn wind_offset(position: vec3<f32>, color: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
+    if (material.wind_mask == 0u || frame.wind.w <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    var mask = 1.0;
+    if (material.wind_mask == 2u) {
+        mask = color.r;
+    } else if (material.wind_mask == 3u) {
+        mask = clamp(1.0 - uv.y, 0.0, 1.0);
+    }
+    // the gusts run along the wind, so the phase grows along it.
+    let along = dot(position.xz, normalize(frame.wind.xz + vec2<f32>(0.0001, 0.0)));
+    let phase = (frame.time * material.wind_frequency - along * 0.15) * 6.2831853;
+    // a slow swing with a quicker flutter on top, bent along the wind more than back.
+    let swing = 0.6 + 0.4 * sin(phase) + 0.1 * sin(phase * 2.7 + position.x + position.z);
+    return frame.wind.xyz * material.wind_strength * mask * swing;
+}
+
+@vertex
+fn vs_main(
     model: VertexInput,
+    @builtin(vertex_index) vertex_index: u32,
 ) -> VertexOutput {
     
     var out: VertexOutput;
 
     out.uv = model.uv;
+    out.color = model.color;
+    out.uv2 = model.uv2;
+    let model_matrix = mat4x4<f32>(model.model_0, model.model_1, model.model_2, model.model_3);
+    let normal_matrix = mat3x3<f32>(model.normal_0, model.normal_1, model.normal_2);
+    let position = model.position + model.offset + vat_offset(vertex_index);
+    out.world_position = (model_matrix * vec4<f32>(position, 1.0)).xyz;
+    // the wind bends the copies in world space, so they sway alike.
+    out.world_position = out.world_position + wind_offset(out.world_position, model.color, model.uv);
+    out.norm = normal_matrix * model.norm;
+    // directions on the surface, they turn and stretch with the model.
+    let tangent_matrix = mat3x3<f32>(model.model_0.xyz, model.model_1.xyz, model.model_2.xyz);
+    out.tangent = tangent_matrix * model.tangent;
+    out.bitangent = tangent_matrix * model.bitangent;
 
-    // add world matrix before camera.view_proj later.
-    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+    out.view_depth = out.clip_position.w;
 
     return out;
 }
 
 // Fragment shader
 
-[[group(0), binding(0)]]
+@group(0) @binding(0)
 var tex_diffuse: texture_2d<f32>; // uniform var
 
-[[group(0), binding(1)]]
+@group(0) @binding(1)
 var sampler_diffuse: sampler; // uniform var
 
-[[stage(fragment)]]
-fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return textureSample(tex_diffuse, sampler_diffuse, in.uv);
+struct MaterialUniform {
+    // 0 = ignore, 1 = multiply, 2 = replace the texture color.
+    vertex_colors: u32,
+    // 0 = none, 1 = multiply, 2 = add.
+    lightmap: u32,
+    // 1 = blend the splat layers over the texture, see splat.rs.
+    splat: u32,
+    splat_tiling: f32,
+    // multiplied into the color.
+    tint: vec4<f32>,
+    // tiling and rotation of the diffuse uvs, column by column, see UvTransform.
+    uv_matrix: vec4<f32>,
+    uv_offset: vec2<f32>,
+    // uvs per second.
+    uv_scroll: vec2<f32>,
+    // 0 frames = no flipbook, see Flipbook.
+    flipbook_columns: u32,
+    flipbook_rows: u32,
+    flipbook_frames: u32,
+    flipbook_fps: f32,
+    // 0 frames = no vertex animation, rows of the texture per frame.
+    vat_frames: u32,
+    vat_rows: u32,
+    vat_fps: f32,
+    // 1 = start again after the last frame.
+    vat_loop: u32,
+    // frame.time the animation starts at.
+    vat_start: f32,
+    // 0 = no sway, 1 = all alike, 2 = by the red vertex color, 3 = by 1 - v.
+    wind_mask: u32,
+    wind_strength: f32,
+    wind_frequency: f32,
+    // 1 = the normals are bent by tex_normal.
+    normal_map: u32,
+    padding: vec3<u32>,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+
+@group(0) @binding(3)
+var tex_lightmap: texture_2d<f32>;
+
+@group(0) @binding(4)
+var sampler_lightmap: sampler;
+
+// r, g, b: weights of the three layers.
+@group(0) @binding(5)
+var tex_splat: texture_2d<f32>;
+
+@group(0) @binding(6)
+var sampler_splat: sampler;
+
+@group(0) @binding(7)
+var tex_layer0: texture_2d<f32>;
+
+@group(0) @binding(8)
+var tex_layer1: texture_2d<f32>;
+
+@group(0) @binding(9)
+var tex_layer2: texture_2d<f32>;
+
+// offsets of the vertices, frame by frame, see vat.rs.
+@group(0) @binding(10)
+var tex_vat: texture_2d<f32>;
+
+// tangent space normals, see Material::set_normal_map().
+@group(0) @binding(11)
+var tex_normal: texture_2d<f32>;
+
+// the painted layers over the texture, it shows where the weights add up to less than 1.
+fn apply_splat(color: vec4<f32>, uv: vec2<f32>) -> vec4<f32> {
+    let weights = textureSample(tex_splat, sampler_splat, uv).rgb;
+    let layer_uv = uv * material.splat_tiling;
+    let layers = textureSample(tex_layer0, sampler_diffuse, layer_uv).rgb * weights.r
+        + textureSample(tex_layer1, sampler_diffuse, layer_uv).rgb * weights.g
+        + textureSample(tex_layer2, sampler_diffuse, layer_uv).rgb * weights.b;
+    if (material.splat != 1u) {
+        return color;
+    }
+    let total = min(weights.r + weights.g + weights.b, 1.0);
+    return vec4<f32>(color.rgb * (1.0 - total) + layers, color.a);
+}
+
+// the uvs the diffuse texture is sampled with. The splat map keeps the
+// mesh uvs, it covers the mesh once however often the texture repeats.
+fn transform_uv(uv: vec2<f32>) -> vec2<f32> {
+    let m = mat2x2<f32>(material.uv_matrix.xy, material.uv_matrix.zw);
+    return m * uv + material.uv_offset + material.uv_scroll * frame.time;
+}
+
+// size of a flipbook frame in uvs of the texture, 1 without a flipbook.
+fn flipbook_cell() -> vec2<f32> {
+    if (material.flipbook_frames == 0u) {
+        return vec2<f32>(1.0);
+    }
+    return 1.0 / vec2<f32>(f32(material.flipbook_columns), f32(material.flipbook_rows));
+}
+
+// from the uvs of a frame to the ones of the current frame on the texture.
+fn flipbook_uv(uv: vec2<f32>) -> vec2<f32> {
+    if (material.flipbook_frames == 0u) {
+        return uv;
+    }
+    let shown = u32(max(frame.time * material.flipbook_fps, 0.0)) % material.flipbook_frames;
+    let cell = vec2<f32>(f32(shown % material.flipbook_columns), f32(shown / material.flipbook_columns));
+    return (fract(uv) + cell) * flipbook_cell();
+}
+
+fn diffuse_texture(uv: vec2<f32>) -> vec4<f32> {
+    // the gradients of the unwrapped uvs, fract() jumps at the frame edges,
+    // the mip level would too.
+    let cell = flipbook_cell();
+    return textureSampleGrad(tex_diffuse, sampler_diffuse, flipbook_uv(uv), dpdx(uv) * cell, dpdy(uv) * cell);
+}
+
+fn diffuse_color(uv: vec2<f32>, vertex_color: vec3<f32>) -> vec4<f32> {
+    let color = apply_splat(diffuse_texture(transform_uv(uv)), uv);
+    if (material.vertex_colors == 1u) {
+        return vec4<f32>(color.rgb * vertex_color, color.a);
+    }
+    if (material.vertex_colors == 2u) {
+        return vec4<f32>(vertex_color, color.a);
+    }
+    return color;
+}
+
+// baked lighting on top of the diffuse color.
+fn apply_lightmap(color: vec4<f32>, uv2: vec2<f32>) -> vec4<f32> {
+    let light = textureSample(tex_lightmap, sampler_lightmap, uv2).rgb;
+    if (material.lightmap == 1u) {
+        return vec4<f32>(color.rgb * light, color.a);
+    }
+    if (material.lightmap == 2u) {
+        return vec4<f32>(color.rgb + light, color.a);
+    }
+    return color;
+}
+
+// the normal the fragment is lit with: the interpolated one, bent by
+// the normal map. Tiled like the diffuse texture, a rotated uv
+// transform doesn't turn the tangents with it.
+fn surface_normal(in: VertexOutput) -> vec3<f32> {
+    let mapped = textureSample(tex_normal, sampler_diffuse, transform_uv(in.uv)).xyz * 2.0 - 1.0;
+    // meshes without normals or uvs have no tangents.
+    if (material.normal_map != 1u || dot(in.norm, in.norm) == 0.0 || dot(in.tangent, in.tangent) == 0.0) {
+        return in.norm;
+    }
+    let t = normalize(in.tangent);
+    let b = normalize(in.bitangent);
+    let n = normalize(in.norm);
+    // green points up in the image, v runs down it.
+    return normalize(t * mapped.x - b * mapped.y + n * mapped.z);
+}
+
+struct SpotUniform {
+    view_proj: mat4x4<f32>,
+    // w: range
+    position: vec4<f32>,
+    // rgb: color * intensity, w: 1 if the light is on.
+    color: vec4<f32>,
+    // x: 1 if the light comes from the half rate light buffer.
+    shading: vec4<u32>,
+}
+
+@group(2) @binding(0)
+var<uniform> spot: SpotUniform;
+
+@group(2) @binding(1)
+var tex_cookie: texture_2d<f32>;
+
+@group(2) @binding(2)
+var sampler_cookie: sampler;
+
+// rgb: light, a: view depth of the shaded fragment. See shading.rs.
+@group(2) @binding(3)
+var tex_light: texture_2d<f32>;
+
+// see light::LightsUniform.
+struct PointLight {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+
+struct LightsUniform {
+    lights: array<PointLight, 8>,
+    count: u32,
+    ambient: f32,
+    specular: f32,
+    shininess: f32,
+}
+
+@group(2) @binding(4)
+var<uniform> point_lights: LightsUniform;
+
+// see shadow::ShadowUniform.
+struct ShadowUniform {
+    view_proj: mat4x4<f32>,
+    // xyz: towards the sun.
+    direction: vec4<f32>,
+    // rgb: color of the sun, w: 1 if there is a sun.
+    color: vec4<f32>,
+    // x: 1 if the map is drawn, y: width of a texel in uv.
+    params: vec4<f32>,
+}
+
+@group(2) @binding(5)
+var tex_shadow: texture_depth_2d;
+
+@group(2) @binding(6)
+var sampler_shadow: sampler_comparison;
+
+@group(2) @binding(7)
+var<uniform> sun: ShadowUniform;
+
+// rgb: the highlights of the half rate light buffer.
+@group(2) @binding(8)
+var tex_specular: texture_2d<f32>;
+
+// how much of the sun reaches this fragment, 0 in its shadow. 3x3 taps
+// of the comparison sampler (PCF), soft edges.
+fn sun_visibility(world_position: vec3<f32>) -> f32 {
+    if (sun.params.x == 0.0) {
+        return 1.0;
+    }
+    let clip = sun.view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    // outside of the map nothing is known to be in the way.
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+    var visible = 0.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * sun.params.y;
+            visible = visible + textureSampleCompareLevel(tex_shadow, sampler_shadow, uv + offset, ndc.z);
+        }
+    }
+    return visible / 9.0;
+}
+
+struct Shading {
+    // multiplied into the color of the surface.
+    diffuse: vec3<f32>,
+    // added on top, in the color of the lights.
+    specular: vec3<f32>,
+}
+
+// one light of `color` in the direction `to_light` (normalized).
+fn blinn_phong(norm: vec3<f32>, to_light: vec3<f32>, to_eye: vec3<f32>, color: vec3<f32>) -> Shading {
+    var shading: Shading;
+    // meshes without normals are lit from every side, without highlights.
+    if (dot(norm, norm) == 0.0) {
+        shading.diffuse = color;
+        shading.specular = vec3<f32>(0.0);
+        return shading;
+    }
+    let normal = normalize(norm);
+    let half_dir = normalize(to_light + to_eye);
+    let diffuse = max(dot(normal, to_light), 0.0);
+    // no highlights on the side facing away.
+    let specular = pow(max(dot(normal, half_dir), 0.0), point_lights.shininess) * step(0.0001, diffuse);
+    shading.diffuse = color * diffuse;
+    shading.specular = color * specular * point_lights.specular;
+    return shading;
+}
+
+// Blinn-Phong with the ambient light and the point lights, the scene is
+// unlit without them and the sun.
+fn shade_points(world_position: vec3<f32>, norm: vec3<f32>) -> Shading {
+    var shading: Shading;
+    if (point_lights.count == 0u && sun.color.w == 0.0) {
+        shading.diffuse = vec3<f32>(1.0);
+        shading.specular = vec3<f32>(0.0);
+        return shading;
+    }
+    shading.diffuse = vec3<f32>(point_lights.ambient);
+    shading.specular = vec3<f32>(0.0);
+    let to_eye = normalize(frame.camera_position.xyz - world_position);
+    for (var i = 0u; i < min(point_lights.count, 8u); i = i + 1u) {
+        let light = point_lights.lights[i];
+        let lit = blinn_phong(norm, normalize(light.position - world_position), to_eye, light.color);
+        shading.diffuse = shading.diffuse + lit.diffuse;
+        shading.specular = shading.specular + lit.specular;
+    }
+    return shading;
+}
+
+// the sun behind its shadow map, nothing without a sun.
+fn shade_sun(world_position: vec3<f32>, norm: vec3<f32>) -> Shading {
+    var shading: Shading;
+    shading.diffuse = vec3<f32>(0.0);
+    shading.specular = vec3<f32>(0.0);
+    if (sun.color.w == 1.0) {
+        let to_eye = normalize(frame.camera_position.xyz - world_position);
+        let visible = sun_visibility(world_position);
+        shading = blinn_phong(norm, sun.direction.xyz, to_eye, sun.color.rgb * visible);
+    }
+    return shading;
+}
+
+// the point lights and the sun together.
+fn shade(world_position: vec3<f32>, norm: vec3<f32>) -> Shading {
+    var shading = shade_points(world_position, norm);
+    let sun_shading = shade_sun(world_position, norm);
+    shading.diffuse = shading.diffuse + sun_shading.diffuse;
+    shading.specular = shading.specular + sun_shading.specular;
+    return shading;
+}
+
+// light of the spot (flashlight) reaching this fragment.
+fn spot_light(world_position: vec3<f32>, norm: vec3<f32>) -> vec3<f32> {
+    // project the fragment into the cookie like into a camera.
+    let clip = spot.view_proj * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xy / max(clip.w, 0.0001);
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let cookie = textureSampleLevel(tex_cookie, sampler_cookie, uv, 0.0).rgb;
+
+    if (spot.color.w == 0.0 || clip.w <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    // round cone inside the square projection.
+    let cone = clamp((1.0 - length(ndc)) * 10.0, 0.0, 1.0);
+
+    let to_light = spot.position.xyz - world_position;
+    let distance = length(to_light);
+    let attenuation = pow(clamp(1.0 - distance / spot.position.w, 0.0, 1.0), 2.0);
+
+    // meshes without normals are lit from every side.
+    var facing = 1.0;
+    if (dot(norm, norm) > 0.0) {
+        facing = max(dot(normalize(norm), to_light / max(distance, 0.0001)), 0.0);
+    }
+    return spot.color.rgb * cookie * cone * attenuation * facing;
+}
+
+struct ClipUniform {
+    // xyz: normal, w: distance, dot(normal, p) + distance < 0 is cut away.
+    planes: array<vec4<f32>, 4>,
+    // only used by the cross-section caps, see clip_shader.wgsl.
+    cap_center: vec4<f32>,
+    cap_color: vec4<f32>,
+    num_planes: u32,
+    cap_plane: u32,
+    padding: vec2<u32>,
+}
+
+@group(3) @binding(0)
+var<uniform> clip: ClipUniform;
+
+fn clipped(position: vec3<f32>) -> bool {
+    var i = 0u;
+    loop {
+        if (i >= clip.num_planes) {
+            break;
+        }
+        let plane = clip.planes[i];
+        if (dot(plane.xyz, position) + plane.w < 0.0) {
+            return true;
+        }
+        i = i + 1u;
+    }
+    return false;
+}
+
+// one texel of the light buffer, weighted by how close its depth is to `depth`.
+fn light_tap(texel: vec2<i32>, bilinear: f32, depth: f32) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(tex_light));
+    let sample = textureLoad(tex_light, clamp(texel, vec2<i32>(0), size - vec2<i32>(1)), 0);
+    let difference = abs(sample.a - depth) / max(depth, 0.0001);
+    let weight = bilinear / (0.001 + difference);
+    return vec4<f32>(sample.rgb * weight, weight);
+}
+
+fn specular_tap(texel: vec2<i32>, weight: f32) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(tex_specular));
+    return textureLoad(tex_specular, clamp(texel, vec2<i32>(0), size - vec2<i32>(1)), 0).rgb * weight;
+}
+
+// depth aware upsample of the half rate light: of the four texels around the pixel,
+// the ones on the same surface count, the light doesn't bleed over edges.
+fn buffered_light(position: vec2<f32>, depth: f32) -> Shading {
+    let coord = position * 0.5 - vec2<f32>(0.5);
+    let base = vec2<i32>(floor(coord));
+    let f = coord - floor(coord);
+    let t00 = light_tap(base, (1.0 - f.x) * (1.0 - f.y), depth);
+    let t10 = light_tap(base + vec2<i32>(1, 0), f.x * (1.0 - f.y), depth);
+    let t01 = light_tap(base + vec2<i32>(0, 1), (1.0 - f.x) * f.y, depth);
+    let t11 = light_tap(base + vec2<i32>(1, 1), f.x * f.y, depth);
+    let sum = t00 + t10 + t01 + t11;
+    // the highlights with the weights of the light.
+    let specular = specular_tap(base, t00.a)
+        + specular_tap(base + vec2<i32>(1, 0), t10.a)
+        + specular_tap(base + vec2<i32>(0, 1), t01.a)
+        + specular_tap(base + vec2<i32>(1, 1), t11.a);
+    var shading: Shading;
+    shading.diffuse = sum.rgb / max(sum.a, 0.0001);
+    shading.specular = specular / max(sum.a, 0.0001);
+    return shading;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (clipped(in.world_position)) {
+        discard;
+    }
+    let color = apply_lightmap(diffuse_color(in.uv, in.color), in.uv2) * material.tint;
+    // the point lights (or none) and the sun, the spot brightens it on top.
+    // At half rate all of it comes from the light buffer.
+    let norm = surface_normal(in);
+    var shading: Shading;
+    if (spot.shading.x == 1u) {
+        shading = buffered_light(in.clip_position.xy, in.view_depth);
+    } else {
+        shading = shade(in.world_position, norm);
+        shading.diffuse = shading.diffuse + spot_light(in.world_position, norm);
+    }
+    return vec4<f32>(color.rgb * shading.diffuse + shading.specular, color.a);
+}
+
+struct LightOutput {
+    // rgb: light, a: view depth.
+    @location(0) light: vec4<f32>,
+    // rgb: the highlights.
+    @location(1) specular: vec4<f32>,
+}
+
+// the light only, into the half rate light buffer.
+@fragment
+fn fs_light(in: VertexOutput) -> LightOutput {
+    if (clipped(in.world_position)) {
+        discard;
+    }
+    let norm = surface_normal(in);
+    let shading = shade(in.world_position, norm);
+    var out: LightOutput;
+    out.light = vec4<f32>(shading.diffuse + spot_light(in.world_position, norm), in.view_depth);
+    out.specular = vec4<f32>(shading.specular, 1.0);
+    return out;
+}
+
+// the depth only, into the sun's shadow map. What's clipped away casts no shadow.
+@fragment
+fn fs_shadow(in: VertexOutput) {
+    if (clipped(in.world_position)) {
+        discard;
+    }
+}
+
+// the simplified material of the secondary views, see lod.rs: the
+// texture with its uv transform, the vertex colors and the tint, unlit.
+@fragment
+fn fs_simple(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (clipped(in.world_position)) {
+        discard;
+    }
+    var color = textureSample(tex_diffuse, sampler_diffuse, transform_uv(in.uv));
+    if (material.vertex_colors == 1u) {
+        color = vec4<f32>(color.rgb * in.color, color.a);
+    } else if (material.vertex_colors == 2u) {
+        color = vec4<f32>(in.color, color.a);
+    }
+    return color * material.tint;
 }
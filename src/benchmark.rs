@@ -0,0 +1,253 @@
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use cgmath::{Point3, Vector3};
+use serde::Serialize;
+
+use crate::events::FrameStats;
+use crate::model::Bounds;
+
+/*
+    Benchmark mode: a fixed camera flight through the scene, timed.
+
+    The camera circles the scene once per run, on a path that only
+    depends on the bounds of the scene and the time, so two runs of
+    the same scene draw the same views. The first frames (pipeline
+    and texture uploads) are not counted.
+
+    The report has the frame times (average and percentiles), the
+    average draw statistics per frame and the estimated gpu memory.
+    Saved as .csv a new run is appended to the file, so the rows of
+    several commits end up next to each other, anything else is
+    written as json.
+
+    usage:
+        zhneeshyx --benchmark demo.scene --seconds 20 --out benchmark.csv
+
+        let mut benchmark = Benchmark::new(Duration::from_secs(20));
+        let path = CameraPath::orbit(&scene_bounds);
+        // every frame
+        let (eye, target) = path.at(benchmark.progress());
+        renderer.render_frame()?;
+        benchmark.record(&renderer.frame_stats());
+        if benchmark.is_finished() {
+            benchmark.report("demo.scene", renderer.estimated_gpu_memory(), "gpu").save("benchmark.csv")?;
+        }
+*/
+
+// frames at the start that aren't measured.
+const WARMUP_FRAMES: usize = 10;
+
+// Circle around the scene, slightly from above.
+#[derive(Debug, Copy, Clone)]
+pub struct CameraPath {
+    center: Point3<f32>,
+    radius: f32,
+    height: f32,
+}
+
+impl CameraPath {
+    pub fn orbit(bounds: &Bounds) -> Self {
+        let (center, radius) = if bounds.is_empty() {
+            ([0.0; 3], 1.0)
+        } else {
+            (bounds.center(), bounds.radius().max(0.1))
+        };
+        Self {
+            center: center.into(),
+            radius: radius * 2.0,
+            height: radius * 0.75,
+        }
+    }
+
+    // eye and target at `t`, 0 = start and 1 = end of the path.
+    pub fn at(&self, t: f32) -> (Point3<f32>, Point3<f32>) {
+        let angle = t * std::f32::consts::TAU;
+        let eye = self.center + Vector3::new(angle.cos() * self.radius, self.height, angle.sin() * self.radius);
+        (eye, self.center)
+    }
+
+    // far enough for the camera to see the whole scene from the path.
+    pub fn far_plane(&self) -> f32 {
+        (self.radius + self.height) * 2.0
+    }
+}
+
+pub struct Benchmark {
+    duration: Duration,
+    // set once the warmup frames are done.
+    start: Option<Instant>,
+    warmup: usize,
+    frame_times: Vec<Duration>,
+    // sums over the measured frames.
+    draw_calls: u64,
+    triangles: u64,
+    instances: u64,
+    culled: u64,
+    bind_group_switches: u64,
+}
+
+impl Benchmark {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            start: None,
+            warmup: 0,
+            frame_times: Vec::new(),
+            draw_calls: 0,
+            triangles: 0,
+            instances: 0,
+            culled: 0,
+            bind_group_switches: 0,
+        }
+    }
+
+    // how far along the run is, 0..1. Stays 0 during the warmup.
+    pub fn progress(&self) -> f32 {
+        match self.start {
+            Some(start) => (start.elapsed().as_secs_f32() / self.duration.as_secs_f32().max(0.001)).min(1.0),
+            None => 0.0,
+        }
+    }
+
+    // call after every rendered frame.
+    pub fn record(&mut self, stats: &FrameStats) {
+        if self.start.is_none() {
+            self.warmup += 1;
+            if self.warmup >= WARMUP_FRAMES {
+                self.start = Some(Instant::now());
+            }
+            return;
+        }
+        self.frame_times.push(stats.frame_time);
+        self.draw_calls += stats.draw_calls as u64;
+        self.triangles += stats.triangles as u64;
+        self.instances += stats.instances as u64;
+        self.culled += stats.culled as u64;
+        self.bind_group_switches += stats.bind_group_switches as u64;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.start.is_some_and(|start| start.elapsed() >= self.duration)
+    }
+
+    pub fn report(&self, scene: &str, gpu_memory: u64, adapter: &str) -> BenchmarkReport {
+        let mut times: Vec<f64> = self.frame_times.iter()
+            .map(|time| time.as_secs_f64() * 1000.0)
+            .collect();
+        times.sort_by(|a, b| a.total_cmp(b));
+
+        let frames = times.len();
+        let total: f64 = times.iter().sum();
+        let per_frame = |sum: u64| if frames == 0 { 0.0 } else { sum as f64 / frames as f64 };
+
+        BenchmarkReport {
+            scene: scene.to_string(),
+            adapter: adapter.to_string(),
+            seconds: self.start.map_or(0.0, |start| start.elapsed().as_secs_f64()),
+            frames,
+            fps: if total > 0.0 { frames as f64 * 1000.0 / total } else { 0.0 },
+            frame_ms_avg: if frames == 0 { 0.0 } else { total / frames as f64 },
+            frame_ms_min: times.first().copied().unwrap_or(0.0),
+            frame_ms_p50: percentile(&times, 50.0),
+            frame_ms_p90: percentile(&times, 90.0),
+            frame_ms_p95: percentile(&times, 95.0),
+            frame_ms_p99: percentile(&times, 99.0),
+            frame_ms_max: times.last().copied().unwrap_or(0.0),
+            draw_calls: per_frame(self.draw_calls),
+            triangles: per_frame(self.triangles),
+            instances: per_frame(self.instances),
+            culled: per_frame(self.culled),
+            bind_group_switches: per_frame(self.bind_group_switches),
+            gpu_memory_bytes: gpu_memory,
+        }
+    }
+}
+
+// of sorted values.
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = (percent / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+// The draw statistics are averages per frame.
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchmarkReport {
+    pub scene: String,
+    pub adapter: String,
+    pub seconds: f64,
+    pub frames: usize,
+    pub fps: f64,
+    pub frame_ms_avg: f64,
+    pub frame_ms_min: f64,
+    pub frame_ms_p50: f64,
+    pub frame_ms_p90: f64,
+    pub frame_ms_p95: f64,
+    pub frame_ms_p99: f64,
+    pub frame_ms_max: f64,
+    pub draw_calls: f64,
+    pub triangles: f64,
+    pub instances: f64,
+    pub culled: f64,
+    pub bind_group_switches: f64,
+    // estimated, see Renderer::estimated_gpu_memory().
+    pub gpu_memory_bytes: u64,
+}
+
+const CSV_HEADER: &str = "scene,adapter,seconds,frames,fps,frame_ms_avg,frame_ms_min,frame_ms_p50,\
+    frame_ms_p90,frame_ms_p95,frame_ms_p99,frame_ms_max,draw_calls,triangles,instances,culled,\
+    bind_group_switches,gpu_memory_bytes";
+
+impl BenchmarkReport {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // one row, without the header.
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{:.3},{},{:.2},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.1},{:.1},{:.1},{:.1},{:.1},{}",
+            csv_field(&self.scene), csv_field(&self.adapter), self.seconds, self.frames, self.fps,
+            self.frame_ms_avg, self.frame_ms_min, self.frame_ms_p50, self.frame_ms_p90,
+            self.frame_ms_p95, self.frame_ms_p99, self.frame_ms_max,
+            self.draw_calls, self.triangles, self.instances, self.culled, self.bind_group_switches,
+            self.gpu_memory_bytes,
+        )
+    }
+
+    // .csv files get a new row (and the header when they are new), everything else json.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let csv = path.extension().and_then(|ext| ext.to_str()) == Some("csv");
+        if !csv {
+            return std::fs::write(path, self.to_json()?)
+                .with_context(|| format!("Unable to write benchmark report {:?}.", path));
+        }
+
+        let new = !path.exists();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Unable to open benchmark report {:?}.", path))?;
+        if new {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        writeln!(file, "{}", self.to_csv_row())
+            .with_context(|| format!("Unable to write benchmark report {:?}.", path))
+    }
+}
+
+// quoted when it would break the row.
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,114 @@
+/*
+    Gpu memory budget.
+
+    wgpu can't tell how much video memory the gpu has, nor how much of
+    it is in use. So both are estimates: the budget is guessed from the
+    kind of adapter (or set by the application), the usage is what the
+    renderer knows it created, see Renderer::estimated_gpu_memory().
+
+    Once the usage crosses WARNING_FRACTION or CRITICAL_FRACTION of the
+    budget the renderer logs it and emits RendererEvent::MemoryBudget,
+    and again when it drops back below. Running out for real makes
+    get_current_texture() fail with OutOfMemory, the warning comes
+    before that, while there is still time to free something.
+
+    There is no streaming in the tree that could evict models on its
+    own, the event is where the application frees what it holds on to
+    (undo history, hidden models, cached thumbnails, ...).
+
+    usage:
+        let mut renderer = RendererBuilder::new().memory_budget(2 << 30).build(&event_loop)?;
+        renderer.on_event(|event| {
+            if let RendererEvent::MemoryBudget { level: BudgetLevel::Critical, .. } = event {
+                // free something
+            }
+        });
+*/
+
+pub const WARNING_FRACTION: f64 = 0.8;
+pub const CRITICAL_FRACTION: f64 = 0.95;
+// a level is only left once the usage is this far below it, so it doesn't
+// flicker while a model is loaded and unloaded around the threshold.
+const HYSTERESIS: f64 = 0.05;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BudgetLevel {
+    Normal,
+    Warning,
+    Critical,
+}
+
+// A guess for adapters without a budget of the application:
+// dedicated memory of a typical discrete gpu, less for the ones sharing
+// the system memory (which the rest of the system needs as well).
+pub fn default_budget(info: &wgpu::AdapterInfo) -> u64 {
+    const GIB: u64 = 1 << 30;
+    match info.device_type {
+        wgpu::DeviceType::DiscreteGpu => 4 * GIB,
+        wgpu::DeviceType::IntegratedGpu | wgpu::DeviceType::VirtualGpu | wgpu::DeviceType::Other => 2 * GIB,
+        wgpu::DeviceType::Cpu => GIB,
+    }
+}
+
+// bytes of a texture with all of its mips, layers and samples.
+pub fn texture_size(texture: &wgpu::Texture) -> u64 {
+    let block_size = texture.format().block_size(None).unwrap_or(4) as u64;
+    let layers = texture.depth_or_array_layers() as u64 * texture.sample_count() as u64;
+    (0..texture.mip_level_count())
+        .map(|mip| {
+            let width = (texture.width() >> mip).max(1) as u64;
+            let height = (texture.height() >> mip).max(1) as u64;
+            width * height * layers * block_size
+        })
+        .sum()
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryBudget {
+    budget: u64,
+    level: BudgetLevel,
+}
+
+impl MemoryBudget {
+    pub fn new(budget: u64) -> Self {
+        Self { budget, level: BudgetLevel::Normal }
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    // the level stays until the next update().
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+
+    pub fn level(&self) -> BudgetLevel {
+        self.level
+    }
+
+    // Some(level) when `used` bytes moved the usage into another level.
+    pub fn update(&mut self, used: u64) -> Option<BudgetLevel> {
+        let fraction = used as f64 / self.budget.max(1) as f64;
+        let level_at = |offset: f64| {
+            if fraction >= CRITICAL_FRACTION - offset {
+                BudgetLevel::Critical
+            } else if fraction >= WARNING_FRACTION - offset {
+                BudgetLevel::Warning
+            } else {
+                BudgetLevel::Normal
+            }
+        };
+        let rising = level_at(0.0);
+        let level = if rising > self.level {
+            rising
+        } else {
+            level_at(HYSTERESIS).min(self.level)
+        };
+        if level == self.level {
+            return None;
+        }
+        self.level = level;
+        Some(level)
+    }
+}
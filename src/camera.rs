@@ -5,6 +5,144 @@ use std::time::Duration;
 use std::f32::consts::FRAC_PI_2;
 
 use std::cell::Cell;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{bail, Context, Result};
+
+use crate::math::{self, Frustum};
+
+// How the CameraController moves the camera. Kept in a small text file
+// (see load() / save()), one setting per line:
+//
+//     # camera controls
+//     move_speed 1
+//     rotate_speed 1
+//     scroll_speed 1
+//     sensitivity 1
+//     invert_y false
+//     sprint_multiplier 3
+//
+// settings that are missing keep their default.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ControllerSettings {
+    // times the controller's move speed (units per second) forwards / backwards.
+    pub move_speed: f32,
+    // times its turn speed (radians per second) that A / D circle around
+    // the target (or turn, flying) and Q / E roll.
+    pub rotate_speed: f32,
+    // of the mouse wheel.
+    pub scroll_speed: f32,
+    // of mouse drags.
+    pub sensitivity: f32,
+    // dragging up moves the view down.
+    pub invert_y: bool,
+    // move and rotate speed while the right Shift is held, the left Shift
+    // moves down. Not Ctrl, Ctrl+D / Ctrl+S / Ctrl+E are bound to actions
+    // in the viewer and would fire while sprinting.
+    pub sprint_multiplier: f32,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        Self {
+            move_speed: 1.0,
+            rotate_speed: 1.0,
+            scroll_speed: 1.0,
+            sensitivity: 1.0,
+            invert_y: false,
+            sprint_multiplier: 3.0,
+        }
+    }
+}
+
+impl ControllerSettings {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read the camera controls {:?}.", path))?;
+        Self::parse(&text).with_context(|| format!("Unable to parse the camera controls {:?}.", path))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_text())
+            .with_context(|| format!("Unable to write the camera controls {:?}.", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut settings = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (key, value) = match (words.next(), words.next(), words.next()) {
+                (Some(key), Some(value), None) => (key, value),
+                _ => bail!("Line {}: expected a setting and its value.", number + 1),
+            };
+            let number_value = || value.parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .with_context(|| format!("Line {}: {:?} is no number.", number + 1, value));
+            match key {
+                "move_speed" => settings.move_speed = number_value()?,
+                "rotate_speed" => settings.rotate_speed = number_value()?,
+                "scroll_speed" => settings.scroll_speed = number_value()?,
+                "sensitivity" => settings.sensitivity = number_value()?,
+                "sprint_multiplier" => settings.sprint_multiplier = number_value()?,
+                "invert_y" => {
+                    settings.invert_y = value.parse::<bool>()
+                        .ok()
+                        .with_context(|| format!("Line {}: invert_y is true or false.", number + 1))?;
+                }
+                _ => bail!("Line {}: unknown setting {:?}.", number + 1, key),
+            }
+        }
+        Ok(settings)
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "# camera controls\nmove_speed {}\nrotate_speed {}\nscroll_speed {}\nsensitivity {}\ninvert_y {}\nsprint_multiplier {}\n",
+            self.move_speed, self.rotate_speed, self.scroll_speed, self.sensitivity, self.invert_y, self.sprint_multiplier,
+        )
+    }
+}
+
+// How the CameraController moves the camera.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControllerMode {
+    // W / S move to and from the target, A / D circle around it.
+    Orbit,
+    // like an aircraft: W / S forward and back, A / D turn, Space / LShift
+    // up and down along the camera's up.
+    Fly,
+}
+
+// world units per second the camera moves at move_speed 1, what was a
+// unit per frame at 60 fps before the movement went by time.
+pub const DEFAULT_MOVE_SPEED: f32 = 60.0;
+// radians per second, at rotate_speed 1, that Q / E roll and A / D turn
+// in fly mode or circle around the target in orbit mode.
+pub const DEFAULT_TURN_SPEED: f32 = 1.2;
+// radians per pixel of look(). Drags move by what the mouse did, not by time.
+const LOOK_STEP: f32 = 0.005;
+// the part of the wheel turns not applied yet that is applied every
+// SCROLL_SMOOTHING_STEP, so zooming eases out instead of jumping.
+const SCROLL_SMOOTHING: f32 = 0.25;
+const SCROLL_SMOOTHING_STEP: f32 = 1.0 / 60.0;
+// one wheel line dollies this part of the distance to the target (orbit
+// mode) or changes the field of view by this many degrees (fly mode).
+const DOLLY_STEP: f32 = 0.1;
+const FOV_STEP: f32 = 2.0;
+// dollying stops at these distances to the target.
+const MIN_DOLLY_DISTANCE: f32 = 0.1;
+const MAX_DOLLY_DISTANCE: f32 = 5000.0;
+// and zooming at these fields of view, in degrees.
+const MIN_FOVY: f32 = 10.0;
+const MAX_FOVY: f32 = 120.0;
 
 #[derive(Debug)]
 pub struct CameraController {
@@ -18,17 +156,21 @@ pub struct CameraController {
     move_backward: Cell<bool>,
     move_up: Cell<bool>,
     move_down: Cell<bool>,
-
-    move_speed: f32,
+    roll_left: Cell<bool>,
+    roll_right: Cell<bool>,
+    sprint: Cell<bool>,
+    // wheel lines not applied yet, up is positive.
+    scroll: Cell<f32>,
 
     rotate: Cell<bool>,
     rotate_horizontal : Cell<f32>,
     rotate_vertical: Cell<f32>,
-    
-    rotate_speed: f32,
 
-    scroll_speed: f32,
-    sensitivity: f32,
+    settings: ControllerSettings,
+    mode: ControllerMode,
+    // units per second and radians per second, times the settings.
+    move_speed: f32,
+    turn_speed: f32,
 }
 
 
@@ -41,20 +183,80 @@ impl CameraController {
             move_backward: Cell::new(false),
             move_up: Cell::new(false),
             move_down: Cell::new(false),
-
-            move_speed: 1.0,
+            roll_left: Cell::new(false),
+            roll_right: Cell::new(false),
+            sprint: Cell::new(false),
+            scroll: Cell::new(0.0),
 
             rotate: Cell::new(false),
             rotate_horizontal : Cell::new(0.0),
             rotate_vertical: Cell::new(0.0),
 
-            rotate_speed: 1.0,
-
-            scroll_speed: 1.0,
-            sensitivity: 1.0,
+            settings: ControllerSettings::default(),
+            mode: ControllerMode::Orbit,
+            move_speed: DEFAULT_MOVE_SPEED,
+            turn_speed: DEFAULT_TURN_SPEED,
         }
     }
 
+    pub fn mode(&self) -> ControllerMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ControllerMode) {
+        self.mode = mode;
+    }
+
+    pub fn with_settings(settings: ControllerSettings) -> Self {
+        Self { settings, ..Self::new() }
+    }
+
+    pub fn settings(&self) -> &ControllerSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut ControllerSettings {
+        &mut self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ControllerSettings) {
+        self.settings = settings;
+    }
+
+    // world units per second at move_speed 1, e.g. to fit the size of the scene.
+    pub fn move_speed(&self) -> f32 {
+        self.move_speed
+    }
+
+    pub fn set_move_speed(&mut self, units_per_second: f32) {
+        self.move_speed = units_per_second;
+    }
+
+    // radians per second at rotate_speed 1.
+    pub fn turn_speed(&self) -> f32 {
+        self.turn_speed
+    }
+
+    pub fn set_turn_speed(&mut self, radians_per_second: f32) {
+        self.turn_speed = radians_per_second;
+    }
+
+    // speeds are multiplied with this, the sprint multiplier while sprinting.
+    fn speed_factor(&self) -> f32 {
+        if self.sprint.get() { self.settings.sprint_multiplier } else { 1.0 }
+    }
+
+    // a mouse drag of `dx` / `dy` pixels with the sensitivity and invert_y applied.
+    pub fn mouse_delta(&self, dx: f32, dy: f32) -> (f32, f32) {
+        let dy = if self.settings.invert_y { -dy } else { dy };
+        (dx * self.settings.sensitivity, dy * self.settings.sensitivity)
+    }
+
+    // wheel lines with the scroll speed applied.
+    pub fn scroll_delta(&self, lines: f32) -> f32 {
+        lines * self.settings.scroll_speed
+    }
+
     pub fn process_keydown(&self, key: VirtualKeyCode) {
         match key {
             VirtualKeyCode::W | VirtualKeyCode::Up => {
@@ -75,6 +277,15 @@ impl CameraController {
             VirtualKeyCode::LShift => {
                 self.move_down.set(true);
             }
+            VirtualKeyCode::Q => {
+                self.roll_left.set(true);
+            }
+            VirtualKeyCode::E => {
+                self.roll_right.set(true);
+            }
+            VirtualKeyCode::RShift => {
+                self.sprint.set(true);
+            }
             _ => (),
         }
     }
@@ -99,115 +310,471 @@ impl CameraController {
             VirtualKeyCode::LShift => {
                 self.move_down.set(false);
             }
+            VirtualKeyCode::Q => {
+                self.roll_left.set(false);
+            }
+            VirtualKeyCode::E => {
+                self.roll_right.set(false);
+            }
+            VirtualKeyCode::RShift => {
+                self.sprint.set(false);
+            }
             _ => (),
         }
     }
+    // lets go of every key, e.g. when the keys go to a text field.
+    pub fn release_all(&self) {
+        self.move_left.set(false);
+        self.move_right.set(false);
+        self.move_forward.set(false);
+        self.move_backward.set(false);
+        self.move_up.set(false);
+        self.move_down.set(false);
+        self.roll_left.set(false);
+        self.roll_right.set(false);
+        self.sprint.set(false);
+        self.scroll.set(0.0);
+    }
+
+    // a mouse drag of `dx` / `dy` pixels (see mouse_delta()): circles
+    // around the target in orbit mode, turns the camera in fly mode.
+    pub fn look(&self, camera: &mut Camera, dx: f32, dy: f32) {
+        let (yaw, pitch) = (Rad(-dx * LOOK_STEP), Rad(-dy * LOOK_STEP));
+        match self.mode {
+            ControllerMode::Orbit => camera.orbit(yaw, -pitch),
+            ControllerMode::Fly => camera.fly(yaw, pitch, Rad(0.0)),
+        }
+    }
 /*
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
         self.rotate_horizontal = mouse_dx as f32;
         self.rotate_vertical = mouse_dy as f32;
     }
-
-    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
-        self.scroll = -match delta {
+*/
+    // the wheel zooms: in orbit mode it dollies towards / away from the
+    // target, in fly mode it narrows / widens the field of view. Applied
+    // over the next frames by update_camera().
+    pub fn process_scroll(&self, delta: &MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, scroll) => *scroll,
             // I'm assuming a line is about 100 pixels
-            MouseScrollDelta::LineDelta(_, scroll) => scroll * 100.0,
             MouseScrollDelta::PixelDelta(PhysicalPosition {
                 y: scroll,
                 ..
-            }) => *scroll as f32,
+            }) => *scroll as f32 / 100.0,
         };
+        self.scroll.set(self.scroll.get() + self.scroll_delta(lines));
     }
-*/
-    pub fn update_camera(&mut self, camera: &mut Camera) {
+
+    // moves the camera by what the held keys and the wheel do in `dt`,
+    // the time since the last call, so it moves as fast at any frame rate.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
         use cgmath::InnerSpace;
 
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
+        let dt = dt.as_secs_f32();
+        let step = self.move_speed * self.settings.move_speed * self.speed_factor() * dt;
+        let turn = self.turn_speed * self.settings.rotate_speed * self.speed_factor() * dt;
+
+        let smoothing = 1.0 - (1.0 - SCROLL_SMOOTHING).powf(dt / SCROLL_SMOOTHING_STEP);
+        let scroll = self.scroll.get() * smoothing;
+        // the rest would take forever.
+        let scroll = if scroll.abs() < 1e-3 { self.scroll.get() } else { scroll };
+        self.scroll.set(self.scroll.get() - scroll);
+        if scroll != 0.0 {
+            match self.mode {
+                ControllerMode::Orbit => {
+                    let distance = camera.distance() * (1.0 - DOLLY_STEP).powf(scroll);
+                    camera.set_distance(distance.clamp(MIN_DOLLY_DISTANCE, MAX_DOLLY_DISTANCE));
+                }
+                ControllerMode::Fly => {
+                    camera.set_fovy((camera.fovy() - scroll * FOV_STEP).clamp(MIN_FOVY, MAX_FOVY));
+                }
+            }
+        }
+
+        let roll = self.roll_right.get() as i32 - self.roll_left.get() as i32;
+        if roll != 0 {
+            camera.roll(Rad(roll as f32 * turn));
+        }
+
+        let yaw = self.move_left.get() as i32 - self.move_right.get() as i32;
+        if self.mode == ControllerMode::Fly {
+            let mut offset = Vector3::zero();
+            if self.move_forward.get() {
+                offset += camera.forward() * step;
+            }
+            if self.move_backward.get() {
+                offset -= camera.forward() * step;
+            }
+            if self.move_up.get() {
+                offset += camera.up() * step;
+            }
+            if self.move_down.get() {
+                offset -= camera.up() * step;
+            }
+            camera.translate(offset);
+            if yaw != 0 {
+                camera.fly(Rad(yaw as f32 * turn), Rad(0.0), Rad(0.0));
+            }
+            return;
+        }
+
+        let forward_norm = (camera.target() - camera.eye()).normalize();
 
        // Prevents glitching when camera gets too close to the
         // center of the scene.
-        if self.move_forward.get() /*&& ( forward_mag > self.move_speed ) */ {
-            camera.eye += forward_norm * self.move_speed;
+        if self.move_forward.get() /*&& ( forward_mag > step ) */ {
+            camera.set_eye(camera.eye() + forward_norm * step);
         }
         if self.move_backward.get() {
-            camera.eye -= forward_norm * self.move_speed;
+            camera.set_eye(camera.eye() - forward_norm * step);
         }
 
-        let right = forward_norm.cross(camera.up);
-
-        // Redo radius calc in case the up/ down is pressed.
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.magnitude();
-
-        if self.move_right.get() {
-            // Rescale the distance between the target and eye so 
-            // that it doesn't change. The eye therefore still 
-            // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.move_speed).normalize() * forward_mag;
-        }
-        if self.move_left.get() {
-            camera.eye = camera.target - (forward - right * self.move_speed).normalize() * forward_mag;
+        // A / D circle around the target at the same distance, D moves
+        // the eye to the left.
+        if yaw != 0 {
+            camera.orbit(Rad(yaw as f32 * turn), Rad(0.0));
         }
     }
 }
 
 
-/*
-    - cgmath crate is built for OpenGL's coordinate system.
-    - this matrix translates and scales our scene from
-     OpenGLs coordinate system to WGPU's.
-*/
-#[rustfmt::skip]
-pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.0,
-    0.0, 0.0, 0.5, 1.0,
-);
+// moved to math.rs with the rest of the view / projection math.
+pub use crate::math::OPENGL_TO_WGPU_MATRIX;
 
+// fit_clip_planes() leaves this much room (as a part of the depth) in
+// front of and behind the boxes.
+const CLIP_MARGIN: f32 = 0.05;
+// the near plane never gets closer than this part of the far plane, the
+// depth precision is mostly decided by far / near.
+const MIN_NEAR_RATIO: f32 = 1e-4;
+// rotate() and orbit() keep the view direction at least this far (radians)
+// from straight up and down, where look_at() has no sideways direction.
+const MIN_PITCH_TO_UP: f32 = 0.01;
+
+// revisions are unique over all cameras, a camera that replaces another
+// one never has the revision the old one was uploaded with.
+static REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+// The camera keeps where it is (eye), which way it's turned (orientation,
+// looking down its -z with +y up, like the view space) and how far ahead
+// the point it looks at is (distance, the target). Turning with the
+// quaternion has no gimbal lock and can roll, for flying; look_at() and
+// the other target based methods keep the camera upright to `world_up`,
+// with the roll it has.
 #[derive(Debug)]
 pub struct Camera {
     eye: cgmath::Point3<f32>,
-    target: cgmath::Point3<f32>,
-    up: cgmath::Vector3<f32>,
+    orientation: Quaternion<f32>,
+    // from the eye to the target.
+    distance: f32,
+    // what look_at() and rotate() keep the camera upright to.
+    world_up: cgmath::Vector3<f32>,
     aspect: f32,
     fovy: f32,
     znear: f32,
     zfar: f32,
+    // changes with every change, see revision().
+    revision: u64,
 }
 
 impl Camera {
     pub fn new(config: &wgpu::SurfaceConfiguration) -> Self {
-        Self {
+        // position the camera one unit up and 2 units back
+        // +z is out of the screen, have it look at the origin.
+        Self::looking_at((0.0, 1.0, 2.0).into(), (0.0, 0.0, 0.0).into(), math::aspect_ratio(config.width, config.height))
+    }
 
+    // camera at `eye` looking at `target`, with the default projection.
+    pub fn looking_at(eye: Point3<f32>, target: Point3<f32>, aspect: f32) -> Self {
+        let mut camera = Self {
             //// view ////
-
-            // position the camera one unit up and 2 units back
-            // +z is out of the screen
-            eye: (0.0, 1.0, 2.0).into(),
-            // have it look at the origin
-            target: (0.0, 0.0, 0.0).into(),
+            eye,
+            orientation: Quaternion::one(),
+            distance: 1.0,
             // which way is "up"
-            up: cgmath::Vector3::unit_y(),
+            world_up: cgmath::Vector3::unit_y(),
 
             //// projection ////
-
-            aspect: config.width as f32 / config.height as f32,
+            aspect,
             fovy: 45.0,
             znear: 0.1,
             zfar: 100.0,
+            revision: next_revision(),
+        };
+        camera.look_at(eye, target);
+        camera
+    }
+
+    // Changes with every change of the view or the projection. The renderer
+    // only uploads cameras whose revision changed, and reports them with
+    // RendererEvent::CameraChanged. Others can do the same: keep the
+    // revision and compare.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // sets `value` and a new revision, if it is a change.
+    fn change<T: PartialEq>(value: &mut T, new: T, revision: &mut u64) {
+        if *value != new {
+            *value = new;
+            *revision = next_revision();
         }
     }
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+
+    // moves the camera to `eye` and turns it to `target`, upright to
+    // world_up() but with the roll it had. The projection stays.
+    pub fn look_at(&mut self, eye: Point3<f32>, target: Point3<f32>) {
+        Self::change(&mut self.eye, eye, &mut self.revision);
+        let direction = target - eye;
+        let distance = direction.magnitude();
+        if distance <= f32::EPSILON {
+            return;
+        }
+        let roll = self.roll_angle();
+        let orientation = self.upright(direction) * Quaternion::from_axis_angle(-Vector3::unit_z(), roll);
+        Self::change(&mut self.orientation, orientation, &mut self.revision);
+        Self::change(&mut self.distance, distance, &mut self.revision);
+    }
+
+    // the orientation looking along `direction` without roll. Straight up or
+    // down there is no upright, the camera keeps its sideways direction.
+    fn upright(&self, direction: Vector3<f32>) -> Quaternion<f32> {
+        let forward = direction.normalize();
+        let mut right = forward.cross(self.world_up);
+        if right.magnitude2() <= f32::EPSILON {
+            right = self.orientation.rotate_vector(Vector3::unit_x());
+            right -= forward * right.dot(forward);
+        }
+        let right = right.normalize();
+        let up = right.cross(forward);
+        Quaternion::from(Matrix3::from_cols(right, up, -forward)).normalize()
+    }
+
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) {
+        Self::change(&mut self.znear, znear, &mut self.revision);
+        Self::change(&mut self.zfar, zfar, &mut self.revision);
+    }
+
+    // vertical field of view in degrees.
+    pub fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    pub fn set_fovy(&mut self, fovy: f32) {
+        Self::change(&mut self.fovy, fovy, &mut self.revision);
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    // width / height of what the camera draws into, follows the window size.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        Self::change(&mut self.aspect, aspect, &mut self.revision);
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.eye
+    }
+
+    // moves the eye, the camera keeps looking at the target.
+    pub fn set_eye(&mut self, eye: Point3<f32>) {
+        self.look_at(eye, self.target());
+    }
+
+    // the point `distance` ahead of the eye.
+    pub fn target(&self) -> Point3<f32> {
+        self.eye + self.forward() * self.distance
+    }
+
+    pub fn set_target(&mut self, target: Point3<f32>) {
+        self.look_at(self.eye, target);
+    }
+
+    // from the eye to the target.
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    // dollies: moves the eye along the view direction, to `distance` from the target.
+    pub fn set_distance(&mut self, distance: f32) {
+        if distance <= f32::EPSILON {
+            return;
+        }
+        let eye = self.target() - self.forward() * distance;
+        Self::change(&mut self.eye, eye, &mut self.revision);
+        Self::change(&mut self.distance, distance, &mut self.revision);
+    }
+
+    // camera space -> world space rotation.
+    pub fn orientation(&self) -> Quaternion<f32> {
+        self.orientation
+    }
+
+    // turns the camera, the target turns with it.
+    pub fn set_orientation(&mut self, orientation: Quaternion<f32>) {
+        Self::change(&mut self.orientation, orientation.normalize(), &mut self.revision);
+    }
+
+    // which way is up on the screen, tilted by the roll.
+    pub fn up(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(Vector3::unit_y())
+    }
+
+    // +y unless set otherwise.
+    pub fn world_up(&self) -> Vector3<f32> {
+        self.world_up
+    }
+
+    // the camera stays turned to the target, upright to the new up.
+    pub fn set_world_up(&mut self, up: Vector3<f32>) {
+        if up.magnitude2() <= f32::EPSILON {
+            return;
+        }
+        Self::change(&mut self.world_up, up.normalize(), &mut self.revision);
+        let target = self.target();
+        self.look_at(self.eye, target);
+    }
+
+    // unit vector from the eye to the target.
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(-Vector3::unit_z())
+    }
+
+    // unit vector to the right on the screen.
+    pub fn right(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(Vector3::unit_x())
+    }
+
+    // how far the camera is rolled from upright to world_up(), positive to the right.
+    pub fn roll_angle(&self) -> Rad<f32> {
+        let forward = self.forward();
+        if forward.cross(self.world_up).magnitude2() <= f32::EPSILON {
+            return Rad(0.0);
+        }
+        let up = self.upright(forward).invert().rotate_vector(self.up());
+        Rad(up.x.atan2(up.y))
+    }
+
+    // rolls the camera around the view direction, positive to the right.
+    pub fn roll<A: Into<Rad<f32>>>(&mut self, angle: A) {
+        self.turn_local(Quaternion::from_axis_angle(-Vector3::unit_z(), angle.into()));
+    }
+
+    // turns the camera around its own axes like an aircraft: `yaw` to the
+    // left (positive) or right, `pitch` up (positive) or down and `roll`
+    // to the right (positive), without stopping at straight up or down.
+    pub fn fly<A: Into<Rad<f32>>>(&mut self, yaw: A, pitch: A, roll: A) {
+        let rotation = Quaternion::from_axis_angle(Vector3::unit_y(), yaw.into())
+            * Quaternion::from_axis_angle(Vector3::unit_x(), pitch.into())
+            * Quaternion::from_axis_angle(-Vector3::unit_z(), roll.into());
+        self.turn_local(rotation);
+    }
+
+    // `rotation` in camera space.
+    fn turn_local(&mut self, rotation: Quaternion<f32>) {
+        self.set_orientation(self.orientation * rotation);
+    }
+
+    // moves eye and target by `offset`, the view direction stays.
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        let eye = self.eye + offset;
+        Self::change(&mut self.eye, eye, &mut self.revision);
+    }
+
+    // turns the view around the eye: `yaw` around world_up() (positive to
+    // the left), `pitch` up (positive) or down. The target stays as far away.
+    pub fn rotate<A: Into<Rad<f32>>>(&mut self, yaw: A, pitch: A) {
+        let direction = self.turn(self.target() - self.eye, yaw.into(), pitch.into());
+        self.set_target(self.eye + direction);
+    }
+
+    // moves the eye around the target at the same distance, e.g. to turn
+    // around a model: `yaw` around world_up(), `pitch` raises (positive)
+    // or lowers the eye.
+    pub fn orbit<A: Into<Rad<f32>>>(&mut self, yaw: A, pitch: A) {
+        let target = self.target();
+        let back = self.turn(self.eye - target, yaw.into(), pitch.into());
+        self.look_at(target + back, target);
+    }
+
+    // `direction` turned by `yaw` around up and `pitch` towards up, the pitch
+    // stops short of up and down.
+    fn turn(&self, direction: Vector3<f32>, yaw: Rad<f32>, pitch: Rad<f32>) -> Vector3<f32> {
+        let up = self.world_up;
+        let direction = Quaternion::from_axis_angle(up, yaw).rotate_vector(direction);
+        let side = direction.cross(up);
+        if side.magnitude2() <= f32::EPSILON {
+            return direction;
+        }
+        // pitching up makes the angle to up smaller.
+        let angle = direction.angle(up).0;
+        let pitch = pitch.0.clamp(angle - (std::f32::consts::PI - MIN_PITCH_TO_UP), angle - MIN_PITCH_TO_UP);
+        Quaternion::from_axis_angle(side.normalize(), Rad(pitch)).rotate_vector(direction)
+    }
+
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
+    // moves znear and zfar to just around the boxes (world space min/max
+    // corners) the camera sees, for the best depth precision the scene
+    // allows. False, and the planes stay, when there is nothing in front
+    // of the camera.
+    pub fn fit_clip_planes<I: IntoIterator<Item = ([f32; 3], [f32; 3])>>(&mut self, boxes: I) -> bool {
+        let view = self.view_matrix();
+        // the near and far planes are what's being looked for, only the sides decide.
+        let frustum = self.frustum();
+        let (mut near, mut far) = (f32::MAX, f32::MIN);
+        for (min, max) in boxes {
+            if min[0] > max[0] || !frustum.intersects_box_sides(min, max) {
+                continue;
+            }
+            for corner in 0..8 {
+                let pick = |axis: usize| if corner & (1 << axis) == 0 { min[axis] } else { max[axis] };
+                // the camera looks down -z.
+                let depth = -(view * Vector4::new(pick(0), pick(1), pick(2), 1.0)).z;
+                near = near.min(depth);
+                far = far.max(depth);
+            }
+        }
+        if far <= 0.0 {
+            return false;
+        }
+        let far = far * (1.0 + CLIP_MARGIN);
+        // inside a box the nearest corner is behind the camera.
+        let near = (near * (1.0 - CLIP_MARGIN)).max(far * MIN_NEAR_RATIO);
+        self.set_clip_planes(near, far);
+        true
+    }
+
+    // world -> camera space.
+    pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
+        math::look_at(self.eye, self.eye + self.forward(), self.up())
+    }
+
+    // camera space -> clip space, already in wgpu's depth range.
+    pub fn projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        math::perspective(self.fovy, self.aspect, self.znear, self.zfar)
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.build_view_projection_matrix())
+    }
+
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         // 1.
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+        let view = self.view_matrix();
         // 2.
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        let proj = self.projection_matrix();
 
         // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        proj * view
     }
 }
 
@@ -232,6 +799,40 @@ impl UniformBuffer {
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix().into();
     }
+
+    // a view projection that isn't a camera's, e.g. of an eye (see stereo.rs).
+    pub fn set_view_proj(&mut self, view_proj: cgmath::Matrix4<f32>) {
+        self.view_proj = view_proj.into();
+    }
+
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // the per frame uniform, see frame.rs.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("camera_bind_group_layout"),
+        })
+    }
 }
 
 
@@ -267,4 +868,123 @@ impl Projection {
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a frame at 60 fps.
+    const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+    #[test]
+    fn set_aspect_changes_the_projection() {
+        let mut camera = Camera::looking_at((0.0, 1.0, 2.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+        let square = camera.projection_matrix();
+        camera.set_aspect(math::aspect_ratio(1600, 900));
+        assert_eq!(camera.aspect(), 16.0 / 9.0);
+        let wide = camera.projection_matrix();
+        assert!((wide.x.x * 16.0 / 9.0 - square.x.x).abs() < 1e-5);
+        assert_eq!(wide.y.y, square.y.y);
+    }
+
+    #[test]
+    fn uniform_holds_the_view_projection() {
+        let camera = Camera::looking_at((3.0, 2.0, 1.0).into(), (0.0, 0.0, 0.0).into(), 1.5);
+        let mut uniform = UniformBuffer::new();
+        uniform.update_view_proj(&camera);
+        let expected: [[f32; 4]; 4] = (camera.projection_matrix() * camera.view_matrix()).into();
+        assert_eq!(uniform.view_proj, expected);
+    }
+
+    #[test]
+    fn rotate_and_orbit_keep_the_distance() {
+        let mut camera = Camera::looking_at((0.0, 0.0, 2.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+        let revision = camera.revision();
+        camera.rotate(Deg(90.0), Deg(0.0));
+        assert!((camera.target() - Point3::new(-2.0, 0.0, 2.0)).magnitude() < 1e-5);
+        assert_ne!(camera.revision(), revision);
+
+        camera.look_at((0.0, 0.0, 2.0).into(), (0.0, 0.0, 0.0).into());
+        camera.orbit(Deg(0.0), Deg(45.0));
+        assert!(camera.eye().y > 0.0);
+        assert!(((camera.eye() - camera.target()).magnitude() - 2.0).abs() < 1e-5);
+        // never over the top.
+        camera.orbit(Deg(0.0), Deg(90.0));
+        assert!(camera.forward().angle(camera.world_up()).0 > std::f32::consts::PI - 0.02);
+
+        let revision = camera.revision();
+        camera.set_eye(camera.eye());
+        assert_eq!(camera.revision(), revision);
+    }
+
+    #[test]
+    fn look_at_keeps_the_roll() {
+        let mut camera = Camera::looking_at((0.0, 0.0, 2.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+        assert!(camera.roll_angle().0.abs() < 1e-6);
+        camera.roll(Deg(30.0));
+        assert!((camera.up() - Vector3::new(0.5, 0.75f32.sqrt(), 0.0)).magnitude() < 1e-5);
+        camera.look_at((2.0, 1.0, 0.0).into(), (0.0, 0.0, 0.0).into());
+        assert!((Deg::from(camera.roll_angle()).0 - 30.0).abs() < 1e-3);
+
+        // flying loops over the top, where look_at() stops.
+        let mut camera = Camera::looking_at((0.0, 0.0, 2.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+        camera.fly(Deg(0.0), Deg(180.0), Deg(0.0));
+        assert!((camera.forward() - Vector3::unit_z()).magnitude() < 1e-5);
+        assert!((camera.up() + Vector3::unit_y()).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn controller_settings_round_trip() {
+        let settings = ControllerSettings { move_speed: 2.5, invert_y: true, ..Default::default() };
+        assert_eq!(ControllerSettings::parse(&settings.to_text()).unwrap(), settings);
+        assert_eq!(ControllerSettings::parse("sensitivity 0.5\n").unwrap().move_speed, 1.0);
+        assert!(ControllerSettings::parse("invert_y maybe\n").is_err());
+    }
+
+    #[test]
+    fn frustum_contains_the_target() {
+        let camera = Camera::looking_at((0.0, 1.0, 2.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+        let frustum = camera.frustum();
+        assert!(frustum.contains_point(Vector3::new(0.0, 0.0, 0.0)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, 2.0, 4.0)));
+    }
+
+    #[test]
+    fn scroll_zooms_smoothly_and_clamped() {
+        let mut camera = Camera::looking_at((0.0, 0.0, 4.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+        let mut controller = CameraController::new();
+        controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 1.0));
+        controller.update_camera(&mut camera, FRAME);
+        // only a part of the line in the first frame.
+        assert!(camera.distance() < 4.0 && camera.distance() > 4.0 * (1.0 - DOLLY_STEP));
+        for _ in 0..100 {
+            controller.update_camera(&mut camera, FRAME);
+        }
+        assert!((camera.distance() - 4.0 * (1.0 - DOLLY_STEP)).abs() < 1e-4);
+        assert!((camera.target() - Point3::new(0.0, 0.0, 0.0)).magnitude() < 1e-4);
+
+        controller.set_mode(ControllerMode::Fly);
+        controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 1000.0));
+        for _ in 0..100 {
+            controller.update_camera(&mut camera, FRAME);
+        }
+        assert_eq!(camera.fovy(), MIN_FOVY);
+    }
+
+    #[test]
+    fn movement_goes_by_time_not_frames() {
+        let moved = |frames: u32| {
+            let mut camera = Camera::looking_at((0.0, 0.0, 100.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+            let mut controller = CameraController::new();
+            controller.set_move_speed(10.0);
+            controller.process_keydown(VirtualKeyCode::W);
+            for _ in 0..frames {
+                controller.update_camera(&mut camera, Duration::from_secs(1) / frames);
+            }
+            100.0 - camera.eye().z
+        };
+        assert!((moved(30) - 10.0).abs() < 1e-3);
+        assert!((moved(144) - 10.0).abs() < 1e-3);
+    }
+}
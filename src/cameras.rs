@@ -0,0 +1,361 @@
+use anyhow::{bail, Result};
+use cgmath::{Matrix4, Point3, Transform, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::{Camera, UniformBuffer};
+use crate::inflight::FramesInFlight;
+use crate::model::Model;
+
+/*
+    Several named cameras, one of them active.
+
+    Every camera keeps its own uniform buffer and bind group, so
+    switching is only a matter of binding another group, and a plugin
+    can draw with a camera that isn't the active one (a minimap in the
+    corner). Every frame the cameras that changed (Camera::revision())
+    are uploaded.
+
+    The renderer starts with one camera, DEFAULT_CAMERA. Adding a camera
+    under a name that exists replaces that camera.
+
+    A camera can be attached to a node of the scene, a model or one of
+    its meshes. Its eye and target are then given in the space of the
+    node and follow the node's world transform every frame. The scene
+    only moves meshes by their offset, so for now the transform is the
+    translation to the node's center plus that offset.
+
+    usage:
+        renderer.add_camera("minimap", Camera::looking_at(eye, target, 1.0));
+        renderer.set_active_camera("minimap")?;
+        ...
+        let bind_group = renderer.camera_bind_group("minimap");
+
+        // behind and above the first mesh of the first model.
+        renderer.attach_camera("follow", CameraAttachment::new(SceneNode::Mesh(0, 0), [0.0, 2.0, 5.0], [0.0; 3]))?;
+*/
+
+pub const DEFAULT_CAMERA: &str = "main";
+
+// What a camera can be attached to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SceneNode {
+    // index of the model.
+    Model(usize),
+    // index of the model and of the mesh in it.
+    Mesh(usize, usize),
+}
+
+impl SceneNode {
+    // node space -> world space, None when the node doesn't exist (anymore).
+    pub fn world_transform(&self, models: &[Model]) -> Option<Matrix4<f32>> {
+        let (center, offset) = match *self {
+            SceneNode::Model(model) => {
+                let model = models.get(model)?;
+                // the offset the meshes have in common, the exploded view spreads them around it.
+                let mut offset = Vector3::new(0.0, 0.0, 0.0);
+                for mesh in &model.meshes {
+                    offset += Vector3::from(mesh.offset);
+                }
+                (model.bounds.center(), offset / model.meshes.len().max(1) as f32)
+            }
+            SceneNode::Mesh(model, mesh) => {
+                let mesh = models.get(model)?.meshes.get(mesh)?;
+                (mesh.bounds.center(), Vector3::from(mesh.offset))
+            }
+        };
+        Some(Matrix4::from_translation(Vector3::from(center) + offset))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraAttachment {
+    pub node: SceneNode,
+    // in the space of the node.
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+}
+
+impl CameraAttachment {
+    pub fn new(node: SceneNode, eye: [f32; 3], target: [f32; 3]) -> Self {
+        Self {
+            node,
+            eye: eye.into(),
+            target: target.into(),
+        }
+    }
+}
+
+pub struct NamedCamera {
+    pub name: String,
+    pub camera: Camera,
+    // the camera follows this node, see follow_nodes().
+    pub attachment: Option<CameraAttachment>,
+    uniform: UniformBuffer,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // Camera::revision() of what's in the buffer.
+    uploaded: u64,
+}
+
+impl NamedCamera {
+    // `frame` is the renderer's per frame uniform, see frame.rs.
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, frame: &wgpu::Buffer, name: &str, camera: Camera) -> Self {
+        let mut uniform = UniformBuffer::new();
+        uniform.update_view_proj(&camera);
+        let uploaded = camera.revision();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("Camera Buffer {}", name)),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: frame.as_entire_binding(),
+                }
+            ],
+            label: Some("camera_bind_group"),
+        });
+        Self {
+            name: name.to_string(),
+            camera,
+            attachment: None,
+            uniform,
+            buffer,
+            bind_group,
+            uploaded,
+        }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+// A camera uniform and bind group for a view that isn't one of the named
+// cameras, e.g. an eye (stereo.rs) or a face of a cube map (cubemap.rs).
+pub struct CameraBinding {
+    uniform: UniformBuffer,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl CameraBinding {
+    // `frame` is the renderer's per frame uniform, see frame.rs.
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, frame: &wgpu::Buffer, label: &str) -> Self {
+        let uniform = UniformBuffer::new();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("Camera Buffer {}", label)),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: frame.as_entire_binding(),
+                }
+            ],
+            label: Some("camera_binding_bind_group"),
+        });
+        Self { uniform, buffer, bind_group }
+    }
+
+    // right away, for views drawn outside of render_frame().
+    pub fn upload(&mut self, queue: &wgpu::Queue, view_proj: Matrix4<f32>) {
+        self.uniform.set_view_proj(view_proj);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    // with the next frame, for views drawn every frame, see inflight.rs.
+    pub fn upload_in_flight(&mut self, device: &wgpu::Device, in_flight: &mut FramesInFlight, view_proj: Matrix4<f32>) {
+        self.uniform.set_view_proj(view_proj);
+        in_flight.write(device, &self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+pub struct Cameras {
+    cameras: Vec<NamedCamera>,
+    active: usize,
+}
+
+impl Cameras {
+    // starts with `camera` as DEFAULT_CAMERA, active.
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, frame: &wgpu::Buffer, camera: Camera) -> Self {
+        Self {
+            cameras: vec![NamedCamera::new(device, layout, frame, DEFAULT_CAMERA, camera)],
+            active: 0,
+        }
+    }
+
+    // returns the index of the camera.
+    pub fn add(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, frame: &wgpu::Buffer, name: &str, camera: Camera) -> usize {
+        match self.index(name) {
+            Some(index) => {
+                self.cameras[index].camera = camera;
+                index
+            }
+            None => {
+                self.cameras.push(NamedCamera::new(device, layout, frame, name, camera));
+                self.cameras.len() - 1
+            }
+        }
+    }
+
+    // new uniform buffers and bind groups on `device`, the cameras and
+    // their attachments stay. After the renderer switched backends.
+    pub fn recreate(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, frame: &wgpu::Buffer) {
+        self.cameras = std::mem::take(&mut self.cameras)
+            .into_iter()
+            .map(|named| {
+                let mut recreated = NamedCamera::new(device, layout, frame, &named.name, named.camera);
+                recreated.attachment = named.attachment;
+                recreated
+            })
+            .collect();
+    }
+
+    // the active camera can't be removed, there has to be one.
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        let index = match self.index(name) {
+            Some(index) => index,
+            None => bail!("No camera named {:?}.", name),
+        };
+        if index == self.active {
+            bail!("The active camera {:?} can't be removed.", name);
+        }
+        self.cameras.remove(index);
+        if index < self.active {
+            self.active -= 1;
+        }
+        Ok(())
+    }
+
+    pub fn set_active(&mut self, name: &str) -> Result<()> {
+        match self.index(name) {
+            Some(index) => {
+                self.active = index;
+                Ok(())
+            }
+            None => bail!("No camera named {:?}.", name),
+        }
+    }
+
+    pub fn active(&self) -> &NamedCamera {
+        &self.cameras[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut NamedCamera {
+        &mut self.cameras[self.active]
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NamedCamera> {
+        self.cameras.iter().find(|camera| camera.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut NamedCamera> {
+        self.cameras.iter_mut().find(|camera| camera.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NamedCamera> {
+        self.cameras.iter()
+    }
+
+    // all cameras draw into the window, they follow its size.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        for camera in &mut self.cameras {
+            camera.camera.set_aspect(aspect);
+        }
+    }
+
+    pub fn attach(&mut self, name: &str, attachment: Option<CameraAttachment>) -> Result<()> {
+        match self.get_mut(name) {
+            Some(camera) => {
+                camera.attachment = attachment;
+                Ok(())
+            }
+            None => bail!("No camera named {:?}.", name),
+        }
+    }
+
+    // moves the attached cameras along with their nodes. Cameras whose
+    // node is gone stay where they were.
+    pub fn follow_nodes(&mut self, models: &[Model]) {
+        for camera in &mut self.cameras {
+            let attachment = match camera.attachment {
+                Some(attachment) => attachment,
+                None => continue,
+            };
+            if let Some(transform) = attachment.node.world_transform(models) {
+                camera.camera.look_at(
+                    transform.transform_point(attachment.eye),
+                    transform.transform_point(attachment.target),
+                );
+            }
+        }
+    }
+
+    // keeps the attachments on their nodes when the model at `index` is
+    // removed or a model is inserted there. Cameras on a removed model are detached.
+    pub fn shift_models(&mut self, index: usize, inserted: bool) {
+        for camera in &mut self.cameras {
+            let attachment = match &mut camera.attachment {
+                Some(attachment) => attachment,
+                None => continue,
+            };
+            let model = match &mut attachment.node {
+                SceneNode::Model(model) | SceneNode::Mesh(model, _) => model,
+            };
+            if inserted && *model >= index {
+                *model += 1;
+            } else if !inserted && *model > index {
+                *model -= 1;
+            } else if !inserted && *model == index {
+                camera.attachment = None;
+            }
+        }
+    }
+
+    // fits the near and far plane of every camera to the boxes, see Camera::fit_clip_planes().
+    pub fn fit_clip_planes(&mut self, boxes: &[([f32; 3], [f32; 3])]) {
+        for camera in &mut self.cameras {
+            camera.camera.fit_clip_planes(boxes.iter().copied());
+        }
+    }
+
+    // writes the matrices of the cameras that changed since the last
+    // upload into their buffers, returns the names of those. They're
+    // copied in front of the next frame, see inflight.rs.
+    pub fn upload(&mut self, device: &wgpu::Device, in_flight: &mut FramesInFlight) -> Vec<String> {
+        let mut changed = Vec::new();
+        for camera in &mut self.cameras {
+            if camera.uploaded == camera.camera.revision() {
+                continue;
+            }
+            camera.uniform.update_view_proj(&camera.camera);
+            in_flight.write(device, &camera.buffer, 0, bytemuck::cast_slice(&[camera.uniform]));
+            camera.uploaded = camera.camera.revision();
+            changed.push(camera.name.clone());
+        }
+        changed
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.cameras.iter().position(|camera| camera.name == name)
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+
+/*
+    Gpu captures with RenderDoc from inside the application.
+
+    RenderDoc can capture any frame with its own hotkey (F12 / PrintScreen),
+    this is for the cases where the application knows better when the
+    interesting frame is, e.g. a key of the viewer or a test that
+    captures the first frame after loading.
+
+    The api is only there when the application was started from RenderDoc
+    (or RenderDoc was injected into it) and the crate was built with the
+    `renderdoc` feature, otherwise connect() fails.
+
+    The passes and models show up as named debug groups in the capture,
+    see render_frame().
+
+    usage:
+        cargo run --features renderdoc
+        ...
+        renderer.capture_frames(1)?;
+*/
+
+pub struct GpuCapture {
+    #[cfg(feature = "renderdoc")]
+    api: renderdoc::RenderDoc<renderdoc::V110>,
+}
+
+#[cfg(feature = "renderdoc")]
+impl GpuCapture {
+    pub fn connect() -> Result<Self> {
+        use anyhow::Context;
+
+        let api = renderdoc::RenderDoc::new()
+            .context("RenderDoc isn't loaded, start the application from RenderDoc.")?;
+        Ok(Self { api })
+    }
+
+    // captures the next `frames` presented frames.
+    pub fn trigger(&mut self, frames: u32) {
+        self.api.trigger_multi_frame_capture(frames);
+    }
+
+    // number of captures RenderDoc wrote so far.
+    pub fn num_captures(&self) -> u32 {
+        self.api.get_num_captures()
+    }
+}
+
+// without the feature there's nothing to connect to.
+#[cfg(not(feature = "renderdoc"))]
+impl GpuCapture {
+    pub fn connect() -> Result<Self> {
+        anyhow::bail!("Built without gpu capture support, enable the renderdoc feature.")
+    }
+
+    pub fn trigger(&mut self, _frames: u32) {}
+
+    pub fn num_captures(&self) -> u32 {
+        0
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use zhneeshyx::baked;
+use zhneeshyx::fetch;
+use zhneeshyx::model::{ModelData, VertexColors};
+use zhneeshyx::monitors;
+use zhneeshyx::pointcloud::PointCloud;
+use zhneeshyx::report::SceneReport;
+use zhneeshyx::scene::{Scene, SceneModel};
+use zhneeshyx::turntable::TurntableSettings;
+use zhneeshyx::{remote, sync};
+
+/*
+    Subcommands that run without opening a window:
+
+        zhneeshyx inspect model.obj
+        zhneeshyx bake model.obj --out model.bin
+        zhneeshyx report level.scene --out report.json
+        zhneeshyx monitors
+
+    models can be obj, fbx, ply or stl files.
+    xyz and pts files are point clouds, ply files can be
+    read as point clouds with --points. `report` writes the json
+    report of report.rs for a scene or models, to stdout without --out.
+    `monitors` lists the connected monitors.
+
+    `view` is the exception, it opens the viewer with extra files,
+    and so is `replay`, which opens it to replay a recorded session
+    (see replay.rs), `--benchmark`, which flies the camera through
+    a scene and writes a report (see benchmark.rs), and `--turntable`,
+    which records the camera going around a model or scene as a gif or
+    png sequence in a hidden window (see turntable.rs). `view --host` and
+    `view --join` sync the viewer with others (see sync.rs), `view
+    --remote` lets scripts control it (see remote.rs), both need the
+    network feature. `view --script` runs rhai scripts (see script.rs,
+    needs the scripting feature), `view --logic` loads game logic from
+    a dynamic library (see logic.rs, needs the logic feature). `view
+    --materials` loads a material library (see matlib.rs), `view
+    --locale` picks the language of the overlay (see locale.rs), `view
+    --palette` its debug colors (see palette.rs) and `view --ui-scale`
+    its size on top of the window's scale factor (see overlay.rs). `view
+    --monitor` opens the window on a monitor of `monitors` (see
+    monitors.rs), `view --fullscreen` fullscreen. `view --supersample`
+    draws the scene bigger than the window and shrinks it (see
+    resolution.rs), for smoother screenshots. `view --frames-in-flight`
+    sets how far the cpu may run ahead of the gpu (see inflight.rs).
+*/
+
+pub const USAGE: &str = "usage:
+    zhneeshyx                               open the viewer
+    zhneeshyx view [--points] <file>...     open the viewer with more models / point clouds
+    zhneeshyx view --record <session>       record the input of the viewer session
+    zhneeshyx view --transparent [<file>...] show the models over the desktop, without a background
+    zhneeshyx view --host <address>         share the camera and scene edits with joining viewers
+    zhneeshyx view --join <address>         follow the viewer hosting at the address
+    zhneeshyx view --remote <address>       let scripts drive the viewer over http
+    zhneeshyx view --script <file>          run a rhai script once the scene is loaded
+    zhneeshyx view --logic <library>        run game logic from a dynamic library, reloaded when rebuilt
+    zhneeshyx view --materials <library>    load a material library, reloaded when it changes
+    zhneeshyx view --locale <en|de|file.lang> language of the overlay text, kept for the next start
+    zhneeshyx view --palette <name>         debug colors: default, okabe-ito, tol or high-contrast (color-blind safe)
+    zhneeshyx view --ui-scale <factor>      make the overlay bigger or smaller, kept for the next start
+    zhneeshyx view --monitor <number|name>  open the window in the middle of that monitor
+    zhneeshyx view --fullscreen             open the window fullscreen
+    zhneeshyx view --supersample <factor>   draw the scene 1 to 2 times the window's resolution
+    zhneeshyx view --frames-in-flight <1-4> frames the cpu may be ahead of the gpu, fewer for less latency
+    zhneeshyx replay <session> [--headless] replay a recorded session, without a window with --headless
+    zhneeshyx --benchmark <scene> [--seconds <n>] [--out <report.json|.csv>]
+                                            time a camera flight through a scene or model
+    zhneeshyx --turntable <scene|model> [--seconds <n>] [--fps <n>] [--size <width>x<height>] [--out <clip.gif|dir>]
+                                            record a turn around a scene or model, without a window
+    zhneeshyx inspect [--points] <model>    print mesh and material stats
+    zhneeshyx monitors                      list the monitors --monitor picks from
+    zhneeshyx bake <model> --out <file>     write the baked binary model
+    zhneeshyx report <scene|model>... [--out <report.json>]
+                                            write triangle, texture, bounds and overdraw stats as json";
+
+// Files to show in the viewer, next to the terrain.
+pub struct ViewFiles {
+    pub models: Vec<PathBuf>,
+    pub point_clouds: Vec<PathBuf>,
+}
+
+// What happens to the input of the viewer.
+pub enum InputSession {
+    Live,
+    Record(PathBuf),
+    Replay { path: PathBuf, headless: bool },
+}
+
+// Hosting or joining a sync session, see sync.rs.
+pub enum SyncRole {
+    Host(String),
+    Join(String),
+}
+
+pub struct BenchmarkOptions {
+    // a scene file, or a single model.
+    pub scene: PathBuf,
+    pub seconds: f32,
+    pub out: PathBuf,
+}
+
+// true if the arguments ask for a subcommand instead of the viewer.
+pub fn is_command(args: &[String]) -> bool {
+    !args.is_empty() && !matches!(args[0].as_str(), "view" | "replay" | "--benchmark" | "--turntable")
+}
+
+pub fn benchmark_options(args: &[String]) -> Result<Option<BenchmarkOptions>> {
+    if args.first().map(|arg| arg.as_str()) != Some("--benchmark") {
+        return Ok(None);
+    }
+    let scene = args.get(1).context("--benchmark needs a scene or model")?;
+    let value = |name: &str| -> Result<Option<&String>> {
+        match args.iter().position(|arg| arg == name) {
+            Some(index) => Ok(Some(args.get(index + 1).with_context(|| format!("{} needs a value", name))?)),
+            None => Ok(None),
+        }
+    };
+    let seconds = match value("--seconds")? {
+        Some(seconds) => seconds.parse().with_context(|| format!("{:?} is no number of seconds", seconds))?,
+        None => 30.0,
+    };
+    let out = value("--out")?.map_or_else(|| PathBuf::from("benchmark.json"), PathBuf::from);
+    Ok(Some(BenchmarkOptions { scene: PathBuf::from(scene), seconds, out }))
+}
+
+pub struct TurntableOptions {
+    // a scene file, or a single model.
+    pub scene: PathBuf,
+    pub settings: TurntableSettings,
+    pub out: PathBuf,
+}
+
+pub fn turntable_options(args: &[String]) -> Result<Option<TurntableOptions>> {
+    if args.first().map(|arg| arg.as_str()) != Some("--turntable") {
+        return Ok(None);
+    }
+    let scene = args.get(1).context("--turntable needs a scene or model")?;
+    let value = |name: &str| -> Result<Option<&String>> {
+        match args.iter().position(|arg| arg == name) {
+            Some(index) => Ok(Some(args.get(index + 1).with_context(|| format!("{} needs a value", name))?)),
+            None => Ok(None),
+        }
+    };
+    let mut settings = TurntableSettings::default();
+    if let Some(seconds) = value("--seconds")? {
+        settings.seconds = seconds.parse().with_context(|| format!("{:?} is no number of seconds", seconds))?;
+    }
+    if let Some(fps) = value("--fps")? {
+        settings.fps = fps.parse().with_context(|| format!("{:?} is no number of frames per second", fps))?;
+    }
+    if let Some(size) = value("--size")? {
+        let parsed = size.split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+        match parsed {
+            Some((width, height)) if width > 0 && height > 0 => {
+                settings.width = width;
+                settings.height = height;
+            }
+            _ => bail!("{:?} is no size, like 512x512", size),
+        }
+    }
+    if settings.seconds <= 0.0 || settings.fps == 0 {
+        bail!("--turntable needs a positive --seconds and --fps");
+    }
+    let out = value("--out")?.map_or_else(|| PathBuf::from("turntable.gif"), PathBuf::from);
+    Ok(Some(TurntableOptions { scene: PathBuf::from(scene), settings, out }))
+}
+
+pub fn input_session(args: &[String]) -> Result<InputSession> {
+    match args.first().map(|arg| arg.as_str()) {
+        Some("replay") => {
+            let path = args.get(1).context("replay needs a recorded session")?;
+            Ok(InputSession::Replay {
+                path: PathBuf::from(path),
+                headless: args.iter().any(|arg| arg == "--headless"),
+            })
+        }
+        Some("view") => match args.iter().position(|arg| arg == "--record") {
+            Some(index) => {
+                let path = args.get(index + 1).context("--record needs a path")?;
+                Ok(InputSession::Record(PathBuf::from(path)))
+            }
+            None => Ok(InputSession::Live),
+        },
+        _ => Ok(InputSession::Live),
+    }
+}
+
+pub fn sync_role(args: &[String]) -> Result<Option<SyncRole>> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Ok(None);
+    }
+    // the default port when there's only a host name.
+    let address = |index: usize, name: &str| -> Result<String> {
+        let address = args.get(index + 1).with_context(|| format!("{} needs an address", name))?;
+        Ok(if address.contains(':') { address.clone() } else { format!("{}:{}", address, sync::DEFAULT_PORT) })
+    };
+    if let Some(index) = args.iter().position(|arg| arg == "--host") {
+        return Ok(Some(SyncRole::Host(address(index, "--host")?)));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--join") {
+        return Ok(Some(SyncRole::Join(address(index, "--join")?)));
+    }
+    Ok(None)
+}
+
+// address of the http remote control, see remote.rs.
+pub fn remote_address(args: &[String]) -> Result<Option<String>> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Ok(None);
+    }
+    match args.iter().position(|arg| arg == "--remote") {
+        Some(index) => {
+            let address = args.get(index + 1).context("--remote needs an address")?;
+            Ok(Some(if address.contains(':') { address.clone() } else { format!("{}:{}", address, remote::DEFAULT_PORT) }))
+        }
+        None => Ok(None),
+    }
+}
+
+// scripts to run after the scene is loaded, see script.rs.
+pub fn scripts(args: &[String]) -> Vec<PathBuf> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Vec::new();
+    }
+    args.windows(2)
+        .filter(|pair| pair[0] == "--script")
+        .map(|pair| PathBuf::from(&pair[1]))
+        .collect()
+}
+
+// the game logic library, see logic.rs.
+pub fn logic_library(args: &[String]) -> Option<PathBuf> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return None;
+    }
+    args.windows(2)
+        .find(|pair| pair[0] == "--logic")
+        .map(|pair| PathBuf::from(&pair[1]))
+}
+
+// language of the overlay, a built in one or a locale file, see locale.rs.
+pub fn locale(args: &[String]) -> Option<String> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return None;
+    }
+    args.windows(2)
+        .find(|pair| pair[0] == "--locale")
+        .map(|pair| pair[1].clone())
+}
+
+// debug colors of the overlay, one of palette::PRESETS.
+pub fn palette(args: &[String]) -> Option<String> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return None;
+    }
+    args.windows(2)
+        .find(|pair| pair[0] == "--palette")
+        .map(|pair| pair[1].clone())
+}
+
+// size of the overlay on top of the window's scale factor, see overlay.rs.
+pub fn ui_scale(args: &[String]) -> Result<Option<f32>> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Ok(None);
+    }
+    match args.windows(2).find(|pair| pair[0] == "--ui-scale") {
+        Some(pair) => {
+            let ui_scale = pair[1].parse().with_context(|| format!("{:?} is no ui scale", pair[1]))?;
+            Ok(Some(ui_scale))
+        }
+        None => Ok(None),
+    }
+}
+
+// times the window's resolution the scene is drawn at, see resolution.rs.
+pub fn supersampling(args: &[String]) -> Result<Option<f32>> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Ok(None);
+    }
+    match args.windows(2).find(|pair| pair[0] == "--supersample") {
+        Some(pair) => {
+            let factor = pair[1].parse().with_context(|| format!("{:?} is no supersampling factor", pair[1]))?;
+            Ok(Some(factor))
+        }
+        None => Ok(None),
+    }
+}
+
+// how many frames the cpu may be ahead of the gpu, see inflight.rs.
+pub fn frames_in_flight(args: &[String]) -> Result<Option<usize>> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Ok(None);
+    }
+    match args.windows(2).find(|pair| pair[0] == "--frames-in-flight") {
+        Some(pair) => {
+            let count = pair[1].parse().with_context(|| format!("{:?} is no count of frames", pair[1]))?;
+            Ok(Some(count))
+        }
+        None => Ok(None),
+    }
+}
+
+// material libraries to load, see matlib.rs.
+pub fn material_libraries(args: &[String]) -> Vec<PathBuf> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return Vec::new();
+    }
+    args.windows(2)
+        .filter(|pair| pair[0] == "--materials")
+        .map(|pair| PathBuf::from(&pair[1]))
+        .collect()
+}
+
+// the files given to `view`, empty without arguments.
+pub fn view_files(args: &[String]) -> ViewFiles {
+    let mut files = ViewFiles { models: Vec::new(), point_clouds: Vec::new() };
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return files;
+    }
+    let mut points = false;
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if matches!(arg.as_str(), "--record" | "--host" | "--join" | "--remote" | "--script" | "--logic" | "--materials" | "--locale" | "--palette" | "--ui-scale" | "--monitor" | "--supersample" | "--frames-in-flight") {
+            args.next();
+        } else if arg == "--transparent" || arg == "--fullscreen" {
+            continue;
+        } else if arg == "--points" {
+            points = true;
+        } else if points || is_point_cloud(Path::new(arg)) {
+            files.point_clouds.push(PathBuf::from(arg));
+            points = false;
+        } else {
+            files.models.push(PathBuf::from(arg));
+        }
+    }
+    files
+}
+
+// monitor to open the viewer on, see monitors.rs.
+pub fn monitor(args: &[String]) -> Option<String> {
+    if args.first().map(|arg| arg.as_str()) != Some("view") {
+        return None;
+    }
+    args.windows(2)
+        .find(|pair| pair[0] == "--monitor")
+        .map(|pair| pair[1].clone())
+}
+
+pub fn fullscreen(args: &[String]) -> bool {
+    args.first().map(|arg| arg.as_str()) == Some("view") && args.iter().any(|arg| arg == "--fullscreen")
+}
+
+// the viewer in a transparent window, see RendererBuilder::transparent().
+pub fn transparent(args: &[String]) -> bool {
+    args.first().map(|arg| arg.as_str()) == Some("view") && args.iter().any(|arg| arg == "--transparent")
+}
+
+fn is_point_cloud(path: &Path) -> bool {
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    matches!(extension.as_deref(), Some("xyz") | Some("pts"))
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args[0].as_str() {
+        "inspect" => {
+            let points = args.get(1).map(|arg| arg == "--points").unwrap_or(false);
+            let path = args.get(if points { 2 } else { 1 }).context("inspect needs a model path")?;
+            if points || is_point_cloud(Path::new(path)) {
+                inspect_points(Path::new(path))
+            } else {
+                inspect(Path::new(path))
+            }
+        }
+        "bake" => {
+            let path = args.get(1).context("bake needs a model path")?;
+            let out = match args.iter().position(|arg| arg == "--out") {
+                Some(index) => args.get(index + 1).context("--out needs a path")?.clone(),
+                None => bail!("bake needs --out <file>"),
+            };
+            bake(Path::new(path), Path::new(&out))
+        }
+        "report" => {
+            let out_index = args.iter().position(|arg| arg == "--out");
+            let out = match out_index {
+                Some(index) => Some(args.get(index + 1).context("--out needs a path")?.clone()),
+                None => None,
+            };
+            let files: Vec<&String> = args.iter()
+                .enumerate()
+                .skip(1)
+                .filter(|(index, _)| out_index.map_or(true, |out| *index != out && *index != out + 1))
+                .map(|(_, arg)| arg)
+                .collect();
+            if files.is_empty() {
+                bail!("report needs a scene or model path");
+            }
+            report(&files, out.as_deref().map(Path::new))
+        }
+        "monitors" => list_monitors(),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => bail!("unknown command {:?}\n{}", other, USAGE),
+    }
+}
+
+// the numbers and names `view --monitor` takes.
+fn list_monitors() -> Result<()> {
+    let event_loop = winit::event_loop::EventLoop::new();
+    let monitors = monitors::list(event_loop.available_monitors(), event_loop.primary_monitor().as_ref());
+    if monitors.is_empty() {
+        println!("no monitors");
+    }
+    for monitor in monitors {
+        println!("{}", monitor);
+    }
+    Ok(())
+}
+
+// baked models can be inspected too, everything else goes through the importers.
+// urls are fetched first, see fetch.rs.
+fn load(path: &Path) -> Result<ModelData> {
+    let path = fetch::local_model(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("bin") | Some(baked::CACHE_EXTENSION) => baked::read(&path),
+        _ => ModelData::load(&path),
+    }
+}
+
+fn inspect(path: &Path) -> Result<()> {
+    let mut data = load(path)?;
+    // report the geometry as it's drawn, a missing map is listed below.
+    if let Err(e) = data.apply_displacement() {
+        eprintln!("warning: {:#}", e);
+    }
+
+    println!("{}", path.display());
+    println!("  meshes:    {}", data.meshes.len());
+    println!("  materials: {}", data.materials.len());
+    println!("  vertices:  {}", data.num_vertices());
+    println!("  triangles: {}", data.num_triangles());
+    println!("  bounds:    min {:?} max {:?} size {:?}",
+        data.bounds.min, data.bounds.max, data.bounds.size());
+
+    for mesh in &data.meshes {
+        let material = data.materials.get(mesh.material)
+            .map(|material| material.name.as_str())
+            .unwrap_or("<none>");
+        println!("  mesh {:?}: {} vertices, {} triangles, material {:?}",
+            mesh.name, mesh.vertices.len(), mesh.num_triangles(), material);
+    }
+
+    let mut missing = 0;
+    for material in &data.materials {
+        match material.diffuse_path(&data.directory) {
+            Some(texture) if texture.exists() => {
+                println!("  material {:?}: diffuse {}", material.name, texture.display());
+            }
+            Some(texture) => {
+                println!("  material {:?}: diffuse {} (missing)", material.name, texture.display());
+                missing += 1;
+            }
+            None => {
+                println!("  material {:?}: no diffuse texture", material.name);
+            }
+        }
+        match material.lightmap_path(&data.directory) {
+            Some(texture) if texture.exists() => {
+                println!("  material {:?}: lightmap {} ({:?})", material.name, texture.display(), material.lightmap_mode);
+            }
+            Some(texture) => {
+                println!("  material {:?}: lightmap {} (missing)", material.name, texture.display());
+                missing += 1;
+            }
+            None => {}
+        }
+        if let Some(displacement) = &material.displacement {
+            let texture = data.directory.join(&displacement.texture);
+            println!("  material {:?}: displacement {}{} (base {}, scale {})",
+                material.name, texture.display(),
+                if texture.exists() { "" } else { " (missing)" },
+                displacement.base, displacement.scale);
+            if !texture.exists() {
+                missing += 1;
+            }
+        }
+        if material.vertex_colors != VertexColors::Ignore {
+            println!("  material {:?}: vertex colors ({:?})", material.name, material.vertex_colors);
+        }
+    }
+    if missing > 0 {
+        println!("  {} missing texture(s)", missing);
+    }
+
+    Ok(())
+}
+
+fn inspect_points(path: &Path) -> Result<()> {
+    let cloud = PointCloud::load(path)?;
+
+    println!("{}", path.display());
+    println!("  points:    {}", cloud.points.len());
+    println!("  bounds:    min {:?} max {:?} size {:?}",
+        cloud.bounds.min, cloud.bounds.max, cloud.bounds.size());
+    Ok(())
+}
+
+fn report(files: &[&String], out: Option<&Path>) -> Result<()> {
+    let source = files.iter().map(|file| file.as_str()).collect::<Vec<_>>().join(" ");
+    let mut report = SceneReport::new(&source);
+    for file in files {
+        let path = Path::new(file.as_str());
+        if path.extension().and_then(|ext| ext.to_str()) != Some("scene") {
+            report_model(&mut report, &SceneModel::new(path), [0.0; 3])?;
+            continue;
+        }
+        let scene = Scene::load(path)?;
+        for model in &scene.models {
+            report_model(&mut report, model, [0.0; 3])?;
+        }
+        // the models of a prefab are placed at its offset.
+        for prefab in &scene.prefabs {
+            for model in &Scene::load(&prefab.path)?.models {
+                report_model(&mut report, model, prefab.offset)?;
+            }
+        }
+    }
+    report.finish();
+
+    match out {
+        Some(out) => {
+            report.save(out)?;
+            eprintln!("wrote {} ({} models, {} triangles)", out.display(), report.totals.models, report.totals.triangles);
+        }
+        None => println!("{}", report.to_json()?),
+    }
+    Ok(())
+}
+
+fn report_model(report: &mut SceneReport, model: &SceneModel, offset: [f32; 3]) -> Result<()> {
+    let mut data = load(&model.path)?;
+    if let Err(e) = data.apply_displacement() {
+        eprintln!("warning: {:#}", e);
+    }
+    let offsets: BTreeMap<usize, [f32; 3]> = (0..data.meshes.len())
+        .map(|mesh| {
+            let moved = model.offsets.get(&mesh).copied().unwrap_or([0.0; 3]);
+            (mesh, [moved[0] + offset[0], moved[1] + offset[1], moved[2] + offset[2]])
+        })
+        .collect();
+    report.add_model(&model.path, &data, &model.hidden_meshes, &offsets);
+    Ok(())
+}
+
+fn bake(path: &Path, out: &Path) -> Result<()> {
+    let data = ModelData::load(path)?;
+    baked::write(&data, baked::source_hash(path)?, out)?;
+
+    println!("baked {} -> {} ({} meshes, {} vertices)",
+        path.display(), out.display(), data.meshes.len(), data.num_vertices());
+    Ok(())
+}
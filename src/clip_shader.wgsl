@@ -0,0 +1,106 @@
+// Cross-section caps: the stencil marks where the inside of a clipped
+// mesh can be seen, the cap quad on the plane fills those pixels.
+
+//
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) //
+var<uniform> camera: CameraUniform;
+
+struct ClipUniform {
+    // xyz: normal, w: distance, dot(normal, p) + distance < 0 is cut away.
+    planes: array<vec4<f32>, 4>,
+    // xyz: center of the cap quad, w: half its size.
+    cap_center: vec4<f32>,
+    cap_color: vec4<f32>,
+    num_planes: u32,
+    // the plane the cap lies on, it doesn't clip its own cap.
+    cap_plane: u32,
+    padding: vec2<u32>,
+}
+
+@group(1) @binding(0)
+var<uniform> clip: ClipUniform;
+
+fn clipped(position: vec3<f32>, skip: u32) -> bool {
+    var i = 0u;
+    loop {
+        if (i >= clip.num_planes) {
+            break;
+        }
+        let plane = clip.planes[i];
+        if (i != skip && dot(plane.xyz, position) + plane.w < 0.0) {
+            return true;
+        }
+        i = i + 1u;
+    }
+    return false;
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+}
+
+// Stencil
+
+@vertex
+fn vs_stencil(
+    @location(0) position: vec3<f32>,
+    @location(5) offset: vec3<f32>,
+    // per copy of the model, see InstanceRaw, the normals aren't needed.
+    @location(6) model_0: vec4<f32>,
+    @location(7) model_1: vec4<f32>,
+    @location(8) model_2: vec4<f32>,
+    @location(9) model_3: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let model_matrix = mat4x4<f32>(model_0, model_1, model_2, model_3);
+    out.world_position = (model_matrix * vec4<f32>(position + offset, 1.0)).xyz;
+    out.clip_position = camera.view_proj * vec4<f32>(out.world_position, 1.0);
+    return out;
+}
+
+// only the stencil is written, front faces count up, back faces down.
+@fragment
+fn fs_stencil(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (clipped(in.world_position, 4294967295u)) {
+        discard;
+    }
+    return vec4<f32>(0.0);
+}
+
+// Cap
+
+@vertex
+fn vs_cap(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    let normal = clip.planes[clip.cap_plane].xyz;
+    var helper = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(normal.y) > 0.99) {
+        helper = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(helper, normal));
+    let bitangent = cross(normal, tangent);
+    let position = clip.cap_center.xyz + (tangent * corner.x + bitangent * corner.y) * clip.cap_center.w;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    out.world_position = position;
+    return out;
+}
+
+@fragment
+fn fs_cap(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (clipped(in.world_position, clip.cap_plane)) {
+        discard;
+    }
+    return clip.cap_color;
+}
@@ -0,0 +1,442 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::model::{Bounds, DrawModel, InstanceBuffer, Model};
+use crate::subdivision::TerrainSubdivision;
+use crate::vertex::{InstanceRaw, MVertex, MeshOffset, Vertex};
+
+/*
+    Clipping planes, to look inside models and terrain.
+
+    Every plane cuts away the half space behind it: the scene shader
+    discards fragments with dot(normal, p) + distance < 0.
+
+    A cut open mesh is hollow, since back faces are culled. Planes with
+    a cap fill the cut with a flat color, using the stencil buffer:
+    - the scene is drawn again into the stencil only, front faces count
+      up and back faces count down. Where the inside of a closed mesh
+      can be seen through the cut only a back face is left, so the
+      count isn't 0.
+    - a quad on the plane is drawn where the stencil isn't 0.
+    This needs closed (watertight) meshes to look right. The caps are
+    drawn after the scene without depth test, so they also cover
+    other models in front of the cut.
+
+    usage:
+        renderer.add_clip_plane(ClipPlane::through([0.0; 3], [1.0, 0.0, 0.0]).with_cap(true))?;
+*/
+
+pub const MAX_CLIP_PLANES: usize = 4;
+const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+// cap_plane of the scene uniform, which has no cap.
+const NO_CAP: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClipPlane {
+    // points to the side that is kept.
+    pub normal: [f32; 3],
+    pub distance: f32,
+    // fill the cross-section.
+    pub cap: bool,
+}
+
+impl ClipPlane {
+    pub fn new(normal: [f32; 3], distance: f32) -> Self {
+        Self { normal, distance, cap: false }
+    }
+
+    // plane through `point`, keeping what is in front of `normal`.
+    pub fn through(point: [f32; 3], normal: [f32; 3]) -> Self {
+        let distance = -Vector3::from(normal).dot(Vector3::from(point));
+        Self::new(normal, distance)
+    }
+
+    pub fn with_cap(mut self, cap: bool) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    // normalized plane equation.
+    fn equation(&self) -> [f32; 4] {
+        let normal = Vector3::from(self.normal);
+        let length = normal.magnitude().max(f32::EPSILON);
+        let n = normal / length;
+        [n.x, n.y, n.z, self.distance / length]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClipUniform {
+    planes: [[f32; 4]; MAX_CLIP_PLANES],
+    cap_center: [f32; 4],
+    cap_color: [f32; 4],
+    num_planes: u32,
+    cap_plane: u32,
+    padding: [u32; 2],
+}
+
+// Gpu side of the clipping planes, owned by the renderer.
+pub struct Clipping {
+    planes: Vec<ClipPlane>,
+    cap_color: [f32; 4],
+    bind_group_layout: wgpu::BindGroupLayout,
+    // for the scene pass.
+    scene_buffer: wgpu::Buffer,
+    scene_bind_group: wgpu::BindGroup,
+    // one per plane, with that plane as the cap plane.
+    cap_buffers: Vec<wgpu::Buffer>,
+    cap_bind_groups: Vec<wgpu::BindGroup>,
+    stencil_pipeline: wgpu::RenderPipeline,
+    cap_pipeline: wgpu::RenderPipeline,
+    stencil_view: wgpu::TextureView,
+}
+
+impl Clipping {
+    fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("clip_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(device);
+        let empty = ClipUniform {
+            planes: [[0.0; 4]; MAX_CLIP_PLANES],
+            cap_center: [0.0; 4],
+            cap_color: [0.0; 4],
+            num_planes: 0,
+            cap_plane: NO_CAP,
+            padding: [0; 2],
+        };
+        let create_uniform = |label: &str| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&[empty]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("clip_bind_group"),
+                layout: &bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            });
+            (buffer, bind_group)
+        };
+        let (scene_buffer, scene_bind_group) = create_uniform("Clip Buffer");
+        let (cap_buffers, cap_bind_groups) = (0..MAX_CLIP_PLANES)
+            .map(|_| create_uniform("Clip Cap Buffer"))
+            .unzip();
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Clip Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("clip_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Clip Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let count = |pass_op| wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op,
+        };
+        let stencil_pipeline = create_pipeline(
+            device,
+            &layout,
+            &shader,
+            config.format,
+            ("vs_stencil", "fs_stencil"),
+            &[MVertex::desc(), MeshOffset::desc(), InstanceRaw::desc()],
+            wgpu::ColorWrites::empty(),
+            wgpu::StencilState {
+                front: count(wgpu::StencilOperation::IncrementWrap),
+                back: count(wgpu::StencilOperation::DecrementWrap),
+                read_mask: 0xff,
+                write_mask: 0xff,
+            },
+        );
+        let inside = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::NotEqual,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+        let cap_pipeline = create_pipeline(
+            device,
+            &layout,
+            &shader,
+            config.format,
+            ("vs_cap", "fs_cap"),
+            &[],
+            wgpu::ColorWrites::ALL,
+            wgpu::StencilState {
+                front: inside,
+                back: inside,
+                read_mask: 0xff,
+                write_mask: 0,
+            },
+        );
+
+        Self {
+            planes: Vec::new(),
+            cap_color: [0.8, 0.25, 0.2, 1.0],
+            bind_group_layout,
+            scene_buffer,
+            scene_bind_group,
+            cap_buffers,
+            cap_bind_groups,
+            stencil_pipeline,
+            cap_pipeline,
+            stencil_view: create_stencil_view(device, config),
+        }
+    }
+
+    pub fn planes(&self) -> &[ClipPlane] {
+        &self.planes
+    }
+
+    pub fn planes_mut(&mut self) -> &mut Vec<ClipPlane> {
+        &mut self.planes
+    }
+
+    pub fn add_plane(&mut self, plane: ClipPlane) -> Result<usize> {
+        if self.planes.len() >= MAX_CLIP_PLANES {
+            bail!("No more than {} clipping planes.", MAX_CLIP_PLANES);
+        }
+        self.planes.push(plane);
+        Ok(self.planes.len() - 1)
+    }
+
+    pub fn cap_color(&self) -> [f32; 4] {
+        self.cap_color
+    }
+
+    pub fn set_cap_color(&mut self, color: [f32; 4]) {
+        self.cap_color = color;
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.scene_bind_group
+    }
+
+    // group 3 of the scene pipeline.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.stencil_view = create_stencil_view(device, config);
+    }
+
+    // uploads the planes, the caps are sized to cover `scene`.
+    pub fn update(&self, queue: &wgpu::Queue, scene: &Bounds) {
+        let mut uniform = ClipUniform {
+            planes: [[0.0; 4]; MAX_CLIP_PLANES],
+            cap_center: [0.0; 4],
+            cap_color: self.cap_color,
+            num_planes: self.planes.len().min(MAX_CLIP_PLANES) as u32,
+            cap_plane: NO_CAP,
+            padding: [0; 2],
+        };
+        for (i, plane) in self.planes.iter().take(MAX_CLIP_PLANES).enumerate() {
+            uniform.planes[i] = plane.equation();
+        }
+        queue.write_buffer(&self.scene_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let (center, radius) = if scene.is_empty() {
+            (Vector3::new(0.0, 0.0, 0.0), 1.0)
+        } else {
+            (Vector3::from(scene.center()), scene.radius().max(f32::EPSILON))
+        };
+        for (i, plane) in self.planes.iter().take(MAX_CLIP_PLANES).enumerate() {
+            if !plane.cap {
+                continue;
+            }
+            // scene center moved onto the plane.
+            let [x, y, z, d] = uniform.planes[i];
+            let normal = Vector3::new(x, y, z);
+            let on_plane = center - normal * (normal.dot(center) + d);
+            uniform.cap_center = [on_plane.x, on_plane.y, on_plane.z, radius * 1.5];
+            uniform.cap_plane = i as u32;
+            queue.write_buffer(&self.cap_buffers[i], 0, bytemuck::cast_slice(&[uniform]));
+        }
+    }
+
+    // one pass per capped plane, on top of the scene in `view`.
+    // `viewport` (x, y, width, height) limits the caps to a part of `view`,
+    // see resolution.rs and stereo.rs.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_caps(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+        models: &[Model],
+        // per model, meshes that aren't drawn.
+        hidden: &[BTreeSet<usize>],
+        subdivision: Option<&TerrainSubdivision>,
+        // bound for the models without instances, see Model::instances.
+        identity_instance: &InstanceBuffer,
+        viewport: Option<[u32; 4]>,
+    ) {
+        for (i, plane) in self.planes.iter().take(MAX_CLIP_PLANES).enumerate() {
+            if !plane.cap {
+                continue;
+            }
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Clip Cap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.stencil_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: false,
+                    }),
+                }),
+            });
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.cap_bind_groups[i], &[]);
+            render_pass.set_stencil_reference(0);
+            if let Some([x, y, width, height]) = viewport {
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+            }
+
+            render_pass.set_pipeline(&self.stencil_pipeline);
+            for (index, model) in models.iter().enumerate() {
+                // every copy of an instanced model is cut open and capped.
+                let instances = model.instances.as_ref().unwrap_or(identity_instance);
+                let visible = |mesh: &usize| hidden.get(index).map_or(true, |hidden| !hidden.contains(mesh));
+                if let Some(subdivision) = subdivision.filter(|s| s.model() == index) {
+                    for (mesh_index, (mesh, source)) in subdivision.meshes().iter().zip(&model.meshes).enumerate() {
+                        if !visible(&mesh_index) {
+                            continue;
+                        }
+                        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                        render_pass.set_vertex_buffer(1, source.offset_buffer.slice(..));
+                        // the draw arguments have a single instance.
+                        render_pass.set_vertex_buffer(2, instances.buffer.slice(..));
+                        render_pass.draw_indirect(&mesh.args_buffer, 0);
+                    }
+                    continue;
+                }
+                for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                    if visible(&mesh_index) {
+                        render_pass.draw_mesh_instanced(mesh, instances, 0..instances.len());
+                    }
+                }
+            }
+
+            render_pass.set_pipeline(&self.cap_pipeline);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+}
+
+fn create_stencil_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Clip Stencil"),
+        size: wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: STENCIL_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    entry_points: (&str, &str),
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    write_mask: wgpu::ColorWrites,
+    stencil: wgpu::StencilState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Clip Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: entry_points.0,
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: entry_points.1,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // both sides count for the stencil.
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // the depth is unused, only the stencil matters.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: STENCIL_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil,
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
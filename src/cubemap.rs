@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::math;
+
+/*
+    Cube map and panorama captures: the scene around a point, e.g. to
+    bake an environment or reflection map from the scene itself.
+
+    Renderer::capture_cubemap() draws the scene six times from the eye of
+    the active camera, with a square 90 degree view along every axis, into
+    the offscreen target of screenshot() and reads the faces back in the
+    background, they're handed over once all are there. A face
+    is as large as the window's shorter side. The plugins (overlay, ui)
+    aren't drawn, the far plane reaches the end of the scene in every
+    direction.
+
+    The faces are in the order and orientation wgpu (and Vulkan, OpenGL)
+    sample cube textures in, +x, -x, +y, -y, +z, -z, so they can be
+    uploaded as the layers of a cube texture as they are. The world is
+    right handed, cube maps are left handed, so every face is the image
+    the camera sees, mirrored.
+
+    to_equirectangular() turns the cube into a 2:1 panorama (longitude
+    left to right, -z in the middle, latitude top to bottom).
+
+    usage:
+        renderer.capture_cubemap(|_, cube| {
+            let cube = cube.unwrap();
+            cube.save("captures", "hall").unwrap(); // hall_px.png ... hall_nz.png
+            cube.to_equirectangular(2048).save("captures/hall_panorama.png").unwrap();
+        })?;
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    // the order of the layers of a cube texture.
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    // px, nx, py, ny, pz, nz, the usual suffixes of the files.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            CubeFace::PositiveX => "px",
+            CubeFace::NegativeX => "nx",
+            CubeFace::PositiveY => "py",
+            CubeFace::NegativeY => "ny",
+            CubeFace::PositiveZ => "pz",
+            CubeFace::NegativeZ => "nz",
+        }
+    }
+
+    // which way the face's camera looks and which way is up in its image.
+    pub fn orientation(self) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            CubeFace::PositiveX => (Vector3::unit_x(), Vector3::unit_y()),
+            CubeFace::NegativeX => (-Vector3::unit_x(), Vector3::unit_y()),
+            CubeFace::PositiveY => (Vector3::unit_y(), -Vector3::unit_z()),
+            CubeFace::NegativeY => (-Vector3::unit_y(), Vector3::unit_z()),
+            CubeFace::PositiveZ => (Vector3::unit_z(), Vector3::unit_y()),
+            CubeFace::NegativeZ => (-Vector3::unit_z(), Vector3::unit_y()),
+        }
+    }
+
+    // world -> clip space of the face seen from `eye`, before mirroring.
+    pub fn view_projection(self, eye: Point3<f32>, znear: f32, zfar: f32) -> Matrix4<f32> {
+        let (forward, up) = self.orientation();
+        math::perspective(90.0, 1.0, znear, zfar) * math::look_at(eye, eye + forward, up)
+    }
+
+    // the face `direction` points at, and where on it: 0..1 from the left and from the top.
+    pub fn locate(direction: Vector3<f32>) -> (CubeFace, f32, f32) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        // the coordinates on the face of the OpenGL spec's table, -1..1.
+        let (face, sc, tc, major) = if ax >= ay && ax >= az {
+            if x > 0.0 { (CubeFace::PositiveX, -z, -y, ax) } else { (CubeFace::NegativeX, z, -y, ax) }
+        } else if ay >= az {
+            if y > 0.0 { (CubeFace::PositiveY, x, z, ay) } else { (CubeFace::NegativeY, x, -z, ay) }
+        } else if z > 0.0 {
+            (CubeFace::PositiveZ, x, -y, az)
+        } else {
+            (CubeFace::NegativeZ, -x, -y, az)
+        };
+        let major = major.max(f32::EPSILON);
+        (face, (sc / major + 1.0) * 0.5, (tc / major + 1.0) * 0.5)
+    }
+
+    fn index(self) -> usize {
+        CubeFace::ALL.iter().position(|face| *face == self).unwrap_or(0)
+    }
+}
+
+pub struct CubeMap {
+    // width and height of every face.
+    pub size: u32,
+    // in the order of CubeFace::ALL.
+    pub faces: Vec<image::RgbaImage>,
+}
+
+impl CubeMap {
+    pub fn face(&self, face: CubeFace) -> &image::RgbaImage {
+        &self.faces[face.index()]
+    }
+
+    // the color the cube has in `direction`, filtered bilinearly within the face.
+    pub fn sample(&self, direction: Vector3<f32>) -> [u8; 4] {
+        let (face, u, v) = CubeFace::locate(direction);
+        let image = self.face(face);
+        let max = (self.size.max(1) - 1) as f32;
+        let (x, y) = ((u * self.size as f32 - 0.5).clamp(0.0, max), (v * self.size as f32 - 0.5).clamp(0.0, max));
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(max as u32), (y0 + 1).min(max as u32));
+        let (fx, fy) = (x.fract(), y.fract());
+        let mut color = [0u8; 4];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let texel = |x: u32, y: u32| image.get_pixel(x, y)[channel] as f32;
+            let top = texel(x0, y0) * (1.0 - fx) + texel(x1, y0) * fx;
+            let bottom = texel(x0, y1) * (1.0 - fx) + texel(x1, y1) * fx;
+            *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+        color
+    }
+
+    // a `width` x `width` / 2 panorama of the cube.
+    pub fn to_equirectangular(&self, width: u32) -> image::RgbaImage {
+        let width = width.max(2);
+        let height = width / 2;
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+            let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * std::f32::consts::PI;
+            let direction = Vector3::new(
+                latitude.cos() * longitude.sin(),
+                latitude.sin(),
+                -latitude.cos() * longitude.cos(),
+            );
+            image::Rgba(self.sample(direction))
+        })
+    }
+
+    // the faces as `dir`/`name`_px.png ... `name`_nz.png, returns the files.
+    pub fn save<P: AsRef<Path>>(&self, dir: P, name: &str) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).with_context(|| format!("Unable to create {:?}.", dir))?;
+        let mut written = Vec::new();
+        for face in CubeFace::ALL {
+            let path = dir.join(format!("{}_{}.png", name, face.suffix()));
+            self.face(face).save(&path).with_context(|| format!("Unable to save {:?}.", path))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+// the far plane that reaches every corner of the scene `bounds` from `eye`.
+pub fn far_plane(eye: Point3<f32>, min: [f32; 3], max: [f32; 3]) -> f32 {
+    let eye = eye.to_vec();
+    (0..8)
+        .map(|corner: usize| {
+            let pick = |axis: usize| if corner & (1 << axis) == 0 { min[axis] } else { max[axis] };
+            (Vector3::new(pick(0), pick(1), pick(2)) - eye).magnitude()
+        })
+        .fold(0.0, f32::max)
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::budget::BudgetLevel;
+
+/*
+    Events the renderer reports to the embedding application.
+
+    Subscribe either with a callback (Renderer::on_event), which runs
+    on the render thread right when the event happens, or with a
+    channel (Renderer::event_channel) to handle them elsewhere.
+*/
+
+#[derive(Debug, Clone)]
+pub enum RendererEvent {
+    ModelLoaded { index: usize, path: PathBuf },
+    AssetReloaded { index: usize, path: PathBuf },
+    // a material library file changed and was loaded again, see matlib.rs.
+    MaterialLibraryReloaded { path: PathBuf },
+    // the models after it moved down by one.
+    ModelRemoved { index: usize, path: PathBuf },
+    // wgpu only tells us about lost surfaces, so these wrap
+    // the surface being lost and configured again.
+    DeviceLost,
+    DeviceRecovered,
+    // the estimated gpu memory moved to another level of the budget, see budget.rs.
+    MemoryBudget { used: u64, budget: u64, level: BudgetLevel },
+    FrameRendered(FrameStats),
+    ObjectPicked { model: usize, mesh: usize, position: [f32; 3] },
+    // the view or projection of the named camera changed and was uploaded.
+    CameraChanged { name: String },
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameStats {
+    pub frame: u64,
+    // time since the previous frame was rendered
+    pub frame_time: Duration,
+    pub draw_calls: u32,
+    pub triangles: u32,
+    // one per draw call, until meshes can be instanced.
+    pub instances: u32,
+    // meshes that weren't drawn: hidden, or without a material.
+    pub culled: u32,
+    // set_bind_group calls of the scene pass, meshes sharing a material share its bind.
+    pub bind_group_switches: u32,
+}
+
+type Callback = Box<dyn FnMut(&RendererEvent)>;
+
+#[derive(Default)]
+pub struct EventHub {
+    callbacks: Vec<Callback>,
+    senders: Vec<mpsc::Sender<RendererEvent>>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe<F: FnMut(&RendererEvent) + 'static>(&mut self, callback: F) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn channel(&mut self) -> mpsc::Receiver<RendererEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    pub fn emit(&mut self, event: RendererEvent) {
+        for callback in &mut self.callbacks {
+            callback(&event);
+        }
+        // drop the senders whose receiver is gone.
+        self.senders.retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
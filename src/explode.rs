@@ -0,0 +1,95 @@
+use cgmath::Vector3;
+
+use crate::model::Model;
+
+/*
+    Exploded view: the meshes of a model move apart, to see how a
+    multi-part assembly (e.g. an obj with one group per part) fits
+    together.
+
+    Every mesh is pushed away from the center of the model along the
+    line through its own center: at a factor of 1 the parts are twice
+    as far from the model center as they are assembled. Changing the
+    factor doesn't jump, the parts glide there at `speed`.
+
+    Only the draw offset of the meshes changes, the geometry stays as
+    it was loaded (picking takes the offsets into account).
+
+    usage:
+        renderer.set_exploded_view(index, 1.0);
+        ...
+        renderer.set_exploded_view(index, 0.0); // back together
+*/
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExplodedView {
+    pub model: usize,
+    // where the animation is heading.
+    target: f32,
+    current: f32,
+    // change of the factor per second.
+    pub speed: f32,
+}
+
+impl ExplodedView {
+    pub fn new(model: usize) -> Self {
+        Self {
+            model,
+            target: 0.0,
+            current: 0.0,
+            speed: 1.5,
+        }
+    }
+
+    pub fn factor(&self) -> f32 {
+        self.target
+    }
+
+    pub fn set_factor(&mut self, factor: f32) {
+        self.target = factor.max(0.0);
+    }
+
+    // the factor the meshes are drawn with right now.
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    // true once the model is back together and nothing moves anymore.
+    pub fn is_assembled(&self) -> bool {
+        self.target == 0.0 && self.current == 0.0
+    }
+
+    // moves `dt` seconds closer to the target factor.
+    pub fn animate(&mut self, dt: f32) {
+        let step = self.speed.max(0.0) * dt;
+        let delta = self.target - self.current;
+        self.current = if delta.abs() <= step {
+            self.target
+        } else {
+            self.current + step * delta.signum()
+        };
+    }
+
+    // uploads the offsets of the meshes that aren't where they should be.
+    pub fn apply(&self, queue: &wgpu::Queue, model: &mut Model) {
+        let offsets = mesh_offsets(model, self.current);
+        for (mesh, offset) in model.meshes.iter_mut().zip(offsets) {
+            if mesh.offset != offset {
+                mesh.set_offset(queue, offset);
+            }
+        }
+    }
+}
+
+// offset of every mesh of `model` at the exploded view `factor`.
+pub fn mesh_offsets(model: &Model, factor: f32) -> Vec<[f32; 3]> {
+    let center = Vector3::from(model.bounds.center());
+    model.meshes.iter()
+        .map(|mesh| {
+            if mesh.bounds.is_empty() {
+                return [0.0; 3];
+            }
+            ((Vector3::from(mesh.bounds.center()) - center) * factor).into()
+        })
+        .collect()
+}
@@ -0,0 +1,88 @@
+use std::sync::OnceLock;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/*
+    Built in textures, made from code instead of loaded from a file:
+    - Missing: magenta and black squares, what a material gets when its
+      texture file isn't there or can't be decoded. The model still loads,
+      with a warning in the log, and the hole is easy to spot.
+    - Checkerboard: gray squares, for looking at how the uvs are laid out
+      (stretching, seams, the box projection of MeshData::project_uvs()).
+    - UvGradient: u in red, v in green, to see which way the uvs run.
+    - Solid: a single color.
+
+    The images are made once, when the renderer is built, and every
+    material that needs one uploads its own copy, as materials own their
+    textures.
+
+    usage:
+        renderer.set_debug_texture(model, material, FallbackTexture::Checkerboard)?;
+*/
+
+// side of the generated images, solid ones are a single pixel.
+const SIZE: u32 = 64;
+// squares per side of the checkerboards.
+const SQUARES: u32 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FallbackTexture {
+    Missing,
+    Checkerboard,
+    UvGradient,
+    Solid([u8; 4]),
+}
+
+impl FallbackTexture {
+    pub fn image(self) -> DynamicImage {
+        let images = images();
+        let image = match self {
+            FallbackTexture::Missing => images.missing.clone(),
+            FallbackTexture::Checkerboard => images.checkerboard.clone(),
+            FallbackTexture::UvGradient => images.uv_gradient.clone(),
+            FallbackTexture::Solid(color) => RgbaImage::from_pixel(1, 1, Rgba(color)),
+        };
+        DynamicImage::ImageRgba8(image)
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            FallbackTexture::Missing => "missing texture".to_string(),
+            FallbackTexture::Checkerboard => "checkerboard texture".to_string(),
+            FallbackTexture::UvGradient => "uv gradient texture".to_string(),
+            FallbackTexture::Solid([r, g, b, a]) => format!("solid texture {} {} {} {}", r, g, b, a),
+        }
+    }
+}
+
+pub struct FallbackImages {
+    missing: RgbaImage,
+    checkerboard: RgbaImage,
+    uv_gradient: RgbaImage,
+}
+
+// The generated images, made on the first call.
+pub fn images() -> &'static FallbackImages {
+    static IMAGES: OnceLock<FallbackImages> = OnceLock::new();
+    IMAGES.get_or_init(|| FallbackImages {
+        missing: checkerboard([255, 0, 255, 255], [0, 0, 0, 255]),
+        checkerboard: checkerboard([200, 200, 200, 255], [90, 90, 90, 255]),
+        uv_gradient: RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+            // pixel centers, so the corners are the same as the uvs.
+            let u = (x as f32 + 0.5) / SIZE as f32;
+            let v = (y as f32 + 0.5) / SIZE as f32;
+            Rgba([(u * 255.0).round() as u8, (v * 255.0).round() as u8, 0, 255])
+        }),
+    })
+}
+
+fn checkerboard(a: [u8; 4], b: [u8; 4]) -> RgbaImage {
+    let square = SIZE / SQUARES;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        if (x / square + y / square) % 2 == 0 {
+            Rgba(a)
+        } else {
+            Rgba(b)
+        }
+    })
+}
@@ -0,0 +1,1104 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{Deg, Matrix, Matrix3, Matrix4, SquareMatrix, Vector3};
+
+use crate::model::DEFAULT_DIFFUSE_COLOR;
+
+/*
+    Reader for FBX 7.x files, ascii and binary.
+
+    Both encodings are read into the same tree of nodes
+    (name, list of values, child nodes), and the scene is pulled out
+    of that tree:
+    - Geometry nodes hold the polygons, normals, uv sets and vertex colors,
+    - Model nodes are the scene graph, with a local transform each,
+    - Material and Texture nodes the basic material,
+    - Connections tie all of them together by object id.
+
+    The node transforms are baked into the positions, so every
+    mesh comes out in world space. Polygons are triangulated as fans,
+    and every triangle corner gets its own vertex (fbx stores normals
+    and uvs per polygon corner, so there is little to share anyway).
+
+    Animation, skinning, cameras and lights are ignored, and so are
+    the axis and unit settings: positions are in the units of the file.
+*/
+
+pub struct FbxScene {
+    pub meshes: Vec<FbxMesh>,
+    pub materials: Vec<FbxMaterial>,
+}
+
+pub struct FbxMaterial {
+    pub name: String,
+    pub diffuse_color: [f32; 3],
+    // as written in the file, relative to the fbx.
+    pub diffuse_texture: Option<PathBuf>,
+    // texture in the ambient slot, where lightmaps usually go.
+    pub lightmap_texture: Option<PathBuf>,
+    pub displacement_texture: Option<PathBuf>,
+    pub displacement_scale: f32,
+}
+
+// triangle list, 3 corners per triangle.
+pub struct FbxMesh {
+    pub name: String,
+    pub material: usize,
+    pub positions: Vec<[f32; 3]>,
+    // empty if the geometry has no normals / uvs / colors.
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 3]>,
+    // second uv set, for lightmaps.
+    pub uvs2: Vec<[f32; 2]>,
+}
+
+pub struct FbxNode {
+    pub name: String,
+    pub values: Vec<FbxValue>,
+    pub children: Vec<FbxNode>,
+}
+
+#[derive(Debug)]
+pub enum FbxValue {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    IntArray(Vec<i64>),
+    FloatArray(Vec<f64>),
+    Raw(Vec<u8>),
+}
+
+impl FbxValue {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            FbxValue::Int(value) => Some(*value),
+            FbxValue::Float(value) => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FbxValue::Int(value) => Some(*value as f64),
+            FbxValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FbxValue::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64s(&self) -> Vec<f64> {
+        match self {
+            FbxValue::IntArray(values) => values.iter().map(|value| *value as f64).collect(),
+            FbxValue::FloatArray(values) => values.clone(),
+            _ => self.as_f64().into_iter().collect(),
+        }
+    }
+
+    pub fn to_i64s(&self) -> Vec<i64> {
+        match self {
+            FbxValue::IntArray(values) => values.clone(),
+            FbxValue::FloatArray(values) => values.iter().map(|value| *value as i64).collect(),
+            _ => self.as_i64().into_iter().collect(),
+        }
+    }
+}
+
+impl FbxNode {
+    pub fn child(&self, name: &str) -> Option<&FbxNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a FbxNode> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn value(&self, index: usize) -> Option<&FbxValue> {
+        self.values.get(index)
+    }
+
+    // first value of the child `name`, e.g. MappingInformationType.
+    fn child_str(&self, name: &str) -> Option<&str> {
+        self.child(name).and_then(|child| child.value(0)).and_then(|value| value.as_str())
+    }
+
+    // Values of an entry in Properties70:
+    //     P: "Lcl Translation", "Lcl Translation", "", "A", 0, 0, 0
+    // -> the numbers after the four header strings.
+    fn property(&self, name: &str) -> Option<&[FbxValue]> {
+        self.child("Properties70")?
+            .children_named("P")
+            .find(|p| p.value(0).and_then(|value| value.as_str()) == Some(name))
+            .map(|p| p.values.get(4..).unwrap_or(&[]))
+    }
+
+    fn property_vec3(&self, name: &str) -> Option<[f64; 3]> {
+        let values = self.property(name)?;
+        match values {
+            [x, y, z, ..] => Some([x.as_f64()?, y.as_f64()?, z.as_f64()?]),
+            _ => None,
+        }
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<FbxScene> {
+    let bytes = std::fs::read(path.as_ref())
+        .with_context(|| format!("Unable to read {:?}.", path.as_ref()))?;
+    let nodes = parse(&bytes)
+        .with_context(|| format!("Unable to parse fbx file {:?}.", path.as_ref()))?;
+    scene(&nodes)
+        .with_context(|| format!("Unable to read the scene of {:?}.", path.as_ref()))
+}
+
+// top level nodes of the file, either encoding.
+pub fn parse(bytes: &[u8]) -> Result<Vec<FbxNode>> {
+    if bytes.starts_with(BINARY_MAGIC) {
+        parse_binary(bytes)
+    } else {
+        parse_ascii(std::str::from_utf8(bytes)?)
+    }
+}
+
+/*
+    Binary encoding:
+        "Kaydara FBX Binary  \0", 0x1a, 0x00, version (u32)
+    then node records:
+        end offset, value count, value list length (u32, u64 since 7.5),
+        name length (u8), name, values, child records
+    a record of zeros ends every list of children.
+*/
+
+const BINARY_MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+// a type code and at least one byte, a value count can't claim more
+// values than that leaves room for.
+const MIN_VALUE_SIZE: usize = 2;
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<FbxNode>> {
+    let mut reader = BinaryReader { bytes, offset: 23 };
+    let version = reader.u32()?;
+    let wide = version >= 7500;
+    if version < 7000 {
+        bail!("FBX version {} is not supported, only 7.x.", version);
+    }
+
+    let mut nodes = Vec::new();
+    while let Some(node) = reader.node(wide)? {
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.offset.checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .context("Unexpected end of fbx data.")?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    // None for the record that ends a list.
+    fn node(&mut self, wide: bool) -> Result<Option<FbxNode>> {
+        let (end, num_values) = if wide {
+            let end = self.u64()? as usize;
+            let num_values = self.u64()? as usize;
+            self.u64()?;
+            (end, num_values)
+        } else {
+            let end = self.u32()? as usize;
+            let num_values = self.u32()? as usize;
+            self.u32()?;
+            (end, num_values)
+        };
+        let name_len = self.u8()? as usize;
+        // all zero record: end of the list.
+        if end == 0 {
+            return Ok(None);
+        }
+        let name = String::from_utf8_lossy(self.take(name_len)?).into_owned();
+        // the values and children lie between here and the end, a node
+        // ending before that would be read again and again.
+        if end < self.offset || end > self.bytes.len() {
+            bail!("Fbx node {:?} ends at {}, outside of {}..{}.", name, end, self.offset, self.bytes.len());
+        }
+        if num_values > (end - self.offset) / MIN_VALUE_SIZE {
+            bail!("Fbx node {:?} has {} values, more than fit into it.", name, num_values);
+        }
+
+        let mut values = Vec::with_capacity(num_values);
+        for _ in 0..num_values {
+            values.push(self.value()?);
+        }
+        if self.offset > end {
+            bail!("The values of fbx node {:?} run past its end.", name);
+        }
+
+        let mut children = Vec::new();
+        while self.offset < end {
+            match self.node(wide)? {
+                Some(child) => children.push(child),
+                None => break,
+            }
+        }
+        self.offset = end;
+
+        Ok(Some(FbxNode { name, values, children }))
+    }
+
+    fn value(&mut self) -> Result<FbxValue> {
+        let kind = self.u8()?;
+        Ok(match kind {
+            b'C' => FbxValue::Int(self.u8()? as i64),
+            b'Y' => {
+                let b = self.take(2)?;
+                FbxValue::Int(i16::from_le_bytes([b[0], b[1]]) as i64)
+            }
+            b'I' => FbxValue::Int(self.u32()? as i32 as i64),
+            b'L' => FbxValue::Int(self.u64()? as i64),
+            b'F' => FbxValue::Float(f32::from_bits(self.u32()?) as f64),
+            b'D' => FbxValue::Float(f64::from_bits(self.u64()?)),
+            b'S' => {
+                let len = self.u32()? as usize;
+                FbxValue::Str(String::from_utf8_lossy(self.take(len)?).into_owned())
+            }
+            b'R' => {
+                let len = self.u32()? as usize;
+                FbxValue::Raw(self.take(len)?.to_vec())
+            }
+            b'f' => FbxValue::FloatArray(self.array(4)?
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                .collect()),
+            b'd' => FbxValue::FloatArray(self.array(8)?
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+                .collect()),
+            b'i' => FbxValue::IntArray(self.array(4)?
+                .chunks_exact(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as i64)
+                .collect()),
+            b'l' => FbxValue::IntArray(self.array(8)?
+                .chunks_exact(8)
+                .map(|b| i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+                .collect()),
+            b'b' => FbxValue::IntArray(self.array(1)?.iter().map(|b| *b as i64).collect()),
+            other => bail!("Unknown fbx value type {:?}.", other as char),
+        })
+    }
+
+    // array values: length, encoding (1 = zlib), byte length, data.
+    fn array(&mut self, item_size: usize) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        let encoding = self.u32()?;
+        let byte_len = self.u32()? as usize;
+        let data = self.take(byte_len)?;
+        let size = len.checked_mul(item_size).context("Fbx array is too long.")?;
+
+        let bytes = match encoding {
+            0 => data.to_vec(),
+            // no more than the array's length, whatever the stream inflates to.
+            1 => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(data, size)
+                .map_err(|e| anyhow::anyhow!("Unable to inflate fbx array: {:?}", e))?,
+            other => bail!("Unknown fbx array encoding {}.", other),
+        };
+        if bytes.len() < size {
+            bail!("Fbx array is shorter than its length.");
+        }
+        Ok(bytes)
+    }
+}
+
+/*
+    Ascii encoding, one node per line:
+        Name: value, value, ... {
+            Child: ...
+        }
+    arrays are written as
+        Vertices: *6 {
+            a: 0,0,0,1,0,0
+        }
+    and are folded into the values of the node here.
+*/
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Key(String),
+    Str(String),
+    Number(String),
+    Word(String),
+    Count,
+    Comma,
+    Open,
+    Close,
+    Newline,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\n' => {
+                tokens.push(Token::Newline);
+                i += 1;
+            }
+            ';' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '{' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            '}' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '"' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '"' {
+                    i += 1;
+                }
+                if i == chars.len() {
+                    bail!("Unterminated string in fbx file.");
+                }
+                tokens.push(Token::Str(chars[start..i].iter().collect()));
+                i += 1;
+            }
+            '*' => {
+                // the element count, the values tell us that anyway.
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                tokens.push(Token::Count);
+            }
+            c if c.is_whitespace() => i += 1,
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "+-.#".contains(chars[i])) {
+                    i += 1;
+                }
+                tokens.push(Token::Number(chars[start..i].iter().collect()));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '|') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if i < chars.len() && chars[i] == ':' {
+                    tokens.push(Token::Key(word));
+                    i += 1;
+                } else {
+                    tokens.push(Token::Word(word));
+                }
+            }
+            other => bail!("Unexpected {:?} in fbx file.", other),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<FbxNode>> {
+    let tokens = tokenize(text)?;
+    let mut offset = 0;
+    ascii_nodes(&tokens, &mut offset, false)
+}
+
+fn ascii_nodes(tokens: &[Token], offset: &mut usize, nested: bool) -> Result<Vec<FbxNode>> {
+    let mut nodes = Vec::new();
+    loop {
+        match tokens.get(*offset) {
+            Some(Token::Newline) => *offset += 1,
+            Some(Token::Key(_)) => nodes.push(ascii_node(tokens, offset)?),
+            Some(Token::Close) if nested => {
+                *offset += 1;
+                return Ok(nodes);
+            }
+            None if !nested => return Ok(nodes),
+            None => bail!("Missing }} at the end of the fbx file."),
+            Some(other) => bail!("Unexpected {:?} in fbx file.", other),
+        }
+    }
+}
+
+fn ascii_node(tokens: &[Token], offset: &mut usize) -> Result<FbxNode> {
+    let name = match &tokens[*offset] {
+        Token::Key(name) => name.clone(),
+        _ => unreachable!(),
+    };
+    *offset += 1;
+
+    let mut values = Vec::new();
+    let mut is_array = false;
+    loop {
+        match tokens.get(*offset) {
+            Some(Token::Str(value)) | Some(Token::Word(value)) => values.push(FbxValue::Str(value.clone())),
+            Some(Token::Number(value)) => values.push(parse_number(value)?),
+            Some(Token::Count) => is_array = true,
+            _ => break,
+        }
+        *offset += 1;
+
+        // values continue after a comma, even on the next line.
+        if tokens.get(*offset) != Some(&Token::Comma) {
+            break;
+        }
+        *offset += 1;
+        while tokens.get(*offset) == Some(&Token::Newline) {
+            *offset += 1;
+        }
+    }
+
+    let mut children = Vec::new();
+    if tokens.get(*offset) == Some(&Token::Open) {
+        *offset += 1;
+        children = ascii_nodes(tokens, offset, true)?;
+    }
+
+    if is_array {
+        if let Some(index) = children.iter().position(|child| child.name == "a") {
+            let items = children.remove(index).values;
+            values = vec![if items.iter().all(|item| matches!(item, FbxValue::Int(_))) {
+                FbxValue::IntArray(items.iter().filter_map(|item| item.as_i64()).collect())
+            } else {
+                FbxValue::FloatArray(items.iter().filter_map(|item| item.as_f64()).collect())
+            }];
+        }
+    }
+
+    Ok(FbxNode { name, values, children })
+}
+
+fn parse_number(text: &str) -> Result<FbxValue> {
+    if let Ok(value) = text.parse::<i64>() {
+        return Ok(FbxValue::Int(value));
+    }
+    text.parse::<f64>()
+        .map(FbxValue::Float)
+        .with_context(|| format!("Invalid number {:?} in fbx file.", text))
+}
+
+// "Model::Cube" in ascii files, "Cube\0\x01Model" in binary ones.
+fn object_name(value: Option<&FbxValue>) -> String {
+    let name = value.and_then(|value| value.as_str()).unwrap_or("");
+    if let Some(end) = name.find("\u{0}\u{1}") {
+        name[..end].to_string()
+    } else if let Some(start) = name.find("::") {
+        name[start + 2..].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/*
+    Scene extraction.
+*/
+
+struct Connection<'a> {
+    child: i64,
+    parent: i64,
+    property: Option<&'a str>,
+}
+
+pub fn scene(nodes: &[FbxNode]) -> Result<FbxScene> {
+    let objects = nodes.iter()
+        .find(|node| node.name == "Objects")
+        .context("FBX file has no Objects.")?;
+
+    let mut by_id: HashMap<i64, &FbxNode> = HashMap::new();
+    for object in &objects.children {
+        if let Some(id) = object.value(0).and_then(|value| value.as_i64()) {
+            by_id.insert(id, object);
+        }
+    }
+
+    // C: "OO", child, parent  or  C: "OP", child, parent, property
+    let connections: Vec<Connection> = nodes.iter()
+        .filter(|node| node.name == "Connections")
+        .flat_map(|node| node.children_named("C"))
+        .filter_map(|c| Some(Connection {
+            child: c.value(1)?.as_i64()?,
+            parent: c.value(2)?.as_i64()?,
+            property: c.value(3).and_then(|value| value.as_str()),
+        }))
+        .collect();
+
+    let is_kind = |id: i64, kind: &str| by_id.get(&id).is_some_and(|node| node.name == kind);
+
+    // materials, in file order.
+    let mut materials = Vec::new();
+    let mut material_index = HashMap::new();
+    for node in objects.children_named("Material") {
+        let id = node.value(0).and_then(|value| value.as_i64()).unwrap_or(0);
+        let diffuse_color = node.property_vec3("DiffuseColor")
+            .or_else(|| node.property_vec3("Diffuse"))
+            .map(|[r, g, b]| [r as f32, g as f32, b as f32])
+            .unwrap_or(DEFAULT_DIFFUSE_COLOR);
+
+        let diffuse_texture = connections.iter()
+            .filter(|c| c.parent == id && is_kind(c.child, "Texture"))
+            .find(|c| c.property.map_or(true, |property| property.contains("Diffuse")))
+            .and_then(|c| texture_path(by_id[&c.child]));
+        let lightmap_texture = connections.iter()
+            .filter(|c| c.parent == id && is_kind(c.child, "Texture"))
+            .find(|c| c.property.is_some_and(|property| property.contains("Ambient")))
+            .and_then(|c| texture_path(by_id[&c.child]));
+        let displacement_texture = connections.iter()
+            .filter(|c| c.parent == id && is_kind(c.child, "Texture"))
+            .find(|c| c.property.is_some_and(|property| property.contains("Displacement")))
+            .and_then(|c| texture_path(by_id[&c.child]));
+        let displacement_scale = node.property("DisplacementFactor")
+            .and_then(|values| values.first())
+            .and_then(|value| value.as_f64())
+            .unwrap_or(1.0) as f32;
+
+        material_index.insert(id, materials.len());
+        materials.push(FbxMaterial {
+            name: object_name(node.value(1)),
+            diffuse_color,
+            diffuse_texture,
+            lightmap_texture,
+            displacement_texture,
+            displacement_scale,
+        });
+    }
+
+    let mut meshes = Vec::new();
+    for geometry in objects.children_named("Geometry") {
+        if geometry.value(2).and_then(|value| value.as_str()) != Some("Mesh") {
+            continue;
+        }
+        let id = geometry.value(0).and_then(|value| value.as_i64()).unwrap_or(0);
+
+        let models: Vec<i64> = connections.iter()
+            .filter(|c| c.child == id && is_kind(c.parent, "Model"))
+            .map(|c| c.parent)
+            .collect();
+
+        if models.is_empty() {
+            // geometry without a node, keep it where it is.
+            let mut scene_materials = Vec::new();
+            let name = object_name(geometry.value(1));
+            read_geometry(geometry, &name, Matrix4::identity(), &mut scene_materials, &mut materials, &mut meshes)?;
+            continue;
+        }
+
+        // the same geometry can be used by several nodes (instances).
+        for model in models {
+            let node = by_id[&model];
+            let mut scene_materials: Vec<usize> = connections.iter()
+                .filter(|c| c.parent == model)
+                .filter_map(|c| material_index.get(&c.child).copied())
+                .collect();
+            let transform = world_transform(model, &by_id, &connections) * geometric_transform(node);
+            read_geometry(geometry, &object_name(node.value(1)), transform, &mut scene_materials, &mut materials, &mut meshes)?;
+        }
+    }
+
+    Ok(FbxScene { meshes, materials })
+}
+
+fn texture_path(texture: &FbxNode) -> Option<PathBuf> {
+    let relative = texture.child_str("RelativeFilename").filter(|name| !name.is_empty());
+    let absolute = texture.child_str("FileName").filter(|name| !name.is_empty());
+
+    // the absolute path is from the machine the file was made on,
+    // so only its file name is of any use.
+    let path = match (relative, absolute) {
+        (Some(relative), _) => relative,
+        (None, Some(absolute)) => absolute.rsplit(['/', '\\']).next()?,
+        (None, None) => return None,
+    };
+    // windows separators, sometimes doubled by escaping.
+    Some(path.split(['/', '\\']).filter(|part| !part.is_empty()).collect())
+}
+
+fn local_transform(node: &FbxNode) -> Matrix4<f32> {
+    let translation = node.property_vec3("Lcl Translation").unwrap_or([0.0; 3]);
+    let rotation = node.property_vec3("Lcl Rotation").unwrap_or([0.0; 3]);
+    let pre_rotation = node.property_vec3("PreRotation").unwrap_or([0.0; 3]);
+    let scaling = node.property_vec3("Lcl Scaling").unwrap_or([1.0; 3]);
+
+    Matrix4::from_translation(to_vector(translation))
+        * euler_xyz(pre_rotation)
+        * euler_xyz(rotation)
+        * Matrix4::from_nonuniform_scale(scaling[0] as f32, scaling[1] as f32, scaling[2] as f32)
+}
+
+// applies to the geometry of the node only, not to its children.
+fn geometric_transform(node: &FbxNode) -> Matrix4<f32> {
+    let translation = node.property_vec3("GeometricTranslation").unwrap_or([0.0; 3]);
+    let rotation = node.property_vec3("GeometricRotation").unwrap_or([0.0; 3]);
+    let scaling = node.property_vec3("GeometricScaling").unwrap_or([1.0; 3]);
+
+    Matrix4::from_translation(to_vector(translation))
+        * euler_xyz(rotation)
+        * Matrix4::from_nonuniform_scale(scaling[0] as f32, scaling[1] as f32, scaling[2] as f32)
+}
+
+// fbx rotations are in degrees, applied x first, then y, then z.
+fn euler_xyz(degrees: [f64; 3]) -> Matrix4<f32> {
+    Matrix4::from_angle_z(Deg(degrees[2] as f32))
+        * Matrix4::from_angle_y(Deg(degrees[1] as f32))
+        * Matrix4::from_angle_x(Deg(degrees[0] as f32))
+}
+
+fn to_vector(v: [f64; 3]) -> Vector3<f32> {
+    Vector3::new(v[0] as f32, v[1] as f32, v[2] as f32)
+}
+
+fn world_transform(
+    model: i64,
+    by_id: &HashMap<i64, &FbxNode>,
+    connections: &[Connection],
+) -> Matrix4<f32> {
+    let mut transform = Matrix4::identity();
+    let mut current = Some(model);
+    // the depth limit guards against broken files with cycles.
+    for _ in 0..256 {
+        let id = match current {
+            Some(id) => id,
+            None => break,
+        };
+        transform = local_transform(by_id[&id]) * transform;
+        current = connections.iter()
+            .find(|c| c.child == id && by_id.get(&c.parent).is_some_and(|node| node.name == "Model"))
+            .map(|c| c.parent);
+    }
+    transform
+}
+
+// One layer element (normals, uvs, ...) of a geometry.
+struct Layer {
+    mapping: String,
+    data: Vec<f64>,
+    index: Option<Vec<i64>>,
+}
+
+impl Layer {
+    // `set` picks between elements of the same kind, e.g. the second uv set.
+    fn read(geometry: &FbxNode, element: &str, set: usize, data: &str, index: &str) -> Option<Self> {
+        let element = geometry.children_named(element).nth(set)?;
+        let direct = element.child_str("ReferenceInformationType") == Some("Direct");
+        Some(Self {
+            mapping: element.child_str("MappingInformationType").unwrap_or("ByPolygonVertex").to_string(),
+            data: element.child(data)?.value(0)?.to_f64s(),
+            index: if direct {
+                None
+            } else {
+                element.child(index).and_then(|node| node.value(0)).map(|value| value.to_i64s())
+            },
+        })
+    }
+
+    // index into the data for one polygon corner.
+    fn lookup(&self, corner: usize, vertex: usize, polygon: usize) -> Option<usize> {
+        let i = match self.mapping.as_str() {
+            "ByPolygonVertex" => corner,
+            "ByVertex" | "ByVertice" => vertex,
+            "ByPolygon" => polygon,
+            "AllSame" => 0,
+            _ => return None,
+        };
+        match &self.index {
+            Some(index) => index.get(i).and_then(|i| usize::try_from(*i).ok()),
+            None => Some(i),
+        }
+    }
+}
+
+// the `i`th of the `size` values long elements in `data`, indices of
+// the file can be anything.
+fn element(data: &[f64], i: usize, size: usize) -> Option<&[f64]> {
+    let start = i.checked_mul(size)?;
+    data.get(start..start.checked_add(size)?)
+}
+
+fn read_geometry(
+    geometry: &FbxNode,
+    name: &str,
+    transform: Matrix4<f32>,
+    scene_materials: &mut Vec<usize>,
+    materials: &mut Vec<FbxMaterial>,
+    meshes: &mut Vec<FbxMesh>,
+) -> Result<()> {
+    let positions = geometry.child("Vertices")
+        .and_then(|node| node.value(0))
+        .context("Geometry without vertices.")?
+        .to_f64s();
+    let polygon_indices = geometry.child("PolygonVertexIndex")
+        .and_then(|node| node.value(0))
+        .context("Geometry without polygons.")?
+        .to_i64s();
+
+    let normals = Layer::read(geometry, "LayerElementNormal", 0, "Normals", "NormalsIndex");
+    let uvs = Layer::read(geometry, "LayerElementUV", 0, "UV", "UVIndex");
+    let uvs2 = Layer::read(geometry, "LayerElementUV", 1, "UV", "UVIndex");
+    // rgba, the alpha is dropped.
+    let colors = Layer::read(geometry, "LayerElementColor", 0, "Colors", "ColorIndex");
+    // the material layer is all index, the data is the per polygon material.
+    let polygon_materials = geometry.child("LayerElementMaterial")
+        .and_then(|element| element.child("Materials"))
+        .and_then(|node| node.value(0))
+        .map(|value| value.to_i64s())
+        .unwrap_or_default();
+
+    if scene_materials.is_empty() {
+        scene_materials.push(materials.len());
+        materials.push(FbxMaterial {
+            name: format!("{} default", name),
+            diffuse_color: DEFAULT_DIFFUSE_COLOR,
+            diffuse_texture: None,
+            lightmap_texture: None,
+            displacement_texture: None,
+            displacement_scale: 1.0,
+        });
+    }
+
+    let normal_matrix = {
+        let m = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate());
+        m.invert().map(|inverse| inverse.transpose()).unwrap_or(m)
+    };
+    let position = |vertex: usize| -> Option<[f32; 3]> {
+        let p = element(&positions, vertex, 3)?;
+        let p = transform * Vector3::new(p[0] as f32, p[1] as f32, p[2] as f32).extend(1.0);
+        Some([p.x, p.y, p.z])
+    };
+
+    // one output mesh per material used by the geometry.
+    let mut by_material: Vec<Option<FbxMesh>> = (0..scene_materials.len()).map(|_| None).collect();
+
+    let mut polygon = 0;
+    let mut start = 0;
+    for (end, raw) in polygon_indices.iter().enumerate() {
+        // the last corner of each polygon is stored as -(index + 1).
+        if *raw >= 0 {
+            continue;
+        }
+        let corners: Vec<(usize, usize)> = (start..=end)
+            .map(|corner| {
+                let vertex = polygon_indices[corner];
+                let vertex = if vertex < 0 { vertex.checked_neg().map(|vertex| vertex - 1) } else { Some(vertex) };
+                // out of range for position() too, if it doesn't fit.
+                (corner, vertex.and_then(|vertex| usize::try_from(vertex).ok()).unwrap_or(usize::MAX))
+            })
+            .collect();
+
+        let local_material = match polygon_materials.as_slice() {
+            [] => 0,
+            [all] => *all as usize,
+            per_polygon => per_polygon.get(polygon).copied().unwrap_or(0) as usize,
+        };
+        let slot = local_material.min(scene_materials.len() - 1);
+        let mesh = by_material[slot].get_or_insert_with(|| FbxMesh {
+            name: if scene_materials.len() > 1 { format!("{} {}", name, slot) } else { name.to_string() },
+            material: scene_materials[slot],
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            uvs2: Vec::new(),
+        });
+
+        for i in 1..corners.len().saturating_sub(1) {
+            for &(corner, vertex) in &[corners[0], corners[i], corners[i + 1]] {
+                mesh.positions.push(position(vertex).context("Polygon index out of range.")?);
+
+                if let Some(layer) = &normals {
+                    let n = layer.lookup(corner, vertex, polygon)
+                        .and_then(|i| element(&layer.data, i, 3))
+                        .map(|n| normal_matrix * Vector3::new(n[0] as f32, n[1] as f32, n[2] as f32))
+                        .map(|n| if n.x == 0.0 && n.y == 0.0 && n.z == 0.0 { n } else { cgmath::InnerSpace::normalize(n) })
+                        .unwrap_or_else(|| Vector3::new(0.0, 1.0, 0.0));
+                    mesh.normals.push(n.into());
+                }
+                for (layer, out) in [(&uvs, &mut mesh.uvs), (&uvs2, &mut mesh.uvs2)] {
+                    if let Some(layer) = layer {
+                        let uv = layer.lookup(corner, vertex, polygon)
+                            .and_then(|i| element(&layer.data, i, 2))
+                            .map(|uv| [uv[0] as f32, uv[1] as f32])
+                            .unwrap_or([0.0, 0.0]);
+                        out.push(uv);
+                    }
+                }
+                if let Some(layer) = &colors {
+                    let color = layer.lookup(corner, vertex, polygon)
+                        .and_then(|i| element(&layer.data, i, 4))
+                        .map(|c| [c[0] as f32, c[1] as f32, c[2] as f32])
+                        .unwrap_or([1.0, 1.0, 1.0]);
+                    mesh.colors.push(color);
+                }
+            }
+        }
+
+        polygon += 1;
+        start = end + 1;
+    }
+
+    meshes.extend(by_material.into_iter().flatten());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 7.4 node record: u32 end offset, value count and list length.
+    fn record(out: &mut Vec<u8>, name: &str, values: &[Vec<u8>], children: impl FnOnce(&mut Vec<u8>)) {
+        let start = out.len();
+        out.extend_from_slice(&[0; 12]);
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        let values_start = out.len();
+        for value in values {
+            out.extend_from_slice(value);
+        }
+        let values_len = (out.len() - values_start) as u32;
+        let before_children = out.len();
+        children(out);
+        if out.len() > before_children {
+            out.extend_from_slice(&[0; 13]);
+        }
+        let end = out.len() as u32;
+        out[start..start + 4].copy_from_slice(&end.to_le_bytes());
+        out[start + 4..start + 8].copy_from_slice(&(values.len() as u32).to_le_bytes());
+        out[start + 8..start + 12].copy_from_slice(&values_len.to_le_bytes());
+    }
+
+    fn array(kind: u8, item_size: usize, bytes: Vec<u8>, compress: bool) -> Vec<u8> {
+        let len = (bytes.len() / item_size) as u32;
+        let (encoding, data) = if compress {
+            (1u32, miniz_oxide::deflate::compress_to_vec_zlib(&bytes, 6))
+        } else {
+            (0u32, bytes)
+        };
+        let mut value = vec![kind];
+        value.extend_from_slice(&len.to_le_bytes());
+        value.extend_from_slice(&encoding.to_le_bytes());
+        value.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        value.extend_from_slice(&data);
+        value
+    }
+
+    fn doubles(values: &[f64], compress: bool) -> Vec<u8> {
+        array(b'd', 8, values.iter().flat_map(|value| value.to_le_bytes()).collect(), compress)
+    }
+
+    fn ints(values: &[i32], compress: bool) -> Vec<u8> {
+        array(b'i', 4, values.iter().flat_map(|value| value.to_le_bytes()).collect(), compress)
+    }
+
+    fn long(value: i64) -> Vec<u8> {
+        let mut bytes = vec![b'L'];
+        bytes.extend_from_slice(&value.to_le_bytes());
+        bytes
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut bytes = vec![b'S'];
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    // a quad as a Geometry under a Model, the vertices compressed.
+    fn quad_file() -> Vec<u8> {
+        let mut file = BINARY_MAGIC.to_vec();
+        file.extend_from_slice(&[0x1a, 0x00]);
+        file.extend_from_slice(&7400u32.to_le_bytes());
+        record(&mut file, "Objects", &[], |out| {
+            record(out, "Geometry", &[long(1), string("Quad\0\x01Geometry"), string("Mesh")], |out| {
+                let vertices = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+                record(out, "Vertices", &[doubles(&vertices, true)], |_| {});
+                record(out, "PolygonVertexIndex", &[ints(&[0, 1, 2, -4], false)], |_| {});
+            });
+            record(out, "Model", &[long(2), string("Quad\0\x01Model"), string("Mesh")], |_| {});
+        });
+        record(&mut file, "Connections", &[], |out| {
+            record(out, "C", &[string("OO"), long(1), long(2)], |_| {});
+            record(out, "C", &[string("OO"), long(2), long(0)], |_| {});
+        });
+        file.extend_from_slice(&[0; 13]);
+        file
+    }
+
+    #[test]
+    fn binary_quad_is_two_triangles() {
+        let scene = scene(&parse(&quad_file()).unwrap()).unwrap();
+        assert_eq!(scene.meshes.len(), 1);
+        assert_eq!(scene.meshes[0].positions.len(), 6);
+        assert_eq!(scene.meshes[0].positions[2], [1.0, 1.0, 0.0]);
+    }
+
+    // the same quad in the ascii encoding.
+    const ASCII_QUAD: &str = "; FBX 7.4.0 project file
+Objects:  {
+    Geometry: 1, \"Geometry::Quad\", \"Mesh\" {
+        Vertices: *12 {
+            a: 0,0,0,1,0,0,
+            1,1,0,0,1,0
+        }
+        PolygonVertexIndex: *4 {
+            a: 0,1,2,-4
+        }
+    }
+    Model: 2, \"Model::Quad\", \"Mesh\" {
+    }
+}
+Connections:  {
+    C: \"OO\",1,2
+    C: \"OO\",2,0
+}
+";
+
+    #[test]
+    fn ascii_quad_is_two_triangles() {
+        let nodes = parse(ASCII_QUAD.as_bytes()).unwrap();
+        let binary = parse(&quad_file()).unwrap();
+        // both encodings give the same vertices.
+        let vertices = |nodes: &[FbxNode]| nodes[0].children[0].children[0].values[0].to_f64s();
+        assert_eq!(vertices(&nodes), vertices(&binary));
+
+        let scene = scene(&nodes).unwrap();
+        assert_eq!(scene.meshes.len(), 1);
+        assert_eq!(scene.meshes[0].positions.len(), 6);
+        assert_eq!(scene.meshes[0].positions[2], [1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn malformed_ascii_is_an_error() {
+        let unclosed = ASCII_QUAD.trim_end().trim_end_matches('}');
+        assert!(parse(unclosed.as_bytes()).is_err());
+        assert!(parse(ASCII_QUAD.replacen("\"Mesh\"", "\"Mesh", 1).as_bytes()).is_err());
+        assert!(parse(ASCII_QUAD.replace("1,1,0", "1,1e,0").as_bytes()).is_err());
+        assert!(parse(b"Objects: { ? }").is_err());
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let file = quad_file();
+        // the records of a cut off file claim more than is there. Shorter
+        // than the magic it's no binary file, the version is needed too.
+        for len in BINARY_MAGIC.len() + 2..file.len() - 13 {
+            assert!(parse(&file[..len]).is_err(), "cut at {}", len);
+        }
+    }
+
+    #[test]
+    fn fuzzed_files_dont_panic() {
+        let file = quad_file();
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..2000 {
+            let mut fuzzed = file.clone();
+            for _ in 0..4 {
+                // xorshift, the same bytes every run.
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                let at = (seed % fuzzed.len() as u64) as usize;
+                fuzzed[at] = (seed >> 32) as u8;
+            }
+            if let Ok(nodes) = parse(&fuzzed) {
+                let _ = scene(&nodes);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_and_offsets_are_checked() {
+        let mut file = BINARY_MAGIC.to_vec();
+        file.extend_from_slice(&[0x1a, 0x00]);
+        file.extend_from_slice(&7400u32.to_le_bytes());
+        let header = file.len();
+        record(&mut file, "Node", &[long(1)], |_| {});
+        let end = file.len();
+
+        // more values than bytes.
+        let mut many = file.clone();
+        many[header + 4..header + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse(&many).is_err());
+
+        // an end before the node's own header.
+        let mut backwards = file.clone();
+        backwards[header..header + 4].copy_from_slice(&(header as u32 + 1).to_le_bytes());
+        assert!(parse(&backwards).is_err());
+
+        // an end past the file.
+        let mut past = file;
+        past[header..header + 4].copy_from_slice(&(end as u32 + 100).to_le_bytes());
+        assert!(parse(&past).is_err());
+    }
+
+    #[test]
+    fn arrays_inflate_to_their_length_only() {
+        // a zlib stream of a megabyte, declared as 4 doubles.
+        let mut bomb = array(b'd', 8, vec![0; 1 << 20], true);
+        bomb[1..5].copy_from_slice(&4u32.to_le_bytes());
+        let mut reader = BinaryReader { bytes: &bomb, offset: 0 };
+        assert!(reader.value().is_err());
+
+        let mut reader = BinaryReader { bytes: &doubles(&[1.0, 2.0], true), offset: 0 };
+        assert_eq!(reader.value().unwrap().to_f64s(), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn polygon_indices_out_of_range_are_errors() {
+        let geometry = |indices: Vec<i64>| FbxNode {
+            name: "Geometry".to_string(),
+            values: Vec::new(),
+            children: vec![
+                FbxNode { name: "Vertices".to_string(), values: vec![FbxValue::FloatArray(vec![0.0; 9])], children: Vec::new() },
+                FbxNode { name: "PolygonVertexIndex".to_string(), values: vec![FbxValue::IntArray(indices)], children: Vec::new() },
+            ],
+        };
+        let read = |indices| {
+            let (mut scene_materials, mut materials, mut meshes) = (Vec::new(), Vec::new(), Vec::new());
+            read_geometry(&geometry(indices), "g", Matrix4::identity(), &mut scene_materials, &mut materials, &mut meshes)
+        };
+        assert!(read(vec![0, 1, -3]).is_ok());
+        assert!(read(vec![0, 1, i64::MIN]).is_err());
+        assert!(read(vec![0, i64::MAX, -3]).is_err());
+        assert!(read(vec![0, 1, -4]).is_err());
+    }
+}
@@ -0,0 +1,420 @@
+use crate::light::Light;
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+
+/*
+    Lens flares and light shafts (god rays) for the renderer lights.
+
+    Both are screen space effects drawn after the scene, for every
+    light that has them switched on (Light::with_flare / with_shafts).
+
+    - flare: a glow sprite on the light and a few tinted ghosts along
+      the line through the screen center. The glow is occlusion tested
+      against the depth buffer, so it fades when the light goes behind
+      something.
+    - shafts: the light's disc is drawn into a half resolution mask,
+      wherever the depth buffer shows sky, and then blurred radially
+      away from the light onto the frame.
+
+    Without the scene's depth (FrameTargets::depth is None with msaa,
+    dynamic resolution or stereo) nothing counts as occluding: flares are
+    only faded at the screen edges and the shafts are a streaky glow.
+
+    usage:
+        renderer.add_light(Light::new((50.0, 40.0, -80.0).into(), [1.0, 0.9, 0.7])
+            .with_flare(LensFlare::default())
+            .with_shafts(LightShafts::default()));
+        renderer.add_plugin(LensFlarePass::new());
+*/
+
+// sprites per flare, see vs_flare.
+const FLARE_ELEMENTS: u32 = 6;
+const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FlareUniform {
+    view_proj: [[f32; 4]; 4],
+    light_position: [f32; 4],
+    color: [f32; 4],
+    viewport: [f32; 2],
+    flare_size: f32,
+    has_depth: u32,
+    shaft_density: f32,
+    shaft_decay: f32,
+    shaft_weight: f32,
+    shaft_exposure: f32,
+    shaft_radius: f32,
+    shaft_samples: u32,
+    padding: [u32; 2],
+}
+
+impl FlareUniform {
+    fn new(light: &Light, ctx: &PluginContext, targets: &FrameTargets) -> Self {
+        let flare = light.flare.unwrap_or_default();
+        let shafts = light.shafts.unwrap_or_default();
+        let p = light.position;
+        Self {
+            view_proj: ctx.camera.build_view_projection_matrix().into(),
+            light_position: [p.x, p.y, p.z, 1.0],
+            color: [light.color[0], light.color[1], light.color[2], flare.intensity],
+            viewport: [targets.width.max(1) as f32, targets.height.max(1) as f32],
+            flare_size: flare.size,
+            has_depth: targets.depth.is_some() as u32,
+            shaft_density: shafts.density,
+            shaft_decay: shafts.decay,
+            shaft_weight: shafts.weight,
+            shaft_exposure: shafts.exposure,
+            shaft_radius: shafts.radius.max(f32::EPSILON),
+            shaft_samples: shafts.samples,
+            padding: [0; 2],
+        }
+    }
+}
+
+struct Mask {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+struct GpuState {
+    layout: wgpu::BindGroupLayout,
+    mask_layout: wgpu::BindGroupLayout,
+    flare_pipeline: wgpu::RenderPipeline,
+    mask_pipeline: wgpu::RenderPipeline,
+    shaft_pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    // bound while FrameTargets::depth is None (msaa, dynamic resolution,
+    // stereo), has_depth keeps the shaders from reading it.
+    dummy_depth: wgpu::TextureView,
+    mask: Mask,
+    // one per light, grows with the number of lights.
+    uniform_buffers: Vec<wgpu::Buffer>,
+}
+
+#[derive(Default)]
+pub struct LensFlarePass {
+    gpu: Option<GpuState>,
+}
+
+impl LensFlarePass {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn create_mask(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    width: u32,
+    height: u32,
+) -> Mask {
+    // half resolution, it gets blurred anyway.
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Light Shaft Mask"),
+        size: wgpu::Extent3d {
+            width: (width / 2).max(1),
+            height: (height / 2).max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: MASK_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("light_shaft_mask_bind_group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+    Mask { view, bind_group }
+}
+
+// fullscreen and sprite pipelines, no vertex buffers and no culling.
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    entry_points: (&str, &str),
+    format: wgpu::TextureFormat,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Lens Flare Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: entry_points.0,
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: entry_points.1,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+const ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+impl RenderPlugin for LensFlarePass {
+    fn name(&self) -> &str {
+        "Lens Flare"
+    }
+
+    fn setup(&mut self, ctx: &PluginContext) {
+        let device = ctx.device;
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lens_flare_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let mask_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light_shaft_mask_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Lens Flare Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("flare_shader.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lens Flare Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shaft_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Shaft Pipeline Layout"),
+            bind_group_layouts: &[&layout, &mask_layout],
+            push_constant_ranges: &[],
+        });
+
+        let format = ctx.config.format;
+        let flare_pipeline = create_pipeline(
+            device, &pipeline_layout, &shader, ("vs_flare", "fs_flare"), format, ADDITIVE,
+        );
+        let mask_pipeline = create_pipeline(
+            device, &pipeline_layout, &shader, ("vs_fullscreen", "fs_mask"), MASK_FORMAT,
+            wgpu::BlendState::REPLACE,
+        );
+        let shaft_pipeline = create_pipeline(
+            device, &shaft_pipeline_layout, &shader, ("vs_fullscreen", "fs_shafts"), format, ADDITIVE,
+        );
+
+        // clamp, rays that reach past the screen edge shouldn't wrap around.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let dummy_depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Lens Flare Dummy Depth"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let mask = create_mask(device, &mask_layout, &sampler, ctx.config.width, ctx.config.height);
+
+        self.gpu = Some(GpuState {
+            layout,
+            mask_layout,
+            flare_pipeline,
+            mask_pipeline,
+            shaft_pipeline,
+            sampler,
+            dummy_depth: dummy_depth.create_view(&wgpu::TextureViewDescriptor::default()),
+            mask,
+            uniform_buffers: Vec::new(),
+        });
+    }
+
+    fn resize(&mut self, ctx: &PluginContext, width: u32, height: u32) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.mask = create_mask(ctx.device, &gpu.mask_layout, &gpu.sampler, width, height);
+        }
+    }
+
+    fn update(&mut self, ctx: &PluginContext) {
+        if let Some(gpu) = &mut self.gpu {
+            while gpu.uniform_buffers.len() < ctx.lights.len() {
+                gpu.uniform_buffers.push(ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Lens Flare Uniform Buffer"),
+                    size: std::mem::size_of::<FlareUniform>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+        }
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    ) {
+        let gpu = match &self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        let depth = targets.depth.unwrap_or(&gpu.dummy_depth);
+
+        for (light, uniform_buffer) in ctx.lights.iter().zip(&gpu.uniform_buffers) {
+            if light.flare.is_none() && light.shafts.is_none() {
+                continue;
+            }
+
+            ctx.queue.write_buffer(
+                uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[FlareUniform::new(light, ctx, targets)]),
+            );
+            let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("lens_flare_bind_group"),
+                layout: &gpu.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth),
+                    },
+                ],
+            });
+
+            if light.shafts.is_some() {
+                let mut mask_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Light Shaft Mask Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &gpu.mask.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+                mask_pass.set_pipeline(&gpu.mask_pipeline);
+                mask_pass.set_bind_group(0, &bind_group, &[]);
+                mask_pass.draw(0..3, 0..1);
+            }
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Lens Flare Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: targets.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            if light.shafts.is_some() {
+                render_pass.set_pipeline(&gpu.shaft_pipeline);
+                render_pass.set_bind_group(1, &gpu.mask.bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+            if light.flare.is_some() {
+                render_pass.set_pipeline(&gpu.flare_pipeline);
+                render_pass.draw(0..6, 0..FLARE_ELEMENTS);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Lens flare sprites and light shafts, one uniform per light.
+
+struct FlareUniform {
+    view_proj: mat4x4<f32>,
+    light_position: vec4<f32>,
+    // rgb: light color, a: flare intensity.
+    color: vec4<f32>,
+    viewport: vec2<f32>,
+    flare_size: f32,
+    has_depth: u32,
+    shaft_density: f32,
+    shaft_decay: f32,
+    shaft_weight: f32,
+    shaft_exposure: f32,
+    shaft_radius: f32,
+    shaft_samples: u32,
+    padding: vec2<u32>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: FlareUniform;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+
+@group(1) @binding(0)
+var t_mask: texture_2d<f32>;
+@group(1) @binding(1)
+var s_mask: sampler;
+
+// smoothstep, which this version of wgsl doesn't have.
+fn smooth_step(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+// light in normalized device coordinates, w: 0 if behind the camera.
+fn light_ndc() -> vec4<f32> {
+    let clip = params.view_proj * params.light_position;
+    if (clip.w <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(clip.xyz / clip.w, 1.0);
+}
+
+fn ndc_to_uv(ndc: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// 0..1, how much of the light is not hidden behind the scene.
+fn visibility(light: vec4<f32>) -> f32 {
+    if (light.w == 0.0) {
+        return 0.0;
+    }
+    // fade out towards the screen edges instead of popping.
+    let edge = 1.0 - smooth_step(0.8, 1.1, max(abs(light.x), abs(light.y)));
+    if (params.has_depth == 0u || edge <= 0.0) {
+        return edge;
+    }
+
+    // 5x5 texels around the light, spread a bit so thin branches don't flicker.
+    let center = vec2<i32>(ndc_to_uv(light.xy) * params.viewport);
+    let size = vec2<i32>(params.viewport) - vec2<i32>(1);
+    var visible = 0.0;
+    var y = -2;
+    loop {
+        if (y > 2) {
+            break;
+        }
+        var x = -2;
+        loop {
+            if (x > 2) {
+                break;
+            }
+            let texel = clamp(center + vec2<i32>(x, y) * 2, vec2<i32>(0), size);
+            if (light.z <= textureLoad(t_depth, texel, 0)) {
+                visible = visible + 1.0;
+            }
+            x = x + 1;
+        }
+        y = y + 1;
+    }
+    return edge * visible / 25.0;
+}
+
+// Flare
+
+struct FlareOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) corner: vec2<f32>,
+}
+
+@vertex
+fn vs_flare(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) element: u32,
+) -> FlareOutput {
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    // x: position along the line light -> center -> opposite side,
+    // y: radius (fraction of the screen height), z: brightness.
+    var elements: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+        vec3<f32>(1.0, 0.15, 1.0),
+        vec3<f32>(0.4, 0.025, 0.3),
+        vec3<f32>(-0.2, 0.04, 0.2),
+        vec3<f32>(-0.5, 0.02, 0.35),
+        vec3<f32>(-0.8, 0.06, 0.15),
+        vec3<f32>(-1.2, 0.03, 0.25),
+    );
+    var tints: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+        vec3<f32>(1.0, 1.0, 1.0),
+        vec3<f32>(0.6, 0.8, 1.0),
+        vec3<f32>(1.0, 0.7, 0.4),
+        vec3<f32>(0.5, 1.0, 0.6),
+        vec3<f32>(0.7, 0.5, 1.0),
+        vec3<f32>(1.0, 0.9, 0.6),
+    );
+    let corner = corners[vertex_index];
+    let e = elements[element];
+
+    let light = light_ndc();
+    let alpha = visibility(light) * params.color.a * e.z;
+
+    let aspect = params.viewport.y / params.viewport.x;
+    let center = light.xy * e.x;
+    let offset = corner * e.y * params.flare_size * vec2<f32>(aspect, 1.0) * 2.0;
+
+    var out: FlareOutput;
+    out.clip_position = vec4<f32>(center + offset, 0.0, 1.0);
+    out.color = vec4<f32>(params.color.rgb * tints[element], alpha);
+    out.corner = corner;
+    return out;
+}
+
+@fragment
+fn fs_flare(in: FlareOutput) -> @location(0) vec4<f32> {
+    let falloff = max(1.0 - length(in.corner), 0.0);
+    return vec4<f32>(in.color.rgb, in.color.a * falloff * falloff);
+}
+
+// Light shafts
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// one triangle covering the screen.
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: FullscreenOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// the light's disc, wherever the sky can be seen.
+@fragment
+fn fs_mask(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let light = light_ndc();
+    if (light.w == 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let aspect = params.viewport.x / params.viewport.y;
+    let delta = (in.uv - ndc_to_uv(light.xy)) * vec2<f32>(aspect, 1.0);
+    let disc = 1.0 - smooth_step(0.0, params.shaft_radius, length(delta));
+
+    var sky = 1.0;
+    if (params.has_depth != 0u) {
+        let size = vec2<i32>(params.viewport) - vec2<i32>(1);
+        let texel = clamp(vec2<i32>(in.uv * params.viewport), vec2<i32>(0), size);
+        if (textureLoad(t_depth, texel, 0) < 1.0) {
+            sky = 0.0;
+        }
+    }
+    return vec4<f32>(params.color.rgb * disc * sky, 1.0);
+}
+
+// radial blur of the mask towards the light, added onto the frame.
+@fragment
+fn fs_shafts(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let light = light_ndc();
+    if (light.w == 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let samples = max(params.shaft_samples, 1u);
+    let step = (in.uv - ndc_to_uv(light.xy)) * params.shaft_density / f32(samples);
+
+    var uv = in.uv;
+    var decay = 1.0;
+    var sum = vec3<f32>(0.0);
+    var i = 0u;
+    loop {
+        if (i >= samples) {
+            break;
+        }
+        uv = uv - step;
+        sum = sum + textureSampleLevel(t_mask, s_mask, uv, 0.0).rgb * decay * params.shaft_weight;
+        decay = decay * params.shaft_decay;
+        i = i + 1u;
+    }
+    return vec4<f32>(sum * params.shaft_exposure, 1.0);
+}
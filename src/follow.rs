@@ -0,0 +1,148 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Transform, Vector3};
+
+use crate::cameras::SceneNode;
+use crate::model::Model;
+use crate::picking::{self, Ray};
+
+/*
+    Third-person camera: orbits a node of the scene (see cameras.rs)
+    on a boom of `distance`, looking at a point slightly above it.
+
+    The camera doesn't jump with the node, the point it looks at eases
+    towards the node, so quick moves are followed smoothly.
+
+    When something is between the node and the camera, the boom is
+    shortened so the node stays in view. This is a sphere cast of
+    `radius`: the center ray and four rays at the edge of the sphere go
+    from the node to the camera, against the meshes whose bounds they
+    cross. The boom shortens at once (the camera must not end up
+    inside the terrain) and grows back smoothly.
+
+    usage:
+        let mut follow = FollowCamera::new(SceneNode::Mesh(1, 0), FollowSettings::default());
+        // every frame
+        if let Some((eye, target)) = follow.update(renderer.models(), dt) {
+            renderer.camera_mut().look_at(eye, target);
+        }
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FollowSettings {
+    // length of the boom without obstacles.
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // the camera looks at this point above the center of the node.
+    pub height: f32,
+    // radius of the sphere cast, about the size of the near plane.
+    pub radius: f32,
+    // how quickly the camera catches up, per second. Higher is stiffer.
+    pub stiffness: f32,
+}
+
+impl Default for FollowSettings {
+    fn default() -> Self {
+        Self {
+            distance: 5.0,
+            min_distance: 0.5,
+            max_distance: 50.0,
+            height: 0.5,
+            radius: 0.2,
+            stiffness: 8.0,
+        }
+    }
+}
+
+// pitch stays away from straight up and down, where the view flips.
+const MAX_PITCH: f32 = 1.4;
+
+pub struct FollowCamera {
+    pub node: SceneNode,
+    pub settings: FollowSettings,
+    // around the node, in radians. Yaw 0 is behind the node (+z).
+    yaw: f32,
+    pitch: f32,
+    // smoothed point the camera looks at, None until the first update.
+    target: Option<Point3<f32>>,
+    // current boom length, shorter than settings.distance when blocked.
+    boom: f32,
+}
+
+impl FollowCamera {
+    pub fn new(node: SceneNode, settings: FollowSettings) -> Self {
+        Self {
+            node,
+            boom: settings.distance,
+            settings,
+            yaw: 0.0,
+            pitch: 0.35,
+            target: None,
+        }
+    }
+
+    // turns the camera around the node, e.g. with the mouse.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // positive moves the camera closer.
+    pub fn zoom(&mut self, amount: f32) {
+        self.settings.distance = (self.settings.distance - amount)
+            .clamp(self.settings.min_distance, self.settings.max_distance);
+    }
+
+    // eye and target for this frame, None when the node doesn't exist.
+    // `dt` is the time since the last update in seconds.
+    pub fn update(&mut self, models: &[Model], dt: f32) -> Option<(Point3<f32>, Point3<f32>)> {
+        let node = self.node.world_transform(models)?
+            .transform_point(Point3::new(0.0, self.settings.height, 0.0));
+
+        // frame rate independent easing.
+        let blend = 1.0 - (-self.settings.stiffness * dt.max(0.0)).exp();
+        let target = match self.target {
+            Some(target) => target + (node - target) * blend,
+            None => node,
+        };
+        self.target = Some(target);
+
+        let direction = Vector3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        );
+        let free = self.sphere_cast(models, target, direction, self.settings.distance);
+        self.boom = if free < self.boom {
+            free
+        } else {
+            self.boom + (free - self.boom) * blend
+        };
+
+        Some((target + direction * self.boom, target))
+    }
+
+    // how far the sphere gets from `origin` along `direction`, at most `length`.
+    fn sphere_cast(&self, models: &[Model], origin: Point3<f32>, direction: Vector3<f32>, length: f32) -> f32 {
+        // two axes across the direction, for the rays at the edge of the sphere.
+        let side = if direction.y.abs() < 0.99 { Vector3::unit_y() } else { Vector3::unit_x() };
+        let across = direction.cross(side).normalize();
+        let up = across.cross(direction).normalize();
+        let radius = self.settings.radius;
+        let offsets = [Vector3::new(0.0, 0.0, 0.0), across * radius, -across * radius, up * radius, -up * radius];
+
+        let followed = self.node;
+        let mut free = length;
+        for offset in offsets.iter() {
+            let ray = Ray::new(origin.to_vec() + offset, direction);
+            // the followed node itself doesn't block the view of it.
+            let hit = picking::pick_filtered(models, &ray, |model, mesh| match followed {
+                SceneNode::Model(followed) => model != followed,
+                SceneNode::Mesh(followed_model, followed_mesh) => (model, mesh) != (followed_model, followed_mesh),
+            });
+            if let Some(hit) = hit {
+                free = free.min(hit.distance - radius);
+            }
+        }
+        free.max(0.0)
+    }
+}
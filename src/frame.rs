@@ -0,0 +1,128 @@
+use cgmath::Point3;
+use wgpu::util::DeviceExt;
+
+use crate::inflight::FramesInFlight;
+
+/*
+    The per frame uniform: what every shader may want to know about the
+    frame it's drawn in, the time, the time since the last frame, the size
+    of the window, where the camera is and the wind.
+
+    It's binding 1 of the camera bind group (see camera::UniformBuffer::
+    bind_group_layout()), the group every pipeline of the renderer and of
+    the plugins already binds, so every shader can read it:
+
+        struct FrameUniform {
+            time: f32,
+            delta_time: f32,
+            frame: u32,
+            screen_size: vec4<f32>,
+            camera_position: vec4<f32>,
+            wind: vec4<f32>,
+        }
+
+        @group(1) @binding(1)
+        var<uniform> frame: FrameUniform;
+
+    (the group is 0 in the plugin pipelines, where the camera group comes
+    first.) There is one buffer for all cameras, written once per frame
+    before anything is drawn, camera_position is the eye of the active
+    camera. It goes through the per frame pools, see inflight.rs.
+
+    usage:
+        let mut frame = FrameBuffer::new(&device);
+        // every frame:
+        frame.update(time, dt, frame_count, (width, height), camera.eye());
+        frame.upload(&device, &mut in_flight);
+*/
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FrameUniform {
+    // seconds since the renderer started.
+    pub time: f32,
+    // seconds since the last frame.
+    pub delta_time: f32,
+    // number of the frame, wraps around.
+    pub frame: u32,
+    padding: u32,
+    // width, height, 1 / width and 1 / height of the window in pixels.
+    pub screen_size: [f32; 4],
+    // the eye of the active camera, w is 1.
+    pub camera_position: [f32; 4],
+    // direction times speed of the wind (units per second), w is the speed. See wind.rs.
+    pub wind: [f32; 4],
+}
+
+impl Default for FrameUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameUniform {
+    pub fn new() -> Self {
+        Self {
+            time: 0.0,
+            delta_time: 0.0,
+            frame: 0,
+            padding: 0,
+            screen_size: [1.0; 4],
+            camera_position: [0.0, 0.0, 0.0, 1.0],
+            wind: [0.0; 4],
+        }
+    }
+
+    pub fn update(&mut self, time: f32, delta_time: f32, frame: u64, size: (u32, u32), eye: Point3<f32>) {
+        self.time = time;
+        self.delta_time = delta_time;
+        self.frame = frame as u32;
+        let (width, height) = (size.0.max(1) as f32, size.1.max(1) as f32);
+        self.screen_size = [width, height, 1.0 / width, 1.0 / height];
+        self.camera_position = [eye.x, eye.y, eye.z, 1.0];
+    }
+
+    pub fn set_wind(&mut self, wind: [f32; 3]) {
+        let speed = (wind[0] * wind[0] + wind[1] * wind[1] + wind[2] * wind[2]).sqrt();
+        self.wind = [wind[0], wind[1], wind[2], speed];
+    }
+}
+
+// The uniform and the buffer it's uploaded into.
+pub struct FrameBuffer {
+    uniform: FrameUniform,
+    buffer: wgpu::Buffer,
+}
+
+impl FrameBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform = FrameUniform::new();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Frame Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        Self { uniform, buffer }
+    }
+
+    pub fn update(&mut self, time: f32, delta_time: f32, frame: u64, size: (u32, u32), eye: Point3<f32>) {
+        self.uniform.update(time, delta_time, frame, size, eye);
+    }
+
+    // kept until it's set again, update() leaves it alone.
+    pub fn set_wind(&mut self, wind: [f32; 3]) {
+        self.uniform.set_wind(wind);
+    }
+
+    pub fn upload(&self, device: &wgpu::Device, in_flight: &mut FramesInFlight) {
+        in_flight.write(device, &self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    pub fn uniform(&self) -> &FrameUniform {
+        &self.uniform
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/*
+    Frames in flight: how many frames the cpu may be ahead of the gpu,
+    and the per frame pools the dynamic uploads go through.
+
+    What changes from frame to frame (the camera matrices, the per frame
+    uniform, the lights and the sun's shadow, the instances of the
+    models) isn't written into its buffer with queue.write_buffer(), but
+    into a staging buffer of the frame, mapped on the cpu, and copied
+    over on the gpu in front of the frame's passes. Every frame in flight
+    has its own staging buffer. Once the frame is submitted its buffer is
+    mapped again, which happens when the gpu is done with it; the frame
+    that wants the buffer next, `count` frames later, waits for that.
+
+    So the cpu is never more than `count` frames ahead, and where it has
+    to wait for the gpu it does so in one place, at the end of
+    render_frame() (the "in flight" scope of the profiler, wait_time()),
+    instead of somewhere in the driver when a buffer the gpu still reads
+    is written. 1 waits for every frame before the next one is prepared,
+    2 (the default) prepares the next frame while the gpu draws the last
+    one, 3 evens out uneven frames at the cost of a frame of latency.
+
+    Writes between frames, e.g. set_model_instances() from an input
+    event, go into the buffer of the next frame. What draws outside of
+    render_frame() (screenshots, posters, cube maps) submits them first,
+    see flush(). Plugins write their own buffers themselves.
+
+    The staging buffers start small and grow to what a frame needed,
+    writes that don't fit get a buffer of their own for the frame.
+
+    usage:
+        let mut in_flight = FramesInFlight::new(&device, 2);
+        // any time, the data is in `buffer` when the next frame is drawn:
+        in_flight.write(&device, &buffer, 0, bytemuck::cast_slice(&[uniform]));
+        // every frame:
+        in_flight.submit(&queue);
+        let submission = queue.submit(std::iter::once(encoder.finish()));
+        in_flight.end_frame(&device, submission);
+*/
+
+// frames in flight when RendererBuilder::frames_in_flight() isn't called.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+// more only adds latency.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+// bytes of a staging buffer to start with, a handful of cameras and lights.
+const INITIAL_STAGING_SIZE: u64 = 64 * 1024;
+
+struct FrameSlot {
+    staging: wgpu::Buffer,
+    size: u64,
+    // set once the buffer is mapped again after the frame, see submit().
+    mapped: Arc<AtomicBool>,
+    // the copies of the frame, created with the first write.
+    encoder: Option<wgpu::CommandEncoder>,
+    // bytes of the staging buffer written this frame.
+    used: u64,
+    // the last submission of the frame that used the slot.
+    submission: Option<wgpu::SubmissionIndex>,
+}
+
+impl FrameSlot {
+    fn new(device: &wgpu::Device, size: u64, index: usize) -> Self {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("Frame Staging Buffer {}", index)),
+            size,
+            usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        Self {
+            staging,
+            size,
+            mapped: Arc::new(AtomicBool::new(true)),
+            encoder: None,
+            used: 0,
+            submission: None,
+        }
+    }
+}
+
+pub struct FramesInFlight {
+    slots: Vec<FrameSlot>,
+    // the slot written to right now.
+    current: usize,
+    // the most a frame needed, the slots grow to it when they come round.
+    wanted_size: u64,
+    wait_time: Duration,
+}
+
+impl FramesInFlight {
+    // `count` is clamped to 1 - MAX_FRAMES_IN_FLIGHT.
+    pub fn new(device: &wgpu::Device, count: usize) -> Self {
+        let count = count.clamp(1, MAX_FRAMES_IN_FLIGHT);
+        Self {
+            slots: (0..count).map(|index| FrameSlot::new(device, INITIAL_STAGING_SIZE, index)).collect(),
+            current: 0,
+            wanted_size: INITIAL_STAGING_SIZE,
+            wait_time: Duration::ZERO,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.slots.len()
+    }
+
+    // the pool of the current frame, 0 to count() - 1.
+    pub fn slot(&self) -> usize {
+        self.current
+    }
+
+    // how long the last end_frame() waited for the gpu.
+    pub fn wait_time(&self) -> Duration {
+        self.wait_time
+    }
+
+    // `data` goes into `target` at `offset` before the next frame is drawn,
+    // writes to the same buffer in the order they were made. `offset` and
+    // the length of `data` are multiples of 4, like for queue.write_buffer().
+    pub fn write(&mut self, device: &wgpu::Device, target: &wgpu::Buffer, offset: u64, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let size = data.len() as u64;
+        let slot = &mut self.slots[self.current];
+        let encoder = slot.encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Frame Upload Encoder") })
+        });
+
+        if slot.used + size > slot.size {
+            // copied from a buffer of its own, in order with the others.
+            self.wanted_size = self.wanted_size.max((slot.used + size).next_power_of_two());
+            let overflow = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Frame Overflow Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: true,
+            });
+            overflow.slice(..).get_mapped_range_mut().copy_from_slice(data);
+            overflow.unmap();
+            encoder.copy_buffer_to_buffer(&overflow, 0, target, offset, size);
+            return;
+        }
+
+        slot.staging.slice(slot.used..slot.used + size).get_mapped_range_mut().copy_from_slice(data);
+        encoder.copy_buffer_to_buffer(&slot.staging, slot.used, target, offset, size);
+        // mapped ranges start at multiples of MAP_ALIGNMENT.
+        slot.used = (slot.used + size).div_ceil(wgpu::MAP_ALIGNMENT) * wgpu::MAP_ALIGNMENT;
+    }
+
+    // submits the copies of the frame, before the frame's own commands.
+    pub fn submit(&mut self, queue: &wgpu::Queue) -> wgpu::SubmissionIndex {
+        let slot = &mut self.slots[self.current];
+        let encoder = match slot.encoder.take() {
+            Some(encoder) => encoder,
+            // nothing written, the buffer stays mapped.
+            None => return queue.submit(None),
+        };
+        slot.staging.unmap();
+        slot.mapped.store(false, Ordering::Release);
+        let submission = queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = slot.mapped.clone();
+        slot.staging.slice(..).map_async(wgpu::MapMode::Write, move |result| {
+            if result.is_ok() {
+                mapped.store(true, Ordering::Release);
+            }
+        });
+        slot.used = 0;
+        submission
+    }
+
+    // after the frame's last submission: the next frame gets the next
+    // pool, once the gpu finished the frame that used it before.
+    pub fn end_frame(&mut self, device: &wgpu::Device, submission: wgpu::SubmissionIndex) {
+        self.slots[self.current].submission = Some(submission);
+        self.current = (self.current + 1) % self.slots.len();
+
+        let start = Instant::now();
+        let slot = &mut self.slots[self.current];
+        if let Some(submission) = slot.submission.take() {
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+        // a map that failed (the device is lost) or a buffer too small for
+        // the last frames: a new one.
+        if !slot.mapped.load(Ordering::Acquire) || slot.size < self.wanted_size {
+            *slot = FrameSlot::new(device, self.wanted_size, self.current);
+        }
+        self.wait_time = start.elapsed();
+    }
+
+    // submits what was written so far, for drawing outside of a frame.
+    pub fn flush(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.slots[self.current].encoder.is_none() {
+            return;
+        }
+        let submission = self.submit(queue);
+        self.end_frame(device, submission);
+    }
+}
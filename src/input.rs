@@ -0,0 +1,269 @@
+use winit::event::{ElementState, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent};
+
+/*
+    Input actions: keys and mouse buttons, with or without modifiers,
+    bound to actions of the application.
+
+    A binding fires only with exactly its modifiers held, so Ctrl+S
+    and S can be bound to different actions. The release of a key or
+    button releases the actions it pressed, even when the modifiers
+    changed in between (Shift let go before the mouse button at the
+    end of a Shift+drag).
+
+    Several bindings can share an action (F5 and Ctrl+S both save).
+    Keys without a virtual key code (some media keys, some layouts)
+    are ignored.
+
+    usage:
+        let mut input = InputMap::new();
+        input.bind(Binding::key(VirtualKeyCode::S).with_ctrl(), Action::Save);
+        input.bind(Binding::mouse(MouseButton::Left).with_shift(), Action::Pan);
+        ...
+        for event in input.handle(&window_event) {
+            if let ActionEvent::Pressed(Action::Save) = event { ... }
+        }
+        if input.is_active(Action::Pan) { ... }
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub trigger: Trigger,
+    pub modifiers: ModifiersState,
+}
+
+impl Binding {
+    pub fn key(key: VirtualKeyCode) -> Self {
+        Self { trigger: Trigger::Key(key), modifiers: ModifiersState::empty() }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self { trigger: Trigger::Mouse(button), modifiers: ModifiersState::empty() }
+    }
+
+    pub fn with_ctrl(mut self) -> Self {
+        self.modifiers |= ModifiersState::CTRL;
+        self
+    }
+
+    pub fn with_shift(mut self) -> Self {
+        self.modifiers |= ModifiersState::SHIFT;
+        self
+    }
+
+    pub fn with_alt(mut self) -> Self {
+        self.modifiers |= ModifiersState::ALT;
+        self
+    }
+
+    // the command key on macos, the windows key elsewhere.
+    pub fn with_logo(mut self) -> Self {
+        self.modifiers |= ModifiersState::LOGO;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ActionEvent<A> {
+    Pressed(A),
+    Released(A),
+}
+
+pub struct InputMap<A> {
+    bindings: Vec<(Binding, A)>,
+    modifiers: ModifiersState,
+    // actions that are held down, with the trigger that pressed them.
+    active: Vec<(Trigger, A)>,
+}
+
+impl<A: Copy + PartialEq> Default for InputMap<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Copy + PartialEq> InputMap<A> {
+    pub fn new() -> Self {
+        Self {
+            bindings: Vec::new(),
+            modifiers: ModifiersState::empty(),
+            active: Vec::new(),
+        }
+    }
+
+    pub fn bind(&mut self, binding: Binding, action: A) {
+        self.bindings.push((binding, action));
+    }
+
+    // removes every binding of `action`.
+    pub fn unbind(&mut self, action: A) {
+        self.bindings.retain(|(_, bound)| *bound != action);
+        self.active.retain(|(_, active)| *active != action);
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = &(Binding, A)> {
+        self.bindings.iter()
+    }
+
+    // the action bound to `trigger` with the modifiers held right now.
+    pub fn action(&self, trigger: Trigger) -> Option<A> {
+        self.bindings.iter()
+            .find(|(binding, _)| binding.trigger == trigger && binding.modifiers == self.modifiers)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    // held down at the moment.
+    pub fn is_active(&self, action: A) -> bool {
+        self.active.iter().any(|(_, active)| *active == action)
+    }
+
+    // the actions the event pressed or released. Key repeats press again.
+    pub fn handle(&mut self, event: &WindowEvent) -> Vec<ActionEvent<A>> {
+        let (trigger, state) = match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                return Vec::new();
+            }
+            WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
+                Some(key) => (Trigger::Key(key), input.state),
+                None => return Vec::new(),
+            },
+            WindowEvent::MouseInput { button, state, .. } => (Trigger::Mouse(*button), *state),
+            // a window that loses the focus doesn't get the releases.
+            WindowEvent::Focused(false) => {
+                return self.active.drain(..).map(|(_, action)| ActionEvent::Released(action)).collect();
+            }
+            _ => return Vec::new(),
+        };
+
+        match state {
+            ElementState::Pressed => match self.action(trigger) {
+                Some(action) => {
+                    if !self.active.contains(&(trigger, action)) {
+                        self.active.push((trigger, action));
+                    }
+                    vec![ActionEvent::Pressed(action)]
+                }
+                None => Vec::new(),
+            },
+            ElementState::Released => {
+                let mut released = Vec::new();
+                self.active.retain(|(active_trigger, action)| {
+                    if *active_trigger == trigger {
+                        released.push(ActionEvent::Released(*action));
+                        false
+                    } else {
+                        true
+                    }
+                });
+                released
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::InputEvent;
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum Action {
+        Save,
+        Step,
+        Pan,
+    }
+
+    fn map() -> InputMap<Action> {
+        let mut input = InputMap::new();
+        input.bind(Binding::key(VirtualKeyCode::S).with_ctrl(), Action::Save);
+        input.bind(Binding::key(VirtualKeyCode::F5), Action::Save);
+        input.bind(Binding::key(VirtualKeyCode::S), Action::Step);
+        input.bind(Binding::mouse(MouseButton::Left).with_shift(), Action::Pan);
+        input
+    }
+
+    // the events as winit sends them, see replay.rs.
+    fn handle(input: &mut InputMap<Action>, event: InputEvent) -> Vec<ActionEvent<Action>> {
+        input.handle(&event.to_window_event().unwrap())
+    }
+
+    fn key(key: VirtualKeyCode, state: ElementState) -> InputEvent {
+        InputEvent::Key { scancode: 0, key: Some(key), state }
+    }
+
+    fn mouse(button: MouseButton, state: ElementState) -> InputEvent {
+        InputEvent::MouseInput { button, state }
+    }
+
+    #[test]
+    fn bindings_need_exactly_their_modifiers() {
+        let mut input = map();
+        assert_eq!(input.action(Trigger::Key(VirtualKeyCode::S)), Some(Action::Step));
+        handle(&mut input, InputEvent::Modifiers(ModifiersState::CTRL));
+        assert_eq!(input.action(Trigger::Key(VirtualKeyCode::S)), Some(Action::Save));
+        handle(&mut input, InputEvent::Modifiers(ModifiersState::CTRL | ModifiersState::SHIFT));
+        assert_eq!(input.action(Trigger::Key(VirtualKeyCode::S)), None);
+        assert!(handle(&mut input, key(VirtualKeyCode::S, ElementState::Pressed)).is_empty());
+        assert_eq!(input.action(Trigger::Mouse(MouseButton::Left)), None);
+    }
+
+    #[test]
+    fn releases_follow_the_trigger_not_the_modifiers() {
+        let mut input = map();
+        handle(&mut input, InputEvent::Modifiers(ModifiersState::SHIFT));
+        assert_eq!(handle(&mut input, mouse(MouseButton::Left, ElementState::Pressed)), vec![ActionEvent::Pressed(Action::Pan)]);
+        assert!(input.is_active(Action::Pan));
+
+        // shift let go before the button.
+        handle(&mut input, InputEvent::Modifiers(ModifiersState::empty()));
+        assert!(handle(&mut input, key(VirtualKeyCode::A, ElementState::Released)).is_empty());
+        assert_eq!(handle(&mut input, mouse(MouseButton::Left, ElementState::Released)), vec![ActionEvent::Released(Action::Pan)]);
+        assert!(!input.is_active(Action::Pan));
+    }
+
+    #[test]
+    fn repeats_press_again_but_stay_one_active_action() {
+        let mut input = map();
+        for _ in 0..3 {
+            assert_eq!(handle(&mut input, key(VirtualKeyCode::F5, ElementState::Pressed)), vec![ActionEvent::Pressed(Action::Save)]);
+        }
+        assert_eq!(handle(&mut input, key(VirtualKeyCode::F5, ElementState::Released)), vec![ActionEvent::Released(Action::Save)]);
+        assert!(!input.is_active(Action::Save));
+
+        // keys without a virtual key code are ignored.
+        let unknown = InputEvent::Key { scancode: 1234, key: None, state: ElementState::Pressed };
+        assert!(handle(&mut input, unknown).is_empty());
+    }
+
+    #[test]
+    fn losing_the_focus_releases_everything() {
+        let mut input = map();
+        handle(&mut input, key(VirtualKeyCode::S, ElementState::Pressed));
+        handle(&mut input, key(VirtualKeyCode::F5, ElementState::Pressed));
+        let mut released = handle(&mut input, InputEvent::Focused(false));
+        released.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(released, vec![ActionEvent::Released(Action::Save), ActionEvent::Released(Action::Step)]);
+        assert!(!input.is_active(Action::Step));
+        assert!(handle(&mut input, key(VirtualKeyCode::S, ElementState::Released)).is_empty());
+    }
+
+    #[test]
+    fn unbind_drops_bindings_and_held_actions() {
+        let mut input = map();
+        handle(&mut input, key(VirtualKeyCode::F5, ElementState::Pressed));
+        input.unbind(Action::Save);
+        assert!(!input.is_active(Action::Save));
+        assert_eq!(input.bindings().count(), 2);
+        assert!(handle(&mut input, key(VirtualKeyCode::F5, ElementState::Pressed)).is_empty());
+    }
+}
@@ -0,0 +1,492 @@
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{self, AtomicBool, AtomicU32, AtomicU64};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::overlay::{self, Overlay};
+
+/*
+    Jobs: work off the render thread, on a small pool of worker threads.
+
+    A job is a closure run on a worker, the finish closure gets its result
+    back on the render thread, where the renderer can be touched again:
+    the worker decodes, the render thread uploads. Waiting jobs are run by
+    priority, the first spawned first within one. Nothing ever blocks on
+    a job, the finished ones are handed out by Jobs::poll(), the renderer
+    does that once a frame in poll_completed_jobs() (the main loop may
+    call it more often, e.g. while the window is hidden and no frames
+    are drawn).
+
+    The renderer runs the background model loads (loader.rs) on the pool,
+    puts captures together and saves posters and screenshots on it once
+    they are read back (the readback itself is polled, see
+    readback::Readbacks) and takes
+    any other work with Renderer::spawn_job().
+
+    A job that panics is dropped with its finish, the worker goes on with
+    the next one. Once the last JobPool handle is dropped the workers run
+    what's still queued and stop, unless the process ends first.
+
+    Long jobs (Renderer::spawn_cancellable_job(), the model loads) get a
+    Progress: they tell how far they are with set(), which the viewer
+    shows on the overlay (draw_progress()), and look at check() between
+    their steps, which fails with Cancelled once the job was cancelled
+    (Renderer::cancel_job(), or the Pause key in the viewer). A cancelled
+    job that hasn't started yet never runs, one that has stops at its
+    next check(); either way its finish isn't called. A job can't be
+    stopped in the middle of a step, e.g. while an obj file is parsed.
+
+    usage:
+        renderer.spawn_job(JobPriority::Low, "poster", move || poster.save(&path), |_, result| {
+            if let Err(e) = result {
+                log::error!("Unable to save the poster: {:?}", e);
+            }
+        });
+        let id = renderer.spawn_cancellable_job(JobPriority::Normal, "scatter", move |progress| {
+            for (index, tile) in tiles.iter().enumerate() {
+                progress.check()?;
+                scatter(tile);
+                progress.set((index + 1) as f32 / tiles.len() as f32);
+            }
+            Ok(())
+        }, |_, result: Result<()>| { ... });
+        renderer.cancel_job(id);
+        // once a frame, or more often
+        renderer.poll_completed_jobs();
+*/
+
+pub const OVERLAY_LAYER: &str = "jobs";
+
+// workers when the number of cpus isn't known.
+const DEFAULT_WORKERS: usize = 4;
+
+// the progress bars, in ui pixels.
+pub const PROGRESS_WIDTH: f32 = 200.0;
+const BAR_HEIGHT: f32 = 4.0;
+const TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const BAR_BACKGROUND: [f32; 4] = [0.2, 0.2, 0.2, 0.8];
+
+// Low waits while there is anything else: saving files, caches.
+// High for what the user is looking at right now.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum JobPriority {
+    Low,
+    Normal,
+    High,
+}
+
+// of a job of Jobs or a model load, unique among both.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct JobId(u64);
+
+impl JobId {
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+}
+
+// The error of Progress::check() once the job is cancelled, to tell it
+// from real failures with error.is::<Cancelled>().
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The job was cancelled.")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+struct ProgressState {
+    // the bits of an f32, 0-1.
+    fraction: AtomicU32,
+    cancelled: AtomicBool,
+}
+
+// How far a job is and whether it should stop, shared by the job and
+// whoever watches it. Clones share the state.
+#[derive(Clone)]
+pub struct Progress {
+    state: Arc<ProgressState>,
+    // the part of the whole job set() fills, see stage().
+    start: f32,
+    end: f32,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(ProgressState { fraction: AtomicU32::new(0), cancelled: AtomicBool::new(false) }),
+            start: 0.0,
+            end: 1.0,
+        }
+    }
+
+    // `fraction` 0-1 of this stage done, never less than before.
+    pub fn set(&self, fraction: f32) {
+        let fraction = self.start + fraction.clamp(0.0, 1.0) * (self.end - self.start);
+        let _ = self.state.fraction.fetch_update(atomic::Ordering::Relaxed, atomic::Ordering::Relaxed, |bits| {
+            (fraction > f32::from_bits(bits)).then(|| fraction.to_bits())
+        });
+    }
+
+    // of the whole job, 0-1.
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.state.fraction.load(atomic::Ordering::Relaxed))
+    }
+
+    // the same job, set() going from `start` to `end` of it, for a step
+    // that doesn't know about the others, e.g. the textures of a load.
+    pub fn stage(&self, start: f32, end: f32) -> Self {
+        let span = self.end - self.start;
+        Self { state: self.state.clone(), start: self.start + start * span, end: self.start + end * span }
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(atomic::Ordering::Relaxed)
+    }
+
+    // Cancelled once the job is cancelled, for `?` between its steps.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+// A running job, for the overlay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobProgress {
+    pub id: JobId,
+    pub name: String,
+    // 0-1.
+    pub fraction: f32,
+}
+
+type Work = Box<dyn FnOnce() + Send>;
+type Output = Box<dyn Any + Send>;
+type Finish<C> = Box<dyn FnOnce(&mut C, Output)>;
+// a finish with its result.
+pub type Finished<C> = Box<dyn FnOnce(&mut C)>;
+
+struct QueuedJob {
+    priority: JobPriority,
+    // order of spawning, the first one first.
+    sequence: u64,
+    work: Work,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// the heap pops the largest: the highest priority, then the lowest sequence.
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<QueuedJob>,
+    sequence: u64,
+    // set when the last handle is dropped, the workers leave once
+    // the queue is empty.
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+// The worker threads, cheap to clone: all clones share the same workers.
+#[derive(Clone)]
+pub struct JobPool {
+    shared: Arc<Shared>,
+    // dropped with the last clone, which closes the queue.
+    _owner: Arc<PoolOwner>,
+    workers: usize,
+}
+
+struct PoolOwner(Arc<Shared>);
+
+impl Drop for PoolOwner {
+    fn drop(&mut self) {
+        self.0.queue.lock().unwrap().closed = true;
+        self.0.ready.notify_all();
+    }
+}
+
+impl Default for JobPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobPool {
+    // a worker for every cpu but the render thread's.
+    pub fn new() -> Self {
+        let cpus = thread::available_parallelism().map_or(DEFAULT_WORKERS, |cpus| cpus.get());
+        Self::with_workers(cpus.saturating_sub(1).max(1))
+    }
+
+    pub fn with_workers(workers: usize) -> Self {
+        let shared = Arc::new(Shared { queue: Mutex::new(Queue::default()), ready: Condvar::new() });
+        for index in 0..workers {
+            let shared = shared.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("job worker {}", index))
+                .spawn(move || work(&shared));
+            if let Err(e) = spawned {
+                log::error!("Unable to start a job worker: {:?}", e);
+            }
+        }
+        Self { _owner: Arc::new(PoolOwner(shared.clone())), shared, workers }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    // runs `work` on a worker, nobody hears when it's done. See Jobs for
+    // a result on the render thread.
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, priority: JobPriority, work: F) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.sequence += 1;
+        let sequence = queue.sequence;
+        queue.jobs.push(QueuedJob { priority, sequence, work: Box::new(work) });
+        drop(queue);
+        self.shared.ready.notify_one();
+    }
+
+    // waiting for a worker, the running ones not counted.
+    pub fn queued(&self) -> usize {
+        self.shared.queue.lock().unwrap().jobs.len()
+    }
+}
+
+// a worker: the next job, until the pool is gone and the queue empty.
+fn work(shared: &Shared) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if let Some(job) = queue.jobs.pop() {
+                    break job;
+                }
+                if queue.closed {
+                    return;
+                }
+                queue = shared.ready.wait(queue).unwrap();
+            }
+        };
+        // the panic message is printed already, the worker goes on.
+        if panic::catch_unwind(AssertUnwindSafe(job.work)).is_err() {
+            log::error!("A job panicked.");
+        }
+    }
+}
+
+// what a worker sends back for a job.
+enum Outcome {
+    Done(Output),
+    Panicked,
+    Cancelled,
+}
+
+// a job that hasn't been polled yet.
+struct PendingJob<C> {
+    name: &'static str,
+    finish: Finish<C>,
+    // None for jobs spawned without one, they can't be cancelled.
+    progress: Option<Progress>,
+}
+
+// Jobs with a result for the render thread, handed to their finish with
+// a `C`, e.g. the renderer.
+pub struct Jobs<C> {
+    pool: JobPool,
+    sender: mpsc::Sender<(JobId, Outcome)>,
+    receiver: mpsc::Receiver<(JobId, Outcome)>,
+    pending: HashMap<JobId, PendingJob<C>>,
+}
+
+impl<C> Jobs<C> {
+    pub fn new(pool: JobPool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { pool, sender, receiver, pending: HashMap::new() }
+    }
+
+    pub fn pool(&self) -> &JobPool {
+        &self.pool
+    }
+
+    // runs `work` on a worker and later `finish` with its result, out of
+    // poll(). `name` is for the log when the job panics.
+    pub fn spawn<T, W, F>(&mut self, priority: JobPriority, name: &'static str, work: W, finish: F) -> JobId
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+        F: FnOnce(&mut C, T) + 'static,
+    {
+        self.spawn_inner(priority, name, None, move |_: &Progress| work(), finish)
+    }
+
+    // spawn() for a job that reports its progress and can be cancelled,
+    // see Progress. `name` is shown with the progress.
+    pub fn spawn_cancellable<T, W, F>(&mut self, priority: JobPriority, name: &'static str, work: W, finish: F) -> JobId
+    where
+        T: Send + 'static,
+        W: FnOnce(&Progress) -> T + Send + 'static,
+        F: FnOnce(&mut C, T) + 'static,
+    {
+        self.spawn_inner(priority, name, Some(Progress::new()), work, finish)
+    }
+
+    fn spawn_inner<T, W, F>(&mut self, priority: JobPriority, name: &'static str, progress: Option<Progress>, work: W, finish: F) -> JobId
+    where
+        T: Send + 'static,
+        W: FnOnce(&Progress) -> T + Send + 'static,
+        F: FnOnce(&mut C, T) + 'static,
+    {
+        let id = JobId::next();
+        let finish: Finish<C> = Box::new(move |context: &mut C, output: Output| {
+            if let Ok(output) = output.downcast::<T>() {
+                finish(context, *output);
+            }
+        });
+        let worker_progress = progress.clone().unwrap_or_default();
+        self.pending.insert(id, PendingJob { name, finish, progress });
+        let sender = self.sender.clone();
+        self.pool.spawn(priority, move || {
+            // cancelled while it was waiting.
+            if worker_progress.is_cancelled() {
+                let _ = sender.send((id, Outcome::Cancelled));
+                return;
+            }
+            let outcome = match panic::catch_unwind(AssertUnwindSafe(|| work(&worker_progress))) {
+                // whatever a cancelled job returned, it's not wanted any more.
+                Ok(_) if worker_progress.is_cancelled() => Outcome::Cancelled,
+                Ok(output) => Outcome::Done(Box::new(output)),
+                Err(_) => Outcome::Panicked,
+            };
+            let _ = sender.send((id, outcome));
+        });
+        id
+    }
+
+    // spawned and not polled yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // the cancellable jobs that aren't polled yet, in no particular order.
+    pub fn progress(&self) -> Vec<JobProgress> {
+        self.pending.iter()
+            .filter_map(|(id, job)| {
+                let progress = job.progress.as_ref()?;
+                Some(JobProgress { id: *id, name: job.name.to_string(), fraction: progress.fraction() })
+            })
+            .collect()
+    }
+
+    // false if there is no such cancellable job, or it's done already.
+    pub fn cancel(&mut self, id: JobId) -> bool {
+        match self.pending.get(&id).and_then(|job| job.progress.as_ref()) {
+            Some(progress) => {
+                progress.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        for progress in self.pending.values().filter_map(|job| job.progress.as_ref()) {
+            progress.cancel();
+        }
+    }
+
+    // the finishes of the jobs done since the last call, with their
+    // results, to be called with the `C`. Doesn't wait.
+    pub fn poll(&mut self) -> Vec<Finished<C>>
+    where
+        C: 'static,
+    {
+        let mut done: Vec<Finished<C>> = Vec::new();
+        while let Ok((id, outcome)) = self.receiver.try_recv() {
+            let job = match self.pending.remove(&id) {
+                Some(job) => job,
+                None => continue,
+            };
+            match outcome {
+                Outcome::Done(output) => {
+                    let finish = job.finish;
+                    done.push(Box::new(move |context: &mut C| finish(context, output)));
+                }
+                Outcome::Panicked => log::error!("The job {:?} panicked.", job.name),
+                Outcome::Cancelled => log::info!("The job {:?} was cancelled.", job.name),
+            }
+        }
+        done
+    }
+}
+
+// the running jobs at x/y ui pixels, a line and a bar for every one,
+// e.g. at the top right corner, PROGRESS_WIDTH wide. Nothing without jobs.
+pub fn draw_progress(jobs: &[JobProgress], overlay: &mut Overlay, x: f32, y: f32) {
+    let strings = overlay.strings();
+    let mut lines: Vec<String> = jobs.iter()
+        .map(|job| strings.format("jobs.progress", &[
+            ("name", &job.name),
+            ("percent", &format!("{:.0}", job.fraction * 100.0)),
+        ]))
+        .collect();
+    if !jobs.is_empty() {
+        lines.push(strings.get("jobs.cancel").to_string());
+    }
+    let color = overlay.palette().accent;
+    let layer = overlay.layer(OVERLAY_LAYER);
+    layer.clear();
+
+    let row = overlay::GLYPH_HEIGHT + BAR_HEIGHT + 2.0;
+    for (index, job) in jobs.iter().enumerate() {
+        let top = y + index as f32 * row;
+        layer.text(x, top, &lines[index], TEXT_COLOR);
+        let bar = top + overlay::GLYPH_HEIGHT;
+        layer.rect(x, bar, [PROGRESS_WIDTH, BAR_HEIGHT], BAR_BACKGROUND);
+        layer.rect(x, bar, [(job.fraction.clamp(0.0, 1.0) * PROGRESS_WIDTH).max(1.0), BAR_HEIGHT], color);
+    }
+    if let Some(hint) = lines.get(jobs.len()) {
+        layer.text(x, y + jobs.len() as f32 * row, hint, TEXT_COLOR);
+    }
+}
@@ -0,0 +1,97 @@
+/*
+    zhneeshyx: a small wgpu renderer.
+
+    The renderer module is the entry point for applications:
+
+        let event_loop = EventLoop::new();
+        let mut renderer = RendererBuilder::new()
+            .title("my viewer")
+            .size(1280, 720)
+            .build(&event_loop)?;
+        renderer.load_model("terrain01.obj")?;
+
+    and then call renderer.render_frame() on every RedrawRequested.
+    Custom passes are added with renderer.add_plugin(), see plugin.rs,
+    renderer events are subscribed with renderer.on_event(), see events.rs.
+*/
+
+pub mod agents;
+pub mod annotations;
+pub mod atlas;
+pub mod baked;
+pub mod benchmark;
+pub mod budget;
+pub mod camera;
+pub mod cameras;
+pub mod capture;
+pub mod clipping;
+pub mod cubemap;
+pub mod events;
+pub mod explode;
+pub mod fallback;
+pub mod fbx;
+pub mod fetch;
+pub mod flare;
+pub mod follow;
+pub mod frame;
+pub mod inflight;
+pub mod input;
+pub mod jobs;
+pub mod light;
+pub mod lightmarker;
+pub mod logic;
+pub mod loader;
+pub mod locale;
+pub mod lod;
+pub mod math;
+pub mod matlib;
+pub mod measure;
+pub mod model;
+pub mod monitors;
+pub mod navmesh;
+pub mod overlay;
+pub mod overrides;
+pub mod pacing;
+pub mod palette;
+pub mod particles;
+pub mod picking;
+pub mod plugin;
+pub mod pointcloud;
+pub mod prefab;
+pub mod preview;
+pub mod profiler;
+pub mod ply;
+pub mod readback;
+pub mod remote;
+pub mod renderer;
+pub mod replay;
+pub mod report;
+pub mod resolution;
+pub mod road;
+pub mod scene;
+pub mod script;
+pub mod shading;
+pub mod shadow;
+pub mod snapping;
+pub mod splat;
+pub mod stats;
+pub mod stereo;
+pub mod stl;
+pub mod subdivision;
+pub mod sync;
+pub mod targets;
+pub mod texture;
+pub mod textinput;
+pub mod thumbnail;
+pub mod tiles;
+pub mod turntable;
+pub mod undo;
+pub mod vat;
+pub mod vertex;
+pub mod weather;
+pub mod wind;
+pub mod windowstate;
+
+pub use events::RendererEvent;
+pub use plugin::RenderPlugin;
+pub use renderer::{Renderer, RendererBuilder};
@@ -1,5 +1,72 @@
 use cgmath::*;
-use winit::event::*;
+
+// A point light as the application sees it.
+// -> gets converted into the UniformBuffer layout before upload.
+#[derive(Debug, Copy, Clone)]
+pub struct Light {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    // screen space effects, drawn by flare::LensFlarePass.
+    pub flare: Option<LensFlare>,
+    pub shafts: Option<LightShafts>,
+}
+
+impl Light {
+    pub fn new(position: Point3<f32>, color: [f32; 3]) -> Self {
+        Self { position, color, flare: None, shafts: None }
+    }
+
+    pub fn with_flare(mut self, flare: LensFlare) -> Self {
+        self.flare = Some(flare);
+        self
+    }
+
+    pub fn with_shafts(mut self, shafts: LightShafts) -> Self {
+        self.shafts = Some(shafts);
+        self
+    }
+}
+
+// glow around the light plus ghosts mirrored through the screen center.
+#[derive(Debug, Copy, Clone)]
+pub struct LensFlare {
+    pub intensity: f32,
+    // 1 = the glow is a third of the screen high.
+    pub size: f32,
+}
+
+impl Default for LensFlare {
+    fn default() -> Self {
+        Self { intensity: 1.0, size: 1.0 }
+    }
+}
+
+// god rays: the light's disc blurred outwards from the light.
+#[derive(Debug, Copy, Clone)]
+pub struct LightShafts {
+    // of the disc, as a fraction of the screen height.
+    pub radius: f32,
+    // how far the rays reach, 1 = all the way from the light to the pixel.
+    pub density: f32,
+    // falloff of every further sample along a ray.
+    pub decay: f32,
+    pub weight: f32,
+    pub exposure: f32,
+    pub samples: u32,
+}
+
+impl Default for LightShafts {
+    fn default() -> Self {
+        Self {
+            radius: 0.1,
+            density: 0.9,
+            decay: 0.96,
+            weight: 0.4,
+            exposure: 0.3,
+            samples: 48,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -22,4 +89,266 @@ impl UniformBuffer {
             _padding_color: 0,
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<&Light> for UniformBuffer {
+    fn from(light: &Light) -> Self {
+        Self {
+            position: light.position.into(),
+            _padding_position: 0,
+            color: light.color,
+            _padding_color: 0,
+        }
+    }
+}
+
+// lights the scene pass shades with, the others only get flares.
+pub const MAX_LIGHTS: usize = 8;
+
+// How the point lights and the sun shade the scene (Blinn-Phong).
+// Without any the scene stays unlit, as bright as its textures.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lighting {
+    // light every surface gets, also the ones facing away.
+    pub ambient: f32,
+    // strength and sharpness of the highlights.
+    pub specular: f32,
+    pub shininess: f32,
+    // a small cube at every light, see lightmarker.rs.
+    pub markers: bool,
+    // of the sun, see Renderer::set_sun().
+    pub sun_color: [f32; 3],
+    // whether the sun casts shadows, see shadow.rs.
+    pub shadows: bool,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: 0.25,
+            specular: 0.5,
+            shininess: 32.0,
+            markers: true,
+            sun_color: [1.0, 0.95, 0.85],
+            shadows: true,
+        }
+    }
+}
+
+// The point lights in the layout of the shaders, binding 4 of the spot
+// light bind group.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    lights: [UniformBuffer; MAX_LIGHTS],
+    count: u32,
+    ambient: f32,
+    specular: f32,
+    shininess: f32,
+}
+
+impl LightsUniform {
+    // the first MAX_LIGHTS of `lights`.
+    pub fn new(lights: &[Light], lighting: &Lighting) -> Self {
+        let mut uniform = Self {
+            lights: [UniformBuffer::new(); MAX_LIGHTS],
+            count: lights.len().min(MAX_LIGHTS) as u32,
+            ambient: lighting.ambient,
+            specular: lighting.specular,
+            shininess: lighting.shininess.max(1.0),
+        };
+        for (slot, light) in uniform.lights.iter_mut().zip(lights) {
+            *slot = UniformBuffer::from(light);
+        }
+        uniform
+    }
+}
+
+// Cone light, with a texture ("cookie") projected through it.
+// The renderer has one attached to the camera as a flashlight.
+#[derive(Debug, Copy, Clone)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    // half angle of the cone in degrees.
+    pub angle: f32,
+    // no light reaches further than this.
+    pub range: f32,
+    pub enabled: bool,
+}
+
+impl SpotLight {
+    pub fn new(position: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            position,
+            direction,
+            color: [1.0, 0.95, 0.85],
+            intensity: 1.5,
+            angle: 25.0,
+            range: 30.0,
+            enabled: true,
+        }
+    }
+
+    // world -> the cookie, like a camera looking down the cone.
+    fn view_proj(&self) -> Matrix4<f32> {
+        let direction = if self.direction.magnitude2() > 0.0 {
+            self.direction.normalize()
+        } else {
+            -Vector3::unit_z()
+        };
+        // any up works, as long as it isn't parallel to the direction.
+        let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let view = Matrix4::look_to_rh(self.position, direction, up);
+        let proj = crate::math::perspective((self.angle * 2.0).clamp(1.0, 170.0), 1.0, 0.05, self.range.max(0.1));
+        proj * view
+    }
+
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // cookie
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // half rate light buffer, see shading.rs.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                // the point lights, a LightsUniform. The markers are placed by it.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // the sun's shadow map, see shadow.rs.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                // the sun, a shadow::ShadowUniform.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // the highlights of the half rate light buffer.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("spot_light_bind_group_layout"),
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpotUniform {
+    view_proj: [[f32; 4]; 4],
+    // w: range
+    position: [f32; 4],
+    // rgb: color * intensity, w: 1 if the light is on.
+    color: [f32; 4],
+    // x: 1 if the light comes from the half rate light buffer.
+    shading: [u32; 4],
+}
+
+impl SpotUniform {
+    // the scene pass looks the light up instead of shading it.
+    pub fn with_light_buffer(mut self, light_buffer: bool) -> Self {
+        self.shading[0] = light_buffer as u32;
+        self
+    }
+}
+
+impl From<&SpotLight> for SpotUniform {
+    fn from(light: &SpotLight) -> Self {
+        let c = light.color;
+        let i = light.intensity;
+        Self {
+            view_proj: light.view_proj().into(),
+            position: [light.position.x, light.position.y, light.position.z, light.range],
+            color: [c[0] * i, c[1] * i, c[2] * i, if light.enabled { 1.0 } else { 0.0 }],
+            shading: [0; 4],
+        }
+    }
+}
+
+// flashlight-ish cookie: bright hot spot, a faint ring and a soft edge.
+pub fn default_cookie(size: u32) -> image::RgbaImage {
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        let r = (u * u + v * v).sqrt();
+
+        let spot = (1.0 - r / 0.45).max(0.0);
+        let ring = (1.0 - ((r - 0.7) / 0.08).abs()).max(0.0) * 0.35;
+        let edge = (1.0 - r).clamp(0.0, 0.2) / 0.2;
+        let value = ((0.45 + spot * 0.55 + ring) * edge).min(1.0);
+
+        let byte = (value * 255.0) as u8;
+        image::Rgba([byte, byte, byte, 255])
+    })
+}
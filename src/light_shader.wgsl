@@ -0,0 +1,51 @@
+// Light markers: a small cube at every point light, in its color, so
+// the lights can be seen in the scene. See lightmarker.rs.
+
+//
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) //
+var<uniform> camera: CameraUniform;
+
+// see light::LightsUniform.
+struct PointLight {
+    position: vec3<f32>,
+    color: vec3<f32>,
+}
+
+struct LightsUniform {
+    lights: array<PointLight, 8>,
+    count: u32,
+    ambient: f32,
+    specular: f32,
+    shininess: f32,
+}
+
+@group(1) @binding(4)
+var<uniform> point_lights: LightsUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+// one instance per light, the cube is already marker sized.
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @builtin(instance_index) instance: u32,
+) -> VertexOutput {
+    let light = point_lights.lights[instance];
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position + light.position, 1.0);
+    out.color = light.color;
+    return out;
+}
+
+// unlit, the light doesn't shade itself.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
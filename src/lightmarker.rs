@@ -0,0 +1,142 @@
+use wgpu::util::DeviceExt;
+
+/*
+    Light markers: a small cube in the color of every point light that
+    shades the scene (the first light::MAX_LIGHTS), so it's clear where
+    the light comes from.
+
+    They are drawn in the scene pass after the models, depth tested
+    against them, with one instanced draw: the cube is the same for all
+    lights, the vertex shader moves it to the light of its instance out
+    of the LightsUniform the scene shader reads too. They are unlit and
+    not clipped, not picked and not part of the saved scene.
+
+    usage:
+        renderer.add_light(Light::new((2.0, 3.0, 1.0).into(), [1.0, 0.9, 0.8]));
+        renderer.set_lighting(Lighting { markers: false, ..Lighting::default() });
+*/
+
+// edge length of the cube, in world units.
+const MARKER_SIZE: f32 = 0.2;
+
+pub struct LightMarkers {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+}
+
+impl LightMarkers {
+    // `lights_layout` is the spot light bind group layout, which has the
+    // point lights at binding 4. The pass is the scene pass, `format`,
+    // `sample_count` and the depth have to match it.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        depth_format: wgpu::TextureFormat,
+    ) -> Self {
+        let vertices = cube(MARKER_SIZE);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Marker Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Light Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("light_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Marker Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, lights_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Light Marker Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self { pipeline, vertex_buffer, num_vertices: vertices.len() as u32 }
+    }
+
+    // a cube at each of the first `count` lights, with the camera of
+    // `camera_bind_group`. Leaves the pass with the marker pipeline.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        lights_bind_group: &'a wgpu::BindGroup,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, lights_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..count);
+    }
+}
+
+// the triangles of a cube around the origin, counter clockwise from outside.
+fn cube(size: f32) -> Vec<[f32; 3]> {
+    let h = size * 0.5;
+    let corner = |i: usize| [
+        if i & 1 == 0 { -h } else { h },
+        if i & 2 == 0 { -h } else { h },
+        if i & 4 == 0 { -h } else { h },
+    ];
+    // the corners of each face, counter clockwise seen from outside.
+    const FACES: [[usize; 4]; 6] = [
+        [1, 3, 7, 5], // +x
+        [0, 4, 6, 2], // -x
+        [2, 6, 7, 3], // +y
+        [0, 1, 5, 4], // -y
+        [4, 5, 7, 6], // +z
+        [0, 2, 3, 1], // -z
+    ];
+    FACES.iter()
+        .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+        .map(corner)
+        .collect()
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+use crate::jobs::{Cancelled, JobId, JobPool, JobPriority, JobProgress, Progress};
+use crate::model::{Bounds, DecodedMaterial, ModelData};
+
+/*
+    Loads models in the background, on the workers of a JobPool (see
+    jobs.rs).
+
+    Every load reports back in two steps:
+    - Parsed, as soon as the geometry is known, so the renderer can
+      show a placeholder box of the right size,
+    - Finished, once the textures are decoded as well.
+
+    Only the gpu upload is left for the render thread, and the renderer
+    does at most one of those per frame to avoid hitches.
+
+    A load counts as a job of its own for the progress on the overlay:
+    reading the file is the first half, the textures the second. Cancelled
+    before the textures are done it reports Cancelled instead of Finished.
+*/
+
+// of the whole load, the file is read (and cached, see baked.rs) when
+// it's at this, the textures fill the rest.
+const PARSED_PROGRESS: f32 = 0.5;
+
+pub enum LoadMessage {
+    Parsed { slot: usize, bounds: Bounds },
+    Finished { slot: usize, path: PathBuf, data: ModelData, textures: Vec<DecodedMaterial> },
+    Failed { slot: usize, path: PathBuf, error: anyhow::Error },
+    Cancelled { slot: usize, path: PathBuf },
+}
+
+pub struct ModelLoader {
+    pool: JobPool,
+    sender: mpsc::Sender<LoadMessage>,
+    receiver: mpsc::Receiver<LoadMessage>,
+    // finished loads waiting for their upload
+    finished: VecDeque<LoadMessage>,
+    // the loads not handed out by poll() yet, with the slot they load.
+    in_flight: Vec<(JobId, usize, String, Progress)>,
+}
+
+impl ModelLoader {
+    pub fn new(pool: JobPool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            pool,
+            sender,
+            receiver,
+            finished: VecDeque::new(),
+            in_flight: Vec::new(),
+        }
+    }
+
+    // Starts loading `path` for the model slot `slot`.
+    pub fn load(&mut self, slot: usize, path: PathBuf) -> JobId {
+        let sender = self.sender.clone();
+        let id = JobId::next();
+        let progress = Progress::new();
+        let name = path.file_name().map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy()).into_owned();
+        self.in_flight.push((id, slot, name, progress.clone()));
+
+        self.pool.spawn(JobPriority::Normal, move || {
+            // cancelled while it was waiting.
+            let data = match progress.check().and_then(|()| crate::baked::load_cached(&path)) {
+                Ok(data) => data,
+                Err(error) => {
+                    let message = if error.is::<Cancelled>() {
+                        LoadMessage::Cancelled { slot, path }
+                    } else {
+                        LoadMessage::Failed { slot, path, error }
+                    };
+                    let _ = sender.send(message);
+                    return;
+                }
+            };
+            // or while the file was read.
+            if progress.is_cancelled() {
+                let _ = sender.send(LoadMessage::Cancelled { slot, path });
+                return;
+            }
+            progress.set(PARSED_PROGRESS);
+            let _ = sender.send(LoadMessage::Parsed { slot, bounds: data.bounds });
+
+            let message = match data.decode_textures_with_progress(&progress.stage(PARSED_PROGRESS, 1.0)) {
+                Ok(textures) => LoadMessage::Finished { slot, path, data, textures },
+                Err(error) if error.is::<Cancelled>() => LoadMessage::Cancelled { slot, path },
+                Err(error) => LoadMessage::Failed { slot, path, error },
+            };
+            let _ = sender.send(message);
+        });
+        id
+    }
+
+    // number of loads that have not been handed out by poll() yet.
+    pub fn pending(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    // the loads that have not been handed out by poll() yet, named
+    // after their file.
+    pub fn progress(&self) -> Vec<JobProgress> {
+        self.in_flight.iter()
+            .map(|(id, _, name, progress)| JobProgress { id: *id, name: name.clone(), fraction: progress.fraction() })
+            .collect()
+    }
+
+    // false if there is no such load. A load that's finished already
+    // and waits for its upload is still uploaded.
+    pub fn cancel(&mut self, id: JobId) -> bool {
+        match self.in_flight.iter().find(|(load, ..)| *load == id) {
+            Some((.., progress)) => {
+                progress.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        for (.., progress) in &self.in_flight {
+            progress.cancel();
+        }
+    }
+
+    // Collects what the loads sent since the last call:
+    // all Parsed messages, plus at most one Finished/Failed message.
+    pub fn poll(&mut self) -> Vec<LoadMessage> {
+        let mut messages = Vec::new();
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                LoadMessage::Parsed { .. } => messages.push(message),
+                _ => self.finished.push_back(message),
+            }
+        }
+
+        if let Some(message) = self.finished.pop_front() {
+            let slot = match &message {
+                LoadMessage::Parsed { slot, .. }
+                | LoadMessage::Finished { slot, .. }
+                | LoadMessage::Failed { slot, .. }
+                | LoadMessage::Cancelled { slot, .. } => *slot,
+            };
+            self.in_flight.retain(|(_, load, ..)| *load != slot);
+            messages.push(message);
+        }
+        messages
+    }
+}
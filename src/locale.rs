@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/*
+    The text the overlay shows the user, by key, in the user's language.
+
+    A locale file has one string per line, the key, an equals sign and
+    the text. Values in braces are filled in by the code, \n starts a
+    new line:
+
+        # german
+        stats.draw_calls = Draw-Calls:  {count}
+        measure.pick = {mode}: Punkt {point} von {points} wählen
+
+    English (locale_en.lang) and german (locale_de.lang) are built in,
+    applications add their own languages from files, or their own keys
+    to a built in language with extend(). A key the language doesn't have
+    falls back to english and then to the key itself, so a half done
+    translation still shows everything.
+
+    The string table belongs to the Overlay, which every tool draws its
+    text with, so they all pick up the language set there. The viewer
+    takes the language from --locale, then the `locale` line of its
+    window state (windowstate.rs), then $LANG.
+
+    The overlay font only has the latin-1 characters (see overlay.rs),
+    others show up as boxes.
+
+    usage:
+        let strings = Strings::builtin("de").unwrap_or_default();
+        overlay.set_strings(strings);
+        ...
+        let text = overlay.strings().format("stats.triangles", &[("count", &triangles.to_string())]);
+*/
+
+// name and text of the built in languages, english first.
+const BUILTIN: [(&str, &str); 2] = [
+    ("en", include_str!("locale_en.lang")),
+    ("de", include_str!("locale_de.lang")),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strings {
+    locale: String,
+    table: HashMap<String, String>,
+    // english, for the keys `table` misses.
+    fallback: HashMap<String, String>,
+}
+
+impl Default for Strings {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+impl Strings {
+    pub fn english() -> Self {
+        let table = parse(BUILTIN[0].1).expect("The english strings don't parse.");
+        Self { locale: "en".to_string(), fallback: table.clone(), table }
+    }
+
+    // one of the built in languages, "de" or "de_DE.UTF-8" alike.
+    pub fn builtin(locale: &str) -> Option<Self> {
+        let language = language(locale);
+        let (name, text) = BUILTIN.iter().find(|(name, _)| *name == language)?;
+        let mut strings = Self::english();
+        strings.locale = name.to_string();
+        strings.table = parse(text).expect("The built in strings don't parse.");
+        Some(strings)
+    }
+
+    // the built in language `locale`, english if there's none.
+    pub fn for_locale(locale: &str) -> Self {
+        Self::builtin(locale).unwrap_or_else(|| {
+            log::warn!("No strings for the locale {:?}, using english.", locale);
+            Self::english()
+        })
+    }
+
+    // A language from a locale file, called like the file ("fr.lang" -> "fr").
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read the strings {:?}.", path))?;
+        let table = parse(&text).with_context(|| format!("Unable to parse the strings {:?}.", path))?;
+        let mut strings = Self::english();
+        strings.locale = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        strings.table = table;
+        Ok(strings)
+    }
+
+    // Adds the strings of a locale file of the same language, replacing
+    // the ones with the same key.
+    pub fn extend(&mut self, text: &str) -> Result<()> {
+        self.table.extend(parse(text)?);
+        Ok(())
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.table.get(key)
+            .or_else(|| self.fallback.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    // get() with the {name}s filled in.
+    pub fn format(&self, key: &str, values: &[(&str, &str)]) -> String {
+        let mut text = self.get(key).to_string();
+        for (name, value) in values {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+}
+
+// the language part of a locale, "de_DE.UTF-8" -> "de".
+pub fn language(locale: &str) -> String {
+    locale.split(['_', '-', '.', '@']).next().unwrap_or("").to_ascii_lowercase()
+}
+
+// The language of the user's environment, $LC_ALL, $LC_MESSAGES or $LANG.
+pub fn system_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|locale| !locale.is_empty() && locale != "C" && locale != "POSIX")
+}
+
+fn parse(text: &str) -> Result<HashMap<String, String>> {
+    let mut table = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => bail!("Line {}: expected a key, = and the text.", number + 1),
+        };
+        if key.is_empty() {
+            bail!("Line {}: the key is missing.", number + 1);
+        }
+        table.insert(key.to_string(), value.replace("\\n", "\n"));
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_keys_and_values() {
+        let table = parse("# a comment\n\n  a.b = one = two  \nc=\nnew.line = first\\nsecond\n").unwrap();
+        assert_eq!(table.len(), 3);
+        // only the first = separates, whitespace around both is trimmed.
+        assert_eq!(table["a.b"], "one = two");
+        assert_eq!(table["c"], "");
+        assert_eq!(table["new.line"], "first\nsecond");
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = parse("a = 1\njust text\n").unwrap_err();
+        assert!(error.to_string().contains("Line 2"), "{}", error);
+        assert!(parse("a = 1\n = no key\n").is_err());
+    }
+
+    #[test]
+    fn builtin_languages_have_the_same_keys() {
+        let english = parse(BUILTIN[0].1).unwrap();
+        for (name, text) in BUILTIN.iter().skip(1) {
+            let table = parse(text).unwrap();
+            let mut missing: Vec<&String> = english.keys().filter(|key| !table.contains_key(*key)).collect();
+            missing.sort();
+            assert!(missing.is_empty(), "{} misses {:?}", name, missing);
+            assert!(table.keys().all(|key| english.contains_key(key)), "{} has keys english doesn't", name);
+        }
+    }
+
+    #[test]
+    fn lookups_fall_back_to_english_and_the_key() {
+        let mut strings = Strings::builtin("de_DE.UTF-8").unwrap();
+        assert_eq!(strings.locale(), "de");
+        strings.table.remove("stats.triangles");
+        assert_eq!(strings.get("stats.triangles"), Strings::english().get("stats.triangles"));
+        assert_eq!(strings.get("no.such.key"), "no.such.key");
+
+        strings.extend("stats.triangles = {count} Dreiecke ({count})\nextra = mehr").unwrap();
+        assert_eq!(strings.format("stats.triangles", &[("count", "12"), ("unused", "x")]), "12 Dreiecke (12)");
+        assert_eq!(strings.get("extra"), "mehr");
+        assert!(strings.extend("broken").is_err());
+
+        assert!(Strings::builtin("fr_FR").is_none());
+        assert_eq!(Strings::for_locale("fr").locale(), "en");
+    }
+
+    #[test]
+    fn language_is_the_first_part_of_the_locale() {
+        assert_eq!(language("de_DE.UTF-8"), "de");
+        assert_eq!(language("EN-us"), "en");
+        assert_eq!(language("sr@latin"), "sr");
+        assert_eq!(language(""), "");
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use wgpu::util::DeviceExt;
+
+use crate::model::Bounds;
+
+/*
+    Level of detail for the secondary views: what the scene is drawn
+    with where nobody looks at it closely, the cube faces of a reflection
+    capture, a minimap, the shadow map of the sun. Those views draw the
+    whole scene again, often several times, for a few pixels each.
+
+    Every view draws at a DetailTier:
+    - Full: what the window shows.
+    - Reduced, Low: simplified materials and meshes. The material is the
+      diffuse texture with the uv transform, the vertex colors and the
+      tint, without the lightmap, the splat layers, the flipbook and the
+      flashlight (fs_simple in basic_shader.wgsl). The mesh is a coarser
+      version of itself, Low coarser than Reduced, and a subdivided
+      terrain is drawn as it was loaded.
+
+    The coarser meshes are built once when a mesh is put on the gpu, by
+    vertex clustering: the vertices are snapped to a grid, every cell
+    keeps one of its vertices and the triangles whose corners end up in
+    the same cell are dropped. Only the index buffer is new, the vertex
+    buffer is shared, so the uvs and the vertex animations (vat.rs) still
+    fit. A mesh that wouldn't get much smaller keeps drawing itself.
+
+    Which tier a view draws at is set in LodSettings, draw_models() in
+    renderer.rs picks the pipeline and the index buffer by it.
+
+    usage:
+        renderer.set_lod_settings(LodSettings { reflection: DetailTier::Low, ..Default::default() });
+        renderer.capture_cubemap(|_, cube| { ... })?; // drawn at the reflection tier
+        renderer.capture_camera("minimap", |_, minimap| { ... })?; // at the minimap tier
+*/
+
+// cell size of the clustering grid, as a part of the mesh's diagonal.
+const REDUCED_CELL: f32 = 1.0 / 48.0;
+const LOW_CELL: f32 = 1.0 / 16.0;
+// a coarser mesh is only kept with at most this part of the triangles.
+const MAX_KEPT: f32 = 0.75;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DetailTier {
+    Full,
+    Reduced,
+    Low,
+}
+
+impl DetailTier {
+    // the coarser meshes of Mesh::lods, Full has none.
+    pub const COARSE: [DetailTier; 2] = [DetailTier::Reduced, DetailTier::Low];
+
+    fn cell(self) -> f32 {
+        match self {
+            DetailTier::Full => 0.0,
+            DetailTier::Reduced => REDUCED_CELL,
+            DetailTier::Low => LOW_CELL,
+        }
+    }
+
+    // whether the simplified materials are drawn.
+    pub fn simple_materials(self) -> bool {
+        self != DetailTier::Full
+    }
+}
+
+// The views other than the window's and the tier each is drawn at.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LodSettings {
+    // the sun's shadow map (see shadow.rs), the casters only need
+    // their outlines.
+    pub shadow: DetailTier,
+    // the faces of Renderer::capture_cubemap().
+    pub reflection: DetailTier,
+    // Renderer::capture_camera(), a view from far above.
+    pub minimap: DetailTier,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            shadow: DetailTier::Low,
+            reflection: DetailTier::Reduced,
+            minimap: DetailTier::Low,
+        }
+    }
+}
+
+// The triangles of a coarser version of a mesh, into its vertex buffer.
+pub struct MeshLod {
+    pub tier: DetailTier,
+    pub index_buffer: wgpu::Buffer,
+    pub num_elements: u32,
+}
+
+impl MeshLod {
+    // the coarser meshes worth keeping, coarsest last.
+    pub fn build(device: &wgpu::Device, name: &str, positions: &[[f32; 3]], indices: &[u32], bounds: &Bounds) -> Vec<MeshLod> {
+        let size = bounds.size();
+        let diagonal = (size[0] * size[0] + size[1] * size[1] + size[2] * size[2]).sqrt();
+        let mut lods: Vec<MeshLod> = Vec::new();
+        if diagonal <= 0.0 || indices.is_empty() {
+            return lods;
+        }
+        for tier in DetailTier::COARSE {
+            let simplified = simplify(positions, indices, diagonal * tier.cell());
+            // every tier has to be smaller than the one before it.
+            let finer = lods.last().map_or(indices.len(), |lod| lod.num_elements as usize);
+            if simplified.is_empty() || simplified.len() as f32 > finer as f32 * MAX_KEPT {
+                continue;
+            }
+            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} {:?} Index Buffer", name, tier)),
+                contents: bytemuck::cast_slice(&simplified),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+            lods.push(MeshLod { tier, index_buffer, num_elements: simplified.len() as u32 });
+        }
+        lods
+    }
+}
+
+// Vertex clustering, see above: the triangles of `indices` with their
+// corners snapped to cells of `cell` size, the degenerate ones dropped.
+pub fn simplify(positions: &[[f32; 3]], indices: &[u32], cell: f32) -> Vec<u32> {
+    if cell <= 0.0 {
+        return indices.to_vec();
+    }
+    // the first vertex that lands in a cell stands for the whole cell.
+    let mut cells: HashMap<[i32; 3], u32> = HashMap::new();
+    let representative: Vec<u32> = positions
+        .iter()
+        .enumerate()
+        .map(|(index, position)| {
+            let key = position.map(|value| (value / cell).floor() as i32);
+            *cells.entry(key).or_insert(index as u32)
+        })
+        .collect();
+
+    let mut simplified = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| {
+            let index = triangle[corner] as usize;
+            representative.get(index).copied().unwrap_or(index as u32)
+        });
+        if a != b && b != c && a != c {
+            simplified.extend_from_slice(&[a, b, c]);
+        }
+    }
+    simplified
+}
+
+// The scene pipeline with the simplified materials (fs_simple), same
+// layout and targets as the one of create_render_pipeline().
+pub fn create_simple_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    depth_format: Option<wgpu::TextureFormat>,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Simple Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_simple",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
@@ -0,0 +1,391 @@
+use std::ffi::{c_char, c_void};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::renderer::Renderer;
+
+/*
+    Game logic in a dynamic library that is loaded again whenever it's
+    rebuilt, so gameplay code can be changed while the viewer keeps
+    running.
+
+    The library and the viewer only share plain C: the library exports
+    these functions
+
+        u32   zx_logic_abi_version(void);
+        void* zx_logic_load(const LogicHost* host, const u8* saved, usize saved_len);
+        void  zx_logic_update(const LogicHost* host, void* state, f32 dt);
+        void  zx_logic_render(const LogicHost* host, void* state);
+        void  zx_logic_unload(const LogicHost* host, void* state);
+
+    and calls back into the viewer through the LogicHost table it gets,
+    the table is only valid during the call. The state pointer is the
+    library's own, the viewer only hands it back. Before a reload the
+    library can keep what it needs with host->save(), the bytes come
+    back as `saved` when the new build is loaded. A library built for
+    another ABI_VERSION isn't loaded, functions are only ever added at
+    the end of LogicHost, and ABI_VERSION goes up when they are.
+
+    zx_logic_render() runs right before the frame is drawn, the lines
+    it draws with host->line() are shown for that frame.
+
+    The file is copied before it's loaded, so the build can overwrite
+    it, and it's checked for changes twice a second. Needs the `logic`
+    feature, without it load() fails.
+
+    usage:
+        cargo run --features logic -- view --logic target/debug/libmy_logic.so
+
+        // in the library, a cdylib
+        #[no_mangle]
+        pub extern "C" fn zx_logic_update(host: *const LogicHost, state: *mut c_void, dt: f32) {
+            let host = unsafe { &*host };
+            let mut offset = [0.0; 3];
+            if (host.mesh_offset)(host.context, 0, 0, &mut offset) {
+                offset[1] += dt;
+                (host.set_mesh_offset)(host.context, 0, 0, &offset);
+            }
+        }
+*/
+
+pub const ABI_VERSION: u32 = 1;
+pub const OVERLAY_LAYER: &str = "logic";
+
+// What the viewer offers the library. Every function gets `context` first.
+#[repr(C)]
+pub struct LogicHost {
+    pub abi_version: u32,
+    pub context: *mut c_void,
+    // a line in the viewer's log.
+    pub log: extern "C" fn(context: *mut c_void, message: *const c_char),
+    // bytes the next build gets in zx_logic_load(), replaces what was saved before.
+    pub save: extern "C" fn(context: *mut c_void, data: *const u8, len: usize),
+    pub model_count: extern "C" fn(context: *mut c_void) -> u32,
+    pub mesh_count: extern "C" fn(context: *mut c_void, model: u32) -> u32,
+    // false when there's no such mesh.
+    pub mesh_offset: extern "C" fn(context: *mut c_void, model: u32, mesh: u32, offset: *mut [f32; 3]) -> bool,
+    pub set_mesh_offset: extern "C" fn(context: *mut c_void, model: u32, mesh: u32, offset: *const [f32; 3]) -> bool,
+    pub set_mesh_visible: extern "C" fn(context: *mut c_void, model: u32, mesh: u32, visible: bool) -> bool,
+    pub camera: extern "C" fn(context: *mut c_void, eye: *mut [f32; 3], target: *mut [f32; 3]),
+    pub look_at: extern "C" fn(context: *mut c_void, eye: *const [f32; 3], target: *const [f32; 3]),
+    // only from zx_logic_render(), colors are rgba 0-1.
+    pub line: extern "C" fn(context: *mut c_void, from: *const [f32; 3], to: *const [f32; 3], color: *const [f32; 4]),
+}
+
+// The LogicHost functions, and loading the library.
+#[cfg(feature = "logic")]
+mod host {
+    use std::ffi::{c_char, c_void, CStr};
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{bail, Context, Result};
+    use cgmath::Point3;
+
+    use super::{LogicHost, ABI_VERSION};
+    use crate::renderer::Renderer;
+
+    type AbiVersionFn = unsafe extern "C" fn() -> u32;
+    type LoadFn = unsafe extern "C" fn(*const LogicHost, *const u8, usize) -> *mut c_void;
+    pub type UpdateFn = unsafe extern "C" fn(*const LogicHost, *mut c_void, f32);
+    pub type RenderFn = unsafe extern "C" fn(*const LogicHost, *mut c_void);
+    type UnloadFn = unsafe extern "C" fn(*const LogicHost, *mut c_void);
+    // from, to, color.
+    pub type Line = ([f32; 3], [f32; 3], [f32; 4]);
+
+    // What `context` points to while the library runs.
+    struct HostContext<'a> {
+        renderer: &'a mut Renderer,
+        saved: &'a mut Vec<u8>,
+        // lines drawn by zx_logic_render().
+        lines: Vec<Line>,
+    }
+
+    // the context behind the pointer the library handed back.
+    unsafe fn host<'a>(context: *mut c_void) -> &'a mut HostContext<'a> {
+        &mut *(context as *mut HostContext)
+    }
+
+    extern "C" fn host_log(_context: *mut c_void, message: *const c_char) {
+        if !message.is_null() {
+            log::info!("logic: {}", unsafe { CStr::from_ptr(message) }.to_string_lossy());
+        }
+    }
+
+    extern "C" fn host_save(context: *mut c_void, data: *const u8, len: usize) {
+        let host = unsafe { host(context) };
+        host.saved.clear();
+        if !data.is_null() {
+            host.saved.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+        }
+    }
+
+    extern "C" fn host_model_count(context: *mut c_void) -> u32 {
+        unsafe { host(context) }.renderer.models().len() as u32
+    }
+
+    extern "C" fn host_mesh_count(context: *mut c_void, model: u32) -> u32 {
+        let host = unsafe { host(context) };
+        host.renderer.models().get(model as usize).map_or(0, |model| model.meshes.len() as u32)
+    }
+
+    extern "C" fn host_mesh_offset(context: *mut c_void, model: u32, mesh: u32, offset: *mut [f32; 3]) -> bool {
+        let host = unsafe { host(context) };
+        match host.renderer.models().get(model as usize).and_then(|model| model.meshes.get(mesh as usize)) {
+            Some(mesh) if !offset.is_null() => {
+                unsafe { *offset = mesh.offset };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    extern "C" fn host_set_mesh_offset(context: *mut c_void, model: u32, mesh: u32, offset: *const [f32; 3]) -> bool {
+        let host = unsafe { host(context) };
+        if offset.is_null() || mesh as usize >= host_mesh_count(context, model) as usize {
+            return false;
+        }
+        host.renderer.set_mesh_offset(model as usize, mesh as usize, unsafe { *offset });
+        true
+    }
+
+    extern "C" fn host_set_mesh_visible(context: *mut c_void, model: u32, mesh: u32, visible: bool) -> bool {
+        let host = unsafe { host(context) };
+        if mesh as usize >= host_mesh_count(context, model) as usize {
+            return false;
+        }
+        host.renderer.set_mesh_visible(model as usize, mesh as usize, visible);
+        true
+    }
+
+    extern "C" fn host_camera(context: *mut c_void, eye: *mut [f32; 3], target: *mut [f32; 3]) {
+        let camera = unsafe { host(context) }.renderer.camera();
+        if !eye.is_null() {
+            unsafe { *eye = camera.eye().into() };
+        }
+        if !target.is_null() {
+            unsafe { *target = camera.target().into() };
+        }
+    }
+
+    extern "C" fn host_look_at(context: *mut c_void, eye: *const [f32; 3], target: *const [f32; 3]) {
+        if eye.is_null() || target.is_null() {
+            return;
+        }
+        let (eye, target) = unsafe { (*eye, *target) };
+        unsafe { host(context) }.renderer.camera_mut().look_at(Point3::from(eye), Point3::from(target));
+    }
+
+    extern "C" fn host_line(context: *mut c_void, from: *const [f32; 3], to: *const [f32; 3], color: *const [f32; 4]) {
+        if from.is_null() || to.is_null() || color.is_null() {
+            return;
+        }
+        let line = unsafe { (*from, *to, *color) };
+        unsafe { host(context) }.lines.push(line);
+    }
+
+    // runs `f` with a LogicHost table around `renderer`.
+    pub fn with_host<R>(renderer: &mut Renderer, saved: &mut Vec<u8>, f: impl FnOnce(&LogicHost) -> R) -> (R, Vec<Line>) {
+        let mut context = HostContext { renderer, saved, lines: Vec::new() };
+        let host = LogicHost {
+            abi_version: ABI_VERSION,
+            context: &mut context as *mut HostContext as *mut c_void,
+            log: host_log,
+            save: host_save,
+            model_count: host_model_count,
+            mesh_count: host_mesh_count,
+            mesh_offset: host_mesh_offset,
+            set_mesh_offset: host_set_mesh_offset,
+            set_mesh_visible: host_set_mesh_visible,
+            camera: host_camera,
+            look_at: host_look_at,
+            line: host_line,
+        };
+        let result = f(&host);
+        (result, context.lines)
+    }
+
+    // The functions of one loaded build.
+    pub struct Library {
+        // keeps the functions below valid.
+        _library: libloading::Library,
+        // the copy that was loaded, removed with the build.
+        copy: PathBuf,
+        pub update: UpdateFn,
+        pub render: RenderFn,
+        unload: UnloadFn,
+        pub state: *mut c_void,
+    }
+
+    impl Library {
+        pub fn open(path: &Path, copy: PathBuf, renderer: &mut Renderer, saved: &mut Vec<u8>) -> Result<Self> {
+            std::fs::copy(path, &copy).with_context(|| format!("Unable to copy the logic library {:?}.", path))?;
+            let library = unsafe { libloading::Library::new(&copy) }
+                .with_context(|| format!("Unable to load the logic library {:?}.", path))?;
+            let (load, update, render, unload) = unsafe {
+                let abi_version = *library.get::<AbiVersionFn>(b"zx_logic_abi_version\0")?;
+                if abi_version() != ABI_VERSION {
+                    bail!("The logic library {:?} was built for abi version {}, this viewer has {}.", path, abi_version(), ABI_VERSION);
+                }
+                (
+                    *library.get::<LoadFn>(b"zx_logic_load\0")?,
+                    *library.get::<UpdateFn>(b"zx_logic_update\0")?,
+                    *library.get::<RenderFn>(b"zx_logic_render\0")?,
+                    *library.get::<UnloadFn>(b"zx_logic_unload\0")?,
+                )
+            };
+            let restored = std::mem::take(saved);
+            let (state, _) = with_host(renderer, saved, |host| unsafe { load(host, restored.as_ptr(), restored.len()) });
+            // load() didn't save anything new, the old bytes stay for the next reload.
+            if saved.is_empty() {
+                *saved = restored;
+            }
+            Ok(Self { _library: library, copy, update, render, unload, state })
+        }
+
+        pub fn close(self, renderer: &mut Renderer, saved: &mut Vec<u8>) {
+            with_host(renderer, saved, |host| unsafe { (self.unload)(host, self.state) });
+            let copy = self.copy.clone();
+            drop(self);
+            let _ = std::fs::remove_file(copy);
+        }
+    }
+}
+
+pub struct GameLogic {
+    path: PathBuf,
+    #[cfg(feature = "logic")]
+    library: Option<host::Library>,
+    // what the library saved for the next build.
+    #[cfg(feature = "logic")]
+    saved: Vec<u8>,
+    // of the file when it was loaded.
+    #[cfg(feature = "logic")]
+    modified: Option<std::time::SystemTime>,
+    #[cfg(feature = "logic")]
+    last_check: std::time::Instant,
+    // builds loaded so far, names the copies.
+    #[cfg(feature = "logic")]
+    loads: u32,
+}
+
+// how often the library file is checked for a new build.
+#[cfg(feature = "logic")]
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+#[cfg(feature = "logic")]
+impl GameLogic {
+    pub fn load<P: AsRef<Path>>(renderer: &mut Renderer, path: P) -> Result<Self> {
+        let mut logic = Self {
+            path: path.as_ref().to_path_buf(),
+            library: None,
+            saved: Vec::new(),
+            modified: None,
+            last_check: std::time::Instant::now(),
+            loads: 0,
+        };
+        logic.reload(renderer)?;
+        Ok(logic)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // loads the library again, the old build stays when the new one can't be loaded.
+    pub fn reload(&mut self, renderer: &mut Renderer) -> Result<()> {
+        self.modified = modified(&self.path);
+        let copy = std::env::temp_dir().join(format!(
+            "{}.{}.{}",
+            self.path.file_name().and_then(|name| name.to_str()).unwrap_or("logic"),
+            std::process::id(),
+            self.loads,
+        ));
+        self.loads += 1;
+
+        // the old build saves its state first, the new one starts from it.
+        let mut saved = self.saved.clone();
+        if let Some(library) = self.library.take() {
+            library.close(renderer, &mut saved);
+        }
+        match host::Library::open(&self.path, copy, renderer, &mut saved) {
+            Ok(library) => {
+                log::info!("Loaded the logic library {:?}", self.path);
+                self.library = Some(library);
+                self.saved = saved;
+                Ok(())
+            }
+            Err(e) => {
+                // there's nothing to go back to, the old build is unloaded.
+                self.saved = saved;
+                Err(e)
+            }
+        }
+    }
+
+    // reloads a new build, then runs zx_logic_update(). Once per frame.
+    pub fn update(&mut self, renderer: &mut Renderer, dt: f32) {
+        if self.last_check.elapsed() >= CHECK_INTERVAL {
+            self.last_check = std::time::Instant::now();
+            let modified = modified(&self.path);
+            if modified.is_some() && modified != self.modified {
+                if let Err(e) = self.reload(renderer) {
+                    log::error!("Unable to reload the logic library: {:?}", e);
+                }
+            }
+        }
+        if let Some(library) = &self.library {
+            host::with_host(renderer, &mut self.saved, |host| unsafe { (library.update)(host, library.state, dt) });
+        }
+    }
+
+    // runs zx_logic_render() and shows its lines, right before the frame is rendered.
+    pub fn render(&mut self, renderer: &mut Renderer) {
+        let library = match &self.library {
+            Some(library) => library,
+            None => return,
+        };
+        let (_, lines) = host::with_host(renderer, &mut self.saved, |host| unsafe { (library.render)(host, library.state) });
+        if let Some(overlay) = renderer.plugin_mut::<crate::overlay::Overlay>() {
+            let layer = overlay.layer(OVERLAY_LAYER);
+            layer.clear();
+            for (from, to, color) in lines {
+                layer.line(from, to, color);
+            }
+        }
+    }
+
+    // calls zx_logic_unload(), e.g. before the viewer closes.
+    pub fn unload(&mut self, renderer: &mut Renderer) {
+        if let Some(library) = self.library.take() {
+            library.close(renderer, &mut self.saved);
+        }
+    }
+}
+
+// without the feature there's no library loader.
+#[cfg(not(feature = "logic"))]
+impl GameLogic {
+    pub fn load<P: AsRef<Path>>(_renderer: &mut Renderer, _path: P) -> Result<Self> {
+        anyhow::bail!("Built without game logic libraries, enable the logic feature.")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn reload(&mut self, _renderer: &mut Renderer) -> Result<()> {
+        anyhow::bail!("Built without game logic libraries, enable the logic feature.")
+    }
+
+    pub fn update(&mut self, _renderer: &mut Renderer, _dt: f32) {}
+
+    pub fn render(&mut self, _renderer: &mut Renderer) {}
+
+    pub fn unload(&mut self, _renderer: &mut Renderer) {}
+}
+
+#[cfg(feature = "logic")]
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -1,570 +1,2112 @@
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
 };
-use std::time::Instant;
-use wgpu::util::DeviceExt;
 
-use crate::vertex::*;
-use anyhow::*;
-
-pub mod texture;
-pub mod camera;
-pub mod light;
-pub mod model;
-pub mod vertex;
+use zhneeshyx::{camera, cameras, model, monitors, texture};
+use zhneeshyx::agents::Agents;
+use zhneeshyx::annotations::Annotations;
+use zhneeshyx::benchmark::{Benchmark, CameraPath};
+use zhneeshyx::budget::BudgetLevel;
+use zhneeshyx::cameras::SceneNode;
+use zhneeshyx::clipping::ClipPlane;
+use zhneeshyx::follow::{FollowCamera, FollowSettings};
+use zhneeshyx::input::{ActionEvent, Binding, InputMap};
+use zhneeshyx::jobs::{self, JobPriority};
+use zhneeshyx::light::Light;
+use zhneeshyx::logic::GameLogic;
+use zhneeshyx::measure::{self, MeasureMode, Measurement};
+use zhneeshyx::navmesh::{NavMesh, NavSettings};
+use zhneeshyx::picking::Plane;
+use zhneeshyx::locale::{self, Strings};
+use zhneeshyx::overlay::Overlay;
+use zhneeshyx::palette::{self, DebugPalette};
+use zhneeshyx::stats::{self, StatsHud};
+use zhneeshyx::pointcloud::{PointCloud, PointCloudPass, PointSize};
+use zhneeshyx::preview::PreviewSubject;
+use zhneeshyx::profiler;
+use zhneeshyx::remote::RemoteControl;
+use zhneeshyx::replay::{InputRecorder, InputReplay};
+use zhneeshyx::resolution::ResolutionSettings;
+use zhneeshyx::road::{self, Road, RoadSettings};
+use zhneeshyx::script::Scripts;
+use zhneeshyx::shading::ShadingRate;
+use zhneeshyx::snapping::{self, SnapSettings};
+use zhneeshyx::splat::{self, Brush, SplatLayers, SplatMap};
+use zhneeshyx::stereo::StereoSettings;
+use zhneeshyx::subdivision::SubdivisionSettings;
+use zhneeshyx::sync::SyncSession;
+use zhneeshyx::textinput::{TextInput, TextInputEvent};
+use zhneeshyx::turntable::{self, Turntable};
+use zhneeshyx::undo::{self, EditCommand, UndoStack};
+use zhneeshyx::windowstate::WindowState;
+
+mod cli;
+use zhneeshyx::{Renderer, RendererBuilder, RendererEvent};
 
 
 struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-
-    camera: camera::Camera,
-    camera_config: camera::UniformBuffer,
-    camera_buffer: wgpu::Buffer,
-    camera_bindgroup: wgpu::BindGroup, 
+    renderer: Renderer,
 
     camera_controller: camera::CameraController,
+    // keys and mouse buttons of the viewer, see default_bindings().
+    input_map: InputMap<Action>,
 
-    config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    time: Instant,
-    clear_color: wgpu::Color,
-
-    render_pipeline: wgpu::RenderPipeline,
+    // index into the renderer materials, switched with space.
+    bind_group_index: usize,
+    num_diffuse_materials: usize,
+
+    // last cursor position, in pixels.
+    cursor: (f32, f32),
+    // switched with M: off -> distance -> angle -> off.
+    measurement: Option<Measurement>,
+    // switched with C: the scene is cut along x, y, z, then not at all.
+    clip_axis: Option<usize>,
+    // switched with E, pulls the meshes of every model apart.
+    exploded: bool,
+    // model and mesh last clicked on outside of the measurement mode,
+    // hidden with H, isolated with I (U shows everything again),
+    // its model removed with Delete and copied with Ctrl+D,
+    // dragged with Alt+click and dropped to the ground with G.
+    selected: Option<(usize, usize)>,
+    // frame statistics, switched with F1.
+    stats_hud: Option<StatsHud>,
+    // the progress of the background jobs is on the overlay, Pause cancels them.
+    job_progress_shown: bool,
+    // index into FPS_LIMITS.
+    fps_limit: usize,
+    // switched with F3.
+    dynamic_resolution: bool,
+    // flight of the "path" camera and when it started.
+    camera_path: Option<(CameraPath, std::time::Instant)>,
+    // the last update(), the camera moves by the time since.
+    last_update: std::time::Instant,
+    // third-person camera of the "follow" camera, behind the selected mesh.
+    follow: Option<FollowCamera>,
+    // edits of the scene, Ctrl+Z / Ctrl+Y.
+    undo: UndoStack,
+    // open text field and what it's for, the keys go to it instead of the hotkeys.
+    text_input: Option<(TextInput, TextTarget)>,
+    // grid the selected mesh snaps to while it's dragged, Ctrl+G switches the size.
+    snap: SnapSettings,
+    // index into SNAP_GRIDS.
+    snap_grid: usize,
+    // mesh dragged with Alt+click, where it was and where it was grabbed.
+    moving: Option<MeshDrag>,
+    // model whose splat map the mouse paints, switched with B on the selected model.
+    painting: Option<usize>,
+    // layer with 1, 2, 3, size with [ and ], opacity with Shift+[ and Shift+].
+    brush: Brush,
+    // road laid out with clicks on the terrain, switched with R.
+    road: Option<RoadTool>,
+    // built and shown with N, clicks then set the start and the goal of a path.
+    navmesh: Option<NavMesh>,
+    nav_start: Option<[f32; 3]>,
+    // camera and scene shared with other viewers, see `view --host` / `--join`.
+    sync: Option<SyncSession>,
+    // http api for scripts, see `view --remote`.
+    remote: Option<RemoteControl>,
+    // `view --script` files and the console opened with `.
+    scripts: Scripts,
+    // `view --logic` library, reloaded when it's rebuilt.
+    logic: Option<GameLogic>,
+    // renderer events the viewer reacts to, so far the memory budget.
+    events: std::sync::mpsc::Receiver<RendererEvent>,
+    // notes pinned to points of the models, T adds one where the cursor points.
+    annotations: Annotations,
+}
 
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
+struct RoadTool {
+    road: Road,
+    // the model the road is drawn as, once it has two points.
+    model: Option<usize>,
+    // control point that follows the cursor while the mouse button is down.
+    dragging: Option<usize>,
+}
 
-    num_indices: u32,
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct MeshDrag {
+    model: usize,
+    mesh: usize,
+    from: [f32; 3],
+    // on the horizontal plane the mesh is dragged along.
+    grabbed: cgmath::Vector3<f32>,
+}
 
-    bind_group_index: usize,
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TextTarget {
+    MeshName(usize, usize),
+    FpsLimit,
+    // a line for the script console, it opens again after every line.
+    Script,
+    // the text of a new annotation at this point.
+    Annotation([f32; 3]),
+}
 
-    texture_bind_group_layout: wgpu::BindGroupLayout,
+// What the keys and mouse buttons do in the viewer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Action {
+    Select,
+    Pan,
+    Measure,
+    Clip,
+    Explode,
+    Hide,
+    Isolate,
+    ShowAll,
+    SaveScene,
+    LoadScene,
+    StatsHud,
+    Profiler,
+    CancelJobs,
+    FpsLimit,
+    DynamicResolution,
+    Supersampling,
+    ShadingRate,
+    Stereo,
+    NextCamera,
+    Capture,
+    DumpTargets,
+    CaptureCubemap,
+    Poster,
+    Screenshot,
+    NextBackend,
+    Flashlight,
+    Sun,
+    AddLight,
+    NextMaterial,
+    NextInstanceMaterial,
+    MaterialPreview,
+    RenameMesh,
+    EnterFpsLimit,
+    ScriptConsole,
+    RemoveModel,
+    Duplicate,
+    Move,
+    DropToGround,
+    SnapGrid,
+    PaintMode,
+    BrushLayer(usize),
+    BrushSize(f32),
+    BrushOpacity(f32),
+    UvTiling(f32),
+    MoveSpeed(f32),
+    Look,
+    FlyMode,
+    RoadMode,
+    RemoveRoadPoint,
+    NavMesh,
+    Agents,
+    Undo,
+    Redo,
+    SavePrefab,
+    PlacePrefab,
+    UpdatePrefab,
+    Annotate,
+    RemoveAnnotation,
+    Fullscreen,
+    NextMonitor,
+    UiScale(f32),
+}
 
-    diffuse_bind_groups: Vec<wgpu::BindGroup>,
+fn default_bindings() -> InputMap<Action> {
+    let mut input = InputMap::new();
+    input.bind(Binding::mouse(MouseButton::Left), Action::Select);
+    input.bind(Binding::mouse(MouseButton::Left).with_shift(), Action::Pan);
+    input.bind(Binding::mouse(MouseButton::Middle), Action::Pan);
+    input.bind(Binding::mouse(MouseButton::Right), Action::Look);
+    input.bind(Binding::key(VirtualKeyCode::V), Action::FlyMode);
+    input.bind(Binding::key(VirtualKeyCode::M), Action::Measure);
+    input.bind(Binding::key(VirtualKeyCode::C), Action::Clip);
+    // E rolls the camera, with Q.
+    input.bind(Binding::key(VirtualKeyCode::E).with_ctrl(), Action::Explode);
+    input.bind(Binding::key(VirtualKeyCode::H), Action::Hide);
+    input.bind(Binding::key(VirtualKeyCode::I), Action::Isolate);
+    input.bind(Binding::key(VirtualKeyCode::U), Action::ShowAll);
+    input.bind(Binding::key(VirtualKeyCode::F5), Action::SaveScene);
+    input.bind(Binding::key(VirtualKeyCode::S).with_ctrl(), Action::SaveScene);
+    input.bind(Binding::key(VirtualKeyCode::F9), Action::LoadScene);
+    input.bind(Binding::key(VirtualKeyCode::O).with_ctrl(), Action::LoadScene);
+    input.bind(Binding::key(VirtualKeyCode::F1), Action::StatsHud);
+    input.bind(Binding::key(VirtualKeyCode::F1).with_ctrl(), Action::Profiler);
+    input.bind(Binding::key(VirtualKeyCode::Pause), Action::CancelJobs);
+    input.bind(Binding::key(VirtualKeyCode::F2), Action::FpsLimit);
+    input.bind(Binding::key(VirtualKeyCode::F3), Action::DynamicResolution);
+    input.bind(Binding::key(VirtualKeyCode::F3).with_shift(), Action::Supersampling);
+    input.bind(Binding::key(VirtualKeyCode::F4), Action::ShadingRate);
+    input.bind(Binding::key(VirtualKeyCode::F6), Action::NextBackend);
+    input.bind(Binding::key(VirtualKeyCode::F7), Action::Stereo);
+    input.bind(Binding::key(VirtualKeyCode::Tab), Action::NextCamera);
+    input.bind(Binding::key(VirtualKeyCode::F11), Action::Capture);
+    input.bind(Binding::key(VirtualKeyCode::F11).with_shift(), Action::DumpTargets);
+    input.bind(Binding::key(VirtualKeyCode::F11).with_ctrl(), Action::CaptureCubemap);
+    input.bind(Binding::key(VirtualKeyCode::F12), Action::Poster);
+    input.bind(Binding::key(VirtualKeyCode::F12).with_shift(), Action::Screenshot);
+    input.bind(Binding::key(VirtualKeyCode::F), Action::Flashlight);
+    input.bind(Binding::key(VirtualKeyCode::F).with_shift(), Action::Sun);
+    input.bind(Binding::key(VirtualKeyCode::L), Action::AddLight);
+    input.bind(Binding::key(VirtualKeyCode::Space), Action::NextMaterial);
+    input.bind(Binding::key(VirtualKeyCode::Space).with_ctrl(), Action::NextInstanceMaterial);
+    input.bind(Binding::key(VirtualKeyCode::M).with_ctrl(), Action::MaterialPreview);
+    input.bind(Binding::key(VirtualKeyCode::R).with_ctrl(), Action::RenameMesh);
+    input.bind(Binding::key(VirtualKeyCode::L).with_ctrl(), Action::EnterFpsLimit);
+    input.bind(Binding::key(VirtualKeyCode::Grave), Action::ScriptConsole);
+    input.bind(Binding::key(VirtualKeyCode::Delete), Action::RemoveModel);
+    input.bind(Binding::key(VirtualKeyCode::D).with_ctrl(), Action::Duplicate);
+    input.bind(Binding::mouse(MouseButton::Left).with_alt(), Action::Move);
+    input.bind(Binding::key(VirtualKeyCode::G), Action::DropToGround);
+    input.bind(Binding::key(VirtualKeyCode::G).with_ctrl(), Action::SnapGrid);
+    input.bind(Binding::key(VirtualKeyCode::B), Action::PaintMode);
+    input.bind(Binding::key(VirtualKeyCode::Key1), Action::BrushLayer(0));
+    input.bind(Binding::key(VirtualKeyCode::Key2), Action::BrushLayer(1));
+    input.bind(Binding::key(VirtualKeyCode::Key3), Action::BrushLayer(2));
+    input.bind(Binding::key(VirtualKeyCode::LBracket), Action::BrushSize(0.8));
+    input.bind(Binding::key(VirtualKeyCode::RBracket), Action::BrushSize(1.25));
+    input.bind(Binding::key(VirtualKeyCode::LBracket).with_shift(), Action::BrushOpacity(0.8));
+    input.bind(Binding::key(VirtualKeyCode::RBracket).with_shift(), Action::BrushOpacity(1.25));
+    input.bind(Binding::key(VirtualKeyCode::LBracket).with_ctrl(), Action::UvTiling(0.5));
+    input.bind(Binding::key(VirtualKeyCode::RBracket).with_ctrl(), Action::UvTiling(2.0));
+    input.bind(Binding::key(VirtualKeyCode::Minus).with_ctrl(), Action::MoveSpeed(0.8));
+    input.bind(Binding::key(VirtualKeyCode::Equals).with_ctrl(), Action::MoveSpeed(1.25));
+    input.bind(Binding::key(VirtualKeyCode::R), Action::RoadMode);
+    input.bind(Binding::key(VirtualKeyCode::Back), Action::RemoveRoadPoint);
+    input.bind(Binding::key(VirtualKeyCode::N), Action::NavMesh);
+    input.bind(Binding::key(VirtualKeyCode::K), Action::Agents);
+    input.bind(Binding::key(VirtualKeyCode::Z).with_ctrl(), Action::Undo);
+    input.bind(Binding::key(VirtualKeyCode::Y).with_ctrl(), Action::Redo);
+    input.bind(Binding::key(VirtualKeyCode::Z).with_ctrl().with_shift(), Action::Redo);
+    input.bind(Binding::key(VirtualKeyCode::P).with_ctrl(), Action::SavePrefab);
+    input.bind(Binding::key(VirtualKeyCode::P).with_ctrl().with_shift(), Action::PlacePrefab);
+    input.bind(Binding::key(VirtualKeyCode::U).with_ctrl().with_shift(), Action::UpdatePrefab);
+    input.bind(Binding::key(VirtualKeyCode::T), Action::Annotate);
+    input.bind(Binding::key(VirtualKeyCode::T).with_ctrl(), Action::RemoveAnnotation);
+    input.bind(Binding::key(VirtualKeyCode::Return).with_alt(), Action::Fullscreen);
+    input.bind(Binding::key(VirtualKeyCode::Return).with_alt().with_shift(), Action::NextMonitor);
+    input.bind(Binding::key(VirtualKeyCode::Minus).with_ctrl().with_shift(), Action::UiScale(-0.25));
+    input.bind(Binding::key(VirtualKeyCode::Equals).with_ctrl().with_shift(), Action::UiScale(0.25));
+    input
+}
 
-    obj_model: model::Model,
+// Tab goes through them, the first one is the camera the renderer starts with.
+// "follow" is skipped while no mesh is selected.
+const CAMERAS: [&str; 5] = [cameras::DEFAULT_CAMERA, "orbit", "path", "minimap", "follow"];
+// seconds the "path" camera takes for one circle.
+const CAMERA_PATH_SECONDS: f32 = 20.0;
+// the camera moves at most this far in time per frame, a stall (a model
+// loaded, the window dragged) doesn't send it flying.
+const MAX_TIME_STEP: std::time::Duration = std::time::Duration::from_millis(100);
+// replays move by the recorded time step of every frame, recordings made
+// before those were recorded by this.
+const REPLAY_TIME_STEP: std::time::Duration = std::time::Duration::from_nanos(16_666_667);
+
+// F2 goes through them, None is uncapped.
+const FPS_LIMITS: [Option<f32>; 3] = [None, Some(30.0), Some(60.0)];
+
+// Shift+F3 goes through them, 1 draws at the window's resolution.
+const SUPERSAMPLING: [f32; 3] = [1.0, 1.5, 2.0];
+
+// the camera controller settings, saved whenever Ctrl+- / Ctrl+= change the speed.
+const CONTROLS_FILE: &str = "controls.cfg";
+
+// Ctrl+G goes through them, 0 doesn't snap.
+const SNAP_GRIDS: [f32; 4] = [0.0, 0.25, 1.0, 5.0];
+
+// the splat map of material `material` of the painted model is saved into
+// it when the paint mode is left, and loaded from it when it's entered.
+fn splat_file(material: usize) -> String {
+    format!("terrain{}.splat.png", material)
 }
+// size of new splat maps, and how often the layers repeat on them.
+const SPLAT_SIZE: u32 = 512;
+const SPLAT_TILING: f32 = 32.0;
+// overlay layer of the script console's output.
+const SCRIPT_LAYER: &str = "script";
+// K adds that many wandering agents.
+const AGENTS_PER_SPAWN: usize = 10;
+
+// overlay layer of the gpu memory warning.
+const MEMORY_LAYER: &str = "memory";
+// overlay layer of the box around the selected mesh.
+const SELECTION_LAYER: &str = "selection";
+
+// Shift+F11 writes the render targets into it.
+const TARGETS_DIR: &str = "targets";
+// Ctrl+F11 writes a cube map and a panorama of the scene around the camera into it.
+const CUBEMAP_DIR: &str = "cubemaps";
+
+// direction Shift+F lets the sun shine in.
+const SUN_DIRECTION: [f32; 3] = [-0.3, -1.0, -0.2];
+
+// F12 writes the view at POSTER_SCALE times the window's size into it, drawn in tiles.
+const POSTER_DIR: &str = "posters";
+const POSTER_SCALE: u32 = 4;
+
+// Shift+F12 saves the view into it, without stopping the viewer.
+const SCREENSHOT_DIR: &str = "screenshots";
+
+// F5 / Ctrl+S saves the session into it, F9 / Ctrl+O loads it.
+const SCENE_FILE: &str = "viewer.scene";
+// Ctrl+P saves the selected model into it, Ctrl+Shift+P places it, Ctrl+Shift+U updates the placed ones.
+const PREFAB_FILE: &str = "selection.prefab";
 
 impl State {
-    // Creating some of the wgpu types requires async code
-    async fn new(window: &Window) -> Self {
-
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
+    fn new(renderer: Renderer, files: cli::ViewFiles) -> Self {
+        let mut renderer = renderer;
+
+        let first = add_diffuse_materials(&mut renderer).unwrap();
+        renderer.set_material_override(Some(first));
 
-        let adapter = instance
-            .enumerate_adapters(wgpu::Backends::all())
-            .filter(|adapter| {
-                // Check if this adapter supports our surface
-                surface.get_preferred_format(&adapter).is_some()
+        // camera controller, with the settings of the last session.
+        let settings = if std::path::Path::new(CONTROLS_FILE).is_file() {
+            camera::ControllerSettings::load(CONTROLS_FILE).unwrap_or_else(|e| {
+                log::error!("{:?}", e);
+                camera::ControllerSettings::default()
             })
-            .next()
-            .unwrap();
-
-        // reguest the graphics card and message queue
-        // Question: can we make request_device() async?
-        let fut_device = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None, // Trace path
-        );
+        } else {
+            camera::ControllerSettings::default()
+        };
+        let camera_controller = camera::CameraController::with_settings(settings);
+
+        let res_dir = std::path::Path::new( env!("OUT_DIR") ).join("res");
+        // shows a placeholder until the terrain is loaded.
+        let terrain = renderer.load_model_async(res_dir.join("terrain01.obj"));
+        // denser terrain around the camera.
+        if let Err(e) = renderer.enable_terrain_subdivision(terrain, SubdivisionSettings::default()) {
+            log::error!("Unable to subdivide the terrain: {:?}", e);
+        }
+
+        for path in files.models {
+            renderer.load_model_async(path);
+        }
+        if !files.point_clouds.is_empty() {
+            let mut points = PointCloudPass::new(PointSize::Attenuated { pixels: 4.0, distance: 10.0 });
+            for path in &files.point_clouds {
+                match PointCloud::load(path) {
+                    Ok(cloud) => points.add(cloud),
+                    Err(e) => log::error!("Unable to load point cloud {:?}: {:?}", path, e),
+                }
+            }
+            renderer.add_plugin(points);
+        }
+        renderer.add_plugin(Overlay::new());
+        let events = renderer.event_channel();
+
+        Self {
+            renderer,
+
+            camera_controller,
+            input_map: default_bindings(),
+
+            bind_group_index: first,
+            num_diffuse_materials: 2,
+
+            cursor: (0.0, 0.0),
+            measurement: None,
+            clip_axis: None,
+            exploded: false,
+            selected: None,
+            stats_hud: None,
+            job_progress_shown: false,
+            fps_limit: 0,
+            dynamic_resolution: false,
+            camera_path: None,
+            last_update: std::time::Instant::now(),
+            follow: None,
+            undo: UndoStack::new(100),
+            text_input: None,
+            snap: SnapSettings { grid: SNAP_GRIDS[2], ..SnapSettings::default() },
+            snap_grid: 2,
+            moving: None,
+            painting: None,
+            brush: Brush::default(),
+            road: None,
+            navmesh: None,
+            nav_start: None,
+            sync: None,
+            remote: None,
+            scripts: Scripts::new(),
+            logic: None,
+            events,
+            annotations: Annotations::new(),
+        }
+    }
 
-        let size = window.inner_size();
+    // places the next camera of CAMERAS around the models and makes it the active one.
+    fn switch_camera(&mut self) {
+        let current = CAMERAS.iter()
+            .position(|name| *name == self.renderer.active_camera())
+            .unwrap_or(0);
+        let mut name = CAMERAS[(current + 1) % CAMERAS.len()];
+        if name == "follow" && self.selected.is_none() {
+            name = CAMERAS[0];
+        }
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_preferred_format(&adapter).unwrap(),
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+        let bounds = self.renderer.models().iter()
+            .fold(model::Bounds::empty(), |bounds, model| bounds.union(&model.bounds));
+        let (center, radius) = if bounds.is_empty() {
+            ([0.0; 3], 1.0)
+        } else {
+            (bounds.center(), bounds.radius().max(0.1))
+        };
+        let center: cgmath::Point3<f32> = center.into();
+        let size = self.renderer.window().inner_size();
+        let aspect = size.width as f32 / size.height.max(1) as f32;
+        let path = CameraPath::orbit(&bounds);
+
+        let camera = match name {
+            "orbit" => {
+                let (eye, target) = path.at(0.125);
+                let mut camera = camera::Camera::looking_at(eye, target, aspect);
+                camera.set_clip_planes(0.1, path.far_plane());
+                Some(camera)
+            }
+            "path" => {
+                let (eye, target) = path.at(0.0);
+                let mut camera = camera::Camera::looking_at(eye, target, aspect);
+                camera.set_clip_planes(0.1, path.far_plane());
+                self.camera_path = Some((path, std::time::Instant::now()));
+                Some(camera)
+            }
+            "minimap" => {
+                // straight down, slightly tilted so "up" stays defined.
+                let eye = center + cgmath::Vector3::new(0.0, radius * 3.0, radius * 0.01);
+                let mut camera = camera::Camera::looking_at(eye, center, aspect);
+                camera.set_clip_planes(0.1, radius * 6.0);
+                Some(camera)
+            }
+            "follow" => self.selected.and_then(|(model, mesh)| {
+                let mut follow = FollowCamera::new(SceneNode::Mesh(model, mesh), FollowSettings {
+                    distance: radius * 0.25,
+                    max_distance: radius * 2.0,
+                    ..Default::default()
+                });
+                let camera = follow.update(self.renderer.models(), 0.0).map(|(eye, target)| {
+                    let mut camera = camera::Camera::looking_at(eye, target, aspect);
+                    camera.set_clip_planes(0.1, radius * 4.0);
+                    camera
+                });
+                self.follow = Some(follow);
+                camera
+            }),
+            // the main camera stays where it was left.
+            _ => None,
         };
+        if let Some(camera) = camera {
+            self.renderer.add_camera(name, camera);
+        }
+        if let Err(e) = self.renderer.set_active_camera(name) {
+            log::error!("Unable to switch the camera: {:?}", e);
+        }
+    }
 
-        // block thread until completion.
-        let (device, queue) = pollster::block_on( fut_device ).unwrap();
-
-        surface.configure(&device, &config);
-
-        let bytes_road = include_bytes!("road01.png");
-        let bytes_gras = include_bytes!("dirt01.png");
-
-        // include_bytes loads a file.
-        let my_tex =
-            texture::Texture::from_bytes( bytes_road, &device, &queue, "road texture").unwrap();
-
-        let my_tex2 =
-            texture::Texture::from_bytes( bytes_gras, &device, &queue, "gras texture").unwrap();
-
-        // bing group describes set of ressources, and they can be accessed
-        // by a shader
-        // -> the layout:
-        let texture_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler {
-                            // This is only for TextureSampleType::Depth
-                            comparison: false,
-                            // This should be true if the sample_type of the texture is:
-                            //     TextureSampleType::Float { filterable: true }
-                            // Otherwise you'll get an error.
-                            filtering: true,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            }
-        );
-
-        // -> the bind group
-        // : more specific declaration of a bind group layout.
-        // -> seperation allows switching between bind groups out efficiently
-        // : as along as they share the same bind group layout.
-        let diffuse_bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&my_tex.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&my_tex.sampler),
+    fn action(&mut self, action: Action) {
+        match action {
+            Action::Select if self.road.is_some() => self.grab_road_point(),
+            Action::Select if self.navmesh.is_some() => self.pick_path_point(),
+            Action::Select if self.painting.is_some() => {
+                self.renderer.paint_splat(self.cursor.0, self.cursor.1, &self.brush);
+            }
+            Action::Select => {
+                // picks the next point of the measurement, or selects a mesh.
+                let hit = self.renderer.pick(self.cursor.0, self.cursor.1);
+                if let Some(measurement) = &mut self.measurement {
+                    if let Some(hit) = hit {
+                        measurement.add_point(hit.position);
+                        self.show_measurement();
                     }
-                ],
-                label: Some("diffuse_bind_group"),
-            }
-        );
-        
-        let diffuse_bind_group2 = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&my_tex2.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&my_tex2.sampler),
+                } else {
+                    self.selected = hit.map(|hit| (hit.model, hit.mesh));
+                }
+            }
+            // moves with the cursor, see input().
+            Action::Pan | Action::Look => {}
+            Action::FlyMode => {
+                let mode = match self.camera_controller.mode() {
+                    camera::ControllerMode::Orbit => camera::ControllerMode::Fly,
+                    camera::ControllerMode::Fly => camera::ControllerMode::Orbit,
+                };
+                self.camera_controller.set_mode(mode);
+                log::info!("Camera mode {:?}", mode);
+            }
+            Action::Measure => {
+                self.measurement = match self.measurement.as_ref().map(|m| m.mode()) {
+                    None => Some(Measurement::new(MeasureMode::Distance)),
+                    Some(MeasureMode::Distance) => Some(Measurement::new(MeasureMode::Angle)),
+                    Some(MeasureMode::Angle) => None,
+                };
+                self.show_measurement();
+            }
+            Action::Clip => {
+                let axis = match self.clip_axis {
+                    None => Some(0),
+                    Some(axis) if axis < 2 => Some(axis + 1),
+                    Some(_) => None,
+                };
+                self.cut_scene(axis);
+            }
+            Action::Explode => {
+                self.exploded = !self.exploded;
+                let factor = if self.exploded { 1.0 } else { 0.0 };
+                for index in 0..self.renderer.models().len() {
+                    // the index is always valid here.
+                    let _ = self.renderer.set_exploded_view(index, factor);
+                }
+            }
+            Action::Hide => {
+                if let Some((model, mesh)) = self.selected {
+                    self.edit(Box::new(undo::SetMeshVisible::new(&self.renderer, model, mesh, false)));
+                    self.selected = None;
+                }
+            }
+            Action::Isolate => {
+                if let Some((model, mesh)) = self.selected {
+                    self.renderer.isolate_mesh(model, mesh);
+                }
+            }
+            Action::ShowAll => self.renderer.show_all_meshes(),
+            Action::SaveScene => {
+                if let Err(e) = self.renderer.save_scene(SCENE_FILE) {
+                    log::error!("Unable to save the scene: {:?}", e);
+                }
+            }
+            Action::LoadScene => {
+                if let Err(e) = self.renderer.load_scene(SCENE_FILE) {
+                    log::error!("Unable to load the scene: {:?}", e);
+                }
+            }
+            Action::StatsHud => {
+                self.stats_hud = match self.stats_hud {
+                    Some(_) => None,
+                    None => Some(StatsHud::new()),
+                };
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    overlay.layer(stats::OVERLAY_LAYER).clear();
+                }
+            }
+            // the cpu profiler, its tree below the frame statistics.
+            Action::Profiler => {
+                let enabled = !self.renderer.profiler().enabled();
+                self.renderer.profiler_mut().set_enabled(enabled);
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    overlay.layer(profiler::OVERLAY_LAYER).clear();
+                }
+            }
+            // model loads and other long jobs, see jobs.rs.
+            Action::CancelJobs => self.renderer.cancel_all_jobs(),
+            Action::FpsLimit => {
+                self.fps_limit = (self.fps_limit + 1) % FPS_LIMITS.len();
+                self.renderer.set_fps_limit(FPS_LIMITS[self.fps_limit]);
+            }
+            Action::DynamicResolution => {
+                if self.dynamic_resolution {
+                    self.renderer.disable_dynamic_resolution();
+                } else {
+                    self.renderer.enable_dynamic_resolution(ResolutionSettings::default());
+                }
+                self.dynamic_resolution = !self.dynamic_resolution;
+            }
+            Action::Supersampling => {
+                let current = SUPERSAMPLING.iter().position(|factor| *factor == self.renderer.supersampling()).unwrap_or(0);
+                self.renderer.set_supersampling(SUPERSAMPLING[(current + 1) % SUPERSAMPLING.len()]);
+            }
+            // side by side stereo on / off.
+            Action::Stereo => {
+                if self.renderer.stereo().is_some() {
+                    self.renderer.disable_stereo();
+                } else {
+                    self.renderer.enable_stereo(StereoSettings::default());
+                }
+            }
+            // half rate lighting on / off.
+            Action::ShadingRate => {
+                let rate = match self.renderer.shading_rate() {
+                    ShadingRate::Full => ShadingRate::Half,
+                    ShadingRate::Half => ShadingRate::Full,
+                };
+                self.renderer.set_shading_rate(rate);
+            }
+            // main -> orbit -> path -> minimap -> follow camera.
+            Action::NextCamera => self.switch_camera(),
+            // gpu capture of the next frame, see capture.rs.
+            Action::Capture => {
+                if let Err(e) = self.renderer.capture_frames(1) {
+                    log::error!("Unable to capture the frame: {:?}", e);
+                }
+            }
+            Action::NextBackend => self.next_backend(),
+            // png files of the render targets of the frame, see targets.rs.
+            Action::DumpTargets => {
+                let dumped = self.renderer.dump_targets(TARGETS_DIR, |_, written| match written {
+                    Ok(paths) => log::info!("Dumped the render targets: {:?}", paths),
+                    Err(e) => log::error!("Unable to dump the render targets: {:?}", e),
+                });
+                if let Err(e) = dumped {
+                    log::error!("Unable to dump the render targets: {:?}", e);
+                }
+            }
+            // saved on the job pool, the log tells when it's written.
+            Action::CaptureCubemap => {
+                if let Err(e) = self.capture_cubemap() {
+                    log::error!("Unable to capture the cube map: {:?}", e);
+                }
+            }
+            // saved on the job pool, the log tells when it's written.
+            Action::Poster => {
+                if let Err(e) = self.save_poster() {
+                    log::error!("Unable to save the poster: {:?}", e);
+                }
+            }
+            Action::Screenshot => {
+                let path = std::path::Path::new(SCREENSHOT_DIR)
+                    .join(format!("frame{:06}.png", self.renderer.frame_stats().frame));
+                let saved = std::fs::create_dir_all(SCREENSHOT_DIR)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|()| self.renderer.save_screenshot(&path));
+                if let Err(e) = saved {
+                    log::error!("Unable to take the screenshot: {:?}", e);
+                }
+            }
+            Action::Flashlight => {
+                let enabled = self.renderer.flashlight_enabled();
+                self.renderer.set_flashlight_enabled(!enabled);
+            }
+            // the sun from above, a little from the side, with its shadows.
+            Action::Sun => {
+                let sun = match self.renderer.sun() {
+                    Some(_) => None,
+                    None => Some(SUN_DIRECTION.into()),
+                };
+                self.renderer.set_sun(sun);
+            }
+            // a white point light where the camera is, Ctrl+Z takes it away.
+            Action::AddLight => {
+                let eye = self.renderer.camera().eye();
+                self.edit(Box::new(undo::AddLight::new(Light::new(eye, [1.0, 1.0, 1.0]))));
+            }
+            // borderless on the monitor the window is on.
+            Action::Fullscreen => {
+                let fullscreen = match self.renderer.window().fullscreen() {
+                    Some(_) => None,
+                    None => Some(""),
+                };
+                if let Err(e) = self.renderer.set_fullscreen(fullscreen) {
+                    log::error!("{:?}", e);
+                }
+            }
+            // fullscreen, on the monitor after the one the window is on.
+            Action::NextMonitor => {
+                let window = self.renderer.window();
+                if let Some(monitor) = monitors::next(window) {
+                    log::info!("Fullscreen on {}", monitor.name().unwrap_or_default());
+                    monitors::set_fullscreen(window, Some(monitor));
+                }
+            }
+            // on top of the window's scale factor, the overlay rounds the product.
+            Action::UiScale(step) => {
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    overlay.set_ui_scale(overlay.ui_scale() + step);
+                    log::info!("Ui scale {} ({}x)", overlay.ui_scale(), overlay.scale());
+                }
+                self.place_text_input();
+            }
+            Action::NextMaterial => {
+                // switch index.
+                self.bind_group_index = (self.bind_group_index + 1) % self.num_diffuse_materials;
+                self.edit(Box::new(undo::SetMaterialOverride::new(&self.renderer, Some(self.bind_group_index))));
+            }
+            Action::NextInstanceMaterial => {
+                // only the selected model: its own materials, then each diffuse material.
+                if let Some((model, _)) = self.selected {
+                    let to = match self.renderer.instance_material(model, None) {
+                        None => Some(0),
+                        Some(material) if material + 1 < self.num_diffuse_materials => Some(material + 1),
+                        Some(_) => None,
+                    };
+                    self.edit(Box::new(undo::SetInstanceMaterial::new(&self.renderer, model, None, to)));
+                }
+            }
+            // the selected mesh's material on the preview ball, or the diffuse
+            // material Space switches to without a selection. Again goes back.
+            Action::MaterialPreview => {
+                let subject = match (self.renderer.preview(), self.selected) {
+                    (Some(_), _) => None,
+                    (None, Some((model, mesh))) => Some(PreviewSubject::ModelMaterial {
+                        model,
+                        material: self.renderer.models()[model].meshes[mesh].material,
+                    }),
+                    (None, None) => Some(PreviewSubject::Material(self.bind_group_index)),
+                };
+                if let Err(e) = self.renderer.show_preview(subject) {
+                    log::error!("Unable to preview the material: {:?}", e);
+                }
+            }
+            Action::RenameMesh => {
+                if let Some((model, mesh)) = self.selected {
+                    let name = &self.renderer.models()[model].meshes[mesh].name;
+                    let field = TextInput::new(&self.tr("input.name"), name);
+                    self.open_text_input(field, TextTarget::MeshName(model, mesh));
+                }
+            }
+            Action::ScriptConsole => self.open_script_console(),
+            Action::Annotate => {
+                if let Some(hit) = self.renderer.pick(self.cursor.0, self.cursor.1) {
+                    let field = TextInput::new(&self.tr("input.annotation"), "");
+                    self.open_text_input(field, TextTarget::Annotation(hit.position));
+                }
+            }
+            Action::RemoveAnnotation => {
+                if let Some(id) = self.annotations.pick(self.cursor.0, self.cursor.1) {
+                    self.annotations.remove(id);
+                }
+            }
+            Action::EnterFpsLimit => {
+                let field = TextInput::number(&self.tr("input.fps_limit"), self.renderer.fps_limit().unwrap_or(0.0));
+                self.open_text_input(field, TextTarget::FpsLimit);
+            }
+            Action::RemoveModel => {
+                if let Some((model, _)) = self.selected.take() {
+                    self.edit(Box::new(undo::RemoveModel::new(model)));
+                }
+            }
+            Action::Duplicate => {
+                if let Some((model, mesh)) = self.selected {
+                    // a bit to the side, so the copy can be told apart.
+                    let shift = self.renderer.models()[model].bounds.radius().max(0.1) * 0.25;
+                    let copies = self.renderer.models().len();
+                    self.edit(Box::new(undo::DuplicateModel::new(model, [shift, 0.0, 0.0])));
+                    // the copy is added at the end.
+                    if self.renderer.models().len() > copies {
+                        self.selected = Some((copies, mesh));
+                    }
+                }
+            }
+            Action::Move => {
+                // grabs the selected mesh where the cursor points at the height of its center.
+                if let Some((model, mesh)) = self.selected {
+                    let from = self.renderer.models()[model].meshes[mesh].offset;
+                    let height = self.renderer.models()[model].meshes[mesh].bounds.center()[1] + from[1];
+                    if let Some(grabbed) = self.cursor_on_plane(height) {
+                        self.moving = Some(MeshDrag { model, mesh, from, grabbed });
+                    }
+                }
+            }
+            Action::DropToGround => {
+                if let Some((model, mesh)) = self.selected {
+                    let renderer = &self.renderer;
+                    let drop = snapping::drop_distance(renderer.models(), SceneNode::Mesh(model, mesh), |model, mesh| {
+                        renderer.mesh_visible(model, mesh)
+                    });
+                    match drop {
+                        Some(drop) => {
+                            let offset = renderer.models()[model].meshes[mesh].offset;
+                            let to = [offset[0], offset[1] - drop, offset[2]];
+                            self.edit(Box::new(undo::MoveMesh::new(&self.renderer, model, mesh, to)));
+                        }
+                        None => log::info!("No ground under the selected mesh."),
+                    }
+                }
+            }
+            Action::SnapGrid => {
+                self.snap_grid = (self.snap_grid + 1) % SNAP_GRIDS.len();
+                self.snap.grid = SNAP_GRIDS[self.snap_grid];
+                log::info!("Snapping to a grid of {}", self.snap.grid);
+            }
+            Action::PaintMode => {
+                match self.painting.take() {
+                    Some(model) => self.save_splat_maps(model),
+                    None => {
+                        if let Some((model, _)) = self.selected {
+                            self.add_splat_maps(model);
+                            self.painting = Some(model);
+                            // the override would hide the painted layers.
+                            self.renderer.set_material_override(None);
+                            log::info!("Painting layer {}", self.brush.layer + 1);
+                        }
+                    }
+                }
+            }
+            Action::BrushLayer(layer) => {
+                self.brush.layer = layer.min(splat::LAYERS - 1);
+                log::info!("Painting layer {}", self.brush.layer + 1);
+            }
+            Action::BrushSize(factor) => {
+                self.brush.radius = (self.brush.radius * factor).clamp(0.001, 0.5);
+                log::info!("Brush radius {}", self.brush.radius);
+            }
+            Action::BrushOpacity(factor) => {
+                self.brush.opacity = (self.brush.opacity * factor).clamp(0.01, 1.0);
+                log::info!("Brush opacity {}", self.brush.opacity);
+            }
+            Action::UvTiling(factor) => {
+                // how often the texture of the selected mesh repeats, e.g. on the terrain.
+                if let Some((model, mesh)) = self.selected {
+                    let material = self.renderer.models()[model].meshes[mesh].material;
+                    if let Some(mut uv_transform) = self.renderer.uv_transform(model, material) {
+                        uv_transform.scale = uv_transform.scale.map(|scale| (scale * factor).clamp(1.0 / 64.0, 1024.0));
+                        if let Err(e) = self.renderer.set_uv_transform(model, material, uv_transform) {
+                            log::error!("{:?}", e);
+                        }
+                        log::info!("Uv tiling {:?}", uv_transform.scale);
+                    }
+                }
+            }
+            Action::MoveSpeed(factor) => {
+                let settings = self.camera_controller.settings_mut();
+                settings.move_speed = (settings.move_speed * factor).clamp(0.01, 100.0);
+                settings.rotate_speed = (settings.rotate_speed * factor).clamp(0.01, 100.0);
+                log::info!("Camera speed {}", settings.move_speed);
+                if let Err(e) = settings.save(CONTROLS_FILE) {
+                    log::error!("{:?}", e);
+                }
+            }
+            Action::RoadMode => {
+                // a new road every time, the last one stays in the scene.
+                self.road = match self.road {
+                    Some(_) => None,
+                    None => Some(RoadTool { road: Road::new(RoadSettings::default()), model: None, dragging: None }),
+                };
+            }
+            Action::RemoveRoadPoint => {
+                if let Some(tool) = &mut self.road {
+                    let last = tool.road.points().len().saturating_sub(1);
+                    tool.road.remove_point(tool.dragging.take().unwrap_or(last));
+                    self.rebuild_road();
+                }
+            }
+            Action::NavMesh => {
+                self.nav_start = None;
+                self.navmesh = match self.navmesh.take() {
+                    Some(_) => None,
+                    None => {
+                        let renderer = &self.renderer;
+                        let navmesh = NavMesh::build(renderer.models(), &NavSettings::default(), |model, mesh| {
+                            renderer.mesh_visible(model, mesh)
+                        });
+                        log::info!("Navmesh: {} walkable cells in {} regions", navmesh.walkable_cells(), navmesh.regions());
+                        Some(navmesh)
+                    }
+                };
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    match &self.navmesh {
+                        Some(navmesh) => navmesh.draw(overlay, None),
+                        None => NavMesh::hide(overlay),
+                    }
+                }
+            }
+            Action::Agents => {
+                // more agents every time, on the navmesh of the scene as it is now.
+                let renderer = &self.renderer;
+                let navmesh = self.navmesh.clone().unwrap_or_else(|| {
+                    NavMesh::build(renderer.models(), &NavSettings::default(), |model, mesh| {
+                        renderer.mesh_visible(model, mesh)
+                    })
+                });
+                match self.renderer.plugin_mut::<Agents>() {
+                    Some(agents) => {
+                        agents.set_navmesh(navmesh);
+                        agents.spawn(AGENTS_PER_SPAWN);
                     }
-                ],
-                label: Some("diffuse_bind_group"),
+                    None => {
+                        let mut agents = Agents::new(navmesh);
+                        agents.spawn(AGENTS_PER_SPAWN);
+                        self.renderer.add_plugin(agents);
+                    }
+                }
+                if let Some(agents) = self.renderer.plugin::<Agents>() {
+                    log::info!("Agents: {}", agents.agents().len());
+                }
+            }
+            Action::SavePrefab => {
+                if let Some((model, _)) = self.selected {
+                    if let Err(e) = self.renderer.save_prefab(PREFAB_FILE, &[model]) {
+                        log::error!("Unable to save the prefab: {:?}", e);
+                    }
+                }
+            }
+            Action::PlacePrefab => {
+                // next to the ones placed before.
+                let bounds = self.renderer.models().iter()
+                    .fold(model::Bounds::empty(), |bounds, model| bounds.union(&model.bounds));
+                let step = if bounds.is_empty() { 1.0 } else { bounds.radius().max(1.0) * 0.5 };
+                let placed = self.renderer.prefab_instances().iter()
+                    .filter(|instance| instance.path == std::path::Path::new(PREFAB_FILE))
+                    .count();
+                let offset = [step * (placed + 1) as f32, 0.0, 0.0];
+                if let Err(e) = self.renderer.instantiate_prefab(PREFAB_FILE, offset) {
+                    log::error!("Unable to place the prefab: {:?}", e);
+                }
+            }
+            Action::UpdatePrefab => {
+                if let Err(e) = self.renderer.update_prefab(PREFAB_FILE) {
+                    log::error!("Unable to update the prefab: {:?}", e);
+                }
+            }
+            Action::Undo => {
+                // the indices of the selection may not fit anymore.
+                self.selected = None;
+                if let Err(e) = self.undo.undo(&mut self.renderer) {
+                    log::error!("Unable to undo: {:?}", e);
+                }
+            }
+            Action::Redo => {
+                self.selected = None;
+                if let Err(e) = self.undo.redo(&mut self.renderer) {
+                    log::error!("Unable to redo: {:?}", e);
+                }
             }
-        );
+        }
+    }
 
-        let mut groups = std::vec::Vec::<wgpu::BindGroup>::new();
+    // where the ray through the cursor crosses the horizontal plane at `height`.
+    fn cursor_on_plane(&self, height: f32) -> Option<cgmath::Vector3<f32>> {
+        self.renderer
+            .cursor_on_plane(self.cursor.0, self.cursor.1, &Plane::horizontal(height))
+            .map(cgmath::Vector3::from)
+    }
 
-        groups.push(diffuse_bind_group);
-        groups.push(diffuse_bind_group2);
+    // the mesh follows the cursor, snapped to the grid.
+    fn drag_mesh(&mut self, drag: MeshDrag) {
+        if let Some(point) = self.cursor_on_plane(drag.grabbed.y) {
+            let moved = point - drag.grabbed;
+            let to = self.snap.position([drag.from[0] + moved.x, drag.from[1], drag.from[2] + moved.z]);
+            // the height stays, only x and z follow the cursor.
+            self.renderer.set_mesh_offset(drag.model, drag.mesh, [to[0], drag.from[1], to[2]]);
+        }
+    }
 
-        // camera
-        let camera = camera::Camera::new(&config);
+    // the drag ends as one edit, from where it started to where it's let go.
+    fn drop_mesh(&mut self, drag: MeshDrag) {
+        let to = self.renderer.models()[drag.model].meshes[drag.mesh].offset;
+        self.renderer.set_mesh_offset(drag.model, drag.mesh, drag.from);
+        if to != drag.from {
+            self.edit(Box::new(undo::MoveMesh::new(&self.renderer, drag.model, drag.mesh, to)));
+        }
+    }
 
-        let mut camera_config = camera::UniformBuffer::new();
-        camera_config.update_view_proj(&camera);
+    // road, dirt and grass layers for the materials of `model` that aren't splatted yet.
+    fn add_splat_maps(&mut self, model: usize) {
+        let res_dir = std::path::Path::new(env!("OUT_DIR")).join("res");
+        for material in 0..self.renderer.models()[model].materials.len() {
+            if self.renderer.splat(model, material).is_some() {
+                continue;
+            }
+            let (device, queue) = (self.renderer.device(), self.renderer.queue());
+            let layers = [
+                texture::Texture::from_bytes(include_bytes!("road01.png"), device, queue, "road layer"),
+                texture::Texture::from_bytes(include_bytes!("dirt01.png"), device, queue, "dirt layer"),
+                texture::Texture::load(device, queue, res_dir.join("grass.png")),
+            ];
+            let layers = match layers {
+                [Ok(road), Ok(dirt), Ok(grass)] => [road, dirt, grass],
+                [road, dirt, grass] => {
+                    for e in [road.err(), dirt.err(), grass.err()].iter().flatten() {
+                        log::error!("Unable to load a splat layer: {:?}", e);
+                    }
+                    return;
+                }
+            };
+            let path = splat_file(material);
+            let control = if std::path::Path::new(&path).exists() {
+                match SplatMap::load(device, queue, &path) {
+                    Ok(control) => control,
+                    Err(e) => {
+                        log::error!("{:?}", e);
+                        SplatMap::new(device, queue, SPLAT_SIZE, SPLAT_SIZE)
+                    }
+                }
+            } else {
+                SplatMap::new(device, queue, SPLAT_SIZE, SPLAT_SIZE)
+            };
+            let splat = SplatLayers::new(control, layers, SPLAT_TILING);
+            if let Err(e) = self.renderer.set_splat(model, material, Some(splat)) {
+                log::error!("Unable to splat the material: {:?}", e);
+            }
+        }
+    }
 
-        let camera_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Camera Buffer"),
-                contents: bytemuck::cast_slice(&[camera_config]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    fn save_splat_maps(&self, model: usize) {
+        let materials = self.renderer.models().get(model).map_or(0, |model| model.materials.len());
+        for material in 0..materials {
+            if let Some(splat) = self.renderer.splat(model, material) {
+                match splat.control.save(splat_file(material)) {
+                    Ok(()) => log::info!("Saved {}", splat_file(material)),
+                    Err(e) => log::error!("{:?}", e),
+                }
             }
-        );
+        }
+    }
 
-        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
+    // where the cursor points on the ground, the road itself isn't ground.
+    fn cursor_on_ground(&self) -> Option<[f32; 3]> {
+        let road = self.road.as_ref().and_then(|tool| tool.model);
+        self.renderer.cursor_on_ground(self.cursor.0, self.cursor.1, |model, _| Some(model) != road)
+    }
+
+    // a click next to a control point grabs it, anywhere else adds one.
+    fn grab_road_point(&mut self) {
+        let position = match self.cursor_on_ground() {
+            Some(position) => position,
+            None => return,
+        };
+        if let Some(tool) = &mut self.road {
+            let grab_distance = tool.road.settings.width;
+            tool.dragging = match tool.road.closest_point(position, grab_distance) {
+                Some(point) => Some(point),
+                None => {
+                    tool.road.add_point(position);
+                    Some(tool.road.points().len() - 1)
                 }
-            ],
-            label: Some("camera_bind_group_layout"),
-        });
+            };
+        }
+        self.rebuild_road();
+    }
+
+    fn drag_road_point(&mut self) {
+        let position = match self.cursor_on_ground() {
+            Some(position) => position,
+            None => return,
+        };
+        if let Some(RoadTool { road, dragging: Some(point), .. }) = &mut self.road {
+            road.move_point(*point, position);
+            self.rebuild_road();
+        }
+    }
 
-        let camera_bindgroup = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: camera_buffer.as_entire_binding(),
+    // Ctrl+F11: the six faces and a panorama, named after the frame.
+    fn capture_cubemap(&mut self) -> anyhow::Result<()> {
+        let name = format!("frame{:06}", self.renderer.frame_stats().frame);
+        self.renderer.capture_cubemap(move |renderer, cube| {
+            let cube = match cube {
+                Ok(cube) => cube,
+                Err(e) => {
+                    log::error!("Unable to capture the cube map: {:?}", e);
+                    return;
                 }
-            ],
-            label: Some("camera_bind_group"),
-        });
-/*
-        let light_config = light::UniformBuffer::new();
-
-        // We'll want to update our lights position, so we use COPY_DST
-        let light_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Light VB"),
-                contents: bytemuck::cast_slice(&[light_config]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            }
-        );
-
-        let light_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: None,
-        });
- 
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &light_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: light_buffer.as_entire_binding(),
-            }],
-            label: None,
-        });
-*/
-        // create shader
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Basic Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("basic_shader.wgsl").into()),
-        });
-        
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[
-                    &texture_bind_group_layout, 
-                    &camera_bind_group_layout
-                ],
-                push_constant_ranges: &[],
+            };
+            // the panorama is four faces wide, that takes a while.
+            renderer.spawn_job(JobPriority::Low, "cube map", move || -> anyhow::Result<Vec<std::path::PathBuf>> {
+                let mut paths = cube.save(CUBEMAP_DIR, &name)?;
+                let panorama = std::path::Path::new(CUBEMAP_DIR).join(format!("{}_panorama.png", name));
+                cube.to_equirectangular(cube.size * 4).save(&panorama)?;
+                paths.push(panorama);
+                Ok(paths)
+            }, |_, saved| match saved {
+                Ok(paths) => log::info!("Captured the cube map: {:?}", paths),
+                Err(e) => log::error!("Unable to save the cube map: {:?}", e),
             });
+        })
+    }
 
-        // TODO: ...
+    fn save_poster(&mut self) -> anyhow::Result<()> {
+        let size = self.renderer.size();
+        std::fs::create_dir_all(POSTER_DIR)?;
+        let path = std::path::Path::new(POSTER_DIR)
+            .join(format!("frame{:06}_poster.png", self.renderer.frame_stats().frame));
+        self.renderer.render_tiled(size.width * POSTER_SCALE, size.height * POSTER_SCALE, move |renderer, poster| {
+            let poster = match poster {
+                Ok(poster) => poster,
+                Err(e) => {
+                    log::error!("Unable to render the poster: {:?}", e);
+                    return;
+                }
+            };
+            // a 4x poster takes seconds to encode.
+            renderer.spawn_job(JobPriority::Low, "poster", move || poster.save(&path).map(|()| path), |_, saved| {
+                match saved {
+                    Ok(path) => log::info!("Saved the poster {:?}", path),
+                    Err(e) => log::error!("Unable to save the poster: {:?}", e),
+                }
+            });
+        })
+    }
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            // vertex shader stage
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "main", // 1.
-                buffers: &[vertex::MVertex::desc()], // 2.
-            },
-            // fragment shader stage
-            fragment: Some(wgpu::FragmentState { // 3.
-                module: &shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState { // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            // rasterizer stage
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-
-                cull_mode: Some(wgpu::Face::Back),
-//                cull_mode: None,
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1, // 2.
-                mask: !0, // 3.
-                alpha_to_coverage_enabled: false, // 4.
+    // F6: the next backend with an adapter (e.g. Vulkan -> GL), the
+    // materials and the road of the viewer are created again on it.
+    fn next_backend(&mut self) {
+        // the splat maps live on the gpu only until the paint mode is left.
+        if self.painting.is_some() {
+            log::warn!("Leave the paint mode before switching the backend.");
+            return;
+        }
+        let backends = Renderer::available_backends();
+        let current = self.renderer.adapter_info().backend;
+        let next = match backends.iter().position(|backend| *backend == current) {
+            Some(index) => backends[(index + 1) % backends.len()],
+            None => match backends.first() {
+                Some(backend) => *backend,
+                None => return,
             },
+        };
+        if next == current {
+            log::info!("{:?} is the only backend with an adapter.", current);
+            return;
+        }
+
+        let material_override = self.renderer.material_override();
+        if let Err(e) = self.renderer.switch_backend(wgpu::Backends::from(next)) {
+            log::error!("Unable to switch to {:?}: {:?}", next, e);
+            return;
+        }
+        log::info!("Switched to {:?}: {}", next, self.renderer.adapter_info().name);
+        // removed models in the history belong to the old device.
+        self.undo.clear();
+
+        match add_diffuse_materials(&mut self.renderer) {
+            Ok(_) => self.renderer.set_material_override(material_override),
+            Err(e) => log::error!("Unable to load the materials: {:?}", e),
+        }
+        if let Some(index) = self.road.as_ref().and_then(|tool| tool.model) {
+            let replaced = new_road_model(&self.renderer).and_then(|road| self.renderer.replace_model(index, road));
+            if let Err(e) = replaced {
+                log::error!("Unable to restore the road: {:?}", e);
+            }
+            self.rebuild_road();
+        }
+    }
+
+    // lays the road onto the terrain again, after its points changed.
+    fn rebuild_road(&mut self) {
+        let tool = match &mut self.road {
+            Some(tool) => tool,
+            None => return,
+        };
+        let road_model = tool.model;
+        let renderer = &self.renderer;
+        let mesh = tool.road.mesh(|x, z| {
+            road::ground_height(renderer.models(), x, z, |model, mesh| {
+                Some(model) != road_model && renderer.mesh_visible(model, mesh)
+            })
         });
-/*
-        const VERTICES: &[Vertex] = &[
-            Vertex { position: [-0.5, 0.5, 1.0], color: [1.0, 0.0, 0.0] },
-            Vertex { position: [-1.0, -0.5, 1.0], color: [0.0, 0.0, 1.0] },
-            Vertex { position: [0.0, -0.5, 1.0], color: [0.0, 1.0, 0.0] },
-        ];
-*/
-
-        const VERTICES_TEX: &[SVertex] = &[
-            SVertex { position: [-0.0868241, 0.49240386, 0.0], uv: [0.4131759, 0.99240386], }, // A
-            SVertex { position: [-0.49513406, 0.06958647, 0.0], uv: [0.0048659444, 0.56958646], }, // B
-            SVertex { position: [-0.21918549, -0.44939706, 0.0], uv: [0.28081453, 0.050602943], }, // C
-            SVertex { position: [0.35966998, -0.3473291, 0.0], uv: [0.85967, 0.15267089], }, // D
-            SVertex { position: [0.44147372, 0.2347359, 0.0], uv: [0.9414737, 0.7347359], }, // E
-        ];
-
-        const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, /* padding */ 0];
-
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES_TEX),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
 
-        let num_indices = INDICES.len() as u32;
+        let model = match (tool.model, &mesh) {
+            (Some(model), _) => model,
+            // nothing to draw yet.
+            (None, None) => return,
+            (None, Some(_)) => {
+                let road = match new_road_model(renderer) {
+                    Ok(road) => road,
+                    Err(e) => {
+                        log::error!("Unable to load the road texture: {:?}", e);
+                        return;
+                    }
+                };
+                let model = self.renderer.add_model("road", road);
+                tool.model = Some(model);
+                model
+            }
+        };
+        let meshes: Vec<model::MeshData> = mesh.into_iter().collect();
+        if let Err(e) = self.renderer.set_model_meshes(model, &meshes) {
+            log::error!("Unable to update the road: {:?}", e);
+        }
+    }
+
+    // the first click is the start of the path, the second the goal.
+    fn pick_path_point(&mut self) {
+        let hit = match self.renderer.pick(self.cursor.0, self.cursor.1) {
+            Some(hit) => hit.position,
+            None => return,
+        };
+        let navmesh = match &self.navmesh {
+            Some(navmesh) => navmesh,
+            None => return,
+        };
+        let path = match self.nav_start.take() {
+            Some(start) => {
+                let path = navmesh.find_path(start, hit);
+                if path.is_none() {
+                    log::info!("No path between these points.");
+                }
+                path
+            }
+            None => {
+                self.nav_start = Some(hit);
+                Some(vec![hit])
+            }
+        };
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            navmesh.draw(overlay, path.as_deref());
+        }
+    }
 
-        // camera controller
-        let camera_controller = camera::CameraController::new();
+    // runs an edit of the scene so it can be undone.
+    fn edit(&mut self, command: Box<dyn EditCommand>) {
+        let name = command.name().to_string();
+        if let Err(e) = self.undo.run(&mut self.renderer, command) {
+            log::error!("Unable to {}: {:?}", name.to_lowercase(), e);
+        }
+    }
 
-        let res_dir = std::path::Path::new( env!("OUT_DIR") ).join("res");
-        let obj_model = model::Model::load(
-            &device,
-            &queue,
-            &texture_bind_group_layout,
-            res_dir.join("terrain01.obj"),
-        ).expect("Unable to create Model.");
+    // the keys go to the field until it's submitted or cancelled.
+    fn open_text_input(&mut self, field: TextInput, target: TextTarget) {
+        self.camera_controller.release_all();
+        self.text_input = Some((field, target));
+        self.place_text_input();
+    }
 
-        Self {
-            surface,
-            device,
-            queue,
+    // the open text field at the bottom of the window, again when the
+    // window or the ui scale changed.
+    fn place_text_input(&mut self) {
+        let ([_, height], scale) = self.ui_size();
+        if let Some((field, _)) = &mut self.text_input {
+            field.show(self.renderer.window(), scale, 10.0, height - 30.0);
+            if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                field.draw(overlay);
+            }
+        }
+    }
 
-            camera,
-            camera_config: camera_config,
-            camera_buffer,
-            camera_bindgroup,
+    // routes keys and characters to the open text field, true if it took the event.
+    fn text_input_event(&mut self, event: &WindowEvent) -> bool {
+        if self.text_input.is_none()
+            || !matches!(event, WindowEvent::KeyboardInput { .. } | WindowEvent::ReceivedCharacter(_)) {
+            return false;
+        }
+        // releases still end the actions of the keys held when the field opened.
+        if let WindowEvent::KeyboardInput { input, .. } = event {
+            if input.state == ElementState::Released {
+                self.input_map.handle(event);
+            }
+        }
+        let ([_, height], scale) = self.ui_size();
+        let (field, target) = match &mut self.text_input {
+            Some(text_input) => text_input,
+            None => return false,
+        };
 
-            camera_controller,
+        match field.handle(event) {
+            Some(TextInputEvent::Changed) => {
+                field.show(self.renderer.window(), scale, 10.0, height - 30.0);
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    field.draw(overlay);
+                }
+            }
+            Some(TextInputEvent::Submitted(text)) => {
+                match *target {
+                    TextTarget::MeshName(model, mesh) => {
+                        let command = undo::RenameMesh::new(&self.renderer, model, mesh, &text);
+                        self.edit(Box::new(command));
+                    }
+                    TextTarget::FpsLimit => match field.value() {
+                        Some(fps) => self.renderer.set_fps_limit(Some(fps).filter(|fps| *fps > 0.0)),
+                        // stays open until it's a number.
+                        None => return true,
+                    },
+                    TextTarget::Annotation(position) => {
+                        if !text.trim().is_empty() {
+                            self.annotations.add(position, text.trim());
+                        }
+                    }
+                    TextTarget::Script => {
+                        // the error is in the console's output.
+                        let _ = self.scripts.run_line(&mut self.renderer, &text);
+                        self.open_script_console();
+                        return true;
+                    }
+                }
+                self.close_text_input();
+            }
+            Some(TextInputEvent::Cancelled) => {
+                if *target == TextTarget::Script {
+                    if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                        overlay.layer(SCRIPT_LAYER).clear();
+                    }
+                }
+                self.close_text_input();
+            }
+            None => {}
+        }
+        true
+    }
+
+    // the bounding box of the selected mesh, where it is now, in the
+    // selection color of the palette (see palette.rs).
+    fn draw_selection(&mut self) {
+        let bounds = self.selected.and_then(|(model, mesh)| {
+            let mesh = self.renderer.models().get(model)?.meshes.get(mesh)?;
+            let offset = |corner: [f32; 3]| [0, 1, 2].map(|axis| corner[axis] + mesh.offset[axis]);
+            Some((offset(mesh.bounds.min), offset(mesh.bounds.max)))
+        });
+        let overlay = match self.renderer.plugin_mut::<Overlay>() {
+            Some(overlay) => overlay,
+            None => return,
+        };
+        let color = overlay.palette().selection;
+        let layer = overlay.layer(SELECTION_LAYER);
+        layer.clear();
+        if let Some((min, max)) = bounds {
+            let corner = |index: usize| [0, 1, 2].map(|axis| if index & (1 << axis) == 0 { min[axis] } else { max[axis] });
+            // the 12 edges join the corners that differ in one axis.
+            for from in 0..8 {
+                for axis in 0..3 {
+                    if from & (1 << axis) == 0 {
+                        layer.line(corner(from), corner(from | (1 << axis)), color);
+                    }
+                }
+            }
+        }
+    }
+
+    // the overlay text `key` in the language of the overlay, see locale.rs.
+    fn tr(&self, key: &str) -> String {
+        match self.renderer.plugin::<Overlay>() {
+            Some(overlay) => overlay.strings().get(key).to_string(),
+            None => Strings::english().get(key).to_string(),
+        }
+    }
+
+    // the window in ui pixels of the overlay, and the scale to screen pixels.
+    fn ui_size(&self) -> ([f32; 2], f32) {
+        let size = self.renderer.window().inner_size();
+        match self.renderer.plugin::<Overlay>() {
+            Some(overlay) => (overlay.ui_size(size.width, size.height), overlay.scale()),
+            None => ([size.width as f32, size.height as f32], 1.0),
+        }
+    }
 
-            config,
-            size,
-            time: Instant::now(),
-            clear_color: wgpu::Color::BLACK,
+    // warns on the overlay while the gpu memory is running out and frees
+    // what the viewer holds on to once it's critical.
+    fn handle_renderer_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            if let RendererEvent::MemoryBudget { used, budget, level } = event {
+                if level == BudgetLevel::Critical {
+                    self.free_gpu_memory();
+                }
+                let ([_, height], _) = self.ui_size();
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    overlay.layer(MEMORY_LAYER).clear();
+                    if level != BudgetLevel::Normal {
+                        let text = overlay.strings().format("memory.warning", &[
+                            ("used", &(used >> 20).to_string()),
+                            ("budget", &(budget >> 20).to_string()),
+                        ]);
+                        let color = overlay.palette().warning;
+                        overlay.layer(MEMORY_LAYER).text(10.0, height - 20.0, &text, color);
+                    }
+                }
+            }
+        }
+    }
 
-            render_pipeline,
+    // models removed from the scene stay on the gpu for undo, they go first.
+    fn free_gpu_memory(&mut self) {
+        if self.undo.undo_name().is_some() || self.undo.redo_name().is_some() {
+            log::warn!("Clearing the undo history to free gpu memory.");
+            self.undo.clear();
+        }
+    }
 
-            vertex_buffer,
-            index_buffer,
+    // an empty line, with the output of the scripts above it.
+    fn open_script_console(&mut self) {
+        let ([_, height], _) = self.ui_size();
+        let output = self.scripts.output();
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            let layer = overlay.layer(SCRIPT_LAYER);
+            layer.clear();
+            layer.text(10.0, height - 40.0 - 15.0 * output.len() as f32, &output.join("\n"), [0.8, 0.8, 0.8, 1.0]);
+        }
+        let field = TextInput::new(&self.tr("input.script"), "");
+        self.open_text_input(field, TextTarget::Script);
+    }
 
-            num_indices,
+    fn close_text_input(&mut self) {
+        self.text_input = None;
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            TextInput::hide(overlay);
+        }
+    }
 
-            bind_group_index: 0 as usize,
+    // moves the camera sideways with the cursor, `dx`/`dy` in pixels.
+    fn pan(&mut self, dx: f32, dy: f32) {
+        use cgmath::InnerSpace;
+
+        let camera = self.renderer.camera_mut();
+        let (eye, target) = (camera.eye(), camera.target());
+        let forward = target - eye;
+        let right = forward.cross(cgmath::Vector3::unit_y()).normalize();
+        let up = right.cross(forward).normalize();
+        // the point looked at stays under the cursor, roughly.
+        let shift = (up * dy - right * dx) * forward.magnitude() * 0.002;
+        camera.look_at(eye + shift, target + shift);
+    }
 
-            texture_bind_group_layout,
-            diffuse_bind_groups: groups,
+    // capped cut through the middle of everything loaded.
+    fn cut_scene(&mut self, axis: Option<usize>) {
+        self.clip_axis = axis;
+        self.renderer.clear_clip_planes();
+        if let Some(axis) = axis {
+            let bounds = self.renderer.models().iter()
+                .fold(model::Bounds::empty(), |bounds, model| bounds.union(&model.bounds));
+            if bounds.is_empty() {
+                return;
+            }
+            let mut normal = [0.0; 3];
+            normal[axis] = -1.0;
+            let plane = ClipPlane::through(bounds.center(), normal).with_cap(true);
+            if let Err(e) = self.renderer.add_clip_plane(plane) {
+                log::error!("Unable to add clipping plane: {:?}", e);
+            }
+        }
+    }
 
-            obj_model
+    fn show_measurement(&mut self) {
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            match &self.measurement {
+                Some(measurement) => measurement.draw(overlay),
+                None => overlay.layer(measure::OVERLAY_LAYER).clear(),
+            }
         }
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+        self.renderer.resize(new_size);
+        self.place_text_input();
+    }
+
+    // the window moved to a monitor with another dpi, the overlay takes the
+    // new scale factor right away so the text is placed with it.
+    fn rescale(&mut self, scale_factor: f64, new_size: winit::dpi::PhysicalSize<u32>) {
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            overlay.set_scale_factor(scale_factor);
         }
+        self.resize(new_size);
     }
     // has an event been processed?
     fn input(&mut self, event: &WindowEvent) -> bool {
+        self.renderer.profiler_mut().begin("input");
+        let processed = self.handle_input(event);
+        self.renderer.profiler_mut().end();
+        processed
+    }
+
+    fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        // typing into a text field, the hotkeys are off.
+        if self.text_input_event(event) {
+            return true;
+        }
+
+        let actions = self.input_map.handle(event);
+        for action in &actions {
+            match action {
+                ActionEvent::Pressed(action) => self.action(*action),
+                ActionEvent::Released(Action::Select) => {
+                    if let Some(tool) = &mut self.road {
+                        tool.dragging = None;
+                    }
+                }
+                ActionEvent::Released(Action::Move) => {
+                    if let Some(drag) = self.moving.take() {
+                        self.drop_mesh(drag);
+                    }
+                }
+                ActionEvent::Released(_) => {}
+            }
+        }
+
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                /*
-                self.clear_color = wgpu::Color {
-                    r: position.x as f64 / self.size.width as f64,
-                    g: position.y as f64 / self.size.height as f64,
-                    b: 1.0,
-                    a: 1.0,
+                let position = (position.x as f32, position.y as f32);
+                let (dx, dy) = self.camera_controller.mouse_delta(position.0 - self.cursor.0, position.1 - self.cursor.1);
+                if self.input_map.is_active(Action::Pan) {
+                    self.pan(dx, dy);
+                }
+                if self.input_map.is_active(Action::Look) {
+                    self.camera_controller.look(self.renderer.camera_mut(), dx, dy);
+                }
+                self.cursor = position;
+                if let Some(drag) = self.moving {
+                    self.drag_mesh(drag);
+                }
+                if self.road.is_some() && self.input_map.is_active(Action::Select) {
+                    self.drag_road_point();
+                }
+                if self.painting.is_some() && self.input_map.is_active(Action::Select) {
+                    self.renderer.paint_splat(position.0, position.1, &self.brush);
+                }
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } if self.renderer.active_camera() == "follow" => {
+                // the wheel moves the follow camera closer / away.
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
                 };
-                 */
+                let lines = self.camera_controller.scroll_delta(lines);
+                if let Some(follow) = &mut self.follow {
+                    let step = follow.settings.distance * 0.1;
+                    follow.zoom(lines * step);
+                }
                 true
             }
-            WindowEvent::KeyboardInput { device_id: _, input, ..} => {
-                // if space was pressed
-                if input.virtual_keycode.unwrap() == VirtualKeyCode::Space {
-                    // switch index.
-                    self.bind_group_index = (self.bind_group_index + 1) % self.diffuse_bind_groups.len();
-                    true
-                } else {
-                    if input.state == ElementState::Pressed {
-                        println!("Pressed!");
-                        self.camera_controller.process_keydown(input.virtual_keycode.unwrap() );
-                    } else if input.state == ElementState::Released {
-                        println!("Released!");
-                        self.camera_controller.process_keyup(input.virtual_keycode.unwrap() );
-                    }
-                    true      
+            WindowEvent::MouseWheel { delta, .. } => {
+                // zooms the camera the keys move, see CameraController::process_scroll().
+                self.camera_controller.process_scroll(delta);
+                true
+            }
+            WindowEvent::MouseInput { .. } => !actions.is_empty(),
+            WindowEvent::KeyboardInput { input, .. } => {
+                if !actions.is_empty() {
+                    return true;
+                }
+                // the rest moves the camera, keys without a code can't.
+                let key = match input.virtual_keycode {
+                    Some(key) => key,
+                    None => return false,
+                };
+                if input.state == ElementState::Pressed {
+                    self.camera_controller.process_keydown(key);
+                } else if input.state == ElementState::Released {
+                    self.camera_controller.process_keyup(key);
                 }
+                true
             }
-            
+
             _ => false,
         }
     }
 
-    fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
-        self.camera_config.update_view_proj(&self.camera);
-        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice( &[self.camera_config] ));
+    // the time since the last call, for update().
+    fn time_step(&mut self) -> std::time::Duration {
+        let now = std::time::Instant::now();
+        let dt = now - self.last_update;
+        self.last_update = now;
+        dt.min(MAX_TIME_STEP)
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {    
-        // get current texture will wait for surface to provide a new SurfaceTexture
-        let output = self.surface.get_current_texture()?;
-
-        let view =
-            output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+    fn update(&mut self, dt: std::time::Duration) {
+        self.renderer.profiler_mut().begin("update");
+        // the plugins (agents, ...) move by the same step.
+        self.renderer.set_time_step(dt);
+        match self.renderer.active_camera() {
+            "path" => {
+                if let Some((path, start)) = &self.camera_path {
+                    let t = (start.elapsed().as_secs_f32() / CAMERA_PATH_SECONDS).fract();
+                    let (eye, target) = path.at(t);
+                    let size = self.renderer.window().inner_size();
+                    let mut camera = camera::Camera::looking_at(eye, target, size.width as f32 / size.height.max(1) as f32);
+                    camera.set_clip_planes(0.1, path.far_plane());
+                    self.renderer.set_camera(camera);
+                }
+            }
+            // the minimap doesn't move.
+            "minimap" => (),
+            "follow" => {
+                if let Some(follow) = &mut self.follow {
+                    if let Some((eye, target)) = follow.update(self.renderer.models(), dt.as_secs_f32()) {
+                        self.renderer.camera_mut().look_at(eye, target);
+                    }
+                }
+            }
+            _ => self.camera_controller.update_camera(self.renderer.camera_mut(), dt),
+        }
+        if let Some(sync) = &mut self.sync {
+            sync.update(&mut self.renderer);
+        }
+        if let Some(remote) = &mut self.remote {
+            remote.update(&mut self.renderer);
+        }
+        self.scripts.update(&mut self.renderer, dt.as_secs_f32());
+        if let Some(logic) = &mut self.logic {
+            logic.update(&mut self.renderer, dt.as_secs_f32());
+        }
+        self.renderer.profiler_mut().end();
+    }
 
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if let Some(logic) = &mut self.logic {
+            logic.render(&mut self.renderer);
+        }
+        self.annotations.update(&self.renderer);
+        self.draw_selection();
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            self.annotations.draw(overlay);
+        }
+        self.renderer.render_frame()?;
+        self.handle_renderer_events();
+
+        if let Some(hud) = &mut self.stats_hud {
+            if hud.record(&self.renderer.frame_stats()) {
+                hud.set_pacing(self.renderer.frame_pacing());
+                if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                    // below the measurement.
+                    hud.draw(overlay, 10.0, 40.0);
+                }
+            }
+        }
+        if self.renderer.profiler().updated() {
+            let profiler = self.renderer.profiler().clone();
+            if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+                // below the frame statistics.
+                profiler.draw(overlay, 10.0, 200.0);
+            }
+        }
+        self.draw_job_progress();
+        Ok(())
+    }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view, // frame.view : what texture to save the colors to.
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        // background clear color
-                        load: wgpu::LoadOp::Clear( wgpu::Color {
-                      //      r: 1.0,
-                            r: self.time.elapsed().as_secs_f64().sin().abs(),
-                            g: 1.0,
-                            b: self.time.elapsed().as_secs_f64().cos().abs(),
-                        //    b: 1.0,
-                            a: 1.0,
-                        }),
-                        store: true, // whether to store render results in the view field above.
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
+    // a bar for every running job at the top right, once more when the
+    // last one is done to clear them.
+    fn draw_job_progress(&mut self) {
+        let mut jobs = self.renderer.job_progress();
+        if jobs.is_empty() && !self.job_progress_shown {
+            return;
+        }
+        self.job_progress_shown = !jobs.is_empty();
+        jobs.sort_by(|a, b| a.name.cmp(&b.name));
+        let ([width, _], _) = self.ui_size();
+        if let Some(overlay) = self.renderer.plugin_mut::<Overlay>() {
+            jobs::draw_progress(&jobs, overlay, width - jobs::PROGRESS_WIDTH - 10.0, 10.0);
+        }
+    }
+}
 
-            // set rendering pipeline created in new()
-            render_pass.set_pipeline(&self.render_pipeline);
+fn main() {
+    env_logger::init();
 
-            render_pass.set_bind_group( 0, self.diffuse_bind_groups.get(self.bind_group_index).unwrap(), &[] );
-            render_pass.set_bind_group(1, &self.camera_bindgroup, &[]);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::is_command(&args) {
+        if let Err(e) = cli::run(&args) {
+            eprintln!("error: {:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-            use model::DrawModel;
+    let session = match cli::input_session(&args) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("error: {:?}\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
+    let (mut recorder, mut replay, headless) = match session {
+        cli::InputSession::Live => (None, None, false),
+        cli::InputSession::Record(path) => (Some((InputRecorder::new(), path)), None, false),
+        cli::InputSession::Replay { path, headless } => match InputReplay::load(&path) {
+            Ok(replay) => (None, Some(replay), headless),
+            Err(e) => {
+                eprintln!("error: {:?}", e);
+                std::process::exit(1);
+            }
+        },
+    };
+    // frame times of the replay, summed up at the end.
+    let mut replay_times = Vec::new();
+    let benchmark_options = match cli::benchmark_options(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {:?}\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
 
-            render_pass.draw_mesh(&self.obj_model.meshes[0]);
-//            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-//            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-//            render_pass.draw(0..self.num_vertices, 0..1);
-//            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
-//            render_pass.draw(0..3, 0..1); // 3 vertices, once instance.
+    let turntable_options = match cli::turntable_options(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {:?}\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
 
-        } // -->
-            // need to drop value _render_pass, since begin_render_pass borrows mutably and
-            // we need to call encoder.finish()
+    // the window and panels of the last session, not for replays,
+    // benchmarks and turntables, which bring their own window size.
+    let restore_window = replay.is_none() && benchmark_options.is_none() && turntable_options.is_none();
+    let mut window_state = if restore_window { WindowState::load_or_default() } else { WindowState::default() };
 
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once( encoder.finish() ));
-        output.present();
+    let event_loop = EventLoop::new();
+    let mut builder = RendererBuilder::new();
+    if let Some([width, height]) = window_state.size {
+        builder = builder.size(width, height);
+    }
+    // --monitor and --fullscreen win over where the window was.
+    if let Some(monitor) = cli::monitor(&args) {
+        builder = builder.monitor(&monitor);
+        window_state.position = None;
+        window_state.monitor = None;
+    }
+    if cli::fullscreen(&args) {
+        builder = builder.fullscreen(true);
+        window_state.fullscreen = false;
+        window_state.maximized = false;
+    }
+    match cli::supersampling(&args) {
+        Ok(Some(factor)) => builder = builder.supersampling(factor),
+        Ok(None) => {}
+        Err(e) => log::error!("{:?}", e),
+    }
+    match cli::frames_in_flight(&args) {
+        Ok(Some(count)) => builder = builder.frames_in_flight(count),
+        Ok(None) => {}
+        Err(e) => log::error!("{:?}", e),
+    }
+    if cli::transparent(&args) {
+        // only the gl backend keeps the alpha of the surface.
+        builder = builder.transparent(true).backends(wgpu::Backends::GL);
+    }
+    let renderer = builder
+        .title("sneesh-x graphics")
+        .visible(!headless && turntable_options.is_none())
+        .auto_clip_planes(true)
+        // the benchmark measures the frames, not the display.
+        .vsync(benchmark_options.is_none() && turntable_options.is_none())
+        .build(&event_loop)
+        .expect("Unable to create Renderer.");
+    if let Some(options) = turntable_options {
+        run_turntable(event_loop, renderer, options);
+    }
 
-        Ok(())
+    if restore_window {
+        window_state.apply(renderer.window());
     }
-}
 
-fn main() {
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    window.set_title("sneesh-x graphics");
+    // started without models, the ones of the last session are opened again.
+    let mut files = cli::view_files(&args);
+    if files.models.is_empty() {
+        files.models = window_state.models.clone();
+    }
+    for path in files.models.iter().rev() {
+        window_state.add_model(path);
+    }
+    let mut state = State::new(renderer, files);
+    if window_state.stats_hud {
+        state.stats_hud = Some(StatsHud::new());
+    }
+    // --locale is kept for the next start.
+    if let Some(locale) = cli::locale(&args) {
+        window_state.locale = Some(locale);
+    }
+    let strings = match window_state.locale.clone().or_else(locale::system_locale) {
+        Some(locale) if locale.ends_with(".lang") => Strings::load(&locale).unwrap_or_else(|e| {
+            log::error!("{:?}", e);
+            Strings::english()
+        }),
+        Some(locale) => Strings::for_locale(&locale),
+        None => Strings::english(),
+    };
+    // --palette as well.
+    if let Some(palette) = cli::palette(&args) {
+        window_state.palette = Some(palette);
+    }
+    let palette = window_state.palette.as_deref().map_or_else(DebugPalette::default, |name| {
+        DebugPalette::preset(name).unwrap_or_else(|| {
+            log::error!("Unknown palette {:?}, the palettes are {}.", name, palette::PRESETS.join(", "));
+            DebugPalette::default()
+        })
+    });
+    // and --ui-scale.
+    match cli::ui_scale(&args) {
+        Ok(Some(ui_scale)) => window_state.ui_scale = Some(ui_scale),
+        Ok(None) => {}
+        Err(e) => log::error!("{:?}", e),
+    }
+    if let Some(overlay) = state.renderer.plugin_mut::<Overlay>() {
+        overlay.set_strings(strings);
+        overlay.set_palette(palette);
+        if let Some(ui_scale) = window_state.ui_scale {
+            overlay.set_ui_scale(ui_scale);
+        }
+    }
+    let sync = cli::sync_role(&args).and_then(|role| match role {
+        Some(cli::SyncRole::Host(address)) => SyncSession::host(&address).map(Some),
+        Some(cli::SyncRole::Join(address)) => SyncSession::join(&address).map(Some),
+        None => Ok(None),
+    });
+    state.sync = match sync {
+        Ok(sync) => sync,
+        Err(e) => {
+            eprintln!("error: {:?}\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
+    for library in cli::material_libraries(&args) {
+        if let Err(e) = state.renderer.load_material_library(&library) {
+            eprintln!("error: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+    for script in cli::scripts(&args) {
+        if let Err(e) = state.scripts.run_file(&mut state.renderer, &script) {
+            eprintln!("error: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = cli::logic_library(&args) {
+        match GameLogic::load(&mut state.renderer, &path) {
+            Ok(logic) => state.logic = Some(logic),
+            Err(e) => {
+                eprintln!("error: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let remote = cli::remote_address(&args).and_then(|address| address.map(|address| RemoteControl::listen(&address)).transpose());
+    state.remote = match remote {
+        Ok(remote) => remote,
+        Err(e) => {
+            eprintln!("error: {:?}\n{}", e, cli::USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    // State::new uses async code, so we're going to wait for it to finish
-    let mut state = pollster::block_on( State::new(&window) );
+    let mut benchmark = benchmark_options.map(|options| {
+        if let Err(e) = load_benchmark_scene(&mut state.renderer, &options.scene) {
+            eprintln!("error: {:?}", e);
+            std::process::exit(1);
+        }
+        let bounds = state.renderer.models().iter()
+            .fold(model::Bounds::empty(), |bounds, model| bounds.union(&model.bounds));
+        let benchmark = Benchmark::new(std::time::Duration::from_secs_f32(options.seconds));
+        (benchmark, CameraPath::orbit(&bounds), options)
+    });
 
     event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent { ref event, window_id } => {
-            if window_id == window.id() {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            state.resize(**new_inner_size);
-                        }
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        _ => {}
+        Event::WindowEvent { ref event, window_id } if window_id == state.renderer.window().id() => {
+            if let Some((recorder, _)) = &mut recorder {
+                recorder.record_window_event(event);
+            }
+            // while replaying or benchmarking, the user input doesn't reach the viewer.
+            if replay.is_some() || benchmark.is_some() || !state.input(event) {
+                match event {
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
                     }
-                }    
+                    // dragged onto another monitor, see monitors.rs.
+                    WindowEvent::Moved(_) => state.renderer.fit_surface(),
+                    WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+                        // new_inner_size is &&mut so we have to dereference it twice
+                        state.rescale(*scale_factor, **new_inner_size);
+                    }
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    _ => {}
+                }
+            }
+        }
+        Event::DeviceEvent { ref event, .. } => {
+            if let Some((recorder, _)) = &mut recorder {
+                recorder.record_device_event(event);
             }
         }
         Event::RedrawRequested(_) => {
-            state.update();
+            if let Some(replay) = &mut replay {
+                for recorded in replay.next_frame() {
+                    match recorded.event.to_window_event() {
+                        // the window follows the recorded size, the resize comes back as event.
+                        Some(WindowEvent::Resized(size)) => state.renderer.window().set_inner_size(size),
+                        Some(event) => {
+                            state.input(&event);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            match &benchmark {
+                Some((benchmark, path, _)) => {
+                    let (eye, target) = path.at(benchmark.progress());
+                    let size = state.renderer.size();
+                    let mut camera = camera::Camera::looking_at(eye, target, size.width as f32 / size.height.max(1) as f32);
+                    camera.set_clip_planes(0.1, path.far_plane());
+                    state.renderer.set_camera(camera);
+                }
+                None => {
+                    let dt = state.time_step();
+                    // the replay moves like the recording did, whatever the frame rate.
+                    let dt = match &replay {
+                        Some(replay) => replay.frame_time().unwrap_or(REPLAY_TIME_STEP),
+                        None => dt,
+                    };
+                    if let Some((recorder, _)) = &mut recorder {
+                        recorder.record_frame_time(dt);
+                    }
+                    state.update(dt);
+                }
+            }
             match state.render() {
                 Ok(_) => {}
                 // Reconfigure the surface if lost
-                Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                // The system is out of memory, we should probably quit
-                Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                Err(wgpu::SurfaceError::Lost) => state.resize(state.renderer.size()),
+                // The system is out of memory, free what we can and keep going
+                Err(wgpu::SurfaceError::OutOfMemory) => {
+                    log::error!("Out of gpu memory, skipped the frame.");
+                    state.free_gpu_memory();
+                }
                 // All other errors (Outdated, Timeout) should be resolved by the next frame
                 Err(e) => eprintln!("{:?}", e),
             }
+            if let Some((recorder, _)) = &mut recorder {
+                recorder.next_frame();
+            }
+            if let Some((benchmark, _, options)) = &mut benchmark {
+                benchmark.record(&state.renderer.frame_stats());
+                if benchmark.is_finished() {
+                    let report = benchmark.report(
+                        &options.scene.display().to_string(),
+                        state.renderer.estimated_gpu_memory(),
+                        &state.renderer.adapter_info().name,
+                    );
+                    match report.save(&options.out) {
+                        Ok(()) => println!("{} frames, {:.1} fps, {:.2} ms average, {:.2} ms p99 -> {}",
+                            report.frames, report.fps, report.frame_ms_avg, report.frame_ms_p99, options.out.display()),
+                        Err(e) => eprintln!("error: {:?}", e),
+                    }
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            if let Some(replay) = &replay {
+                replay_times.push(state.renderer.frame_stats().frame_time);
+                if replay.is_finished() {
+                    print_replay_summary(&replay_times);
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
         },
         Event::MainEventsCleared => {
+            // also while no frames are drawn, e.g. minimized.
+            state.renderer.poll_completed_jobs();
             // RedrawRequested will only trigger once, unless we manually
             // request it.
-            window.request_redraw();
+            state.renderer.window().request_redraw();
         },
+        Event::LoopDestroyed => {
+            // the library gets to clean up while the renderer is still there.
+            if let Some(logic) = &mut state.logic {
+                logic.unload(&mut state.renderer);
+            }
+            if let Some((recorder, path)) = &recorder {
+                match recorder.save(path) {
+                    Ok(()) => println!("recorded {} frames into {}", recorder.num_frames(), path.display()),
+                    Err(e) => eprintln!("error: {:?}", e),
+                }
+            }
+            if restore_window {
+                window_state.capture(state.renderer.window());
+                window_state.stats_hud = state.stats_hud.is_some();
+                if let Some(overlay) = state.renderer.plugin::<Overlay>() {
+                    window_state.ui_scale = Some(overlay.ui_scale());
+                }
+                if let Some(path) = WindowState::path() {
+                    if let Err(e) = window_state.save(&path) {
+                        log::error!("{:?}", e);
+                    }
+                }
+            }
+        }
         _ => {}
     });
 }
+
+// the road and gras materials swapped in for the terrain with space,
+// returns the index of the first one.
+fn add_diffuse_materials(renderer: &mut Renderer) -> anyhow::Result<usize> {
+    let bytes_road = include_bytes!("road01.png");
+    let bytes_gras = include_bytes!("dirt01.png");
+
+    // include_bytes loads a file.
+    let my_tex = texture::Texture::from_bytes(bytes_road, renderer.device(), renderer.queue(), "road texture")?;
+    let my_tex2 = texture::Texture::from_bytes(bytes_gras, renderer.device(), renderer.queue(), "gras texture")?;
+
+    // both textures share the renderer's material layout,
+    // so they can be swapped in for the terrain material.
+    let road = model::Material::new(renderer.device(), renderer.texture_bind_group_layout(), "road", my_tex);
+    let gras = model::Material::new(renderer.device(), renderer.texture_bind_group_layout(), "gras", my_tex2);
+
+    let first = renderer.add_material(road);
+    renderer.add_material(gras);
+    Ok(first)
+}
+
+// the model a road is drawn as, without meshes until it has two points.
+fn new_road_model(renderer: &Renderer) -> anyhow::Result<model::Model> {
+    let texture = texture::Texture::from_bytes(include_bytes!("road01.png"), renderer.device(), renderer.queue(), "road texture")?;
+    let material = model::Material::new(renderer.device(), renderer.texture_bind_group_layout(), "road", texture);
+    Ok(model::Model { meshes: Vec::new(), materials: vec![material], bounds: model::Bounds::empty(), instances: None })
+}
+
+fn load_benchmark_scene(renderer: &mut Renderer, path: &std::path::Path) -> anyhow::Result<()> {
+    if path.extension().and_then(|ext| ext.to_str()) == Some("scene") {
+        renderer.load_scene(path)?;
+    } else {
+        renderer.load_model(path)?;
+    }
+    Ok(())
+}
+
+// --turntable: only the scene or model, without the viewer (its terrain,
+// materials and overlay), on a neutral background. Exits when it's saved.
+fn run_turntable(event_loop: EventLoop<()>, mut renderer: Renderer, options: cli::TurntableOptions) -> ! {
+    if let Err(e) = load_benchmark_scene(&mut renderer, &options.scene) {
+        eprintln!("error: {:?}", e);
+        std::process::exit(1);
+    }
+    renderer.set_background(Some(turntable::BACKGROUND));
+    let bounds = renderer.models().iter()
+        .fold(model::Bounds::empty(), |bounds, model| bounds.union(&model.bounds));
+    let mut turntable = Turntable::new(&bounds, options.settings);
+    // the frames come back from render_tiled() one by one, in order.
+    let (frames, drawn) = std::sync::mpsc::channel();
+    let mut drawing = false;
+
+    // the window is hidden, it may never be asked to redraw.
+    event_loop.run(move |event, _, control_flow| {
+        if let Event::MainEventsCleared = event {
+            let (width, height) = turntable.size();
+            if !drawing {
+                renderer.set_camera(turntable.camera());
+                // the uploads of the frame, the turntable frame is drawn on its own.
+                if let Err(e) = renderer.render_frame() {
+                    log::warn!("{:?}", e);
+                }
+                let frames = frames.clone();
+                if let Err(e) = renderer.render_tiled(width, height, move |_, frame| {
+                    let _ = frames.send(frame);
+                }) {
+                    eprintln!("error: {:?}", e);
+                    std::process::exit(1);
+                }
+                drawing = true;
+            }
+            renderer.poll_completed_jobs();
+            match drawn.try_recv() {
+                Ok(Ok(frame)) => {
+                    turntable.record(frame);
+                    drawing = false;
+                }
+                Ok(Err(e)) => {
+                    eprintln!("error: {:?}", e);
+                    std::process::exit(1);
+                }
+                Err(_) => return,
+            }
+            if turntable.is_finished() {
+                match turntable.save(&options.out) {
+                    Ok(()) => println!("{} frames of {}x{} -> {}", turntable.num_frames(), width, height, options.out.display()),
+                    Err(e) => {
+                        eprintln!("error: {:?}", e);
+                        std::process::exit(1);
+                    }
+                }
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+    })
+}
+
+// the same replay on two versions should give comparable numbers.
+fn print_replay_summary(frame_times: &[std::time::Duration]) {
+    if frame_times.is_empty() {
+        return;
+    }
+    let total: std::time::Duration = frame_times.iter().sum();
+    let slowest = frame_times.iter().max().copied().unwrap_or_default();
+    println!("replayed {} frames in {:.2}s", frame_times.len(), total.as_secs_f64());
+    println!("  average frame: {:.2} ms", total.as_secs_f64() * 1000.0 / frame_times.len() as f64);
+    println!("  slowest frame: {:.2} ms", slowest.as_secs_f64() * 1000.0);
+}
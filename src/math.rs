@@ -0,0 +1,344 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+#[cfg(not(feature = "glam"))]
+use cgmath::{Deg, SquareMatrix};
+
+/*
+    View and projection math as plain functions, no gpu needed.
+
+    Camera, picking and the spot light cookies build their matrices with
+    these, so they can be checked in unit tests (cargo test) instead of
+    by looking at the window.
+
+    wgpu's clip space is left handed with depth 0..1 (OpenGL: -1..1),
+    cgmath builds OpenGL matrices, OPENGL_TO_WGPU_MATRIX converts them.
+
+    With the `glam` feature the matrices are built and inverted with glam
+    (simd) instead. The api stays cgmath either way, to_glam() / from_glam()
+    convert for code that wants to use glam itself.
+
+    usage:
+        let view_proj = math::perspective(45.0, math::aspect_ratio(width, height), 0.1, 100.0)
+            * math::look_at(eye, target, Vector3::unit_y());
+        let frustum = Frustum::from_matrix(view_proj);
+        let (origin, direction) = math::ray_from_screen(view_proj, x, y, width, height);
+*/
+
+/*
+    - cgmath crate is built for OpenGL's coordinate system.
+    - this matrix translates and scales our scene from
+     OpenGLs coordinate system to WGPU's.
+*/
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+// width / height, a minimized window (0 high) counts as 1 high.
+pub fn aspect_ratio(width: u32, height: u32) -> f32 {
+    width.max(1) as f32 / height.max(1) as f32
+}
+
+// world -> camera space, right handed, the camera looks down -z.
+#[cfg(not(feature = "glam"))]
+pub fn look_at(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Matrix4<f32> {
+    Matrix4::look_at_rh(eye, target, up)
+}
+
+#[cfg(feature = "glam")]
+pub fn look_at(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Matrix4<f32> {
+    from_glam(glam::Mat4::look_at_rh(point_to_glam(eye), point_to_glam(target), vector_to_glam(up)))
+}
+
+// camera space -> wgpu clip space, `fovy` in degrees.
+#[cfg(not(feature = "glam"))]
+pub fn perspective(fovy: f32, aspect: f32, znear: f32, zfar: f32) -> Matrix4<f32> {
+    OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(fovy), aspect, znear, zfar)
+}
+
+// glam's perspective is already in wgpu's depth range.
+#[cfg(feature = "glam")]
+pub fn perspective(fovy: f32, aspect: f32, znear: f32, zfar: f32) -> Matrix4<f32> {
+    from_glam(glam::Mat4::perspective_rh(fovy.to_radians(), aspect, znear, zfar))
+}
+
+// camera space -> wgpu clip space, the box `left`..`right`, `bottom`..`top`
+// and `znear`..`zfar` in front of the camera.
+#[cfg(not(feature = "glam"))]
+pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, znear: f32, zfar: f32) -> Matrix4<f32> {
+    OPENGL_TO_WGPU_MATRIX * cgmath::ortho(left, right, bottom, top, znear, zfar)
+}
+
+#[cfg(feature = "glam")]
+pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, znear: f32, zfar: f32) -> Matrix4<f32> {
+    from_glam(glam::Mat4::orthographic_rh(left, right, bottom, top, znear, zfar))
+}
+
+// None for matrices that can't be inverted.
+#[cfg(not(feature = "glam"))]
+pub fn invert(matrix: Matrix4<f32>) -> Option<Matrix4<f32>> {
+    matrix.invert()
+}
+
+#[cfg(feature = "glam")]
+pub fn invert(matrix: Matrix4<f32>) -> Option<Matrix4<f32>> {
+    let matrix = to_glam(matrix);
+    if matrix.determinant().abs() <= f32::EPSILON * f32::EPSILON {
+        return None;
+    }
+    Some(from_glam(matrix.inverse()))
+}
+
+// both store the columns one after the other.
+#[cfg(feature = "glam")]
+pub fn to_glam(matrix: Matrix4<f32>) -> glam::Mat4 {
+    glam::Mat4::from_cols_array_2d(&matrix.into())
+}
+
+#[cfg(feature = "glam")]
+pub fn from_glam(matrix: glam::Mat4) -> Matrix4<f32> {
+    matrix.to_cols_array_2d().into()
+}
+
+#[cfg(feature = "glam")]
+pub fn vector_to_glam(vector: Vector3<f32>) -> glam::Vec3 {
+    glam::Vec3::new(vector.x, vector.y, vector.z)
+}
+
+#[cfg(feature = "glam")]
+pub fn vector_from_glam(vector: glam::Vec3) -> Vector3<f32> {
+    Vector3::new(vector.x, vector.y, vector.z)
+}
+
+#[cfg(feature = "glam")]
+pub fn point_to_glam(point: Point3<f32>) -> glam::Vec3 {
+    glam::Vec3::new(point.x, point.y, point.z)
+}
+
+// window pixels (from the top left corner) -> normalized device coordinates.
+pub fn screen_to_ndc(x: f32, y: f32, width: u32, height: u32) -> (f32, f32) {
+    (
+        x / width.max(1) as f32 * 2.0 - 1.0,
+        1.0 - y / height.max(1) as f32 * 2.0,
+    )
+}
+
+// the world position of a point in normalized device coordinates,
+// `depth` 0 on the near plane, 1 on the far plane.
+pub fn unproject(view_proj: Matrix4<f32>, ndc_x: f32, ndc_y: f32, depth: f32) -> Option<Vector3<f32>> {
+    let world = invert(view_proj)? * Vector4::new(ndc_x, ndc_y, depth, 1.0);
+    Some(world.truncate() / world.w)
+}
+
+// ray from the near plane through the pixel x/y, the direction is normalized.
+// A matrix that can't be inverted gives a ray from the origin down -z.
+pub fn ray_from_screen(view_proj: Matrix4<f32>, x: f32, y: f32, width: u32, height: u32) -> (Vector3<f32>, Vector3<f32>) {
+    let (ndc_x, ndc_y) = screen_to_ndc(x, y, width, height);
+    match (unproject(view_proj, ndc_x, ndc_y, 0.0), unproject(view_proj, ndc_x, ndc_y, 1.0)) {
+        (Some(near), Some(far)) => (near, (far - near).normalize()),
+        _ => (Vector3::new(0.0, 0.0, 0.0), -Vector3::unit_z()),
+    }
+}
+
+// The six planes of a view_proj matrix, as (normal, distance) with the
+// normals pointing inside: a point p is inside a plane if
+// dot(normal, p) + distance >= 0.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    // left, right, bottom, top, near, far.
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Gribb / Hartmann, with wgpu's 0..1 depth for the near plane.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let row = |i: usize| view_proj.row(i);
+        let normalize = |plane: Vector4<f32>| plane / plane.truncate().magnitude();
+        Self {
+            planes: [
+                normalize(row(3) + row(0)),
+                normalize(row(3) - row(0)),
+                normalize(row(3) + row(1)),
+                normalize(row(3) - row(1)),
+                normalize(row(2)),
+                normalize(row(3) - row(2)),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+
+    // false only if the box is completely outside of one plane, boxes
+    // near the corners can pass without being inside.
+    pub fn intersects_box(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        self.planes.iter().all(|plane| box_inside(plane, min, max))
+    }
+
+    // intersects_box() without the near and far plane, e.g. to find out
+    // where those should go.
+    pub fn intersects_box_sides(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        self.planes[..4].iter().all(|plane| box_inside(plane, min, max))
+    }
+}
+
+// whether any part of the box is inside the plane.
+fn box_inside(plane: &Vector4<f32>, min: [f32; 3], max: [f32; 3]) -> bool {
+    // the corner furthest along the normal.
+    let corner = Vector3::new(
+        if plane.x >= 0.0 { max[0] } else { min[0] },
+        if plane.y >= 0.0 { max[1] } else { min[1] },
+        if plane.z >= 0.0 { max[2] } else { min[2] },
+    );
+    plane.truncate().dot(corner) + plane.w >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < EPSILON, "{} != {}", a, b);
+    }
+
+    fn clip(matrix: Matrix4<f32>, point: [f32; 3]) -> Vector3<f32> {
+        let clip = matrix * Vector4::new(point[0], point[1], point[2], 1.0);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn opengl_to_wgpu_maps_depth_to_zero_one() {
+        let gl = cgmath::perspective(cgmath::Deg(45.0), 1.0, 0.1, 100.0);
+        let wgpu = perspective(45.0, 1.0, 0.1, 100.0);
+        // OpenGL: -1 on the near plane, 1 on the far plane.
+        assert_close(clip(gl, [0.0, 0.0, -0.1]).z, -1.0);
+        assert_close(clip(wgpu, [0.0, 0.0, -0.1]).z, 0.0);
+        assert_close(clip(wgpu, [0.0, 0.0, -100.0]).z, 1.0);
+        // x and y don't change.
+        let gl_point = clip(gl, [0.3, -0.2, -5.0]);
+        let wgpu_point = clip(wgpu, [0.3, -0.2, -5.0]);
+        assert_close(gl_point.x, wgpu_point.x);
+        assert_close(gl_point.y, wgpu_point.y);
+    }
+
+    #[test]
+    fn right_handed_view_looks_down_minus_z() {
+        let view = look_at(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        let target = view * Vector4::new(0.0, 0.0, 0.0, 1.0);
+        assert_close(target.z, -5.0);
+        // +x in the world is to the right on screen.
+        let right = clip(perspective(45.0, 1.0, 0.1, 100.0) * view, [1.0, 0.0, 0.0]);
+        assert!(right.x > 0.0);
+    }
+
+    #[test]
+    fn aspect_ratio_widens_the_view() {
+        assert_close(aspect_ratio(1920, 1080), 16.0 / 9.0);
+        assert_close(aspect_ratio(800, 0), 800.0);
+        // the same point is further left / right on a narrower screen.
+        let wide = clip(perspective(45.0, 2.0, 0.1, 100.0), [1.0, 0.0, -5.0]);
+        let square = clip(perspective(45.0, 1.0, 0.1, 100.0), [1.0, 0.0, -5.0]);
+        assert_close(wide.x * 2.0, square.x);
+        assert_close(wide.y, square.y);
+    }
+
+    #[test]
+    fn screen_corners_to_ndc() {
+        assert_eq!(screen_to_ndc(0.0, 0.0, 200, 100), (-1.0, 1.0));
+        assert_eq!(screen_to_ndc(200.0, 100.0, 200, 100), (1.0, -1.0));
+        assert_eq!(screen_to_ndc(100.0, 50.0, 200, 100), (0.0, 0.0));
+    }
+
+    #[test]
+    fn unproject_inverts_the_projection() {
+        let view_proj = perspective(60.0, 1.5, 0.5, 50.0)
+            * look_at(Point3::new(3.0, 2.0, 4.0), Point3::new(0.0, 0.5, 0.0), Vector3::unit_y());
+        let point = [0.7, 1.1, -0.4];
+        let ndc = clip(view_proj, point);
+        let world = unproject(view_proj, ndc.x, ndc.y, ndc.z).unwrap();
+        for axis in 0..3 {
+            assert_close(world[axis], point[axis]);
+        }
+    }
+
+    #[test]
+    fn ray_through_the_center_hits_the_target() {
+        let eye = Point3::new(0.0, 3.0, 4.0);
+        let view_proj = perspective(45.0, aspect_ratio(640, 480), 0.1, 100.0)
+            * look_at(eye, Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        let (origin, direction) = ray_from_screen(view_proj, 320.0, 240.0, 640, 480);
+        assert_close(direction.magnitude(), 1.0);
+        let expected = (Vector3::new(0.0, 0.0, 0.0) - Vector3::new(eye.x, eye.y, eye.z)).normalize();
+        for axis in 0..3 {
+            assert_close(direction[axis], expected[axis]);
+        }
+        // the origin is on the near plane, 0.1 in front of the eye.
+        assert_close((origin - Vector3::new(eye.x, eye.y, eye.z)).magnitude(), 0.1);
+
+        // a pixel left of the center points left of the target.
+        let (_, left) = ray_from_screen(view_proj, 100.0, 240.0, 640, 480);
+        assert!(left.x < 0.0);
+    }
+
+    #[test]
+    fn frustum_planes() {
+        let view_proj = perspective(90.0, 1.0, 1.0, 10.0)
+            * look_at(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vector3::unit_y());
+        let frustum = Frustum::from_matrix(view_proj);
+
+        assert!(frustum.contains_point(Vector3::new(0.0, 0.0, -5.0)));
+        // behind the camera, in front of the near / behind the far plane.
+        assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, 5.0)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, -11.0)));
+        // 90 degrees: the sides are at 45 degrees.
+        assert!(frustum.contains_point(Vector3::new(4.9, 0.0, -5.0)));
+        assert!(!frustum.contains_point(Vector3::new(5.1, 0.0, -5.0)));
+        assert!(!frustum.contains_point(Vector3::new(0.0, -5.1, -5.0)));
+
+        // the near plane is at depth 0 (wgpu), not -1 (OpenGL).
+        let near = frustum.planes[4];
+        assert_close(near.truncate().dot(Vector3::new(0.0, 0.0, -1.0)) + near.w, 0.0);
+
+        assert!(frustum.intersects_box([-1.0, -1.0, -6.0], [1.0, 1.0, -4.0]));
+        // sticks into the frustum from the side.
+        assert!(frustum.intersects_box([4.0, -1.0, -6.0], [8.0, 1.0, -4.0]));
+        assert!(!frustum.intersects_box([7.0, -1.0, -6.0], [9.0, 1.0, -4.0]));
+        assert!(!frustum.intersects_box([-1.0, -1.0, 1.0], [1.0, 1.0, 2.0]));
+    }
+
+    // glam and cgmath + OPENGL_TO_WGPU_MATRIX have to agree.
+    #[cfg(feature = "glam")]
+    #[test]
+    fn glam_matches_cgmath() {
+        let matrices = [
+            (
+                perspective(50.0, 1.6, 0.2, 80.0),
+                OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(50.0), 1.6, 0.2, 80.0),
+            ),
+            (
+                look_at(Point3::new(1.0, 2.0, 3.0), Point3::new(0.0, 0.5, -1.0), Vector3::unit_y()),
+                Matrix4::look_at_rh(Point3::new(1.0, 2.0, 3.0), Point3::new(0.0, 0.5, -1.0), Vector3::unit_y()),
+            ),
+            (
+                orthographic(-4.0, 6.0, -2.0, 3.0, 0.5, 40.0),
+                OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-4.0, 6.0, -2.0, 3.0, 0.5, 40.0),
+            ),
+        ];
+        for (glam, cgmath) in matrices.iter() {
+            let glam: [[f32; 4]; 4] = (*glam).into();
+            let cgmath: [[f32; 4]; 4] = (*cgmath).into();
+            for (a, b) in glam.iter().flatten().zip(cgmath.iter().flatten()) {
+                assert_close(*a, *b);
+            }
+        }
+        let view_proj = matrices[0].1 * matrices[1].1;
+        assert_eq!(from_glam(to_glam(view_proj)), view_proj);
+        assert!(invert(Matrix4::from_scale(0.0)).is_none());
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+
+use crate::fetch;
+use crate::model::{Flipbook, LightmapMode, Material, TextureAnimation, UvTransform, VertexColors};
+use crate::texture::Texture;
+use crate::vat::VertexAnimation;
+use crate::wind::{WindMask, WindSway};
+
+/*
+    Material libraries: materials defined once in a file of their own and
+    used by name, by any model and any scene. Like an OBJ's .mtl, but not
+    tied to one model.
+
+    A small text format like the scene files:
+
+        # comment
+        material rusty_metal
+        diffuse textures/rust.png
+        lightmap baked/rust_light.png multiply
+        vertex_colors multiply
+        tint 1 0.8 0.8 1
+        uv_scale 8 8
+        uv_offset 0.5 0
+        uv_rotation 90
+        uv_scroll 0 0.25
+        flipbook 4 4 16 24
+        vertex_animation baked/flag.png 1024 30
+        vertex_animation_range -0.5 -0.5 -0.2 0.5 0.5 0.2
+        wind 0.05 1.2 uv
+
+    `material` starts a material, the lines after it belong to it:
+    `diffuse` is its texture (white without one), `lightmap` a baked
+    lightmap with how it's combined (multiply or add), `vertex_colors`
+    how the vertex colors go with the texture (ignore, multiply or
+    replace), `tint` an rgba the color is multiplied with and `uv_scale`,
+    `uv_offset` and `uv_rotation` (degrees) tile, move and turn the
+    texture on the mesh (see model::UvTransform). `uv_scroll` moves the
+    uvs by u v a second and `flipbook` plays the texture as a flipbook
+    (columns, rows, frames, fps), see model::TextureAnimation.
+    `vertex_animation` moves the vertices by a baked vertex animation
+    texture (path, vertices, fps) and `vertex_animation_range` gives the
+    min x y z and max x y z its offsets were remapped from, see vat.rs.
+    `wind` makes it sway in the wind like vegetation (strength,
+    frequency and the mask: uniform, vertex_color or uv), see wind.rs.
+    Those
+    settings are what the shader is switched by, there are no other
+    shader permutations in the tree. Relative paths are relative to the
+    folder of the library.
+
+    The renderer keeps the loaded libraries: their materials are added
+    like the ones of add_material() and can be drawn on any model or mesh
+    by name (Renderer::set_instance_material_named(), see overrides.rs).
+    Scene files load libraries with a `library` line and refer to their
+    materials by name. A library file that changes is loaded again while
+    the renderer runs, its materials keep their indices.
+
+    usage:
+        renderer.load_material_library("materials/props.matlib")?;
+        renderer.set_instance_material_named(model, None, "rusty_metal")?;
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDef {
+    pub name: String,
+    pub diffuse: Option<PathBuf>,
+    pub lightmap: Option<(PathBuf, LightmapMode)>,
+    pub vertex_colors: VertexColors,
+    pub tint: [f32; 4],
+    pub uv_transform: UvTransform,
+    pub animation: TextureAnimation,
+    // path, vertices and fps.
+    pub vertex_animation: Option<(PathBuf, u32, f32)>,
+    pub vertex_animation_range: Option<([f32; 3], [f32; 3])>,
+    pub wind: Option<WindSway>,
+}
+
+impl MaterialDef {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: None,
+            lightmap: None,
+            vertex_colors: VertexColors::Ignore,
+            tint: [1.0; 4],
+            uv_transform: UvTransform::default(),
+            animation: TextureAnimation::default(),
+            vertex_animation: None,
+            vertex_animation_range: None,
+            wind: None,
+        }
+    }
+
+    // loads the textures and puts the material on the gpu.
+    pub fn create(&self, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Result<Material> {
+        let load = |path: &Path| -> Result<Texture> {
+            let image = image::open(fetch::local(path)?).with_context(|| format!("Unable to load texture {:?}.", path))?;
+            let image = image::DynamicImage::ImageRgba8(image.to_rgba8());
+            Texture::from_image(device, queue, &image, path.to_str())
+        };
+        let diffuse = match &self.diffuse {
+            Some(path) => load(path)?,
+            None => Texture::from_color(device, queue, [255; 4], &self.name),
+        };
+        let mut material = Material::new(device, layout, &self.name, diffuse);
+        if let Some((path, mode)) = &self.lightmap {
+            material.set_lightmap(device, queue, layout, Some(load(path)?), *mode);
+        }
+        material.set_vertex_colors(queue, self.vertex_colors);
+        material.set_tint(queue, self.tint);
+        material.set_uv_transform(queue, self.uv_transform);
+        material.set_animation(queue, self.animation);
+        material.set_wind(queue, self.wind);
+        if let Some((path, vertices, fps)) = &self.vertex_animation {
+            let vat = VertexAnimation::load(device, queue, fetch::local(path)?, *vertices, *fps, self.vertex_animation_range)?;
+            material.set_vertex_animation(device, queue, layout, Some(vat));
+        }
+        Ok(material)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialLibrary {
+    pub path: PathBuf,
+    pub materials: Vec<MaterialDef>,
+    // of the file when it was loaded, to notice that it changed.
+    pub modified: Option<SystemTime>,
+}
+
+impl MaterialLibrary {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(fetch::local(path)?)
+            .with_context(|| format!("Unable to read material library {:?}.", path))?;
+        let mut library = Self::parse(&text)
+            .with_context(|| format!("Unable to parse material library {:?}.", path))?;
+        library.path = path.to_path_buf();
+        library.modified = modified(path);
+
+        // relative to the library, which may be a url.
+        for material in &mut library.materials {
+            if let Some(diffuse) = &mut material.diffuse {
+                *diffuse = fetch::resolve(path, diffuse);
+            }
+            if let Some((lightmap, _)) = &mut material.lightmap {
+                *lightmap = fetch::resolve(path, lightmap);
+            }
+            if let Some((vat, _, _)) = &mut material.vertex_animation {
+                *vat = fetch::resolve(path, vat);
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut library = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = match line.find(char::is_whitespace) {
+                Some(split) => (&line[..split], line[split..].trim()),
+                None => (line, ""),
+            };
+            if keyword == "material" {
+                if rest.is_empty() {
+                    bail!("Line {}: material without a name.", number + 1);
+                }
+                if library.get(rest).is_some() {
+                    bail!("Line {}: material {:?} is defined twice.", number + 1, rest);
+                }
+                library.materials.push(MaterialDef::new(rest));
+                continue;
+            }
+            let material = library.materials.last_mut()
+                .with_context(|| format!("Line {}: {} before the first material.", number + 1, keyword))?;
+            match keyword {
+                "diffuse" => {
+                    if rest.is_empty() {
+                        bail!("Line {}: diffuse without a path.", number + 1);
+                    }
+                    material.diffuse = Some(PathBuf::from(rest));
+                }
+                "lightmap" => {
+                    // the path may contain spaces, the mode is the last word.
+                    let (path, mode) = rest.rsplit_once(char::is_whitespace)
+                        .with_context(|| format!("Line {}: lightmap needs a path and multiply or add.", number + 1))?;
+                    let mode = match mode {
+                        "multiply" => LightmapMode::Multiply,
+                        "add" => LightmapMode::Add,
+                        _ => bail!("Line {}: {:?} is no lightmap mode, multiply or add.", number + 1, mode),
+                    };
+                    material.lightmap = Some((PathBuf::from(path.trim()), mode));
+                }
+                "vertex_colors" => {
+                    material.vertex_colors = match rest {
+                        "ignore" => VertexColors::Ignore,
+                        "multiply" => VertexColors::Multiply,
+                        "replace" => VertexColors::Replace,
+                        _ => bail!("Line {}: {:?} is no vertex color mode, ignore, multiply or replace.", number + 1, rest),
+                    };
+                }
+                "tint" => {
+                    let tint = parse_floats(rest)
+                        .filter(|tint| tint.len() == 3 || tint.len() == 4)
+                        .with_context(|| format!("Line {}: tint needs r g b and optionally a.", number + 1))?;
+                    material.tint = [tint[0], tint[1], tint[2], tint.get(3).copied().unwrap_or(1.0)];
+                }
+                "uv_scale" | "uv_offset" | "uv_scroll" => {
+                    let values = parse_floats(rest)
+                        .filter(|values| values.len() == 2)
+                        .with_context(|| format!("Line {}: {} needs u v.", number + 1, keyword))?;
+                    match keyword {
+                        "uv_scale" => material.uv_transform.scale = [values[0], values[1]],
+                        "uv_offset" => material.uv_transform.offset = [values[0], values[1]],
+                        _ => material.animation.scroll = [values[0], values[1]],
+                    }
+                }
+                "uv_rotation" => {
+                    let degrees = rest.parse::<f32>()
+                        .ok()
+                        .with_context(|| format!("Line {}: uv_rotation needs an angle in degrees.", number + 1))?;
+                    material.uv_transform.rotation = degrees.to_radians();
+                }
+                "flipbook" => {
+                    let words: Vec<&str> = rest.split_whitespace().collect();
+                    let counts = words.get(..3)
+                        .and_then(|counts| counts.iter().map(|word| word.parse::<u32>().ok()).collect::<Option<Vec<u32>>>());
+                    let fps = words.get(3).and_then(|word| word.parse::<f32>().ok());
+                    let (counts, fps) = counts.zip(fps)
+                        .filter(|(counts, _)| words.len() == 4 && counts.iter().all(|count| *count > 0))
+                        .with_context(|| format!("Line {}: flipbook needs columns rows frames fps.", number + 1))?;
+                    material.animation.flipbook = Some(Flipbook::new(counts[0], counts[1], counts[2], fps));
+                }
+                "vertex_animation" => {
+                    // the path may contain spaces, vertices and fps are the last words.
+                    let parsed = rest.rsplit_once(char::is_whitespace).and_then(|(rest, fps)| {
+                        let (path, vertices) = rest.trim_end().rsplit_once(char::is_whitespace)?;
+                        Some((path.trim(), vertices.parse::<u32>().ok()?, fps.parse::<f32>().ok()?))
+                    });
+                    let (path, vertices, fps) = parsed
+                        .filter(|(path, vertices, fps)| !path.is_empty() && *vertices > 0 && *fps > 0.0)
+                        .with_context(|| format!("Line {}: vertex_animation needs a path, vertices and fps.", number + 1))?;
+                    material.vertex_animation = Some((PathBuf::from(path), vertices, fps));
+                }
+                "vertex_animation_range" => {
+                    let values = parse_floats(rest)
+                        .filter(|values| values.len() == 6)
+                        .with_context(|| format!("Line {}: vertex_animation_range needs min x y z and max x y z.", number + 1))?;
+                    material.vertex_animation_range = Some(([values[0], values[1], values[2]], [values[3], values[4], values[5]]));
+                }
+                "wind" => {
+                    let words: Vec<&str> = rest.split_whitespace().collect();
+                    let values = words.get(..2).and_then(|values| parse_floats(&values.join(" ")));
+                    let mask = match words.get(2) {
+                        Some(&"uniform") => Some(WindMask::Uniform),
+                        Some(&"vertex_color") => Some(WindMask::VertexColor),
+                        Some(&"uv") => Some(WindMask::Uv),
+                        _ => None,
+                    };
+                    let (values, mask) = values.zip(mask)
+                        .filter(|_| words.len() == 3)
+                        .with_context(|| format!("Line {}: wind needs strength frequency and uniform, vertex_color or uv.", number + 1))?;
+                    material.wind = Some(WindSway { strength: values[0], frequency: values[1], mask });
+                }
+                _ => bail!("Line {}: unknown entry {:?}.", number + 1, keyword),
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MaterialDef> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    // whether the file was changed (or removed) since it was loaded.
+    pub fn changed(&self) -> bool {
+        modified(&self.path) != self.modified
+    }
+}
+
+// the numbers of a line, None when one of them isn't a number.
+fn parse_floats(text: &str) -> Option<Vec<f32>> {
+    text.split_whitespace()
+        .map(|word| word.parse::<f32>().ok())
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,170 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::locale::Strings;
+use crate::overlay::Overlay;
+
+/*
+    Measuring in the scene, for model viewers.
+
+    Points are picked on the meshes (see picking.rs) and the result is
+    drawn into the overlay: the picked points, the lines between them
+    and the distance or angle as text next to them and in the corner.
+
+    - Distance: two points, the length of the line between them.
+    - Angle: three points, the angle at the second one.
+
+    Adding a point to a finished measurement starts a new one.
+
+    usage:
+        renderer.add_plugin(Overlay::new());
+        let mut measurement = Measurement::new(MeasureMode::Distance);
+        // on click
+        if let Some(hit) = renderer.pick(x, y) {
+            measurement.add_point(hit.position);
+            measurement.draw(renderer.plugin_mut::<Overlay>().unwrap());
+        }
+*/
+
+// overlay layer the measurement is drawn into.
+pub const OVERLAY_LAYER: &str = "measurement";
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// the markers scale with the measured length, but no smaller than this.
+const MIN_MARKER_SIZE: f32 = 0.05;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MeasureMode {
+    Distance,
+    Angle,
+}
+
+impl MeasureMode {
+    pub fn num_points(&self) -> usize {
+        match self {
+            MeasureMode::Distance => 2,
+            MeasureMode::Angle => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    mode: MeasureMode,
+    points: Vec<[f32; 3]>,
+}
+
+impl Measurement {
+    pub fn new(mode: MeasureMode) -> Self {
+        Self { mode, points: Vec::new() }
+    }
+
+    pub fn mode(&self) -> MeasureMode {
+        self.mode
+    }
+
+    // drops the points picked so far.
+    pub fn set_mode(&mut self, mode: MeasureMode) {
+        self.mode = mode;
+        self.points.clear();
+    }
+
+    pub fn points(&self) -> &[[f32; 3]] {
+        &self.points
+    }
+
+    pub fn add_point(&mut self, point: [f32; 3]) {
+        if self.is_complete() {
+            self.points.clear();
+        }
+        self.points.push(point);
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.points.len() >= self.mode.num_points()
+    }
+
+    // world space distance between the two points.
+    pub fn distance(&self) -> Option<f32> {
+        match (self.mode, self.points.as_slice()) {
+            (MeasureMode::Distance, [a, b]) => Some(length(*a, *b)),
+            _ => None,
+        }
+    }
+
+    // angle at the second point, in degrees.
+    pub fn angle(&self) -> Option<f32> {
+        match (self.mode, self.points.as_slice()) {
+            (MeasureMode::Angle, [a, b, c]) => {
+                let ba = Vector3::from(*a) - Vector3::from(*b);
+                let bc = Vector3::from(*c) - Vector3::from(*b);
+                if ba.magnitude2() == 0.0 || bc.magnitude2() == 0.0 {
+                    return None;
+                }
+                Some(ba.angle(bc).0.to_degrees())
+            }
+            _ => None,
+        }
+    }
+
+    // replaces what the overlay showed of the previous measurement.
+    pub fn draw(&self, overlay: &mut Overlay) {
+        let summary = self.summary(overlay.strings());
+        // points and lines in the accent color of the palette, see palette.rs.
+        let color = overlay.palette().accent;
+        let overlay = overlay.layer(OVERLAY_LAYER);
+        overlay.clear();
+
+        let extent = self.points.windows(2)
+            .map(|pair| length(pair[0], pair[1]))
+            .fold(0.0f32, f32::max);
+        let marker = (extent * 0.03).max(MIN_MARKER_SIZE);
+        for point in &self.points {
+            overlay.cross(*point, marker, color);
+        }
+        for pair in self.points.windows(2) {
+            overlay.line(pair[0], pair[1], color);
+        }
+
+        if let Some(distance) = self.distance() {
+            let middle = midpoint(self.points[0], self.points[1]);
+            overlay.text_at(middle, &format!("{:.3}", distance), TEXT_COLOR);
+        } else if let Some(angle) = self.angle() {
+            overlay.text_at(self.points[1], &format!("{:.1}°", angle), TEXT_COLOR);
+            for pair in self.points.windows(2) {
+                overlay.text_at(midpoint(pair[0], pair[1]), &format!("{:.3}", length(pair[0], pair[1])), TEXT_COLOR);
+            }
+        }
+        overlay.text(10.0, 10.0, &summary, TEXT_COLOR);
+    }
+
+    // the line in the top left corner, in the language of `strings`.
+    fn summary(&self, strings: &Strings) -> String {
+        if let Some(distance) = self.distance() {
+            strings.format("measure.distance", &[("distance", &format!("{:.3}", distance))])
+        } else if let Some(angle) = self.angle() {
+            strings.format("measure.angle", &[("angle", &format!("{:.1}", angle))])
+        } else {
+            let mode = match self.mode {
+                MeasureMode::Distance => "measure.mode.distance",
+                MeasureMode::Angle => "measure.mode.angle",
+            };
+            strings.format("measure.pick", &[
+                ("mode", strings.get(mode)),
+                ("point", &(self.points.len() + 1).to_string()),
+                ("points", &self.mode.num_points().to_string()),
+            ])
+        }
+    }
+}
+
+fn length(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (Vector3::from(b) - Vector3::from(a)).magnitude()
+}
+
+fn midpoint(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    ((Vector3::from(a) + Vector3::from(b)) * 0.5).into()
+}
@@ -1,7 +1,16 @@
+use crate::fallback::FallbackTexture;
+use crate::inflight::FramesInFlight;
+use crate::jobs::Progress;
+use crate::lod::{DetailTier, MeshLod};
+use crate::splat::SplatLayers;
 use crate::texture::*;
+use crate::vat::VertexAnimation;
+use crate::wind::WindSway;
 use crate::vertex::*;
 
-use std::time::Instant;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use wgpu::util::DeviceExt;
 
 use anyhow::{Context, Result};
@@ -9,71 +18,1419 @@ use anyhow::{Context, Result};
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    pub bounds: Bounds,
+    // copies drawn instead of the model itself, see Renderer::set_instances().
+    pub instances: Option<InstanceBuffer>,
+}
+
+// axis aligned bounding box in model space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Bounds {
+    // inverted box, so that any extend() call replaces it.
+    pub fn empty() -> Self {
+        Self {
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min[0] > self.max[0]
+    }
+
+    pub fn extend(&mut self, point: [f32; 3]) {
+        for (i, value) in point.iter().enumerate() {
+            self.min[i] = self.min[i].min(*value);
+            self.max[i] = self.max[i].max(*value);
+        }
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        let mut bounds = *self;
+        if !other.is_empty() {
+            bounds.extend(other.min);
+            bounds.extend(other.max);
+        }
+        bounds
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) * 0.5,
+            (self.min[1] + self.max[1]) * 0.5,
+            (self.min[2] + self.max[2]) * 0.5,
+        ]
+    }
+
+    pub fn size(&self) -> [f32; 3] {
+        [
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+            self.max[2] - self.min[2],
+        ]
+    }
+
+    // radius of the sphere around center() that contains the box.
+    pub fn radius(&self) -> f32 {
+        let size = self.size();
+        0.5 * (size[0] * size[0] + size[1] * size[1] + size[2] * size[2]).sqrt()
+    }
 }
 
 pub struct Material {
     pub name: String,
     pub diffuse_texture: Texture,
+    pub vertex_colors: VertexColors,
+    // baked lighting, sampled with the second uv set.
+    pub lightmap: Option<Texture>,
+    pub lightmap_mode: LightmapMode,
+    // painted terrain layers, see splat.rs.
+    pub splat: Option<SplatLayers>,
+    // multiplied into the color, rgba.
+    pub tint: [f32; 4],
+    // of the uvs the diffuse texture is sampled with.
+    pub uv_transform: UvTransform,
+    // scrolling / flipbook, see TextureAnimation.
+    pub animation: TextureAnimation,
+    // baked vertex offsets, see vat.rs.
+    pub vertex_animation: Option<VertexAnimation>,
+    // vegetation swaying in the wind, see wind.rs.
+    pub wind: Option<WindSway>,
+    // tangent space normals (linear rgba8), tiled like the diffuse
+    // texture, see MeshData::compute_tangents().
+    pub normal_map: Option<Texture>,
+    pub uniform_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
+// How the vertex colors of a mesh are combined with the diffuse texture.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VertexColors {
+    Ignore,
+    Multiply,
+    Replace,
+}
+
+impl VertexColors {
+    // value of the mode in the shader.
+    pub fn as_u32(self) -> u32 {
+        match self {
+            VertexColors::Ignore => 0,
+            VertexColors::Multiply => 1,
+            VertexColors::Replace => 2,
+        }
+    }
+
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            1 => VertexColors::Multiply,
+            2 => VertexColors::Replace,
+            _ => VertexColors::Ignore,
+        }
+    }
+}
+
+// How a lightmap is combined with the diffuse color.
+// Multiply for baked light and shadow, Add for baked glow / bounce light.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightmapMode {
+    Multiply,
+    Add,
+}
+
+impl LightmapMode {
+    pub fn as_u32(self) -> u32 {
+        match self {
+            LightmapMode::Multiply => 1,
+            LightmapMode::Add => 2,
+        }
+    }
+
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            2 => LightmapMode::Add,
+            _ => LightmapMode::Multiply,
+        }
+    }
+}
+
+// Tiling, offset and rotation of the uvs a material's diffuse texture is
+// sampled with, e.g. to repeat a road texture across the whole terrain
+// without touching the mesh. The uvs are scaled, then rotated (radians,
+// around uv 0 0) and then offset.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UvTransform {
+    pub scale: [f32; 2],
+    pub offset: [f32; 2],
+    pub rotation: f32,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self { scale: [1.0; 2], offset: [0.0; 2], rotation: 0.0 }
+    }
+}
+
+impl UvTransform {
+    // the same tiling in both directions.
+    pub fn tiling(tiling: f32) -> Self {
+        Self { scale: [tiling; 2], ..Default::default() }
+    }
+
+    // scale and rotation as a 2x2 matrix, column by column.
+    pub fn matrix(&self) -> [f32; 4] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            cos * self.scale[0], sin * self.scale[0],
+            -sin * self.scale[1], cos * self.scale[1],
+        ]
+    }
+
+    pub fn apply(&self, uv: [f32; 2]) -> [f32; 2] {
+        let m = self.matrix();
+        [
+            m[0] * uv[0] + m[2] * uv[1] + self.offset[0],
+            m[1] * uv[0] + m[3] * uv[1] + self.offset[1],
+        ]
+    }
+}
+
+// A flipbook: the frames of an animation in a grid on the texture, left
+// to right and top to bottom, shown one after the other `fps` times a
+// second. The uvs of the mesh cover one frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Flipbook {
+    pub columns: u32,
+    pub rows: u32,
+    // the last row may not be full.
+    pub frames: u32,
+    pub fps: f32,
+}
+
+impl Flipbook {
+    pub fn new(columns: u32, rows: u32, frames: u32, fps: f32) -> Self {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        Self { columns, rows, frames: frames.clamp(1, columns * rows), fps }
+    }
+
+    // the frame shown `time` seconds in.
+    pub fn frame(&self, time: f32) -> u32 {
+        ((time * self.fps).max(0.0) as u32) % self.frames.max(1)
+    }
+}
+
+// Animated textures, for water, conveyor belts and effects: the uvs
+// scroll `scroll` per second (after the UvTransform), and with a flipbook
+// the texture is played as one. Both run on the time of the per frame
+// uniform, see frame.rs.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TextureAnimation {
+    pub scroll: [f32; 2],
+    pub flipbook: Option<Flipbook>,
+}
+
+impl TextureAnimation {
+    pub fn scrolling(u: f32, v: f32) -> Self {
+        Self { scroll: [u, v], flipbook: None }
+    }
+
+    pub fn flipbook(flipbook: Flipbook) -> Self {
+        Self { scroll: [0.0; 2], flipbook: Some(flipbook) }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.scroll != [0.0; 2] || self.flipbook.is_some()
+    }
+}
+
+// per material values, binding 2 of the material bind group.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    vertex_colors: u32,
+    // 0 = no lightmap, otherwise LightmapMode::as_u32().
+    lightmap: u32,
+    // 1 = blend the splat layers over the diffuse texture.
+    splat: u32,
+    splat_tiling: f32,
+    tint: [f32; 4],
+    // UvTransform::matrix(), then the offset.
+    uv_matrix: [f32; 4],
+    uv_offset: [f32; 2],
+    uv_scroll: [f32; 2],
+    // frames 0 = no flipbook.
+    flipbook_columns: u32,
+    flipbook_rows: u32,
+    flipbook_frames: u32,
+    flipbook_fps: f32,
+    // frames 0 = no vertex animation, see VertexAnimation.
+    vat_frames: u32,
+    vat_rows: u32,
+    vat_fps: f32,
+    vat_loop: u32,
+    vat_start: f32,
+    // 0 = no wind sway, otherwise WindMask::as_u32().
+    wind_mask: u32,
+    wind_strength: f32,
+    wind_frequency: f32,
+    // 1 = perturb the normals by the normal map.
+    normal_map: u32,
+    // the size of a uniform is a multiple of 16 bytes.
+    _padding: [u32; 3],
+}
+
+impl Material {
+    // bind group describes set of ressources, and they can be accessed
+    // by a shader
+    // -> the layout every material bind group is created against:
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // Filtering needs the sample_type of the texture to be:
+                    //     TextureSampleType::Float { filterable: true }
+                    // Otherwise you'll get an error. (Comparison is only for
+                    // TextureSampleType::Depth.)
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // the vertex shader reads the vertex animation values.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // lightmap, same kind of texture and sampler as the diffuse one.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // splat control map and its sampler, then the three layers
+                // (sampled with the diffuse sampler).
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                // vertex animation, 32 bit floats read with textureLoad(), see vat.rs.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                // normal map, sampled with the diffuse sampler.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        name: &str,
+        diffuse_texture: Texture,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Material Buffer", name)),
+            contents: bytemuck::cast_slice(&[MaterialUniform {
+                vertex_colors: VertexColors::Ignore.as_u32(),
+                lightmap: 0,
+                splat: 0,
+                splat_tiling: 1.0,
+                tint: [1.0; 4],
+                uv_matrix: UvTransform::default().matrix(),
+                uv_offset: [0.0; 2],
+                uv_scroll: [0.0; 2],
+                flipbook_columns: 1,
+                flipbook_rows: 1,
+                flipbook_frames: 0,
+                flipbook_fps: 0.0,
+                vat_frames: 0,
+                vat_rows: 1,
+                vat_fps: 0.0,
+                vat_loop: 0,
+                vat_start: 0.0,
+                wind_mask: 0,
+                wind_strength: 0.0,
+                wind_frequency: 0.0,
+                normal_map: 0,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = Self::create_bind_group(device, layout, name, &diffuse_texture, None, None, None, None, &uniform_buffer);
+
+        Self {
+            name: name.to_string(),
+            diffuse_texture,
+            vertex_colors: VertexColors::Ignore,
+            lightmap: None,
+            lightmap_mode: LightmapMode::Multiply,
+            splat: None,
+            tint: [1.0; 4],
+            uv_transform: UvTransform::default(),
+            animation: TextureAnimation::default(),
+            vertex_animation: None,
+            wind: None,
+            normal_map: None,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        name: &str,
+        diffuse_texture: &Texture,
+        lightmap: Option<&Texture>,
+        splat: Option<&SplatLayers>,
+        vertex_animation: Option<&VertexAnimation>,
+        normal_map: Option<&Texture>,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        // without a lightmap the diffuse texture fills the slot,
+        // the shader doesn't sample it then. Same for the splat,
+        // vertex animation and normal map slots.
+        let lightmap = lightmap.unwrap_or(diffuse_texture);
+        let (control_view, control_sampler) = match splat {
+            Some(splat) => (&splat.control.view, &splat.control.sampler),
+            None => (&diffuse_texture.view, &diffuse_texture.sampler),
+        };
+        let layer = |index: usize| splat.map_or(&diffuse_texture.view, |splat| &splat.layers[index].view);
+        let vertex_animation = vertex_animation.map_or(&diffuse_texture.view, |vat| &vat.view);
+        let normal_map = normal_map.unwrap_or(diffuse_texture);
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&lightmap.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&lightmap.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(control_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(control_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(layer(0)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(layer(1)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::TextureView(layer(2)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: wgpu::BindingResource::TextureView(vertex_animation),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                },
+            ],
+            label: Some(name),
+        })
+    }
+
+    // bytes of the textures and the uniform on the gpu.
+    pub fn memory_size(&self) -> u64 {
+        self.diffuse_texture.memory_size()
+            + self.lightmap.as_ref().map_or(0, |lightmap| lightmap.memory_size())
+            + self.splat.as_ref().map_or(0, |splat| splat.memory_size())
+            + self.vertex_animation.as_ref().map_or(0, |vat| vat.memory_size())
+            + self.normal_map.as_ref().map_or(0, |normal_map| normal_map.memory_size())
+            + std::mem::size_of::<MaterialUniform>() as u64
+    }
+
+    fn write_uniform(&self, queue: &wgpu::Queue) {
+        let flipbook = self.animation.flipbook.unwrap_or(Flipbook { columns: 1, rows: 1, frames: 0, fps: 0.0 });
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[MaterialUniform {
+            vertex_colors: self.vertex_colors.as_u32(),
+            lightmap: match &self.lightmap {
+                Some(_) => self.lightmap_mode.as_u32(),
+                None => 0,
+            },
+            splat: self.splat.is_some() as u32,
+            splat_tiling: self.splat.as_ref().map_or(1.0, |splat| splat.tiling),
+            tint: self.tint,
+            uv_matrix: self.uv_transform.matrix(),
+            uv_offset: self.uv_transform.offset,
+            uv_scroll: self.animation.scroll,
+            flipbook_columns: flipbook.columns,
+            flipbook_rows: flipbook.rows,
+            flipbook_frames: flipbook.frames,
+            flipbook_fps: flipbook.fps,
+            vat_frames: self.vertex_animation.as_ref().map_or(0, |vat| vat.frames),
+            vat_rows: self.vertex_animation.as_ref().map_or(1, |vat| vat.rows_per_frame()),
+            vat_fps: self.vertex_animation.as_ref().map_or(0.0, |vat| vat.fps),
+            vat_loop: self.vertex_animation.as_ref().is_some_and(|vat| vat.looping) as u32,
+            vat_start: self.vertex_animation.as_ref().map_or(0.0, |vat| vat.start),
+            wind_mask: self.wind.map_or(0, |wind| wind.mask.as_u32()),
+            wind_strength: self.wind.map_or(0.0, |wind| wind.strength),
+            wind_frequency: self.wind.map_or(0.0, |wind| wind.frequency),
+            normal_map: self.normal_map.is_some() as u32,
+            _padding: [0; 3],
+        }]));
+    }
+
+    pub fn set_vertex_colors(&mut self, queue: &wgpu::Queue, vertex_colors: VertexColors) {
+        self.vertex_colors = vertex_colors;
+        self.write_uniform(queue);
+    }
+
+    // rgba the color is multiplied with, white leaves it as it is.
+    pub fn set_tint(&mut self, queue: &wgpu::Queue, tint: [f32; 4]) {
+        self.tint = tint;
+        self.write_uniform(queue);
+    }
+
+    pub fn set_uv_transform(&mut self, queue: &wgpu::Queue, uv_transform: UvTransform) {
+        self.uv_transform = uv_transform;
+        self.write_uniform(queue);
+    }
+
+    pub fn set_animation(&mut self, queue: &wgpu::Queue, animation: TextureAnimation) {
+        self.animation = animation;
+        self.write_uniform(queue);
+    }
+
+    // Sets or removes (None) the wind sway of the material.
+    pub fn set_wind(&mut self, queue: &wgpu::Queue, wind: Option<WindSway>) {
+        self.wind = wind;
+        self.write_uniform(queue);
+    }
+
+    // Replaces the diffuse texture, e.g. by a debug one of fallback.rs.
+    pub fn set_diffuse_texture(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        diffuse_texture: Texture,
+    ) {
+        self.diffuse_texture = diffuse_texture;
+        self.bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &self.name,
+            &self.diffuse_texture,
+            self.lightmap.as_ref(),
+            self.splat.as_ref(),
+            self.vertex_animation.as_ref(),
+            self.normal_map.as_ref(),
+            &self.uniform_buffer,
+        );
+    }
+
+    // Sets or removes (None) the lightmap, it's sampled with the second uv set.
+    pub fn set_lightmap(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        lightmap: Option<Texture>,
+        mode: LightmapMode,
+    ) {
+        self.lightmap = lightmap;
+        self.lightmap_mode = mode;
+        self.bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &self.name,
+            &self.diffuse_texture,
+            self.lightmap.as_ref(),
+            self.splat.as_ref(),
+            self.vertex_animation.as_ref(),
+            self.normal_map.as_ref(),
+            &self.uniform_buffer,
+        );
+        self.write_uniform(queue);
+    }
+
+    // Sets or removes (None) the splat layers, the control map is sampled with the first uv set.
+    pub fn set_splat(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        splat: Option<SplatLayers>,
+    ) {
+        self.splat = splat;
+        self.bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &self.name,
+            &self.diffuse_texture,
+            self.lightmap.as_ref(),
+            self.splat.as_ref(),
+            self.vertex_animation.as_ref(),
+            self.normal_map.as_ref(),
+            &self.uniform_buffer,
+        );
+        self.write_uniform(queue);
+    }
+
+    // Sets or removes (None) the vertex animation, the meshes drawn with
+    // the material have to keep the vertex order it was baked with.
+    pub fn set_vertex_animation(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        vertex_animation: Option<VertexAnimation>,
+    ) {
+        self.vertex_animation = vertex_animation;
+        self.bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &self.name,
+            &self.diffuse_texture,
+            self.lightmap.as_ref(),
+            self.splat.as_ref(),
+            self.vertex_animation.as_ref(),
+            self.normal_map.as_ref(),
+            &self.uniform_buffer,
+        );
+        self.write_uniform(queue);
+    }
+
+    // Sets or removes (None) the normal map, in tangent space: red along
+    // u, green against v (up in the image), blue out of the surface.
+    pub fn set_normal_map(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        normal_map: Option<Texture>,
+    ) {
+        self.normal_map = normal_map;
+        self.bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &self.name,
+            &self.diffuse_texture,
+            self.lightmap.as_ref(),
+            self.splat.as_ref(),
+            self.vertex_animation.as_ref(),
+            self.normal_map.as_ref(),
+            &self.uniform_buffer,
+        );
+        self.write_uniform(queue);
+    }
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    pub bounds: Bounds,
+    // cpu copy of the triangles, for picking (and painting, with the uvs).
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    // moves the whole mesh, e.g. in the exploded view. Set with set_offset().
+    pub offset: [f32; 3],
+    pub offset_buffer: wgpu::Buffer,
+    // coarser triangles for the secondary views, see lod.rs.
+    pub lods: Vec<MeshLod>,
 }
 
-impl Model {
-    pub fn load<P: AsRef<std::path::Path>>(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        layout: &wgpu::BindGroupLayout,
-        path: P,
-    ) -> Result<Self> {
-        let (obj_models, obj_materials) = tobj::load_obj(path.as_ref(), &tobj::LoadOptions {
+// Cpu side of a model, as it comes out of the file.
+// -> can be inspected or converted without a gpu.
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub bounds: Bounds,
+    // folder the texture paths of the materials are relative to.
+    pub directory: PathBuf,
+}
+
+// light gray, for materials that don't say otherwise.
+pub const DEFAULT_DIFFUSE_COLOR: [f32; 3] = [200.0 / 255.0, 200.0 / 255.0, 200.0 / 255.0];
+
+// vertex color of models without vertex colors.
+pub const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+
+pub struct MaterialData {
+    pub name: String,
+    // path as written in the material file.
+    pub diffuse_texture: Option<PathBuf>,
+    // used when there is no texture.
+    pub diffuse_color: [f32; 3],
+    pub vertex_colors: VertexColors,
+    // path as written in the material file, sampled with uv2.
+    pub lightmap_texture: Option<PathBuf>,
+    pub lightmap_mode: LightmapMode,
+    pub displacement: Option<Displacement>,
+    // tangent space normal map, path as written in the material file.
+    pub normal_texture: Option<PathBuf>,
+}
+
+// Height map that pushes the vertices of a material along their normals,
+// applied on the cpu when the model is loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Displacement {
+    // grayscale image, path as written in the material file.
+    pub texture: PathBuf,
+    // offset = base + scale * height, with height in 0..1.
+    pub base: f32,
+    pub scale: f32,
+}
+
+// the file of a texture line of an mtl file, after its options
+// ("-bm 0.5 rock_normal.png"). File names with spaces aren't supported.
+fn texture_from_mtl(value: &str) -> Option<PathBuf> {
+    value.split_whitespace().last().map(PathBuf::from)
+}
+
+impl Displacement {
+    // "disp" line of an mtl file: [-mm base gain] file
+    fn from_mtl(value: &str) -> Option<Self> {
+        let words: Vec<&str> = value.split_whitespace().collect();
+        let texture = PathBuf::from(*words.last()?);
+        let (mut base, mut scale) = (0.0, 1.0);
+        if let Some(i) = words.iter().position(|word| *word == "-mm") {
+            base = words.get(i + 1).and_then(|word| word.parse().ok()).unwrap_or(0.0);
+            scale = words.get(i + 2).and_then(|word| word.parse().ok()).unwrap_or(1.0);
+        }
+        Some(Self { texture, base, scale })
+    }
+}
+
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<MVertex>,
+    pub indices: Vec<u32>,
+    pub material: usize,
+    pub bounds: Bounds,
+}
+
+impl MeshData {
+    pub fn new(name: String, vertices: Vec<MVertex>, indices: Vec<u32>, material: usize) -> Self {
+        let mut bounds = Bounds::empty();
+        for vertex in &vertices {
+            bounds.extend(vertex.position);
+        }
+
+        Self {
+            name,
+            vertices,
+            indices,
+            material,
+            bounds,
+        }
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // Smooth per-vertex normals: every vertex gets the sum of the
+    // normals of the triangles using it, weighted by triangle area.
+    pub fn compute_normals(&mut self) {
+        use cgmath::{InnerSpace, Vector3};
+
+        let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let a = Vector3::from(self.vertices[triangle[0] as usize].position);
+            let b = Vector3::from(self.vertices[triangle[1] as usize].position);
+            let c = Vector3::from(self.vertices[triangle[2] as usize].position);
+            // the length of the cross product is twice the area.
+            let normal = (b - a).cross(c - a);
+            for index in triangle {
+                normals[*index as usize] += normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.norm = if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                // unused or degenerate vertex
+                [0.0, 1.0, 0.0]
+            };
+        }
+    }
+
+    // Like compute_normals(), but the vertices at the same position share
+    // the normal, for meshes split only where the uvs are (an obj without
+    // "vn" lines): the uv seams don't show in the lighting. Meshes split to
+    // have hard edges need compute_normals().
+    pub fn compute_smooth_normals(&mut self) {
+        use cgmath::{InnerSpace, Vector3};
+        use std::collections::HashMap;
+
+        // by the bits of the position, the split vertices are exact copies.
+        let key = |position: [f32; 3]| position.map(f32::to_bits);
+        let mut normals: HashMap<[u32; 3], Vector3<f32>> = HashMap::new();
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| Vector3::from(self.vertices[triangle[corner] as usize].position));
+            // the length of the cross product is twice the area.
+            let normal = (b - a).cross(c - a);
+            for index in triangle {
+                *normals.entry(key(self.vertices[*index as usize].position)).or_insert(Vector3::new(0.0, 0.0, 0.0)) += normal;
+            }
+        }
+
+        for vertex in &mut self.vertices {
+            vertex.norm = match normals.get(&key(vertex.position)) {
+                Some(normal) if normal.magnitude2() > 0.0 => normal.normalize().into(),
+                // unused or degenerate vertex
+                _ => [0.0, 1.0, 0.0],
+            };
+        }
+    }
+
+    // Tangents and bitangents for normal mapping: the directions u and v
+    // grow in on the surface, from the uvs of the triangles using the
+    // vertex (weighted by area like compute_normals()), then made
+    // perpendicular to the normal. The bitangent is the cross product of
+    // the two, flipped where the uvs are mirrored. Needs the normals and
+    // uvs, run it after compute_normals() and project_uvs().
+    pub fn compute_tangents(&mut self) {
+        use cgmath::{InnerSpace, Vector3};
+
+        let zero = Vector3::new(0.0f32, 0.0, 0.0);
+        let mut tangents = vec![zero; self.vertices.len()];
+        let mut bitangents = vec![zero; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| &self.vertices[triangle[corner] as usize]);
+            let (edge1, edge2) = (
+                Vector3::from(b.position) - Vector3::from(a.position),
+                Vector3::from(c.position) - Vector3::from(a.position),
+            );
+            let (du1, dv1) = (b.uv[0] - a.uv[0], b.uv[1] - a.uv[1]);
+            let (du2, dv2) = (c.uv[0] - a.uv[0], c.uv[1] - a.uv[1]);
+            // twice the area in uv space, without one the directions are unknown.
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            // not divided by det, so bigger triangles count more, the sign of it only.
+            let sign = det.signum();
+            let tangent = (edge1 * dv2 - edge2 * dv1) * sign;
+            let bitangent = (edge2 * du1 - edge1 * du2) * sign;
+            for index in triangle {
+                tangents[*index as usize] += tangent;
+                bitangents[*index as usize] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents) {
+            let normal = Vector3::from(vertex.norm);
+            // Gram-Schmidt: the part of the tangent along the normal goes.
+            let mut t = tangent - normal * normal.dot(tangent);
+            if t.magnitude2() <= f32::EPSILON * f32::EPSILON {
+                // no uvs to go by, any direction on the surface does.
+                let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+                t = axis - normal * normal.dot(axis);
+            }
+            if t.magnitude2() == 0.0 {
+                // no normal either.
+                vertex.tangent = [0.0; 3];
+                vertex.bitangent = [0.0; 3];
+                continue;
+            }
+            let t = t.normalize();
+            let b = normal.cross(t);
+            let side = if b.dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+            vertex.tangent = t.into();
+            vertex.bitangent = (b * side).into();
+        }
+    }
+
+    // Box projected uvs for meshes imported without any: every vertex is
+    // projected along the axis its triangles mostly face, scaled so the
+    // longest side of the mesh spans 0..1. Both uv sets get them. Seams
+    // where the axis changes stretch, but textures, lightmaps and the
+    // splat map get something to work with.
+    pub fn project_uvs(&mut self) {
+        let mut facing = vec![[0.0f32; 3]; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| self.vertices[triangle[corner] as usize].position);
+            let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+            // the cross product, weighted by the area like compute_normals().
+            let normal = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+            for index in triangle {
+                for axis in 0..3 {
+                    facing[*index as usize][axis] += normal[axis].abs();
+                }
+            }
+        }
+
+        let size = self.bounds.size();
+        let scale = 1.0 / size[0].max(size[1]).max(size[2]).max(f32::EPSILON);
+        let min = self.bounds.min;
+        for (vertex, facing) in self.vertices.iter_mut().zip(facing) {
+            let p = [0, 1, 2].map(|axis| (vertex.position[axis] - min[axis]) * scale);
+            // v runs down the texture, so up in the world is up in the image.
+            let uv = if facing[0] >= facing[1] && facing[0] >= facing[2] {
+                [p[2], 1.0 - p[1]]
+            } else if facing[1] >= facing[2] {
+                [p[0], p[2]]
+            } else {
+                [p[0], 1.0 - p[1]]
+            };
+            vertex.uv = uv;
+            vertex.uv2 = uv;
+        }
+    }
+
+    // Moves every vertex along its normal by the height under its uv,
+    // then recomputes the normals of the new surface.
+    pub fn displace(&mut self, height: &image::GrayImage, base: f32, scale: f32) {
+        // imported without normals -> use the smooth ones.
+        if self.vertices.iter().all(|vertex| vertex.norm == [0.0, 0.0, 0.0]) {
+            self.compute_normals();
+        }
+
+        for vertex in &mut self.vertices {
+            let offset = base + scale * sample_height(height, vertex.uv);
+            for (position, normal) in vertex.position.iter_mut().zip(vertex.norm.iter()) {
+                *position += normal * offset;
+            }
+        }
+
+        self.bounds = Bounds::empty();
+        for vertex in &self.vertices {
+            self.bounds.extend(vertex.position);
+        }
+        self.compute_normals();
+        self.compute_tangents();
+    }
+
+    // box with outward facing sides, 4 vertices per side so the uvs work out.
+    pub fn cuboid(name: &str, bounds: &Bounds) -> Self {
+        let (min, max) = (bounds.min, bounds.max);
+        // corners of each side in counter clockwise order, seen from outside.
+        let sides: [[[f32; 3]; 4]; 6] = [
+            [[max[0], min[1], max[2]], [max[0], min[1], min[2]], [max[0], max[1], min[2]], [max[0], max[1], max[2]]], // +x
+            [[min[0], min[1], min[2]], [min[0], min[1], max[2]], [min[0], max[1], max[2]], [min[0], max[1], min[2]]], // -x
+            [[min[0], max[1], max[2]], [max[0], max[1], max[2]], [max[0], max[1], min[2]], [min[0], max[1], min[2]]], // +y
+            [[min[0], min[1], min[2]], [max[0], min[1], min[2]], [max[0], min[1], max[2]], [min[0], min[1], max[2]]], // -y
+            [[min[0], min[1], max[2]], [max[0], min[1], max[2]], [max[0], max[1], max[2]], [min[0], max[1], max[2]]], // +z
+            [[max[0], min[1], min[2]], [min[0], min[1], min[2]], [min[0], max[1], min[2]], [max[0], max[1], min[2]]], // -z
+        ];
+        let normals: [[f32; 3]; 6] = [
+            [1.0, 0.0, 0.0], [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0], [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0], [0.0, 0.0, -1.0],
+        ];
+        let uvs: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (side, norm) in sides.iter().zip(normals.iter()) {
+            let first = vertices.len() as u32;
+            for (position, uv) in side.iter().zip(uvs.iter()) {
+                vertices.push(MVertex {
+                    position: *position,
+                    uv: *uv,
+                    norm: *norm,
+                    color: WHITE,
+                    uv2: *uv,
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                });
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+
+        let mut mesh = Self::new(name.to_string(), vertices, indices, 0);
+        mesh.compute_tangents();
+        mesh
+    }
+
+    // uv sphere, `rings` from pole to pole and `segments` around, the
+    // texture wrapped around it once. The seam has its own vertices.
+    pub fn sphere(name: &str, center: [f32; 3], radius: f32, rings: u32, segments: u32) -> Self {
+        use std::f32::consts::{PI, TAU};
+
+        let (rings, segments) = (rings.max(2), segments.max(3));
+        let mut vertices = Vec::with_capacity(((rings + 1) * (segments + 1)) as usize);
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let (sin_polar, cos_polar) = (v * PI).sin_cos();
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let (sin_azimuth, cos_azimuth) = (u * TAU).sin_cos();
+                let norm = [sin_polar * cos_azimuth, cos_polar, -sin_polar * sin_azimuth];
+                vertices.push(MVertex {
+                    position: [0, 1, 2].map(|axis| center[axis] + norm[axis] * radius),
+                    uv: [u, v],
+                    norm,
+                    color: WHITE,
+                    uv2: [u, v],
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                });
+            }
+        }
+
+        let mut indices = Vec::with_capacity((rings * segments * 6) as usize);
+        let row = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * row + segment;
+                let b = a + row;
+                // counter clockwise seen from outside.
+                indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+
+        let mut mesh = Self::new(name.to_string(), vertices, indices, 0);
+        mesh.compute_tangents();
+        mesh
+    }
+}
+
+// bilinear, with the uvs repeating like the gpu sampler does.
+fn sample_height(image: &image::GrayImage, uv: [f32; 2]) -> f32 {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let x = uv[0].rem_euclid(1.0) * width as f32 - 0.5;
+    let y = uv[1].rem_euclid(1.0) * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as i64).rem_euclid(height as i64) as u32;
+        image.get_pixel(x, y)[0] as f32 / 255.0
+    };
+    let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+    let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+impl MaterialData {
+    // full path of the diffuse texture, if the material has one.
+    pub fn diffuse_path(&self, directory: &Path) -> Option<PathBuf> {
+        self.diffuse_texture.as_ref().map(|path| directory.join(path))
+    }
+
+    pub fn lightmap_path(&self, directory: &Path) -> Option<PathBuf> {
+        self.lightmap_texture.as_ref().map(|path| directory.join(path))
+    }
+
+    pub fn normal_path(&self, directory: &Path) -> Option<PathBuf> {
+        self.normal_texture.as_ref().map(|path| directory.join(path))
+    }
+}
+
+impl ModelData {
+    pub fn new(meshes: Vec<MeshData>, materials: Vec<MaterialData>, directory: PathBuf) -> Self {
+        let bounds = meshes.iter()
+            .fold(Bounds::empty(), |bounds, mesh| bounds.union(&mesh.bounds));
+
+        Self {
+            meshes,
+            materials,
+            bounds,
+            directory,
+        }
+    }
+
+    // picks the importer from the file extension, obj is the default.
+    // The tangents are computed for all of them, none of the formats
+    // brings its own.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let extension = path.as_ref().extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        let mut data = match extension.as_deref() {
+            Some("ply") => Self::load_ply(path),
+            Some("stl") => Self::load_stl(path),
+            Some("fbx") => Self::load_fbx(path),
+            _ => Self::load_obj(path),
+        }?;
+        data.meshes.par_iter_mut().for_each(MeshData::compute_tangents);
+        Ok(data)
+    }
+
+    // Meshes from PLY (scans) and STL (CAD) files have no materials,
+    // they all use this one.
+    fn default_material() -> MaterialData {
+        MaterialData {
+            name: "default".to_string(),
+            diffuse_texture: None,
+            diffuse_color: DEFAULT_DIFFUSE_COLOR,
+            vertex_colors: VertexColors::Ignore,
+            lightmap_texture: None,
+            lightmap_mode: LightmapMode::Multiply,
+            displacement: None,
+            normal_texture: None,
+        }
+    }
+
+    pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let ply = crate::ply::PlyData::load(path.as_ref())?;
+        let vertex = ply.element("vertex")
+            .context("PLY file has no vertex element.")?;
+
+        let (x, y, z) = match (vertex.scalar("x"), vertex.scalar("y"), vertex.scalar("z")) {
+            (Some(x), Some(y), Some(z)) => (x, y, z),
+            _ => anyhow::bail!("PLY vertices have no x/y/z."),
+        };
+        // texture coordinates go by a few different names.
+        let u = vertex.scalar("u").or_else(|| vertex.scalar("s")).or_else(|| vertex.scalar("texture_u"));
+        let v = vertex.scalar("v").or_else(|| vertex.scalar("t")).or_else(|| vertex.scalar("texture_v"));
+        let (r, g, b) = (vertex.scalar("red"), vertex.scalar("green"), vertex.scalar("blue"));
+        // colors are usually uchar, sometimes 0-1 floats.
+        let color_scale = match (r, g, b) {
+            (Some(r), Some(g), Some(b)) if r.iter().chain(g).chain(b).any(|c| *c > 1.0) => 1.0 / 255.0,
+            _ => 1.0,
+        };
+
+        let vertices = (0..vertex.count)
+            .map(|i| {
+                let uv = match (u, v) {
+                    (Some(u), Some(v)) => [u[i], v[i]],
+                    _ => [0.0, 0.0],
+                };
+                MVertex {
+                    position: [x[i], y[i], z[i]],
+                    uv,
+                    norm: [0.0, 0.0, 0.0],
+                    color: match (r, g, b) {
+                        (Some(r), Some(g), Some(b)) => [r[i] * color_scale, g[i] * color_scale, b[i] * color_scale],
+                        _ => WHITE,
+                    },
+                    uv2: uv,
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                }
+            })
+            .collect();
+
+        // polygons are triangulated as fans.
+        let mut indices = Vec::new();
+        if let Some(faces) = ply.element("face") {
+            let polygons = faces.list("vertex_indices")
+                .or_else(|| faces.list("vertex_index"))
+                .unwrap_or(&[]);
+            for polygon in polygons {
+                if let Some(index) = polygon.iter().find(|index| **index as usize >= vertex.count) {
+                    anyhow::bail!("PLY face index {} out of range for {} vertices.", index, vertex.count);
+                }
+                for i in 1..polygon.len().saturating_sub(1) {
+                    indices.extend_from_slice(&[polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+        }
+
+        let name = path.as_ref().file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut mesh = MeshData::new(name, vertices, indices, 0);
+        mesh.compute_normals();
+        if u.is_none() || v.is_none() {
+            mesh.project_uvs();
+        }
+
+        // scans come with colors instead of a texture.
+        let mut material = Self::default_material();
+        if r.is_some() && g.is_some() && b.is_some() {
+            material.vertex_colors = VertexColors::Replace;
+        }
+
+        let directory = path.as_ref().parent().context("Directory has no parent")?;
+        Ok(Self::new(vec![mesh], vec![material], directory.to_path_buf()))
+    }
+
+    pub fn load_stl<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let triangles = crate::stl::load(path.as_ref())?;
+
+        // corners are not shared between triangles in stl,
+        // so the computed normals come out flat per facet.
+        let mut vertices = Vec::with_capacity(triangles.len() * 3);
+        for triangle in &triangles {
+            for corner in &triangle.vertices {
+                vertices.push(MVertex {
+                    position: *corner,
+                    uv: [0.0, 0.0],
+                    norm: [0.0, 0.0, 0.0],
+                    color: WHITE,
+                    uv2: [0.0, 0.0],
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                });
+            }
+        }
+        let indices = (0..vertices.len() as u32).collect();
+
+        let name = path.as_ref().file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut mesh = MeshData::new(name, vertices, indices, 0);
+        mesh.compute_normals();
+        // stl has no uvs at all.
+        mesh.project_uvs();
+
+        let directory = path.as_ref().parent().context("Directory has no parent")?;
+        Ok(Self::new(vec![mesh], vec![Self::default_material()], directory.to_path_buf()))
+    }
+
+    pub fn load_fbx<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let scene = crate::fbx::load(path.as_ref())?;
+
+        let mut materials: Vec<MaterialData> = scene.materials.into_iter()
+            .map(|mat| {
+                let scale = mat.displacement_scale;
+                MaterialData {
+                    name: mat.name,
+                    diffuse_texture: mat.diffuse_texture,
+                    diffuse_color: mat.diffuse_color,
+                    vertex_colors: VertexColors::Ignore,
+                    lightmap_texture: mat.lightmap_texture,
+                    lightmap_mode: LightmapMode::Multiply,
+                    displacement: mat.displacement_texture.map(|texture| Displacement {
+                        texture,
+                        base: 0.0,
+                        scale,
+                    }),
+                    normal_texture: None,
+                }
+            })
+            .collect();
+        for mesh in &scene.meshes {
+            if !mesh.colors.is_empty() {
+                materials[mesh.material].vertex_colors = VertexColors::Multiply;
+            }
+        }
+
+        let meshes = scene.meshes.into_iter().map(|m| {
+            let vertices = (0..m.positions.len())
+                .map(|i| {
+                    let uv = m.uvs.get(i).copied().unwrap_or([0.0, 0.0]);
+                    MVertex {
+                        position: m.positions[i],
+                        uv,
+                        norm: m.normals.get(i).copied().unwrap_or([0.0, 0.0, 0.0]),
+                        color: m.colors.get(i).copied().unwrap_or(WHITE),
+                        // lightmaps without their own uv set share the first one.
+                        uv2: m.uvs2.get(i).copied().unwrap_or(uv),
+                        tangent: [0.0; 3],
+                        bitangent: [0.0; 3],
+                    }
+                })
+                .collect();
+            let indices = (0..m.positions.len() as u32).collect();
+
+            let mut mesh = MeshData::new(m.name, vertices, indices, m.material);
+            if m.normals.is_empty() {
+                mesh.compute_normals();
+            }
+            if m.uvs.is_empty() {
+                mesh.project_uvs();
+            }
+            mesh
+        }).collect();
+
+        let directory = path.as_ref().parent().context("Directory has no parent")?;
+        Ok(Self::new(meshes, materials, directory.to_path_buf()))
+    }
+
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (mut obj_models, obj_materials) = tobj::load_obj(path.as_ref(), &tobj::LoadOptions {
                 triangulate: true,
                 single_index: true,
                 ..Default::default()
             },
-        ).expect("Unable to load model.");
+        ).with_context(|| format!("Unable to load model {:?}.", path.as_ref()))?;
 
-        let obj_materials = obj_materials.expect("Unable to unwrap obj_materials.");
+        // geometry only exports have no mtllib or one that isn't there,
+        // their meshes are drawn with the default material.
+        let obj_materials = obj_materials.unwrap_or_else(|e| {
+            log::warn!("{:?} has no materials ({}), using the default one.", path.as_ref(), e);
+            Vec::new()
+        });
 
         // We're assuming that the texture files are stored with the obj file
         let containing_folder = path.as_ref().parent()
-            .context("Directory has no parent").expect("No parent directory found.");
+            .context("Directory has no parent")?;
 
-        let mut materials = Vec::new();
-        for mat in obj_materials {
-            let diffuse_path = mat.diffuse_texture;
-            let diffuse_texture = Texture::load(device, queue, containing_folder.join(diffuse_path)).expect("Unable to load diffuse texture.");
-
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                ],
-                label: None,
-            });
-
-            materials.push(Material {
+        let mut materials: Vec<MaterialData> = obj_materials.into_iter()
+            .map(|mat| MaterialData {
                 name: mat.name,
-                diffuse_texture,
-                bind_group,
-            });
+                diffuse_texture: if mat.diffuse_texture.is_empty() {
+                    None
+                } else {
+                    Some(PathBuf::from(&mat.diffuse_texture))
+                },
+                // tobj gives black for a missing Kd, keep the usual gray.
+                diffuse_color: DEFAULT_DIFFUSE_COLOR,
+                vertex_colors: VertexColors::Ignore,
+                // obj has no lightmap slot, the ambient map is the usual stand in.
+                // exporters that write the diffuse map there as well are ignored.
+                lightmap_texture: if mat.ambient_texture.is_empty() || mat.ambient_texture == mat.diffuse_texture {
+                    None
+                } else {
+                    Some(PathBuf::from(&mat.ambient_texture))
+                },
+                lightmap_mode: LightmapMode::Multiply,
+                displacement: mat.unknown_param.get("disp").and_then(|value| Displacement::from_mtl(value)),
+                // tobj reads "map_Bump" and "bump" into normal_texture, some exporters write "norm".
+                normal_texture: Some(mat.normal_texture.as_str())
+                    .filter(|value| !value.is_empty())
+                    .or(mat.unknown_param.get("norm").map(String::as_str))
+                    .and_then(texture_from_mtl),
+            })
+            .collect();
+
+        // meshes without a usemtl, or with one the mtl file doesn't have,
+        // get the default material at the end of the list.
+        if obj_models.iter().any(|m| m.mesh.material_id.map_or(materials.is_empty(), |id| id >= materials.len())) {
+            let default = materials.len();
+            for m in &mut obj_models {
+                if m.mesh.material_id.map_or(true, |id| id >= default) {
+                    m.mesh.material_id = Some(default);
+                }
+            }
+            materials.push(Self::default_material());
         }
 
-        let mut meshes = Vec::new();
-        for m in obj_models {
+        // "v x y z r g b" lines tint the texture of the material.
+        for m in &obj_models {
+            if !m.mesh.vertex_color.is_empty() {
+                if let Some(material) = materials.get_mut(m.mesh.material_id.unwrap_or(0)) {
+                    material.vertex_colors = VertexColors::Multiply;
+                }
+            }
+        }
+
+        // every obj model becomes one mesh, they don't depend on each other.
+        let meshes = obj_models.into_par_iter().map(|m| {
+            // exports without "vt" lines get box projected uvs, see MeshData::project_uvs().
+            let has_uvs = m.mesh.texcoords.len() >= m.mesh.positions.len() / 3 * 2;
+            let uv = |i: usize| if has_uvs { [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]] } else { [0.0, 0.0] };
+            // the same for "vn" lines, smooth normals are computed without them.
+            let has_normals = m.mesh.normals.len() == m.mesh.positions.len();
+            let norm = |i: usize| if has_normals {
+                [m.mesh.normals[i * 3], m.mesh.normals[i * 3 + 1], m.mesh.normals[i * 3 + 2]]
+            } else {
+                [0.0, 0.0, 0.0]
+            };
             let mut vertices = Vec::new();
             for i in 0..m.mesh.positions.len() / 3 {
                 vertices.push(MVertex {
@@ -82,51 +1439,405 @@ impl Model {
                         m.mesh.positions[i * 3 + 1],
                         m.mesh.positions[i * 3 + 2],
                     ],
-                    uv: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
-                    norm: [
-//                        m.mesh.normals[i * 3],
-                        0.0,
-//                        m.mesh.normals[i * 3 + 1],
-                        0.0,
-//                        m.mesh.normals[i * 3 + 2],
-                        0.0,
-                    ],
+                    uv: uv(i),
+                    norm: norm(i),
+                    color: if m.mesh.vertex_color.is_empty() {
+                        WHITE
+                    } else {
+                        [
+                            m.mesh.vertex_color[i * 3],
+                            m.mesh.vertex_color[i * 3 + 1],
+                            m.mesh.vertex_color[i * 3 + 2],
+                        ]
+                    },
+                    // obj has a single uv set.
+                    uv2: uv(i),
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
                 });
             }
 
-            let vertex_buffer = device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{:?} Vertex Buffer", path.as_ref())),
-                    contents: bytemuck::cast_slice(&vertices),
-                    usage: wgpu::BufferUsages::VERTEX,
-                }
+            let mut mesh = MeshData::new(
+                m.name,
+                vertices,
+                m.mesh.indices,
+                m.mesh.material_id.unwrap_or(0),
             );
-            let index_buffer = device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{:?} Index Buffer", path.as_ref())),
-                    contents: bytemuck::cast_slice(&m.mesh.indices),
-                    usage: wgpu::BufferUsages::INDEX,
+            if !has_normals {
+                mesh.compute_smooth_normals();
+            }
+            if !has_uvs {
+                log::warn!("{:?} has no texture coordinates, they are projected.", mesh.name);
+                mesh.project_uvs();
+            }
+            mesh
+        }).collect();
+
+        Ok(Self::new(meshes, materials, containing_folder.to_path_buf()))
+    }
+
+    // Displaces the meshes of every material that has a height map.
+    pub fn apply_displacement(&mut self) -> Result<()> {
+        for (index, material) in self.materials.iter().enumerate() {
+            let displacement = match &material.displacement {
+                Some(displacement) => displacement,
+                None => continue,
+            };
+            let path = self.directory.join(&displacement.texture);
+            let height = image::open(&path)
+                .with_context(|| format!("Unable to load displacement map {:?}.", path))?
+                .to_luma8();
+
+            self.meshes.par_iter_mut()
+                .filter(|mesh| mesh.material == index)
+                .for_each(|mesh| mesh.displace(&height, displacement.base, displacement.scale));
+        }
+
+        self.bounds = self.meshes.iter()
+            .fold(Bounds::empty(), |bounds, mesh| bounds.union(&mesh.bounds));
+        Ok(())
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.meshes.iter().map(|mesh| mesh.vertices.len()).sum()
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.meshes.iter().map(|mesh| mesh.num_triangles()).sum()
+    }
+}
+
+impl Model {
+    pub fn load<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+    ) -> Result<Self> {
+        // parsing the obj is slow for big terrains,
+        // so go through the baked cache next to it.
+        let data = crate::baked::load_cached(path)?;
+        Self::from_data(device, queue, layout, &data)
+    }
+
+    // uploads the cpu side model to the gpu.
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        data: &ModelData,
+    ) -> Result<Self> {
+        let textures = data.decode_textures()?;
+        Ok(Self::from_decoded(device, queue, layout, data, textures))
+    }
+
+    // upload only, the textures were decoded before (maybe on another thread).
+    pub fn from_decoded(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        data: &ModelData,
+        textures: Vec<DecodedMaterial>,
+    ) -> Self {
+        let mut materials = Vec::new();
+        for (mat, decoded) in data.materials.iter().zip(textures) {
+            let diffuse = &decoded.diffuse;
+            let diffuse_texture = Texture::from_image(device, queue, &diffuse.image, Some(&diffuse.label))
+                .expect("Unable to upload diffuse texture.");
+
+            let mut material = Material::new(device, layout, &mat.name, diffuse_texture);
+            if mat.vertex_colors != VertexColors::Ignore {
+                material.set_vertex_colors(queue, mat.vertex_colors);
+            }
+            if let Some(lightmap) = &decoded.lightmap {
+                let texture = Texture::from_image(device, queue, &lightmap.image, Some(&lightmap.label))
+                    .expect("Unable to upload lightmap.");
+                material.set_lightmap(device, queue, layout, Some(texture), mat.lightmap_mode);
+            }
+            if let Some(normal) = &decoded.normal {
+                let texture = Texture::from_image_linear(device, queue, &normal.image, Some(&normal.label))
+                    .expect("Unable to upload normal map.");
+                material.set_normal_map(device, queue, layout, Some(texture));
+            }
+            materials.push(material);
+        }
+
+        let meshes = data.meshes.iter()
+            .map(|mesh| Mesh::from_data(device, mesh))
+            .collect();
+
+        Self { meshes, materials, bounds: data.bounds, instances: None }
+    }
+
+    // Stand-in while the real model is loading: a gray box filling `bounds`.
+    // An empty `bounds` gives a model without meshes, which draws nothing.
+    pub fn placeholder(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        bounds: Bounds,
+    ) -> Self {
+        let texture = Texture::from_color(device, queue, [128, 128, 128, 255], "placeholder texture");
+        let material = Material::new(device, layout, "placeholder", texture);
+
+        let meshes = if bounds.is_empty() {
+            Vec::new()
+        } else {
+            vec![Mesh::from_data(device, &MeshData::cuboid("placeholder", &bounds))]
+        };
+
+        Self { meshes, materials: vec![material], bounds, instances: None }
+    }
+
+    // estimated bytes of the buffers and textures of the model on the gpu.
+    pub fn memory_size(&self) -> u64 {
+        self.meshes.iter().map(Mesh::memory_size).sum::<u64>()
+            + self.materials.iter().map(Material::memory_size).sum::<u64>()
+            + self.instances.as_ref().map_or(0, InstanceBuffer::memory_size)
+    }
+
+    // Draws the model once per instance instead of where it was loaded,
+    // in one draw call per mesh. An empty slice goes back to that. Changed
+    // instances are drawn from the next frame on, see inflight.rs.
+    pub fn set_instances(&mut self, device: &wgpu::Device, in_flight: &mut FramesInFlight, instances: &[Instance]) {
+        if instances.is_empty() {
+            self.instances = None;
+            return;
+        }
+        match &mut self.instances {
+            Some(buffer) => buffer.update(device, in_flight, instances),
+            None => self.instances = Some(InstanceBuffer::new(device, instances)),
+        }
+    }
+
+    // The box `bounds` (of a mesh) around every copy of the model, or as
+    // it is without instances. Axis aligned around the turned corners.
+    pub fn instance_bounds(&self, bounds: Bounds) -> Vec<Bounds> {
+        let instances = match &self.instances {
+            Some(buffer) => &buffer.instances,
+            None => return vec![bounds],
+        };
+        instances.iter()
+            .map(|instance| {
+                let matrix = instance.matrix();
+                let mut moved = Bounds::empty();
+                for corner in 0..8 {
+                    let pick = |axis: usize| if corner & (1 << axis) == 0 { bounds.min[axis] } else { bounds.max[axis] };
+                    let point = matrix * cgmath::Vector4::new(pick(0), pick(1), pick(2), 1.0);
+                    moved.extend([point.x, point.y, point.z]);
                 }
-            );
+                moved
+            })
+            .collect()
+    }
+}
+
+// The instances of a model on the gpu, an InstanceRaw each.
+pub struct InstanceBuffer {
+    pub instances: Vec<Instance>,
+    pub buffer: wgpu::Buffer,
+    // in instances, the buffer grows but isn't shrunk.
+    capacity: usize,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device, instances: &[Instance]) -> Self {
+        let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&raw),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        Self { instances: instances.to_vec(), buffer, capacity: instances.len() }
+    }
 
-            meshes.push(Mesh {
-                name: m.name,
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
-            });
+    // A single copy where the model was loaded, drawn with the models
+    // that have no instances of their own.
+    pub fn identity(device: &wgpu::Device) -> Self {
+        Self::new(device, &[Instance::default()])
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, in_flight: &mut FramesInFlight, instances: &[Instance]) {
+        if instances.len() > self.capacity {
+            *self = Self::new(device, instances);
+            return;
         }
+        let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        in_flight.write(device, &self.buffer, 0, bytemuck::cast_slice(&raw));
+        self.instances = instances.to_vec();
+    }
+
+    pub fn len(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn memory_size(&self) -> u64 {
+        (self.capacity * std::mem::size_of::<InstanceRaw>()) as u64
+    }
+}
+
+pub struct DecodedTexture {
+    pub label: String,
+    pub image: image::DynamicImage,
+}
+
+// the decoded textures of one material.
+pub struct DecodedMaterial {
+    pub diffuse: DecodedTexture,
+    pub lightmap: Option<DecodedTexture>,
+    pub normal: Option<DecodedTexture>,
+}
 
-        Ok(Self { meshes, materials })
+impl ModelData {
+    // decoding the images is the slow part, do it on all cores
+    // and only upload them one after the other.
+    pub fn decode_textures(&self) -> Result<Vec<DecodedMaterial>> {
+        self.decode_textures_with_progress(&Progress::new())
+    }
+
+    // decode_textures() for a job: `progress` goes up with every material,
+    // Cancelled once the job is cancelled, see jobs.rs.
+    pub fn decode_textures_with_progress(&self, progress: &Progress) -> Result<Vec<DecodedMaterial>> {
+        let decoded = AtomicUsize::new(0);
+        self.materials.par_iter()
+            .map(|mat| {
+                progress.check()?;
+                let diffuse = match mat.diffuse_path(&self.directory) {
+                    Some(path) => match image::open(&path) {
+                        Ok(image) => DecodedTexture { label: path.to_string_lossy().into_owned(), image },
+                        // a missing texture shouldn't lose the whole model, see fallback.rs.
+                        Err(e) => {
+                            log::warn!("Unable to load diffuse texture {:?}: {}", path, e);
+                            DecodedTexture {
+                                label: format!("{} {}", mat.name, FallbackTexture::Missing.label()),
+                                image: FallbackTexture::Missing.image(),
+                            }
+                        }
+                    },
+                    // materials without texture are drawn in their plain color.
+                    None => {
+                        let [r, g, b] = mat.diffuse_color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                        DecodedTexture {
+                            label: format!("{} default texture", mat.name),
+                            image: FallbackTexture::Solid([r, g, b, 255]).image(),
+                        }
+                    }
+                };
+
+                // without its lightmap the material misses the baked lighting, nothing else.
+                let lightmap = match mat.lightmap_path(&self.directory) {
+                    Some(path) => match image::open(&path) {
+                        Ok(image) => Some(DecodedTexture { label: path.to_string_lossy().into_owned(), image }),
+                        Err(e) => {
+                            log::warn!("Unable to load lightmap {:?}: {}", path, e);
+                            None
+                        }
+                    },
+                    None => None,
+                };
+
+                // flat without its normal map.
+                let normal = match mat.normal_path(&self.directory) {
+                    Some(path) => match image::open(&path) {
+                        Ok(image) => Some(DecodedTexture { label: path.to_string_lossy().into_owned(), image }),
+                        Err(e) => {
+                            log::warn!("Unable to load normal map {:?}: {}", path, e);
+                            None
+                        }
+                    },
+                    None => None,
+                };
+
+                let done = decoded.fetch_add(1, Ordering::Relaxed) + 1;
+                progress.set(done as f32 / self.materials.len() as f32);
+                Ok(DecodedMaterial { diffuse, lightmap, normal })
+            })
+            .collect()
+    }
+}
+
+impl Mesh {
+    pub fn from_data(device: &wgpu::Device, data: &MeshData) -> Self {
+        // STORAGE so compute passes (terrain subdivision) can read the geometry.
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Vertex Buffer", data.name)),
+                contents: bytemuck::cast_slice(&data.vertices),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Index Buffer", data.name)),
+                contents: bytemuck::cast_slice(&data.indices),
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+            }
+        );
+
+        let positions: Vec<[f32; 3]> = data.vertices.iter().map(|vertex| vertex.position).collect();
+        let lods = MeshLod::build(device, &data.name, &positions, &data.indices, &data.bounds);
+
+        let offset_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Offset Buffer", data.name)),
+                contents: bytemuck::cast_slice(&[MeshOffset { offset: [0.0; 3] }]),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        Self {
+            name: data.name.clone(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: data.indices.len() as u32,
+            material: data.material,
+            bounds: data.bounds,
+            positions,
+            uvs: data.vertices.iter().map(|vertex| vertex.uv).collect(),
+            indices: data.indices.clone(),
+            offset: [0.0; 3],
+            offset_buffer,
+            lods,
+        }
+    }
+
+    // bytes of the vertex, index and offset buffer and of the coarser index buffers.
+    pub fn memory_size(&self) -> u64 {
+        let lod_indices: usize = self.lods.iter().map(|lod| lod.num_elements as usize).sum();
+        (self.positions.len() * std::mem::size_of::<MVertex>()
+            + (self.indices.len() + lod_indices) * std::mem::size_of::<u32>()
+            + std::mem::size_of::<MeshOffset>()) as u64
+    }
+
+    // the index buffer and its length to draw the mesh at `tier` with,
+    // the coarsest one that isn't coarser than the tier.
+    pub fn lod(&self, tier: DetailTier) -> (&wgpu::Buffer, u32) {
+        self.lods
+            .iter()
+            .rev()
+            .find(|lod| lod.tier <= tier)
+            .map_or((&self.index_buffer, self.num_elements), |lod| (&lod.index_buffer, lod.num_elements))
+    }
+
+    pub fn set_offset(&mut self, queue: &wgpu::Queue, offset: [f32; 3]) {
+        self.offset = offset;
+        queue.write_buffer(&self.offset_buffer, 0, bytemuck::cast_slice(&[MeshOffset { offset }]));
     }
 }
 
 pub trait DrawModel<'a> {
+    // for the pipelines without the instance buffer, MVertex and MeshOffset only.
     fn draw_mesh(&mut self, mesh: &'a Mesh);
+    // with the triangles of `tier` (see lod.rs), once per instance.
+    fn draw_mesh_lod(&mut self, mesh: &'a Mesh, tier: DetailTier, instances: &'a InstanceBuffer);
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
+        instance_buffer: &'a InstanceBuffer,
         instances: core::ops::Range<u32>,
     );
 }
@@ -136,16 +1847,61 @@ where
     'b: 'a,
 {
     fn draw_mesh(&mut self, mesh: &'b Mesh) {
-        self.draw_mesh_instanced(mesh, 0..1);
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_vertex_buffer(1, mesh.offset_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..mesh.num_elements, 0, 0..1);
+    }
+
+    fn draw_mesh_lod(&mut self, mesh: &'b Mesh, tier: DetailTier, instances: &'b InstanceBuffer) {
+        let (index_buffer, num_elements) = mesh.lod(tier);
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_vertex_buffer(1, mesh.offset_buffer.slice(..));
+        self.set_vertex_buffer(2, instances.buffer.slice(..));
+        self.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.draw_indexed(0..num_elements, 0, 0..instances.len());
     }
 
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'b Mesh,
+        instance_buffer: &'b InstanceBuffer,
         instances: core::ops::Range<u32>,
     ){
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_vertex_buffer(1, mesh.offset_buffer.slice(..));
+        self.set_vertex_buffer(2, instance_buffer.buffer.slice(..));
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_ply(name: &str, faces: &str) -> Result<ModelData> {
+        let text = format!(
+            "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n{}\n",
+            faces,
+        );
+        let path = std::env::temp_dir().join(format!("zhneeshyx-{}-{}.ply", name, std::process::id()));
+        std::fs::write(&path, text)?;
+        let model = ModelData::load_ply(&path);
+        std::fs::remove_file(&path)?;
+        model
+    }
+
+    #[test]
+    fn ply_faces_are_triangles_with_normals() {
+        let model = load_ply("triangle", "3 0 1 2").unwrap();
+        assert_eq!(model.meshes[0].indices, vec![0, 1, 2]);
+        assert_eq!(model.meshes[0].vertices[0].norm, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn ply_face_indices_out_of_range_are_errors() {
+        assert!(load_ply("out-of-range", "3 0 1 7").is_err());
+        assert!(load_ply("one-past-the-end", "3 0 1 3").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window};
+
+/*
+    The monitors connected to the machine, to pick the one the window
+    opens on and the one it goes fullscreen on.
+
+    A monitor is picked by a selector: its number in the list (0 is the
+    first, `zhneeshyx monitors` prints them), its name, or a part of the
+    name, ignoring case, so "dell" finds "DELL U2719D". Monitors come and
+    go, so it's looked up again every time.
+
+    Moving the window to a monitor with another scale factor resizes it
+    in physical pixels: the renderer gets the new size with the
+    ScaleFactorChanged event, and checks the surface against the window
+    again after it moved (Renderer::fit_surface()), for the platforms that
+    don't send one.
+
+    usage:
+        let renderer = RendererBuilder::new().monitor("1").fullscreen(true).build(&event_loop)?;
+        for monitor in renderer.monitors() {
+            println!("{}", monitor);
+        }
+        renderer.set_fullscreen(Some("dell"));
+*/
+
+// size of a window without one on the builder, in logical pixels, as winit picks it.
+pub const DEFAULT_WINDOW_SIZE: LogicalSize<f64> = LogicalSize::new(800.0, 600.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    // the number that selects it.
+    pub index: usize,
+    pub name: String,
+    // of the top left corner on the desktop, and the size, in physical pixels.
+    pub position: [i32; 2],
+    pub size: [u32; 2],
+    pub scale_factor: f64,
+    // in millihertz, None where the platform doesn't tell.
+    pub refresh_rate: Option<u32>,
+    pub primary: bool,
+}
+
+impl fmt::Display for MonitorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} {}x{} at {},{}, scale {}",
+            self.index, self.name, self.size[0], self.size[1], self.position[0], self.position[1], self.scale_factor,
+        )?;
+        if let Some(refresh_rate) = self.refresh_rate {
+            write!(f, ", {:.2} Hz", refresh_rate as f64 / 1000.0)?;
+        }
+        if self.primary {
+            write!(f, " (primary)")?;
+        }
+        Ok(())
+    }
+}
+
+// `monitors` (window.available_monitors() or the event loop's) numbered for the selectors.
+pub fn list(monitors: impl IntoIterator<Item = MonitorHandle>, primary: Option<&MonitorHandle>) -> Vec<MonitorInfo> {
+    monitors
+        .into_iter()
+        .enumerate()
+        .map(|(index, monitor)| {
+            let (position, size) = (monitor.position(), monitor.size());
+            MonitorInfo {
+                index,
+                name: monitor.name().unwrap_or_else(|| format!("Monitor {}", index)),
+                position: [position.x, position.y],
+                size: [size.width, size.height],
+                scale_factor: monitor.scale_factor(),
+                refresh_rate: monitor.refresh_rate_millihertz(),
+                primary: primary == Some(&monitor),
+            }
+        })
+        .collect()
+}
+
+// The monitor `selector` picks out of `monitors`, see above.
+pub fn find(monitors: impl IntoIterator<Item = MonitorHandle>, selector: &str) -> Option<MonitorHandle> {
+    let monitors: Vec<MonitorHandle> = monitors.into_iter().collect();
+    if let Ok(index) = selector.trim().parse::<usize>() {
+        return monitors.get(index).cloned();
+    }
+    let selector = selector.trim().to_lowercase();
+    let name = |monitor: &MonitorHandle| monitor.name().unwrap_or_default().to_lowercase();
+    // the whole name first, "DELL U2719D" shouldn't lose against "DELL U2719D 2".
+    monitors.iter().find(|monitor| name(monitor) == selector)
+        .or_else(|| monitors.iter().find(|monitor| name(monitor).contains(&selector)))
+        .cloned()
+}
+
+// outer position of a window with the inner `size` in the middle of `monitor`.
+pub fn centered(monitor: &MonitorHandle, size: PhysicalSize<u32>) -> PhysicalPosition<i32> {
+    let (origin, monitor_size) = (monitor.position(), monitor.size());
+    PhysicalPosition::new(
+        origin.x + (monitor_size.width as i32 - size.width as i32).max(0) / 2,
+        origin.y + (monitor_size.height as i32 - size.height as i32).max(0) / 2,
+    )
+}
+
+// Moves `window` into the middle of `monitor`, keeping its size. The size
+// in physical pixels changes with the monitor's scale factor once it's there.
+pub fn center_on(window: &Window, monitor: &MonitorHandle) {
+    window.set_outer_position(centered(monitor, window.outer_size()));
+}
+
+// Borderless fullscreen on `monitor`, or on the one the window is on with None.
+pub fn set_fullscreen(window: &Window, monitor: Option<MonitorHandle>) {
+    let monitor = monitor.or_else(|| window.current_monitor());
+    window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+}
+
+// the monitor after the one the window is on, the first one after the last.
+pub fn next(window: &Window) -> Option<MonitorHandle> {
+    let monitors: Vec<MonitorHandle> = window.available_monitors().collect();
+    let current = window.current_monitor()
+        .and_then(|current| monitors.iter().position(|monitor| *monitor == current));
+    match current {
+        Some(index) => monitors.get((index + 1) % monitors.len()).cloned(),
+        None => monitors.first().cloned(),
+    }
+}
@@ -0,0 +1,544 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::model::{Bounds, Model};
+use crate::overlay::Overlay;
+
+/*
+    Navigation mesh: where agents can walk on the loaded terrain, and
+    the shortest way from one point to another.
+
+    Building works on a grid over the ground plane, `cell_size` wide:
+
+    - voxelize: every triangle of the meshes is rasterized into the
+      cells whose centers it covers. A cell keeps the highest surface
+      over it, walkable if that triangle isn't steeper than `max_slope`.
+    - region growing: walkable cells are connected to their four
+      neighbours when the height between them is at most `max_step`.
+      Connected cells form a region, regions smaller than
+      `min_region_cells` (a table top, a roof) are dropped.
+
+    find_path() is A* over the walkable cells, eight neighbours, with
+    diagonal moves only where both cells next to them are walkable so
+    paths don't cut corners. The path is the centers of the cells it
+    crosses, from the exact start to the exact goal.
+
+    draw() shows the walkable cells as a wireframe in the overlay,
+    each region in its own color, and a path on top.
+
+    usage:
+        let navmesh = NavMesh::build(renderer.models(), &NavSettings::default(), |_, _| true);
+        if let Some(path) = navmesh.find_path(start, goal) {
+            navmesh.draw(renderer.plugin_mut::<Overlay>().unwrap(), Some(&path));
+        }
+*/
+
+// overlay layer the navmesh and paths are drawn into.
+pub const OVERLAY_LAYER: &str = "navmesh";
+
+// start and goal of a path, in pixels.
+const PATH_MARKER_SIZE: f32 = 10.0;
+// the wireframe floats a bit above the ground, so the terrain doesn't hide it.
+const DRAW_LIFT: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NavSettings {
+    // width of a grid cell in world units.
+    pub cell_size: f32,
+    // steepest walkable ground, in degrees.
+    pub max_slope: f32,
+    // highest step between two cells an agent can climb.
+    pub max_step: f32,
+    pub min_region_cells: usize,
+}
+
+impl Default for NavSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 1.0,
+            max_slope: 40.0,
+            max_step: 0.5,
+            min_region_cells: 8,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Cell {
+    height: f32,
+    region: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct NavMesh {
+    settings: NavSettings,
+    // x and z of the corner of the first cell.
+    origin: [f32; 2],
+    width: usize,
+    depth: usize,
+    // row by row along z, None where nobody can walk.
+    cells: Vec<Option<Cell>>,
+    regions: usize,
+}
+
+impl NavMesh {
+    // the navmesh of the meshes `filter(model, mesh)` accepts, e.g. the visible ones.
+    pub fn build<F: Fn(usize, usize) -> bool>(models: &[Model], settings: &NavSettings, filter: F) -> Self {
+        let cell_size = settings.cell_size.max(0.01);
+
+        let mut bounds = Bounds::empty();
+        for (model_index, model) in models.iter().enumerate() {
+            for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                if filter(model_index, mesh_index) && !mesh.bounds.is_empty() {
+                    let offset = Vector3::from(mesh.offset);
+                    let min = Vector3::from(mesh.bounds.min) + offset;
+                    let max = Vector3::from(mesh.bounds.max) + offset;
+                    bounds = bounds.union(&Bounds { min: min.into(), max: max.into() });
+                }
+            }
+        }
+        let mut navmesh = Self {
+            settings: *settings,
+            origin: [bounds.min[0], bounds.min[2]],
+            width: 0,
+            depth: 0,
+            cells: Vec::new(),
+            regions: 0,
+        };
+        if bounds.is_empty() {
+            return navmesh;
+        }
+        navmesh.width = ((bounds.max[0] - bounds.min[0]) / cell_size).ceil().max(1.0) as usize;
+        navmesh.depth = ((bounds.max[2] - bounds.min[2]) / cell_size).ceil().max(1.0) as usize;
+
+        // voxelize: the highest surface over every cell, and whether it's walkable.
+        let min_up = settings.max_slope.to_radians().cos();
+        let mut surfaces: Vec<Option<(f32, bool)>> = vec![None; navmesh.width * navmesh.depth];
+        for (model_index, model) in models.iter().enumerate() {
+            for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+                if !filter(model_index, mesh_index) {
+                    continue;
+                }
+                let offset = Vector3::from(mesh.offset);
+                for indices in mesh.indices.chunks_exact(3) {
+                    let corner = |i: usize| Vector3::from(mesh.positions[indices[i] as usize]) + offset;
+                    navmesh.rasterize([corner(0), corner(1), corner(2)], min_up, &mut surfaces);
+                }
+            }
+        }
+        navmesh.cells = surfaces.iter()
+            .map(|surface| match surface {
+                Some((height, true)) => Some(Cell { height: *height, region: usize::MAX }),
+                _ => None,
+            })
+            .collect();
+
+        navmesh.grow_regions();
+        navmesh
+    }
+
+    pub fn settings(&self) -> &NavSettings {
+        &self.settings
+    }
+
+    // connected areas agents can walk around in.
+    pub fn regions(&self) -> usize {
+        self.regions
+    }
+
+    pub fn walkable_cells(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.is_some()).count()
+    }
+
+    // centers of the walkable cells, on the ground. E.g. to place agents.
+    pub fn walkable_points(&self) -> Vec<[f32; 3]> {
+        (0..self.cells.len())
+            .filter(|&index| self.cells[index].is_some())
+            .map(|index| self.center(index))
+            .collect()
+    }
+
+    // height of the walkable ground at `x`/`z`, None where it isn't walkable.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let (cell_x, cell_z) = self.cell_at(x, z)?;
+        self.cell(cell_x, cell_z).map(|cell| cell.height)
+    }
+
+    // region of the walkable ground at `x`/`z`. Two points can only be
+    // connected by a path when they are in the same region.
+    pub fn region_at(&self, x: f32, z: f32) -> Option<usize> {
+        let (cell_x, cell_z) = self.cell_at(x, z)?;
+        self.cell(cell_x, cell_z).map(|cell| cell.region)
+    }
+
+    // shortest walkable way from `start` to `goal`, None when there is none.
+    pub fn find_path(&self, start: [f32; 3], goal: [f32; 3]) -> Option<Vec<[f32; 3]>> {
+        let start_cell = self.cell_at(start[0], start[2])?;
+        let goal_cell = self.cell_at(goal[0], goal[2])?;
+        let start_index = self.index(start_cell.0, start_cell.1);
+        let goal_index = self.index(goal_cell.0, goal_cell.1);
+        if self.cells[start_index]?.region != self.cells[goal_index]?.region {
+            return None;
+        }
+
+        let mut cost = vec![f32::INFINITY; self.cells.len()];
+        let mut came_from = vec![usize::MAX; self.cells.len()];
+        let mut open = BinaryHeap::new();
+        cost[start_index] = 0.0;
+        open.push(Open { index: start_index, estimate: self.estimate(start_index, goal_index) });
+
+        while let Some(Open { index, .. }) = open.pop() {
+            if index == goal_index {
+                break;
+            }
+            for (neighbour, step) in self.neighbours(index) {
+                let through = cost[index] + step;
+                if through < cost[neighbour] {
+                    cost[neighbour] = through;
+                    came_from[neighbour] = index;
+                    open.push(Open { index: neighbour, estimate: through + self.estimate(neighbour, goal_index) });
+                }
+            }
+        }
+        if cost[goal_index].is_infinite() {
+            return None;
+        }
+
+        // back from the goal, the cells in between at their centers.
+        let mut cells = Vec::new();
+        let mut index = came_from[goal_index];
+        while index != usize::MAX && index != start_index {
+            cells.push(index);
+            index = came_from[index];
+        }
+        let mut path = vec![start];
+        path.extend(cells.iter().rev().map(|&index| self.center(index)));
+        path.push(goal);
+        Some(path)
+    }
+
+    // the walkable cells as a wireframe, and `path` on top of it.
+    pub fn draw(&self, overlay: &mut Overlay, path: Option<&[[f32; 3]]>) {
+        // regions by category, the path in the path color, see palette.rs.
+        let palette = overlay.palette().clone();
+        let layer = overlay.layer(OVERLAY_LAYER);
+        layer.clear();
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                let index = self.index(x, z);
+                let cell = match self.cells[index] {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+                let color = palette.category(cell.region);
+                let mut from = self.center(index);
+                from[1] += DRAW_LIFT;
+                // to the connected neighbours in +x and +z, each edge once.
+                for (neighbour_x, neighbour_z) in [(x + 1, z), (x, z + 1)] {
+                    if neighbour_x >= self.width || neighbour_z >= self.depth {
+                        continue;
+                    }
+                    let neighbour = self.index(neighbour_x, neighbour_z);
+                    if self.cells[neighbour].is_some_and(|other| other.region == cell.region) {
+                        let mut to = self.center(neighbour);
+                        to[1] += DRAW_LIFT;
+                        layer.line(from, to, color);
+                    }
+                }
+            }
+        }
+        if let Some(path) = path {
+            let lift = |point: [f32; 3]| [point[0], point[1] + DRAW_LIFT * 2.0, point[2]];
+            for pair in path.windows(2) {
+                layer.line(lift(pair[0]), lift(pair[1]), palette.path);
+            }
+            for point in path.first().iter().chain(path.last().iter()) {
+                layer.marker_at(lift(**point), PATH_MARKER_SIZE, palette.path);
+            }
+        }
+    }
+
+    // removes the navmesh (and paths) from the overlay.
+    pub fn hide(overlay: &mut Overlay) {
+        overlay.layer(OVERLAY_LAYER).clear();
+    }
+
+    fn rasterize(&self, triangle: [Vector3<f32>; 3], min_up: f32, surfaces: &mut [Option<(f32, bool)>]) {
+        let [a, b, c] = triangle;
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude2() <= f32::EPSILON {
+            return;
+        }
+        // both windings count, a triangle facing down is still a floor seen from above.
+        let walkable = normal.normalize().y.abs() >= min_up;
+
+        let cell_size = self.settings.cell_size.max(0.01);
+        let to_cell = |value: f32, origin: f32| ((value - origin) / cell_size - 0.5).ceil().max(0.0) as usize;
+        let min_x = to_cell(a.x.min(b.x).min(c.x), self.origin[0]);
+        let min_z = to_cell(a.z.min(b.z).min(c.z), self.origin[1]);
+        let max_x = a.x.max(b.x).max(c.x);
+        let max_z = a.z.max(b.z).max(c.z);
+
+        // the plane of the triangle over the ground, for the height at the cell centers.
+        let area = (b.x - a.x) * (c.z - a.z) - (c.x - a.x) * (b.z - a.z);
+        if area.abs() <= f32::EPSILON {
+            // a vertical wall, it covers no cell.
+            return;
+        }
+        for z in min_z..self.depth {
+            let center_z = self.origin[1] + (z as f32 + 0.5) * cell_size;
+            if center_z > max_z {
+                break;
+            }
+            for x in min_x..self.width {
+                let center_x = self.origin[0] + (x as f32 + 0.5) * cell_size;
+                if center_x > max_x {
+                    break;
+                }
+                // barycentric coordinates in the ground plane.
+                let v = ((center_x - a.x) * (c.z - a.z) - (c.x - a.x) * (center_z - a.z)) / area;
+                let w = ((b.x - a.x) * (center_z - a.z) - (center_x - a.x) * (b.z - a.z)) / area;
+                let u = 1.0 - v - w;
+                if u < 0.0 || v < 0.0 || w < 0.0 {
+                    continue;
+                }
+                let height = a.y * u + b.y * v + c.y * w;
+                let surface = &mut surfaces[self.index(x, z)];
+                if surface.map_or(true, |(top, _)| height > top) {
+                    *surface = Some((height, walkable));
+                }
+            }
+        }
+    }
+
+    // flood fills the connected cells, small regions are dropped.
+    fn grow_regions(&mut self) {
+        let mut region = 0;
+        for start in 0..self.cells.len() {
+            if self.cells[start].map_or(true, |cell| cell.region != usize::MAX) {
+                continue;
+            }
+            let mut members = vec![start];
+            let mut queue = VecDeque::from(vec![start]);
+            if let Some(cell) = &mut self.cells[start] {
+                cell.region = region;
+            }
+            while let Some(index) = queue.pop_front() {
+                for neighbour in self.straight_neighbours(index) {
+                    if let Some(cell) = &mut self.cells[neighbour] {
+                        if cell.region == usize::MAX {
+                            cell.region = region;
+                            members.push(neighbour);
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+            }
+            if members.len() < self.settings.min_region_cells {
+                for index in members {
+                    self.cells[index] = None;
+                }
+            } else {
+                region += 1;
+            }
+        }
+        self.regions = region;
+    }
+
+    fn index(&self, x: usize, z: usize) -> usize {
+        z * self.width + x
+    }
+
+    fn cell(&self, x: usize, z: usize) -> Option<Cell> {
+        self.cells[self.index(x, z)]
+    }
+
+    fn cell_at(&self, x: f32, z: f32) -> Option<(usize, usize)> {
+        let cell_size = self.settings.cell_size.max(0.01);
+        let x = ((x - self.origin[0]) / cell_size).floor();
+        let z = ((z - self.origin[1]) / cell_size).floor();
+        if x < 0.0 || z < 0.0 || x >= self.width as f32 || z >= self.depth as f32 {
+            return None;
+        }
+        Some((x as usize, z as usize))
+    }
+
+    fn center(&self, index: usize) -> [f32; 3] {
+        let cell_size = self.settings.cell_size.max(0.01);
+        let (x, z) = (index % self.width, index / self.width);
+        [
+            self.origin[0] + (x as f32 + 0.5) * cell_size,
+            self.cells[index].map_or(0.0, |cell| cell.height),
+            self.origin[1] + (z as f32 + 0.5) * cell_size,
+        ]
+    }
+
+    // whether an agent can go from cell `from` straight to `to`.
+    fn connected(&self, from: usize, to: usize) -> bool {
+        match (self.cells[from], self.cells[to]) {
+            (Some(from), Some(to)) => (from.height - to.height).abs() <= self.settings.max_step,
+            _ => false,
+        }
+    }
+
+    // the connected cells left, right, in front and behind.
+    fn straight_neighbours(&self, index: usize) -> Vec<usize> {
+        let (x, z) = (index % self.width, index / self.width);
+        let mut neighbours = Vec::with_capacity(4);
+        if x > 0 {
+            neighbours.push(index - 1);
+        }
+        if x + 1 < self.width {
+            neighbours.push(index + 1);
+        }
+        if z > 0 {
+            neighbours.push(index - self.width);
+        }
+        if z + 1 < self.depth {
+            neighbours.push(index + self.width);
+        }
+        neighbours.retain(|&neighbour| self.connected(index, neighbour));
+        neighbours
+    }
+
+    // the connected cells around `index` with the length of the step there.
+    fn neighbours(&self, index: usize) -> Vec<(usize, f32)> {
+        let cell_size = self.settings.cell_size.max(0.01);
+        let straight = self.straight_neighbours(index);
+        let mut neighbours: Vec<(usize, f32)> = straight.iter().map(|&neighbour| (neighbour, cell_size)).collect();
+
+        // diagonals, only around corners that are walkable on both sides.
+        let (x, z) = ((index % self.width) as i64, (index / self.width) as i64);
+        for (dx, dz) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0 || nz < 0 || nx >= self.width as i64 || nz >= self.depth as i64 {
+                continue;
+            }
+            let side_x = self.index(nx as usize, z as usize);
+            let side_z = self.index(x as usize, nz as usize);
+            let diagonal = self.index(nx as usize, nz as usize);
+            if straight.contains(&side_x) && straight.contains(&side_z)
+                && self.connected(side_x, diagonal) && self.connected(side_z, diagonal)
+            {
+                neighbours.push((diagonal, cell_size * std::f32::consts::SQRT_2));
+            }
+        }
+        neighbours
+    }
+
+    // octile distance, never more than the real way.
+    fn estimate(&self, from: usize, to: usize) -> f32 {
+        let dx = (from % self.width).abs_diff(to % self.width) as f32;
+        let dz = (from / self.width).abs_diff(to / self.width) as f32;
+        let cell_size = self.settings.cell_size.max(0.01);
+        (dx.max(dz) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dz)) * cell_size
+    }
+}
+
+// a cell waiting in the A* queue, the lowest estimate comes out first.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Open {
+    index: usize,
+    estimate: f32,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a navmesh from a map, one row per z: '.' is walkable ground,
+    // a digit is ground that high, anything else is blocked.
+    fn grid(rows: &[&str], min_region_cells: usize) -> NavMesh {
+        let cells = rows.iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '.' => Some(Cell { height: 0.0, region: usize::MAX }),
+                digit if digit.is_ascii_digit() => Some(Cell { height: digit.to_digit(10).unwrap() as f32, region: usize::MAX }),
+                _ => None,
+            })
+            .collect();
+        let mut navmesh = NavMesh {
+            settings: NavSettings { min_region_cells, ..NavSettings::default() },
+            origin: [0.0, 0.0],
+            width: rows[0].len(),
+            depth: rows.len(),
+            cells,
+            regions: 0,
+        };
+        navmesh.grow_regions();
+        navmesh
+    }
+
+    fn at(x: usize, z: usize) -> [f32; 3] {
+        [x as f32 + 0.5, 0.0, z as f32 + 0.5]
+    }
+
+    fn length(path: &[[f32; 3]]) -> f32 {
+        path.windows(2)
+            .map(|pair| (Vector3::from(pair[1]) - Vector3::from(pair[0])).magnitude())
+            .sum()
+    }
+
+    #[test]
+    fn open_ground_is_a_straight_diagonal() {
+        let navmesh = grid(&["....", "....", "....", "...."], 1);
+        let path = navmesh.find_path(at(0, 0), at(3, 3)).unwrap();
+        assert_eq!(path.len(), 4);
+        assert!((length(&path) - 3.0 * std::f32::consts::SQRT_2).abs() < 1e-4);
+        assert_eq!(navmesh.find_path(at(1, 2), at(1, 2)).unwrap(), vec![at(1, 2), at(1, 2)]);
+    }
+
+    #[test]
+    fn paths_go_around_walls_not_through_corners() {
+        let navmesh = grid(&[
+            ".....",
+            "####.",
+            ".....",
+        ], 1);
+        assert_eq!(navmesh.regions(), 1);
+        let path = navmesh.find_path(at(0, 0), at(0, 2)).unwrap();
+        // along the top row, down past the end of the wall and back. No
+        // diagonal around the end of the wall, that would cut its corner.
+        assert!(path.iter().any(|point| point[0] > 4.0));
+        assert!((length(&path) - 10.0).abs() < 1e-4);
+        // no cell of the path is blocked.
+        for point in &path {
+            assert!(navmesh.height_at(point[0], point[2]).is_some());
+        }
+    }
+
+    #[test]
+    fn steps_split_regions_and_block_paths() {
+        let navmesh = grid(&["..5..", "..5..", "..5.."], 1);
+        assert_eq!(navmesh.regions(), 3);
+        assert_ne!(navmesh.region_at(0.5, 0.5), navmesh.region_at(4.5, 0.5));
+        assert!(navmesh.find_path(at(0, 0), at(4, 0)).is_none());
+        // outside the grid.
+        assert!(navmesh.find_path(at(0, 0), [-3.0, 0.0, 0.5]).is_none());
+        assert_eq!(navmesh.height_at(2.5, 1.5), Some(5.0));
+    }
+
+    #[test]
+    fn small_regions_are_dropped() {
+        let navmesh = grid(&["...#.", "...#."], 4);
+        assert_eq!(navmesh.regions(), 1);
+        assert_eq!(navmesh.walkable_cells(), 6);
+        assert!(navmesh.height_at(4.5, 0.5).is_none());
+    }
+}
@@ -0,0 +1,744 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+
+use cgmath::{Matrix4, Vector4};
+use embedded_graphics::{
+    mono_font::{iso_8859_1::FONT_9X15, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::atlas::{AtlasBuilder, AtlasRect};
+use crate::locale::Strings;
+use crate::palette::DebugPalette;
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+use crate::vertex::{LineVertex, SpriteInstance, Vertex};
+
+/*
+    Debug overlay: lines in world space and text on top of the frame.
+
+    Everything is collected by the application between frames and
+    stays until it is cleared, so tools only have to redraw when
+    something changes. Each tool draws into its own layer, which it
+    can clear without wiping what the others show.
+
+    Text and sprites are quads out of one texture atlas (see atlas.rs):
+    the glyphs of a bitmap font, a round marker and the images the
+    application added with add_sprite(). So all of them go into one
+    draw call, however many labels and markers there are. The atlas
+    is packed again when a sprite is added. Text and sprites can be
+    placed in pixels or at a world position, which follows the camera.
+
+    On high-dpi screens the whole overlay is scaled up: positions and
+    sizes on the screen are in ui pixels, each of them scale() screen
+    pixels wide and high. The scale is the window's scale factor (which the
+    renderer passes to the plugins) times the ui scale the user picked
+    with set_ui_scale(), rounded to a whole number. The font is a bitmap
+    one, so this way every font pixel stays a square of whole screen
+    pixels, also on displays with fractional scale factors like 1.25 or
+    1.5, where it would otherwise get blurry or uneven. The atlas is
+    sampled without filtering for the same reason.
+
+    usage:
+        renderer.add_plugin(Overlay::new());
+        let overlay = renderer.plugin_mut::<Overlay>().unwrap();
+        overlay.add_sprite("pin", image::open("pin.png")?.to_rgba8());
+        let layer = overlay.layer("debug");
+        layer.clear();
+        layer.line([0.0; 3], [1.0, 2.0, 0.0], [1.0, 1.0, 0.0, 1.0]);
+        layer.text_at([1.0, 2.0, 0.0], "2.24 m", [1.0; 4]);
+        layer.sprite_at([1.0, 2.0, 0.0], "pin", [16.0, 16.0], [1.0; 4]);
+        layer.marker_at([0.0; 3], 8.0, [1.0, 0.0, 0.0, 1.0]);
+*/
+
+// the atlas doesn't grow beyond it.
+const MAX_ATLAS_SIZE: u32 = 4096;
+// cell of a glyph of FONT_9X15, the text advances by it.
+pub const GLYPH_WIDTH: f32 = 9.0;
+pub const GLYPH_HEIGHT: f32 = 15.0;
+// names of what the overlay packs into the atlas itself.
+const WHITE: &str = "overlay/white";
+const MARKER: &str = "overlay/marker";
+const MARKER_SIZE: u32 = 32;
+// text placed at a world position starts a bit to the right of it.
+const LABEL_OFFSET: i32 = 8;
+// dark box behind the text, so it stays readable on bright scenes.
+const LABEL_PADDING: i32 = 3;
+const LABEL_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 160.0 / 255.0];
+// range of set_ui_scale().
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone)]
+enum Anchor {
+    // ui pixels from the top left corner.
+    Screen(f32, f32),
+    World([f32; 3]),
+}
+
+struct Label {
+    anchor: Anchor,
+    text: String,
+    color: [f32; 4],
+}
+
+struct Sprite {
+    // the center for world positions, the top left corner for screen positions.
+    anchor: Anchor,
+    name: String,
+    // in ui pixels.
+    size: [f32; 2],
+    color: [f32; 4],
+}
+
+// DrawTarget for embedded-graphics, writing the glyphs into an rgba image.
+struct Canvas<'a> {
+    image: &'a mut image::RgbaImage,
+    alpha: u8,
+}
+
+impl OriginDimensions for Canvas<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.image.width(), self.image.height())
+    }
+}
+
+impl DrawTarget for Canvas<'_> {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (width, height) = self.image.dimensions();
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 && (point.x as u32) < width && (point.y as u32) < height {
+                let rgba = image::Rgba([color.r(), color.g(), color.b(), self.alpha]);
+                self.image.put_pixel(point.x as u32, point.y as u32, rgba);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct GpuState {
+    line_pipeline: wgpu::RenderPipeline,
+    line_buffer: Option<wgpu::Buffer>,
+    // in vertices.
+    line_capacity: usize,
+    sprite_pipeline: wgpu::RenderPipeline,
+    sprite_buffer: Option<wgpu::Buffer>,
+    // in instances.
+    sprite_capacity: usize,
+    atlas_layout: wgpu::BindGroupLayout,
+    // nearest, the quads are whole multiples of the atlas pixels.
+    atlas_sampler: wgpu::Sampler,
+    // None until the atlas is packed, and again when a sprite was added.
+    atlas_bind_group: Option<wgpu::BindGroup>,
+}
+
+// The scale of the window and the one the user picked, see above.
+#[derive(Debug, Copy, Clone)]
+struct UiScale {
+    window: f32,
+    user: f32,
+}
+
+impl Default for UiScale {
+    fn default() -> Self {
+        Self { window: 1.0, user: 1.0 }
+    }
+}
+
+// Lines, text and sprites that belong together, e.g. everything a tool
+// shows, so it can be cleared without touching the rest of the overlay.
+#[derive(Default)]
+pub struct OverlayLayer {
+    lines: Vec<LineVertex>,
+    labels: Vec<Label>,
+    sprites: Vec<Sprite>,
+}
+
+impl OverlayLayer {
+    // removes all lines, text and sprites of this layer.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.labels.clear();
+        self.sprites.clear();
+    }
+
+    // colors are rgba, 0-1.
+    pub fn line(&mut self, from: [f32; 3], to: [f32; 3], color: [f32; 4]) {
+        self.lines.push(LineVertex { position: from, color });
+        self.lines.push(LineVertex { position: to, color });
+    }
+
+    // three small axis aligned lines crossing at `position`, to mark a point.
+    pub fn cross(&mut self, position: [f32; 3], size: f32, color: [f32; 4]) {
+        let half = size * 0.5;
+        for axis in 0..3 {
+            let mut from = position;
+            let mut to = position;
+            from[axis] -= half;
+            to[axis] += half;
+            self.line(from, to, color);
+        }
+    }
+
+    // text with its top left corner at x/y ui pixels, \n starts a new line.
+    pub fn text(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        self.labels.push(Label { anchor: Anchor::Screen(x, y), text: text.to_string(), color });
+    }
+
+    // text next to a point in the scene, hidden while the point is behind the camera.
+    pub fn text_at(&mut self, position: [f32; 3], text: &str, color: [f32; 4]) {
+        self.labels.push(Label { anchor: Anchor::World(position), text: text.to_string(), color });
+    }
+
+    // sprite `name` (see Overlay::add_sprite()) with its top left corner at x/y ui pixels,
+    // `size` ui pixels big. The color tints it.
+    pub fn sprite(&mut self, x: f32, y: f32, name: &str, size: [f32; 2], color: [f32; 4]) {
+        self.sprites.push(Sprite { anchor: Anchor::Screen(x, y), name: name.to_string(), size, color });
+    }
+
+    // a filled rectangle with its top left corner at x/y ui pixels.
+    pub fn rect(&mut self, x: f32, y: f32, size: [f32; 2], color: [f32; 4]) {
+        self.sprite(x, y, WHITE, size, color);
+    }
+
+    // a sprite centered on a point in the scene, facing the camera at the same
+    // size in ui pixels however far away. Hidden while the point is behind the camera.
+    pub fn sprite_at(&mut self, position: [f32; 3], name: &str, size: [f32; 2], color: [f32; 4]) {
+        self.sprites.push(Sprite { anchor: Anchor::World(position), name: name.to_string(), size, color });
+    }
+
+    // a round dot `size` ui pixels across, to mark a point.
+    pub fn marker_at(&mut self, position: [f32; 3], size: f32, color: [f32; 4]) {
+        self.sprite_at(position, MARKER, [size, size], color);
+    }
+}
+
+#[derive(Default)]
+pub struct Overlay {
+    // drawn in the order of their names.
+    layers: BTreeMap<String, OverlayLayer>,
+    hidden: bool,
+    // images of add_sprite(), packed with the glyphs.
+    sprite_images: BTreeMap<String, image::RgbaImage>,
+    // where the glyphs and sprites are in the packed atlas.
+    glyphs: HashMap<char, AtlasRect>,
+    sprites: HashMap<String, AtlasRect>,
+    // the lines and quads of all layers, as uploaded.
+    lines: Vec<LineVertex>,
+    instances: Vec<SpriteInstance>,
+    gpu: Option<GpuState>,
+    // the language of the tools' text, see locale.rs.
+    strings: Strings,
+    // and the colors they draw with, see palette.rs.
+    palette: DebugPalette,
+    scale: UiScale,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the layer called `name`, created when it doesn't exist yet.
+    pub fn layer(&mut self, name: &str) -> &mut OverlayLayer {
+        self.layers.entry(name.to_string()).or_default()
+    }
+
+    // removes all lines, text and sprites of all layers.
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    // an image layers can draw with sprite() and sprite_at(), packed into the
+    // atlas before the next frame. Adding a name again replaces the image.
+    pub fn add_sprite(&mut self, name: &str, image: image::RgbaImage) {
+        self.sprite_images.insert(name.to_string(), image);
+        if let Some(gpu) = &mut self.gpu {
+            gpu.atlas_bind_group = None;
+        }
+    }
+
+    // the functions below draw into the default layer, called "".
+
+    pub fn line(&mut self, from: [f32; 3], to: [f32; 3], color: [f32; 4]) {
+        self.layer("").line(from, to, color);
+    }
+
+    pub fn cross(&mut self, position: [f32; 3], size: f32, color: [f32; 4]) {
+        self.layer("").cross(position, size, color);
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        self.layer("").text(x, y, text, color);
+    }
+
+    pub fn text_at(&mut self, position: [f32; 3], text: &str, color: [f32; 4]) {
+        self.layer("").text_at(position, text, color);
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.hidden = !visible;
+    }
+
+    // The text is drawn as given, tools look it up here before, so
+    // what's on the overlay only changes language as it's redrawn.
+    pub fn set_strings(&mut self, strings: Strings) {
+        self.strings = strings;
+    }
+
+    pub fn strings(&self) -> &Strings {
+        &self.strings
+    }
+
+    // Like set_strings(), the tools take their colors from it as they redraw.
+    pub fn set_palette(&mut self, palette: DebugPalette) {
+        self.palette = palette;
+    }
+
+    pub fn palette(&self) -> &DebugPalette {
+        &self.palette
+    }
+
+    pub fn visible(&self) -> bool {
+        !self.hidden
+    }
+
+    // of the window, the renderer passes it every frame, see plugin.rs.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale.window = scale_factor as f32;
+    }
+
+    // Makes the overlay bigger or smaller than the window's scale factor
+    // alone would, clamped to MIN_UI_SCALE - MAX_UI_SCALE.
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.scale.user = ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.scale.user
+    }
+
+    // screen pixels per ui pixel, a whole number, see above.
+    pub fn scale(&self) -> f32 {
+        (self.scale.window * self.scale.user).round().max(1.0)
+    }
+
+    // a `width` x `height` frame in ui pixels, to place text at its edges.
+    pub fn ui_size(&self, width: u32, height: u32) -> [f32; 2] {
+        let scale = self.scale();
+        [(width as f32 / scale).floor(), (height as f32 / scale).floor()]
+    }
+
+    // the quads of all sprites and labels, in clip space, for a `width` x `height` frame.
+    fn place_quads(&mut self, view_proj: Matrix4<f32>, width: u32, height: u32) {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        // everything below is in screen pixels.
+        let scale = self.scale();
+        let (glyph_width, glyph_height) = (GLYPH_WIDTH * scale, GLYPH_HEIGHT * scale);
+        // -> screen pixels, whole ones so the glyphs stay sharp.
+        let project = |anchor: Anchor| match anchor {
+            Anchor::Screen(x, y) => Some(((x * scale).round(), (y * scale).round())),
+            Anchor::World(position) => {
+                let clip = view_proj * Vector4::new(position[0], position[1], position[2], 1.0);
+                if clip.w <= 0.0 {
+                    return None;
+                }
+                let x = (clip.x / clip.w * 0.5 + 0.5) * width;
+                let y = (0.5 - clip.y / clip.w * 0.5) * height;
+                Some((x.round(), y.round()))
+            }
+        };
+        let quad = |x: f32, y: f32, size: [f32; 2], rect: &AtlasRect, color: [f32; 4]| SpriteInstance {
+            rect: [
+                x / width * 2.0 - 1.0,
+                1.0 - y / height * 2.0,
+                (x + size[0]) / width * 2.0 - 1.0,
+                1.0 - (y + size[1]) / height * 2.0,
+            ],
+            uv: [rect.uv_min[0], rect.uv_min[1], rect.uv_max[0], rect.uv_max[1]],
+            color,
+        };
+
+        self.instances.clear();
+        let white = match self.sprites.get(WHITE) {
+            Some(white) => *white,
+            None => return,
+        };
+        for layer in self.layers.values() {
+            for sprite in &layer.sprites {
+                let rect = match self.sprites.get(&sprite.name) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                if let Some((x, y)) = project(sprite.anchor) {
+                    let size = [(sprite.size[0] * scale).round(), (sprite.size[1] * scale).round()];
+                    let (x, y) = match sprite.anchor {
+                        Anchor::Screen(..) => (x, y),
+                        Anchor::World(_) => ((x - size[0] * 0.5).round(), (y - size[1] * 0.5).round()),
+                    };
+                    self.instances.push(quad(x, y, size, rect, sprite.color));
+                }
+            }
+
+            for label in &layer.labels {
+                let (x, y) = match (project(label.anchor), label.anchor) {
+                    (Some((x, y)), Anchor::Screen(..)) => (x, y),
+                    (Some((x, y)), Anchor::World(_)) => (x + LABEL_OFFSET as f32 * scale, y - LABEL_OFFSET as f32 * scale),
+                    (None, _) => continue,
+                };
+                let rows = label.text.split('\n').count() as f32;
+                let columns = label.text.split('\n').map(|line| line.chars().count()).max().unwrap_or(0) as f32;
+                let padding = LABEL_PADDING as f32 * scale;
+                self.instances.push(quad(
+                    x - padding,
+                    y - padding,
+                    [columns * glyph_width + 2.0 * padding, rows * glyph_height + 2.0 * padding],
+                    &white,
+                    LABEL_BACKGROUND,
+                ));
+
+                for (row, line) in label.text.split('\n').enumerate() {
+                    for (column, c) in line.chars().enumerate() {
+                        // the font draws a ? for what it doesn't have.
+                        let glyph = match self.glyphs.get(&c).or_else(|| self.glyphs.get(&'?')) {
+                            Some(glyph) => glyph,
+                            None => continue,
+                        };
+                        let glyph_x = x + column as f32 * glyph_width;
+                        let glyph_y = y + row as f32 * glyph_height;
+                        self.instances.push(quad(glyph_x, glyph_y, [glyph_width, glyph_height], glyph, label.color));
+                    }
+                }
+            }
+        }
+    }
+
+    // packs the glyphs, the marker and the sprites again and uploads them.
+    fn create_atlas(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<()> {
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => return Ok(()),
+        };
+
+        let mut builder = AtlasBuilder::new(MAX_ATLAS_SIZE);
+        let glyph_chars = (32u8..127).chain(160..=255).map(char::from);
+        for c in glyph_chars.clone() {
+            builder.add(&glyph_name(c), glyph_image(c));
+        }
+        builder.add(WHITE, image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])));
+        builder.add(MARKER, marker_image(MARKER_SIZE));
+        for (name, image) in &self.sprite_images {
+            builder.add(name, image.clone());
+        }
+        let atlas = builder.build()?;
+
+        self.glyphs = glyph_chars
+            .filter_map(|c| atlas.rect(&glyph_name(c)).map(|rect| (c, *rect)))
+            .collect();
+        self.sprites = atlas.rects()
+            .filter(|(name, _)| !name.starts_with(GLYPH_PREFIX))
+            .map(|(name, rect)| (name.to_string(), *rect))
+            .collect();
+
+        let texture = atlas.upload(device, queue, "Overlay Atlas")?;
+        gpu.atlas_bind_group = Some(create_atlas_bind_group(device, &gpu.atlas_layout, &texture.view, &gpu.atlas_sampler));
+        Ok(())
+    }
+}
+
+const GLYPH_PREFIX: &str = "overlay/glyph/";
+
+fn glyph_name(c: char) -> String {
+    format!("{}{}", GLYPH_PREFIX, c as u32)
+}
+
+// `c` white on transparent, one glyph cell big.
+fn glyph_image(c: char) -> image::RgbaImage {
+    let mut image = image::RgbaImage::new(GLYPH_WIDTH as u32, GLYPH_HEIGHT as u32);
+    let style = MonoTextStyle::new(&FONT_9X15, Rgb888::WHITE);
+    let mut buffer = [0u8; 4];
+    let text = Text::with_baseline(c.encode_utf8(&mut buffer), Point::zero(), style, Baseline::Top);
+    let mut canvas = Canvas { image: &mut image, alpha: 255 };
+    // drawing into an image can't fail.
+    let _ = text.draw(&mut canvas);
+    image
+}
+
+// a white dot with a soft edge, `size` pixels across.
+fn marker_image(size: u32) -> image::RgbaImage {
+    let radius = size as f32 * 0.5;
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let dx = x as f32 + 0.5 - radius;
+        let dy = y as f32 + 0.5 - radius;
+        let coverage = (radius - (dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+        image::Rgba([255, 255, 255, (coverage * 255.0).round() as u8])
+    })
+}
+
+fn create_atlas_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("overlay_atlas_bind_group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    entry_points: (&str, &str),
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    topology: wgpu::PrimitiveTopology,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Overlay Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: entry_points.0,
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: entry_points.1,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        // always on top of the scene.
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+impl RenderPlugin for Overlay {
+    fn name(&self) -> &str {
+        "Overlay"
+    }
+
+    fn setup(&mut self, ctx: &PluginContext) {
+        let device = ctx.device;
+        self.set_scale_factor(ctx.scale_factor);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("overlay_shader.wgsl").into()),
+        });
+
+        let line_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Line Pipeline Layout"),
+            bind_group_layouts: &[ctx.camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let line_pipeline = create_pipeline(
+            device,
+            &line_layout,
+            &shader,
+            ctx.config.format,
+            ("vs_line", "fs_line"),
+            &[LineVertex::desc()],
+            wgpu::PrimitiveTopology::LineList,
+        );
+
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("overlay_atlas_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sprite_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Sprite Pipeline Layout"),
+            bind_group_layouts: &[&atlas_layout],
+            push_constant_ranges: &[],
+        });
+        let sprite_pipeline = create_pipeline(
+            device,
+            &sprite_pipeline_layout,
+            &shader,
+            ctx.config.format,
+            ("vs_sprite", "fs_sprite"),
+            &[SpriteInstance::desc()],
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+        let atlas_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Overlay Atlas Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        self.gpu = Some(GpuState {
+            line_pipeline,
+            line_buffer: None,
+            line_capacity: 0,
+            sprite_pipeline,
+            sprite_buffer: None,
+            sprite_capacity: 0,
+            atlas_layout,
+            atlas_sampler,
+            atlas_bind_group: None,
+        });
+    }
+
+    // the scale factor changes when the window moves to another monitor.
+    fn update(&mut self, ctx: &PluginContext) {
+        self.set_scale_factor(ctx.scale_factor);
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    ) {
+        let needs_atlas = match &self.gpu {
+            Some(gpu) => gpu.atlas_bind_group.is_none(),
+            None => return,
+        };
+        if self.hidden {
+            return;
+        }
+        if needs_atlas {
+            if let Err(e) = self.create_atlas(ctx.device, ctx.queue) {
+                log::error!("Unable to pack the overlay atlas: {:?}", e);
+                // only the lines then, until the sprites change.
+                self.glyphs.clear();
+                self.sprites.clear();
+            }
+        }
+
+        self.place_quads(ctx.camera.build_view_projection_matrix(), targets.width, targets.height);
+
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+
+        self.lines.clear();
+        for layer in self.layers.values() {
+            self.lines.extend_from_slice(&layer.lines);
+        }
+        if !self.lines.is_empty() {
+            // grows, but is never shrunk.
+            if self.lines.len() > gpu.line_capacity {
+                gpu.line_capacity = self.lines.len().next_power_of_two();
+                gpu.line_buffer = Some(ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Overlay Line Buffer"),
+                    size: (gpu.line_capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+            if let Some(buffer) = &gpu.line_buffer {
+                ctx.queue.write_buffer(buffer, 0, bytemuck::cast_slice(&self.lines));
+            }
+        }
+
+        if !self.instances.is_empty() {
+            if self.instances.len() > gpu.sprite_capacity {
+                gpu.sprite_capacity = self.instances.len().next_power_of_two();
+                gpu.sprite_buffer = Some(ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Overlay Sprite Buffer"),
+                    size: (gpu.sprite_capacity * std::mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+            if let Some(buffer) = &gpu.sprite_buffer {
+                ctx.queue.write_buffer(buffer, 0, bytemuck::cast_slice(&self.instances));
+            }
+        }
+
+        if self.lines.is_empty() && self.instances.is_empty() {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: targets.color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        if let (false, Some(buffer)) = (self.lines.is_empty(), &gpu.line_buffer) {
+            render_pass.set_pipeline(&gpu.line_pipeline);
+            render_pass.set_bind_group(0, targets.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..self.lines.len() as u32, 0..1);
+        }
+
+        // every glyph and sprite in one draw.
+        if let (false, Some(buffer), Some(bind_group)) =
+            (self.instances.is_empty(), &gpu.sprite_buffer, &gpu.atlas_bind_group)
+        {
+            render_pass.set_pipeline(&gpu.sprite_pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffer.slice(..));
+            render_pass.draw(0..6, 0..self.instances.len() as u32);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Debug lines in world space, text and sprites out of the atlas on top of them.
+
+//
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) //
+var<uniform> camera: CameraUniform;
+
+// Lines
+
+struct LineInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct LineOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_line(line: LineInput) -> LineOutput {
+    var out: LineOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(line.position, 1.0);
+    out.color = line.color;
+    return out;
+}
+
+@fragment
+fn fs_line(in: LineOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+
+// Text and sprites
+
+@group(0) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(0) @binding(1)
+var s_atlas: sampler;
+
+struct SpriteInput {
+    // top left and bottom right corner, in clip space.
+    @location(0) rect: vec4<f32>,
+    @location(1) uv: vec4<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct SpriteOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+// six vertices per instance, two triangles of the quad.
+@vertex
+fn vs_sprite(@builtin(vertex_index) vertex_index: u32, sprite: SpriteInput) -> SpriteOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: SpriteOutput;
+    out.clip_position = vec4<f32>(mix(sprite.rect.xy, sprite.rect.zw, corner), 0.0, 1.0);
+    out.uv = mix(sprite.uv.xy, sprite.uv.zw, corner);
+    out.color = sprite.color;
+    return out;
+}
+
+// glyphs are white in the atlas, the color tints them.
+@fragment
+fn fs_sprite(in: SpriteOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_atlas, s_atlas, in.uv) * in.color;
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+/*
+    Per instance material overrides.
+
+    Every model in the scene is an instance of its file (duplicates and
+    prefab instances load the file again). An override draws one of them,
+    or one of its meshes, with another material, e.g. a highlighted copy
+    or a red variant of a car, without touching the model's own materials
+    and without another set of vertex buffers.
+
+    The materials are the renderer's own (Renderer::add_material()), the
+    table only holds their indices and is looked at while drawing, for
+    every mesh:
+    - the override of the mesh,
+    - else the override of its whole model,
+    - else Renderer::set_material_override(), the one for everything,
+    - else the mesh's own material.
+
+    A material's tint (Material::set_tint()) colors everything drawn with
+    it, so a tinted variant is a material with the same texture and a tint.
+
+    usage:
+        let red = renderer.add_material(material);
+        renderer.material_mut(red).unwrap().set_tint(renderer.queue(), [1.0, 0.3, 0.3, 1.0]);
+        renderer.set_instance_material(model, None, Some(red))?;
+        renderer.set_instance_material(model, Some(2), Some(chrome))?;
+*/
+
+#[derive(Debug, Clone, Default)]
+pub struct MaterialOverrides {
+    // (model, mesh, None for all of its meshes) -> index of the renderer's material.
+    entries: BTreeMap<(usize, Option<usize>), usize>,
+}
+
+impl MaterialOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `mesh` None overrides every mesh of the model that has no override of its own.
+    pub fn set(&mut self, model: usize, mesh: Option<usize>, material: usize) {
+        self.entries.insert((model, mesh), material);
+    }
+
+    pub fn remove(&mut self, model: usize, mesh: Option<usize>) -> Option<usize> {
+        self.entries.remove(&(model, mesh))
+    }
+
+    // the material the mesh is drawn with instead of its own, if any.
+    pub fn get(&self, model: usize, mesh: usize) -> Option<usize> {
+        self.entries.get(&(model, Some(mesh)))
+            .or_else(|| self.entries.get(&(model, None)))
+            .copied()
+    }
+
+    // the override set for exactly this model / mesh, without falling back to the model's.
+    pub fn entry(&self, model: usize, mesh: Option<usize>) -> Option<usize> {
+        self.entries.get(&(model, mesh)).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // the overrides of a model as (mesh, material), e.g. to put them back with it.
+    pub fn of_model(&self, model: usize) -> Vec<(Option<usize>, usize)> {
+        self.entries.range((model, None)..)
+            .take_while(|((entry, _), _)| *entry == model)
+            .map(|((_, mesh), material)| (*mesh, *material))
+            .collect()
+    }
+
+    // keeps the overrides on their models when the model at `index` is
+    // removed or a model is inserted there. The ones of a removed model are dropped.
+    pub fn shift_models(&mut self, index: usize, inserted: bool) {
+        self.entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .filter_map(|((model, mesh), material)| {
+                let model = if inserted && model >= index {
+                    model + 1
+                } else if !inserted && model > index {
+                    model - 1
+                } else if !inserted && model == index {
+                    return None;
+                } else {
+                    model
+                };
+                Some(((model, mesh), material))
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/*
+    Frame limiter and frame pacing statistics.
+
+    With a target fps the renderer waits before every frame until the
+    frame is due, instead of rendering as fast as Mailbox presents
+    (which keeps a laptop gpu busy for frames nobody sees).
+
+    Sleeping alone is too coarse (the os wakes the thread up to a few
+    milliseconds late), so the limiter sleeps until shortly before the
+    deadline and spins for the rest. A frame that is late doesn't make
+    the next ones hurry, the schedule starts again from the late frame.
+
+    The pacing statistics show how even the frames are: the spread of
+    the intervals between frames matters as much as their average.
+
+    usage:
+        let renderer = RendererBuilder::new().fps_limit(60.0).build(&event_loop)?;
+        ...
+        renderer.set_fps_limit(None);
+        let pacing = renderer.frame_pacing();
+*/
+
+// the last part of the wait is spun instead of slept.
+const SPIN_TIME: Duration = Duration::from_micros(1500);
+// frames the statistics are taken over.
+const HISTORY: usize = 120;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FramePacing {
+    // interval of the fps limit, None when uncapped.
+    pub target: Option<Duration>,
+    // of the intervals between frame starts.
+    pub average: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    // standard deviation of the intervals.
+    pub jitter: Duration,
+    // frames later than the target (by more than a tenth of it).
+    pub missed: u32,
+    // average time per frame spent waiting for the deadline.
+    pub idle: Duration,
+}
+
+pub struct FrameLimiter {
+    target: Option<Duration>,
+    // when the next frame is due.
+    next: Instant,
+    last: Option<Instant>,
+    intervals: VecDeque<Duration>,
+    idle: VecDeque<Duration>,
+}
+
+impl FrameLimiter {
+    // None renders as fast as the present mode allows.
+    pub fn new(fps: Option<f32>) -> Self {
+        let mut limiter = Self {
+            target: None,
+            next: Instant::now(),
+            last: None,
+            intervals: VecDeque::with_capacity(HISTORY),
+            idle: VecDeque::with_capacity(HISTORY),
+        };
+        limiter.set_fps(fps);
+        limiter
+    }
+
+    pub fn set_fps(&mut self, fps: Option<f32>) {
+        self.target = fps
+            .filter(|fps| *fps > 0.0)
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64));
+        self.next = Instant::now();
+        self.intervals.clear();
+        self.idle.clear();
+    }
+
+    pub fn fps(&self) -> Option<f32> {
+        self.target.map(|target| 1.0 / target.as_secs_f32())
+    }
+
+    // blocks until the next frame is due, call once at the start of every frame.
+    pub fn wait(&mut self) {
+        let arrived = Instant::now();
+        if self.target.is_some() && arrived < self.next {
+            let remaining = self.next - arrived;
+            if remaining > SPIN_TIME {
+                std::thread::sleep(remaining - SPIN_TIME);
+            }
+            while Instant::now() < self.next {
+                std::hint::spin_loop();
+            }
+        }
+
+        let start = Instant::now();
+        if let Some(target) = self.target {
+            // more than a frame behind: start over instead of catching up.
+            self.next = if start.duration_since(self.next) > target {
+                start + target
+            } else {
+                self.next + target
+            };
+        }
+        if let Some(last) = self.last {
+            push(&mut self.intervals, start - last);
+            push(&mut self.idle, start - arrived);
+        }
+        self.last = Some(start);
+    }
+
+    pub fn pacing(&self) -> FramePacing {
+        if self.intervals.is_empty() {
+            return FramePacing { target: self.target, ..Default::default() };
+        }
+        let count = self.intervals.len() as u32;
+        let average = self.intervals.iter().sum::<Duration>() / count;
+        let variance = self.intervals.iter()
+            .map(|interval| (interval.as_secs_f64() - average.as_secs_f64()).powi(2))
+            .sum::<f64>() / count as f64;
+        let missed = match self.target {
+            Some(target) => self.intervals.iter()
+                .filter(|interval| **interval > target + target / 10)
+                .count() as u32,
+            None => 0,
+        };
+
+        FramePacing {
+            target: self.target,
+            average,
+            min: self.intervals.iter().min().copied().unwrap_or_default(),
+            max: self.intervals.iter().max().copied().unwrap_or_default(),
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+            missed,
+            idle: self.idle.iter().sum::<Duration>() / self.idle.len().max(1) as u32,
+        }
+    }
+}
+
+fn push(history: &mut VecDeque<Duration>, value: Duration) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
@@ -0,0 +1,118 @@
+/*
+    Colors of the debug drawing on the overlay: the measuring tool, the
+    navmesh regions and paths, the selection box of the viewer, warnings
+    and, once they are drawn, the shadow cascades.
+
+    Tools don't pick their own colors, they take them by role from the
+    palette of the Overlay, so switching the palette recolors all of them
+    (on their next redraw):
+    - accent: what a tool is working on, the measured points and lines,
+    - selection: the selected mesh,
+    - path: a route through the navmesh,
+    - warning: text that needs attention, like the gpu memory running out,
+    - categories: things that are only told apart from each other, the
+      regions of the navmesh, cascades; the n-th gets categories[n % len].
+
+    The default palette mixes red and green, which is hard to tell apart
+    for about one in twelve men. The other presets avoid that:
+    - okabe-ito: the color-blind safe set of Okabe and Ito, for all kinds
+      of red-green blindness,
+    - tol: Paul Tol's "bright" scheme, also safe and a bit lighter on dark
+      scenes,
+    - high-contrast: white, yellow, blue and gray, told apart by their
+      brightness as well, for tritanopia and monochrome displays.
+
+    The viewer takes the palette from --palette, or the `palette` line of
+    its window state (windowstate.rs).
+
+    usage:
+        overlay.set_palette(DebugPalette::preset("okabe-ito").unwrap());
+        let color = overlay.palette().accent;
+        overlay.layer("my tool").line(from, to, color);
+*/
+
+pub const PRESETS: [&str; 4] = ["default", "okabe-ito", "tol", "high-contrast"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugPalette {
+    pub name: String,
+    pub accent: [f32; 4],
+    pub selection: [f32; 4],
+    pub path: [f32; 4],
+    pub warning: [f32; 4],
+    pub categories: Vec<[f32; 4]>,
+}
+
+impl Default for DebugPalette {
+    // the colors the tools had before there were palettes.
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            accent: [1.0, 0.8, 0.1, 1.0],
+            selection: [0.3, 1.0, 0.4, 1.0],
+            path: [1.0, 0.2, 0.2, 1.0],
+            warning: [1.0, 0.5, 0.2, 1.0],
+            categories: vec![
+                [0.2, 0.8, 1.0, 1.0],
+                [0.4, 1.0, 0.4, 1.0],
+                [1.0, 0.6, 0.2, 1.0],
+                [0.8, 0.4, 1.0, 1.0],
+                [1.0, 1.0, 0.3, 1.0],
+                [0.3, 0.5, 1.0, 1.0],
+            ],
+        }
+    }
+}
+
+impl DebugPalette {
+    // one of PRESETS, None for other names.
+    pub fn preset(name: &str) -> Option<Self> {
+        let palette = match name {
+            "default" => Self::default(),
+            "okabe-ito" => Self {
+                name: name.to_string(),
+                accent: rgb(0xE69F00),
+                selection: rgb(0x56B4E9),
+                path: rgb(0xD55E00),
+                warning: rgb(0xF0E442),
+                categories: [0x56B4E9, 0xE69F00, 0x009E73, 0xF0E442, 0x0072B2, 0xD55E00, 0xCC79A7]
+                    .map(rgb)
+                    .to_vec(),
+            },
+            "tol" => Self {
+                name: name.to_string(),
+                accent: rgb(0xCCBB44),
+                selection: rgb(0x66CCEE),
+                path: rgb(0xEE6677),
+                warning: rgb(0xCCBB44),
+                categories: [0x4477AA, 0x66CCEE, 0x228833, 0xCCBB44, 0xEE6677, 0xAA3377, 0xBBBBBB]
+                    .map(rgb)
+                    .to_vec(),
+            },
+            "high-contrast" => Self {
+                name: name.to_string(),
+                accent: rgb(0xFFFFFF),
+                selection: rgb(0xFFDD00),
+                path: rgb(0x3377FF),
+                warning: rgb(0xFFDD00),
+                categories: [0xFFFFFF, 0xFFDD00, 0x3377FF, 0x888888].map(rgb).to_vec(),
+            },
+            _ => return None,
+        };
+        Some(palette)
+    }
+
+    // the color of the `index`-th category, repeating.
+    pub fn category(&self, index: usize) -> [f32; 4] {
+        if self.categories.is_empty() {
+            return self.accent;
+        }
+        self.categories[index % self.categories.len()]
+    }
+}
+
+// 0xRRGGBB, opaque.
+fn rgb(hex: u32) -> [f32; 4] {
+    let channel = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+    [channel(16), channel(8), channel(0), 1.0]
+}
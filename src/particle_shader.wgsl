@@ -0,0 +1,279 @@
+// Shared by the simulation and the billboards, one per emitter.
+
+struct ParticleUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // xyz: position, w: speed.
+    emitter: vec4<f32>,
+    // xyz: half size of the box particles spawn in, 0 for a point.
+    extent: vec4<f32>,
+    // xyz: normalized direction, w: cosine of the cone half angle.
+    direction: vec4<f32>,
+    // xyz: gravity, w: time step in seconds.
+    gravity: vec4<f32>,
+    // xyz: wind velocity, w: how fast particles pick it up.
+    wind: vec4<f32>,
+    // xyz: position, w: strength (negative pushes away).
+    attractors: array<vec4<f32>, 4>,
+    // billboard axes in world space, w: size at birth / death.
+    camera_right: vec4<f32>,
+    camera_up: vec4<f32>,
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    viewport: vec2<f32>,
+    lifetime: f32,
+    // 0..1, how much speed is kept when bouncing off the scene.
+    restitution: f32,
+    near: f32,
+    far: f32,
+    // distance over which billboards fade out in front of geometry.
+    softness: f32,
+    seed: u32,
+    // particles [spawn_offset, spawn_offset + spawn_count) are (re)born this frame.
+    spawn_offset: u32,
+    spawn_count: u32,
+    max_particles: u32,
+    num_attractors: u32,
+    has_depth: u32,
+    lifetime_jitter: f32,
+    speed_jitter: f32,
+    // seconds of movement a billboard is stretched over, 0 keeps them square.
+    stretch: f32,
+}
+
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+}
+
+struct Particles {
+    data: array<Particle>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: ParticleUniform;
+@group(0) @binding(1)
+var t_depth: texture_depth_2d;
+@group(0) @binding(2)
+var<storage, read_write> particles: Particles;
+
+// Compute shader
+
+// pcg hash, good enough for spawn positions and directions.
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+
+// random direction within the cone around params.direction.
+fn cone_direction(seed: ptr<function, u32>) -> vec3<f32> {
+    let axis = params.direction.xyz;
+    var helper = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(axis.y) > 0.99) {
+        helper = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(helper, axis));
+    let bitangent = cross(axis, tangent);
+
+    let cos_theta = mix(1.0, params.direction.w, random(seed));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 6.2831853 * random(seed);
+    return axis * cos_theta + (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta;
+}
+
+fn spawn(index: u32) -> Particle {
+    var seed = hash(index ^ params.seed);
+    var p: Particle;
+    let offset = vec3<f32>(random(&seed), random(&seed), random(&seed)) * 2.0 - 1.0;
+    p.position = params.emitter.xyz + offset * params.extent.xyz;
+    let speed = params.emitter.w * (1.0 + params.speed_jitter * (random(&seed) * 2.0 - 1.0));
+    p.velocity = cone_direction(&seed) * speed;
+    p.age = 0.0;
+    p.lifetime = params.lifetime * (1.0 + params.lifetime_jitter * (random(&seed) * 2.0 - 1.0));
+    return p;
+}
+
+// scene depth under a world position, x/y: texel, z: particle depth, w: 0 if off screen.
+fn screen_position(position: vec3<f32>) -> vec4<f32> {
+    let clip = params.view_proj * vec4<f32>(position, 1.0);
+    if (clip.w <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let ndc = clip.xyz / clip.w;
+    if (abs(ndc.x) >= 1.0 || abs(ndc.y) >= 1.0) {
+        return vec4<f32>(0.0);
+    }
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    return vec4<f32>(floor(uv * params.viewport), ndc.z, 1.0);
+}
+
+// world position of the depth buffer at `texel`.
+fn scene_position(texel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(params.viewport) - vec2<i32>(1);
+    let clamped = clamp(texel, vec2<i32>(0), size);
+    let depth = textureLoad(t_depth, clamped, 0);
+    let uv = (vec2<f32>(clamped) + 0.5) / params.viewport;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = params.inv_view_proj * ndc;
+    return world.xyz / world.w;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= params.max_particles) {
+        return;
+    }
+
+    let since_spawn = (index + params.max_particles - params.spawn_offset) % params.max_particles;
+    if (since_spawn < params.spawn_count) {
+        particles.data[index] = spawn(index);
+        return;
+    }
+
+    var p = particles.data[index];
+    let dt = params.gravity.w;
+    p.age = p.age + dt;
+    if (p.age >= p.lifetime) {
+        particles.data[index] = p;
+        return;
+    }
+
+    var acceleration = params.gravity.xyz;
+    var i = 0u;
+    loop {
+        if (i >= params.num_attractors) {
+            break;
+        }
+        let attractor = params.attractors[i];
+        let to_attractor = attractor.xyz - p.position;
+        // softened, so particles passing through the center don't explode.
+        let distance_sq = dot(to_attractor, to_attractor) + 1.0;
+        acceleration = acceleration + to_attractor * attractor.w / (distance_sq * sqrt(distance_sq));
+        i = i + 1u;
+    }
+    p.velocity = p.velocity + acceleration * dt;
+    p.velocity = p.velocity + (params.wind.xyz - p.velocity) * min(params.wind.w * dt, 1.0);
+
+    let next = p.position + p.velocity * dt;
+
+    // bounce off the scene when the particle moves behind the depth buffer.
+    if (params.has_depth != 0u) {
+        let before = screen_position(p.position);
+        let after = screen_position(next);
+        if (before.w > 0.0 && after.w > 0.0) {
+            let texel = vec2<i32>(after.xy);
+            let scene_depth = textureLoad(t_depth, texel, 0);
+            if (after.z > scene_depth && before.z <= scene_depth) {
+                let center = scene_position(texel);
+                let dx = scene_position(texel + vec2<i32>(1, 0)) - center;
+                let dy = scene_position(texel + vec2<i32>(0, 1)) - center;
+                var normal = normalize(cross(dx, dy));
+                if (dot(normal, p.velocity) > 0.0) {
+                    normal = -normal;
+                }
+                p.velocity = reflect(p.velocity, normal) * params.restitution;
+                particles.data[index] = p;
+                return;
+            }
+        }
+    }
+
+    p.position = next;
+    particles.data[index] = p;
+}
+
+// Vertex shader
+
+struct ParticleInput {
+    @location(0) position: vec3<f32>,
+    @location(1) age: f32,
+    @location(2) velocity: vec3<f32>,
+    @location(3) lifetime: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) corner: vec2<f32>,
+    // camera distance of the billboard, for the soft fade.
+    @location(2) distance: f32,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    particle: ParticleInput,
+) -> VertexOutput {
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.corner = corner;
+
+    // dead particles collapse into a point behind the camera.
+    if (particle.age >= particle.lifetime) {
+        out.clip_position = vec4<f32>(0.0, 0.0, -1.0, 1.0);
+        out.color = vec4<f32>(0.0);
+        out.distance = 0.0;
+        return out;
+    }
+
+    let life = clamp(particle.age / particle.lifetime, 0.0, 1.0);
+    let size = mix(params.camera_right.w, params.camera_up.w, life) * 0.5;
+    var right = params.camera_right.xyz * size;
+    var up = params.camera_up.xyz * size;
+    // streaks along the velocity, e.g. rain.
+    let speed = length(particle.velocity);
+    if (params.stretch > 0.0 && speed > 0.0) {
+        let forward = cross(params.camera_up.xyz, params.camera_right.xyz);
+        let side = cross(particle.velocity, forward);
+        if (dot(side, side) > 0.0) {
+            right = normalize(side) * size;
+            up = particle.velocity * params.stretch * 0.5 + normalize(particle.velocity) * size;
+        }
+    }
+    let position = particle.position + right * corner.x + up * corner.y;
+
+    out.clip_position = params.view_proj * vec4<f32>(position, 1.0);
+    out.color = mix(params.start_color, params.end_color, life);
+    out.distance = out.clip_position.w;
+    return out;
+}
+
+// Fragment shader
+
+// depth buffer value -> distance from the camera.
+fn linear_depth(depth: f32) -> f32 {
+    return params.near * params.far / (params.far - depth * (params.far - params.near));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // round, soft edged sprites.
+    let falloff = max(1.0 - dot(in.corner, in.corner), 0.0);
+    var alpha = in.color.a * falloff * falloff;
+
+    // fade out where the billboard cuts into geometry.
+    if (params.has_depth != 0u) {
+        let texel = vec2<i32>(in.clip_position.xy);
+        let scene = linear_depth(textureLoad(t_depth, texel, 0));
+        if (scene < in.distance) {
+            discard;
+        }
+        alpha = alpha * clamp((scene - in.distance) / max(params.softness, 0.0001), 0.0, 1.0);
+    }
+
+    return vec4<f32>(in.color.rgb, alpha);
+}
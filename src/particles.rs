@@ -0,0 +1,603 @@
+use std::time::Instant;
+
+use cgmath::{InnerSpace, SquareMatrix};
+use wgpu::util::DeviceExt;
+
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+
+/*
+    Gpu particles.
+
+    Every emitter owns a ring of `max_particles` particles in a storage
+    buffer. Each frame a compute pass (particle_shader.wgsl)
+    - respawns the next `rate * dt` particles of the ring at the emitter,
+      flying off in a random direction within the velocity cone,
+    - ages the others and moves them by gravity, wind and the attractors,
+    - bounces them off the scene if the frame shares the scene's depth
+      (FrameTargets::depth, not with msaa, dynamic resolution or stereo).
+    The same buffer is then drawn as camera facing, additive billboards
+    whose color and size blend from start to end over their life.
+    With a depth buffer the billboards fade out softly where they
+    cut into geometry, without one they are drawn on top of everything.
+
+    usage:
+        let mut particles = ParticleSystem::new();
+        particles.add_emitter(EmitterSettings { position: [0.0, 1.0, 0.0], ..Default::default() });
+        particles.add_attractor(Attractor { position: [2.0, 2.0, 0.0], strength: 4.0 });
+        renderer.add_plugin(particles);
+*/
+
+pub const MAX_ATTRACTORS: usize = 4;
+// workgroup_size of the simulation.
+const WORKGROUP_SIZE: u32 = 64;
+// a frame taking longer than this (window dragged, breakpoint) is simulated as this long.
+const MAX_TIME_STEP: f32 = 0.1;
+
+#[derive(Copy, Clone, Debug)]
+pub struct EmitterSettings {
+    pub position: [f32; 3],
+    // half size of the box particles spawn in, zero spawns them all at `position`.
+    pub extent: [f32; 3],
+    // particles per second.
+    pub rate: f32,
+    // seconds.
+    pub lifetime: f32,
+    // 0..1, lifetimes vary by up to this fraction.
+    pub lifetime_jitter: f32,
+    // center of the velocity cone.
+    pub direction: [f32; 3],
+    // half angle of the velocity cone in degrees, 180 emits in all directions.
+    pub cone_angle: f32,
+    pub speed: f32,
+    pub speed_jitter: f32,
+    pub gravity: [f32; 3],
+    // billboard size in world units, at birth and at the end of the life.
+    pub start_size: f32,
+    pub end_size: f32,
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    // 0..1, speed kept after hitting the scene.
+    pub restitution: f32,
+    // world units over which billboards fade out in front of geometry.
+    pub softness: f32,
+    // seconds of movement the billboards are stretched over, for streaks.
+    // 0 draws them as round sprites.
+    pub stretch: f32,
+    // size of the ring, the oldest particles are reused once it's full.
+    pub max_particles: u32,
+}
+
+impl Default for EmitterSettings {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            extent: [0.0; 3],
+            rate: 100.0,
+            lifetime: 2.0,
+            lifetime_jitter: 0.2,
+            direction: [0.0, 1.0, 0.0],
+            cone_angle: 20.0,
+            speed: 2.0,
+            speed_jitter: 0.2,
+            gravity: [0.0, -1.0, 0.0],
+            start_size: 0.1,
+            end_size: 0.02,
+            start_color: [1.0, 0.8, 0.3, 1.0],
+            end_color: [1.0, 0.2, 0.0, 0.0],
+            restitution: 0.5,
+            softness: 0.2,
+            stretch: 0.0,
+            max_particles: 1024,
+        }
+    }
+}
+
+// pulls particles towards `position`, negative strength pushes them away.
+#[derive(Copy, Clone, Debug)]
+pub struct Attractor {
+    pub position: [f32; 3],
+    pub strength: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    position: [f32; 3],
+    age: f32,
+    velocity: [f32; 3],
+    lifetime: f32,
+}
+
+impl Particle {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Particle>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+// ParticleUniform in particle_shader.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleUniform {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    emitter: [f32; 4],
+    extent: [f32; 4],
+    direction: [f32; 4],
+    gravity: [f32; 4],
+    wind: [f32; 4],
+    attractors: [[f32; 4]; MAX_ATTRACTORS],
+    camera_right: [f32; 4],
+    camera_up: [f32; 4],
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    viewport: [f32; 2],
+    lifetime: f32,
+    restitution: f32,
+    near: f32,
+    far: f32,
+    softness: f32,
+    seed: u32,
+    spawn_offset: u32,
+    spawn_count: u32,
+    max_particles: u32,
+    num_attractors: u32,
+    has_depth: u32,
+    lifetime_jitter: f32,
+    speed_jitter: f32,
+    stretch: f32,
+}
+
+struct GpuEmitter {
+    particles: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    max_particles: u32,
+}
+
+struct Emitter {
+    settings: EmitterSettings,
+    // fraction of a particle left over from the previous frames.
+    spawn_budget: f32,
+    spawn_offset: u32,
+    spawn_count: u32,
+    gpu: Option<GpuEmitter>,
+}
+
+struct GpuState {
+    simulation_layout: wgpu::BindGroupLayout,
+    draw_layout: wgpu::BindGroupLayout,
+    simulation_pipeline: wgpu::ComputePipeline,
+    draw_pipeline: wgpu::RenderPipeline,
+    // bound while FrameTargets::depth is None (msaa, dynamic resolution,
+    // stereo), has_depth keeps the shader from reading it.
+    dummy_depth: wgpu::TextureView,
+}
+
+pub struct ParticleSystem {
+    emitters: Vec<Emitter>,
+    attractors: Vec<Attractor>,
+    wind: [f32; 3],
+    drag: f32,
+    last_update: Option<Instant>,
+    time_step: f32,
+    frame: u32,
+    gpu: Option<GpuState>,
+}
+
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self {
+            emitters: Vec::new(),
+            attractors: Vec::new(),
+            wind: [0.0; 3],
+            drag: 0.0,
+            last_update: None,
+            time_step: 0.0,
+            frame: 0,
+            gpu: None,
+        }
+    }
+
+    // returns the index of the emitter.
+    pub fn add_emitter(&mut self, settings: EmitterSettings) -> usize {
+        self.emitters.push(Emitter {
+            settings,
+            spawn_budget: 0.0,
+            spawn_offset: 0,
+            spawn_count: 0,
+            gpu: None,
+        });
+        self.emitters.len() - 1
+    }
+
+    // changes apply from the next frame, a new max_particles restarts the emitter.
+    pub fn emitter_mut(&mut self, index: usize) -> Option<&mut EmitterSettings> {
+        self.emitters.get_mut(index).map(|emitter| &mut emitter.settings)
+    }
+
+    pub fn num_emitters(&self) -> usize {
+        self.emitters.len()
+    }
+
+    // only the first MAX_ATTRACTORS are used.
+    pub fn add_attractor(&mut self, attractor: Attractor) -> usize {
+        self.attractors.push(attractor);
+        self.attractors.len() - 1
+    }
+
+    pub fn attractors_mut(&mut self) -> &mut Vec<Attractor> {
+        &mut self.attractors
+    }
+
+    // particles take on the `wind` velocity, `drag` per second (0 ignores the wind).
+    pub fn set_wind(&mut self, wind: [f32; 3], drag: f32) {
+        self.wind = wind;
+        self.drag = drag;
+    }
+
+    fn uniform(&self, emitter: &Emitter, ctx: &PluginContext, targets: &FrameTargets) -> ParticleUniform {
+        let settings = &emitter.settings;
+        let camera = ctx.camera;
+        let view_proj = camera.build_view_projection_matrix();
+        let inv_view_proj = view_proj.invert().unwrap_or_else(cgmath::Matrix4::identity);
+        // the rows of the view rotation are the camera axes in world space.
+        let view = camera.view_matrix();
+        let right = cgmath::Vector3::new(view.x.x, view.y.x, view.z.x);
+        let up = cgmath::Vector3::new(view.x.y, view.y.y, view.z.y);
+        let (near, far) = camera.clip_planes();
+
+        let direction = cgmath::Vector3::from(settings.direction);
+        let direction = if direction.magnitude2() > 0.0 { direction.normalize() } else { cgmath::Vector3::unit_y() };
+
+        let mut attractors = [[0.0; 4]; MAX_ATTRACTORS];
+        for (slot, attractor) in attractors.iter_mut().zip(&self.attractors) {
+            let p = attractor.position;
+            *slot = [p[0], p[1], p[2], attractor.strength];
+        }
+
+        let extend = |v: [f32; 3], w: f32| [v[0], v[1], v[2], w];
+        ParticleUniform {
+            view_proj: view_proj.into(),
+            inv_view_proj: inv_view_proj.into(),
+            emitter: extend(settings.position, settings.speed),
+            extent: extend(settings.extent, 0.0),
+            direction: extend(direction.into(), settings.cone_angle.clamp(0.0, 180.0).to_radians().cos()),
+            gravity: extend(settings.gravity, self.time_step),
+            wind: extend(self.wind, self.drag.max(0.0)),
+            attractors,
+            camera_right: extend(right.into(), settings.start_size),
+            camera_up: extend(up.into(), settings.end_size),
+            start_color: settings.start_color,
+            end_color: settings.end_color,
+            viewport: [targets.width.max(1) as f32, targets.height.max(1) as f32],
+            lifetime: settings.lifetime.max(f32::EPSILON),
+            restitution: settings.restitution,
+            near,
+            far,
+            softness: settings.softness,
+            seed: self.frame.wrapping_mul(0x9e37_79b9),
+            spawn_offset: emitter.spawn_offset,
+            spawn_count: emitter.spawn_count,
+            max_particles: emitter.gpu.as_ref().map_or(0, |gpu| gpu.max_particles),
+            num_attractors: self.attractors.len().min(MAX_ATTRACTORS) as u32,
+            has_depth: targets.depth.is_some() as u32,
+            lifetime_jitter: settings.lifetime_jitter.clamp(0.0, 1.0),
+            speed_jitter: settings.speed_jitter.clamp(0.0, 1.0),
+            stretch: settings.stretch.max(0.0),
+        }
+    }
+}
+
+impl RenderPlugin for ParticleSystem {
+    fn name(&self) -> &str {
+        "Particles"
+    }
+
+    fn setup(&mut self, ctx: &PluginContext) {
+        let device = ctx.device;
+        // a new device after a backend switch, update() allocates the rings again.
+        for emitter in &mut self.emitters {
+            emitter.gpu = None;
+        }
+
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE
+                | wgpu::ShaderStages::VERTEX
+                | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let depth_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Depth,
+            },
+            count: None,
+        };
+        let simulation_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("particle_simulation_bind_group_layout"),
+            entries: &[
+                uniform_entry,
+                depth_entry,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        // the particle buffer is the vertex buffer while drawing,
+        // so it can't be bound as storage at the same time.
+        let draw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("particle_draw_bind_group_layout"),
+            entries: &[uniform_entry, depth_entry],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("particle_shader.wgsl").into()),
+        });
+
+        let simulation_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Simulation Pipeline Layout"),
+            bind_group_layouts: &[&simulation_layout],
+            push_constant_ranges: &[],
+        });
+        let simulation_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Particle Simulation Pipeline"),
+            layout: Some(&simulation_pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        let draw_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Draw Pipeline Layout"),
+            bind_group_layouts: &[&draw_layout],
+            push_constant_ranges: &[],
+        });
+        // additive, unsorted and without culling: the order of
+        // the billboards doesn't matter when they are added up.
+        let draw_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Particle Draw Pipeline"),
+            layout: Some(&draw_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Particle::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: ctx.config.format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let dummy_depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Particle Dummy Depth"),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        self.gpu = Some(GpuState {
+            simulation_layout,
+            draw_layout,
+            simulation_pipeline,
+            draw_pipeline,
+            dummy_depth: dummy_depth.create_view(&wgpu::TextureViewDescriptor::default()),
+        });
+    }
+
+    fn update(&mut self, ctx: &PluginContext) {
+        let now = Instant::now();
+        self.time_step = self.last_update
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32())
+            .min(MAX_TIME_STEP);
+        self.last_update = Some(now);
+        self.frame = self.frame.wrapping_add(1);
+
+        for emitter in &mut self.emitters {
+            let max_particles = emitter.settings.max_particles.max(1);
+
+            // (re)allocate the ring, every particle starts out dead.
+            if emitter.gpu.as_ref().map(|gpu| gpu.max_particles) != Some(max_particles) {
+                let dead = Particle { position: [0.0; 3], age: 1.0, velocity: [0.0; 3], lifetime: 0.0 };
+                let particles = ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Particle Buffer"),
+                    contents: bytemuck::cast_slice(&vec![dead; max_particles as usize]),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+                });
+                let uniform_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Particle Uniform Buffer"),
+                    size: std::mem::size_of::<ParticleUniform>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                emitter.gpu = Some(GpuEmitter { particles, uniform_buffer, max_particles });
+                emitter.spawn_budget = 0.0;
+                emitter.spawn_offset = 0;
+            } else {
+                emitter.spawn_offset = (emitter.spawn_offset + emitter.spawn_count) % max_particles;
+            }
+
+            emitter.spawn_budget += emitter.settings.rate.max(0.0) * self.time_step;
+            let spawn_count = (emitter.spawn_budget.floor() as u32).min(max_particles);
+            emitter.spawn_budget -= spawn_count as f32;
+            // can't spawn more than the ring holds, drop the rest.
+            emitter.spawn_budget = emitter.spawn_budget.min(1.0);
+            emitter.spawn_count = spawn_count;
+        }
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    ) {
+        let gpu = match &self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        let depth = targets.depth.unwrap_or(&gpu.dummy_depth);
+
+        // bind groups are rebuilt every frame, the depth view can change with it.
+        let mut draws = Vec::with_capacity(self.emitters.len());
+        for emitter in &self.emitters {
+            let emitter_gpu = match &emitter.gpu {
+                Some(emitter_gpu) => emitter_gpu,
+                None => continue,
+            };
+            ctx.queue.write_buffer(
+                &emitter_gpu.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[self.uniform(emitter, ctx, targets)]),
+            );
+
+            let simulation_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("particle_simulation_bind_group"),
+                layout: &gpu.simulation_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: emitter_gpu.uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: emitter_gpu.particles.as_entire_binding(),
+                    },
+                ],
+            });
+            let draw_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("particle_draw_bind_group"),
+                layout: &gpu.draw_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: emitter_gpu.uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth),
+                    },
+                ],
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Particle Simulation Pass"),
+                });
+                compute_pass.set_pipeline(&gpu.simulation_pipeline);
+                compute_pass.set_bind_group(0, &simulation_bind_group, &[]);
+                compute_pass.dispatch_workgroups(emitter_gpu.max_particles.div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+
+            draws.push((emitter_gpu, draw_bind_group));
+        }
+
+        if draws.is_empty() {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Particle Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: targets.color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&gpu.draw_pipeline);
+        for (emitter_gpu, bind_group) in &draws {
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_vertex_buffer(0, emitter_gpu.particles.slice(..));
+            render_pass.draw(0..6, 0..emitter_gpu.max_particles);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::camera::Camera;
+use crate::math;
+use crate::model::{Bounds, Model};
+
+/*
+    Picking: which mesh is under the mouse cursor?
+
+    A ray is shot from the camera through the cursor position and tested
+    against the triangles of every mesh (on the cpu, with the copy of the
+    geometry every Mesh keeps). Meshes whose bounding box the ray misses
+    are skipped, so this stays cheap enough to run on every click.
+
+    usage:
+        let ray = Ray::from_screen(renderer.camera(), x, y, width, height);
+        if let Some(hit) = picking::pick(renderer.models(), &ray) {
+            println!("{} units away", hit.distance);
+        }
+    or simply renderer.pick(x, y), which also emits ObjectPicked.
+
+    For placing things the cursor ray also crosses planes, e.g. the
+    ground at y = 0, and the terrain (the visible meshes):
+        let point = renderer.cursor_on_plane(x, y, &Plane::default());
+        let point = renderer.cursor_on_ground(x, y, |_, _| true);
+*/
+
+// The points p with dot(normal, p) + distance == 0, like the planes of
+// math::Frustum. The default is the ground, y = 0.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    // normalized.
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    // the plane through `point`, facing `normal`.
+    pub fn new(point: Vector3<f32>, normal: Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        Self { normal, distance: -normal.dot(point) }
+    }
+
+    // the horizontal plane at y = `height`.
+    pub fn horizontal(height: f32) -> Self {
+        Self { normal: Vector3::unit_y(), distance: -height }
+    }
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Self::horizontal(0.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    // normalized.
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    // x/y in pixels, from the top left corner of the window.
+    pub fn from_screen(camera: &Camera, x: f32, y: f32, width: u32, height: u32) -> Self {
+        // unprojects a point on the near and on the far plane.
+        let (origin, direction) = math::ray_from_screen(camera.build_view_projection_matrix(), x, y, width, height);
+        Self::new(origin, direction)
+    }
+
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    // distance to where the ray crosses the plane, None when it is parallel
+    // to the plane or points away from it.
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let facing = plane.normal.dot(self.direction);
+        if facing.abs() < 1e-6 {
+            return None;
+        }
+        let distance = -(plane.normal.dot(self.origin) + plane.distance) / facing;
+        if distance >= 0.0 { Some(distance) } else { None }
+    }
+
+    // distance to where the ray enters the box, slab test.
+    pub fn intersect_bounds(&self, bounds: &Bounds) -> Option<f32> {
+        if bounds.is_empty() {
+            return None;
+        }
+        let mut near = 0.0f32;
+        let mut far = f32::MAX;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let mut t0 = (bounds.min[axis] - self.origin[axis]) * inverse;
+            let mut t1 = (bounds.max[axis] - self.origin[axis]) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            near = near.max(t0);
+            far = far.min(t1);
+            // NaN (ray parallel to and inside a slab) compares false and passes.
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    // Möller–Trumbore, both sides of the triangle count.
+    pub fn intersect_triangle(&self, a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> Option<f32> {
+        let a = Vector3::from(a);
+        let edge1 = Vector3::from(b) - a;
+        let edge2 = Vector3::from(c) - a;
+
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge2.dot(q) * inverse;
+        if distance > 0.0 { Some(distance) } else { None }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hit {
+    pub model: usize,
+    pub mesh: usize,
+    // index of the triangle within the mesh.
+    pub triangle: usize,
+    pub position: [f32; 3],
+    pub distance: f32,
+}
+
+impl Hit {
+    // uv of the mesh where it was hit, interpolated over the triangle.
+    pub fn uv(&self, models: &[Model]) -> Option<[f32; 2]> {
+        let mesh = models.get(self.model)?.meshes.get(self.mesh)?;
+        let indices = mesh.indices.get(self.triangle * 3..self.triangle * 3 + 3)?;
+        let corner = |i: usize| Vector3::from(mesh.positions[indices[i] as usize]);
+        let uv = |i: usize| mesh.uvs.get(indices[i] as usize).copied().unwrap_or([0.0; 2]);
+
+        // barycentric coordinates of the hit in the triangle, without the offset.
+        let point = Vector3::from(self.position) - Vector3::from(mesh.offset);
+        let (a, b, c) = (corner(0), corner(1), corner(2));
+        let (edge1, edge2, to_point) = (b - a, c - a, point - a);
+        let (d11, d12, d22) = (edge1.dot(edge1), edge1.dot(edge2), edge2.dot(edge2));
+        let denominator = d11 * d22 - d12 * d12;
+        if denominator.abs() < f32::EPSILON {
+            return Some(uv(0));
+        }
+        let (dp1, dp2) = (to_point.dot(edge1), to_point.dot(edge2));
+        let v = (d22 * dp1 - d12 * dp2) / denominator;
+        let w = (d11 * dp2 - d12 * dp1) / denominator;
+        let u = 1.0 - v - w;
+        let (uv0, uv1, uv2) = (uv(0), uv(1), uv(2));
+        Some([
+            uv0[0] * u + uv1[0] * v + uv2[0] * w,
+            uv0[1] * u + uv1[1] * v + uv2[1] * w,
+        ])
+    }
+}
+
+// closest triangle along the ray.
+pub fn pick(models: &[Model], ray: &Ray) -> Option<Hit> {
+    pick_filtered(models, ray, |_, _| true)
+}
+
+// closest triangle of the meshes `filter(model, mesh)` accepts, e.g. only visible ones.
+pub fn pick_filtered<F: Fn(usize, usize) -> bool>(models: &[Model], ray: &Ray, filter: F) -> Option<Hit> {
+    let mut closest: Option<Hit> = None;
+    for (model_index, model) in models.iter().enumerate() {
+        for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+            if !filter(model_index, mesh_index) {
+                continue;
+            }
+            // the geometry stays put, the ray moves against the mesh offset instead.
+            let ray = Ray { origin: ray.origin - Vector3::from(mesh.offset), direction: ray.direction };
+            match ray.intersect_bounds(&mesh.bounds) {
+                Some(distance) if closest.map_or(true, |hit| distance < hit.distance) => {}
+                _ => continue,
+            }
+
+            for (triangle, indices) in mesh.indices.chunks_exact(3).enumerate() {
+                let corner = |i: usize| mesh.positions[indices[i] as usize];
+                let distance = match ray.intersect_triangle(corner(0), corner(1), corner(2)) {
+                    Some(distance) => distance,
+                    None => continue,
+                };
+                if closest.map_or(true, |hit| distance < hit.distance) {
+                    closest = Some(Hit {
+                        model: model_index,
+                        mesh: mesh_index,
+                        triangle,
+                        position: (ray.at(distance) + Vector3::from(mesh.offset)).into(),
+                        distance,
+                    });
+                }
+            }
+        }
+    }
+    closest
+}
@@ -0,0 +1,86 @@
+use std::any::Any;
+
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::model::Bounds;
+use crate::shadow::ShadowFit;
+
+/*
+    Plugins let an application add its own passes (ui, effects, debug views)
+    to the frame without touching the renderer internals.
+
+    The renderer calls, in order:
+    - setup() when the plugin is added, and again with the new device
+      after Renderer::switch_backend(): everything the plugin created on
+      the old device has to be created again there,
+    - resize() whenever the surface changes size,
+    - update() at the start of every frame, bounds() right before it
+      when the renderer fits the clip planes,
+    - encode() after the scene pass, with the frame's command encoder.
+
+    Once added, a plugin can be reached again with
+    renderer.plugin_mut::<MyPlugin>(), e.g. to change its settings.
+*/
+
+// Gpu handles shared with every plugin callback.
+pub struct PluginContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub config: &'a wgpu::SurfaceConfiguration,
+    // layout of the camera uniform, so plugins can create pipelines
+    // that draw in world space. Binding 1 is the per frame uniform
+    // with the time, see frame.rs.
+    pub camera_bind_group_layout: &'a wgpu::BindGroupLayout,
+    // for passes that need the matrices on the cpu or in a compute shader.
+    pub camera: &'a Camera,
+    pub lights: &'a [Light],
+    // the sun's shadow projection of this frame, None without a sun.
+    pub shadow: Option<&'a ShadowFit>,
+    // of the window, physical pixels per logical one (its dpi / 96).
+    // Changes when the window moves to another monitor.
+    pub scale_factor: f64,
+    // seconds to advance simulations by this frame, the step the application
+    // gave Renderer::set_time_step() (clamped, recorded and replayed by the
+    // viewer), otherwise the time since the last frame.
+    pub time_step: f32,
+}
+
+// The targets of the frame that is currently being encoded.
+pub struct FrameTargets<'a> {
+    // the (resolved) surface texture, already containing the scene.
+    // -> passes should use LoadOp::Load to draw on top of it.
+    pub color: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub camera_bind_group: &'a wgpu::BindGroup,
+    // single sampled scene depth (Texture::DEPTH_FORMAT), None with msaa,
+    // dynamic resolution or stereo, where it doesn't match the frame.
+    pub depth: Option<&'a wgpu::TextureView>,
+}
+
+pub trait RenderPlugin: Any {
+    // name of the debug group around encode(), shown in gpu captures.
+    fn name(&self) -> &str {
+        "Plugin"
+    }
+
+    fn setup(&mut self, _ctx: &PluginContext) {}
+
+    fn resize(&mut self, _ctx: &PluginContext, _width: u32, _height: u32) {}
+
+    fn update(&mut self, _ctx: &PluginContext) {}
+
+    // world space box around what the plugin draws into the scene, so the
+    // automatic clip planes (RendererBuilder::auto_clip_planes()) keep it.
+    fn bounds(&self) -> Option<Bounds> {
+        None
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    );
+}
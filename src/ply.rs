@@ -0,0 +1,424 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/*
+    Reader for PLY (polygon file format / stanford triangle format).
+
+    Supports the ascii and both binary encodings. Every element is read
+    into columns, one per property, so callers can just ask for the
+    properties they know ("x", "red", "vertex_indices", ...) and
+    ignore the rest.
+*/
+
+pub struct PlyData {
+    pub elements: Vec<PlyElement>,
+}
+
+pub struct PlyElement {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<PlyProperty>,
+    // one column per property, in the same order.
+    pub columns: Vec<PlyColumn>,
+}
+
+pub struct PlyProperty {
+    pub name: String,
+    kind: PropertyKind,
+}
+
+pub enum PlyColumn {
+    Scalar(Vec<f32>),
+    List(Vec<Vec<u32>>),
+}
+
+#[derive(Copy, Clone)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    List(ScalarType, ScalarType),
+}
+
+#[derive(Copy, Clone)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            other => bail!("Unknown ply property type {:?}.", other),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+impl PlyElement {
+    pub fn scalar(&self, name: &str) -> Option<&[f32]> {
+        self.column(name).and_then(|column| match column {
+            PlyColumn::Scalar(values) => Some(values.as_slice()),
+            PlyColumn::List(_) => None,
+        })
+    }
+
+    pub fn list(&self, name: &str) -> Option<&[Vec<u32>]> {
+        self.column(name).and_then(|column| match column {
+            PlyColumn::List(values) => Some(values.as_slice()),
+            PlyColumn::Scalar(_) => None,
+        })
+    }
+
+    fn column(&self, name: &str) -> Option<&PlyColumn> {
+        self.properties.iter()
+            .position(|property| property.name == name)
+            .map(|index| &self.columns[index])
+    }
+}
+
+impl PlyData {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = std::fs::read(path.as_ref())
+            .with_context(|| format!("Unable to read {:?}.", path.as_ref()))?;
+        Self::parse(&bytes)
+            .with_context(|| format!("Unable to parse ply file {:?}.", path.as_ref()))
+    }
+
+    pub fn element(&self, name: &str) -> Option<&PlyElement> {
+        self.elements.iter().find(|element| element.name == name)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let header_end = find(bytes, b"end_header")
+            .context("Missing end_header.")?;
+        // the body starts after the line break following end_header.
+        let body_start = bytes[header_end..].iter()
+            .position(|byte| *byte == b'\n')
+            .map(|offset| header_end + offset + 1)
+            .unwrap_or(bytes.len());
+
+        let header = std::str::from_utf8(&bytes[..header_end])?;
+        let mut lines = header.lines();
+        if lines.next().map(|line| line.trim()) != Some("ply") {
+            bail!("Not a ply file.");
+        }
+
+        let mut format = None;
+        let mut elements: Vec<PlyElement> = Vec::new();
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "ascii", ..] => format = Some(Format::Ascii),
+                ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+                ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+                ["element", name, count] => elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse()?,
+                    properties: Vec::new(),
+                    columns: Vec::new(),
+                }),
+                ["property", "list", count_type, item_type, name] => {
+                    let element = elements.last_mut().context("Property before any element.")?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: PropertyKind::List(ScalarType::parse(count_type)?, ScalarType::parse(item_type)?),
+                    });
+                }
+                ["property", ty, name] => {
+                    let element = elements.last_mut().context("Property before any element.")?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: PropertyKind::Scalar(ScalarType::parse(ty)?),
+                    });
+                }
+                // comment, obj_info, empty lines
+                _ => {}
+            }
+        }
+        let format = format.context("Missing format line.")?;
+
+        let body = &bytes[body_start..];
+        if format == Format::Ascii {
+            read_ascii(std::str::from_utf8(body)?, &mut elements)?;
+        } else {
+            read_binary(body, format == Format::BinaryBigEndian, &mut elements)?;
+        }
+
+        Ok(Self { elements })
+    }
+}
+
+fn empty_columns(element: &PlyElement) -> Vec<PlyColumn> {
+    element.properties.iter()
+        .map(|property| match property.kind {
+            PropertyKind::Scalar(_) => PlyColumn::Scalar(Vec::with_capacity(element.count)),
+            PropertyKind::List(..) => PlyColumn::List(Vec::with_capacity(element.count)),
+        })
+        .collect()
+}
+
+// the header and the lists say how many values follow, the counts are
+// checked against what's left of the data before anything is allocated.
+fn check_count(count: usize, size: usize, left: usize, what: &str) -> Result<()> {
+    match count.checked_mul(size) {
+        Some(needed) if needed <= left => Ok(()),
+        _ => bail!("{} count {} doesn't fit into the rest of the ply data.", what, count),
+    }
+}
+
+fn read_ascii(body: &str, elements: &mut [PlyElement]) -> Result<()> {
+    let mut left = body.split_whitespace().count();
+    let mut tokens = body.split_whitespace();
+    let mut next = move || -> Result<f64> {
+        Ok(tokens.next().context("Unexpected end of ply data.")?.parse::<f64>()?)
+    };
+
+    for element in elements.iter_mut() {
+        if element.properties.is_empty() {
+            continue;
+        }
+        // every property is at least one number.
+        check_count(element.count, element.properties.len(), left, &element.name)?;
+        let mut columns = empty_columns(element);
+        for _ in 0..element.count {
+            for (property, column) in element.properties.iter().zip(columns.iter_mut()) {
+                match (property.kind, column) {
+                    (PropertyKind::Scalar(_), PlyColumn::Scalar(values)) => {
+                        values.push(next()? as f32);
+                        left -= 1;
+                    }
+                    (PropertyKind::List(..), PlyColumn::List(values)) => {
+                        let count = next()? as usize;
+                        left -= 1;
+                        check_count(count, 1, left, &property.name)?;
+                        let mut items = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            items.push(next()? as u32);
+                        }
+                        left -= count;
+                        values.push(items);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        element.columns = columns;
+    }
+    Ok(())
+}
+
+fn read_binary(body: &[u8], big_endian: bool, elements: &mut [PlyElement]) -> Result<()> {
+    let mut offset = 0;
+    for element in elements.iter_mut() {
+        if element.properties.is_empty() {
+            continue;
+        }
+        // the smallest a row can be: the scalars and the counts of empty lists.
+        let row_size = element.properties.iter()
+            .map(|property| match property.kind {
+                PropertyKind::Scalar(ty) | PropertyKind::List(ty, _) => ty.size(),
+            })
+            .sum();
+        check_count(element.count, row_size, body.len() - offset, &element.name)?;
+        let mut columns = empty_columns(element);
+        for _ in 0..element.count {
+            for (property, column) in element.properties.iter().zip(columns.iter_mut()) {
+                match (property.kind, column) {
+                    (PropertyKind::Scalar(ty), PlyColumn::Scalar(values)) => {
+                        values.push(read_scalar(body, &mut offset, ty, big_endian)? as f32);
+                    }
+                    (PropertyKind::List(count_type, item_type), PlyColumn::List(values)) => {
+                        let count = read_scalar(body, &mut offset, count_type, big_endian)? as usize;
+                        check_count(count, item_type.size(), body.len() - offset, &property.name)?;
+                        let mut items = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            items.push(read_scalar(body, &mut offset, item_type, big_endian)? as u32);
+                        }
+                        values.push(items);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        element.columns = columns;
+    }
+    Ok(())
+}
+
+// the value of type `ty` at `offset`, which moves past it.
+fn read_scalar(body: &[u8], offset: &mut usize, ty: ScalarType, big_endian: bool) -> Result<f64> {
+    let size = ty.size();
+    let raw = body.get(*offset..*offset + size).context("Unexpected end of ply data.")?;
+    *offset += size;
+
+    let mut bytes = [0u8; 8];
+    bytes[..size].copy_from_slice(raw);
+    // normalize to little endian
+    if big_endian {
+        bytes[..size].reverse();
+    }
+    Ok(match ty {
+        ScalarType::I8 => bytes[0] as i8 as f64,
+        ScalarType::U8 => bytes[0] as f64,
+        ScalarType::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        ScalarType::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        ScalarType::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        ScalarType::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        ScalarType::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        ScalarType::F64 => f64::from_le_bytes(bytes),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "\
+ply
+format {} 1.0
+comment a single triangle
+element vertex 3
+property float x
+property float y
+property uchar red
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const POSITIONS: [[f32; 2]; 3] = [[0.0, 0.0], [1.5, 0.0], [0.0, -2.0]];
+    const REDS: [u8; 3] = [0, 128, 255];
+
+    fn header(format: &str) -> Vec<u8> {
+        HEADER.replace("{}", format).into_bytes()
+    }
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let mut bytes = header(if big_endian { "binary_big_endian" } else { "binary_little_endian" });
+        let mut push = |raw: &[u8]| {
+            let start = bytes.len();
+            bytes.extend_from_slice(raw);
+            if big_endian {
+                bytes[start..].reverse();
+            }
+        };
+        for ([x, y], red) in POSITIONS.iter().zip(REDS.iter()) {
+            push(&x.to_le_bytes());
+            push(&y.to_le_bytes());
+            push(&[*red]);
+        }
+        push(&[3]);
+        for index in 0..3i32 {
+            push(&index.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn assert_triangle(ply: &PlyData) {
+        let vertex = ply.element("vertex").unwrap();
+        assert_eq!(vertex.count, 3);
+        let xs: Vec<f32> = POSITIONS.iter().map(|p| p[0]).collect();
+        let ys: Vec<f32> = POSITIONS.iter().map(|p| p[1]).collect();
+        let reds: Vec<f32> = REDS.iter().map(|r| *r as f32).collect();
+        assert_eq!(vertex.scalar("x").unwrap(), xs.as_slice());
+        assert_eq!(vertex.scalar("y").unwrap(), ys.as_slice());
+        assert_eq!(vertex.scalar("red").unwrap(), reds.as_slice());
+        // scalars and lists aren't interchangeable.
+        assert!(vertex.list("x").is_none());
+        assert!(vertex.scalar("z").is_none());
+
+        let face = ply.element("face").unwrap();
+        assert_eq!(face.list("vertex_indices").unwrap(), &[vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let mut bytes = header("ascii");
+        for ([x, y], red) in POSITIONS.iter().zip(REDS.iter()) {
+            bytes.extend_from_slice(format!("{} {} {}\n", x, y, red).as_bytes());
+        }
+        bytes.extend_from_slice(b"3 0 1 2\n");
+        assert_triangle(&PlyData::parse(&bytes).unwrap());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        assert_triangle(&PlyData::parse(&binary(false)).unwrap());
+        assert_triangle(&PlyData::parse(&binary(true)).unwrap());
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let mut truncated = binary(false);
+        truncated.pop();
+        assert!(PlyData::parse(&truncated).is_err());
+
+        let mut missing_values = header("ascii");
+        missing_values.extend_from_slice(b"0 0 0\n1 0\n");
+        assert!(PlyData::parse(&missing_values).is_err());
+
+        let no_end = String::from_utf8(header("ascii")).unwrap().replace("end_header", "");
+        assert!(PlyData::parse(no_end.as_bytes()).is_err());
+        let not_ply = String::from_utf8(header("ascii")).unwrap().replacen("ply", "obj", 1);
+        assert!(PlyData::parse(not_ply.as_bytes()).is_err());
+        let no_format = String::from_utf8(header("ascii")).unwrap().replace("format", "formet");
+        assert!(PlyData::parse(no_format.as_bytes()).is_err());
+        let unknown_type = String::from_utf8(header("ascii")).unwrap().replace("uchar red", "quad red");
+        assert!(PlyData::parse(unknown_type.as_bytes()).is_err());
+        let orphan = "ply\nformat ascii 1.0\nproperty float x\nend_header\n";
+        assert!(PlyData::parse(orphan.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn counts_are_checked_against_the_data() {
+        let mut huge_list = header("ascii");
+        huge_list.extend_from_slice(b"0 0 0\n1 0 0\n0 1 0\n1e30 0 1 2\n");
+        assert!(PlyData::parse(&huge_list).is_err());
+
+        let header_len = header("binary_little_endian").len();
+        let mut huge_element = binary(false);
+        let many = String::from_utf8(huge_element[..header_len].to_vec()).unwrap()
+            .replace("element vertex 3", &format!("element vertex {}", usize::MAX / 2));
+        huge_element.splice(..header_len, many.into_bytes());
+        assert!(PlyData::parse(&huge_element).is_err());
+
+        // the face's list count, more indices than bytes are left.
+        let mut huge_binary_list = binary(false);
+        let count = huge_binary_list.len() - 13;
+        huge_binary_list[count] = 200;
+        assert!(PlyData::parse(&huge_binary_list).is_err());
+    }
+}
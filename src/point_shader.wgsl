@@ -0,0 +1,75 @@
+// Vertex shader
+
+//
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) //
+var<uniform> camera: CameraUniform;
+
+struct PointUniform {
+    // surface size in pixels.
+    viewport: vec2<f32>,
+    // point diameter in pixels.
+    size: f32,
+    // distance at which a point is `size` pixels big,
+    // 0 keeps every point the same size on screen.
+    attenuation_distance: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> params: PointUniform;
+
+struct PointInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) corner: vec2<f32>,
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    point: PointInput,
+) -> VertexOutput {
+    // two triangles per point, counter clockwise.
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.color = point.color;
+    out.corner = corner;
+
+    let center = camera.view_proj * vec4<f32>(point.position, 1.0);
+    // half the size, in normalized device coordinates.
+    let offset = corner * params.size / params.viewport;
+
+    // the offset is scaled by w so it stays the same after the
+    // perspective divide, unless the points should get smaller with distance.
+    var scale = center.w;
+    if (params.attenuation_distance > 0.0) {
+        scale = params.attenuation_distance;
+    }
+    out.clip_position = center + vec4<f32>(offset * scale, 0.0, 0.0);
+
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // round points
+    if (dot(in.corner, in.corner) > 1.0) {
+        discard;
+    }
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,354 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use wgpu::util::DeviceExt;
+
+use crate::model::Bounds;
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+use crate::vertex::{PointVertex, Vertex};
+
+/*
+    Point clouds, for previewing LiDAR and photogrammetry scans.
+
+    Points are read from
+    - xyz / pts / txt files: one point per line, "x y z", optionally
+      followed by an intensity and/or "r g b" (0-1 or 0-255),
+    - ply files: the vertex element, faces are ignored.
+    Points without color are tinted by their height.
+
+    They are drawn by PointCloudPass as round, camera facing quads,
+    one instance per point.
+
+    usage:
+        let mut points = PointCloudPass::new(PointSize::Attenuated { pixels: 4.0, distance: 10.0 });
+        points.add(PointCloud::load("scan.xyz")?);
+        renderer.add_plugin(points);
+*/
+
+pub struct PointCloud {
+    pub points: Vec<PointVertex>,
+    pub bounds: Bounds,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<PointVertex>) -> Self {
+        let mut bounds = Bounds::empty();
+        for point in &points {
+            bounds.extend(point.position);
+        }
+        Self { points, bounds }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let extension = path.as_ref().extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        match extension.as_deref() {
+            Some("ply") => Self::load_ply(path),
+            _ => Self::load_xyz(path),
+        }
+    }
+
+    pub fn load_xyz<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Unable to read {:?}.", path.as_ref()))?;
+
+        let mut positions = Vec::new();
+        let mut colors = Vec::new();
+        for line in text.lines() {
+            let values: Vec<f32> = match line.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .filter(|word| !word.is_empty())
+                .map(|word| word.parse())
+                .collect()
+            {
+                Ok(values) => values,
+                // header or comment
+                Err(_) => continue,
+            };
+
+            let color = match values.len() {
+                // the point count at the top of pts files
+                0..=2 => continue,
+                3 => None,
+                // intensity only
+                4 | 5 => Some([values[3]; 3]),
+                // rgb, with or without an intensity before it
+                6 => Some([values[3], values[4], values[5]]),
+                _ => Some([values[4], values[5], values[6]]),
+            };
+            positions.push([values[0], values[1], values[2]]);
+            colors.extend(color);
+        }
+
+        if positions.is_empty() {
+            bail!("No points in {:?}.", path.as_ref());
+        }
+        // every point has a color, or the file has none at all.
+        let colors = if colors.len() == positions.len() { Some(colors) } else { None };
+        Ok(Self::from_positions(positions, colors))
+    }
+
+    pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let ply = crate::ply::PlyData::load(path.as_ref())?;
+        let vertex = ply.element("vertex")
+            .context("PLY file has no vertex element.")?;
+
+        let (x, y, z) = match (vertex.scalar("x"), vertex.scalar("y"), vertex.scalar("z")) {
+            (Some(x), Some(y), Some(z)) => (x, y, z),
+            _ => bail!("PLY vertices have no x/y/z."),
+        };
+        let positions = (0..vertex.count).map(|i| [x[i], y[i], z[i]]).collect();
+
+        let channel = |name: &str| vertex.scalar(name).or_else(|| vertex.scalar(&format!("diffuse_{}", name)));
+        let colors = match (channel("red"), channel("green"), channel("blue")) {
+            (Some(r), Some(g), Some(b)) => Some((0..vertex.count).map(|i| [r[i], g[i], b[i]]).collect()),
+            _ => None,
+        };
+
+        Ok(Self::from_positions(positions, colors))
+    }
+
+    // colors can be 0-1 or 0-255, anything above 1 means the latter.
+    fn from_positions(positions: Vec<[f32; 3]>, colors: Option<Vec<[f32; 3]>>) -> Self {
+        let points = match colors {
+            Some(colors) => {
+                let max = colors.iter().flatten().fold(0.0f32, |max, c| max.max(*c));
+                let scale = if max > 1.0 { 1.0 / 255.0 } else { 1.0 };
+                positions.into_iter()
+                    .zip(colors)
+                    .map(|(position, color)| PointVertex {
+                        position,
+                        color: [color[0] * scale, color[1] * scale, color[2] * scale],
+                    })
+                    .collect()
+            }
+            None => {
+                let mut bounds = Bounds::empty();
+                for position in &positions {
+                    bounds.extend(*position);
+                }
+                let height = bounds.size()[1].max(f32::EPSILON);
+                positions.into_iter()
+                    .map(|position| PointVertex {
+                        position,
+                        color: height_color((position[1] - bounds.min[1]) / height),
+                    })
+                    .collect()
+            }
+        };
+        Self::new(points)
+    }
+}
+
+// blue (low) -> green -> red (high)
+fn height_color(t: f32) -> [f32; 3] {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let t = t * 2.0;
+        [0.0, t, 1.0 - t]
+    } else {
+        let t = (t - 0.5) * 2.0;
+        [t, 1.0 - t, 0.0]
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum PointSize {
+    // same size on screen no matter how far away.
+    Pixels(f32),
+    // `pixels` big at `distance`, smaller further away.
+    Attenuated { pixels: f32, distance: f32 },
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointUniform {
+    viewport: [f32; 2],
+    size: f32,
+    attenuation_distance: f32,
+}
+
+struct GpuCloud {
+    buffer: wgpu::Buffer,
+    num_points: u32,
+    // kept to upload it again when setup() runs on another device.
+    cloud: PointCloud,
+}
+
+struct GpuState {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+// Draws point clouds on top of the scene.
+pub struct PointCloudPass {
+    size: PointSize,
+    // waiting for their upload in the next update().
+    pending: Vec<PointCloud>,
+    clouds: Vec<GpuCloud>,
+    gpu: Option<GpuState>,
+}
+
+impl PointCloudPass {
+    pub fn new(size: PointSize) -> Self {
+        Self {
+            size,
+            pending: Vec::new(),
+            clouds: Vec::new(),
+            gpu: None,
+        }
+    }
+
+    pub fn add(&mut self, cloud: PointCloud) {
+        self.pending.push(cloud);
+    }
+
+    pub fn set_size(&mut self, size: PointSize) {
+        self.size = size;
+    }
+
+    pub fn num_points(&self) -> usize {
+        let uploaded: usize = self.clouds.iter().map(|cloud| cloud.num_points as usize).sum();
+        let pending: usize = self.pending.iter().map(|cloud| cloud.points.len()).sum();
+        uploaded + pending
+    }
+
+    fn uniform(&self, width: u32, height: u32) -> PointUniform {
+        let (size, attenuation_distance) = match self.size {
+            PointSize::Pixels(pixels) => (pixels, 0.0),
+            PointSize::Attenuated { pixels, distance } => (pixels, distance),
+        };
+        PointUniform {
+            viewport: [width.max(1) as f32, height.max(1) as f32],
+            size,
+            attenuation_distance,
+        }
+    }
+}
+
+impl RenderPlugin for PointCloudPass {
+    fn name(&self) -> &str {
+        "Point Cloud"
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        let bounds = self.clouds.iter()
+            .map(|uploaded| &uploaded.cloud.bounds)
+            .chain(self.pending.iter().map(|cloud| &cloud.bounds))
+            .fold(Bounds::empty(), |bounds, cloud| bounds.union(cloud));
+        if bounds.is_empty() { None } else { Some(bounds) }
+    }
+
+    fn setup(&mut self, ctx: &PluginContext) {
+        let device = ctx.device;
+
+        // a new device after a backend switch, the clouds go up again.
+        let mut clouds: Vec<PointCloud> = self.clouds.drain(..).map(|uploaded| uploaded.cloud).collect();
+        clouds.append(&mut self.pending);
+        self.pending = clouds;
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Point Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[self.uniform(ctx.config.width, ctx.config.height)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("point_uniform_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("point_uniform_bind_group"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Point Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("point_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Point Pipeline Layout"),
+            bind_group_layouts: &[ctx.camera_bind_group_layout, &uniform_layout],
+            push_constant_ranges: &[],
+        });
+        // plugins draw into the resolved surface texture -> no msaa.
+        let pipeline = crate::renderer::create_render_pipeline(
+            device,
+            &layout,
+            ctx.config.format,
+            1,
+            None,
+            &[PointVertex::desc()],
+            &shader,
+        );
+
+        self.gpu = Some(GpuState { pipeline, uniform_buffer, uniform_bind_group });
+    }
+
+    fn update(&mut self, ctx: &PluginContext) {
+        for cloud in self.pending.drain(..) {
+            let buffer = ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Point Cloud Buffer"),
+                contents: bytemuck::cast_slice(&cloud.points),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            self.clouds.push(GpuCloud { buffer, num_points: cloud.points.len() as u32, cloud });
+        }
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    ) {
+        let gpu = match &self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        if self.clouds.is_empty() {
+            return;
+        }
+
+        // size and viewport are cheap enough to upload every frame.
+        ctx.queue.write_buffer(
+            &gpu.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform(targets.width, targets.height)]),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Point Cloud Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: targets.color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&gpu.pipeline);
+        render_pass.set_bind_group(0, targets.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &gpu.uniform_bind_group, &[]);
+        for cloud in &self.clouds {
+            render_pass.set_vertex_buffer(0, cloud.buffer.slice(..));
+            render_pass.draw(0..6, 0..cloud.num_points);
+        }
+    }
+}
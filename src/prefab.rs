@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use crate::scene::Scene;
+
+/*
+    Prefabs: a few models with their mesh offsets, materials and hidden
+    meshes, saved to a file and placed into the scene as often as needed.
+
+    A prefab file is a scene file with only models in it (see scene.rs).
+    Every placement is a PrefabInstance, which remembers the file, where
+    it was placed and which models it added. When the prefab file
+    changes, update_prefab() brings all its instances up to date at
+    once. Scenes save the instances as `prefab` entries instead of
+    their models, so they stay instances when loaded again.
+
+    usage:
+        renderer.save_prefab("crates.prefab", &[selected_model])?;
+        renderer.instantiate_prefab("crates.prefab", [4.0, 0.0, 0.0])?;
+        ...
+        // after crates.prefab was edited
+        renderer.update_prefab("crates.prefab")?;
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabInstance {
+    pub path: PathBuf,
+    // added to the offsets of all meshes of the prefab.
+    pub offset: [f32; 3],
+    // indices of the models it placed, in the order of the prefab file.
+    pub models: Vec<usize>,
+}
+
+impl PrefabInstance {
+    // keeps the indices right when the model at `index` is removed or
+    // a model is inserted there. Returns whether the removed model was ours.
+    pub fn shift_models(&mut self, index: usize, inserted: bool) -> bool {
+        let mut contained = false;
+        if !inserted {
+            let before = self.models.len();
+            self.models.retain(|model| *model != index);
+            contained = self.models.len() != before;
+        }
+        for model in &mut self.models {
+            if inserted && *model >= index {
+                *model += 1;
+            } else if !inserted && *model > index {
+                *model -= 1;
+            }
+        }
+        contained
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene> {
+    let path = path.as_ref();
+    let prefab = Scene::load(path)?;
+    if !prefab.prefabs.is_empty() {
+        bail!("Prefab {:?} contains prefabs, only models are supported.", path);
+    }
+    Ok(prefab)
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::camera::Camera;
+use crate::fetch;
+use crate::light::Light;
+use crate::model::{Bounds, Material, Mesh, MeshData, Model};
+use crate::texture::Texture;
+
+/*
+    Material preview: one material or texture on its own, on a ball on a
+    pedestal in front of a neutral gray backdrop, lit by three lights
+    (key, fill and rim). For editing the material library, where the
+    scene is the wrong place to judge a material: too far, too dark,
+    on the wrong mesh.
+
+    While the preview is shown it's drawn instead of the scene, by the
+    camera PREVIEW_CAMERA, and its lights shade it instead of the scene's.
+    The scene stays as it is, picking and edits still go to it; hiding
+    the preview switches back to the camera that was active before.
+
+    The ball is textured by its uvs, the texture wrapped around it once,
+    so uv_scale and the other uv settings of the material show too.
+
+    usage:
+        renderer.show_preview(Some(PreviewSubject::Material(3)))?;
+        renderer.show_preview(Some(PreviewSubject::Texture("rust.png".into())))?;
+        renderer.show_preview(None)?;
+*/
+
+// the camera the preview is drawn with, added while it's shown.
+pub const PREVIEW_CAMERA: &str = "preview";
+
+// clear color behind the backdrop, at the edges of wide windows.
+pub const BACKGROUND: wgpu::Color = wgpu::Color { r: 0.18, g: 0.18, b: 0.18, a: 1.0 };
+
+// the ball, standing on the pedestal.
+const BALL_CENTER: [f32; 3] = [0.0, 1.6, 0.0];
+const BALL_RADIUS: f32 = 1.0;
+// smooth enough for highlights to stay round.
+const BALL_RINGS: u32 = 48;
+const BALL_SEGMENTS: u32 = 96;
+
+const PEDESTAL: Bounds = Bounds { min: [-0.7, 0.0, -0.7], max: [0.7, 0.6, 0.7] };
+const FLOOR: Bounds = Bounds { min: [-8.0, -0.1, -4.0], max: [8.0, 0.0, 8.0] };
+const BACKDROP: Bounds = Bounds { min: [-8.0, 0.0, -4.2], max: [8.0, 8.0, -4.0] };
+
+// neutral grays, the backdrop lighter than the pedestal so the ball
+// stands out against both.
+const BALL_COLOR: [u8; 4] = [200, 200, 200, 255];
+const PEDESTAL_COLOR: [u8; 4] = [90, 90, 90, 255];
+const BACKDROP_COLOR: [u8; 4] = [150, 150, 150, 255];
+
+// what the preview shows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviewSubject {
+    // of the renderer's materials (add_material(), material libraries).
+    Material(usize),
+    // material `material` of the model at `model`.
+    ModelMaterial { model: usize, material: usize },
+    // an image file, as the diffuse texture of an otherwise plain material.
+    Texture(PathBuf),
+}
+
+pub struct MaterialPreview {
+    subject: PreviewSubject,
+    // the ball and the stage (pedestal, floor and backdrop), parallel
+    // like the renderer's models, paths and hidden meshes.
+    models: Vec<Model>,
+    names: Vec<PathBuf>,
+    hidden: Vec<BTreeSet<usize>>,
+    // for PreviewSubject::Texture.
+    texture_material: Option<Material>,
+    lights: Vec<Light>,
+    // active before the preview was shown.
+    previous_camera: String,
+}
+
+impl MaterialPreview {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        subject: PreviewSubject,
+        previous_camera: &str,
+    ) -> Result<Self> {
+        let texture_material = match &subject {
+            PreviewSubject::Texture(path) => {
+                let image = image::open(fetch::local(path)?).with_context(|| format!("Unable to load texture {:?}.", path))?;
+                let image = image::DynamicImage::ImageRgba8(image.to_rgba8());
+                let texture = Texture::from_image(device, queue, &image, path.to_str())?;
+                Some(Material::new(device, layout, "Preview Texture", texture))
+            }
+            _ => None,
+        };
+
+        let plain = |name: &str, color: [u8; 4]| {
+            Material::new(device, layout, name, Texture::from_color(device, queue, color, name))
+        };
+        let model = |meshes: Vec<MeshData>, materials: Vec<Material>| {
+            let mut bounds = Bounds::empty();
+            for mesh in &meshes {
+                bounds.extend(mesh.bounds.min);
+                bounds.extend(mesh.bounds.max);
+            }
+            Model {
+                meshes: meshes.iter().map(|mesh| Mesh::from_data(device, mesh)).collect(),
+                materials,
+                bounds,
+                instances: None,
+            }
+        };
+
+        // the ball's own material is only drawn when the subject's is gone.
+        let ball = model(
+            vec![MeshData::sphere("Preview Ball", BALL_CENTER, BALL_RADIUS, BALL_RINGS, BALL_SEGMENTS)],
+            vec![plain("Preview Ball", BALL_COLOR)],
+        );
+        let mut floor = MeshData::cuboid("Preview Floor", &FLOOR);
+        let mut backdrop = MeshData::cuboid("Preview Backdrop", &BACKDROP);
+        floor.material = 1;
+        backdrop.material = 1;
+        let stage = model(
+            vec![MeshData::cuboid("Preview Pedestal", &PEDESTAL), floor, backdrop],
+            vec![plain("Preview Pedestal", PEDESTAL_COLOR), plain("Preview Backdrop", BACKDROP_COLOR)],
+        );
+
+        Ok(Self {
+            subject,
+            models: vec![ball, stage],
+            names: vec![PathBuf::from("preview ball"), PathBuf::from("preview stage")],
+            hidden: vec![BTreeSet::new(), BTreeSet::new()],
+            texture_material,
+            lights: three_point_lights(),
+            previous_camera: previous_camera.to_string(),
+        })
+    }
+
+    pub fn subject(&self) -> &PreviewSubject {
+        &self.subject
+    }
+
+    pub fn previous_camera(&self) -> &str {
+        &self.previous_camera
+    }
+
+    // the ball first, then the stage.
+    pub fn models(&self) -> &[Model] {
+        &self.models
+    }
+
+    pub fn names(&self) -> &[PathBuf] {
+        &self.names
+    }
+
+    pub fn hidden(&self) -> &[BTreeSet<usize>] {
+        &self.hidden
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    // the material the ball is drawn with, out of the renderer's
+    // `materials` and `models`. None when it was removed since.
+    pub fn material<'a>(&'a self, materials: &'a [Material], models: &'a [Model]) -> Option<&'a Material> {
+        match &self.subject {
+            PreviewSubject::Material(index) => materials.get(*index),
+            PreviewSubject::ModelMaterial { model, material } => {
+                models.get(*model).and_then(|model| model.materials.get(*material))
+            }
+            PreviewSubject::Texture(_) => self.texture_material.as_ref(),
+        }
+    }
+}
+
+// a little above the ball's center and in front of it, the whole
+// pedestal in the picture.
+pub fn camera(aspect: f32) -> Camera {
+    Camera::looking_at((0.0, 2.2, 5.0).into(), (0.0, 1.3, 0.0).into(), aspect)
+}
+
+// key from the front right and above, warm; a weaker cool fill from the
+// left; the rim from behind, for the silhouette against the backdrop.
+fn three_point_lights() -> Vec<Light> {
+    vec![
+        Light::new((3.0, 4.0, 3.5).into(), [1.0, 0.95, 0.85]),
+        Light::new((-4.0, 2.0, 2.5).into(), [0.3, 0.35, 0.45]),
+        Light::new((0.5, 3.5, -3.0).into(), [0.7, 0.7, 0.7]),
+    ]
+}
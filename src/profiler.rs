@@ -0,0 +1,260 @@
+use std::time::{Duration, Instant};
+
+use crate::locale::Strings;
+use crate::overlay::{self, Overlay};
+
+/*
+    Cpu profiler: where the time of a frame goes on the cpu, as a tree of
+    named scopes.
+
+    The code between begin() and end() is timed, scopes begun inside
+    another one are its children. The same scope begun again under the
+    same parent, e.g. for every input event, adds up and counts the
+    calls. The renderer times its part of render_frame() (the frame
+    limiter, finished jobs, animation, culling, uploads, the acquire of the
+    surface texture, encode with its passes and submit), the application
+    its own around it (input, update).
+
+    At the end of every frame (render_frame() calls end_frame()) the
+    frame is counted; every INTERVAL the sums are averaged per frame,
+    like the frame statistics (stats.rs), and that's what scopes() returns
+    and the overlay shows: the tree as text, milliseconds per frame and
+    share of the frame, and above it a flame graph, every scope a bar
+    under its parent as wide as its share of the frame.
+
+    Only the cpu is timed, wgpu times the gpu with timestamp queries only,
+    which not all adapters have (see resolution.rs). While the profiler
+    is off a scope costs a branch.
+
+    usage:
+        renderer.profiler_mut().set_enabled(true);
+        renderer.profiler_mut().begin("input");
+        handle_input();
+        renderer.profiler_mut().end();
+        renderer.render_frame()?;
+        if renderer.profiler().updated() {
+            let profiler = renderer.profiler().clone();
+            profiler.draw(renderer.plugin_mut::<Overlay>().unwrap(), 10.0, 200.0);
+        }
+*/
+
+pub const OVERLAY_LAYER: &str = "profiler";
+
+const TEXT_COLOR: [f32; 4] = [0.8, 1.0, 0.8, 1.0];
+const INTERVAL: Duration = Duration::from_millis(500);
+
+// the flame graph, in ui pixels: the whole frame is FLAME_WIDTH wide,
+// every level of the tree a row of bars BAR_HEIGHT high.
+const FLAME_WIDTH: f32 = 360.0;
+const BAR_HEIGHT: f32 = 8.0;
+// the square in front of every line of the tree, in the color of its bar.
+const SWATCH_SIZE: f32 = 9.0;
+
+// a scope, averaged per frame over the last interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileScope {
+    pub name: &'static str,
+    // 0 for the scopes outside of all others.
+    pub depth: usize,
+    // index into scopes(), None at depth 0.
+    pub parent: Option<usize>,
+    // inclusive, with the children.
+    pub time: Duration,
+    pub calls: f32,
+}
+
+// a scope while the interval is summed up.
+#[derive(Clone)]
+struct Node {
+    name: &'static str,
+    parent: Option<usize>,
+    time: Duration,
+    calls: u32,
+}
+
+#[derive(Clone)]
+pub struct Profiler {
+    enabled: bool,
+    // sums since the last update, scopes in the order they were first begun.
+    nodes: Vec<Node>,
+    // the begun scopes, innermost last.
+    open: Vec<(usize, Instant)>,
+    frames: u32,
+    started: Instant,
+    // averages shown right now, the tree depth first.
+    shown: Vec<ProfileScope>,
+    frame_time: Duration,
+    updated: bool,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            nodes: Vec::new(),
+            open: Vec::new(),
+            frames: 0,
+            started: Instant::now(),
+            shown: Vec::new(),
+            frame_time: Duration::ZERO,
+            updated: false,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // switching it drops what was measured so far.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        *self = Self { enabled, ..Self::default() };
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn begin(&mut self, name: &'static str) {
+        if !self.enabled {
+            return;
+        }
+        let parent = self.open.last().map(|(index, _)| *index);
+        let index = match self.nodes.iter().position(|node| node.parent == parent && node.name == name) {
+            Some(index) => index,
+            None => {
+                self.nodes.push(Node { name, parent, time: Duration::ZERO, calls: 0 });
+                self.nodes.len() - 1
+            }
+        };
+        self.nodes[index].calls += 1;
+        self.open.push((index, Instant::now()));
+    }
+
+    // ends the innermost scope. Without one, e.g. when the profiler was
+    // switched on inside of it, nothing happens.
+    pub fn end(&mut self) {
+        if let Some((index, start)) = self.open.pop() {
+            self.nodes[index].time += start.elapsed();
+        }
+    }
+
+    // counts a frame, every INTERVAL the averages are updated. Not while
+    // a scope is open, it would be cut in two.
+    pub fn end_frame(&mut self) {
+        self.updated = false;
+        if !self.enabled {
+            return;
+        }
+        self.frames += 1;
+        let elapsed = self.started.elapsed();
+        if elapsed < INTERVAL || !self.open.is_empty() {
+            return;
+        }
+
+        let frames = self.frames;
+        let mut shown = Vec::with_capacity(self.nodes.len());
+        self.collect(None, 0, None, frames, &mut shown);
+        self.shown = shown;
+        self.frame_time = elapsed / frames;
+        self.updated = true;
+
+        self.nodes.clear();
+        self.frames = 0;
+        self.started = Instant::now();
+    }
+
+    // the children of `parent` and theirs, depth first. `shown_parent`
+    // is where `parent` went in `shown`.
+    fn collect(&self, parent: Option<usize>, depth: usize, shown_parent: Option<usize>, frames: u32, shown: &mut Vec<ProfileScope>) {
+        for (index, node) in self.nodes.iter().enumerate().filter(|(_, node)| node.parent == parent) {
+            shown.push(ProfileScope {
+                name: node.name,
+                depth,
+                parent: shown_parent,
+                time: node.time / frames,
+                calls: node.calls as f32 / frames as f32,
+            });
+            let position = shown.len() - 1;
+            self.collect(Some(index), depth + 1, Some(position), frames, shown);
+        }
+    }
+
+    // true when the last end_frame() updated the averages.
+    pub fn updated(&self) -> bool {
+        self.updated
+    }
+
+    // averaged over the last interval, the tree depth first.
+    pub fn scopes(&self) -> &[ProfileScope] {
+        &self.shown
+    }
+
+    // from the end of one frame to the end of the next, averaged too.
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    // share of the frame, 0-1.
+    fn share(&self, time: Duration) -> f32 {
+        if self.frame_time.is_zero() {
+            return 0.0;
+        }
+        (time.as_secs_f64() / self.frame_time.as_secs_f64()) as f32
+    }
+
+    // a line for the frame and one for every scope, indented by depth, in
+    // the language of `strings`, see locale.rs.
+    pub fn lines(&self, strings: &Strings) -> Vec<String> {
+        let ms = |duration: Duration| format!("{:.2}", duration.as_secs_f64() * 1000.0);
+        let mut lines = vec![strings.format("profiler.frame", &[("ms", &ms(self.frame_time))])];
+        for scope in &self.shown {
+            let calls = if (scope.calls - 1.0).abs() < 0.05 {
+                String::new()
+            } else {
+                strings.format("profiler.calls", &[("calls", &format!("{:.1}", scope.calls))])
+            };
+            lines.push(format!(
+                "{}{} {} ms {:.0}% {}",
+                "  ".repeat(scope.depth),
+                scope.name,
+                ms(scope.time),
+                self.share(scope.time) * 100.0,
+                calls,
+            ).trim_end().to_string());
+        }
+        lines
+    }
+
+    // top left corner of the flame graph at x/y ui pixels, the tree below it.
+    pub fn draw(&self, overlay: &mut Overlay, x: f32, y: f32) {
+        let lines = self.lines(overlay.strings());
+        let colors: Vec<[f32; 4]> = (0..self.shown.len()).map(|index| overlay.palette().category(index)).collect();
+        let depth = self.shown.iter().map(|scope| scope.depth + 1).max().unwrap_or(0);
+        let layer = overlay.layer(OVERLAY_LAYER);
+        layer.clear();
+
+        // every bar starts where its previous sibling ended, the first
+        // one where its parent starts. Depth first, the children of a
+        // scope come right after it.
+        let mut next = vec![0.0; depth + 1];
+        for (index, scope) in self.shown.iter().enumerate() {
+            let start = next[scope.depth];
+            let width = self.share(scope.time).min(1.0) * FLAME_WIDTH;
+            next[scope.depth] = start + width;
+            next[scope.depth + 1] = start;
+            layer.rect(x + start, y + scope.depth as f32 * BAR_HEIGHT, [(width - 1.0).max(1.0), BAR_HEIGHT - 1.0], colors[index]);
+        }
+
+        let top = y + depth as f32 * BAR_HEIGHT + 4.0;
+        for (index, line) in lines.iter().enumerate() {
+            let line_y = top + index as f32 * overlay::GLYPH_HEIGHT;
+            // the first line is the frame, without a bar.
+            if let Some(color) = index.checked_sub(1).and_then(|scope| colors.get(scope)) {
+                layer.rect(x, line_y + (overlay::GLYPH_HEIGHT - SWATCH_SIZE) * 0.5, [SWATCH_SIZE, SWATCH_SIZE], *color);
+            }
+            layer.text(x + SWATCH_SIZE + 4.0, line_y, line, TEXT_COLOR);
+        }
+    }
+}
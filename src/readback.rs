@@ -0,0 +1,415 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use anyhow::{bail, Context as _, Result};
+
+use crate::jobs::Finished;
+
+/*
+    Reading textures and buffers back from the gpu.
+
+    Copies to the cpu go through a staging buffer with MAP_READ, texture
+    rows in it have to be aligned to 256 bytes, the helpers take care of
+    that and return tightly packed data. The textures and buffers read
+    from need COPY_SRC in their usage.
+
+    The helpers are async, the data is there once the mapping is done.
+    The mapping only finishes while the device is polled, natively they
+    poll it themselves (blocking until the copy is done), on the web the
+    browser does it. Outside of an executor pollster::block_on() runs them.
+
+    Each helper submits its copy on its own, after whatever was submitted
+    before, so the frame that drew into the texture has to be submitted
+    first.
+
+    PendingReadback doesn't wait at all: it submits the copy of a color
+    texture and is polled, e.g. once a frame, until the image is there.
+    Readbacks polls the ones that belong together (the faces of a cube
+    map, the tiles of a poster) and hands them to a finish once all are
+    back, the renderer puts them together on the job pool, see jobs.rs.
+
+    usage:
+        let image = pollster::block_on(readback::read_texture_to_image(
+            &device, &queue, &texture, wgpu::TextureFormat::Rgba8UnormSrgb, width, height,
+        ))?;
+        let counts: Vec<u32> = pollster::block_on(readback::read_buffer_to_vec(&device, &queue, &buffer, 16))?;
+
+        let pending = PendingReadback::start(&device, &queue, &texture, format, width, height)?;
+        // every frame
+        if let Some(image) = pending.poll(&device) {
+            image?.save("shot.png")?;
+        }
+
+        readbacks.push(vec![left, right], |context, texels| { ... });
+        // every frame
+        for finish in readbacks.poll(&device) {
+            finish(&mut context);
+        }
+*/
+
+// Reads `texture` back into tightly packed rows of `bytes_per_pixel` wide texels.
+pub async fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+) -> Result<Vec<u8>> {
+    let (staging, padded_bytes_per_row) = copy_texture(device, queue, texture, width, height, bytes_per_pixel);
+    let data = map(device, &staging).await?;
+    Ok(unpad(&data, padded_bytes_per_row, bytes_per_pixel * width))
+}
+
+// Submits the copy of `texture` into a new staging buffer, returns it and
+// the bytes of a row in it.
+fn copy_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+) -> (wgpu::Buffer, u32) {
+    // rows of a texture -> buffer copy have to be aligned to 256 bytes.
+    let unpadded_bytes_per_row = bytes_per_pixel * width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Texture Readback"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Texture Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &staging,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+    (staging, padded_bytes_per_row)
+}
+
+// the rows of a staging buffer without the padding at their ends.
+fn unpad(data: &[u8], padded_bytes_per_row: u32, unpadded_bytes_per_row: u32) -> Vec<u8> {
+    data.chunks(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+// Reads a color texture back as an image, 8 bit formats as they are,
+// bgra swizzled, float formats clamped to 0..1.
+pub async fn read_texture_to_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> Result<image::RgbaImage> {
+    let bytes = read_texture(device, queue, texture, width, height, bytes_per_texel(format)?).await?;
+    to_image(bytes, format, width, height)
+}
+
+// of the formats read_texture_to_image() reads, and depth textures.
+fn bytes_per_texel(format: wgpu::TextureFormat) -> Result<u32> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm
+        | wgpu::TextureFormat::Rgba8UnormSrgb
+        | wgpu::TextureFormat::Bgra8Unorm
+        | wgpu::TextureFormat::Bgra8UnormSrgb
+        | wgpu::TextureFormat::Depth32Float => Ok(4),
+        wgpu::TextureFormat::Rgba16Float => Ok(8),
+        format => bail!("Unable to read back a {:?} texture.", format),
+    }
+}
+
+// the texels of a `format` texture as an rgba image.
+pub fn to_image(mut bytes: Vec<u8>, format: wgpu::TextureFormat, width: u32, height: u32) -> Result<image::RgbaImage> {
+    let pixels = match format {
+        // surfaces are mostly bgra.
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for pixel in bytes.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            bytes
+        }
+        wgpu::TextureFormat::Rgba16Float => halves_to_floats(&bytes)
+            .iter()
+            .flat_map(|texel| texel.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+            .collect(),
+        wgpu::TextureFormat::Depth32Float => bail!("A depth texture is no color image, see depths()."),
+        _ => bytes,
+    };
+    image::RgbaImage::from_raw(width, height, pixels).context("Readback has the wrong size.")
+}
+
+// Reads an Rgba16Float texture back as floats.
+pub async fn read_texture_to_floats(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+) -> Result<Vec<[f32; 4]>> {
+    let bytes = read_texture(device, queue, texture, width, height, 8).await?;
+    Ok(halves_to_floats(&bytes))
+}
+
+// the texels of a Depth32Float texture.
+pub fn depths(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4).map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]])).collect()
+}
+
+// the texels of an Rgba16Float texture.
+pub fn halves_to_floats(bytes: &[u8]) -> Vec<[f32; 4]> {
+    bytes
+        .chunks_exact(8)
+        .map(|texel| {
+            let channel = |i: usize| half_to_f32(u16::from_le_bytes([texel[2 * i], texel[2 * i + 1]]));
+            [channel(0), channel(1), channel(2), channel(3)]
+        })
+        .collect()
+}
+
+// A color or Depth32Float texture on its way back, see read_texture_to_image()
+// for the color formats. Polled instead of awaited, nothing waits for the gpu.
+pub struct PendingReadback {
+    staging: wgpu::Buffer,
+    mapping: Mapping,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl PendingReadback {
+    // submits the copy, after the frame that drew into `texture`.
+    pub fn start(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let bytes_per_pixel = bytes_per_texel(format)?;
+        let (staging, padded_bytes_per_row) = copy_texture(device, queue, texture, width, height, bytes_per_pixel);
+        let mapping = Mapping::default();
+        let done = mapping.clone();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| done.finish(result));
+        Ok(Self { staging, mapping, format, width, height, padded_bytes_per_row })
+    }
+
+    // the image once the copy is done, None before. Polls the device
+    // without waiting, an error only comes once.
+    pub fn poll(&self, device: &wgpu::Device) -> Option<Result<image::RgbaImage>> {
+        device.poll(wgpu::Maintain::Poll);
+        let texels = self.take()?;
+        Some(texels.and_then(|texels| to_image(texels, self.format, self.width, self.height)))
+    }
+
+    // the tightly packed texels once the mapping is done, without polling.
+    // to_image() or halves_to_floats() turn them into something.
+    fn take(&self) -> Option<Result<Vec<u8>>> {
+        let result = self.mapping.0.lock().unwrap().result.take()?;
+        Some(result.context("Unable to map the readback buffer.").and_then(|()| {
+            let data = self.staging.slice(..).get_mapped_range().to_vec();
+            self.staging.unmap();
+            let bytes_per_pixel = bytes_per_texel(self.format)?;
+            Ok(unpad(&data, self.padded_bytes_per_row, bytes_per_pixel * self.width))
+        }))
+    }
+}
+
+type ReadbacksFinish<C> = Box<dyn FnOnce(&mut C, Result<Vec<Vec<u8>>>)>;
+
+// PendingReadbacks that are finished together, with what's back of them.
+struct Batch<C> {
+    parts: Vec<(PendingReadback, Option<Vec<u8>>)>,
+    finish: ReadbacksFinish<C>,
+}
+
+// Polls PendingReadbacks in batches. Like the finish of a job, the one of
+// a batch runs on the thread that polls, with its context `C`.
+pub struct Readbacks<C> {
+    batches: Vec<Batch<C>>,
+}
+
+impl<C: 'static> Readbacks<C> {
+    pub fn new() -> Self {
+        Self { batches: Vec::new() }
+    }
+
+    // `finish` gets the texels of all `parts`, in their order, or the
+    // first error. See PendingReadback::take() for the texels.
+    pub fn push<F>(&mut self, parts: Vec<PendingReadback>, finish: F)
+    where
+        F: FnOnce(&mut C, Result<Vec<Vec<u8>>>) + 'static,
+    {
+        let parts = parts.into_iter().map(|part| (part, None)).collect();
+        self.batches.push(Batch { parts, finish: Box::new(finish) });
+    }
+
+    // batches that aren't back yet.
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    // The finishes of the batches that are back, to be called with the
+    // context. Polls the device without waiting.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Vec<Finished<C>> {
+        device.poll(wgpu::Maintain::Poll);
+        let mut finished: Vec<Finished<C>> = Vec::new();
+        let mut index = 0;
+        while index < self.batches.len() {
+            let mut failed = None;
+            for (part, texels) in &mut self.batches[index].parts {
+                if texels.is_none() {
+                    match part.take() {
+                        Some(Ok(back)) => *texels = Some(back),
+                        Some(Err(e)) => failed = Some(e),
+                        None => {}
+                    }
+                }
+            }
+            let done = failed.is_some() || self.batches[index].parts.iter().all(|(_, texels)| texels.is_some());
+            if !done {
+                index += 1;
+                continue;
+            }
+            // the other parts of a failed batch are dropped with their staging buffers.
+            let batch = self.batches.remove(index);
+            let result = match failed {
+                Some(e) => Err(e),
+                None => Ok(batch.parts.into_iter().filter_map(|(_, texels)| texels).collect()),
+            };
+            let finish = batch.finish;
+            finished.push(Box::new(move |context: &mut C| finish(context, result)));
+        }
+        finished
+    }
+}
+
+impl<C: 'static> Default for Readbacks<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Reads the first `len` `T`s of `buffer` back, e.g. counters of a compute pass.
+// wgpu buffers don't know their size, the caller does. Copies are in
+// steps of 4 bytes, `len` of them have to fill whole steps.
+pub async fn read_buffer_to_vec<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    len: usize,
+) -> Result<Vec<T>> {
+    let element = std::mem::size_of::<T>();
+    let size = (element * len) as wgpu::BufferAddress;
+    if element == 0 || size == 0 || size % wgpu::COPY_BUFFER_ALIGNMENT != 0 {
+        bail!("Unable to read back {} x {}, the copy has to be a multiple of 4 bytes.", len, std::any::type_name::<T>());
+    }
+
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Buffer Readback"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Buffer Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let data = map(device, &staging).await?;
+    // the copied bytes have no alignment for T.
+    Ok(data.chunks_exact(element).map(bytemuck::pod_read_unaligned).collect())
+}
+
+// Maps the whole staging buffer and copies it out.
+async fn map(device: &wgpu::Device, staging: &wgpu::Buffer) -> Result<Vec<u8>> {
+    let slice = staging.slice(..);
+    let mapping = Mapping::default();
+    let done = mapping.clone();
+    slice.map_async(wgpu::MapMode::Read, move |result| done.finish(result));
+    device.poll(wgpu::Maintain::Wait);
+    mapping.await.context("Unable to map the readback buffer.")?;
+    let data = slice.get_mapped_range().to_vec();
+    staging.unmap();
+    Ok(data)
+}
+
+// map_async() reports through a callback, this turns it into a future.
+#[derive(Clone, Default)]
+struct Mapping(Arc<Mutex<MappingState>>);
+
+#[derive(Default)]
+struct MappingState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+impl Mapping {
+    fn finish(&self, result: Result<(), wgpu::BufferAsyncError>) {
+        let mut state = self.0.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for Mapping {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Rgba16Float has no cpu type in the tree, this is enough for reading it.
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        // subnormals
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
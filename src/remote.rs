@@ -0,0 +1,405 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::Result;
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobPriority;
+use crate::renderer::Renderer;
+
+/*
+    Remote control: a small http server to drive the viewer from
+    scripts and other tools.
+
+        GET    /status          models, camera and the last frame as json
+        POST   /models          {"path": "model.obj"}, loads a model
+        DELETE /models/<index>  takes the model out of the scene
+        GET    /camera          {"eye": [x, y, z], "target": [x, y, z]}
+        POST   /camera          the same, moves the camera
+        POST   /settings        {"fps_limit": 30, "background": [r, g, b, a],
+                                 "camera": "minimap", "flashlight": true},
+                                every field is optional, an fps_limit of 0
+                                and a background of null go back to the defaults
+        GET    /screenshot      the current view as png
+        POST   /screenshot      {"path": "shot.png"}, saves it on the viewer's machine
+
+    Answers are json, errors come as {"error": "..."} with a 4xx / 5xx
+    status.
+
+    The renderer can only be used on the thread that owns the window,
+    so there's no server thread: update() accepts connections and
+    answers the requests that fully arrived, once per frame. A
+    screenshot is answered a few frames later, once it's read back
+    and encoded on the job pool. Anyone who
+    can reach the address controls the viewer, bind it to 127.0.0.1
+    unless that's wanted.
+
+    The sockets are only there with the `network` feature, without it
+    listen() fails.
+
+    usage:
+        cargo run --features network -- view --remote 127.0.0.1:7879
+        curl -X POST localhost:7879/camera -d '{"eye": [0, 5, 10], "target": [0, 0, 0]}'
+        curl localhost:7879/screenshot -o shot.png
+        ...
+        let mut remote = RemoteControl::listen("127.0.0.1:7879")?;
+        // every frame, before rendering
+        remote.update(&mut renderer);
+*/
+
+pub const DEFAULT_PORT: u16 = 7879;
+
+// requests larger than this are refused.
+const MAX_REQUEST_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self { status: 200, content_type: "application/json", body },
+            Err(e) => Self::error(500, &e.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        let body = serde_json::to_vec(&serde_json::json!({ "error": message })).unwrap_or_default();
+        Self { status, content_type: "application/json", body }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+
+    // the whole response as it goes over the wire.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len(),
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+// The answer to a request, or where it goes once it's there.
+pub enum Reply {
+    Now(Response),
+    Later(Rc<RefCell<Option<Response>>>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct CameraPose {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub fps_limit: Option<f32>,
+    // null for the animated default, a missing field leaves it as it is.
+    #[serde(default, deserialize_with = "some")]
+    pub background: Option<Option<[f64; 4]>>,
+    pub camera: Option<String>,
+    pub flashlight: Option<bool>,
+}
+
+// tells a null apart from a missing field.
+fn some<'de, D: serde::Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+struct PathBody {
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct Status {
+    models: usize,
+    camera: CameraPose,
+    active_camera: String,
+    frame: u64,
+    frame_time_ms: f32,
+    draw_calls: u32,
+    triangles: u32,
+}
+
+// one request, answered with what it did.
+pub fn handle(renderer: &mut Renderer, request: &Request) -> Reply {
+    let segments: Vec<&str> = request.path.split('?').next().unwrap_or("")
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let result = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["status"]) => {
+            let stats = renderer.frame_stats();
+            Ok(Response::json(&Status {
+                models: renderer.models().len(),
+                camera: camera_pose(renderer),
+                active_camera: renderer.active_camera().to_string(),
+                frame: stats.frame,
+                frame_time_ms: stats.frame_time.as_secs_f32() * 1000.0,
+                draw_calls: stats.draw_calls,
+                triangles: stats.triangles,
+            }))
+        }
+        ("POST", ["models"]) => parse::<PathBody>(&request.body).and_then(|body| {
+            let index = renderer.load_model(&body.path).map_err(|e| Response::error(400, &format!("{:#}", e)))?;
+            Ok(Response::json(&serde_json::json!({ "index": index })))
+        }),
+        ("DELETE", ["models", index]) => match index.parse::<usize>() {
+            Ok(index) => renderer.remove_model(index)
+                .map(|_| Response::json(&serde_json::json!({ "removed": index })))
+                .map_err(|e| Response::error(400, &format!("{:#}", e))),
+            Err(_) => Err(Response::error(400, "The model index is no number.")),
+        },
+        ("GET", ["camera"]) => Ok(Response::json(&camera_pose(renderer))),
+        ("POST", ["camera"]) => parse::<CameraPose>(&request.body).map(|pose| {
+            renderer.camera_mut().look_at(Point3::from(pose.eye), Point3::from(pose.target));
+            Response::json(&pose)
+        }),
+        ("POST", ["settings"]) => parse::<Settings>(&request.body).and_then(|settings| {
+            apply_settings(renderer, &settings).map_err(|e| Response::error(400, &format!("{:#}", e)))?;
+            Ok(Response::json(&serde_json::json!({ "ok": true })))
+        }),
+        ("GET", ["screenshot"]) => return screenshot_png(renderer),
+        ("POST", ["screenshot"]) => parse::<PathBody>(&request.body).and_then(|body| {
+            renderer.save_screenshot(&body.path).map_err(|e| Response::error(500, &format!("{:#}", e)))?;
+            Ok(Response::json(&serde_json::json!({ "path": body.path })))
+        }),
+        (_, ["status"]) | (_, ["models", ..]) | (_, ["camera"]) | (_, ["settings"]) | (_, ["screenshot"]) => {
+            Err(Response::error(405, "This method isn't supported here."))
+        }
+        _ => Err(Response::error(404, "There's nothing here.")),
+    };
+    Reply::Now(result.unwrap_or_else(|response| response))
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, Response> {
+    serde_json::from_slice(body).map_err(|e| Response::error(400, &format!("The body can't be read: {}", e)))
+}
+
+fn camera_pose(renderer: &Renderer) -> CameraPose {
+    CameraPose {
+        eye: renderer.camera().eye().into(),
+        target: renderer.camera().target().into(),
+    }
+}
+
+fn apply_settings(renderer: &mut Renderer, settings: &Settings) -> Result<()> {
+    // the camera first, it's the only one that can fail.
+    if let Some(camera) = &settings.camera {
+        renderer.set_active_camera(camera)?;
+    }
+    if let Some(fps) = settings.fps_limit {
+        renderer.set_fps_limit(if fps > 0.0 { Some(fps) } else { None });
+    }
+    if let Some(background) = settings.background {
+        renderer.set_background(background.map(|[r, g, b, a]| wgpu::Color { r, g, b, a }));
+    }
+    if let Some(flashlight) = settings.flashlight {
+        renderer.set_flashlight_enabled(flashlight);
+    }
+    Ok(())
+}
+
+fn screenshot_png(renderer: &mut Renderer) -> Reply {
+    let answer = Rc::new(RefCell::new(None));
+    let later = answer.clone();
+    let taken = renderer.screenshot(move |renderer, image| {
+        let image = match image {
+            Ok(image) => image,
+            Err(e) => {
+                *later.borrow_mut() = Some(Response::error(500, &format!("{:#}", e)));
+                return;
+            }
+        };
+        renderer.spawn_job(JobPriority::Normal, "remote screenshot", move || -> Result<Vec<u8>> {
+            let mut png = Vec::new();
+            image::codecs::png::PngEncoder::new(&mut png)
+                .encode(&image, image.width(), image.height(), image::ColorType::Rgba8)?;
+            Ok(png)
+        }, move |_, png| {
+            *later.borrow_mut() = Some(match png {
+                Ok(body) => Response { status: 200, content_type: "image/png", body },
+                Err(e) => Response::error(500, &format!("{:#}", e)),
+            });
+        });
+    });
+    match taken {
+        Ok(()) => Reply::Later(answer),
+        Err(e) => Reply::Now(Response::error(500, &format!("{:#}", e))),
+    }
+}
+
+// what arrived of a request so far. Some(Err) when it can't be a valid one.
+pub fn parse_request(received: &[u8]) -> Option<Result<Request, Response>> {
+    let header_end = match received.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(end) => end,
+        None if received.len() > MAX_REQUEST_SIZE => return Some(Err(Response::error(413, "The request is too large."))),
+        None => return None,
+    };
+    let header = String::from_utf8_lossy(&received[..header_end]);
+    let mut lines = header.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) => (method.to_uppercase(), path.to_string()),
+        _ => return Some(Err(Response::error(400, "That's no http request."))),
+    };
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>());
+    let content_length = match content_length {
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Some(Err(Response::error(400, "The content length is no number."))),
+        None => 0,
+    };
+    if content_length > MAX_REQUEST_SIZE {
+        return Some(Err(Response::error(413, "The request is too large.")));
+    }
+
+    let body_start = header_end + 4;
+    if received.len() < body_start + content_length {
+        return None;
+    }
+    Some(Ok(Request { method, path, body: received[body_start..body_start + content_length].to_vec() }))
+}
+
+#[cfg(feature = "network")]
+struct Connection {
+    stream: std::net::TcpStream,
+    received: Vec<u8>,
+    // of a request that's answered in a later frame.
+    answer: Option<Rc<RefCell<Option<Response>>>>,
+}
+
+pub struct RemoteControl {
+    #[cfg(feature = "network")]
+    listener: std::net::TcpListener,
+    #[cfg(feature = "network")]
+    connections: Vec<Connection>,
+}
+
+#[cfg(feature = "network")]
+impl RemoteControl {
+    pub fn listen(address: &str) -> Result<Self> {
+        use anyhow::Context;
+
+        let listener = std::net::TcpListener::bind(address)
+            .with_context(|| format!("Unable to listen for remote control at {:?}.", address))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Remote control listening at http://{}", listener.local_addr()?);
+        Ok(Self { listener, connections: Vec::new() })
+    }
+
+    // answers the requests that arrived since the last frame.
+    pub fn update(&mut self, renderer: &mut Renderer) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.connections.push(Connection { stream, received: Vec::new(), answer: None });
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Unable to accept a remote control connection: {:?}", e);
+                    break;
+                }
+            }
+        }
+
+        self.connections.retain_mut(|connection| {
+            use std::io::Read;
+
+            if let Some(answer) = &connection.answer {
+                let response = answer.borrow_mut().take();
+                return match response {
+                    Some(response) => {
+                        send(connection, &response);
+                        false
+                    }
+                    None => true,
+                };
+            }
+
+            let mut buffer = [0u8; 4096];
+            let closed = loop {
+                match connection.stream.read(&mut buffer) {
+                    Ok(0) => break true,
+                    Ok(read) => connection.received.extend_from_slice(&buffer[..read]),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break false,
+                    Err(_) => break true,
+                }
+            };
+            let response = match parse_request(&connection.received) {
+                Some(Ok(request)) => {
+                    log::info!("Remote control: {} {}", request.method, request.path);
+                    match handle(renderer, &request) {
+                        Reply::Now(response) => response,
+                        Reply::Later(answer) => {
+                            connection.answer = Some(answer);
+                            return true;
+                        }
+                    }
+                }
+                Some(Err(response)) => response,
+                // waits for the rest, unless nothing more can come.
+                None => return !closed,
+            };
+            send(connection, &response);
+            false
+        });
+    }
+}
+
+#[cfg(feature = "network")]
+fn send(connection: &mut Connection, response: &Response) {
+    use std::io::Write;
+
+    // screenshots can be larger than the socket buffer, this one waits for them.
+    let sent = connection.stream.set_nonblocking(false)
+        .and_then(|_| connection.stream.set_write_timeout(Some(std::time::Duration::from_secs(5))))
+        .and_then(|_| connection.stream.write_all(&response.to_bytes()));
+    if let Err(e) = sent {
+        log::warn!("Unable to answer a remote control request: {:?}", e);
+    }
+}
+
+// without the feature there's nothing to listen with.
+#[cfg(not(feature = "network"))]
+impl RemoteControl {
+    pub fn listen(_address: &str) -> Result<Self> {
+        anyhow::bail!("Built without networking, enable the network feature.")
+    }
+
+    pub fn update(&mut self, _renderer: &mut Renderer) {}
+}
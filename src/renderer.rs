@@ -0,0 +1,3559 @@
+use winit::{
+    event_loop::EventLoop,
+    window::{Fullscreen, Window, WindowBuilder},
+};
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use wgpu::util::DeviceExt;
+
+use anyhow::{bail, Context, Result};
+
+use crate::budget::{self, BudgetLevel, MemoryBudget};
+use crate::camera;
+use crate::cameras::{CameraAttachment, CameraBinding, Cameras};
+use crate::capture::GpuCapture;
+use crate::clipping::{ClipPlane, Clipping};
+use crate::cubemap::{self, CubeFace, CubeMap};
+use crate::events::{EventHub, FrameStats, RendererEvent};
+use crate::explode::ExplodedView;
+use crate::fallback::{self, FallbackTexture};
+use crate::fetch;
+use crate::frame::FrameBuffer;
+use crate::inflight::{FramesInFlight, DEFAULT_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT};
+use crate::jobs::{JobId, JobPool, JobPriority, JobProgress, Jobs, Progress};
+use crate::light;
+use crate::lightmarker::LightMarkers;
+use crate::loader::{LoadMessage, ModelLoader};
+use crate::math;
+use crate::matlib::MaterialLibrary;
+use crate::model;
+use crate::monitors::{self, MonitorInfo};
+use crate::pacing::{FrameLimiter, FramePacing};
+use crate::overrides::MaterialOverrides;
+use crate::picking::{self, Hit, Plane, Ray};
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+use crate::resolution::{self, DynamicResolution, ResolutionSettings};
+use crate::prefab::{self, PrefabInstance};
+use crate::preview::{self, MaterialPreview, PreviewSubject};
+use crate::profiler::Profiler;
+use crate::scene::{Scene, ScenePrefab, SceneModel};
+use crate::shading::{self, LightBuffer, ShadingRate};
+use crate::lod::{self, DetailTier, LodSettings};
+use crate::shadow::{self, ShadowFit, ShadowMap};
+use crate::splat::{Brush, SplatLayers};
+use crate::stereo::{self, Eye, Stereo, StereoLayout, StereoSettings};
+use crate::subdivision::{SubdivisionSettings, TerrainSubdivision};
+use crate::readback::{self, PendingReadback, Readbacks};
+use crate::targets;
+use crate::tiles;
+use crate::texture;
+use crate::vat::VertexAnimation;
+use crate::vertex::{self, Vertex};
+use crate::wind::WindSway;
+
+/*
+    High-level entry point into the crate.
+
+    RendererBuilder collects the window and device options,
+    Renderer owns the window, the gpu handles and everything that
+    gets drawn each frame. That way an application only has to
+    drive the event loop and call render_frame().
+*/
+
+// how often the files of the material libraries are checked for changes.
+const LIBRARY_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+pub struct RendererBuilder {
+    title: String,
+    size: Option<winit::dpi::PhysicalSize<u32>>,
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    vsync: bool,
+    msaa_samples: u32,
+    features: wgpu::Features,
+    visible: bool,
+    fps_limit: Option<f32>,
+    shading_rate: ShadingRate,
+    transparent: bool,
+    memory_budget: Option<u64>,
+    auto_clip_planes: bool,
+    monitor: Option<String>,
+    fullscreen: bool,
+    supersampling: f32,
+    frames_in_flight: usize,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RendererBuilder {
+    pub fn new() -> Self {
+        Self {
+            title: "sneesh-x graphics".to_string(),
+            size: None,
+            // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            vsync: true,
+            msaa_samples: 1,
+            features: wgpu::Features::empty(),
+            visible: true,
+            fps_limit: None,
+            shading_rate: ShadingRate::Full,
+            transparent: false,
+            memory_budget: None,
+            auto_clip_planes: false,
+            monitor: None,
+            fullscreen: false,
+            supersampling: 1.0,
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+        }
+    }
+
+    /// Title of the window created by `build`.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Initial inner size of the window in physical pixels.
+    /// Without it the platform picks a default size.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some(winit::dpi::PhysicalSize::new(width, height));
+        self
+    }
+
+    /// Restrict the graphics apis the adapter may be picked from.
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// `true` presents with Fifo (capped to the display), `false` with Mailbox.
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
+    /// Sample count for multisampling, 1 disables it.
+    /// Most gpus support 1 and 4.
+    pub fn msaa(mut self, samples: u32) -> Self {
+        self.msaa_samples = samples.max(1);
+        self
+    }
+
+    /// Extra device features to request, on top of the ones the renderer needs.
+    pub fn features(mut self, features: wgpu::Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Caps the frame rate at `fps`, see set_fps_limit().
+    pub fn fps_limit(mut self, fps: f32) -> Self {
+        self.fps_limit = Some(fps);
+        self
+    }
+
+    /// Draws the scene at `factor` times the window's resolution, see
+    /// Renderer::set_supersampling().
+    pub fn supersampling(mut self, factor: f32) -> Self {
+        self.supersampling = factor;
+        self
+    }
+
+    /// `ShadingRate::Half` shades the lights at half resolution, for weak gpus.
+    pub fn shading_rate(mut self, shading_rate: ShadingRate) -> Self {
+        self.shading_rate = shading_rate;
+        self
+    }
+
+    /// `false` keeps the window hidden, e.g. to replay a recorded session
+    /// without one on the screen.
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    /// Window without decorations whose background shows the desktop, for
+    /// widget like applications. Only the models (and plugins) are opaque.
+    /// The surface keeps the alpha where the backend offers a premultiplied or
+    /// postmultiplied alpha mode, otherwise it stays opaque.
+    /// The platform has to composite the window as well (on X11 a compositor).
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Bytes of gpu memory the renderer warns about when they run out, see
+    /// budget.rs. Without one it's guessed from the kind of adapter.
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Fits the near and far plane of every camera to the visible meshes
+    /// each frame, instead of keeping the ones set on the cameras.
+    pub fn auto_clip_planes(mut self, auto: bool) -> Self {
+        self.auto_clip_planes = auto;
+        self
+    }
+
+    /// Opens the window in the middle of the monitor `selector` picks, its
+    /// number or (a part of) its name, see monitors.rs.
+    pub fn monitor(mut self, selector: &str) -> Self {
+        self.monitor = Some(selector.to_string());
+        self
+    }
+
+    /// Opens the window borderless fullscreen, on the monitor of `monitor()`
+    /// or the one the platform puts it on.
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    /// How many frames the cpu may prepare while the gpu hasn't finished
+    /// them yet, 1 to 4, see inflight.rs. More evens out uneven frames,
+    /// fewer cuts the latency. 2 by default.
+    pub fn frames_in_flight(mut self, count: usize) -> Self {
+        self.frames_in_flight = count.clamp(1, MAX_FRAMES_IN_FLIGHT);
+        self
+    }
+
+    /// Creates the window on `event_loop` and sets up the gpu for it.
+    pub fn build<T>(self, event_loop: &EventLoop<T>) -> Result<Renderer> {
+        let mut window_builder = WindowBuilder::new()
+            .with_title(&self.title)
+            .with_visible(self.visible)
+            .with_transparent(self.transparent)
+            .with_decorations(!self.transparent);
+        if let Some(size) = self.size {
+            window_builder = window_builder.with_inner_size(size);
+        }
+        let monitor = self.monitor.as_ref().and_then(|selector| {
+            let monitor = monitors::find(event_loop.available_monitors(), selector);
+            if monitor.is_none() {
+                log::warn!("No monitor {:?}, the window opens where the platform puts it.", selector);
+            }
+            monitor
+        });
+        if let Some(monitor) = &monitor {
+            // the size has to be known to center the window, it's the
+            // platform's default one in the monitor's pixels otherwise.
+            let size = self.size
+                .unwrap_or_else(|| monitors::DEFAULT_WINDOW_SIZE.to_physical(monitor.scale_factor()));
+            window_builder = window_builder
+                .with_inner_size(size)
+                .with_position(monitors::centered(monitor, size));
+        }
+        if self.fullscreen {
+            window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        }
+        let window = Rc::new(window_builder.build(event_loop)?);
+
+        let gpu = self.request_gpu(&window)?;
+        let renderer = self.finish(window, gpu)?;
+        renderer.surface.configure(&renderer.device, &renderer.config);
+        Ok(renderer)
+    }
+
+    // instance, surface, adapter and device for `window`, fails before
+    // anything of the renderer is touched, see Renderer::switch_backend().
+    fn request_gpu(&self, window: &Window) -> Result<Gpu> {
+        // The instance is a handle to our GPU
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        });
+        // the window lives in the Renderer, as long as the surface.
+        let surface = unsafe { instance.create_surface(&window) }?;
+        let adapter = pollster::block_on(instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            },
+        )).context("No adapter found that supports the window surface.")?;
+
+        // reguest the graphics card and message queue
+        // block thread until completion.
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: self.features,
+                limits: wgpu::Limits::default(),
+                label: Some("Renderer Device"),
+            },
+            None, // Trace path
+        ))?;
+
+        Ok(Gpu { surface, adapter, device, queue })
+    }
+
+    // everything else of the renderer, the surface isn't configured yet.
+    fn finish(self, window: Rc<Window>, gpu: Gpu) -> Result<Renderer> {
+        let Gpu { surface, adapter, device, queue } = gpu;
+        let size = window.inner_size();
+
+        let capabilities = surface.get_capabilities(&adapter);
+        // the shaders expect an srgb surface, the first format is the preferred one.
+        let format = capabilities.formats.iter().copied()
+            .find(|format| format.is_srgb())
+            .or_else(|| capabilities.formats.first().copied())
+            .context("Surface is incompatible with the adapter.")?;
+        // Fifo is always there, without vsync the first one that doesn't wait.
+        let present_mode = [wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate]
+            .iter()
+            .copied()
+            .find(|mode| !self.vsync && capabilities.present_modes.contains(mode))
+            .unwrap_or(wgpu::PresentMode::Fifo);
+        // a transparent window needs a surface that keeps the alpha.
+        let alpha_mode = [wgpu::CompositeAlphaMode::PreMultiplied, wgpu::CompositeAlphaMode::PostMultiplied]
+            .iter()
+            .copied()
+            .find(|mode| self.transparent && capabilities.alpha_modes.contains(mode))
+            .unwrap_or(wgpu::CompositeAlphaMode::Auto);
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode,
+            alpha_mode,
+            view_formats: vec![],
+        };
+
+        let texture_bind_group_layout = model::Material::bind_group_layout(&device);
+        // the stand ins for missing textures, made now instead of while a model loads.
+        fallback::images();
+
+        // camera
+        let camera_bind_group_layout = camera::UniformBuffer::bind_group_layout(&device);
+        let camera = camera::Camera::new(&config);
+
+        // flashlight, off until toggled.
+        let flashlight = light::SpotLight {
+            enabled: false,
+            ..light::SpotLight::new(camera.eye(), camera.target() - camera.eye())
+        };
+        let frame = FrameBuffer::new(&device);
+        let in_flight = FramesInFlight::new(&device, self.frames_in_flight);
+        let cameras = Cameras::new(&device, &camera_bind_group_layout, frame.buffer(), camera);
+        let flashlight_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Flashlight Buffer"),
+                contents: bytemuck::cast_slice(&[light::SpotUniform::from(&flashlight)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let flashlight_bind_group_layout = light::SpotLight::bind_group_layout(&device);
+        let lighting = light::Lighting::default();
+        let lights_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Lights Buffer"),
+                contents: bytemuck::cast_slice(&[light::LightsUniform::new(&[], &lighting)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let flashlight_cookie = texture::Texture::from_image(
+            &device,
+            &queue,
+            &image::DynamicImage::ImageRgba8(light::default_cookie(128)),
+            Some("Flashlight Cookie"),
+        )?;
+        let light_buffer = match self.shading_rate {
+            ShadingRate::Half => Some(LightBuffer::new(&device, &config)),
+            ShadingRate::Full => None,
+        };
+        let light_placeholder = shading::placeholder_view(&device);
+        let shadow_map = ShadowMap::new(&device, &camera_bind_group_layout, frame.buffer());
+        let flashlight_bind_group = create_spot_bind_group(
+            &device,
+            &flashlight_bind_group_layout,
+            &flashlight_buffer,
+            &flashlight_cookie,
+            light_buffer.as_ref().map_or(
+                (&light_placeholder, &light_placeholder),
+                |buffer| (buffer.view(), buffer.specular_view()),
+            ),
+            &lights_buffer,
+            &shadow_map,
+            shadow_map.view(),
+        );
+        // the light pass can't read the buffer it draws into.
+        let light_pass_bind_group = create_spot_bind_group(
+            &device,
+            &flashlight_bind_group_layout,
+            &flashlight_buffer,
+            &flashlight_cookie,
+            (&light_placeholder, &light_placeholder),
+            &lights_buffer,
+            &shadow_map,
+            shadow_map.view(),
+        );
+        // and the shadow pass not the map.
+        let shadow_pass_bind_group = create_spot_bind_group(
+            &device,
+            &flashlight_bind_group_layout,
+            &flashlight_buffer,
+            &flashlight_cookie,
+            (&light_placeholder, &light_placeholder),
+            &lights_buffer,
+            &shadow_map,
+            shadow_map.placeholder(),
+        );
+
+        let clipping = Clipping::new(&device, &config, &camera_bind_group_layout);
+
+        // create shader
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Basic Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("basic_shader.wgsl").into()),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &flashlight_bind_group_layout,
+                    clipping.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            config.format,
+            self.msaa_samples,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[vertex::MVertex::desc(), vertex::MeshOffset::desc(), vertex::InstanceRaw::desc()],
+            &shader,
+        );
+
+        let simple_pipeline = lod::create_simple_pipeline(
+            &device,
+            &render_pipeline_layout,
+            config.format,
+            self.msaa_samples,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[vertex::MVertex::desc(), vertex::MeshOffset::desc(), vertex::InstanceRaw::desc()],
+            &shader,
+        );
+
+        let light_pipeline = shading::create_light_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &[vertex::MVertex::desc(), vertex::MeshOffset::desc(), vertex::InstanceRaw::desc()],
+            &shader,
+        );
+
+        let shadow_pipeline = shadow::create_shadow_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &[vertex::MVertex::desc(), vertex::MeshOffset::desc(), vertex::InstanceRaw::desc()],
+            &shader,
+        );
+
+        let light_markers = LightMarkers::new(
+            &device,
+            &camera_bind_group_layout,
+            &flashlight_bind_group_layout,
+            config.format,
+            self.msaa_samples,
+            texture::Texture::DEPTH_FORMAT,
+        );
+
+        let msaa_framebuffer = create_msaa_framebuffer(&device, &config, self.msaa_samples);
+        let depth_texture = texture::Texture::create_depth_texture(&device, &config, self.msaa_samples, "Depth Texture");
+        let identity_instance = model::InstanceBuffer::identity(&device);
+        let adapter_info = adapter.get_info();
+        let memory_budget = MemoryBudget::new(
+            self.memory_budget.unwrap_or_else(|| budget::default_budget(&adapter_info)),
+        );
+
+        let supersampling = self.supersampling;
+        let pool = JobPool::new();
+        let mut renderer = Renderer {
+            window,
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            time: Instant::now(),
+            background: if self.transparent { Some(wgpu::Color::TRANSPARENT) } else { None },
+            last_frame: Instant::now(),
+            time_step: None,
+            frame_count: 0,
+            last_stats: FrameStats::default(),
+            limiter: FrameLimiter::new(self.fps_limit),
+            profiler: Profiler::new(),
+            msaa_samples: self.msaa_samples,
+            msaa_framebuffer,
+            depth_texture,
+            supersampling: 1.0,
+
+            cameras,
+            camera_bind_group_layout,
+            frame,
+            in_flight,
+
+            adapter_info,
+            memory_budget,
+            auto_clip_planes: self.auto_clip_planes,
+            sun: None,
+            shadow_fit: None,
+            shadow_map,
+            shadow_pipeline,
+            shadow_pass_bind_group,
+
+            texture_bind_group_layout,
+            identity_instance,
+            render_pipeline,
+            simple_pipeline,
+            lod_settings: LodSettings::default(),
+
+            models: Vec::new(),
+            model_paths: Vec::new(),
+            hidden_meshes: Vec::new(),
+            loader: ModelLoader::new(pool.clone()),
+            jobs: Jobs::new(pool),
+            readbacks: Readbacks::new(),
+            pending_settings: BTreeMap::new(),
+            prefab_instances: Vec::new(),
+            materials: Vec::new(),
+            material_override: None,
+            material_overrides: MaterialOverrides::new(),
+            material_libraries: Vec::new(),
+            library_check: Instant::now(),
+            lights: Vec::new(),
+            lighting,
+            lights_buffer,
+            light_markers,
+            preview: None,
+            flashlight,
+            flashlight_buffer,
+            flashlight_bind_group_layout,
+            flashlight_bind_group,
+            flashlight_cookie,
+            light_pipeline,
+            light_buffer,
+            light_placeholder,
+            light_pass_bind_group,
+            subdivision: None,
+            clipping,
+            exploded_views: Vec::new(),
+            dynamic_resolution: None,
+            stereo: None,
+            capture: None,
+
+            plugins: Vec::new(),
+            events: EventHub::new(),
+            builder: self,
+        };
+        renderer.set_supersampling(supersampling);
+        Ok(renderer)
+    }
+}
+
+// what RendererBuilder::request_gpu() hands to finish().
+struct Gpu {
+    surface: wgpu::Surface,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+// A model taken out of the scene with everything needed to put it back,
+// see Renderer::remove_model().
+pub struct RemovedModel {
+    pub model: model::Model,
+    pub path: PathBuf,
+    pub hidden_meshes: BTreeSet<usize>,
+    // index of the prefab instance the model was part of.
+    pub prefab_instance: Option<usize>,
+    // its material overrides, as (mesh, material).
+    pub material_overrides: Vec<(Option<usize>, usize)>,
+}
+
+pub struct Renderer {
+    // shared with the renderer that replaces this one, see switch_backend().
+    window: Rc<Window>,
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    size: winit::dpi::PhysicalSize<u32>,
+    time: Instant,
+    // clear color of the scene, None for the animated one.
+    background: Option<wgpu::Color>,
+    last_frame: Instant,
+    // set by the application for the plugins, see set_time_step().
+    time_step: Option<Duration>,
+    frame_count: u64,
+    last_stats: FrameStats,
+    limiter: FrameLimiter,
+    profiler: Profiler,
+    msaa_samples: u32,
+    msaa_framebuffer: Option<wgpu::TextureView>,
+    // of the scene pass, as big as the scene is drawn (see render_config()) and with msaa_samples.
+    depth_texture: texture::Texture,
+    // the scene is drawn this many times bigger than the window, 1 = off.
+    supersampling: f32,
+
+    // named cameras with their uniform buffers, see cameras.rs.
+    cameras: Cameras,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    // time, window size and eye for every shader, in the camera bind group. See frame.rs.
+    frame: FrameBuffer,
+    // the per frame pools the cameras, lights and instances are uploaded through.
+    in_flight: FramesInFlight,
+
+    adapter_info: wgpu::AdapterInfo,
+    // estimated usage against a guessed budget, checked every frame.
+    memory_budget: MemoryBudget,
+    // the cameras' znear / zfar follow the visible meshes.
+    auto_clip_planes: bool,
+    // direction of the directional light, and its shadow projection fitted every frame.
+    sun: Option<cgmath::Vector3<f32>>,
+    shadow_fit: Option<ShadowFit>,
+    shadow_map: ShadowMap,
+    shadow_pipeline: wgpu::RenderPipeline,
+    shadow_pass_bind_group: wgpu::BindGroup,
+
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    // bound as the instances of the models without their own, see vertex::Instance.
+    identity_instance: model::InstanceBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+    // the simplified materials of the secondary views and the tier of each, see lod.rs.
+    simple_pipeline: wgpu::RenderPipeline,
+    lod_settings: LodSettings,
+
+    models: Vec<model::Model>,
+    model_paths: Vec<PathBuf>,
+    // per model, the indices of the meshes that aren't drawn.
+    hidden_meshes: Vec<BTreeSet<usize>>,
+    loader: ModelLoader,
+    // work on the job pool, finished in poll_completed_jobs().
+    jobs: Jobs<Renderer>,
+    // screenshots and captures on their way back from the gpu, with what
+    // becomes of them.
+    readbacks: Readbacks<Renderer>,
+    // mesh offsets and materials of scene models, applied once they are loaded.
+    pending_settings: BTreeMap<usize, SceneModel>,
+    prefab_instances: Vec<PrefabInstance>,
+    // materials that are not owned by a model, see set_material_override()
+    materials: Vec<model::Material>,
+    material_override: Option<usize>,
+    // per model / mesh, see overrides.rs.
+    material_overrides: MaterialOverrides,
+    // loaded material libraries, with the index in `materials` of each of their materials.
+    material_libraries: Vec<(MaterialLibrary, BTreeMap<String, usize>)>,
+    library_check: Instant,
+    lights: Vec<light::Light>,
+    // how the first light::MAX_LIGHTS of them shade the scene, uploaded every frame.
+    lighting: light::Lighting,
+    lights_buffer: wgpu::Buffer,
+    light_markers: LightMarkers,
+    // drawn instead of the scene while shown, see show_preview().
+    preview: Option<MaterialPreview>,
+    // spot light that follows the camera.
+    flashlight: light::SpotLight,
+    flashlight_buffer: wgpu::Buffer,
+    flashlight_bind_group_layout: wgpu::BindGroupLayout,
+    flashlight_bind_group: wgpu::BindGroup,
+    flashlight_cookie: texture::Texture,
+    // half rate shading, see shading.rs. The buffer only exists at ShadingRate::Half.
+    light_pipeline: wgpu::RenderPipeline,
+    light_buffer: Option<LightBuffer>,
+    light_placeholder: wgpu::TextureView,
+    light_pass_bind_group: wgpu::BindGroup,
+    subdivision: Option<TerrainSubdivision>,
+    clipping: Clipping,
+    exploded_views: Vec<ExplodedView>,
+    dynamic_resolution: Option<DynamicResolution>,
+    stereo: Option<Stereo>,
+    // connected on the first capture request.
+    capture: Option<GpuCapture>,
+
+    plugins: Vec<Box<dyn RenderPlugin>>,
+    events: EventHub,
+    // the options the renderer was built with, switch_backend() builds again.
+    builder: RendererBuilder,
+}
+
+impl Renderer {
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    /// Name, backend and type of the gpu the renderer runs on.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Backends with an adapter on this machine, to pick one for switch_backend().
+    pub fn available_backends() -> Vec<wgpu::Backend> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let mut backends: Vec<wgpu::Backend> = instance.enumerate_adapters(wgpu::Backends::all())
+            .map(|adapter| adapter.get_info().backend)
+            .collect();
+        backends.sort_by_key(|backend| *backend as u8);
+        backends.dedup();
+        backends
+    }
+
+    /// Tears the whole gpu stack down (instance, adapter, device, surface,
+    /// pipelines) and builds it again on `backends`, e.g. Vulkan -> GL without
+    /// a restart. Emits DeviceLost before and DeviceRecovered after, like a
+    /// lost surface: that's when the application creates its gpu resources again.
+    ///
+    /// Cameras, lights, the sun, the wind, clip planes, exploded views, the
+    /// shading, resolution, lod and subdivision settings carry over, plugins are
+    /// set up again on the new device. Models loaded from files are loaded again
+    /// in the background with their mesh settings. What only lives on the gpu can't: materials
+    /// of add_material() and of material libraries are gone (the instance
+    /// overrides stay, for when they are added again in the same order), models
+    /// of add_model() stay at their index
+    /// without meshes and materials (see replace_model()) and the flashlight
+    /// gets the default cookie again.
+    ///
+    /// Fails without changing anything if no adapter of `backends` can draw
+    /// to the window, or while models are loading.
+    pub fn switch_backend(&mut self, backends: wgpu::Backends) -> Result<()> {
+        if self.loader.pending() > 0 {
+            bail!("The backend can't be switched while models are loading.");
+        }
+        let mut builder = self.builder.clone();
+        builder.backends = backends;
+        builder.fps_limit = self.fps_limit();
+        builder.shading_rate = self.shading_rate();
+        builder.supersampling = self.supersampling;
+        builder.frames_in_flight = self.frames_in_flight();
+        let gpu = builder.request_gpu(&self.window)?;
+
+        self.events.emit(RendererEvent::DeviceLost);
+        let renderer = builder.finish(self.window.clone(), gpu)?;
+        let mut old = std::mem::replace(self, renderer);
+        let scene_models: Vec<Option<SceneModel>> = (0..old.models.len())
+            .map(|index| old.has_model_file(index).then(|| old.scene_model(index)))
+            .collect();
+        let resolution = old.dynamic_resolution.as_ref().map(|resolution| *resolution.settings());
+        let stereo = old.stereo.as_ref().map(|stereo| *stereo.settings());
+        let subdivision = old.subdivision.as_ref().map(|subdivision| (subdivision.model(), subdivision.settings()));
+        let model_paths = std::mem::take(&mut old.model_paths);
+        let hidden_meshes = std::mem::take(&mut old.hidden_meshes);
+        let preview = old.preview.take().map(|preview| (preview.subject().clone(), preview.previous_camera().to_string()));
+        self.plugins = std::mem::take(&mut old.plugins);
+        self.events = std::mem::take(&mut old.events);
+        self.background = old.background;
+        self.time = old.time;
+        self.frame_count = old.frame_count;
+        self.lights = std::mem::take(&mut old.lights);
+        self.lighting = old.lighting;
+        self.flashlight = old.flashlight;
+        self.sun = old.sun;
+        self.set_wind(old.wind());
+        self.lod_settings = old.lod_settings;
+        self.material_overrides = std::mem::take(&mut old.material_overrides);
+        self.prefab_instances = std::mem::take(&mut old.prefab_instances);
+        self.exploded_views = std::mem::take(&mut old.exploded_views);
+        *self.clipping.planes_mut() = old.clipping.planes().to_vec();
+        self.clipping.set_cap_color(old.clipping.cap_color());
+        std::mem::swap(&mut self.cameras, &mut old.cameras);
+        self.cameras.recreate(&self.device, &self.camera_bind_group_layout, self.frame.buffer());
+        // the old surface has to be gone before the new one is configured.
+        drop(old);
+
+        self.surface.configure(&self.device, &self.config);
+        if let Some(settings) = resolution {
+            self.enable_dynamic_resolution(settings);
+        }
+        if let Some(settings) = stereo {
+            self.enable_stereo(settings);
+        }
+
+        // the same indices as before, generated models stay empty.
+        for (path, scene_model) in model_paths.into_iter().zip(scene_models) {
+            match scene_model {
+                Some(scene_model) => {
+                    let index = self.load_model_async(&path);
+                    self.pending_settings.insert(index, scene_model);
+                }
+                None => {
+                    self.models.push(model::Model { meshes: Vec::new(), materials: Vec::new(), bounds: model::Bounds::empty(), instances: None });
+                    self.model_paths.push(path);
+                    self.hidden_meshes.push(BTreeSet::new());
+                }
+            }
+        }
+        self.hidden_meshes = hidden_meshes;
+        if let Some((index, settings)) = subdivision {
+            if let Err(e) = self.enable_terrain_subdivision(index, settings) {
+                log::warn!("Unable to subdivide the terrain again: {:?}", e);
+            }
+        }
+        if let Some((subject, previous_camera)) = preview {
+            match MaterialPreview::new(&self.device, &self.queue, &self.texture_bind_group_layout, subject, &previous_camera) {
+                Ok(preview) => self.preview = Some(preview),
+                Err(e) => {
+                    log::warn!("Unable to show the material preview again: {:?}", e);
+                    self.leave_preview(&previous_camera);
+                }
+            }
+        }
+
+        let ctx = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            config: &self.config,
+            camera_bind_group_layout: &self.camera_bind_group_layout,
+            camera: &self.cameras.active().camera,
+            lights: &self.lights,
+            shadow: self.shadow_fit.as_ref(),
+            scale_factor: self.window.scale_factor(),
+            time_step: self.time_step(),
+        };
+        for plugin in &mut self.plugins {
+            plugin.setup(&ctx);
+        }
+        self.events.emit(RendererEvent::DeviceRecovered);
+        Ok(())
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Layout materials have to be created with to be drawable by this renderer.
+    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.texture_bind_group_layout
+    }
+
+    /// Loads an obj file (plus its materials) and adds it to the drawn models.
+    /// Returns the index of the model.
+    pub fn load_model<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let model = model::Model::load(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            path.as_ref(),
+        )?;
+        self.models.push(model);
+        self.model_paths.push(path.as_ref().to_path_buf());
+        self.hidden_meshes.push(BTreeSet::new());
+
+        let index = self.models.len() - 1;
+        self.events.emit(RendererEvent::ModelLoaded {
+            index,
+            path: path.as_ref().to_path_buf(),
+        });
+        Ok(index)
+    }
+
+    /// Adds a model made by the application, e.g. generated geometry.
+    /// `path` only names it, scenes don't save models that aren't files.
+    pub fn add_model<P: AsRef<Path>>(&mut self, path: P, model: model::Model) -> usize {
+        self.models.push(model);
+        self.model_paths.push(path.as_ref().to_path_buf());
+        self.hidden_meshes.push(BTreeSet::new());
+
+        let index = self.models.len() - 1;
+        self.events.emit(RendererEvent::ModelLoaded {
+            index,
+            path: path.as_ref().to_path_buf(),
+        });
+        index
+    }
+
+    /// Replaces the meshes of the model at `index`, the materials stay.
+    /// For geometry that changes while it's edited, like a road.
+    pub fn set_model_meshes(&mut self, index: usize, meshes: &[model::MeshData]) -> Result<()> {
+        let model = self.models.get_mut(index).context("No model with this index.")?;
+        if let Some(mesh) = meshes.iter().find(|mesh| mesh.material >= model.materials.len()) {
+            bail!("Mesh {} uses material {}, the model has {}.", mesh.name, mesh.material, model.materials.len());
+        }
+        let device = &self.device;
+        model.meshes = meshes.iter().map(|mesh| model::Mesh::from_data(device, mesh)).collect();
+        model.bounds = meshes.iter().fold(model::Bounds::empty(), |bounds, mesh| bounds.union(&mesh.bounds));
+        self.hidden_meshes[index].retain(|mesh| *mesh < meshes.len());
+        self.rebuild_subdivision(index);
+        Ok(())
+    }
+
+    /// Starts loading a model in the background and returns its index right away.
+    /// A placeholder box is drawn until the model is ready, the ModelLoaded
+    /// event tells when it was swapped in.
+    pub fn load_model_async<P: AsRef<Path>>(&mut self, path: P) -> usize {
+        let placeholder = model::Model::placeholder(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            model::Bounds::empty(),
+        );
+        self.models.push(placeholder);
+        self.model_paths.push(path.as_ref().to_path_buf());
+        self.hidden_meshes.push(BTreeSet::new());
+
+        let index = self.models.len() - 1;
+        self.loader.load(index, path.as_ref().to_path_buf());
+        index
+    }
+
+    /// Number of background loads that are not swapped in yet.
+    pub fn pending_loads(&self) -> usize {
+        self.loader.pending()
+    }
+
+    /// Runs `work` on the job pool and `finish` with its result on this
+    /// thread, out of poll_completed_jobs(), see jobs.rs.
+    pub fn spawn_job<T, W, F>(&mut self, priority: JobPriority, name: &'static str, work: W, finish: F) -> JobId
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+        F: FnOnce(&mut Renderer, T) + 'static,
+    {
+        self.jobs.spawn(priority, name, work, finish)
+    }
+
+    /// Like spawn_job(), for long work: it tells how far it is with the
+    /// Progress it gets, which job_progress() lists, and stops at the next
+    /// Progress::check() once it's cancelled with cancel_job(). The
+    /// finish of a cancelled job isn't called.
+    pub fn spawn_cancellable_job<T, W, F>(&mut self, priority: JobPriority, name: &'static str, work: W, finish: F) -> JobId
+    where
+        T: Send + 'static,
+        W: FnOnce(&Progress) -> T + Send + 'static,
+        F: FnOnce(&mut Renderer, T) + 'static,
+    {
+        self.jobs.spawn_cancellable(priority, name, work, finish)
+    }
+
+    /// The background model loads and cancellable jobs that aren't done
+    /// yet, with how far they are, e.g. for a progress bar.
+    pub fn job_progress(&self) -> Vec<JobProgress> {
+        let mut jobs = self.loader.progress();
+        jobs.extend(self.jobs.progress());
+        jobs
+    }
+
+    /// Cancels a job of job_progress(). False if it's done already or
+    /// can't be cancelled. A cancelled model load keeps its placeholder.
+    pub fn cancel_job(&mut self, id: JobId) -> bool {
+        self.loader.cancel(id) || self.jobs.cancel(id)
+    }
+
+    /// Cancels all jobs of job_progress().
+    pub fn cancel_all_jobs(&mut self) {
+        self.loader.cancel_all();
+        self.jobs.cancel_all();
+    }
+
+    /// Jobs of spawn_job(), screenshots and captures that aren't finished yet.
+    pub fn pending_jobs(&self) -> usize {
+        self.jobs.pending() + self.readbacks.len()
+    }
+
+    /// Takes what the background work finished, without waiting for the
+    /// rest: swaps loaded models in, calls the finish of done jobs and of
+    /// screenshots and captures that are read back.
+    /// render_frame() calls it, the main loop may call it more often.
+    pub fn poll_completed_jobs(&mut self) {
+        self.poll_loads();
+        for finish in self.jobs.poll() {
+            finish(self);
+        }
+        for finish in self.readbacks.poll(&self.device) {
+            finish(self);
+        }
+    }
+
+    // swaps placeholders and finished models in, called once per frame.
+    fn poll_loads(&mut self) {
+        for message in self.loader.poll() {
+            match message {
+                LoadMessage::Parsed { slot, bounds } => {
+                    self.models[slot] = model::Model::placeholder(
+                        &self.device,
+                        &self.queue,
+                        &self.texture_bind_group_layout,
+                        bounds,
+                    );
+                    self.rebuild_subdivision(slot);
+                }
+                LoadMessage::Finished { slot, path, data, textures } => {
+                    self.models[slot] = model::Model::from_decoded(
+                        &self.device,
+                        &self.queue,
+                        &self.texture_bind_group_layout,
+                        &data,
+                        textures,
+                    );
+                    self.rebuild_subdivision(slot);
+                    if let Some(settings) = self.pending_settings.remove(&slot) {
+                        self.apply_scene_model(slot, &settings, [0.0; 3]);
+                    }
+                    self.events.emit(RendererEvent::ModelLoaded { index: slot, path });
+                }
+                LoadMessage::Failed { path, error, .. } => {
+                    log::error!("Unable to load model {:?}: {:?}", path, error);
+                }
+                LoadMessage::Cancelled { path, .. } => {
+                    log::info!("Cancelled loading model {:?}", path);
+                }
+            }
+        }
+    }
+
+    /// Loads the model at `index` again from the file it came from,
+    /// e.g. after the file was edited.
+    pub fn reload_model(&mut self, index: usize) -> Result<()> {
+        let path = self.model_paths.get(index)
+            .context("No model with this index.")?
+            .clone();
+        self.models[index] = model::Model::load(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            &path,
+        )?;
+        self.rebuild_subdivision(index);
+        self.events.emit(RendererEvent::AssetReloaded { index, path });
+        Ok(())
+    }
+
+    /// Puts `model` in place of the one at `index`, e.g. to give a generated
+    /// model its meshes and materials back after switch_backend().
+    pub fn replace_model(&mut self, index: usize, model: model::Model) -> Result<()> {
+        let slot = self.models.get_mut(index).context("No model with this index.")?;
+        *slot = model;
+        self.hidden_meshes[index].clear();
+        self.rebuild_subdivision(index);
+        Ok(())
+    }
+
+    pub fn models(&self) -> &[model::Model] {
+        &self.models
+    }
+
+    /// The file the model at `index` was loaded from.
+    pub fn model_path(&self, index: usize) -> Option<&Path> {
+        self.model_paths.get(index).map(|path| path.as_path())
+    }
+
+    /// Takes the model at `index` out of the scene, the models after it move down.
+    /// Fails while models are loading in the background, their indices would change.
+    pub fn remove_model(&mut self, index: usize) -> Result<RemovedModel> {
+        if index >= self.models.len() {
+            bail!("No model with this index.");
+        }
+        if self.loader.pending() > 0 {
+            bail!("Models can't be removed while others are loading.");
+        }
+        let prefab_instance = self.prefab_instances.iter_mut()
+            .enumerate()
+            .fold(None, |found, (instance_index, instance)| {
+                if instance.shift_models(index, false) { Some(instance_index) } else { found }
+            });
+        let removed = RemovedModel {
+            model: self.models.remove(index),
+            path: self.model_paths.remove(index),
+            hidden_meshes: self.hidden_meshes.remove(index),
+            prefab_instance,
+            material_overrides: self.material_overrides.of_model(index),
+        };
+        self.material_overrides.shift_models(index, false);
+
+        self.exploded_views.retain(|view| view.model != index);
+        for view in &mut self.exploded_views {
+            if view.model > index {
+                view.model -= 1;
+            }
+        }
+        match self.subdivision.as_ref().map(|subdivision| subdivision.model()) {
+            Some(model) if model == index => self.subdivision = None,
+            Some(model) if model > index => {
+                if let Some(subdivision) = &mut self.subdivision {
+                    subdivision.set_model(model - 1);
+                }
+            }
+            _ => {}
+        }
+        self.cameras.shift_models(index, false);
+
+        self.events.emit(RendererEvent::ModelRemoved { index, path: removed.path.clone() });
+        Ok(removed)
+    }
+
+    /// Puts a removed model back at `index`, the models from there on move up.
+    pub fn insert_model(&mut self, index: usize, removed: RemovedModel) -> Result<usize> {
+        if index > self.models.len() {
+            bail!("No model with this index.");
+        }
+        if self.loader.pending() > 0 {
+            bail!("Models can't be inserted while others are loading.");
+        }
+        self.models.insert(index, removed.model);
+        self.model_paths.insert(index, removed.path.clone());
+        self.hidden_meshes.insert(index, removed.hidden_meshes);
+
+        for instance in &mut self.prefab_instances {
+            instance.shift_models(index, true);
+        }
+        if let Some(instance) = removed.prefab_instance.and_then(|instance| self.prefab_instances.get_mut(instance)) {
+            instance.models.push(index);
+            instance.models.sort_unstable();
+        }
+        self.material_overrides.shift_models(index, true);
+        for (mesh, material) in removed.material_overrides {
+            self.material_overrides.set(index, mesh, material);
+        }
+
+        for view in &mut self.exploded_views {
+            if view.model >= index {
+                view.model += 1;
+            }
+        }
+        if let Some(subdivision) = &mut self.subdivision {
+            if subdivision.model() >= index {
+                subdivision.set_model(subdivision.model() + 1);
+            }
+        }
+        self.cameras.shift_models(index, true);
+
+        self.events.emit(RendererEvent::ModelLoaded { index, path: removed.path });
+        Ok(index)
+    }
+
+    /// Loads the model at `index` again as a new model, with the same hidden
+    /// meshes, mesh materials and mesh offsets, moved by `offset`.
+    /// Returns the index of the copy.
+    pub fn duplicate_model(&mut self, index: usize, offset: [f32; 3]) -> Result<usize> {
+        if index >= self.models.len() {
+            bail!("No model with this index.");
+        }
+        let settings = self.scene_model(index);
+        let copy = self.load_model(&settings.path)?;
+        self.apply_scene_model(copy, &settings, offset);
+        Ok(copy)
+    }
+
+    /// Draws a single mesh with another material of its model.
+    pub fn set_mesh_material(&mut self, model: usize, mesh: usize, material: usize) -> Result<()> {
+        let model = self.models.get_mut(model).context("No model with this index.")?;
+        if material >= model.materials.len() {
+            bail!("The model has no material {}.", material);
+        }
+        model.meshes.get_mut(mesh).context("No mesh with this index.")?.material = material;
+        Ok(())
+    }
+
+    /// Renames a single mesh, the name isn't saved with the scene.
+    pub fn set_mesh_name(&mut self, model: usize, mesh: usize, name: &str) {
+        if let Some(mesh) = self.models.get_mut(model).and_then(|model| model.meshes.get_mut(mesh)) {
+            mesh.name = name.to_string();
+        }
+    }
+
+    /// Moves a single mesh, cameras attached to it follow.
+    pub fn set_mesh_offset(&mut self, model: usize, mesh: usize, offset: [f32; 3]) {
+        if let Some(mesh) = self.models.get_mut(model).and_then(|model| model.meshes.get_mut(mesh)) {
+            mesh.set_offset(&self.queue, offset);
+        }
+    }
+
+    /// Draws the model at `model` once per instance, each moved, turned and
+    /// scaled by it (see vertex::Instance), in a single draw call per mesh.
+    /// An empty slice draws it once where it was loaded again. The copies
+    /// aren't picked, capped by clip planes or saved with the scene, and
+    /// reloading the model drops them.
+    pub fn set_model_instances(&mut self, model: usize, instances: &[vertex::Instance]) -> Result<()> {
+        let model = self.models.get_mut(model).context("No model with this index.")?;
+        model.set_instances(&self.device, &mut self.in_flight, instances);
+        Ok(())
+    }
+
+    /// The instances of set_model_instances(), empty for a model drawn once.
+    pub fn model_instances(&self, model: usize) -> &[vertex::Instance] {
+        self.models.get(model)
+            .and_then(|model| model.instances.as_ref())
+            .map_or(&[], |buffer| &buffer.instances)
+    }
+
+    /// Hides or shows a single mesh, kept when the model is reloaded.
+    pub fn set_mesh_visible(&mut self, model: usize, mesh: usize, visible: bool) {
+        if let Some(hidden) = self.hidden_meshes.get_mut(model) {
+            if visible {
+                hidden.remove(&mesh);
+            } else {
+                hidden.insert(mesh);
+            }
+        }
+    }
+
+    pub fn mesh_visible(&self, model: usize, mesh: usize) -> bool {
+        self.hidden_meshes.get(model).map_or(true, |hidden| !hidden.contains(&mesh))
+    }
+
+    /// Hides every mesh of every model except this one.
+    pub fn isolate_mesh(&mut self, model: usize, mesh: usize) {
+        for (index, hidden) in self.hidden_meshes.iter_mut().enumerate() {
+            *hidden = (0..self.models[index].meshes.len()).collect();
+            if index == model {
+                hidden.remove(&mesh);
+            }
+        }
+    }
+
+    /// Shows all meshes again.
+    pub fn show_all_meshes(&mut self) {
+        for hidden in &mut self.hidden_meshes {
+            hidden.clear();
+        }
+    }
+
+    // whether the model at `index` came from a file or url, not add_model().
+    fn has_model_file(&self, index: usize) -> bool {
+        let path = &self.model_paths[index];
+        path.is_file() || fetch::is_url(path)
+    }
+
+    /// Writes the loaded models, their mesh settings and the prefab instances
+    /// to a scene file, see scene.rs. Models that aren't files (add_model()) are left out.
+    pub fn save_scene<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let in_prefab = |index: usize| self.prefab_instances.iter().any(|instance| instance.models.contains(&index));
+        let scene = Scene {
+            libraries: self.material_libraries().map(Path::to_path_buf).collect(),
+            models: (0..self.models.len())
+                .filter(|index| !in_prefab(*index) && self.has_model_file(*index))
+                .map(|index| self.scene_model(index))
+                .collect(),
+            prefabs: self.prefab_instances.iter()
+                .map(|instance| ScenePrefab { path: instance.path.clone(), offset: instance.offset })
+                .collect(),
+        };
+        scene.save(path)
+    }
+
+    /// Loads the models of a scene file in the background, next to the ones
+    /// already loaded, and places its prefabs. Returns the indices of the models.
+    pub fn load_scene<P: AsRef<Path>>(&mut self, path: P) -> Result<Vec<usize>> {
+        let scene = Scene::load(path)?;
+        for library in &scene.libraries {
+            self.load_material_library(library)?;
+        }
+        for prefab in &scene.prefabs {
+            self.instantiate_prefab(&prefab.path, prefab.offset)?;
+        }
+        let indices = scene.models.into_iter()
+            .map(|model| {
+                let index = self.load_model_async(&model.path);
+                self.hidden_meshes[index] = model.hidden_meshes.clone();
+                if !model.offsets.is_empty() || !model.materials.is_empty() || !model.library_materials.is_empty() {
+                    self.pending_settings.insert(index, model);
+                }
+                index
+            })
+            .collect();
+        Ok(indices)
+    }
+
+    /// Saves the models at `models` with their mesh offsets, materials and
+    /// hidden meshes as a prefab, see prefab.rs.
+    pub fn save_prefab<P: AsRef<Path>>(&self, path: P, models: &[usize]) -> Result<()> {
+        if let Some(index) = models.iter().find(|index| **index >= self.models.len()) {
+            bail!("No model with index {}.", index);
+        }
+        let prefab = Scene {
+            libraries: Vec::new(),
+            models: models.iter().map(|index| self.scene_model(*index)).collect(),
+            prefabs: Vec::new(),
+        };
+        prefab.save(path)
+    }
+
+    /// Loads the models of a prefab and moves them by `offset`.
+    /// Returns the index of the instance, see prefab_instances().
+    pub fn instantiate_prefab<P: AsRef<Path>>(&mut self, path: P, offset: [f32; 3]) -> Result<usize> {
+        let prefab = prefab::load(path.as_ref())?;
+        let mut models = Vec::new();
+        for prefab_model in &prefab.models {
+            let index = self.load_model(&prefab_model.path)?;
+            self.apply_scene_model(index, prefab_model, offset);
+            models.push(index);
+        }
+        self.prefab_instances.push(PrefabInstance {
+            path: path.as_ref().to_path_buf(),
+            offset,
+            models,
+        });
+        Ok(self.prefab_instances.len() - 1)
+    }
+
+    /// Reads the prefab file again and updates all of its instances.
+    /// Returns how many instances were updated.
+    pub fn update_prefab<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let path = path.as_ref();
+        let prefab = prefab::load(path)?;
+        let instances: Vec<usize> = (0..self.prefab_instances.len())
+            .filter(|instance| self.prefab_instances[*instance].path == path)
+            .collect();
+
+        for instance in &instances {
+            let PrefabInstance { offset, models, .. } = self.prefab_instances[*instance].clone();
+            for (slot, prefab_model) in prefab.models.iter().enumerate() {
+                let index = match models.get(slot) {
+                    Some(index) => {
+                        if self.model_paths[*index] != prefab_model.path {
+                            self.model_paths[*index] = prefab_model.path.clone();
+                            self.reload_model(*index)?;
+                        }
+                        *index
+                    }
+                    // models added to the prefab are added to every instance.
+                    None => {
+                        let index = self.load_model(&prefab_model.path)?;
+                        self.prefab_instances[*instance].models.push(index);
+                        index
+                    }
+                };
+                self.apply_scene_model(index, prefab_model, offset);
+            }
+            if models.len() > prefab.models.len() {
+                log::warn!(
+                    "Prefab {:?} has fewer models than its instance {}, the rest stays in the scene.",
+                    path, instance,
+                );
+            }
+        }
+        Ok(instances.len())
+    }
+
+    pub fn prefab_instances(&self) -> &[PrefabInstance] {
+        &self.prefab_instances
+    }
+
+    // the model at `index` as it's saved.
+    fn scene_model(&self, index: usize) -> SceneModel {
+        let model = &self.models[index];
+        let mut scene_model = SceneModel::new(&self.model_paths[index]);
+        scene_model.hidden_meshes = self.hidden_meshes[index].clone();
+        for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+            if mesh.offset != [0.0; 3] {
+                scene_model.offsets.insert(mesh_index, mesh.offset);
+            }
+            scene_model.materials.insert(mesh_index, mesh.material);
+        }
+        for (mesh, material) in self.material_overrides.of_model(index) {
+            if let Some(name) = self.library_material_name(material) {
+                scene_model.library_materials.insert(mesh, name.to_string());
+            }
+        }
+        scene_model
+    }
+
+    // hidden meshes, materials and offsets (moved by `offset`) of a saved model.
+    fn apply_scene_model(&mut self, index: usize, scene_model: &SceneModel, offset: [f32; 3]) {
+        self.hidden_meshes[index] = scene_model.hidden_meshes.clone();
+        for (mesh, material) in &scene_model.materials {
+            if let Err(e) = self.set_mesh_material(index, *mesh, *material) {
+                log::warn!("{:?}: {:?}", scene_model.path, e);
+            }
+        }
+        for (mesh, name) in &scene_model.library_materials {
+            if let Err(e) = self.set_instance_material_named(index, *mesh, name) {
+                log::warn!("{:?}: {:?}", scene_model.path, e);
+            }
+        }
+        for mesh in 0..self.models[index].meshes.len() {
+            let local = scene_model.offsets.get(&mesh).copied().unwrap_or([0.0; 3]);
+            self.set_mesh_offset(index, mesh, [local[0] + offset[0], local[1] + offset[1], local[2] + offset[2]]);
+        }
+    }
+
+    /// Moves the meshes of the model at `index` apart, 0 puts them back together.
+    /// The meshes glide to the new factor over the next frames.
+    pub fn set_exploded_view(&mut self, index: usize, factor: f32) -> Result<()> {
+        if index >= self.models.len() {
+            bail!("No model with this index.");
+        }
+        match self.exploded_views.iter_mut().find(|view| view.model == index) {
+            Some(view) => view.set_factor(factor),
+            None => {
+                let mut view = ExplodedView::new(index);
+                view.set_factor(factor);
+                self.exploded_views.push(view);
+            }
+        }
+        Ok(())
+    }
+
+    /// Factor the model at `index` is heading to, 0 when it isn't exploded.
+    pub fn exploded_view(&self, index: usize) -> f32 {
+        self.exploded_views.iter()
+            .find(|view| view.model == index)
+            .map_or(0.0, |view| view.factor())
+    }
+
+    /// e.g. to change the animation speed, None while the model isn't exploded.
+    pub fn exploded_view_mut(&mut self, index: usize) -> Option<&mut ExplodedView> {
+        self.exploded_views.iter_mut().find(|view| view.model == index)
+    }
+
+    /// Draws the model at `index` refined around the camera by a compute pass,
+    /// see the subdivision module. Meant for terrain, only one model at a time.
+    pub fn enable_terrain_subdivision(&mut self, index: usize, settings: SubdivisionSettings) -> Result<()> {
+        let model = self.models.get(index)
+            .context("No model with this index.")?;
+        let mut subdivision = TerrainSubdivision::new(&self.device, index, settings);
+        subdivision.build(&self.device, model);
+        self.subdivision = Some(subdivision);
+        Ok(())
+    }
+
+    /// Goes back to drawing the subdivided model as it is.
+    pub fn disable_terrain_subdivision(&mut self) {
+        self.subdivision = None;
+    }
+
+    pub fn set_subdivision_settings(&mut self, settings: SubdivisionSettings) {
+        if let Some(subdivision) = &mut self.subdivision {
+            subdivision.set_settings(&self.device, &self.models[subdivision.model()], settings);
+        }
+    }
+
+    pub fn subdivision_settings(&self) -> Option<SubdivisionSettings> {
+        self.subdivision.as_ref().map(|subdivision| subdivision.settings())
+    }
+
+    // the refined buffers read from the model buffers, new model -> new buffers.
+    fn rebuild_subdivision(&mut self, index: usize) {
+        if let Some(subdivision) = &mut self.subdivision {
+            if subdivision.model() == index {
+                subdivision.build(&self.device, &self.models[index]);
+            }
+        }
+    }
+
+    /// Registers a material that can be drawn in place of the model materials.
+    /// Returns the index to pass to `set_material_override`.
+    pub fn add_material(&mut self, material: model::Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// Draw every mesh with the given material instead of its own,
+    /// `None` goes back to the model materials.
+    pub fn set_material_override(&mut self, material: Option<usize>) {
+        self.material_override = material;
+    }
+
+    pub fn material_override(&self) -> Option<usize> {
+        self.material_override
+    }
+
+    /// Draws one model, or with `mesh` one of its meshes, with a material of
+    /// add_material() instead of its own, without another copy of the model.
+    /// `None` removes the override. See overrides.rs.
+    pub fn set_instance_material(&mut self, model: usize, mesh: Option<usize>, material: Option<usize>) -> Result<()> {
+        let meshes = self.models.get(model).context("No model with this index.")?.meshes.len();
+        if mesh.is_some_and(|mesh| mesh >= meshes) {
+            bail!("No mesh with this index.");
+        }
+        match material {
+            Some(material) if material >= self.materials.len() => bail!("No material {} was added.", material),
+            Some(material) => self.material_overrides.set(model, mesh, material),
+            None => {
+                self.material_overrides.remove(model, mesh);
+            }
+        }
+        Ok(())
+    }
+
+    /// The override set_instance_material() set for the model or mesh, if any.
+    pub fn instance_material(&self, model: usize, mesh: Option<usize>) -> Option<usize> {
+        self.material_overrides.entry(model, mesh)
+    }
+
+    /// The material of add_material() the mesh is drawn with instead of its own,
+    /// its own override or the one of its model.
+    pub fn mesh_material_override(&self, model: usize, mesh: usize) -> Option<usize> {
+        self.material_overrides.get(model, mesh)
+    }
+
+    /// Loads a material library (see matlib.rs) and adds its materials, a
+    /// library that is already loaded is loaded again. The file is watched
+    /// and loaded again whenever it changes.
+    pub fn load_material_library<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let library = MaterialLibrary::load(path)?;
+        let slot = self.material_libraries.iter().position(|(loaded, _)| loaded.path == library.path);
+        let mut indices = slot.map_or_else(BTreeMap::new, |slot| self.material_libraries[slot].1.clone());
+        // all of them or none.
+        let materials = library.materials.iter()
+            .map(|material| material.create(&self.device, &self.queue, &self.texture_bind_group_layout))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Unable to load material library {:?}.", library.path))?;
+        // known names keep their index, so the models drawn with them keep them too.
+        for (material, def) in materials.into_iter().zip(&library.materials) {
+            match indices.get(&def.name) {
+                Some(index) => self.materials[*index] = material,
+                None => {
+                    let index = self.add_material(material);
+                    indices.insert(def.name.clone(), index);
+                }
+            }
+        }
+        match slot {
+            Some(slot) => self.material_libraries[slot] = (library, indices),
+            None => self.material_libraries.push((library, indices)),
+        }
+        Ok(())
+    }
+
+    /// Files of the loaded material libraries.
+    pub fn material_libraries(&self) -> impl Iterator<Item = &Path> {
+        self.material_libraries.iter().map(|(library, _)| library.path.as_path())
+    }
+
+    /// Index (for set_instance_material()) of a material of the loaded
+    /// libraries, the last library loaded wins when two have the name.
+    pub fn library_material(&self, name: &str) -> Option<usize> {
+        self.material_libraries.iter().rev().find_map(|(_, indices)| indices.get(name).copied())
+    }
+
+    /// Like set_instance_material(), with a material of the loaded libraries.
+    pub fn set_instance_material_named(&mut self, model: usize, mesh: Option<usize>, name: &str) -> Result<()> {
+        let material = self.library_material(name)
+            .with_context(|| format!("No material library has a material {:?}.", name))?;
+        self.set_instance_material(model, mesh, Some(material))
+    }
+
+    // the name of a library material, None for the other materials.
+    fn library_material_name(&self, index: usize) -> Option<&str> {
+        self.material_libraries.iter()
+            .flat_map(|(_, indices)| indices.iter())
+            .find(|(_, material)| **material == index)
+            .map(|(name, _)| name.as_str())
+    }
+
+    // loads the libraries whose file changed again, every LIBRARY_CHECK_INTERVAL.
+    fn poll_material_libraries(&mut self) {
+        if self.library_check.elapsed() < LIBRARY_CHECK_INTERVAL {
+            return;
+        }
+        self.library_check = Instant::now();
+        let changed: Vec<PathBuf> = self.material_libraries.iter()
+            .filter(|(library, _)| library.changed())
+            .map(|(library, _)| library.path.clone())
+            .collect();
+        for path in changed {
+            match self.load_material_library(&path) {
+                Ok(()) => {
+                    log::info!("Reloaded material library {:?}", path);
+                    self.events.emit(RendererEvent::MaterialLibraryReloaded { path });
+                }
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    // the old materials stay, until the file changes again.
+                    if let Some((library, _)) = self.material_libraries.iter_mut().find(|(library, _)| library.path == path) {
+                        library.modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+                    }
+                }
+            }
+        }
+    }
+
+    /// A material of add_material(), e.g. to tint it.
+    pub fn material_mut(&mut self, index: usize) -> Option<&mut model::Material> {
+        self.materials.get_mut(index)
+    }
+
+    pub fn materials(&self) -> &[model::Material] {
+        &self.materials
+    }
+
+    /// Draws the material `material` of the model at `model` as splatted terrain,
+    /// `None` goes back to its diffuse texture.
+    pub fn set_splat(&mut self, model: usize, material: usize, splat: Option<SplatLayers>) -> Result<()> {
+        let material = self.models.get_mut(model)
+            .context("No model with this index.")?
+            .materials.get_mut(material)
+            .context("No material with this index.")?;
+        material.set_splat(&self.device, &self.queue, &self.texture_bind_group_layout, splat);
+        Ok(())
+    }
+
+    /// Draws the material `material` of the model at `model` with a built in
+    /// texture, e.g. FallbackTexture::Checkerboard to look at its uvs, see
+    /// fallback.rs. The old texture is dropped, reloading the model brings
+    /// it back.
+    pub fn set_debug_texture(&mut self, model: usize, material: usize, texture: FallbackTexture) -> Result<()> {
+        let material = self.models.get_mut(model)
+            .context("No model with this index.")?
+            .materials.get_mut(material)
+            .context("No material with this index.")?;
+        let diffuse_texture = texture::Texture::from_image(&self.device, &self.queue, &texture.image(), Some(&texture.label()))?;
+        material.set_diffuse_texture(&self.device, &self.texture_bind_group_layout, diffuse_texture);
+        Ok(())
+    }
+
+    pub fn splat(&self, model: usize, material: usize) -> Option<&SplatLayers> {
+        self.models.get(model)?.materials.get(material)?.splat.as_ref()
+    }
+
+    /// Tiles, moves and turns the uvs the material `material` of the model at
+    /// `model` samples its diffuse texture with, see model::UvTransform.
+    /// Materials of add_material() are changed through material_mut().
+    pub fn set_uv_transform(&mut self, model: usize, material: usize, uv_transform: model::UvTransform) -> Result<()> {
+        let material = self.models.get_mut(model)
+            .context("No model with this index.")?
+            .materials.get_mut(material)
+            .context("No material with this index.")?;
+        material.set_uv_transform(&self.queue, uv_transform);
+        Ok(())
+    }
+
+    pub fn uv_transform(&self, model: usize, material: usize) -> Option<model::UvTransform> {
+        Some(self.models.get(model)?.materials.get(material)?.uv_transform)
+    }
+
+    /// Scrolls the texture of the material `material` of the model at `model`
+    /// or plays it as a flipbook, see model::TextureAnimation. Materials of
+    /// add_material() are changed through material_mut().
+    pub fn set_texture_animation(&mut self, model: usize, material: usize, animation: model::TextureAnimation) -> Result<()> {
+        let material = self.models.get_mut(model)
+            .context("No model with this index.")?
+            .materials.get_mut(material)
+            .context("No material with this index.")?;
+        material.set_animation(&self.queue, animation);
+        Ok(())
+    }
+
+    pub fn texture_animation(&self, model: usize, material: usize) -> Option<model::TextureAnimation> {
+        Some(self.models.get(model)?.materials.get(material)?.animation)
+    }
+
+    /// Moves the vertices of the meshes drawn with the material `material`
+    /// of the model at `model` by a baked vertex animation, starting now, or
+    /// stops it (None), see vat.rs. Materials of add_material() are changed
+    /// through material_mut().
+    pub fn set_vertex_animation(&mut self, model: usize, material: usize, vertex_animation: Option<VertexAnimation>) -> Result<()> {
+        let start = self.time.elapsed().as_secs_f32();
+        let material = self.models.get_mut(model)
+            .context("No model with this index.")?
+            .materials.get_mut(material)
+            .context("No material with this index.")?;
+        let vertex_animation = vertex_animation.map(|mut vat| {
+            vat.start = start;
+            vat
+        });
+        material.set_vertex_animation(&self.device, &self.queue, &self.texture_bind_group_layout, vertex_animation);
+        Ok(())
+    }
+
+    pub fn vertex_animation(&self, model: usize, material: usize) -> Option<&VertexAnimation> {
+        self.models.get(model)?.materials.get(material)?.vertex_animation.as_ref()
+    }
+
+    /// Lets the meshes drawn with the material `material` of the model at
+    /// `model` sway in the wind like vegetation, or stand still (None), see
+    /// wind.rs. Materials of add_material() are changed through material_mut().
+    pub fn set_wind_sway(&mut self, model: usize, material: usize, wind: Option<WindSway>) -> Result<()> {
+        let material = self.models.get_mut(model)
+            .context("No model with this index.")?
+            .materials.get_mut(material)
+            .context("No material with this index.")?;
+        material.set_wind(&self.queue, wind);
+        Ok(())
+    }
+
+    pub fn wind_sway(&self, model: usize, material: usize) -> Option<WindSway> {
+        self.models.get(model)?.materials.get(material)?.wind
+    }
+
+    /// Direction times speed (units per second) of the wind the vegetation
+    /// materials sway in, the same everywhere. No wind by default.
+    pub fn set_wind(&mut self, wind: [f32; 3]) {
+        self.frame.set_wind(wind);
+    }
+
+    pub fn wind(&self) -> [f32; 3] {
+        let wind = self.frame.uniform().wind;
+        [wind[0], wind[1], wind[2]]
+    }
+
+    /// Paints a dab of `brush` into the splat map of the visible mesh under the
+    /// window position `x`/`y`. False when there's no splatted mesh there.
+    pub fn paint_splat(&mut self, x: f32, y: f32, brush: &Brush) -> bool {
+        let ray = self.cursor_ray(x, y);
+        let hidden = &self.hidden_meshes;
+        let hit = match picking::pick_filtered(&self.models, &ray, |model, mesh| !hidden[model].contains(&mesh)) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let uv = match hit.uv(&self.models) {
+            Some(uv) => uv,
+            None => return false,
+        };
+        let model = &mut self.models[hit.model];
+        let material = model.meshes[hit.mesh].material;
+        let splat = match model.materials.get_mut(material).and_then(|material| material.splat.as_mut()) {
+            Some(splat) => splat,
+            None => return false,
+        };
+        if let Err(e) = splat.control.paint(uv, brush) {
+            log::warn!("{:?}", e);
+            return false;
+        }
+        splat.control.upload(&self.queue);
+        true
+    }
+
+    /// The active camera, the one the scene is drawn with.
+    pub fn camera(&self) -> &camera::Camera {
+        &self.cameras.active().camera
+    }
+
+    /// Changes made through the returned reference are uploaded with the next
+    /// frame and reported with `RendererEvent::CameraChanged`.
+    pub fn camera_mut(&mut self) -> &mut camera::Camera {
+        &mut self.cameras.active_mut().camera
+    }
+
+    /// Replaces the active camera.
+    pub fn set_camera(&mut self, camera: camera::Camera) {
+        self.cameras.active_mut().camera = camera;
+    }
+
+    /// Adds a camera under `name`, or replaces the camera with that name.
+    /// Each camera has its own uniform buffer, see cameras.rs.
+    pub fn add_camera(&mut self, name: &str, camera: camera::Camera) -> usize {
+        self.cameras.add(&self.device, &self.camera_bind_group_layout, self.frame.buffer(), name, camera)
+    }
+
+    /// Fails for the active camera and for unknown names.
+    pub fn remove_camera(&mut self, name: &str) -> Result<()> {
+        self.cameras.remove(name)
+    }
+
+    /// Draws the following frames with the camera `name`.
+    pub fn set_active_camera(&mut self, name: &str) -> Result<()> {
+        self.cameras.set_active(name)
+    }
+
+    pub fn active_camera(&self) -> &str {
+        &self.cameras.active().name
+    }
+
+    pub fn camera_names(&self) -> Vec<&str> {
+        self.cameras.iter().map(|camera| camera.name.as_str()).collect()
+    }
+
+    pub fn named_camera(&self, name: &str) -> Option<&camera::Camera> {
+        self.cameras.get(name).map(|camera| &camera.camera)
+    }
+
+    /// Changes made through the returned reference are uploaded with the next
+    /// frame and reported with `RendererEvent::CameraChanged`.
+    pub fn named_camera_mut(&mut self, name: &str) -> Option<&mut camera::Camera> {
+        self.cameras.get_mut(name).map(|camera| &mut camera.camera)
+    }
+
+    /// Lets the camera `name` follow a model or mesh, see cameras.rs.
+    /// Its eye and target are then relative to that node.
+    pub fn attach_camera(&mut self, name: &str, attachment: CameraAttachment) -> Result<()> {
+        self.cameras.attach(name, Some(attachment))
+    }
+
+    /// The camera stays where it was last moved to by its node.
+    pub fn detach_camera(&mut self, name: &str) -> Result<()> {
+        self.cameras.attach(name, None)
+    }
+
+    /// Bind group of the camera `name`, for plugins drawing with another camera.
+    pub fn camera_bind_group(&self, name: &str) -> Option<&wgpu::BindGroup> {
+        self.cameras.get(name).map(|camera| camera.bind_group())
+    }
+
+    /// Adds a point light to the scene, returns its index.
+    pub fn add_light(&mut self, light: light::Light) -> usize {
+        self.lights.push(light);
+        self.lights.len() - 1
+    }
+
+    pub fn lights(&self) -> &[light::Light] {
+        &self.lights
+    }
+
+    pub fn set_light(&mut self, index: usize, light: light::Light) {
+        if let Some(slot) = self.lights.get_mut(index) {
+            *slot = light;
+        }
+    }
+
+    /// The lights after it move down.
+    pub fn remove_light(&mut self, index: usize) -> Option<light::Light> {
+        if index < self.lights.len() {
+            Some(self.lights.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Puts a light at `index`, the lights from there on move up.
+    pub fn insert_light(&mut self, index: usize, light: light::Light) {
+        self.lights.insert(index.min(self.lights.len()), light);
+    }
+
+    /// Ambient, highlights and markers of the point lights, color and
+    /// shadows of the sun. The first light::MAX_LIGHTS lights and the sun
+    /// shade the scene (Blinn-Phong), without any it's unlit.
+    pub fn set_lighting(&mut self, lighting: light::Lighting) {
+        self.lighting = lighting;
+    }
+
+    pub fn lighting(&self) -> &light::Lighting {
+        &self.lighting
+    }
+
+    /// Shows a material or texture on its own, on a ball in a neutral
+    /// preview scene with three-point lighting, instead of the scene.
+    /// `None` goes back to the scene and the camera active before.
+    /// Fails for materials that don't exist and textures that can't be
+    /// loaded, the preview stays as it was then.
+    pub fn show_preview(&mut self, subject: Option<PreviewSubject>) -> Result<()> {
+        let subject = match subject {
+            Some(subject) => subject,
+            None => {
+                if let Some(preview) = self.preview.take() {
+                    self.leave_preview(preview.previous_camera());
+                }
+                return Ok(());
+            }
+        };
+        match &subject {
+            PreviewSubject::Material(index) if *index >= self.materials.len() => {
+                bail!("No material {} to preview, there are {}.", index, self.materials.len());
+            }
+            PreviewSubject::ModelMaterial { model, material } => {
+                let materials = self.models.get(*model).map_or(0, |model| model.materials.len());
+                if *material >= materials {
+                    bail!("Model {} has no material {}.", model, material);
+                }
+            }
+            _ => {}
+        }
+
+        // another subject keeps the camera where it is.
+        let previous_camera = match &self.preview {
+            Some(preview) => preview.previous_camera().to_string(),
+            None => self.active_camera().to_string(),
+        };
+        let preview = MaterialPreview::new(&self.device, &self.queue, &self.texture_bind_group_layout, subject, &previous_camera)?;
+        if self.preview.is_none() {
+            let aspect = math::aspect_ratio(self.config.width, self.config.height);
+            self.add_camera(preview::PREVIEW_CAMERA, preview::camera(aspect));
+        }
+        self.set_active_camera(preview::PREVIEW_CAMERA)?;
+        self.preview = Some(preview);
+        Ok(())
+    }
+
+    /// What the material preview shows, if it's shown.
+    pub fn preview(&self) -> Option<&PreviewSubject> {
+        self.preview.as_ref().map(|preview| preview.subject())
+    }
+
+    // back to `previous_camera`, or the default one if it was removed meanwhile.
+    fn leave_preview(&mut self, previous_camera: &str) {
+        if self.cameras.set_active(previous_camera).is_err() {
+            let _ = self.cameras.set_active(crate::cameras::DEFAULT_CAMERA);
+        }
+        let _ = self.cameras.remove(preview::PREVIEW_CAMERA);
+    }
+
+    // shading the drawn models: the preview's three lights while it's shown.
+    fn shading_lights(&self) -> &[light::Light] {
+        match &self.preview {
+            Some(preview) => preview.lights(),
+            None => &self.lights,
+        }
+    }
+
+    /// Switches the spot light attached to the camera on or off.
+    pub fn set_flashlight_enabled(&mut self, enabled: bool) {
+        self.flashlight.enabled = enabled;
+    }
+
+    pub fn flashlight_enabled(&self) -> bool {
+        self.flashlight.enabled
+    }
+
+    /// Color, angle and range of the flashlight.
+    /// Its position and direction are taken from the camera every frame.
+    pub fn flashlight_mut(&mut self) -> &mut light::SpotLight {
+        &mut self.flashlight
+    }
+
+    /// Texture projected through the flashlight, white is fully lit.
+    pub fn set_flashlight_cookie(&mut self, cookie: texture::Texture) {
+        self.flashlight_cookie = cookie;
+        self.rebuild_spot_bind_groups();
+    }
+
+    /// Cuts the scene with another plane, up to clipping::MAX_CLIP_PLANES.
+    pub fn add_clip_plane(&mut self, plane: ClipPlane) -> Result<usize> {
+        self.clipping.add_plane(plane)
+    }
+
+    pub fn clip_planes(&self) -> &[ClipPlane] {
+        self.clipping.planes()
+    }
+
+    /// e.g. to move a plane through the model.
+    pub fn clip_plane_mut(&mut self, index: usize) -> Option<&mut ClipPlane> {
+        self.clipping.planes_mut().get_mut(index)
+    }
+
+    pub fn remove_clip_plane(&mut self, index: usize) {
+        let planes = self.clipping.planes_mut();
+        if index < planes.len() {
+            planes.remove(index);
+        }
+    }
+
+    pub fn clear_clip_planes(&mut self) {
+        self.clipping.planes_mut().clear();
+    }
+
+    /// Fill color of the cross-sections of capped planes.
+    pub fn set_cross_section_color(&mut self, color: [f32; 4]) {
+        self.clipping.set_cap_color(color);
+    }
+
+    /// Statistics of the last rendered frame, also sent with FrameRendered.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.last_stats
+    }
+
+    /// Calls `callback` for every event, right when it happens.
+    pub fn on_event<F: FnMut(&RendererEvent) + 'static>(&mut self, callback: F) {
+        self.events.subscribe(callback);
+    }
+
+    /// Receiving end for all events emitted from now on.
+    pub fn event_channel(&mut self) -> mpsc::Receiver<RendererEvent> {
+        self.events.channel()
+    }
+
+    /// Lets other parts of the application report events,
+    /// e.g. a picking tool reporting ObjectPicked.
+    pub fn emit_event(&mut self, event: RendererEvent) {
+        self.events.emit(event);
+    }
+
+    /// Registers a plugin, its setup() runs right away.
+    pub fn add_plugin<P: RenderPlugin + 'static>(&mut self, plugin: P) {
+        let mut plugin = Box::new(plugin);
+        plugin.setup(&PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            config: &self.config,
+            camera_bind_group_layout: &self.camera_bind_group_layout,
+            camera: &self.cameras.active().camera,
+            lights: &self.lights,
+            shadow: self.shadow_fit.as_ref(),
+            scale_factor: self.window.scale_factor(),
+            time_step: self.time_step(),
+        });
+        self.plugins.push(plugin);
+    }
+
+    /// The first registered plugin of type `P`.
+    pub fn plugin<P: RenderPlugin>(&self) -> Option<&P> {
+        self.plugins.iter().find_map(|plugin| (plugin.as_ref() as &dyn Any).downcast_ref::<P>())
+    }
+
+    /// The first registered plugin of type `P`, e.g. to change its settings.
+    pub fn plugin_mut<P: RenderPlugin>(&mut self) -> Option<&mut P> {
+        self.plugins.iter_mut().find_map(|plugin| (plugin.as_mut() as &mut dyn Any).downcast_mut::<P>())
+    }
+
+    /// The ray from the active camera through the window position `x`/`y`
+    /// (pixels from the top left).
+    pub fn cursor_ray(&self, x: f32, y: f32) -> Ray {
+        Ray::from_screen(self.camera(), x, y, self.config.width, self.config.height)
+    }
+
+    /// Where the ray through the window position `x`/`y` crosses `plane`,
+    /// None when the plane isn't in front of the camera there.
+    pub fn cursor_on_plane(&self, x: f32, y: f32, plane: &Plane) -> Option<[f32; 3]> {
+        let ray = self.cursor_ray(x, y);
+        ray.intersect_plane(plane).map(|distance| ray.at(distance).into())
+    }
+
+    /// Where the ray through the window position `x`/`y` hits the terrain: the
+    /// closest visible mesh that `filter(model, mesh)` accepts, e.g. to leave
+    /// out what is being placed. Unlike pick() this emits no event.
+    pub fn cursor_on_ground<F: Fn(usize, usize) -> bool>(&self, x: f32, y: f32, filter: F) -> Option<[f32; 3]> {
+        let ray = self.cursor_ray(x, y);
+        picking::pick_filtered(&self.models, &ray, |model, mesh| {
+            self.mesh_visible(model, mesh) && filter(model, mesh)
+        }).map(|hit| hit.position)
+    }
+
+    /// Closest mesh under the window position `x`/`y` (pixels from the top left),
+    /// emits ObjectPicked when something was hit.
+    pub fn pick(&mut self, x: f32, y: f32) -> Option<Hit> {
+        let ray = self.cursor_ray(x, y);
+        let hidden = &self.hidden_meshes;
+        let hit = picking::pick_filtered(&self.models, &ray, |model, mesh| {
+            !hidden[model].contains(&mesh)
+        })?;
+        self.events.emit(RendererEvent::ObjectPicked {
+            model: hit.model,
+            mesh: hit.mesh,
+            position: hit.position,
+        });
+        Some(hit)
+    }
+
+    /// Resizes the surface if the window's size changed without a
+    /// Resized event, e.g. after it was dragged onto a monitor with another
+    /// scale factor. Call it when the window moved.
+    pub fn fit_surface(&mut self) {
+        let size = self.window.inner_size();
+        if size != self.size {
+            log::info!("Window is {}x{} now, resizing the surface.", size.width, size.height);
+            self.resize(size);
+        }
+    }
+
+    /// The connected monitors, numbered like the selectors of `set_fullscreen()`.
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        monitors::list(self.window.available_monitors(), self.window.primary_monitor().as_ref())
+    }
+
+    /// Borderless fullscreen on the monitor `selector` picks (see monitors.rs),
+    /// `Some("")` on the one the window is on, `None` leaves fullscreen.
+    pub fn set_fullscreen(&self, selector: Option<&str>) -> Result<()> {
+        match selector {
+            None => self.window.set_fullscreen(None),
+            Some("") => monitors::set_fullscreen(&self.window, None),
+            Some(selector) => {
+                let monitor = monitors::find(self.window.available_monitors(), selector)
+                    .with_context(|| format!("No monitor {:?}.", selector))?;
+                monitors::set_fullscreen(&self.window, Some(monitor));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(&self.device, &self.config);
+            self.cameras.set_aspect(math::aspect_ratio(new_size.width, new_size.height));
+            self.resize_scene_targets();
+
+            let ctx = PluginContext {
+                device: &self.device,
+                queue: &self.queue,
+                config: &self.config,
+                camera_bind_group_layout: &self.camera_bind_group_layout,
+                camera: &self.cameras.active().camera,
+                lights: &self.lights,
+                shadow: self.shadow_fit.as_ref(),
+                scale_factor: self.window.scale_factor(),
+                time_step: self.time_step(),
+            };
+            for plugin in &mut self.plugins {
+                plugin.resize(&ctx, new_size.width, new_size.height);
+            }
+        }
+    }
+
+    /// The time step the application moves its simulation by, given to the
+    /// plugins as PluginContext::time_step until the next call. Without one
+    /// they get the time since the last frame.
+    pub fn set_time_step(&mut self, dt: Duration) {
+        self.time_step = Some(dt);
+    }
+
+    // of the plugins, see set_time_step().
+    fn time_step(&self) -> f32 {
+        self.time_step.map_or_else(|| self.last_frame.elapsed().as_secs_f32(), |dt| dt.as_secs_f32())
+    }
+
+    /// Caps the frame rate, render_frame() waits until the next frame is due.
+    /// None renders as fast as the present mode allows.
+    pub fn set_fps_limit(&mut self, fps: Option<f32>) {
+        self.limiter.set_fps(fps);
+    }
+
+    pub fn fps_limit(&self) -> Option<f32> {
+        self.limiter.fps()
+    }
+
+    /// Frames the cpu may be ahead of the gpu, see RendererBuilder::frames_in_flight().
+    pub fn frames_in_flight(&self) -> usize {
+        self.in_flight.count()
+    }
+
+    /// Changes the count of RendererBuilder::frames_in_flight(), waits for the
+    /// frames in flight.
+    pub fn set_frames_in_flight(&mut self, count: usize) {
+        if count == self.in_flight.count() {
+            return;
+        }
+        self.in_flight.flush(&self.device, &self.queue);
+        self.device.poll(wgpu::Maintain::Wait);
+        self.in_flight = FramesInFlight::new(&self.device, count);
+    }
+
+    /// How long the last frame waited for the gpu to finish the one that
+    /// used its upload pool before, see inflight.rs.
+    pub fn in_flight_wait(&self) -> Duration {
+        self.in_flight.wait_time()
+    }
+
+    /// How regular the last frames started, see FramePacing.
+    pub fn frame_pacing(&self) -> FramePacing {
+        self.limiter.pacing()
+    }
+
+    /// The cpu profiler, render_frame() times its parts in it and ends
+    /// its frames. Off until it's enabled.
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    /// For the application's own scopes around render_frame(), see Profiler::begin().
+    pub fn profiler_mut(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// Color the scene is cleared to, None for the animated default.
+    /// An alpha below 1 lets a transparent window show through, see
+    /// RendererBuilder::transparent().
+    pub fn set_background(&mut self, background: Option<wgpu::Color>) {
+        self.background = background;
+    }
+
+    pub fn background(&self) -> Option<wgpu::Color> {
+        self.background
+    }
+
+    /// `ShadingRate::Half` shades the lights at half resolution, see shading.rs.
+    pub fn set_shading_rate(&mut self, shading_rate: ShadingRate) {
+        if shading_rate == self.shading_rate() {
+            return;
+        }
+        self.light_buffer = match shading_rate {
+            ShadingRate::Half => Some(LightBuffer::new(&self.device, &self.render_config())),
+            ShadingRate::Full => None,
+        };
+        self.rebuild_spot_bind_groups();
+    }
+
+    pub fn shading_rate(&self) -> ShadingRate {
+        match self.light_buffer {
+            Some(_) => ShadingRate::Half,
+            None => ShadingRate::Full,
+        }
+    }
+
+    fn rebuild_spot_bind_groups(&mut self) {
+        let light = self.light_buffer.as_ref().map_or(
+            (&self.light_placeholder, &self.light_placeholder),
+            |buffer| (buffer.view(), buffer.specular_view()),
+        );
+        self.flashlight_bind_group = create_spot_bind_group(
+            &self.device,
+            &self.flashlight_bind_group_layout,
+            &self.flashlight_buffer,
+            &self.flashlight_cookie,
+            light,
+            &self.lights_buffer,
+            &self.shadow_map,
+            self.shadow_map.view(),
+        );
+        self.light_pass_bind_group = create_spot_bind_group(
+            &self.device,
+            &self.flashlight_bind_group_layout,
+            &self.flashlight_buffer,
+            &self.flashlight_cookie,
+            (&self.light_placeholder, &self.light_placeholder),
+            &self.lights_buffer,
+            &self.shadow_map,
+            self.shadow_map.view(),
+        );
+    }
+
+    /// Draws the scene at a lower resolution while frames are slower than
+    /// `settings.target_frame_time`, plugins still draw at full resolution.
+    pub fn enable_dynamic_resolution(&mut self, settings: ResolutionSettings) {
+        match &mut self.dynamic_resolution {
+            Some(resolution) => resolution.set_settings(settings),
+            None => self.dynamic_resolution = Some(DynamicResolution::new(&self.device, &self.render_config(), settings)),
+        }
+    }
+
+    /// Supersampling keeps the offscreen target, at the full scale.
+    pub fn disable_dynamic_resolution(&mut self) {
+        match &mut self.dynamic_resolution {
+            Some(resolution) if self.supersampling > 1.0 => resolution.set_settings(ResolutionSettings::fixed()),
+            _ => self.dynamic_resolution = None,
+        }
+    }
+
+    /// Draws the scene at `factor` (1 to 2) times the window's resolution
+    /// and shrinks it down to the window, for smooth edges and thin lines
+    /// in screenshots, at about `factor`² the cost of a frame. 1 turns it
+    /// off. With dynamic resolution the scale is a part of the bigger size,
+    /// see resolution.rs.
+    pub fn set_supersampling(&mut self, factor: f32) {
+        let factor = if factor.is_finite() { factor.clamp(1.0, resolution::MAX_SUPERSAMPLING) } else { 1.0 };
+        if factor == self.supersampling {
+            return;
+        }
+        self.supersampling = factor;
+        if factor > 1.0 && self.dynamic_resolution.is_none() {
+            self.dynamic_resolution = Some(DynamicResolution::new(&self.device, &self.render_config(), ResolutionSettings::fixed()));
+        } else if factor == 1.0 && self.dynamic_resolution.as_ref().map(DynamicResolution::settings) == Some(&ResolutionSettings::fixed()) {
+            self.dynamic_resolution = None;
+        }
+        self.resize_scene_targets();
+    }
+
+    pub fn supersampling(&self) -> f32 {
+        self.supersampling
+    }
+
+    // the surface configuration at the size the scene is drawn at, the
+    // window's times the supersampling.
+    fn render_config(&self) -> wgpu::SurfaceConfiguration {
+        resolution::supersampled(&self.config, self.supersampling, self.device.limits().max_texture_dimension_2d)
+    }
+
+    // the targets of the scene pass, after the window or the supersampling changed.
+    fn resize_scene_targets(&mut self) {
+        let config = self.render_config();
+        self.msaa_framebuffer = create_msaa_framebuffer(&self.device, &config, self.msaa_samples);
+        self.depth_texture =
+            texture::Texture::create_depth_texture(&self.device, &config, self.msaa_samples, "Depth Texture");
+        self.clipping.resize(&self.device, &config);
+        if let Some(resolution) = &mut self.dynamic_resolution {
+            resolution.resize(&self.device, &config);
+        }
+        if let Some(stereo) = &mut self.stereo {
+            stereo.resize(&self.device, &config);
+        }
+        if self.light_buffer.is_some() {
+            self.light_buffer = Some(LightBuffer::new(&self.device, &config));
+            self.rebuild_spot_bind_groups();
+        }
+    }
+
+    /// Draws the scene once per eye, side by side or into the layers of
+    /// stereo_texture(), see stereo.rs. Enabling it again changes the settings.
+    pub fn enable_stereo(&mut self, settings: StereoSettings) {
+        let config = self.render_config();
+        match &mut self.stereo {
+            Some(stereo) => stereo.set_settings(&self.device, &config, settings),
+            None => {
+                self.stereo = Some(Stereo::new(
+                    &self.device,
+                    &self.camera_bind_group_layout,
+                    self.frame.buffer(),
+                    &config,
+                    settings,
+                ))
+            }
+        }
+    }
+
+    pub fn disable_stereo(&mut self) {
+        self.stereo = None;
+    }
+
+    pub fn stereo(&self) -> Option<&StereoSettings> {
+        self.stereo.as_ref().map(Stereo::settings)
+    }
+
+    /// The eyes with `StereoLayout::ArrayTexture`, layer 0 is the left one.
+    pub fn stereo_texture(&self) -> Option<&wgpu::Texture> {
+        self.stereo.as_ref().and_then(Stereo::texture)
+    }
+
+    /// Part of the window resolution the scene is drawn at, 1 without dynamic
+    /// resolution, above 1 with supersampling.
+    pub fn resolution_scale(&self) -> f32 {
+        self.dynamic_resolution.as_ref().map_or(1.0, |resolution| resolution.scale()) * self.supersampling
+    }
+
+    /// Estimated gpu memory of the models, materials and render targets, in bytes.
+    /// wgpu can't report what is really allocated, this sums up the
+    /// buffers and textures the renderer created for them (not the ones of plugins).
+    pub fn estimated_gpu_memory(&self) -> u64 {
+        let config = self.render_config();
+        let msaa = match self.msaa_framebuffer {
+            Some(_) => config.width as u64 * config.height as u64 * self.msaa_samples as u64 * 4,
+            None => 0,
+        };
+        // Depth32Float, one per sample.
+        let depth = self.depth_texture.memory_size() * self.msaa_samples as u64;
+        self.models.iter().map(model::Model::memory_size).sum::<u64>()
+            + self.materials.iter().map(model::Material::memory_size).sum::<u64>()
+            + self.flashlight_cookie.memory_size()
+            + msaa
+            + depth
+            + self.light_buffer.as_ref().map_or(0, |buffer| {
+                budget::texture_size(buffer.texture()) + budget::texture_size(buffer.specular_texture())
+            })
+            + self.dynamic_resolution.as_ref().map_or(0, |resolution| budget::texture_size(resolution.texture()))
+            + self.stereo.as_ref().and_then(Stereo::texture).map_or(0, budget::texture_size)
+    }
+
+    /// Budget estimated_gpu_memory() is checked against, see budget.rs.
+    pub fn memory_budget(&self) -> u64 {
+        self.memory_budget.budget()
+    }
+
+    /// Replaces the budget guessed from the adapter, kept across switch_backend().
+    pub fn set_memory_budget(&mut self, bytes: u64) {
+        self.memory_budget.set_budget(bytes);
+        self.builder.memory_budget = Some(bytes);
+    }
+
+    /// Direction the sun shines in, None for no sun. It lights the scene in
+    /// `Lighting::sun_color` and casts shadows unless `Lighting::shadows` is
+    /// off: the shadow map is fitted to it and the active camera every
+    /// frame, see shadow.rs.
+    pub fn set_sun(&mut self, direction: Option<cgmath::Vector3<f32>>) {
+        self.sun = direction;
+        if direction.is_none() {
+            self.shadow_fit = None;
+        }
+    }
+
+    pub fn sun(&self) -> Option<cgmath::Vector3<f32>> {
+        self.sun
+    }
+
+    /// The sun's shadow projection as of the last frame.
+    pub fn shadow_fit(&self) -> Option<&ShadowFit> {
+        self.shadow_fit.as_ref()
+    }
+
+    // the models the scene pass draws and their hidden meshes: the
+    // preview's while it's shown, see show_preview().
+    fn drawn_models(&self) -> (&[model::Model], &[BTreeSet<usize>]) {
+        match &self.preview {
+            Some(preview) => (preview.models(), preview.hidden()),
+            None => (&self.models, &self.hidden_meshes),
+        }
+    }
+
+    // the drawn models into `render_pass`, which has the scene or the light
+    // pipeline set. The preview's ball with the previewed material.
+    fn draw_scene_models<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        material_override: Option<&'a model::Material>,
+        tier: DetailTier,
+        stats: &mut FrameStats,
+    ) {
+        let preview = match &self.preview {
+            Some(preview) => preview,
+            None => {
+                draw_models(
+                    render_pass,
+                    &self.models,
+                    &self.model_paths,
+                    &self.hidden_meshes,
+                    self.subdivision.as_ref(),
+                    &self.materials,
+                    &self.material_overrides,
+                    material_override,
+                    &self.identity_instance,
+                    tier,
+                    stats,
+                );
+                return;
+            }
+        };
+        // the scene's overrides are by its model indices.
+        let overrides = MaterialOverrides::new();
+        let (models, names, hidden) = (preview.models(), preview.names(), preview.hidden());
+        let ball = preview.material(&self.materials, &self.models);
+        for (part, material) in [(0..1, ball), (1..2, None)] {
+            draw_models(
+                render_pass,
+                &models[part.clone()],
+                &names[part.clone()],
+                &hidden[part],
+                None,
+                &self.materials,
+                &overrides,
+                material,
+                &self.identity_instance,
+                tier,
+                stats,
+            );
+        }
+    }
+
+    // the sun's shadow projection if the shadow map is drawn this frame.
+    fn sun_shadow(&self) -> Option<&ShadowFit> {
+        self.shadow_fit.as_ref().filter(|_| self.lighting.shadows && self.preview.is_none())
+    }
+
+    // the depth of the drawn models as the sun sees them, see shadow.rs.
+    fn encode_shadow_map(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.shadow_map.view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(1, self.shadow_map.camera_bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.shadow_pass_bind_group, &[]);
+        render_pass.set_bind_group(3, self.clipping.bind_group(), &[]);
+        // the frame's statistics are the scene pass's.
+        let mut shadow_stats = FrameStats::default();
+        self.draw_scene_models(&mut render_pass, None, self.lod_settings.shadow, &mut shadow_stats);
+    }
+
+    // world space boxes around the visible meshes and what plugins draw.
+    fn visible_boxes(&self) -> Vec<([f32; 3], [f32; 3])> {
+        let (models, hidden) = self.drawn_models();
+        models.iter()
+            .enumerate()
+            .flat_map(|(index, model)| {
+                model.meshes.iter()
+                    .enumerate()
+                    .filter(move |(mesh, _)| !hidden[index].contains(mesh))
+                    .filter(|(_, mesh)| !mesh.bounds.is_empty())
+                    .flat_map(move |(_, mesh)| {
+                        let shift = |corner: [f32; 3]| [0, 1, 2].map(|axis| corner[axis] + mesh.offset[axis]);
+                        let bounds = model::Bounds { min: shift(mesh.bounds.min), max: shift(mesh.bounds.max) };
+                        model.instance_bounds(bounds).into_iter().map(|bounds| (bounds.min, bounds.max))
+                    })
+            })
+            .chain(self.plugins.iter().filter_map(|plugin| plugin.bounds()).map(|bounds| (bounds.min, bounds.max)))
+            .collect()
+    }
+
+    /// Turns the fitting of the near and far planes to the visible meshes
+    /// on or off, see RendererBuilder::auto_clip_planes(). When it's turned
+    /// off the cameras keep the planes of the last frame.
+    pub fn set_auto_clip_planes(&mut self, auto: bool) {
+        self.auto_clip_planes = auto;
+        self.builder.auto_clip_planes = auto;
+    }
+
+    pub fn auto_clip_planes(&self) -> bool {
+        self.auto_clip_planes
+    }
+
+    /// How close the estimated usage is to the budget, as of the last frame.
+    pub fn memory_budget_level(&self) -> BudgetLevel {
+        self.memory_budget.level()
+    }
+
+    // logs and emits MemoryBudget when the usage moved to another level.
+    fn check_memory_budget(&mut self) {
+        let used = self.estimated_gpu_memory();
+        let level = match self.memory_budget.update(used) {
+            Some(level) => level,
+            None => return,
+        };
+        let budget = self.memory_budget.budget();
+        let mib = |bytes: u64| bytes as f64 / (1 << 20) as f64;
+        match level {
+            BudgetLevel::Normal => log::info!("Gpu memory back to {:.0} of {:.0} MiB.", mib(used), mib(budget)),
+            BudgetLevel::Warning | BudgetLevel::Critical => log::warn!(
+                "Gpu memory at {:.0} of {:.0} MiB ({:?}), free models or materials.",
+                mib(used), mib(budget), level,
+            ),
+        }
+        self.events.emit(RendererEvent::MemoryBudget { used, budget, level });
+    }
+
+    /// Captures the next `frames` frames with RenderDoc. Needs the `renderdoc`
+    /// feature and the application started from RenderDoc.
+    pub fn capture_frames(&mut self, frames: u32) -> Result<()> {
+        if self.capture.is_none() {
+            self.capture = Some(GpuCapture::connect()?);
+        }
+        if let Some(capture) = &mut self.capture {
+            capture.trigger(frames);
+        }
+        Ok(())
+    }
+
+    /// Number of captures written since the first capture_frames().
+    pub fn num_captures(&self) -> u32 {
+        self.capture.as_ref().map_or(0, |capture| capture.num_captures())
+    }
+
+    /// Draws the current frame into an image instead of the window, handed
+    /// to `finish` once it's read back while the next frames are drawn, see
+    /// poll_completed_jobs(). Plugins draw too, but aren't updated, the
+    /// window shows nothing new.
+    pub fn screenshot<F>(&mut self, finish: F) -> Result<()>
+    where
+        F: FnOnce(&mut Renderer, Result<image::RgbaImage>) + 'static,
+    {
+        let texture = self.render_offscreen();
+        let (width, height, format) = (self.config.width, self.config.height, self.config.format);
+        let pending = PendingReadback::start(&self.device, &self.queue, &texture, format, width, height)?;
+        self.read_back_in_background(vec![pending], "screenshot", move |mut texels| {
+            readback::to_image(texels.remove(0), format, width, height)
+        }, |renderer, image| finish(renderer, image.context("Unable to read back the screenshot.")));
+        Ok(())
+    }
+
+    /// The scene around the eye of the active camera as a cube map, faces as
+    /// large as the window's shorter side, see cubemap.rs. Handed to `finish`
+    /// like screenshot(). Supersampled faces are shrunk on the job pool.
+    pub fn capture_cubemap<F>(&mut self, finish: F) -> Result<()>
+    where
+        F: FnOnce(&mut Renderer, Result<CubeMap>) + 'static,
+    {
+        self.flush_uploads();
+        let size = self.config.width.min(self.config.height);
+        // the scene targets are as big as the drawn scene, the face has to be too.
+        let config = self.render_config();
+        let drawn_size = config.width.min(config.height);
+        let texture = self.create_offscreen_texture(config.width, config.height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut binding = CameraBinding::new(&self.device, &self.camera_bind_group_layout, self.frame.buffer(), "Cube Map Face");
+
+        let camera = &self.cameras.active().camera;
+        let eye = camera.eye();
+        let (znear, zfar) = camera.clip_planes();
+        let scene_bounds = self.models.iter()
+            .fold(model::Bounds::empty(), |bounds, model| bounds.union(&model.bounds));
+        // every direction has to reach the end of the scene, not only the camera's.
+        let zfar = if scene_bounds.is_empty() {
+            zfar
+        } else {
+            cubemap::far_plane(eye, scene_bounds.min, scene_bounds.max).max(znear * 2.0) * 1.01
+        };
+
+        let mut faces = Vec::with_capacity(CubeFace::ALL.len());
+        for face in CubeFace::ALL {
+            binding.upload(&self.queue, face.view_projection(eye, znear, zfar));
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Cube Map Encoder"),
+            });
+            let (attachment, resolve_target) = match &self.msaa_framebuffer {
+                Some(msaa_view) => (msaa_view, Some(&view)),
+                None => (&view, None),
+            };
+            let mut stats = FrameStats::default();
+            let views = [(binding.bind_group(), Some([0, 0, drawn_size, drawn_size]))];
+            self.encode_scene(&mut encoder, attachment, resolve_target, &view, &views, self.lod_settings.reflection, &mut stats);
+            self.queue.submit(std::iter::once(encoder.finish()));
+
+            // the top left of the target, copied before the next face is drawn into it.
+            faces.push(PendingReadback::start(&self.device, &self.queue, &texture, self.config.format, drawn_size, drawn_size)?);
+        }
+
+        let format = self.config.format;
+        self.read_back_in_background(faces, "cube map", move |faces| {
+            let faces = faces
+                .into_iter()
+                .map(|texels| {
+                    let image = shrink(readback::to_image(texels, format, drawn_size, drawn_size)?, size, size);
+                    // mirrored into the cube's handedness.
+                    Ok(image::imageops::flip_horizontal(&image))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(CubeMap { size, faces })
+        }, |renderer, cube| finish(renderer, cube.context("Unable to read back the cube map.")));
+        Ok(())
+    }
+
+    /// The scene seen by the camera `name` at the window's size, drawn at the
+    /// minimap's tier of the lod settings (see lod.rs) without the plugins.
+    /// Handed to `finish` like screenshot().
+    pub fn capture_camera<F>(&mut self, name: &str, finish: F) -> Result<()>
+    where
+        F: FnOnce(&mut Renderer, Result<image::RgbaImage>) + 'static,
+    {
+        self.flush_uploads();
+        let camera = &self.cameras.get(name)
+            .with_context(|| format!("No camera named {:?}.", name))?
+            .camera;
+        let (width, height) = (self.config.width, self.config.height);
+        let view_proj = math::perspective(camera.fovy(), math::aspect_ratio(width, height), camera.clip_planes().0, camera.clip_planes().1)
+            * camera.view_matrix();
+        let mut binding = CameraBinding::new(&self.device, &self.camera_bind_group_layout, self.frame.buffer(), name);
+        binding.upload(&self.queue, view_proj);
+
+        let config = self.render_config();
+        let texture = self.create_offscreen_texture(config.width, config.height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Camera Capture Encoder"),
+        });
+        let (attachment, resolve_target) = match &self.msaa_framebuffer {
+            Some(msaa_view) => (msaa_view, Some(&view)),
+            None => (&view, None),
+        };
+        let mut stats = FrameStats::default();
+        let views = [(binding.bind_group(), None)];
+        self.encode_scene(&mut encoder, attachment, resolve_target, &view, &views, self.lod_settings.minimap, &mut stats);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let (format, drawn_width, drawn_height) = (self.config.format, config.width, config.height);
+        let pending = PendingReadback::start(&self.device, &self.queue, &texture, format, drawn_width, drawn_height)?;
+        let name = name.to_string();
+        self.read_back_in_background(vec![pending], "camera capture", move |mut texels| {
+            Ok(shrink(readback::to_image(texels.remove(0), format, drawn_width, drawn_height)?, width, height))
+        }, move |renderer, image| finish(renderer, image.with_context(|| format!("Unable to read back the view of {:?}.", name))));
+        Ok(())
+    }
+
+    /// The active camera's view as an image of `width` x `height`, bigger
+    /// than the gpu could draw at once, put together out of tiles as big as
+    /// the window (times the supersampling) on the job pool and handed to
+    /// `finish` like screenshot(). Without the plugins, see tiles.rs.
+    pub fn render_tiled<F>(&mut self, width: u32, height: u32, finish: F) -> Result<()>
+    where
+        F: FnOnce(&mut Renderer, Result<image::RgbaImage>) + 'static,
+    {
+        if width == 0 || height == 0 || width.max(height) > tiles::MAX_TILED_SIZE {
+            bail!("A tiled image is 1 to {} pixels wide and high, not {}x{}.", tiles::MAX_TILED_SIZE, width, height);
+        }
+        self.flush_uploads();
+        let camera = &self.cameras.active().camera;
+        let (znear, zfar) = camera.clip_planes();
+        // the camera's field of view, over the image's aspect.
+        let view_proj = math::perspective(camera.fovy(), math::aspect_ratio(width, height), znear, zfar)
+            * camera.view_matrix();
+        let mut binding = CameraBinding::new(&self.device, &self.camera_bind_group_layout, self.frame.buffer(), "Tile");
+
+        let config = self.render_config();
+        let texture = self.create_offscreen_texture(config.width, config.height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let grid = tiles::grid(width, height, config.width, config.height);
+        let mut parts = Vec::with_capacity(grid.len());
+        for tile in &grid {
+            binding.upload(&self.queue, tile.projection(width, height) * view_proj);
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Tile Encoder"),
+            });
+            let (attachment, resolve_target) = match &self.msaa_framebuffer {
+                Some(msaa_view) => (msaa_view, Some(&view)),
+                None => (&view, None),
+            };
+            let mut stats = FrameStats::default();
+            let views = [(binding.bind_group(), Some([0, 0, tile.width, tile.height]))];
+            self.encode_scene(&mut encoder, attachment, resolve_target, &view, &views, DetailTier::Full, &mut stats);
+            self.queue.submit(std::iter::once(encoder.finish()));
+
+            // the top left of the target, copied before the next tile is drawn into it.
+            parts.push(PendingReadback::start(&self.device, &self.queue, &texture, self.config.format, tile.width, tile.height)?);
+        }
+
+        let format = self.config.format;
+        self.read_back_in_background(parts, "tiles", move |parts| {
+            let mut image = image::RgbaImage::new(width, height);
+            for (tile, texels) in grid.iter().zip(parts) {
+                let pixels = readback::to_image(texels, format, tile.width, tile.height)?;
+                image::imageops::replace(&mut image, &pixels, tile.x, tile.y);
+            }
+            Ok(image)
+        }, |renderer, image| finish(renderer, image.context("Unable to read back the tiles.")));
+        Ok(())
+    }
+
+    // Polls `parts` and hands their texels to `work` on the job pool, what
+    // it makes of them to `finish`. A failed readback goes to `finish` too.
+    fn read_back_in_background<T, W, F>(&mut self, parts: Vec<PendingReadback>, name: &'static str, work: W, finish: F)
+    where
+        T: Send + 'static,
+        W: FnOnce(Vec<Vec<u8>>) -> Result<T> + Send + 'static,
+        F: FnOnce(&mut Renderer, Result<T>) + 'static,
+    {
+        self.readbacks.push(parts, move |renderer, texels| match texels {
+            Ok(texels) => {
+                renderer.spawn_job(JobPriority::Normal, name, move || work(texels), finish);
+            }
+            Err(e) => finish(renderer, Err(e)),
+        });
+    }
+
+    /// The tiers the secondary views (reflections, minimap, shadows) draw at, see lod.rs.
+    pub fn set_lod_settings(&mut self, settings: LodSettings) {
+        self.lod_settings = settings;
+    }
+
+    pub fn lod_settings(&self) -> &LodSettings {
+        &self.lod_settings
+    }
+
+    // draws the frame into a texture that can be copied from, the surface texture can't.
+    fn render_offscreen(&mut self) -> wgpu::Texture {
+        self.flush_uploads();
+        let texture = self.create_offscreen_texture(self.config.width, self.config.height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Encoder"),
+        });
+        self.encode_frame(&mut encoder, &view);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        texture
+    }
+
+    // the uploads since the last frame, for drawing outside of render_frame().
+    fn flush_uploads(&mut self) {
+        self.in_flight.flush(&self.device, &self.queue);
+    }
+
+    // of the window's format.
+    fn create_offscreen_texture(&self, width: u32, height: u32) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Frame"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Draws the current frame offscreen and saves its intermediate render
+    /// targets into `dir` as png files, named after the frame number. They
+    /// are read back and saved in the background, `finish` gets the written
+    /// files, see targets.rs.
+    pub fn dump_targets<P, F>(&mut self, dir: P, finish: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Renderer, Result<Vec<PathBuf>>) + 'static,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        let frame = self.render_offscreen();
+        let format = self.config.format;
+
+        let mut parts = Vec::new();
+        let (width, height) = (self.config.width, self.config.height);
+        parts.push(PendingReadback::start(&self.device, &self.queue, &frame, format, width, height)?);
+
+        // only the top left viewport of the target is drawn into.
+        let mut scene = None;
+        if let Some(resolution) = &self.dynamic_resolution {
+            let (target_width, target_height) = resolution.size();
+            parts.push(PendingReadback::start(&self.device, &self.queue, resolution.texture(), format, target_width, target_height)?);
+            scene = Some((resolution.size(), resolution.viewport()));
+        }
+
+        let mut light = None;
+        if let Some(light_buffer) = &self.light_buffer {
+            let (buffer_width, buffer_height) = light_buffer.size();
+            parts.push(PendingReadback::start(
+                &self.device,
+                &self.queue,
+                light_buffer.texture(),
+                wgpu::TextureFormat::Rgba16Float,
+                buffer_width,
+                buffer_height,
+            )?);
+            light = Some((buffer_width, buffer_height));
+        }
+
+        // only there with the sun's shadows, see shadow.rs.
+        let shadow = self.sun_shadow().is_some();
+        if shadow {
+            let size = shadow::SHADOW_MAP_SIZE;
+            parts.push(PendingReadback::start(&self.device, &self.queue, self.shadow_map.texture(), shadow::SHADOW_FORMAT, size, size)?);
+        }
+
+        let frame_count = self.frame_count;
+        self.read_back_in_background(parts, "render targets", move |parts| {
+            std::fs::create_dir_all(&dir).with_context(|| format!("Unable to create {:?}.", dir))?;
+            let mut written = Vec::new();
+            let mut save = |name: &str, image: &dyn Fn(&Path) -> image::ImageResult<()>| -> Result<()> {
+                let path = dir.join(format!("frame{:06}_{}.png", frame_count, name));
+                image(&path).with_context(|| format!("Unable to save {:?}.", path))?;
+                written.push(path);
+                Ok(())
+            };
+
+            let mut parts = parts.into_iter();
+            let mut next = || parts.next().context("A render target wasn't read back.");
+            let final_image = readback::to_image(next()?, format, width, height)?;
+            save("final", &|path| final_image.save(path))?;
+
+            if let Some(((target_width, target_height), (viewport_width, viewport_height))) = scene {
+                let scene = readback::to_image(next()?, format, target_width, target_height)?;
+                let scene = image::imageops::crop_imm(&scene, 0, 0, viewport_width, viewport_height).to_image();
+                save("scene", &|path| scene.save(path))?;
+            }
+
+            if let Some((buffer_width, buffer_height)) = light {
+                let texels = readback::halves_to_floats(&next()?);
+                let light = image::RgbaImage::from_raw(
+                    buffer_width,
+                    buffer_height,
+                    texels
+                        .iter()
+                        .flat_map(|texel| {
+                            let [r, g, b, _] = texel.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                            [r, g, b, 255]
+                        })
+                        .collect(),
+                )
+                .context("Light buffer readback has the wrong size.")?;
+                save("light", &|path| light.save(path))?;
+
+                // scaled to the farthest drawn texel, the empty ones are white.
+                let far = targets::max_depth(&texels, shading::EMPTY_DEPTH as f32).max(f32::EPSILON);
+                let depth = targets::depth_image(&texels, buffer_width, buffer_height, far)?;
+                save("light_depth", &|path| depth.save(path))?;
+            }
+
+            if shadow {
+                let size = shadow::SHADOW_MAP_SIZE;
+                let depth = targets::depth_texture_image(&readback::depths(&next()?), size, size)?;
+                save("shadow_map", &|path| depth.save(path))?;
+            }
+
+            Ok(written)
+        }, finish);
+        Ok(())
+    }
+
+    /// Saves screenshot() as an image file, the format follows the extension.
+    /// The viewer keeps drawing, the image is encoded on the job pool and the
+    /// log tells when it's saved.
+    pub fn save_screenshot<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.screenshot(move |renderer, image| {
+            let image = match image {
+                Ok(image) => image,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    return;
+                }
+            };
+            // encoding a png takes longer than drawing a frame.
+            renderer.spawn_job(JobPriority::Low, "screenshot", move || {
+                image.save(&path)
+                    .with_context(|| format!("Unable to save the screenshot {:?}.", path))
+                    .map(|()| path)
+            }, |_, saved| match saved {
+                Ok(path) => log::info!("Saved the screenshot {:?}", path),
+                Err(e) => log::error!("{:?}", e),
+            });
+        })
+    }
+
+    /// Uploads the camera and draws all models into the next surface texture,
+    /// followed by the passes of the registered plugins.
+    pub fn render_frame(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.profiler.begin("limiter");
+        self.limiter.wait();
+        self.profiler.end();
+        let frame_start = Instant::now();
+        self.profiler.begin("jobs");
+        self.poll_completed_jobs();
+        self.poll_material_libraries();
+        self.check_memory_budget();
+        self.profiler.end();
+
+        self.profiler.begin("animation");
+        let dt = self.last_frame.elapsed().as_secs_f32();
+        for view in &mut self.exploded_views {
+            view.animate(dt);
+            if let Some(model) = self.models.get_mut(view.model) {
+                view.apply(&self.queue, model);
+            }
+        }
+        self.exploded_views.retain(|view| !view.is_assembled());
+
+        self.cameras.follow_nodes(&self.models);
+        self.profiler.end();
+
+        self.profiler.begin("culling");
+        let boxes = self.visible_boxes();
+        if self.auto_clip_planes {
+            self.cameras.fit_clip_planes(&boxes);
+        }
+        // after the clip planes, the far plane decides how much the shadow has to cover.
+        self.shadow_fit = self.sun.and_then(|direction| {
+            let visible = boxes.iter().fold(model::Bounds::empty(), |mut bounds, (min, max)| {
+                bounds.extend(*min);
+                bounds.extend(*max);
+                bounds
+            });
+            shadow::fit_directional(direction, &self.cameras.active().camera, &visible, shadow::SHADOW_MAP_SIZE)
+        });
+        self.profiler.end();
+
+        self.profiler.begin("upload");
+        for name in self.cameras.upload(&self.device, &mut self.in_flight) {
+            self.events.emit(RendererEvent::CameraChanged { name });
+        }
+        if let Some(stereo) = &mut self.stereo {
+            stereo.update(&self.device, &mut self.in_flight, &self.cameras.active().camera);
+        }
+        self.frame.update(
+            self.time.elapsed().as_secs_f32(),
+            dt,
+            self.frame_count,
+            (self.size.width, self.size.height),
+            self.cameras.active().camera.eye(),
+        );
+        self.frame.upload(&self.device, &mut self.in_flight);
+
+        self.flashlight.position = self.cameras.active().camera.eye();
+        self.flashlight.direction = self.cameras.active().camera.target() - self.cameras.active().camera.eye();
+        self.in_flight.write(
+            &self.device,
+            &self.flashlight_buffer,
+            0,
+            bytemuck::cast_slice(&[light::SpotUniform::from(&self.flashlight)
+                .with_light_buffer(self.light_buffer.is_some())]),
+        );
+        let lights = light::LightsUniform::new(self.shading_lights(), &self.lighting);
+        self.in_flight.write(&self.device, &self.lights_buffer, 0, bytemuck::cast_slice(&[lights]));
+        // the preview has its own lights, without the sun.
+        let sun = self.sun.filter(|_| self.preview.is_none());
+        let fit = self.sun_shadow().copied();
+        self.shadow_map.upload(&self.device, &mut self.in_flight, sun, &self.lighting, fit.as_ref());
+
+        // the caps have to reach every copy of an instanced model.
+        let scene_bounds = self.models.iter()
+            .flat_map(|model| model.instance_bounds(model.bounds))
+            .fold(model::Bounds::empty(), |bounds, copy| bounds.union(&copy));
+        self.clipping.update(&self.queue, &scene_bounds);
+
+        let ctx = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            config: &self.config,
+            camera_bind_group_layout: &self.camera_bind_group_layout,
+            camera: &self.cameras.active().camera,
+            lights: &self.lights,
+            shadow: self.shadow_fit.as_ref(),
+            scale_factor: self.window.scale_factor(),
+            time_step: self.time_step(),
+        };
+        for plugin in &mut self.plugins {
+            plugin.update(&ctx);
+        }
+        // the copies into the buffers above, ahead of the frame.
+        let uploads = self.in_flight.submit(&self.queue);
+        self.profiler.end();
+
+        // get current texture will wait for surface to provide a new SurfaceTexture
+        self.profiler.begin("acquire");
+        let output = self.surface.get_current_texture();
+        self.profiler.end();
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                // nothing is drawn, the next frame takes the next pool all the same.
+                self.in_flight.end_frame(&self.device, uploads);
+                // Reconfigure the surface if lost
+                if e == wgpu::SurfaceError::Lost {
+                    self.events.emit(RendererEvent::DeviceLost);
+                    self.resize(self.size);
+                    self.events.emit(RendererEvent::DeviceRecovered);
+                    self.profiler.end_frame();
+                    return Ok(());
+                }
+                return Err(e);
+            }
+        };
+        let view =
+            output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.profiler.begin("encode");
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let mut stats = self.encode_frame(&mut encoder, &view);
+        self.profiler.end();
+
+        // submit will accept anything that implements IntoIter
+        self.profiler.begin("submit");
+        let submission = self.queue.submit(std::iter::once( encoder.finish() ));
+        output.present();
+        self.profiler.end();
+
+        // the cpu doesn't get further ahead than the frames in flight.
+        self.profiler.begin("in flight");
+        self.in_flight.end_frame(&self.device, submission);
+        self.profiler.end();
+
+        stats.frame = self.frame_count;
+        stats.frame_time = self.last_frame.elapsed();
+        self.frame_count += 1;
+        self.last_frame = Instant::now();
+        self.last_stats = stats;
+        if let Some(resolution) = &mut self.dynamic_resolution {
+            resolution.record_frame_time(frame_start.elapsed());
+        }
+        self.events.emit(RendererEvent::FrameRendered(stats));
+        self.profiler.end_frame();
+
+        Ok(())
+    }
+
+    // the passes of a frame drawing into `view`, the window's surface texture or an offscreen one.
+    fn encode_frame(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> FrameStats {
+        let mut stats = FrameStats::default();
+
+        // with dynamic resolution the scene goes into the offscreen target first.
+        let scene_view = self.dynamic_resolution.as_ref()
+            .map_or(view, |resolution| resolution.target());
+        let viewport = self.dynamic_resolution.as_ref().map(|resolution| resolution.viewport());
+
+        // with msaa we draw into the multisampled framebuffer
+        // and let the pass resolve it into the surface texture.
+        let (attachment, resolve_target) = match &self.msaa_framebuffer {
+            Some(msaa_view) => (msaa_view, Some(scene_view)),
+            None => (scene_view, None),
+        };
+
+        if let Some(subdivision) = &mut self.subdivision {
+            if subdivision.needs_refine(self.cameras.active().camera.eye()) {
+                encoder.push_debug_group("Terrain Subdivision");
+                subdivision.refine(&self.queue, encoder, self.cameras.active().camera.eye());
+                encoder.pop_debug_group();
+            }
+        }
+
+        if self.sun_shadow().is_some() {
+            self.profiler.begin("shadow");
+            self.encode_shadow_map(encoder);
+            self.profiler.end();
+        }
+
+        // the cameras the scene is drawn with and where, once per eye side by side.
+        let views: Vec<(&wgpu::BindGroup, Option<[u32; 4]>)> = match &self.stereo {
+            Some(stereo) if stereo.settings().layout == StereoLayout::SideBySide => {
+                let (width, height) = viewport.unwrap_or((self.config.width, self.config.height));
+                Eye::BOTH
+                    .iter()
+                    .map(|eye| (stereo.bind_group(*eye), Some(stereo::side_by_side_viewport(*eye, width, height))))
+                    .collect()
+            }
+            _ => vec![(self.cameras.active().bind_group(), viewport.map(|(width, height)| [0, 0, width, height]))],
+        };
+        self.profiler.begin("scene");
+        self.encode_scene(encoder, attachment, resolve_target, scene_view, &views, DetailTier::Full, &mut stats);
+
+        // every eye into its layer, the window keeps the mono frame.
+        if let Some(stereo) = self.stereo.as_ref().filter(|stereo| stereo.settings().layout == StereoLayout::ArrayTexture) {
+            for eye in Eye::BOTH {
+                let layer = match stereo.layer(eye) {
+                    Some(layer) => layer,
+                    None => continue,
+                };
+                let (attachment, resolve_target) = match &self.msaa_framebuffer {
+                    Some(msaa_view) => (msaa_view, Some(layer)),
+                    None => (layer, None),
+                };
+                encoder.push_debug_group(eye.label());
+                let views = [(stereo.bind_group(eye), None)];
+                self.encode_scene(encoder, attachment, resolve_target, layer, &views, DetailTier::Full, &mut stats);
+                encoder.pop_debug_group();
+            }
+        }
+        self.profiler.end();
+
+        if let Some(resolution) = &self.dynamic_resolution {
+            encoder.push_debug_group("Upscale");
+            resolution.encode_upscale(&self.queue, encoder, view, (self.config.width, self.config.height));
+            encoder.pop_debug_group();
+        }
+
+        let shares_depth = self.shares_depth();
+        let ctx = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            config: &self.config,
+            camera_bind_group_layout: &self.camera_bind_group_layout,
+            camera: &self.cameras.active().camera,
+            lights: &self.lights,
+            shadow: self.shadow_fit.as_ref(),
+            scale_factor: self.window.scale_factor(),
+            time_step: self.time_step(),
+        };
+        let targets = FrameTargets {
+            color: view,
+            format: self.config.format,
+            width: self.config.width,
+            height: self.config.height,
+            camera_bind_group: self.cameras.active().bind_group(),
+            depth: shares_depth.then_some(&self.depth_texture.view),
+        };
+        self.profiler.begin("plugins");
+        for plugin in &mut self.plugins {
+            encoder.push_debug_group(plugin.name());
+            plugin.encode(&ctx, encoder, &targets);
+            encoder.pop_debug_group();
+        }
+        self.profiler.end();
+
+        stats
+    }
+
+    // Whether the plugins get the scene depth: only if it's single sampled
+    // and matches the frame's pixels, so not with dynamic resolution (a
+    // part of it, upscaled) or stereo (the other eyes).
+    fn shares_depth(&self) -> bool {
+        self.msaa_samples == 1 && self.dynamic_resolution.is_none() && self.stereo.is_none()
+    }
+
+    // the light pass (at half rate), the scene pass and the cross-section
+    // caps, drawing into `attachment` (resolved into `scene_view` with msaa),
+    // once for every camera bind group and viewport (x, y, width, height) of
+    // `views`.
+    #[allow(clippy::too_many_arguments)]
+    fn encode_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        attachment: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        scene_view: &wgpu::TextureView,
+        views: &[(&wgpu::BindGroup, Option<[u32; 4]>)],
+        tier: DetailTier,
+        stats: &mut FrameStats,
+    ) {
+        let material_override = self.material_override
+            .and_then(|index| self.materials.get(index))
+            .filter(|_| self.preview.is_none());
+
+        // half rate lighting first, the scene pass reads it. Nothing to shade
+        // with the simplified materials, they aren't lit.
+        let lit = !tier.simple_materials();
+        if let Some(light_buffer) = self.light_buffer.as_ref().filter(|_| lit) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Light Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: light_buffer.view(),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 0.0,
+                                g: 0.0,
+                                b: 0.0,
+                                a: shading::EMPTY_DEPTH,
+                            }),
+                            store: true,
+                        },
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: light_buffer.specular_view(),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.light_pipeline);
+            render_pass.set_bind_group(2, &self.light_pass_bind_group, &[]);
+            render_pass.set_bind_group(3, self.clipping.bind_group(), &[]);
+            for (camera_bind_group, viewport) in views {
+                let [x, y, width, height] = light_buffer.viewport(*viewport);
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(1, camera_bind_group, &[]);
+                // counted once, in the scene pass.
+                let mut light_stats = FrameStats::default();
+                self.draw_scene_models(&mut render_pass, material_override, tier, &mut light_stats);
+            }
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Scene Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: attachment, // what texture to save the colors to.
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        // background clear color
+                        load: wgpu::LoadOp::Clear(self.preview.as_ref().map(|_| preview::BACKGROUND).or(self.background).unwrap_or(wgpu::Color {
+                            r: (self.frame.uniform().time as f64).sin().abs(),
+                            g: 1.0,
+                            b: (self.frame.uniform().time as f64).cos().abs(),
+                            a: 1.0,
+                        })),
+                        store: true, // whether to store render results in the view field above.
+                    },
+                })],
+                // cleared for every view, the meshes are tested against it
+                // so the nearest one is seen, whatever order they're drawn in.
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            // set rendering pipeline created in build()
+            render_pass.set_pipeline(if tier.simple_materials() { &self.simple_pipeline } else { &self.render_pipeline });
+            render_pass.set_bind_group(2, &self.flashlight_bind_group, &[]);
+            render_pass.set_bind_group(3, self.clipping.bind_group(), &[]);
+            stats.bind_group_switches += 2;
+
+            for (camera_bind_group, viewport) in views {
+                if let Some([x, y, width, height]) = *viewport {
+                    render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                    render_pass.set_scissor_rect(x, y, width, height);
+                }
+                render_pass.set_bind_group(1, camera_bind_group, &[]);
+                stats.bind_group_switches += 1;
+
+                self.draw_scene_models(&mut render_pass, material_override, tier, stats);
+            }
+
+            // the lights on top, depth tested against the models.
+            let markers = if self.lighting.markers && !tier.simple_materials() {
+                self.shading_lights().len().min(light::MAX_LIGHTS) as u32
+            } else {
+                0
+            };
+            if markers > 0 {
+                render_pass.push_debug_group("Light Markers");
+                for (camera_bind_group, viewport) in views {
+                    if let Some([x, y, width, height]) = *viewport {
+                        render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                        render_pass.set_scissor_rect(x, y, width, height);
+                    }
+                    self.light_markers.draw(&mut render_pass, camera_bind_group, &self.flashlight_bind_group, markers);
+                    stats.draw_calls += 1;
+                    stats.triangles += 12 * markers;
+                    stats.instances += markers;
+                }
+                render_pass.pop_debug_group();
+            }
+        } // -->
+            // need to drop value render_pass, since begin_render_pass borrows mutably and
+            // we need to call encoder.finish()
+
+        encoder.push_debug_group("Cross-section Caps");
+        let (models, hidden_meshes) = self.drawn_models();
+        let subdivision = self.subdivision.as_ref().filter(|_| self.preview.is_none());
+        for (camera_bind_group, viewport) in views {
+            self.clipping.encode_caps(
+                encoder,
+                scene_view,
+                camera_bind_group,
+                models,
+                hidden_meshes,
+                subdivision,
+                &self.identity_instance,
+                *viewport,
+            );
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+// the visible meshes of all models, with their materials, or the ones of
+// `overrides` (out of `materials`), or `material_override`.
+#[allow(clippy::too_many_arguments)]
+fn draw_models<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    models: &'a [model::Model],
+    model_paths: &[PathBuf],
+    hidden_meshes: &[BTreeSet<usize>],
+    subdivision: Option<&'a TerrainSubdivision>,
+    materials: &'a [model::Material],
+    overrides: &MaterialOverrides,
+    material_override: Option<&'a model::Material>,
+    identity_instance: &'a model::InstanceBuffer,
+    tier: DetailTier,
+    stats: &mut FrameStats,
+) {
+    use model::DrawModel;
+
+    // meshes sharing a material don't bind it again.
+    let mut bound_material: Option<&model::Material> = None;
+
+    for (index, model) in models.iter().enumerate() {
+        let hidden = &hidden_meshes[index];
+        let instances = model.instances.as_ref().unwrap_or(identity_instance);
+
+        // one group per model in gpu captures, named after the file.
+        let name = model_paths[index].file_name()
+            .map_or_else(|| format!("Model {}", index), |name| name.to_string_lossy().into_owned());
+        render_pass.push_debug_group(&name);
+
+        // the secondary views draw the terrain as it was loaded, see lod.rs.
+        if let Some(subdivision) = subdivision.filter(|s| s.model() == index && tier == DetailTier::Full) {
+            for (mesh_index, (mesh, source)) in subdivision.meshes().iter().zip(&model.meshes).enumerate() {
+                let material = overrides.get(index, mesh_index)
+                    .and_then(|material| materials.get(material))
+                    .or(material_override)
+                    .or_else(|| model.materials.get(mesh.material));
+                let material = match material {
+                    Some(material) if !hidden.contains(&mesh_index) => material,
+                    _ => {
+                        stats.culled += 1;
+                        continue;
+                    }
+                };
+                if !bound_material.is_some_and(|bound| std::ptr::eq(bound, material)) {
+                    render_pass.set_bind_group(0, &material.bind_group, &[]);
+                    bound_material = Some(material);
+                    stats.bind_group_switches += 1;
+                }
+                render_pass.insert_debug_marker(&source.name);
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, source.offset_buffer.slice(..));
+                // the draw arguments have a single instance.
+                render_pass.set_vertex_buffer(2, instances.buffer.slice(..));
+                // the vertex count is only known on the gpu,
+                // so the refined triangles aren't counted.
+                render_pass.draw_indirect(&mesh.args_buffer, 0);
+                stats.draw_calls += 1;
+                stats.instances += 1;
+            }
+            render_pass.pop_debug_group();
+            continue;
+        }
+
+        for (mesh_index, mesh) in model.meshes.iter().enumerate() {
+            let material = overrides.get(index, mesh_index)
+                .and_then(|material| materials.get(material))
+                .or(material_override)
+                .or_else(|| model.materials.get(mesh.material));
+
+            // hidden, or nothing to sample from: skip the mesh.
+            let material = match material {
+                Some(material) if !hidden.contains(&mesh_index) => material,
+                _ => {
+                    stats.culled += 1;
+                    continue;
+                }
+            };
+            if !bound_material.is_some_and(|bound| std::ptr::eq(bound, material)) {
+                render_pass.set_bind_group(0, &material.bind_group, &[]);
+                bound_material = Some(material);
+                stats.bind_group_switches += 1;
+            }
+            render_pass.insert_debug_marker(&mesh.name);
+            render_pass.draw_mesh_lod(mesh, tier, instances);
+
+            stats.draw_calls += 1;
+            stats.instances += instances.len();
+            stats.triangles += mesh.lod(tier).1 / 3 * instances.len();
+        }
+        render_pass.pop_debug_group();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_spot_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    cookie: &texture::Texture,
+    // the light buffer and its highlights, or the placeholder.
+    light: (&wgpu::TextureView, &wgpu::TextureView),
+    lights: &wgpu::Buffer,
+    shadow: &ShadowMap,
+    // the map, or its placeholder while it's drawn.
+    shadow_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&cookie.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&cookie.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(light.0),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(shadow_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::Sampler(shadow.sampler()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: shadow.buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(light.1),
+            },
+        ],
+        label: Some("spot_light_bind_group"),
+    })
+}
+
+pub fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    depth_format: Option<wgpu::TextureFormat>,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        // vertex shader stage
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: vertex_layouts,
+        },
+        // fragment shader stage
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent::REPLACE,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        // rasterizer stage
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLAMPING
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            // draw fragments that are closer than the stored depth.
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+// multisampled color target, resolved into the surface texture at the end of the pass.
+// `image` at `width` x `height`, after it was drawn supersampled.
+fn shrink(image: image::RgbaImage, width: u32, height: u32) -> image::RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+    image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+}
+
+fn create_msaa_framebuffer(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count <= 1 {
+        return None;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("MSAA Framebuffer"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
@@ -0,0 +1,327 @@
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{
+    DeviceEvent, DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton,
+    MouseScrollDelta, TouchPhase, VirtualKeyCode, WindowEvent,
+};
+
+/*
+    Recording and replaying input sessions.
+
+    InputRecorder keeps the window and device events the application
+    handles, together with the frame they arrived in. InputReplay hands
+    them out again, frame by frame. What moves by time, like the camera,
+    goes by the time step of every frame, which is recorded as well
+    (record_frame_time(), frame_time()): a replay ends up in the same
+    state as the recording, no matter how fast the frames are.
+    That makes bug reports reproducible and gives the same workload to
+    compare frame times between two versions.
+
+    Sessions are saved as json lines, one event per line:
+
+        {"frame":12,"time":0.2,"event":{"Key":{"scancode":17,"key":"W","state":"Pressed"}}}
+
+    usage:
+        let mut recorder = InputRecorder::new();
+        // in the event loop
+        recorder.record_window_event(&event);
+        // every frame, before moving by `dt`
+        recorder.record_frame_time(dt);
+        // after every frame
+        recorder.next_frame();
+        ...
+        recorder.save("session.replay")?;
+
+        let mut replay = InputReplay::load("session.replay")?;
+        // before every frame
+        for event in replay.next_frame() {
+            if let Some(event) = event.to_window_event() {
+                state.input(&event);
+            }
+        }
+        state.update(replay.frame_time().unwrap_or(Duration::from_secs_f32(1.0 / 60.0)));
+*/
+
+// The part of the winit events that is recorded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    Resized { width: u32, height: u32 },
+    Focused(bool),
+    CursorMoved { x: f64, y: f64 },
+    MouseInput { button: MouseButton, state: ElementState },
+    // `lines` is false for pixel deltas (touchpads).
+    MouseWheel { x: f32, y: f32, lines: bool },
+    Key { scancode: u32, key: Option<VirtualKeyCode>, state: ElementState },
+    Modifiers(ModifiersState),
+    Character(char),
+    // device event: raw mouse movement.
+    MouseMotion { x: f64, y: f64 },
+    // no event: the time step the frame moved by, see record_frame_time().
+    FrameTime { seconds: f64 },
+}
+
+impl InputEvent {
+    // None for events that don't come from the user.
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::Resized(size) => InputEvent::Resized { width: size.width, height: size.height },
+            WindowEvent::Focused(focused) => InputEvent::Focused(*focused),
+            WindowEvent::CursorMoved { position, .. } => InputEvent::CursorMoved { x: position.x, y: position.y },
+            WindowEvent::MouseInput { button, state, .. } => InputEvent::MouseInput { button: *button, state: *state },
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => InputEvent::MouseWheel { x: *x, y: *y, lines: true },
+                MouseScrollDelta::PixelDelta(position) => {
+                    InputEvent::MouseWheel { x: position.x as f32, y: position.y as f32, lines: false }
+                }
+            },
+            WindowEvent::KeyboardInput { input, .. } => InputEvent::Key {
+                scancode: input.scancode,
+                key: input.virtual_keycode,
+                state: input.state,
+            },
+            WindowEvent::ModifiersChanged(modifiers) => InputEvent::Modifiers(*modifiers),
+            WindowEvent::ReceivedCharacter(character) => InputEvent::Character(*character),
+            _ => return None,
+        })
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta } => Some(InputEvent::MouseMotion { x: delta.0, y: delta.1 }),
+            _ => None,
+        }
+    }
+
+    // None for device events.
+    pub fn to_window_event(&self) -> Option<WindowEvent<'static>> {
+        // the events come from no real device,
+        // nothing in the viewer looks at the id anyway.
+        let device_id = unsafe { DeviceId::dummy() };
+        Some(match self {
+            InputEvent::Resized { width, height } => WindowEvent::Resized(PhysicalSize::new(*width, *height)),
+            InputEvent::Focused(focused) => WindowEvent::Focused(*focused),
+            #[allow(deprecated)]
+            InputEvent::CursorMoved { x, y } => WindowEvent::CursorMoved {
+                device_id,
+                position: PhysicalPosition::new(*x, *y),
+                modifiers: ModifiersState::empty(),
+            },
+            #[allow(deprecated)]
+            InputEvent::MouseInput { button, state } => WindowEvent::MouseInput {
+                device_id,
+                state: *state,
+                button: *button,
+                modifiers: ModifiersState::empty(),
+            },
+            #[allow(deprecated)]
+            InputEvent::MouseWheel { x, y, lines } => WindowEvent::MouseWheel {
+                device_id,
+                delta: if *lines {
+                    MouseScrollDelta::LineDelta(*x, *y)
+                } else {
+                    MouseScrollDelta::PixelDelta(PhysicalPosition::new(*x as f64, *y as f64))
+                },
+                phase: TouchPhase::Moved,
+                modifiers: ModifiersState::empty(),
+            },
+            #[allow(deprecated)]
+            InputEvent::Key { scancode, key, state } => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode: *scancode,
+                    state: *state,
+                    virtual_keycode: *key,
+                    modifiers: ModifiersState::empty(),
+                },
+                is_synthetic: false,
+            },
+            InputEvent::Modifiers(modifiers) => WindowEvent::ModifiersChanged(*modifiers),
+            InputEvent::Character(character) => WindowEvent::ReceivedCharacter(*character),
+            InputEvent::MouseMotion { .. } | InputEvent::FrameTime { .. } => return None,
+        })
+    }
+
+    // None for window events.
+    pub fn to_device_event(&self) -> Option<DeviceEvent> {
+        match self {
+            InputEvent::MouseMotion { x, y } => Some(DeviceEvent::MouseMotion { delta: (*x, *y) }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedEvent {
+    // the frame the event arrived before, replays go by this.
+    pub frame: u64,
+    // seconds since the recording started, for reading the file.
+    pub time: f64,
+    pub event: InputEvent,
+}
+
+pub struct InputRecorder {
+    start: Instant,
+    frame: u64,
+    events: Vec<RecordedEvent>,
+}
+
+impl Default for InputRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            frame: 0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record_window_event(&mut self, event: &WindowEvent) {
+        if let Some(event) = InputEvent::from_window_event(event) {
+            self.record(event);
+        }
+    }
+
+    pub fn record_device_event(&mut self, event: &DeviceEvent) {
+        if let Some(event) = InputEvent::from_device_event(event) {
+            self.record(event);
+        }
+    }
+
+    pub fn record(&mut self, event: InputEvent) {
+        self.events.push(RecordedEvent {
+            frame: self.frame,
+            time: self.start.elapsed().as_secs_f64(),
+            event,
+        });
+    }
+
+    // the time step the current frame moves by, call once per frame.
+    pub fn record_frame_time(&mut self, dt: Duration) {
+        self.record(InputEvent::FrameTime { seconds: dt.as_secs_f64() });
+    }
+
+    // call once per rendered frame.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn num_frames(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Unable to create input recording {:?}.", path))?;
+        let mut writer = BufWriter::new(file);
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, event)?;
+            writeln!(writer)?;
+        }
+        // the frame count, so the replay runs as long as the recording did.
+        writeln!(writer, "{{\"frames\":{}}}", self.frame)?;
+        writer.flush()
+            .with_context(|| format!("Unable to write input recording {:?}.", path))
+    }
+}
+
+// Last line of a recording.
+#[derive(Deserialize)]
+struct RecordingEnd {
+    frames: u64,
+}
+
+pub struct InputReplay {
+    events: Vec<RecordedEvent>,
+    num_frames: u64,
+    // index of the next event to hand out.
+    next: usize,
+    frame: u64,
+    // of the frame next_frame() handed out last.
+    frame_time: Option<Duration>,
+}
+
+impl InputReplay {
+    pub fn new(events: Vec<RecordedEvent>, num_frames: u64) -> Self {
+        let num_frames = events.last().map_or(num_frames, |event| num_frames.max(event.frame + 1));
+        Self {
+            events,
+            num_frames,
+            next: 0,
+            frame: 0,
+            frame_time: None,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Unable to open input recording {:?}.", path))?;
+
+        let mut events = Vec::new();
+        let mut num_frames = 0;
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Ok(end) = serde_json::from_str::<RecordingEnd>(&line) {
+                num_frames = end.frames;
+                continue;
+            }
+            let event: RecordedEvent = serde_json::from_str(&line)
+                .with_context(|| format!("Line {} of {:?} is no recorded event.", number + 1, path))?;
+            events.push(event);
+        }
+        // the events are written in order, but better safe than out of sync.
+        events.sort_by_key(|event| event.frame);
+        Ok(Self::new(events, num_frames))
+    }
+
+    // the events that arrived before the current frame, moves on to the next frame.
+    pub fn next_frame(&mut self) -> &[RecordedEvent] {
+        let start = self.next;
+        while self.next < self.events.len() && self.events[self.next].frame <= self.frame {
+            self.next += 1;
+        }
+        self.frame += 1;
+        let events = &self.events[start..self.next];
+        self.frame_time = events.iter().rev().find_map(|event| match event.event {
+            InputEvent::FrameTime { seconds } if seconds.is_finite() && seconds >= 0.0 => Some(Duration::from_secs_f64(seconds)),
+            _ => None,
+        });
+        events
+    }
+
+    // the time step the frame of the last next_frame() moved by in the
+    // recording, None for recordings made before they were recorded.
+    pub fn frame_time(&self) -> Option<Duration> {
+        self.frame_time
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn num_frames(&self) -> u64 {
+        self.num_frames
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.num_frames
+    }
+}
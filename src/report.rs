@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::model::{Bounds, ModelData};
+
+/*
+    Scene reports for asset QA: what a scene (or a single model) costs,
+    as json, so a pipeline can check the assets without opening a
+    window.
+
+        zhneeshyx report level.scene --out level_report.json
+
+    Per model and mesh: vertices, triangles, bounds (with the offsets of
+    the scene) and the material; per material its textures with their
+    size in pixels and the bytes they take on the gpu (rgba8, no mips,
+    like texture.rs uploads them), missing files are flagged. The totals
+    only count the meshes that are shown, hidden meshes are listed with
+    `hidden: true`.
+
+    The overdraw estimate rasterizes the shown meshes on the cpu, from
+    the front, the side and from above, each view fitted to the scene:
+    fragments drawn per covered pixel, counting the faces that face the
+    view (the renderer culls the others) at OVERDRAW_RESOLUTION. 1 is
+    no overdraw. It depends on the views, so it's meant to compare
+    versions of the same scene, not scenes with each other.
+
+    usage:
+        let mut report = SceneReport::new("level.scene");
+        report.add_model("rocks.obj", &data, &hidden, &offsets);
+        report.finish();
+        report.save("level_report.json")?;
+*/
+
+// width and height in pixels of the overdraw views.
+pub const OVERDRAW_RESOLUTION: u32 = 256;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BoundsReport {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub size: [f32; 3],
+}
+
+impl BoundsReport {
+    // None for nothing, an empty box has no sensible numbers.
+    fn new(bounds: &Bounds) -> Option<Self> {
+        (!bounds.is_empty()).then(|| Self { min: bounds.min, max: bounds.max, size: bounds.size() })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TextureReport {
+    // diffuse, lightmap or displacement.
+    pub kind: String,
+    pub path: PathBuf,
+    pub missing: bool,
+    pub width: u32,
+    pub height: u32,
+    pub gpu_bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MaterialReport {
+    pub name: String,
+    pub textures: Vec<TextureReport>,
+    pub gpu_bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MeshReport {
+    pub name: String,
+    pub vertices: usize,
+    pub triangles: usize,
+    pub material: Option<String>,
+    pub hidden: bool,
+    pub bounds: Option<BoundsReport>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ModelReport {
+    pub path: PathBuf,
+    pub vertices: usize,
+    pub triangles: usize,
+    pub bounds: Option<BoundsReport>,
+    pub meshes: Vec<MeshReport>,
+    pub materials: Vec<MaterialReport>,
+}
+
+// Fragments per covered pixel of one view, see above.
+#[derive(Serialize, Debug, Clone)]
+pub struct OverdrawView {
+    pub view: String,
+    pub overdraw: f32,
+    // part of the view the scene covers, 0..1.
+    pub coverage: f32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SceneTotals {
+    pub models: usize,
+    pub meshes: usize,
+    pub hidden_meshes: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    pub materials: usize,
+    pub textures: usize,
+    pub missing_textures: usize,
+    pub texture_gpu_bytes: u64,
+    // the average of the views.
+    pub overdraw: f32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SceneReport {
+    pub source: String,
+    pub totals: SceneTotals,
+    pub bounds: Option<BoundsReport>,
+    pub overdraw: Vec<OverdrawView>,
+    pub models: Vec<ModelReport>,
+    // the shown triangles in world space, for the overdraw.
+    #[serde(skip)]
+    triangles: Vec<[[f32; 3]; 3]>,
+    #[serde(skip)]
+    scene_bounds: Bounds,
+}
+
+impl SceneReport {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            totals: SceneTotals::default(),
+            bounds: None,
+            overdraw: Vec::new(),
+            models: Vec::new(),
+            triangles: Vec::new(),
+            scene_bounds: Bounds::empty(),
+        }
+    }
+
+    // `hidden` meshes and mesh `offsets` as in a scene file, see scene.rs.
+    pub fn add_model<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        data: &ModelData,
+        hidden: &BTreeSet<usize>,
+        offsets: &BTreeMap<usize, [f32; 3]>,
+    ) {
+        let materials: Vec<MaterialReport> = data.materials
+            .iter()
+            .map(|material| {
+                let mut textures = Vec::new();
+                let mut add = |kind: &str, path: Option<PathBuf>| {
+                    if let Some(path) = path {
+                        textures.push(texture_report(kind, path));
+                    }
+                };
+                add("diffuse", material.diffuse_path(&data.directory));
+                add("lightmap", material.lightmap_path(&data.directory));
+                add("displacement", material.displacement.as_ref().map(|displacement| data.directory.join(&displacement.texture)));
+                let gpu_bytes = textures.iter().map(|texture| texture.gpu_bytes).sum();
+                MaterialReport { name: material.name.clone(), textures, gpu_bytes }
+            })
+            .collect();
+
+        let mut model_bounds = Bounds::empty();
+        let mut meshes = Vec::with_capacity(data.meshes.len());
+        let (mut vertices, mut triangles) = (0, 0);
+        for (index, mesh) in data.meshes.iter().enumerate() {
+            let offset = offsets.get(&index).copied().unwrap_or([0.0; 3]);
+            let is_hidden = hidden.contains(&index);
+            let mut bounds = Bounds::empty();
+            if !mesh.bounds.is_empty() {
+                bounds.extend(add(mesh.bounds.min, offset));
+                bounds.extend(add(mesh.bounds.max, offset));
+            }
+            if is_hidden {
+                self.totals.hidden_meshes += 1;
+            } else {
+                vertices += mesh.vertices.len();
+                triangles += mesh.num_triangles();
+                model_bounds = model_bounds.union(&bounds);
+                self.triangles.extend(mesh.indices.chunks_exact(3).filter_map(|triangle| {
+                    let corner = |corner: usize| mesh.vertices.get(triangle[corner] as usize).map(|vertex| add(vertex.position, offset));
+                    Some([corner(0)?, corner(1)?, corner(2)?])
+                }));
+            }
+            meshes.push(MeshReport {
+                name: mesh.name.clone(),
+                vertices: mesh.vertices.len(),
+                triangles: mesh.num_triangles(),
+                material: data.materials.get(mesh.material).map(|material| material.name.clone()),
+                hidden: is_hidden,
+                bounds: BoundsReport::new(&bounds),
+            });
+        }
+
+        self.totals.models += 1;
+        self.totals.meshes += meshes.len();
+        self.totals.vertices += vertices;
+        self.totals.triangles += triangles;
+        self.totals.materials += materials.len();
+        for texture in materials.iter().flat_map(|material| &material.textures) {
+            self.totals.textures += 1;
+            self.totals.missing_textures += texture.missing as usize;
+            self.totals.texture_gpu_bytes += texture.gpu_bytes;
+        }
+        self.scene_bounds = self.scene_bounds.union(&model_bounds);
+        self.models.push(ModelReport {
+            path: path.as_ref().to_path_buf(),
+            vertices,
+            triangles,
+            bounds: BoundsReport::new(&model_bounds),
+            meshes,
+            materials,
+        });
+    }
+
+    // the scene bounds and the overdraw, once all models are added.
+    pub fn finish(&mut self) {
+        self.bounds = BoundsReport::new(&self.scene_bounds);
+        self.overdraw = OVERDRAW_VIEWS
+            .iter()
+            .map(|(name, right, up)| {
+                let (overdraw, coverage) = estimate_overdraw(&self.triangles, &self.scene_bounds, *right, *up, OVERDRAW_RESOLUTION);
+                OverdrawView { view: name.to_string(), overdraw, coverage }
+            })
+            .collect();
+        let covered: Vec<f32> = self.overdraw.iter().filter(|view| view.coverage > 0.0).map(|view| view.overdraw).collect();
+        self.totals.overdraw = if covered.is_empty() { 0.0 } else { covered.iter().sum::<f32>() / covered.len() as f32 };
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_json()?)
+            .with_context(|| format!("Unable to write scene report {:?}.", path))
+    }
+}
+
+// name, right and up of the views, each looks along up x right.
+const OVERDRAW_VIEWS: [(&str, [f32; 3], [f32; 3]); 3] = [
+    ("front", [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ("side", [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+    ("top", [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+];
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn texture_report(kind: &str, path: PathBuf) -> TextureReport {
+    // only the header is read, not the pixels.
+    let (width, height) = image::image_dimensions(&path).unwrap_or((0, 0));
+    TextureReport {
+        kind: kind.to_string(),
+        missing: !path.exists(),
+        path,
+        width,
+        height,
+        gpu_bytes: width as u64 * height as u64 * 4,
+    }
+}
+
+// Overdraw and coverage of `triangles` seen orthographically along
+// `up` x `right` (the camera's -z), in a `resolution` square fitted to `bounds`.
+pub fn estimate_overdraw(
+    triangles: &[[[f32; 3]; 3]],
+    bounds: &Bounds,
+    right: [f32; 3],
+    up: [f32; 3],
+    resolution: u32,
+) -> (f32, f32) {
+    if bounds.is_empty() || triangles.is_empty() || resolution == 0 {
+        return (0.0, 0.0);
+    }
+    // the extent of the box on the view plane, from its corners.
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for corner in 0..8 {
+        let point = [0, 1, 2].map(|axis| if corner & (1 << axis) == 0 { bounds.min[axis] } else { bounds.max[axis] });
+        let projected = [dot(point, right), dot(point, up)];
+        for axis in 0..2 {
+            min[axis] = min[axis].min(projected[axis]);
+            max[axis] = max[axis].max(projected[axis]);
+        }
+    }
+    // square pixels, the longer side fills the view.
+    let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+    let scale = resolution as f32 / extent;
+    let size = resolution as usize;
+    let mut fragments = vec![0u32; size * size];
+
+    for triangle in triangles {
+        // x to the right, y down like the image.
+        let [a, b, c] = triangle.map(|point| [
+            (dot(point, right) - min[0]) * scale,
+            (max[1] - dot(point, up)) * scale,
+        ]);
+        // counter clockwise on screen (front facing) is negative with y down.
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+        if area >= 0.0 {
+            continue;
+        }
+        let x0 = a[0].min(b[0]).min(c[0]).floor().max(0.0) as usize;
+        let x1 = (a[0].max(b[0]).max(c[0]).ceil() as usize).min(size);
+        let y0 = a[1].min(b[1]).min(c[1]).floor().max(0.0) as usize;
+        let y1 = (a[1].max(b[1]).max(c[1]).ceil() as usize).min(size);
+        // a pixel center right on an edge belongs to one of the two triangles
+        // sharing it: they run along it in opposite directions.
+        let inside = |p: [f32; 2], q: [f32; 2], x: f32, y: f32| {
+            let (dx, dy) = (q[0] - p[0], q[1] - p[1]);
+            let edge = dx * (y - p[1]) - dy * (x - p[0]);
+            edge < 0.0 || (edge == 0.0 && (dy > 0.0 || (dy == 0.0 && dx < 0.0)))
+        };
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                if inside(a, b, px, py) && inside(b, c, px, py) && inside(c, a, px, py) {
+                    fragments[y * size + x] += 1;
+                }
+            }
+        }
+    }
+
+    let covered = fragments.iter().filter(|count| **count > 0).count();
+    if covered == 0 {
+        return (0.0, 0.0);
+    }
+    let total: u64 = fragments.iter().map(|count| *count as u64).sum();
+    (total as f32 / covered as f32, covered as f32 / fragments.len() as f32)
+}
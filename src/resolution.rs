@@ -0,0 +1,391 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use wgpu::util::DeviceExt;
+
+/*
+    Dynamic resolution: the scene is drawn at a lower resolution when
+    the frames get too slow, and stretched over the window afterwards.
+
+    The offscreen target has the size of the window, the scene only
+    draws into the top left `scale` part of it (viewport + scissor), so
+    changing the scale doesn't allocate anything. The upscale pass runs
+    before the plugins, the ui and effects stay at full resolution.
+
+    With supersampling (Renderer::set_supersampling()) the target and the
+    other scene targets are bigger than the window, 1.5 or 2 times, and
+    the same pass shrinks the drawn part down again, averaging the texels
+    under every pixel instead of sampling once, so the edges and thin
+    lines come out smooth. The scale then is a part of that bigger size,
+    both work together. Without dynamic resolution the target is kept
+    at ResolutionSettings::fixed().
+
+    The scale follows the frame times: above the target it goes down,
+    well below it goes up again, between `min_scale` and `max_scale`.
+    wgpu can't time the gpu without timestamp queries, so these are the
+    cpu times of render_frame() (without the fps limiter), which include
+    waiting for the gpu as soon as it's the bottleneck.
+
+    usage:
+        renderer.enable_dynamic_resolution(ResolutionSettings::default());
+        ...
+        let scale = renderer.resolution_scale();
+        renderer.set_supersampling(2.0);
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UpscaleFilter {
+    Bilinear,
+    // bilinear plus contrast adaptive sharpening, like FSR's last pass.
+    Sharpen,
+}
+
+impl UpscaleFilter {
+    // value of the filter in the shader.
+    pub fn as_u32(self) -> u32 {
+        match self {
+            UpscaleFilter::Bilinear => 0,
+            UpscaleFilter::Sharpen => 1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ResolutionSettings {
+    pub min_scale: f32,
+    pub max_scale: f32,
+    // frame time the scale is adjusted for.
+    pub target_frame_time: Duration,
+    pub filter: UpscaleFilter,
+    // 0..1, for UpscaleFilter::Sharpen.
+    pub sharpness: f32,
+}
+
+impl ResolutionSettings {
+    // the whole target, never adjusted, for supersampling alone.
+    pub fn fixed() -> Self {
+        Self {
+            min_scale: 1.0,
+            max_scale: 1.0,
+            filter: UpscaleFilter::Bilinear,
+            sharpness: 0.0,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for ResolutionSettings {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.5,
+            max_scale: 1.0,
+            target_frame_time: Duration::from_secs_f64(1.0 / 60.0),
+            filter: UpscaleFilter::Sharpen,
+            sharpness: 0.5,
+        }
+    }
+}
+
+// supersampling factors above this cost more than they smooth.
+pub const MAX_SUPERSAMPLING: f32 = 2.0;
+
+// frames averaged before the scale changes.
+const WINDOW: usize = 15;
+// change of the scale per step.
+const STEP: f32 = 0.05;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniform {
+    uv_scale: [f32; 2],
+    texel: [f32; 2],
+    sharpness: f32,
+    filter: u32,
+    // one pixel of the window, in uv of the target.
+    footprint: [f32; 2],
+}
+
+pub struct DynamicResolution {
+    settings: ResolutionSettings,
+    scale: f32,
+    frame_times: VecDeque<Duration>,
+    width: u32,
+    height: u32,
+    texture: wgpu::Texture,
+    target: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl DynamicResolution {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, settings: ResolutionSettings) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("upscale_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Upscale Buffer"),
+            contents: bytemuck::cast_slice(&[UpscaleUniform {
+                uv_scale: [1.0; 2],
+                texel: [0.0; 2],
+                sharpness: 0.0,
+                filter: 0,
+                footprint: [0.0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Upscale Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let (texture, target) = create_target(device, config);
+        let bind_group = create_bind_group(device, &bind_group_layout, &uniform_buffer, &target, &sampler);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Upscale Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscale_shader.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Upscale Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            scale: settings.max_scale.clamp(0.1, 1.0),
+            settings,
+            frame_times: VecDeque::with_capacity(WINDOW),
+            width: config.width.max(1),
+            height: config.height.max(1),
+            texture,
+            target,
+            sampler,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.width = config.width.max(1);
+        self.height = config.height.max(1);
+        let (texture, target) = create_target(device, config);
+        self.texture = texture;
+        self.target = target;
+        self.bind_group = create_bind_group(device, &self.bind_group_layout, &self.uniform_buffer, &self.target, &self.sampler);
+    }
+
+    pub fn settings(&self) -> &ResolutionSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ResolutionSettings) {
+        self.settings = settings;
+        self.scale = self.scale.clamp(settings.min_scale, settings.max_scale);
+    }
+
+    // part of the window the scene is drawn at, 1 = full resolution.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // adapts the scale to the time the last frames took.
+    pub fn record_frame_time(&mut self, frame_time: Duration) {
+        if self.frame_times.len() == WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        if self.frame_times.len() < WINDOW {
+            return;
+        }
+
+        let average = self.frame_times.iter().sum::<Duration>() / WINDOW as u32;
+        let target = self.settings.target_frame_time;
+        let scale = if average > target.mul_f32(1.1) {
+            self.scale - STEP
+        } else if average < target.mul_f32(0.8) {
+            self.scale + STEP
+        } else {
+            return;
+        };
+        self.scale = scale.clamp(self.settings.min_scale, self.settings.max_scale);
+        // the next change waits for frames at the new scale.
+        self.frame_times.clear();
+    }
+
+    // pixels the scene is drawn at.
+    pub fn viewport(&self) -> (u32, u32) {
+        (
+            ((self.width as f32 * self.scale).round() as u32).clamp(1, self.width),
+            ((self.height as f32 * self.scale).round() as u32).clamp(1, self.height),
+        )
+    }
+
+    // the scene draws into this, instead of the surface texture.
+    pub fn target(&self) -> &wgpu::TextureView {
+        &self.target
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    // the size of the target.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // stretches (or shrinks) the drawn part of the target over `view`,
+    // which is `output` pixels big.
+    pub fn encode_upscale(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        output: (u32, u32),
+    ) {
+        let (width, height) = self.viewport();
+        let uv_scale = [width as f32 / self.width as f32, height as f32 / self.height as f32];
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[UpscaleUniform {
+            uv_scale,
+            texel: [1.0 / self.width as f32, 1.0 / self.height as f32],
+            sharpness: self.settings.sharpness.clamp(0.0, 1.0),
+            filter: self.settings.filter.as_u32(),
+            footprint: [uv_scale[0] / output.0.max(1) as f32, uv_scale[1] / output.1.max(1) as f32],
+        }]));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// `config` with the size the scene is drawn at with supersampling
+// `factor`, no bigger than textures can be (`max_dimension`).
+pub fn supersampled(config: &wgpu::SurfaceConfiguration, factor: f32, max_dimension: u32) -> wgpu::SurfaceConfiguration {
+    let scale = |size: u32| ((size.max(1) as f32 * factor).round() as u32).clamp(1, max_dimension.max(size));
+    wgpu::SurfaceConfiguration {
+        width: scale(config.width),
+        height: scale(config.height),
+        ..config.clone()
+    }
+}
+
+fn create_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Dynamic Resolution Target"),
+        size: wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        // COPY_SRC for the target dump, see targets.rs.
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    target: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("upscale_bind_group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(target),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
@@ -0,0 +1,205 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::model::{MeshData, Model, WHITE};
+use crate::picking::{self, Ray};
+use crate::vertex::MVertex;
+
+/*
+    Roads: a ribbon of triangles along a spline, laid onto the terrain.
+
+    The control points are the ones the user placed, the curve goes
+    through all of them (Catmull-Rom). It's sampled about every
+    `spacing` units, at every sample the two edges of the road are
+    dropped onto the ground below them (see ground_height()) and lifted
+    by `lift` so the road doesn't flicker with the terrain.
+
+    The texture runs along the road: u goes across from the left edge
+    (0) to the right edge (1), v is the distance along the curve in
+    units of `texture_length`, so the texture repeats instead of being
+    stretched over long roads.
+
+    Moving a point only needs mesh() again, the renderer swaps the
+    meshes of the road model with Renderer::set_model_meshes().
+
+    usage:
+        let mut road = Road::new(RoadSettings::default());
+        road.add_point([0.0, 0.0, 0.0]);
+        road.add_point([10.0, 0.0, 5.0]);
+        let models = renderer.models();
+        if let Some(mesh) = road.mesh(|x, z| road::ground_height(models, x, z, |_, _| true)) {
+            renderer.set_model_meshes(road_model, vec![mesh])?;
+        }
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RoadSettings {
+    pub width: f32,
+    // length of the road one repeat of the texture covers.
+    pub texture_length: f32,
+    // above the ground.
+    pub lift: f32,
+    // between two samples of the curve.
+    pub spacing: f32,
+}
+
+impl Default for RoadSettings {
+    fn default() -> Self {
+        Self {
+            width: 4.0,
+            texture_length: 4.0,
+            lift: 0.05,
+            spacing: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Road {
+    pub settings: RoadSettings,
+    points: Vec<[f32; 3]>,
+}
+
+impl Road {
+    pub fn new(settings: RoadSettings) -> Self {
+        Self { settings, points: Vec::new() }
+    }
+
+    pub fn points(&self) -> &[[f32; 3]] {
+        &self.points
+    }
+
+    // adds a point at the end of the road.
+    pub fn add_point(&mut self, point: [f32; 3]) {
+        self.points.push(point);
+    }
+
+    pub fn move_point(&mut self, index: usize, point: [f32; 3]) {
+        if let Some(old) = self.points.get_mut(index) {
+            *old = point;
+        }
+    }
+
+    pub fn remove_point(&mut self, index: usize) -> Option<[f32; 3]> {
+        if index < self.points.len() {
+            Some(self.points.remove(index))
+        } else {
+            None
+        }
+    }
+
+    // the control point closest to `position`, if it's within `distance` (on the ground plane).
+    pub fn closest_point(&self, position: [f32; 3], distance: f32) -> Option<usize> {
+        self.points.iter()
+            .map(|point| {
+                let (dx, dz) = (point[0] - position[0], point[2] - position[2]);
+                dx * dx + dz * dz
+            })
+            .enumerate()
+            .filter(|(_, squared)| *squared <= distance * distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+
+    // points along the curve, about `spacing` apart.
+    pub fn curve(&self) -> Vec<Vector3<f32>> {
+        let points: Vec<Vector3<f32>> = self.points.iter().map(|point| Vector3::from(*point)).collect();
+        if points.len() < 2 {
+            return points;
+        }
+        let mut curve = Vec::new();
+        for span in 0..points.len() - 1 {
+            // the ends repeat their point, the curve starts and ends in them.
+            let p0 = points[span.saturating_sub(1)];
+            let p1 = points[span];
+            let p2 = points[span + 1];
+            let p3 = points[(span + 2).min(points.len() - 1)];
+
+            let steps = ((p2 - p1).magnitude() / self.settings.spacing.max(0.01)).ceil().max(1.0) as usize;
+            for step in 0..steps {
+                curve.push(catmull_rom(p0, p1, p2, p3, step as f32 / steps as f32));
+            }
+        }
+        curve.push(points[points.len() - 1]);
+        curve
+    }
+
+    // the ribbon along the curve, None with less than two points.
+    // `ground(x, z)` is the height of the terrain, None where there is none.
+    pub fn mesh<F: Fn(f32, f32) -> Option<f32>>(&self, ground: F) -> Option<MeshData> {
+        let curve = self.curve();
+        if curve.len() < 2 {
+            return None;
+        }
+
+        let mut vertices = Vec::with_capacity(curve.len() * 2);
+        let mut distance = 0.0;
+        for (index, center) in curve.iter().enumerate() {
+            if index > 0 {
+                distance += (center - curve[index - 1]).magnitude();
+            }
+            // direction of the road here, flat, from the samples around it.
+            let before = curve[index.saturating_sub(1)];
+            let after = curve[(index + 1).min(curve.len() - 1)];
+            let mut tangent = after - before;
+            tangent.y = 0.0;
+            if tangent.magnitude2() < f32::EPSILON {
+                tangent = Vector3::unit_z();
+            }
+            let right = tangent.cross(Vector3::unit_y()).normalize() * (self.settings.width * 0.5);
+
+            let v = distance / self.settings.texture_length.max(0.01);
+            for (edge, u) in [(center - right, 0.0), (center + right, 1.0)] {
+                let height = ground(edge.x, edge.z).unwrap_or(edge.y);
+                vertices.push(MVertex {
+                    position: [edge.x, height + self.settings.lift, edge.z],
+                    uv: [u, v],
+                    norm: [0.0, 1.0, 0.0],
+                    color: WHITE,
+                    uv2: [u, v],
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                });
+            }
+        }
+
+        // two triangles between every two samples, counter clockwise seen from above.
+        let mut indices = Vec::with_capacity((curve.len() - 1) * 6);
+        for segment in 0..curve.len() as u32 - 1 {
+            let (left, right) = (segment * 2, segment * 2 + 1);
+            let (next_left, next_right) = (left + 2, right + 2);
+            indices.extend_from_slice(&[left, right, next_left, right, next_right, next_left]);
+        }
+
+        let mut mesh = MeshData::new("road".to_string(), vertices, indices, 0);
+        mesh.compute_normals();
+        mesh.compute_tangents();
+        Some(mesh)
+    }
+}
+
+fn catmull_rom(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>, t: f32) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p3 - p0 + p1 * 3.0 - p2 * 3.0) * t3)
+        * 0.5
+}
+
+// height of the highest surface at `x`/`z` of the meshes `filter(model, mesh)` accepts,
+// e.g. everything but the road itself.
+pub fn ground_height<F: Fn(usize, usize) -> bool>(models: &[Model], x: f32, z: f32, filter: F) -> Option<f32> {
+    // from above everything, straight down.
+    let top = models.iter()
+        .flat_map(|model| model.meshes.iter())
+        .filter(|mesh| !mesh.bounds.is_empty())
+        .map(|mesh| mesh.bounds.max[1] + mesh.offset[1])
+        .fold(f32::MIN, f32::max);
+    if top == f32::MIN {
+        return None;
+    }
+    let start = top + 1.0;
+    let ray = Ray::new(Vector3::new(x, start, z), -Vector3::unit_y());
+    picking::pick_filtered(models, &ray, filter).map(|hit| start - hit.distance)
+}
@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use crate::fetch;
+
+/*
+    Scene files: which models are loaded and how they are shown,
+    so a viewer session can be saved and opened again.
+
+    A small text format, one entry per line:
+
+        # comment
+        library props.matlib
+        model terrain01.obj
+        hidden 2 5
+
+        offset 0 1.5 0 0
+        material 3 1
+        library_material * rusty_metal
+        prefab crates.prefab 4 0 0
+
+    `model` adds a model, relative paths are relative to the folder
+    of the scene file. The lines after it belong to that model:
+    `hidden` lists the indices of the meshes that are hidden, `offset`
+    moves a mesh (index, then x y z) and `material` draws a mesh with
+    another material of the model (mesh index, material index).
+
+    `library` loads a material library (see matlib.rs), whose materials
+    `library_material` draws a mesh (index) or the whole model (*) with,
+    by name.
+
+    `prefab` places a prefab at an offset (see prefab.rs). Prefab files
+    are scene files themselves, only with models.
+
+    usage:
+        renderer.save_scene("viewer.scene")?;
+        ...
+        renderer.load_scene("viewer.scene")?;
+*/
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneModel {
+    pub path: PathBuf,
+    pub hidden_meshes: BTreeSet<usize>,
+    // per mesh index, meshes without an entry stay where they were loaded.
+    pub offsets: BTreeMap<usize, [f32; 3]>,
+    // per mesh index, the material in the model it's drawn with.
+    pub materials: BTreeMap<usize, usize>,
+    // per mesh index (None for the whole model), the material of a library drawn instead.
+    pub library_materials: BTreeMap<Option<usize>, String>,
+}
+
+impl SceneModel {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScenePrefab {
+    pub path: PathBuf,
+    pub offset: [f32; 3],
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    pub libraries: Vec<PathBuf>,
+    pub models: Vec<SceneModel>,
+    pub prefabs: Vec<ScenePrefab>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(fetch::local(path)?)
+            .with_context(|| format!("Unable to read scene {:?}.", path))?;
+        let mut scene = Self::parse(&text)
+            .with_context(|| format!("Unable to parse scene {:?}.", path))?;
+
+        // relative to the scene, which may be a url.
+        for library in &mut scene.libraries {
+            *library = fetch::resolve(path, library);
+        }
+        for model in &mut scene.models {
+            model.path = fetch::resolve(path, &model.path);
+        }
+        for prefab in &mut scene.prefabs {
+            prefab.path = fetch::resolve(path, &prefab.path);
+        }
+        Ok(scene)
+    }
+
+    // model paths inside the folder of the scene file are written relative to it.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut relative = self.clone();
+        for library in &mut relative.libraries {
+            if let Ok(stripped) = library.strip_prefix(directory) {
+                *library = stripped.to_path_buf();
+            }
+        }
+        for model in &mut relative.models {
+            if let Ok(stripped) = model.path.strip_prefix(directory) {
+                model.path = stripped.to_path_buf();
+            }
+        }
+        for prefab in &mut relative.prefabs {
+            if let Ok(stripped) = prefab.path.strip_prefix(directory) {
+                prefab.path = stripped.to_path_buf();
+            }
+        }
+        std::fs::write(path, relative.to_text())
+            .with_context(|| format!("Unable to write scene {:?}.", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut scene = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = match line.find(char::is_whitespace) {
+                Some(split) => (&line[..split], line[split..].trim()),
+                None => (line, ""),
+            };
+            match keyword {
+                "library" => {
+                    if rest.is_empty() {
+                        bail!("Line {}: library without a path.", number + 1);
+                    }
+                    scene.libraries.push(PathBuf::from(rest));
+                }
+                "model" => {
+                    if rest.is_empty() {
+                        bail!("Line {}: model without a path.", number + 1);
+                    }
+                    scene.models.push(SceneModel::new(rest));
+                }
+                "hidden" => {
+                    let model = scene.models.last_mut()
+                        .with_context(|| format!("Line {}: hidden before the first model.", number + 1))?;
+                    for word in rest.split_whitespace() {
+                        let mesh = word.parse()
+                            .with_context(|| format!("Line {}: {:?} is no mesh index.", number + 1, word))?;
+                        model.hidden_meshes.insert(mesh);
+                    }
+                }
+                "offset" => {
+                    let model = scene.models.last_mut()
+                        .with_context(|| format!("Line {}: offset before the first model.", number + 1))?;
+                    let (mesh, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    let mesh = mesh.parse()
+                        .with_context(|| format!("Line {}: {:?} is no mesh index.", number + 1, mesh))?;
+                    let offset = parse_numbers(rest, 3)
+                        .with_context(|| format!("Line {}: offset needs a mesh index and x y z.", number + 1))?;
+                    model.offsets.insert(mesh, [offset[0], offset[1], offset[2]]);
+                }
+                "material" => {
+                    let model = scene.models.last_mut()
+                        .with_context(|| format!("Line {}: material before the first model.", number + 1))?;
+                    let indices = rest.split_whitespace()
+                        .map(|word| word.parse::<usize>())
+                        .collect::<Result<Vec<usize>, _>>()
+                        .ok()
+                        .filter(|indices| indices.len() == 2)
+                        .with_context(|| format!("Line {}: material needs a mesh and a material index.", number + 1))?;
+                    model.materials.insert(indices[0], indices[1]);
+                }
+                "library_material" => {
+                    let model = scene.models.last_mut()
+                        .with_context(|| format!("Line {}: library_material before the first model.", number + 1))?;
+                    let (mesh, name) = rest.split_once(char::is_whitespace)
+                        .with_context(|| format!("Line {}: library_material needs a mesh index or * and a name.", number + 1))?;
+                    let mesh = match mesh {
+                        "*" => None,
+                        mesh => Some(mesh.parse()
+                            .with_context(|| format!("Line {}: {:?} is no mesh index.", number + 1, mesh))?),
+                    };
+                    model.library_materials.insert(mesh, name.trim().to_string());
+                }
+                "prefab" => {
+                    // the path may contain spaces, the offset is the last three words.
+                    let words: Vec<&str> = rest.rsplitn(4, char::is_whitespace).collect();
+                    if words.len() != 4 {
+                        bail!("Line {}: prefab needs a path and x y z.", number + 1);
+                    }
+                    let offset = parse_numbers(&format!("{} {} {}", words[2], words[1], words[0]), 3)
+                        .with_context(|| format!("Line {}: prefab needs a path and x y z.", number + 1))?;
+                    scene.prefabs.push(ScenePrefab {
+                        path: PathBuf::from(words[3].trim()),
+                        offset: [offset[0], offset[1], offset[2]],
+                    });
+                }
+                _ => bail!("Line {}: unknown entry {:?}.", number + 1, keyword),
+            }
+        }
+        Ok(scene)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# zhneeshyx scene\n");
+        for library in &self.libraries {
+            text.push_str(&format!("library {}\n", library.display()));
+        }
+        for model in &self.models {
+            text.push_str(&format!("model {}\n", model.path.display()));
+            if !model.hidden_meshes.is_empty() {
+                let meshes: Vec<String> = model.hidden_meshes.iter().map(|mesh| mesh.to_string()).collect();
+                text.push_str(&format!("hidden {}\n", meshes.join(" ")));
+            }
+            for (mesh, offset) in &model.offsets {
+                text.push_str(&format!("offset {} {} {} {}\n", mesh, offset[0], offset[1], offset[2]));
+            }
+            for (mesh, material) in &model.materials {
+                text.push_str(&format!("material {} {}\n", mesh, material));
+            }
+            for (mesh, name) in &model.library_materials {
+                match mesh {
+                    Some(mesh) => text.push_str(&format!("library_material {} {}\n", mesh, name)),
+                    None => text.push_str(&format!("library_material * {}\n", name)),
+                }
+            }
+        }
+        for prefab in &self.prefabs {
+            let offset = prefab.offset;
+            text.push_str(&format!("prefab {} {} {} {}\n", prefab.path.display(), offset[0], offset[1], offset[2]));
+        }
+        text
+    }
+}
+
+// exactly `count` numbers separated by whitespace.
+fn parse_numbers(text: &str, count: usize) -> Result<Vec<f32>> {
+    let numbers = text.split_whitespace()
+        .map(|word| word.parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()?;
+    if numbers.len() != count {
+        bail!("Expected {} numbers, got {}.", count, numbers.len());
+    }
+    Ok(numbers)
+}
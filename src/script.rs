@@ -0,0 +1,451 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cgmath::Point3;
+
+use crate::light::Light;
+use crate::renderer::Renderer;
+
+/*
+    Scripting: rhai scripts that set up scenes and drive small
+    behaviors, run from a file when the viewer starts or typed into the
+    console line by line.
+
+    Scripts can't hold on to the renderer, what they change is queued
+    (ScriptCommand) and applied when the script returns, in the order
+    it was called. What they read comes from a copy of the scene taken
+    before the script runs, so a script that moves a mesh still reads
+    the old offset until the next run. load_model() returns the index
+    the model will have.
+
+        scene       model_count(), mesh_count(model), mesh_name(model, mesh),
+                    mesh_offset(model, mesh), load_model(path), remove_model(model),
+                    move_mesh(model, mesh, x, y, z), show_mesh(model, mesh),
+                    hide_mesh(model, mesh)
+        camera      camera_eye(), camera_target(), look_at(ex, ey, ez, tx, ty, tz),
+                    set_camera(name)
+        materials   set_material(model, mesh, material), material_override(material),
+                    clear_material_override()
+        lights      light_count(), add_light(x, y, z, r, g, b), move_light(light, x, y, z),
+                    remove_light(light), flashlight(on)
+        viewer      background(r, g, b), fps_limit(fps), print(text)
+
+    A script file that defines `fn update(dt) { ... }` gets it called
+    every frame with the seconds since the last one, its global
+    variables live as long as the script.
+
+    Needs the `scripting` feature, without it running a script fails.
+
+    usage:
+        cargo run --features scripting -- view --script setup.rhai
+        ...
+        let mut scripts = Scripts::new();
+        scripts.run_file(&mut renderer, "setup.rhai")?;
+        scripts.run_line(&mut renderer, "look_at(0.0, 5.0, 10.0, 0.0, 0.0, 0.0)")?;
+        // every frame
+        scripts.update(&mut renderer, dt);
+*/
+
+// lines of output kept for the console.
+pub const OUTPUT_LINES: usize = 8;
+
+// A change a script made to the scene, applied after it returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    LoadModel(PathBuf),
+    RemoveModel(usize),
+    MoveMesh { model: usize, mesh: usize, offset: [f32; 3] },
+    SetMeshVisible { model: usize, mesh: usize, visible: bool },
+    SetMeshMaterial { model: usize, mesh: usize, material: usize },
+    MaterialOverride(Option<usize>),
+    LookAt { eye: [f32; 3], target: [f32; 3] },
+    SetCamera(String),
+    AddLight { position: [f32; 3], color: [f32; 3] },
+    MoveLight { light: usize, position: [f32; 3] },
+    RemoveLight(usize),
+    Flashlight(bool),
+    Background([f32; 3]),
+    FpsLimit(Option<f32>),
+}
+
+impl ScriptCommand {
+    pub fn apply(&self, renderer: &mut Renderer) -> Result<()> {
+        match self {
+            ScriptCommand::LoadModel(path) => {
+                renderer.load_model(path)?;
+            }
+            ScriptCommand::RemoveModel(model) => {
+                renderer.remove_model(*model)?;
+            }
+            ScriptCommand::MoveMesh { model, mesh, offset } => {
+                check_mesh(renderer, *model, *mesh)?;
+                renderer.set_mesh_offset(*model, *mesh, *offset);
+            }
+            ScriptCommand::SetMeshVisible { model, mesh, visible } => {
+                check_mesh(renderer, *model, *mesh)?;
+                renderer.set_mesh_visible(*model, *mesh, *visible);
+            }
+            ScriptCommand::SetMeshMaterial { model, mesh, material } => {
+                renderer.set_mesh_material(*model, *mesh, *material)?;
+            }
+            ScriptCommand::MaterialOverride(material) => renderer.set_material_override(*material),
+            ScriptCommand::LookAt { eye, target } => {
+                renderer.camera_mut().look_at(Point3::from(*eye), Point3::from(*target));
+            }
+            ScriptCommand::SetCamera(name) => renderer.set_active_camera(name)?,
+            ScriptCommand::AddLight { position, color } => {
+                renderer.add_light(Light::new(Point3::from(*position), *color));
+            }
+            ScriptCommand::MoveLight { light, position } => {
+                let mut moved = *renderer.lights().get(*light).context("No light with this index.")?;
+                moved.position = Point3::from(*position);
+                renderer.set_light(*light, moved);
+            }
+            ScriptCommand::RemoveLight(light) => {
+                renderer.remove_light(*light).context("No light with this index.")?;
+            }
+            ScriptCommand::Flashlight(on) => renderer.set_flashlight_enabled(*on),
+            ScriptCommand::Background([r, g, b]) => renderer.set_background(Some(wgpu::Color {
+                r: *r as f64,
+                g: *g as f64,
+                b: *b as f64,
+                a: 1.0,
+            })),
+            ScriptCommand::FpsLimit(fps) => renderer.set_fps_limit(*fps),
+        }
+        Ok(())
+    }
+}
+
+fn check_mesh(renderer: &Renderer, model: usize, mesh: usize) -> Result<()> {
+    renderer.models().get(model)
+        .context("No model with this index.")?
+        .meshes.get(mesh)
+        .context("No mesh with this index.")?;
+    Ok(())
+}
+
+// What scripts can read, copied from the renderer before they run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneInfo {
+    // name and offset of every mesh, by model.
+    pub models: Vec<Vec<(String, [f32; 3])>>,
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub lights: usize,
+}
+
+impl SceneInfo {
+    pub fn of(renderer: &Renderer) -> Self {
+        Self {
+            models: renderer.models().iter()
+                .map(|model| model.meshes.iter().map(|mesh| (mesh.name.clone(), mesh.offset)).collect())
+                .collect(),
+            eye: renderer.camera().eye().into(),
+            target: renderer.camera().target().into(),
+            lights: renderer.lights().len(),
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use std::cell::RefCell;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    use rhai::{Array, Dynamic, Engine, FLOAT, INT};
+
+    use super::{SceneInfo, ScriptCommand, OUTPUT_LINES};
+
+    // shared between the engine's functions and Scripts.
+    #[derive(Default)]
+    pub struct Shared {
+        pub commands: Vec<ScriptCommand>,
+        pub scene: SceneInfo,
+        // models load_model() was called for in this run.
+        pub loading: usize,
+        pub output: Vec<String>,
+    }
+
+    impl Shared {
+        pub fn print(&mut self, text: &str) {
+            self.output.extend(text.lines().map(|line| line.to_string()));
+            let excess = self.output.len().saturating_sub(OUTPUT_LINES);
+            self.output.drain(..excess);
+        }
+    }
+
+    fn index(value: INT) -> usize {
+        value.max(0) as usize
+    }
+
+    fn vector(values: [f32; 3]) -> Array {
+        values.iter().map(|value| Dynamic::from_float(*value as FLOAT)).collect()
+    }
+
+    pub fn create(shared: &Rc<RefCell<Shared>>) -> Engine {
+        let mut engine = Engine::new();
+
+        let print = shared.clone();
+        engine.on_print(move |text| print.borrow_mut().print(text));
+        let debug = shared.clone();
+        engine.on_debug(move |text, _, _| debug.borrow_mut().print(text));
+
+        // queues a command, `command` gets the arguments of the script function.
+        macro_rules! queue {
+            ($name:expr, |$($arg:ident: $ty:ty),*| $command:expr) => {{
+                let shared = shared.clone();
+                engine.register_fn($name, move |$($arg: $ty),*| shared.borrow_mut().commands.push($command));
+            }};
+        }
+
+        // scene
+        let scene = shared.clone();
+        engine.register_fn("model_count", move || {
+            let scene = scene.borrow();
+            (scene.scene.models.len() + scene.loading) as INT
+        });
+        let scene = shared.clone();
+        engine.register_fn("mesh_count", move |model: INT| {
+            scene.borrow().scene.models.get(index(model)).map_or(0, |meshes| meshes.len()) as INT
+        });
+        let scene = shared.clone();
+        engine.register_fn("mesh_name", move |model: INT, mesh: INT| {
+            scene.borrow().scene.models.get(index(model))
+                .and_then(|meshes| meshes.get(index(mesh)))
+                .map_or(String::new(), |(name, _)| name.clone())
+        });
+        let scene = shared.clone();
+        engine.register_fn("mesh_offset", move |model: INT, mesh: INT| {
+            let offset = scene.borrow().scene.models.get(index(model))
+                .and_then(|meshes| meshes.get(index(mesh)))
+                .map_or([0.0; 3], |(_, offset)| *offset);
+            vector(offset)
+        });
+        let load = shared.clone();
+        engine.register_fn("load_model", move |path: &str| {
+            let mut shared = load.borrow_mut();
+            shared.commands.push(ScriptCommand::LoadModel(PathBuf::from(path)));
+            shared.loading += 1;
+            (shared.scene.models.len() + shared.loading - 1) as INT
+        });
+        queue!("remove_model", |model: INT| ScriptCommand::RemoveModel(index(model)));
+        queue!("move_mesh", |model: INT, mesh: INT, x: FLOAT, y: FLOAT, z: FLOAT| ScriptCommand::MoveMesh {
+            model: index(model),
+            mesh: index(mesh),
+            offset: [x as f32, y as f32, z as f32],
+        });
+        queue!("show_mesh", |model: INT, mesh: INT| ScriptCommand::SetMeshVisible {
+            model: index(model),
+            mesh: index(mesh),
+            visible: true,
+        });
+        queue!("hide_mesh", |model: INT, mesh: INT| ScriptCommand::SetMeshVisible {
+            model: index(model),
+            mesh: index(mesh),
+            visible: false,
+        });
+
+        // camera
+        let camera = shared.clone();
+        engine.register_fn("camera_eye", move || vector(camera.borrow().scene.eye));
+        let camera = shared.clone();
+        engine.register_fn("camera_target", move || vector(camera.borrow().scene.target));
+        queue!("look_at", |ex: FLOAT, ey: FLOAT, ez: FLOAT, tx: FLOAT, ty: FLOAT, tz: FLOAT| ScriptCommand::LookAt {
+            eye: [ex as f32, ey as f32, ez as f32],
+            target: [tx as f32, ty as f32, tz as f32],
+        });
+        queue!("set_camera", |name: &str| ScriptCommand::SetCamera(name.to_string()));
+
+        // materials
+        queue!("set_material", |model: INT, mesh: INT, material: INT| ScriptCommand::SetMeshMaterial {
+            model: index(model),
+            mesh: index(mesh),
+            material: index(material),
+        });
+        queue!("material_override", |material: INT| ScriptCommand::MaterialOverride(Some(index(material))));
+        queue!("clear_material_override", | | ScriptCommand::MaterialOverride(None));
+
+        // lights
+        let lights = shared.clone();
+        engine.register_fn("light_count", move || lights.borrow().scene.lights as INT);
+        queue!("add_light", |x: FLOAT, y: FLOAT, z: FLOAT, r: FLOAT, g: FLOAT, b: FLOAT| ScriptCommand::AddLight {
+            position: [x as f32, y as f32, z as f32],
+            color: [r as f32, g as f32, b as f32],
+        });
+        queue!("move_light", |light: INT, x: FLOAT, y: FLOAT, z: FLOAT| ScriptCommand::MoveLight {
+            light: index(light),
+            position: [x as f32, y as f32, z as f32],
+        });
+        queue!("remove_light", |light: INT| ScriptCommand::RemoveLight(index(light)));
+        queue!("flashlight", |on: bool| ScriptCommand::Flashlight(on));
+
+        // viewer
+        queue!("background", |r: FLOAT, g: FLOAT, b: FLOAT| ScriptCommand::Background([r as f32, g as f32, b as f32]));
+        queue!("fps_limit", |fps: FLOAT| ScriptCommand::FpsLimit(if fps > 0.0 { Some(fps as f32) } else { None }));
+
+        engine
+    }
+}
+
+// A script file that stays loaded, for its update().
+#[cfg(feature = "scripting")]
+struct LoadedScript {
+    path: PathBuf,
+    ast: rhai::AST,
+    scope: rhai::Scope<'static>,
+    has_update: bool,
+}
+
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    engine: rhai::Engine,
+    #[cfg(feature = "scripting")]
+    shared: std::rc::Rc<std::cell::RefCell<engine::Shared>>,
+    #[cfg(feature = "scripting")]
+    loaded: Vec<LoadedScript>,
+    // variables of the console, kept from line to line.
+    #[cfg(feature = "scripting")]
+    console: rhai::Scope<'static>,
+}
+
+#[cfg(feature = "scripting")]
+impl Scripts {
+    pub fn new() -> Self {
+        let shared = Default::default();
+        Self {
+            engine: engine::create(&shared),
+            shared,
+            loaded: Vec::new(),
+            console: rhai::Scope::new(),
+        }
+    }
+
+    // runs the script at `path`, it stays loaded when it has an update().
+    pub fn run_file<P: AsRef<Path>>(&mut self, renderer: &mut Renderer, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read the script {:?}.", path))?;
+        let ast = self.engine.compile(&source)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Unable to compile the script {:?}.", path))?;
+
+        let mut scope = rhai::Scope::new();
+        self.begin(renderer);
+        let result = self.engine.run_ast_with_scope(&mut scope, &ast);
+        self.finish(renderer);
+        result.map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("The script {:?} failed.", path))?;
+
+        let has_update = ast.iter_functions().any(|function| function.name == "update" && function.params.len() == 1);
+        if has_update {
+            self.loaded.retain(|script| script.path != path);
+            self.loaded.push(LoadedScript { path: path.to_path_buf(), ast, scope, has_update });
+        }
+        Ok(())
+    }
+
+    // runs a line typed into the console, its output goes to output().
+    pub fn run_line(&mut self, renderer: &mut Renderer, line: &str) -> Result<()> {
+        self.shared.borrow_mut().print(&format!("> {}", line));
+        self.begin(renderer);
+        let result = self.engine.eval_with_scope::<rhai::Dynamic>(&mut self.console, line);
+        self.finish(renderer);
+        match result {
+            Ok(value) => {
+                // shows what expressions evaluate to, like a repl.
+                if !value.is_unit() {
+                    self.shared.borrow_mut().print(&value.to_string());
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.shared.borrow_mut().print(&e.to_string());
+                Err(anyhow::anyhow!("{}", e))
+            }
+        }
+    }
+
+    // calls update(dt) of the loaded scripts. A script that fails is unloaded.
+    pub fn update(&mut self, renderer: &mut Renderer, dt: f32) {
+        if self.loaded.is_empty() {
+            return;
+        }
+        self.begin(renderer);
+        let engine = &self.engine;
+        let shared = &self.shared;
+        self.loaded.retain_mut(|script| {
+            if !script.has_update {
+                return true;
+            }
+            match engine.call_fn::<rhai::Dynamic>(&mut script.scope, &script.ast, "update", (dt as rhai::FLOAT,)) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::error!("The script {:?} failed, it's stopped: {}", script.path, e);
+                    shared.borrow_mut().print(&e.to_string());
+                    false
+                }
+            }
+        });
+        self.finish(renderer);
+    }
+
+    // the last lines printed by scripts, and their errors.
+    pub fn output(&self) -> Vec<String> {
+        self.shared.borrow().output.clone()
+    }
+
+    // scripts with an update() that are running.
+    pub fn loaded(&self) -> Vec<&Path> {
+        self.loaded.iter().map(|script| script.path.as_path()).collect()
+    }
+
+    fn begin(&mut self, renderer: &Renderer) {
+        let mut shared = self.shared.borrow_mut();
+        shared.scene = SceneInfo::of(renderer);
+        shared.loading = 0;
+    }
+
+    fn finish(&mut self, renderer: &mut Renderer) {
+        let commands = std::mem::take(&mut self.shared.borrow_mut().commands);
+        for command in commands {
+            if let Err(e) = command.apply(renderer) {
+                log::warn!("Script command {:?} failed: {:?}", command, e);
+                self.shared.borrow_mut().print(&format!("{:?} failed: {}", command, e));
+            }
+        }
+    }
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// without the feature there's nothing to run scripts with.
+#[cfg(not(feature = "scripting"))]
+impl Scripts {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, _renderer: &mut Renderer, _path: P) -> Result<()> {
+        anyhow::bail!("Built without scripting, enable the scripting feature.")
+    }
+
+    pub fn run_line(&mut self, _renderer: &mut Renderer, _line: &str) -> Result<()> {
+        anyhow::bail!("Built without scripting, enable the scripting feature.")
+    }
+
+    pub fn update(&mut self, _renderer: &mut Renderer, _dt: f32) {}
+
+    pub fn output(&self) -> Vec<String> {
+        vec!["Built without scripting, enable the scripting feature.".to_string()]
+    }
+
+    pub fn loaded(&self) -> Vec<&Path> {
+        Vec::new()
+    }
+}
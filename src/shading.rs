@@ -0,0 +1,180 @@
+/*
+    Half rate shading, for integrated gpus.
+
+    With ShadingRate::Half the lighting (the flashlight, the point
+    lights and the sun with its shadow map) runs in a pass of its own
+    at half the resolution, into the light buffer: rgb is the light
+    multiplied into the surface, a the view depth of the fragment it
+    was shaded for, and a second texture holds the highlights added on
+    top. The scene pass at full resolution only looks the light up.
+
+    The upsample is depth aware: of the four light texels around a
+    pixel, the ones with about the same depth as the pixel count, so
+    the light of a surface doesn't bleed onto the one behind it.
+
+    usage:
+        let renderer = RendererBuilder::new().shading_rate(ShadingRate::Half).build(&event_loop)?;
+        ...
+        renderer.set_shading_rate(ShadingRate::Full);
+*/
+
+pub const LIGHT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// depth of the texels nothing was drawn into, far behind everything.
+pub const EMPTY_DEPTH: f64 = 60000.0;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ShadingRate {
+    #[default]
+    Full,
+    // lighting at half the width and height.
+    Half,
+}
+
+pub struct LightBuffer {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    // rgb: the specular highlights.
+    specular_texture: wgpu::Texture,
+    specular_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let (width, height) = half_size(config.width, config.height);
+        let texture = create_texture(device, "Light Buffer", width, height);
+        let specular_texture = create_texture(device, "Specular Buffer", width, height);
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture,
+            specular_view: specular_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            specular_texture,
+            width,
+            height,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn specular_view(&self) -> &wgpu::TextureView {
+        &self.specular_view
+    }
+
+    pub fn specular_texture(&self) -> &wgpu::Texture {
+        &self.specular_texture
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // the part of the buffer that matches the scene `viewport`, x, y, width
+    // and height (None: the whole window).
+    pub fn viewport(&self, viewport: Option<[u32; 4]>) -> [u32; 4] {
+        match viewport {
+            Some([x, y, width, height]) => {
+                let (x, y) = ((x / 2).min(self.width - 1), (y / 2).min(self.height - 1));
+                let (width, height) = half_size(width, height);
+                [x, y, width.min(self.width - x), height.min(self.height - y)]
+            }
+            None => [0, 0, self.width, self.height],
+        }
+    }
+}
+
+fn create_texture(device: &wgpu::Device, label: &str, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: LIGHT_FORMAT,
+        // COPY_SRC for the target dump, see targets.rs.
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+// 1x1 stand-in, bound while the light comes from the scene pass itself.
+pub fn placeholder_view(device: &wgpu::Device) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Light Buffer Placeholder"),
+        size: wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: LIGHT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// the scene pipeline with the `fs_light` fragment shader, drawing into the light
+// buffer and its specular texture.
+pub fn create_light_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Light Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_light",
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format: LIGHT_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: LIGHT_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+fn half_size(width: u32, height: u32) -> (u32, u32) {
+    (width.div_ceil(2).max(1), height.div_ceil(2).max(1))
+}
@@ -0,0 +1,331 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::cameras::CameraBinding;
+use crate::inflight::FramesInFlight;
+use crate::light::Lighting;
+use crate::math;
+use crate::model::Bounds;
+
+/*
+    The shadow map of a directional light (the sun), and fitting it to
+    the view.
+
+    A directional light has no position, its shadow map is an orthographic
+    projection along the light direction. It has to cover what the camera
+    sees, but no more, or every texel covers more of the ground and the
+    shadows get blocky. fit_directional() puts it around the camera's view
+    frustum (up to the far plane, which the automatic clip planes keep at
+    the end of the scene) and shrinks it to the scene where the scene is
+    smaller.
+
+    Fitted naively the shadow edges crawl (shimmer) while the camera
+    moves: the texels of the map land on other spots every frame. So
+    - the size of the map doesn't follow the rotation of the camera, it
+      covers a sphere around the frustum,
+    - its position in light space is snapped to whole texels.
+    Then the texels stay put on the ground while the camera moves.
+
+    The depth range covers the whole scene along the light, so objects
+    outside of the view still cast their shadows into it.
+
+    With Renderer::set_sun() the renderer fits it to the active camera
+    every frame (see Renderer::shadow_fit() and PluginContext) and draws
+    the depth of the scene as seen from the sun into the map, before the
+    scene pass: the scene's vertex shader with ShadowMap's camera, at the
+    tier of LodSettings::shadow, the light buffer and the map itself not
+    bound. The scene shader lights with the sun like with a point light
+    infinitely far away, and compares against the map through a
+    comparison sampler, 3x3 taps (PCF), so the shadow edges are soft.
+    Secondary views (captures, posters) use the map of the last frame.
+
+    usage:
+        let sun = Vector3::new(-0.3, -1.0, -0.2);
+        if let Some(fit) = shadow::fit_directional(sun, &camera, &scene_bounds, SHADOW_MAP_SIZE) {
+            // draw the shadow casters with fit.view_proj into the map
+        }
+
+        renderer.set_sun(Some(sun));
+        renderer.set_lighting(Lighting { shadows: false, ..Lighting::default() });
+*/
+
+// width and height in texels of the map the projection is fitted for.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// the casters are pushed back in the map by this much (in depth units and
+// by their slope towards the sun), or lit surfaces shadow themselves in
+// stripes (shadow acne).
+const DEPTH_BIAS_CONSTANT: i32 = 2;
+const DEPTH_BIAS_SLOPE: f32 = 2.0;
+// the depth range reaches this far (as a part of its length) past the
+// scene, so casters right at its edge aren't clipped.
+const DEPTH_MARGIN: f32 = 0.05;
+// the radius of the frustum's sphere is rounded up to steps of this, so
+// float noise doesn't change the size of the texels from frame to frame.
+const RADIUS_STEP: f32 = 1.0 / 16.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowFit {
+    // world -> light space.
+    pub view: Matrix4<f32>,
+    // light space -> clip space, orthographic.
+    pub projection: Matrix4<f32>,
+    pub view_proj: Matrix4<f32>,
+    // world units one texel of the map covers.
+    pub texel_size: f32,
+}
+
+// The projection of a light shining in `direction` for a `resolution` x
+// `resolution` map, fitted to `camera` and the `scene`. None without a
+// direction or when there is nothing to cover.
+pub fn fit_directional(direction: Vector3<f32>, camera: &Camera, scene: &Bounds, resolution: u32) -> Option<ShadowFit> {
+    if direction.magnitude2() <= f32::EPSILON || resolution == 0 {
+        return None;
+    }
+    let direction = direction.normalize();
+    // only turns, the position of the map is the projection's business,
+    // so the snapping happens in a space that doesn't move with the camera.
+    let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+    let view = math::look_at(Point3::origin(), Point3::from_vec(direction), up);
+    let to_light = |point: Vector3<f32>| (view * point.extend(1.0)).truncate();
+
+    // a sphere around the frustum has the same size however the camera turns.
+    let view_proj = camera.build_view_projection_matrix();
+    let mut corners = Vec::with_capacity(8);
+    for corner in 0..8 {
+        let x = if corner & 1 == 0 { -1.0 } else { 1.0 };
+        let y = if corner & 2 == 0 { -1.0 } else { 1.0 };
+        let depth = if corner & 4 == 0 { 0.0 } else { 1.0 };
+        corners.push(math::unproject(view_proj, x, y, depth)?);
+    }
+    let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner) / 8.0;
+    let radius = corners.iter().map(|corner| (corner - center).magnitude()).fold(0.0, f32::max);
+    let radius = (radius / RADIUS_STEP).ceil() * RADIUS_STEP;
+    let center = to_light(center);
+
+    let mut size = 2.0 * radius;
+    let (mut x, mut y) = (center.x, center.y);
+    // light space z, the light looks down -z.
+    let (mut top, mut bottom) = (center.z + radius, center.z - radius);
+    if !scene.is_empty() {
+        let (min, max) = light_space_box(to_light, scene);
+        // the longer side, so it doesn't change with the camera either.
+        size = size.min((max.x - min.x).max(max.y - min.y));
+        x = clamp_window(x, size, min.x, max.x);
+        y = clamp_window(y, size, min.y, max.y);
+        top = top.max(max.z);
+        bottom = bottom.min(min.z);
+    }
+    if size <= f32::EPSILON {
+        return None;
+    }
+
+    let texel_size = size / resolution as f32;
+    let x = (x / texel_size).round() * texel_size;
+    let y = (y / texel_size).round() * texel_size;
+    let margin = (top - bottom) * DEPTH_MARGIN;
+    let half = size * 0.5;
+    let projection = math::orthographic(x - half, x + half, y - half, y + half, -top - margin, -bottom + margin);
+    Some(ShadowFit { view, projection, view_proj: projection * view, texel_size })
+}
+
+// min and max corner of the light space box around `bounds`.
+fn light_space_box<F: Fn(Vector3<f32>) -> Vector3<f32>>(to_light: F, bounds: &Bounds) -> (Vector3<f32>, Vector3<f32>) {
+    let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+    for corner in 0..8 {
+        let pick = |axis: usize| if corner & (1 << axis) == 0 { bounds.min[axis] } else { bounds.max[axis] };
+        let point = to_light(Vector3::new(pick(0), pick(1), pick(2)));
+        for axis in 0..3 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    (min, max)
+}
+
+// the center of a `size` wide window around `center` that stays within
+// `min`..`max` as far as it can, centered on them when it's wider.
+fn clamp_window(center: f32, size: f32, min: f32, max: f32) -> f32 {
+    if max - min <= size {
+        (min + max) * 0.5
+    } else {
+        center.clamp(min + size * 0.5, max - size * 0.5)
+    }
+}
+
+// The sun and its shadow map in the layout of the shaders, binding 7 of
+// the spot light bind group.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    view_proj: [[f32; 4]; 4],
+    // xyz: towards the sun.
+    direction: [f32; 4],
+    // rgb: color of the sun, w: 1 if there is a sun.
+    color: [f32; 4],
+    // x: 1 if the map is drawn, y: width of a texel in uv.
+    params: [f32; 4],
+}
+
+impl ShadowUniform {
+    // `sun` is the direction the sun shines in, the map is only used with a `fit`.
+    pub fn new(sun: Option<Vector3<f32>>, lighting: &Lighting, fit: Option<&ShadowFit>) -> Self {
+        let sun = sun.filter(|direction| direction.magnitude2() > f32::EPSILON);
+        let towards = sun.map_or(Vector3::new(0.0, 1.0, 0.0), |direction| -direction.normalize());
+        let fit = fit.filter(|_| sun.is_some() && lighting.shadows);
+        Self {
+            view_proj: fit.map_or(Matrix4::from_scale(1.0), |fit| fit.view_proj).into(),
+            direction: towards.extend(0.0).into(),
+            color: [lighting.sun_color[0], lighting.sun_color[1], lighting.sun_color[2], sun.is_some() as u32 as f32],
+            params: [fit.is_some() as u32 as f32, 1.0 / SHADOW_MAP_SIZE as f32, 0.0, 0.0],
+        }
+    }
+}
+
+// The depth texture the sun's view is drawn into, what the scene shader
+// reads it with, and the camera it's drawn with.
+pub struct ShadowMap {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    buffer: wgpu::Buffer,
+    camera: CameraBinding,
+    // bound instead of the map while it's drawn.
+    placeholder: wgpu::TextureView,
+}
+
+impl ShadowMap {
+    // `frame` is the renderer's per frame uniform, the vertex shader animates with it.
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, frame: &wgpu::Buffer) -> Self {
+        let create_texture = |label: &str, size: u32, usage: wgpu::TextureUsages| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SHADOW_FORMAT,
+                usage,
+                view_formats: &[],
+            })
+        };
+        // read back by Renderer::dump_targets().
+        let texture = create_texture(
+            "Shadow Map",
+            SHADOW_MAP_SIZE,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let placeholder = create_texture("Shadow Map Placeholder", 1, wgpu::TextureUsages::TEXTURE_BINDING)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        // linear filtering compares the four texels around the sample and
+        // blends the results, on top of the taps of the shader.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Buffer"),
+            contents: bytemuck::cast_slice(&[ShadowUniform::new(None, &Lighting::default(), None)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera = CameraBinding::new(device, camera_layout, frame, "Sun");
+        Self { texture, view, sampler, buffer, camera, placeholder }
+    }
+
+    // the sun of this frame, the casters are drawn with `fit`.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        in_flight: &mut FramesInFlight,
+        sun: Option<Vector3<f32>>,
+        lighting: &Lighting,
+        fit: Option<&ShadowFit>,
+    ) {
+        in_flight.write(device, &self.buffer, 0, bytemuck::cast_slice(&[ShadowUniform::new(sun, lighting, fit)]));
+        if let Some(fit) = fit {
+            self.camera.upload_in_flight(device, in_flight, fit.view_proj);
+        }
+    }
+
+    // SHADOW_MAP_SIZE wide and high, in SHADOW_FORMAT.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn placeholder(&self) -> &wgpu::TextureView {
+        &self.placeholder
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    // group 1 of the shadow pass.
+    pub fn camera_bind_group(&self) -> &wgpu::BindGroup {
+        self.camera.bind_group()
+    }
+}
+
+// the scene pipeline with the `fs_shadow` fragment shader, only the
+// depth, into the shadow map. Same layout as the scene pipeline.
+pub fn create_shadow_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex_layouts: &[wgpu::VertexBufferLayout],
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: vertex_layouts,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_shadow",
+            targets: &[],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: SHADOW_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState {
+                constant: DEPTH_BIAS_CONSTANT,
+                slope_scale: DEPTH_BIAS_SLOPE,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
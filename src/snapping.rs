@@ -0,0 +1,124 @@
+use cgmath::Vector3;
+
+use crate::cameras::SceneNode;
+use crate::model::{Bounds, Model};
+use crate::picking::{self, Ray};
+
+/*
+    Snapping and placement helpers for moving things around the scene.
+
+    SnapSettings rounds positions to a grid and angles to steps of a
+    few degrees while something is dragged. A grid or an angle step of
+    0 turns that kind of snapping off. The scene only moves meshes by
+    their offset (see model.rs), so the viewer only snaps translations,
+    angles are for applications that rotate things of their own.
+
+    drop_distance() finds the ground under a node: rays go straight
+    down through the bottom of the node's bounds, from its center and
+    near its four corners, against every other mesh. The node rests on
+    the highest surface any of them hits. The rays start at the top of
+    the bounds, so a node that sank into the ground is lifted out.
+
+    usage:
+        let snap = SnapSettings { grid: 0.5, ..SnapSettings::default() };
+        renderer.set_mesh_offset(model, mesh, snap.position(dragged_to));
+
+        let node = SceneNode::Mesh(model, mesh);
+        if let Some(drop) = snapping::drop_distance(renderer.models(), node, |_, _| true) {
+            let offset = renderer.models()[model].meshes[mesh].offset;
+            renderer.set_mesh_offset(model, mesh, [offset[0], offset[1] - drop, offset[2]]);
+        }
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SnapSettings {
+    // size of a grid cell in world units, 0 is off.
+    pub grid: f32,
+    // in degrees, 0 is off.
+    pub angle: f32,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self { grid: 1.0, angle: 15.0 }
+    }
+}
+
+impl SnapSettings {
+    // the closest grid point.
+    pub fn position(&self, position: [f32; 3]) -> [f32; 3] {
+        if self.grid <= 0.0 {
+            return position;
+        }
+        let snap = |value: f32| (value / self.grid).round() * self.grid;
+        [snap(position[0]), snap(position[1]), snap(position[2])]
+    }
+
+    // `angle` in radians, rounded to the closest step.
+    pub fn angle(&self, angle: f32) -> f32 {
+        if self.angle <= 0.0 {
+            return angle;
+        }
+        let step = self.angle.to_radians();
+        (angle / step).round() * step
+    }
+}
+
+// bounds of the node where it is drawn, with the mesh offsets.
+fn world_bounds(models: &[Model], meshes: &[(usize, usize)]) -> Bounds {
+    let mut bounds = Bounds::empty();
+    for &(model, mesh) in meshes {
+        let mesh = &models[model].meshes[mesh];
+        if mesh.bounds.is_empty() {
+            continue;
+        }
+        let offset = Vector3::from(mesh.offset);
+        let min = Vector3::from(mesh.bounds.min) + offset;
+        let max = Vector3::from(mesh.bounds.max) + offset;
+        bounds = bounds.union(&Bounds { min: min.into(), max: max.into() });
+    }
+    bounds
+}
+
+// how far the node has to move down to rest on the ground, negative to
+// move up. None when there's nothing under it. Only meshes `filter(model, mesh)`
+// accepts count as ground, e.g. only visible ones.
+pub fn drop_distance<F: Fn(usize, usize) -> bool>(models: &[Model], node: SceneNode, filter: F) -> Option<f32> {
+    let meshes: Vec<(usize, usize)> = match node {
+        SceneNode::Model(model) => (0..models.get(model)?.meshes.len()).map(|mesh| (model, mesh)).collect(),
+        SceneNode::Mesh(model, mesh) => {
+            models.get(model)?.meshes.get(mesh)?;
+            vec![(model, mesh)]
+        }
+    };
+    let bounds = world_bounds(models, &meshes);
+    if bounds.is_empty() {
+        return None;
+    }
+
+    // slightly inside the corners, a ray along an edge of the ground would miss it.
+    let center = bounds.center();
+    let size = bounds.size();
+    let inset = 0.45;
+    let points = [
+        (center[0], center[2]),
+        (center[0] - size[0] * inset, center[2] - size[2] * inset),
+        (center[0] + size[0] * inset, center[2] - size[2] * inset),
+        (center[0] - size[0] * inset, center[2] + size[2] * inset),
+        (center[0] + size[0] * inset, center[2] + size[2] * inset),
+    ];
+
+    let height = size[1];
+    let mut drop: Option<f32> = None;
+    for &(x, z) in points.iter() {
+        let ray = Ray::new(Vector3::new(x, bounds.max[1], z), -Vector3::unit_y());
+        let hit = picking::pick_filtered(models, &ray, |model, mesh| {
+            !meshes.contains(&(model, mesh)) && filter(model, mesh)
+        });
+        if let Some(hit) = hit {
+            let distance = hit.distance - height;
+            drop = Some(drop.map_or(distance, |drop| drop.min(distance)));
+        }
+    }
+    drop
+}
@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::texture::Texture;
+
+/*
+    Splat maps: terrain that blends a few layer textures (road, dirt,
+    grass) by weights painted into a control texture.
+
+    The control texture has one channel per layer, red for the first,
+    green for the second and blue for the third. Where the weights add
+    up to less than 1 the material's own diffuse texture shows through,
+    so an unpainted control map leaves the terrain as it was. The layers
+    repeat `tiling` times across the control map, which covers the uv
+    range 0..1 of the mesh once.
+
+    Painting happens on a cpu copy of the control map: paint() blends
+    the brush into the pixels under it and remembers the rectangle it
+    touched, upload() sends only that rectangle to the gpu. Painting
+    a layer takes the same amount from the others, so the weights
+    never add up to more than 1.
+
+    usage:
+        let control = SplatMap::new(device, queue, 512, 512);
+        let splat = SplatLayers::new(control, [road, dirt, grass], 16.0);
+        renderer.set_splat(terrain, 0, Some(splat))?;
+        // while the mouse button is down
+        renderer.paint_splat(x, y, &Brush { layer: 2, radius: 0.02, opacity: 0.3 });
+        ...
+        renderer.splat(terrain, 0).unwrap().control.save("terrain.splat.png")?;
+*/
+
+// channels of the control texture.
+pub const LAYERS: usize = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Brush {
+    // 0 = red, 1 = green, 2 = blue channel of the control map.
+    pub layer: usize,
+    // in uv units, 0.5 covers half the control map.
+    pub radius: f32,
+    // how much of the layer a single dab adds in the center, 0..1.
+    pub opacity: f32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self { layer: 0, radius: 0.02, opacity: 0.25 }
+    }
+}
+
+pub struct SplatMap {
+    pixels: image::RgbaImage,
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    // pixels painted since the last upload, x0, y0, x1, y1 (exclusive).
+    dirty: Option<(u32, u32, u32, u32)>,
+}
+
+impl SplatMap {
+    // all weights 0, the diffuse texture shows everywhere.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) -> Self {
+        Self::from_pixels(device, queue, image::RgbaImage::new(width.max(1), height.max(1)))
+    }
+
+    // a control map saved with save().
+    pub fn load<P: AsRef<Path>>(device: &wgpu::Device, queue: &wgpu::Queue, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let pixels = image::open(path)
+            .with_context(|| format!("Unable to load splat map {:?}.", path))?
+            .to_rgba8();
+        Ok(Self::from_pixels(device, queue, pixels))
+    }
+
+    fn from_pixels(device: &wgpu::Device, queue: &wgpu::Queue, pixels: image::RgbaImage) -> Self {
+        // the weights are linear, no srgb.
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Splat Map"),
+            size: wgpu::Extent3d { width: pixels.width(), height: pixels.height(), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut map = Self {
+            dirty: Some((0, 0, pixels.width(), pixels.height())),
+            pixels,
+            texture,
+            view,
+            sampler,
+        };
+        map.upload(queue);
+        map
+    }
+
+    pub fn width(&self) -> u32 {
+        self.pixels.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.pixels.height()
+    }
+
+    // weights of the layers at `uv`, nearest pixel.
+    pub fn weights(&self, uv: [f32; 2]) -> [f32; LAYERS] {
+        let (x, y) = self.pixel_at(uv);
+        let pixel = self.pixels.get_pixel(x, y);
+        [pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0, pixel[2] as f32 / 255.0]
+    }
+
+    // blends a dab of the brush in at `uv`. The brush fades out towards its radius.
+    pub fn paint(&mut self, uv: [f32; 2], brush: &Brush) -> Result<()> {
+        if brush.layer >= LAYERS {
+            bail!("The splat map has {} layers, there's no layer {}.", LAYERS, brush.layer);
+        }
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        // the control map repeats like its sampler, uvs outside 0..1 wrap around.
+        let center_x = uv[0].rem_euclid(1.0) * width as f32;
+        let center_y = uv[1].rem_euclid(1.0) * height as f32;
+        let radius_x = (brush.radius * width as f32).max(0.5);
+        let radius_y = (brush.radius * height as f32).max(0.5);
+
+        let x0 = (center_x - radius_x).floor() as i64;
+        let x1 = (center_x + radius_x).ceil() as i64;
+        let y0 = (center_y - radius_y).floor() as i64;
+        let y1 = (center_y + radius_y).ceil() as i64;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let dx = (x as f32 + 0.5 - center_x) / radius_x;
+                let dy = (y as f32 + 0.5 - center_y) / radius_y;
+                let distance = (dx * dx + dy * dy).sqrt();
+                if distance >= 1.0 {
+                    continue;
+                }
+                // smooth edge.
+                let falloff = 1.0 - distance * distance * (3.0 - 2.0 * distance);
+                let strength = (brush.opacity * falloff).clamp(0.0, 1.0);
+
+                let (px, py) = (x.rem_euclid(width) as u32, y.rem_euclid(height) as u32);
+                let pixel = self.pixels.get_pixel_mut(px, py);
+                for channel in 0..LAYERS {
+                    let weight = pixel[channel] as f32 / 255.0;
+                    let weight = if channel == brush.layer {
+                        weight + (1.0 - weight) * strength
+                    } else {
+                        weight * (1.0 - strength)
+                    };
+                    pixel[channel] = (weight * 255.0).round() as u8;
+                }
+                self.mark_dirty(px, py);
+            }
+        }
+        Ok(())
+    }
+
+    // sends the painted pixels to the gpu.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        let (x0, y0, x1, y1) = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return,
+        };
+        let width = self.width();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: x0, y: y0, z: 0 },
+            },
+            &self.pixels,
+            wgpu::ImageDataLayout {
+                // rows of the whole image, starting at the first painted pixel.
+                offset: (y0 as u64 * width as u64 + x0 as u64) * 4,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(self.height()),
+            },
+            wgpu::Extent3d { width: x1 - x0, height: y1 - y0, depth_or_array_layers: 1 },
+        );
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.pixels.save(path)
+            .with_context(|| format!("Unable to save splat map {:?}.", path))
+    }
+
+    // bytes of the control texture on the gpu.
+    pub fn memory_size(&self) -> u64 {
+        self.width() as u64 * self.height() as u64 * 4
+    }
+
+    fn pixel_at(&self, uv: [f32; 2]) -> (u32, u32) {
+        let x = (uv[0].rem_euclid(1.0) * self.width() as f32) as u32;
+        let y = (uv[1].rem_euclid(1.0) * self.height() as f32) as u32;
+        (x.min(self.width() - 1), y.min(self.height() - 1))
+    }
+
+    fn mark_dirty(&mut self, x: u32, y: u32) {
+        self.dirty = Some(match self.dirty {
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)),
+            None => (x, y, x + 1, y + 1),
+        });
+    }
+}
+
+// What a material needs to be drawn as splatted terrain.
+pub struct SplatLayers {
+    pub control: SplatMap,
+    // in the order of the control channels, sampled with the diffuse sampler.
+    pub layers: [Texture; LAYERS],
+    // how often the layer textures repeat across the control map.
+    pub tiling: f32,
+}
+
+impl SplatLayers {
+    pub fn new(control: SplatMap, layers: [Texture; LAYERS], tiling: f32) -> Self {
+        Self { control, layers, tiling }
+    }
+
+    // bytes of the control map and the layer textures on the gpu.
+    pub fn memory_size(&self) -> u64 {
+        self.control.memory_size() + self.layers.iter().map(Texture::memory_size).sum::<u64>()
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use crate::events::FrameStats;
+use crate::locale::Strings;
+use crate::overlay::Overlay;
+use crate::pacing::FramePacing;
+
+/*
+    Frame statistics on the overlay.
+
+    The renderer counts what it submits every frame (see FrameStats):
+    draw calls, triangles, instances, skipped meshes and bind group
+    switches. StatsHud averages them over half a second, so the
+    numbers can be read, and writes them into its own overlay layer.
+
+    usage:
+        let mut hud = StatsHud::new();
+        // after every render_frame()
+        if hud.record(&renderer.frame_stats()) {
+            hud.draw(renderer.plugin_mut::<Overlay>().unwrap(), 10.0, 40.0);
+        }
+*/
+
+pub const OVERLAY_LAYER: &str = "stats";
+
+const TEXT_COLOR: [f32; 4] = [0.8, 1.0, 0.8, 1.0];
+const INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default)]
+pub struct StatsHud {
+    // sums since the last update.
+    frames: u32,
+    frame_time: Duration,
+    sum: FrameStats,
+    // averages shown right now.
+    shown: FrameStats,
+    fps: f32,
+    pacing: Option<FramePacing>,
+}
+
+impl StatsHud {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // adds the stats of a frame, true when the averages were updated.
+    pub fn record(&mut self, stats: &FrameStats) -> bool {
+        self.frames += 1;
+        self.frame_time += stats.frame_time;
+        self.sum.draw_calls += stats.draw_calls;
+        self.sum.triangles += stats.triangles;
+        self.sum.instances += stats.instances;
+        self.sum.culled += stats.culled;
+        self.sum.bind_group_switches += stats.bind_group_switches;
+        if self.frame_time < INTERVAL {
+            return false;
+        }
+
+        let frames = self.frames;
+        self.shown = FrameStats {
+            frame: stats.frame,
+            frame_time: self.frame_time / frames,
+            draw_calls: self.sum.draw_calls / frames,
+            triangles: self.sum.triangles / frames,
+            instances: self.sum.instances / frames,
+            culled: self.sum.culled / frames,
+            bind_group_switches: self.sum.bind_group_switches / frames,
+        };
+        self.fps = frames as f32 / self.frame_time.as_secs_f32();
+
+        self.frames = 0;
+        self.frame_time = Duration::ZERO;
+        self.sum = FrameStats::default();
+        true
+    }
+
+    // shown below the frame statistics, e.g. renderer.frame_pacing().
+    pub fn set_pacing(&mut self, pacing: FramePacing) {
+        self.pacing = Some(pacing);
+    }
+
+    // averaged over the last interval.
+    pub fn stats(&self) -> &FrameStats {
+        &self.shown
+    }
+
+    // the panel in the language of `strings`, see locale.rs.
+    pub fn text(&self, strings: &Strings) -> String {
+        let stats = &self.shown;
+        let ms = |duration: Duration| format!("{:.2}", duration.as_secs_f64() * 1000.0);
+        let count = |key: &str, count: String| strings.format(key, &[("count", &count)]);
+        let mut lines = vec![
+            strings.format("stats.fps", &[("fps", &format!("{:.0}", self.fps)), ("ms", &ms(stats.frame_time))]),
+            count("stats.draw_calls", stats.draw_calls.to_string()),
+            count("stats.triangles", stats.triangles.to_string()),
+            count("stats.instances", stats.instances.to_string()),
+            count("stats.culled", stats.culled.to_string()),
+            count("stats.bind_groups", stats.bind_group_switches.to_string()),
+        ];
+        if let Some(pacing) = &self.pacing {
+            let cap = match pacing.target {
+                Some(target) => strings.format("stats.fps_cap", &[("fps", &format!("{:.0}", 1.0 / target.as_secs_f64()))]),
+                None => strings.get("stats.uncapped").to_string(),
+            };
+            lines.push(strings.format("stats.pacing", &[
+                ("average", &ms(pacing.average)),
+                ("jitter", &ms(pacing.jitter)),
+                ("cap", &cap),
+            ]));
+            lines.push(strings.format("stats.missed", &[("missed", &pacing.missed.to_string()), ("idle", &ms(pacing.idle))]));
+        }
+        lines.join("\n")
+    }
+
+    // top left corner of the text at x/y pixels.
+    pub fn draw(&self, overlay: &mut Overlay, x: f32, y: f32) {
+        let text = self.text(overlay.strings());
+        let layer = overlay.layer(OVERLAY_LAYER);
+        layer.clear();
+        layer.text(x, y, &text, TEXT_COLOR);
+    }
+}
@@ -0,0 +1,226 @@
+use cgmath::{Matrix4, Vector3};
+use crate::camera::Camera;
+use crate::cameras::CameraBinding;
+use crate::inflight::FramesInFlight;
+use crate::math;
+
+/*
+    Stereo rendering: the scene drawn once per eye, for stereo displays,
+    cardboard style viewers and, later, headsets.
+
+    The eyes are the active camera moved half the interpupillary distance
+    (ipd) to the left and to the right, looking the same way (parallel
+    axes, like a headset's displays, no toe-in). Every eye has its own
+    camera uniform and bind group, the per frame uniform is shared.
+
+    Two layouts:
+    - SideBySide: the left eye in the left half of the window, the right
+      eye in the right half, each with the aspect of its half.
+    - ArrayTexture: every eye into a layer of a 2 layer texture of the
+      window's size and format, layer 0 is the left eye. That is what a
+      headset compositor takes. The window shows the normal, mono frame.
+
+    The light pass, the scene pass and the cross-section caps are drawn
+    per eye, the plugins (overlay, effects) once over the whole window
+    with the active camera.
+
+    usage:
+        renderer.enable_stereo(StereoSettings { ipd: 0.07, ..Default::default() });
+        ...
+        let layers = renderer.stereo_texture(); // with StereoLayout::ArrayTexture
+*/
+
+// average distance between the pupils of adults, in world units (meters).
+pub const DEFAULT_IPD: f32 = 0.064;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    pub const BOTH: [Eye; 2] = [Eye::Left, Eye::Right];
+
+    // the layer of the array texture.
+    pub fn index(self) -> usize {
+        match self {
+            Eye::Left => 0,
+            Eye::Right => 1,
+        }
+    }
+
+    // which way the eye is moved from the camera, along its right vector.
+    fn side(self) -> f32 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Eye::Left => "Left Eye",
+            Eye::Right => "Right Eye",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StereoLayout {
+    SideBySide,
+    ArrayTexture,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StereoSettings {
+    // distance between the eyes, in world units.
+    pub ipd: f32,
+    pub layout: StereoLayout,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self {
+            ipd: DEFAULT_IPD,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+}
+
+// world -> clip space of `eye`, for a view of `aspect` (width / height).
+pub fn eye_view_projection(camera: &Camera, eye: Eye, ipd: f32, aspect: f32) -> Matrix4<f32> {
+    let (znear, zfar) = camera.clip_planes();
+    // moving the eye right moves the world left in view space.
+    let offset = Matrix4::from_translation(Vector3::new(-eye.side() * ipd * 0.5, 0.0, 0.0));
+    math::perspective(camera.fovy(), aspect, znear, zfar) * offset * camera.view_matrix()
+}
+
+// x, y, width and height of where `eye` is drawn in a `width` x `height`
+// target with the side by side layout.
+pub fn side_by_side_viewport(eye: Eye, width: u32, height: u32) -> [u32; 4] {
+    let left = width / 2;
+    match eye {
+        Eye::Left => [0, 0, left.max(1), height],
+        Eye::Right => [left, 0, (width - left).max(1), height],
+    }
+}
+
+// the eyes' layers of StereoLayout::ArrayTexture.
+struct EyeLayers {
+    texture: wgpu::Texture,
+    views: Vec<wgpu::TextureView>,
+}
+
+impl EyeLayers {
+    fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Stereo Eye Layers"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: Eye::BOTH.len() as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            // TEXTURE_BINDING and COPY_SRC to hand the eyes on, to a compositor or readback.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let views = Eye::BOTH
+            .iter()
+            .map(|eye| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some(eye.label()),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: eye.index() as u32,
+                array_layer_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+        Self { texture, views }
+    }
+}
+
+pub struct Stereo {
+    settings: StereoSettings,
+    eyes: Vec<CameraBinding>,
+    layers: Option<EyeLayers>,
+    // of the window.
+    width: u32,
+    height: u32,
+}
+
+impl Stereo {
+    // `frame` is the renderer's per frame uniform, see frame.rs.
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        frame: &wgpu::Buffer,
+        config: &wgpu::SurfaceConfiguration,
+        settings: StereoSettings,
+    ) -> Self {
+        let mut stereo = Self {
+            settings,
+            eyes: Eye::BOTH.iter().map(|eye| CameraBinding::new(device, layout, frame, eye.label())).collect(),
+            layers: None,
+            width: config.width,
+            height: config.height,
+        };
+        stereo.resize(device, config);
+        stereo
+    }
+
+    pub fn settings(&self) -> &StereoSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, settings: StereoSettings) {
+        let layout_changed = settings.layout != self.settings.layout;
+        self.settings = settings;
+        if layout_changed {
+            self.resize(device, config);
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.width = config.width;
+        self.height = config.height;
+        self.layers = match self.settings.layout {
+            StereoLayout::ArrayTexture => Some(EyeLayers::new(device, config)),
+            StereoLayout::SideBySide => None,
+        };
+    }
+
+    // the eyes of `camera`, every frame.
+    pub fn update(&mut self, device: &wgpu::Device, in_flight: &mut FramesInFlight, camera: &Camera) {
+        for eye in Eye::BOTH {
+            let (width, height) = match self.settings.layout {
+                StereoLayout::SideBySide => {
+                    let viewport = side_by_side_viewport(eye, self.width, self.height);
+                    (viewport[2], viewport[3])
+                }
+                StereoLayout::ArrayTexture => (self.width, self.height),
+            };
+            let view_proj = eye_view_projection(camera, eye, self.settings.ipd, math::aspect_ratio(width, height));
+            self.eyes[eye.index()].upload_in_flight(device, in_flight, view_proj);
+        }
+    }
+
+    pub fn bind_group(&self, eye: Eye) -> &wgpu::BindGroup {
+        self.eyes[eye.index()].bind_group()
+    }
+
+    // the layer `eye` is drawn into, with StereoLayout::ArrayTexture.
+    pub fn layer(&self, eye: Eye) -> Option<&wgpu::TextureView> {
+        self.layers.as_ref().map(|layers| &layers.views[eye.index()])
+    }
+
+    // both layers, with StereoLayout::ArrayTexture.
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        self.layers.as_ref().map(|layers| &layers.texture)
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/*
+    Reader for STL files, ascii and binary.
+
+    STL only stores triangles (three corners + a facet normal),
+    there is no vertex sharing, no uvs and no materials.
+*/
+
+pub struct StlTriangle {
+    pub normal: [f32; 3],
+    pub vertices: [[f32; 3]; 3],
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<StlTriangle>> {
+    let bytes = std::fs::read(path.as_ref())
+        .with_context(|| format!("Unable to read {:?}.", path.as_ref()))?;
+    parse(&bytes).with_context(|| format!("Unable to parse stl file {:?}.", path.as_ref()))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Vec<StlTriangle>> {
+    // binary files may start with "solid" too, so check if the size
+    // matches the triangle count of the binary header first.
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() == 84 + count * 50 {
+            return parse_binary(&bytes[84..], count);
+        }
+    }
+
+    if bytes.starts_with(b"solid") {
+        parse_ascii(std::str::from_utf8(bytes)?)
+    } else {
+        bail!("Neither an ascii nor a binary stl file.")
+    }
+}
+
+fn parse_binary(body: &[u8], count: usize) -> Result<Vec<StlTriangle>> {
+    let read_vec3 = |data: &[u8]| -> [f32; 3] {
+        let mut v = [0.0; 3];
+        for (i, chunk) in data.chunks_exact(4).take(3).enumerate() {
+            v[i] = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        v
+    };
+
+    // per triangle: normal, 3 vertices, 2 byte attribute count.
+    Ok(body.chunks_exact(50)
+        .take(count)
+        .map(|triangle| StlTriangle {
+            normal: read_vec3(&triangle[0..12]),
+            vertices: [
+                read_vec3(&triangle[12..24]),
+                read_vec3(&triangle[24..36]),
+                read_vec3(&triangle[36..48]),
+            ],
+        })
+        .collect())
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<StlTriangle>> {
+    let mut triangles = Vec::new();
+    let mut normal = [0.0; 3];
+    let mut corners = Vec::with_capacity(3);
+
+    for line in text.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["facet", "normal", x, y, z] => {
+                normal = [x.parse()?, y.parse()?, z.parse()?];
+                corners.clear();
+            }
+            ["vertex", x, y, z] => corners.push([x.parse()?, y.parse()?, z.parse()?]),
+            ["endfacet"] => {
+                if corners.len() != 3 {
+                    bail!("Facet with {} vertices, expected 3.", corners.len());
+                }
+                triangles.push(StlTriangle {
+                    normal,
+                    vertices: [corners[0], corners[1], corners[2]],
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: StlTriangle = StlTriangle {
+        normal: [0.0, 0.0, 1.0],
+        vertices: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.5, -1.0]],
+    };
+
+    fn ascii(triangles: &[StlTriangle]) -> String {
+        let mut text = String::from("solid test\n");
+        for triangle in triangles {
+            let [x, y, z] = triangle.normal;
+            text += &format!("  facet normal {} {} {}\n    outer loop\n", x, y, z);
+            for [x, y, z] in triangle.vertices.iter() {
+                text += &format!("      vertex {} {} {}\n", x, y, z);
+            }
+            text += "    endloop\n  endfacet\n";
+        }
+        text + "endsolid test\n"
+    }
+
+    // the header may start with "solid" too, parse has to go by the size.
+    fn binary(triangles: &[StlTriangle]) -> Vec<u8> {
+        let mut bytes = b"solid but actually binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            let corners = std::iter::once(&triangle.normal).chain(triangle.vertices.iter());
+            for value in corners.flatten() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    fn assert_same(parsed: &[StlTriangle], expected: &[StlTriangle]) {
+        assert_eq!(parsed.len(), expected.len());
+        for (a, b) in parsed.iter().zip(expected) {
+            assert_eq!(a.normal, b.normal);
+            assert_eq!(a.vertices, b.vertices);
+        }
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let triangles = [TRIANGLE, TRIANGLE];
+        assert_same(&parse(ascii(&triangles).as_bytes()).unwrap(), &triangles);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let triangles = [TRIANGLE, TRIANGLE, TRIANGLE];
+        assert_same(&parse(&binary(&triangles)).unwrap(), &triangles);
+        assert!(parse(&binary(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        // neither "solid" nor a size matching the triangle count.
+        let mut truncated = binary(&[TRIANGLE]);
+        truncated[0] = b'x';
+        truncated.pop();
+        assert!(parse(&truncated).is_err());
+        assert!(parse(b"").is_err());
+
+        let two_corners = ascii(&[TRIANGLE]).replacen("      vertex 0 0 0\n", "", 1);
+        assert!(parse(two_corners.as_bytes()).is_err());
+        let not_a_number = ascii(&[TRIANGLE]).replace("2.5", "two");
+        assert!(parse(not_a_number.as_bytes()).is_err());
+    }
+}
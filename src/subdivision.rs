@@ -0,0 +1,308 @@
+use cgmath::{MetricSpace, Point3};
+use wgpu::util::DeviceExt;
+
+use crate::model;
+use crate::vertex::MVertex;
+
+/*
+    Terrain subdivision in a compute pass.
+
+    wgpu has no tessellation shaders, so the refinement is done the
+    old fashioned way: a compute shader (subdivision.wgsl) reads the
+    triangles of every mesh and writes a denser copy into a vertex
+    buffer, with more triangles the closer they are to the camera.
+    The new vertices are placed on curved PN triangles, which smooths
+    the silhouette of hills without needing a height map.
+
+    The refined buffers are only rebuilt once the camera moved
+    `refine_distance` away from where they were made, in between the
+    same buffers are drawn every frame.
+    Each mesh gets room for `max_vertices` refined vertices, that's the
+    memory traded for the detail. Triangles that don't fit any more
+    are dropped until the camera moves on.
+
+    Neighbouring triangles can end up with different levels,
+    which leaves small cracks along their shared edge.
+
+    usage:
+        renderer.enable_terrain_subdivision(terrain_index, SubdivisionSettings::default())?;
+*/
+
+// workgroup_size of the main entry point.
+const WORKGROUP_SIZE: u32 = 64;
+// most gpus can't dispatch more workgroups than this in one dimension.
+const MAX_WORKGROUPS: u32 = 65535;
+// 4^6 triangles out of one is plenty, more overflows the vertex counts.
+const MAX_LEVEL: u32 = 6;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SubdivisionSettings {
+    // triangles within `radius` of the camera are split into 4^max_level.
+    pub max_level: u32,
+    pub radius: f32,
+    // how far the camera moves before the buffers are refined again.
+    pub refine_distance: f32,
+    // refined vertices per mesh.
+    pub max_vertices: u32,
+}
+
+impl Default for SubdivisionSettings {
+    fn default() -> Self {
+        Self {
+            max_level: 3,
+            radius: 5.0,
+            refine_distance: 1.0,
+            // ~100mb per mesh
+            max_vertices: 1 << 21,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SubdivisionUniform {
+    camera: [f32; 4],
+    max_level: u32,
+    num_triangles: u32,
+    capacity: u32,
+    row_length: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+pub struct SubdividedMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    // draw_indirect() arguments, the vertex count is written by the shader.
+    pub args_buffer: wgpu::Buffer,
+    pub material: usize,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    num_triangles: u32,
+    // in vertices.
+    capacity: u32,
+}
+
+pub struct TerrainSubdivision {
+    model: usize,
+    settings: SubdivisionSettings,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    clamp_pipeline: wgpu::ComputePipeline,
+    meshes: Vec<SubdividedMesh>,
+    // camera position of the last refine, None until the first one.
+    refined_at: Option<Point3<f32>>,
+}
+
+impl TerrainSubdivision {
+    // `model` is the index of the model that gets refined.
+    pub fn new(device: &wgpu::Device, model: usize, settings: SubdivisionSettings) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("subdivision_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Subdivision Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("subdivision.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Subdivision Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Subdivision Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+        let clamp_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Subdivision Clamp Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "clamp_args",
+        });
+
+        Self {
+            model,
+            settings,
+            layout,
+            pipeline,
+            clamp_pipeline,
+            meshes: Vec::new(),
+            refined_at: None,
+        }
+    }
+
+    // after the models before it were removed or inserted.
+    pub fn set_model(&mut self, model: usize) {
+        self.model = model;
+    }
+
+    pub fn model(&self) -> usize {
+        self.model
+    }
+
+    pub fn settings(&self) -> SubdivisionSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, device: &wgpu::Device, model: &model::Model, settings: SubdivisionSettings) {
+        let resize = settings.max_vertices != self.settings.max_vertices;
+        self.settings = settings;
+        if resize {
+            self.build(device, model);
+        }
+        self.refined_at = None;
+    }
+
+    pub fn meshes(&self) -> &[SubdividedMesh] {
+        &self.meshes
+    }
+
+    // (re)creates the buffers for the meshes of `model`,
+    // has to be called whenever the model is replaced.
+    pub fn build(&mut self, device: &wgpu::Device, model: &model::Model) {
+        let vertex_size = std::mem::size_of::<MVertex>() as u64;
+        // a storage binding can't be bigger than this by default.
+        let limit = (wgpu::Limits::default().max_storage_buffer_binding_size as u64 / vertex_size) as u32;
+        let capacity = self.settings.max_vertices.min(limit).max(3);
+
+        self.meshes = model.meshes.iter()
+            .map(|mesh| {
+                let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{} Subdivision Uniform", mesh.name)),
+                    size: std::mem::size_of::<SubdivisionUniform>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{} Subdivided Vertex Buffer", mesh.name)),
+                    size: capacity as u64 * vertex_size,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                });
+                // nothing to draw until the first refine.
+                let args_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Subdivision Draw Args", mesh.name)),
+                    contents: bytemuck::cast_slice(&[DrawArgs {
+                        vertex_count: 0,
+                        instance_count: 1,
+                        first_vertex: 0,
+                        first_instance: 0,
+                    }]),
+                    usage: wgpu::BufferUsages::INDIRECT
+                        | wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_DST,
+                });
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("subdivision_bind_group"),
+                    layout: &self.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: uniform_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: mesh.vertex_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: mesh.index_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: vertex_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: args_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+
+                SubdividedMesh {
+                    vertex_buffer,
+                    args_buffer,
+                    material: mesh.material,
+                    uniform_buffer,
+                    bind_group,
+                    num_triangles: mesh.num_elements / 3,
+                    capacity,
+                }
+            })
+            .collect();
+        self.refined_at = None;
+    }
+
+    // true once the camera is `refine_distance` away from the last refine.
+    pub fn needs_refine(&self, eye: Point3<f32>) -> bool {
+        match self.refined_at {
+            Some(refined_at) => refined_at.distance(eye) >= self.settings.refine_distance,
+            None => true,
+        }
+    }
+
+    // records the compute passes that rebuild the refined vertex buffers around `eye`.
+    pub fn refine(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, eye: Point3<f32>) {
+        for mesh in &self.meshes {
+            let groups = mesh.num_triangles.div_ceil(WORKGROUP_SIZE);
+            let groups_x = groups.clamp(1, MAX_WORKGROUPS);
+            let groups_y = groups.div_ceil(groups_x);
+            let uniform = SubdivisionUniform {
+                camera: [eye.x, eye.y, eye.z, self.settings.radius.max(f32::EPSILON)],
+                max_level: self.settings.max_level.min(MAX_LEVEL),
+                num_triangles: mesh.num_triangles,
+                capacity: mesh.capacity,
+                row_length: groups_x * WORKGROUP_SIZE,
+            };
+            queue.write_buffer(&mesh.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            // the shader counts the vertices up from 0 again.
+            queue.write_buffer(&mesh.args_buffer, 0, bytemuck::cast_slice(&[0u32]));
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Subdivision Pass"),
+            });
+            compute_pass.set_bind_group(0, &mesh.bind_group, &[]);
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.dispatch_workgroups(groups_x, groups_y, 1);
+            compute_pass.set_pipeline(&self.clamp_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        self.refined_at = Some(eye);
+    }
+}
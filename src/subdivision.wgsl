@@ -0,0 +1,243 @@
+// Compute shader
+
+// Splits every triangle of a mesh into n*n smaller ones (n = 2^level),
+// the level depends on how close the triangle is to the camera.
+// New vertices are placed on the curved PN triangle through the corners
+// and their normals, that's what rounds off the silhouette. Flat
+// triangles stay flat.
+
+struct SubdivisionUniform {
+    // xyz: camera position in model space, w: radius with full detail.
+    camera: vec4<f32>,
+    max_level: u32,
+    num_triangles: u32,
+    // in vertices.
+    capacity: u32,
+    // triangles per row of workgroups, big meshes are dispatched in 2d.
+    row_length: u32,
+}
+
+// MVertex, 19 floats. vec3 would be padded to 16 bytes in a storage
+// buffer, so the vertices are read and written float by float.
+struct Vertices {
+    data: array<f32>,
+}
+
+struct Indices {
+    data: array<u32>,
+}
+
+// same layout as the arguments of draw_indirect().
+struct DrawArgs {
+    vertex_count: atomic<u32>,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: SubdivisionUniform;
+@group(0) @binding(1)
+var<storage, read> src_vertices: Vertices;
+@group(0) @binding(2)
+var<storage, read> src_indices: Indices;
+@group(0) @binding(3)
+var<storage, read_write> dst_vertices: Vertices;
+@group(0) @binding(4)
+var<storage, read_write> args: DrawArgs;
+
+struct Vertex {
+    position: vec3<f32>,
+    uv: vec2<f32>,
+    norm: vec3<f32>,
+    color: vec3<f32>,
+    uv2: vec2<f32>,
+    tangent: vec3<f32>,
+    bitangent: vec3<f32>,
+}
+
+// PN triangle control points, see "Curved PN Triangles" (Vlachos et al.)
+struct Patch {
+    b300: vec3<f32>,
+    b030: vec3<f32>,
+    b003: vec3<f32>,
+    b210: vec3<f32>,
+    b120: vec3<f32>,
+    b021: vec3<f32>,
+    b012: vec3<f32>,
+    b102: vec3<f32>,
+    b201: vec3<f32>,
+    b111: vec3<f32>,
+}
+
+const VERTEX_FLOATS: u32 = 19u;
+
+fn load_vertex(index: u32) -> Vertex {
+    let i = index * VERTEX_FLOATS;
+    var v: Vertex;
+    v.position = vec3<f32>(src_vertices.data[i], src_vertices.data[i + 1u], src_vertices.data[i + 2u]);
+    v.uv = vec2<f32>(src_vertices.data[i + 3u], src_vertices.data[i + 4u]);
+    v.norm = vec3<f32>(src_vertices.data[i + 5u], src_vertices.data[i + 6u], src_vertices.data[i + 7u]);
+    v.color = vec3<f32>(src_vertices.data[i + 8u], src_vertices.data[i + 9u], src_vertices.data[i + 10u]);
+    v.uv2 = vec2<f32>(src_vertices.data[i + 11u], src_vertices.data[i + 12u]);
+    v.tangent = vec3<f32>(src_vertices.data[i + 13u], src_vertices.data[i + 14u], src_vertices.data[i + 15u]);
+    v.bitangent = vec3<f32>(src_vertices.data[i + 16u], src_vertices.data[i + 17u], src_vertices.data[i + 18u]);
+    return v;
+}
+
+fn store_vertex(index: u32, v: Vertex) {
+    let i = index * VERTEX_FLOATS;
+    dst_vertices.data[i] = v.position.x;
+    dst_vertices.data[i + 1u] = v.position.y;
+    dst_vertices.data[i + 2u] = v.position.z;
+    dst_vertices.data[i + 3u] = v.uv.x;
+    dst_vertices.data[i + 4u] = v.uv.y;
+    dst_vertices.data[i + 5u] = v.norm.x;
+    dst_vertices.data[i + 6u] = v.norm.y;
+    dst_vertices.data[i + 7u] = v.norm.z;
+    dst_vertices.data[i + 8u] = v.color.x;
+    dst_vertices.data[i + 9u] = v.color.y;
+    dst_vertices.data[i + 10u] = v.color.z;
+    dst_vertices.data[i + 11u] = v.uv2.x;
+    dst_vertices.data[i + 12u] = v.uv2.y;
+    dst_vertices.data[i + 13u] = v.tangent.x;
+    dst_vertices.data[i + 14u] = v.tangent.y;
+    dst_vertices.data[i + 15u] = v.tangent.z;
+    dst_vertices.data[i + 16u] = v.bitangent.x;
+    dst_vertices.data[i + 17u] = v.bitangent.y;
+    dst_vertices.data[i + 18u] = v.bitangent.z;
+}
+
+// control point next to p_i on the edge towards p_j.
+fn edge_point(pi: vec3<f32>, pj: vec3<f32>, ni: vec3<f32>) -> vec3<f32> {
+    let w = dot(pj - pi, ni);
+    return (2.0 * pi + pj - w * ni) / 3.0;
+}
+
+fn make_patch(a: Vertex, b: Vertex, c: Vertex) -> Patch {
+    var p: Patch;
+    p.b300 = a.position;
+    p.b030 = b.position;
+    p.b003 = c.position;
+    p.b210 = edge_point(a.position, b.position, a.norm);
+    p.b120 = edge_point(b.position, a.position, b.norm);
+    p.b021 = edge_point(b.position, c.position, b.norm);
+    p.b012 = edge_point(c.position, b.position, c.norm);
+    p.b102 = edge_point(c.position, a.position, c.norm);
+    p.b201 = edge_point(a.position, c.position, a.norm);
+    let e = (p.b210 + p.b120 + p.b021 + p.b012 + p.b102 + p.b201) / 6.0;
+    let v = (a.position + b.position + c.position) / 3.0;
+    p.b111 = e + (e - v) / 2.0;
+    return p;
+}
+
+// barycentric weights (w, u, v) for the corners (a, b, c).
+fn interpolate(p: Patch, a: Vertex, b: Vertex, c: Vertex, bary: vec3<f32>) -> Vertex {
+    let w = bary.x;
+    let u = bary.y;
+    let v = bary.z;
+
+    var out: Vertex;
+    out.position = p.b300 * w * w * w + p.b030 * u * u * u + p.b003 * v * v * v
+        + p.b210 * 3.0 * w * w * u + p.b120 * 3.0 * w * u * u + p.b201 * 3.0 * w * w * v
+        + p.b021 * 3.0 * u * u * v + p.b102 * 3.0 * w * v * v + p.b012 * 3.0 * u * v * v
+        + p.b111 * 6.0 * w * u * v;
+    out.uv = a.uv * w + b.uv * u + c.uv * v;
+    out.norm = a.norm * w + b.norm * u + c.norm * v;
+    // meshes without normals have all zero normals, keep them that way.
+    if (dot(out.norm, out.norm) > 0.0) {
+        out.norm = normalize(out.norm);
+    }
+    out.color = a.color * w + b.color * u + c.color * v;
+    out.uv2 = a.uv2 * w + b.uv2 * u + c.uv2 * v;
+    // the uvs are interpolated linearly, so are their directions; the
+    // fragment shader normalizes them.
+    out.tangent = a.tangent * w + b.tangent * u + c.tangent * v;
+    out.bitangent = a.bitangent * w + b.bitangent * u + c.bitangent * v;
+    return out;
+}
+
+// one full detail step per radius, halving with every doubling of the distance.
+fn subdivision_level(center: vec3<f32>) -> u32 {
+    let distance = length(center - params.camera.xyz);
+    let steps = floor(log2(max(distance / params.camera.w, 1.0)));
+    return u32(max(f32(params.max_level) - steps, 0.0));
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let triangle = id.x + id.y * params.row_length;
+    if (triangle >= params.num_triangles) {
+        return;
+    }
+
+    let a = load_vertex(src_indices.data[triangle * 3u]);
+    let b = load_vertex(src_indices.data[triangle * 3u + 1u]);
+    let c = load_vertex(src_indices.data[triangle * 3u + 2u]);
+
+    let level = subdivision_level((a.position + b.position + c.position) / 3.0);
+    let n = 1u << level;
+    let count = n * n * 3u;
+    let first = atomicAdd(&args.vertex_count, count);
+
+    // out of room: fill what is left with degenerate triangles,
+    // clamp_args() cuts the vertex count back to the capacity afterwards.
+    if (first + count > params.capacity) {
+        var empty: Vertex;
+        var slot = first;
+        loop {
+            if (slot >= params.capacity) {
+                break;
+            }
+            store_vertex(slot, empty);
+            slot = slot + 1u;
+        }
+        return;
+    }
+
+    let tri = make_patch(a, b, c);
+    let step = 1.0 / f32(n);
+    var slot = first;
+
+    // rows along a -> b, columns along a -> c.
+    var i = 0u;
+    loop {
+        if (i >= n) {
+            break;
+        }
+        var j = 0u;
+        loop {
+            if (i + j >= n) {
+                break;
+            }
+            let p00 = vec3<f32>(1.0 - f32(i + j) * step, f32(i) * step, f32(j) * step);
+            let p10 = vec3<f32>(1.0 - f32(i + j + 1u) * step, f32(i + 1u) * step, f32(j) * step);
+            let p01 = vec3<f32>(1.0 - f32(i + j + 1u) * step, f32(i) * step, f32(j + 1u) * step);
+
+            store_vertex(slot, interpolate(tri, a, b, c, p00));
+            store_vertex(slot + 1u, interpolate(tri, a, b, c, p10));
+            store_vertex(slot + 2u, interpolate(tri, a, b, c, p01));
+            slot = slot + 3u;
+
+            // the upside down triangle between this one and the next.
+            if (i + j + 1u < n) {
+                let p11 = vec3<f32>(1.0 - f32(i + j + 2u) * step, f32(i + 1u) * step, f32(j + 1u) * step);
+                store_vertex(slot, interpolate(tri, a, b, c, p10));
+                store_vertex(slot + 1u, interpolate(tri, a, b, c, p11));
+                store_vertex(slot + 2u, interpolate(tri, a, b, c, p01));
+                slot = slot + 3u;
+            }
+            j = j + 1u;
+        }
+        i = i + 1u;
+    }
+}
+
+// runs once after main, so the draw never reads past the buffer.
+@compute @workgroup_size(1)
+fn clamp_args() {
+    let count = atomicLoad(&args.vertex_count);
+    if (count > params.capacity) {
+        atomicStore(&args.vertex_count, params.capacity - params.capacity % 3u);
+    }
+}
@@ -0,0 +1,430 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use cgmath::{Point3, Quaternion};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::model::{Bounds, Model};
+use crate::renderer::Renderer;
+
+/*
+    Syncing the camera and the scene between viewers on different
+    machines, e.g. a presenter walking reviewers through a model.
+
+    One viewer hosts, the others join it. They talk over tcp, every
+    message is one line of json (SyncMessage). The host passes what
+    one viewer sends on to the others.
+
+    Scene edits aren't sent one by one from the places that make them:
+    update() compares the scene with the one it saw in the last frame
+    (SceneSnapshot) and sends what changed, so undo / redo, the exploded
+    view and everything else that moves meshes is synced the same way.
+    Models are sent as paths, every viewer loads them from its own disk,
+    so the files have to be there on every machine (e.g. a shared
+    directory). Models that can't be loaded are replaced by empty ones,
+    so the indices of the models after them stay the same everywhere.
+
+    Joining viewers get the whole scene of the host first, the models
+    they had loaded themselves are replaced by it.
+
+    The camera is led by the host by default: it sends its camera
+    whenever it moves, the others follow it. A viewer that sets
+    lead_camera sends its camera instead. The whole camera goes over,
+    with its orientation (so a rolled or upside down one too) and field
+    of view, only the aspect stays the one of each window.
+
+    The sockets are only there with the `network` feature, without it
+    host() and join() fail.
+
+    usage:
+        cargo run --features network -- view --host 0.0.0.0:7878
+        cargo run --features network -- view --join 192.168.0.10:7878
+        ...
+        let mut sync = SyncSession::host("0.0.0.0:7878")?;
+        // every frame, before rendering
+        sync.update(&mut renderer);
+*/
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MeshState {
+    pub name: String,
+    pub offset: [f32; 3],
+    pub visible: bool,
+    pub material: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelState {
+    pub path: PathBuf,
+    pub meshes: Vec<MeshState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum SyncMessage {
+    // orientation as x, y, z, w of the quaternion, see Camera::orientation().
+    Camera { eye: [f32; 3], target: [f32; 3], orientation: [f32; 4], fovy: f32 },
+    // loaded at the end of the models.
+    AddModel { path: PathBuf },
+    RemoveModel { index: usize },
+    Mesh { model: usize, mesh: usize, state: MeshState },
+    // the whole scene, for viewers that just joined.
+    Scene { models: Vec<ModelState> },
+}
+
+impl SyncMessage {
+    // where `camera` is and how it's turned.
+    pub fn camera(camera: &Camera) -> Self {
+        SyncMessage::Camera {
+            eye: camera.eye().into(),
+            target: camera.target().into(),
+            orientation: camera.orientation().into(),
+            fovy: camera.fovy(),
+        }
+    }
+
+    pub fn apply(&self, renderer: &mut Renderer) -> Result<()> {
+        match self {
+            SyncMessage::Camera { eye, target, orientation, fovy } => {
+                let camera = renderer.camera_mut();
+                camera.look_at(Point3::from(*eye), Point3::from(*target));
+                // the roll and an upside down view, which look_at() leaves out.
+                camera.set_orientation(Quaternion::from(*orientation));
+                camera.set_fovy(*fovy);
+            }
+            SyncMessage::AddModel { path } => {
+                if let Err(e) = renderer.load_model(path) {
+                    // keeps the indices of the models after it.
+                    let empty = Model { meshes: Vec::new(), materials: Vec::new(), bounds: Bounds::empty(), instances: None };
+                    renderer.add_model(path, empty);
+                    return Err(e);
+                }
+            }
+            SyncMessage::RemoveModel { index } => {
+                renderer.remove_model(*index)?;
+            }
+            SyncMessage::Mesh { model, mesh, state } => {
+                renderer.models().get(*model)
+                    .and_then(|model| model.meshes.get(*mesh))
+                    .context("No mesh with this index.")?;
+                renderer.set_mesh_name(*model, *mesh, &state.name);
+                renderer.set_mesh_offset(*model, *mesh, state.offset);
+                renderer.set_mesh_visible(*model, *mesh, state.visible);
+                renderer.set_mesh_material(*model, *mesh, state.material)?;
+            }
+            SyncMessage::Scene { models } => {
+                let current = SceneSnapshot::of(renderer);
+                let wanted = SceneSnapshot { models: models.clone() };
+                for message in current.changes(&wanted) {
+                    if let Err(e) = message.apply(renderer) {
+                        log::warn!("Unable to sync {:?}: {:?}", message, e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The synced parts of the scene at one point in time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneSnapshot {
+    pub models: Vec<ModelState>,
+}
+
+impl SceneSnapshot {
+    pub fn of(renderer: &Renderer) -> Self {
+        let models = renderer.models().iter()
+            .enumerate()
+            .map(|(model_index, model)| ModelState {
+                path: renderer.model_path(model_index).map(|path| path.to_path_buf()).unwrap_or_default(),
+                meshes: model.meshes.iter()
+                    .enumerate()
+                    .map(|(mesh_index, mesh)| MeshState {
+                        name: mesh.name.clone(),
+                        offset: mesh.offset,
+                        visible: renderer.mesh_visible(model_index, mesh_index),
+                        material: mesh.material,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { models }
+    }
+
+    // the messages that turn this scene into `newer`.
+    pub fn changes(&self, newer: &SceneSnapshot) -> Vec<SyncMessage> {
+        let (old, new) = (&self.models, &newer.models);
+        let mut messages = Vec::new();
+
+        // the models up to the first one that isn't the same file stayed.
+        let kept = old.iter().zip(new.iter()).take_while(|(old, new)| old.path == new.path).count();
+        // models that stay after it too: some were taken out in between.
+        let removed = old.len().saturating_sub(new.len());
+        let only_removed = old[(kept + removed).min(old.len())..].iter()
+            .map(|model| &model.path)
+            .eq(new[kept..].iter().map(|model| &model.path));
+
+        // (old index, new index) of the models that are still there.
+        let mut same: Vec<(usize, usize)> = (0..kept).map(|index| (index, index)).collect();
+        if only_removed {
+            for _ in 0..removed {
+                messages.push(SyncMessage::RemoveModel { index: kept });
+            }
+            same.extend((kept..new.len()).map(|index| (index + removed, index)));
+        } else {
+            // anything else, e.g. a model put back by an undo: the rest is replaced.
+            for index in (kept..old.len()).rev() {
+                messages.push(SyncMessage::RemoveModel { index });
+            }
+            for (index, model) in new.iter().enumerate().skip(kept) {
+                messages.push(SyncMessage::AddModel { path: model.path.clone() });
+                for (mesh, state) in model.meshes.iter().enumerate() {
+                    messages.push(SyncMessage::Mesh { model: index, mesh, state: state.clone() });
+                }
+            }
+        }
+
+        for (old_index, new_index) in same {
+            let old_meshes = &old[old_index].meshes;
+            for (mesh, state) in new[new_index].meshes.iter().enumerate() {
+                if old_meshes.get(mesh) != Some(state) {
+                    messages.push(SyncMessage::Mesh { model: new_index, mesh, state: state.clone() });
+                }
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(feature = "network")]
+struct Peer {
+    stream: std::net::TcpStream,
+    // bytes of a line that hasn't fully arrived yet.
+    received: Vec<u8>,
+}
+
+#[cfg(feature = "network")]
+impl Peer {
+    fn new(stream: std::net::TcpStream) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, received: Vec::new() })
+    }
+
+    fn send(&mut self, line: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+        self.stream.write_all(line)
+    }
+
+    // the messages that arrived since the last call, Err once the connection is gone.
+    fn receive(&mut self) -> std::io::Result<Vec<SyncMessage>> {
+        use std::io::Read;
+        let mut buffer = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.received.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let mut messages = Vec::new();
+        while let Some(end) = self.received.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.received.drain(..=end).collect();
+            match serde_json::from_slice(&line) {
+                Ok(message) => messages.push(message),
+                Err(e) => log::warn!("Skipped a sync message that couldn't be read: {:?}", e),
+            }
+        }
+        Ok(messages)
+    }
+}
+
+pub struct SyncSession {
+    // sends its camera instead of following the others.
+    pub lead_camera: bool,
+    #[cfg(feature = "network")]
+    listener: Option<std::net::TcpListener>,
+    #[cfg(feature = "network")]
+    peers: Vec<Peer>,
+    // the scene and camera as they were last synced.
+    #[cfg(feature = "network")]
+    snapshot: Option<SceneSnapshot>,
+    #[cfg(feature = "network")]
+    camera: Option<SyncMessage>,
+}
+
+#[cfg(feature = "network")]
+impl SyncSession {
+    // waits for viewers to join at `address`, e.g. "0.0.0.0:7878".
+    pub fn host(address: &str) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(address)
+            .with_context(|| format!("Unable to host a sync session at {:?}.", address))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Hosting a sync session at {}", listener.local_addr()?);
+        Ok(Self {
+            lead_camera: true,
+            listener: Some(listener),
+            peers: Vec::new(),
+            snapshot: None,
+            camera: None,
+        })
+    }
+
+    // joins the session hosted at `address`.
+    pub fn join(address: &str) -> Result<Self> {
+        let stream = std::net::TcpStream::connect(address)
+            .with_context(|| format!("Unable to join the sync session at {:?}.", address))?;
+        log::info!("Joined the sync session at {}", address);
+        Ok(Self {
+            lead_camera: false,
+            listener: None,
+            peers: vec![Peer::new(stream)?],
+            snapshot: None,
+            camera: None,
+        })
+    }
+
+    // viewers connected to this one.
+    pub fn peers(&self) -> usize {
+        self.peers.len()
+    }
+
+    // sends what changed here, applies what changed elsewhere. Once per frame.
+    pub fn update(&mut self, renderer: &mut Renderer) {
+        self.accept(renderer);
+
+        let scene = SceneSnapshot::of(renderer);
+        if let Some(snapshot) = &self.snapshot {
+            for message in snapshot.changes(&scene) {
+                self.send(&message, None);
+            }
+        }
+        if self.lead_camera {
+            let camera = SyncMessage::camera(renderer.camera());
+            if self.camera.as_ref() != Some(&camera) {
+                self.send(&camera, None);
+                self.camera = Some(camera);
+            }
+        }
+
+        let mut peer = 0;
+        while peer < self.peers.len() {
+            match self.peers[peer].receive() {
+                Ok(messages) => {
+                    for message in messages {
+                        self.receive(renderer, &message, peer);
+                    }
+                    peer += 1;
+                }
+                Err(e) => {
+                    log::info!("A viewer left the sync session: {}", e);
+                    self.peers.remove(peer);
+                }
+            }
+        }
+        // the remote edits aren't sent back.
+        self.snapshot = Some(SceneSnapshot::of(renderer));
+    }
+
+    fn accept(&mut self, renderer: &Renderer) {
+        let listener = match &self.listener {
+            Some(listener) => listener,
+            None => return,
+        };
+        loop {
+            match listener.accept() {
+                Ok((stream, address)) => {
+                    let mut peer = match Peer::new(stream) {
+                        Ok(peer) => peer,
+                        Err(e) => {
+                            log::warn!("Unable to set up the connection to {}: {:?}", address, e);
+                            continue;
+                        }
+                    };
+                    // it starts out with the scene and the camera of the host.
+                    let welcome = [
+                        SyncMessage::Scene { models: SceneSnapshot::of(renderer).models },
+                        SyncMessage::camera(renderer.camera()),
+                    ];
+                    let sent = welcome.iter().try_for_each(|message| peer.send(&line(message)));
+                    match sent {
+                        Ok(()) => {
+                            log::info!("{} joined the sync session", address);
+                            self.peers.push(peer);
+                        }
+                        Err(e) => log::warn!("Unable to send the scene to {}: {:?}", address, e),
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Unable to accept a viewer: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self, renderer: &mut Renderer, message: &SyncMessage, from: usize) {
+        if let SyncMessage::Camera { .. } = message {
+            if self.lead_camera {
+                return;
+            }
+            self.camera = Some(message.clone());
+        }
+        if let Err(e) = message.apply(renderer) {
+            log::warn!("Unable to sync {:?}: {:?}", message, e);
+        }
+        // the host passes it on to the others.
+        if self.listener.is_some() {
+            self.send(message, Some(from));
+        }
+    }
+
+    // to every peer but `except`. Peers that can't take it are dropped.
+    fn send(&mut self, message: &SyncMessage, except: Option<usize>) {
+        let line = line(message);
+        let mut index = 0;
+        self.peers.retain_mut(|peer| {
+            let keep = Some(index) == except || match peer.send(&line) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::info!("A viewer left the sync session: {}", e);
+                    false
+                }
+            };
+            index += 1;
+            keep
+        });
+    }
+}
+
+// without the feature there's nobody to sync with.
+#[cfg(not(feature = "network"))]
+impl SyncSession {
+    pub fn host(_address: &str) -> Result<Self> {
+        anyhow::bail!("Built without networking, enable the network feature.")
+    }
+
+    pub fn join(_address: &str) -> Result<Self> {
+        anyhow::bail!("Built without networking, enable the network feature.")
+    }
+
+    pub fn peers(&self) -> usize {
+        0
+    }
+
+    pub fn update(&mut self, _renderer: &mut Renderer) {}
+}
+
+#[cfg(feature = "network")]
+fn line(message: &SyncMessage) -> Vec<u8> {
+    // serializing these can't fail, there are no maps with non-string keys.
+    let mut line = serde_json::to_vec(message).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+
+/*
+    Dumps the intermediate render targets of a frame into png files, to
+    see what each pass wrote without a gpu debugger.
+
+    The targets are the ones the renderer has: the frame itself, the
+    light buffer of ShadingRate::Half (light and view depth, see
+    shading.rs), the offscreen target of the dynamic resolution (see
+    resolution.rs) and the sun's shadow map (see shadow.rs). There is no
+    G-buffer, SSAO or bloom chain in the tree, when those come they go
+    into Renderer::dump_targets() too. Targets that are turned off are
+    skipped.
+
+    The frame is drawn again for the dump, offscreen like screenshot(),
+    so the targets are all of the same frame. They are read back while
+    the next frames are drawn (see readback.rs) and saved on the job pool.
+
+    usage:
+        renderer.dump_targets("targets", |_, written| match written {
+            Ok(paths) => println!("{:?}", paths),
+            Err(e) => log::error!("{:?}", e),
+        })?;
+*/
+
+// One channel of `texels` as grey, `far` and everything behind it white.
+// For the depths in the light buffer's alpha.
+pub fn depth_image(texels: &[[f32; 4]], width: u32, height: u32, far: f32) -> Result<image::GrayImage> {
+    let pixels = texels
+        .iter()
+        .map(|texel| ((texel[3] / far).clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    image::GrayImage::from_raw(width, height, pixels).context("Readback has the wrong size.")
+}
+
+// The farthest depth something was drawn at, EMPTY_DEPTH texels don't count.
+pub fn max_depth(texels: &[[f32; 4]], empty: f32) -> f32 {
+    texels
+        .iter()
+        .map(|texel| texel[3])
+        .filter(|depth| *depth < empty)
+        .fold(0.0, f32::max)
+}
+
+// A depth texture as grey, scaled to the farthest drawn texel, the
+// cleared ones (1.0) white. For the shadow map.
+pub fn depth_texture_image(depths: &[f32], width: u32, height: u32) -> Result<image::GrayImage> {
+    let far = depths.iter().copied().filter(|depth| *depth < 1.0).fold(0.0, f32::max).max(f32::EPSILON);
+    let pixels = depths
+        .iter()
+        .map(|depth| ((depth / far).clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    image::GrayImage::from_raw(width, height, pixels).context("Readback has the wrong size.")
+}
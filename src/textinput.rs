@@ -0,0 +1,190 @@
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, VirtualKeyCode, WindowEvent};
+use winit::window::Window;
+
+use crate::overlay::Overlay;
+
+/*
+    Text input on the overlay: a single line field, e.g. to rename a
+    mesh or to type a number.
+
+    While a field is open the application routes the window events to
+    it instead of its hotkeys, so typing "w" doesn't move the camera.
+    The text comes from ReceivedCharacter, which is what the platform
+    made of the keys, including dead keys and the characters an input
+    method (IME) composed. The keys themselves only edit: backspace,
+    delete, the arrows, home and end. Enter submits, Escape cancels
+    and the application goes back to navigating.
+
+    The IME window is placed at the caret with show(). The overlay
+    font only has the latin-1 characters, others show up as boxes but
+    are kept in the text.
+
+    usage:
+        let mut field = TextInput::new("name", "terrain");
+        field.show(renderer.window(), overlay.scale(), 10.0, 70.0);
+        // every window event while the field is open
+        match field.handle(&event) {
+            Some(TextInputEvent::Submitted(text)) => ...,
+            Some(TextInputEvent::Cancelled) => ...,
+            Some(TextInputEvent::Changed) => {
+                // the IME window follows the caret.
+                field.show(renderer.window(), overlay.scale(), 10.0, 70.0);
+                field.draw(overlay);
+            }
+            None => {}
+        }
+*/
+
+pub const OVERLAY_LAYER: &str = "text input";
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 0.6, 1.0];
+// size of a character of the overlay font, in ui pixels (see overlay.rs).
+const CHAR_WIDTH: f32 = 9.0;
+const CHAR_HEIGHT: f32 = 15.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextKind {
+    Text,
+    // digits, one '.' and a leading '-'.
+    Number,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextInputEvent {
+    // the text or the caret changed.
+    Changed,
+    Submitted(String),
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextInput {
+    label: String,
+    text: String,
+    // in characters, not bytes.
+    caret: usize,
+    kind: TextKind,
+    // where the field is drawn, set by show().
+    x: f32,
+    y: f32,
+}
+
+impl TextInput {
+    pub fn new(label: &str, text: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            text: text.to_string(),
+            caret: text.chars().count(),
+            kind: TextKind::Text,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    pub fn number(label: &str, value: f32) -> Self {
+        Self {
+            kind: TextKind::Number,
+            ..Self::new(label, &value.to_string())
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // None while the text isn't a number.
+    pub fn value(&self) -> Option<f32> {
+        self.text.trim().parse().ok()
+    }
+
+    // places the field at `x`, `y` (ui pixels) and the IME window below the caret,
+    // `scale` is Overlay::scale(). Call again when the caret moved.
+    pub fn show(&mut self, window: &Window, scale: f32, x: f32, y: f32) {
+        self.x = x;
+        self.y = y;
+        window.set_ime_position(PhysicalPosition::new(self.caret_x() * scale, (y + CHAR_HEIGHT) * scale));
+    }
+
+    pub fn handle(&mut self, event: &WindowEvent) -> Option<TextInputEvent> {
+        match event {
+            WindowEvent::ReceivedCharacter(c) => {
+                // backspace, enter and escape come as characters as well, the keys handle them.
+                if c.is_control() || !self.accepts(*c) {
+                    return None;
+                }
+                let index = self.byte_index(self.caret);
+                self.text.insert(index, *c);
+                self.caret += 1;
+                Some(TextInputEvent::Changed)
+            }
+            WindowEvent::KeyboardInput { input, .. } if input.state == ElementState::Pressed => {
+                let key = input.virtual_keycode?;
+                let length = self.text.chars().count();
+                match key {
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        return Some(TextInputEvent::Submitted(self.text.clone()));
+                    }
+                    VirtualKeyCode::Escape => return Some(TextInputEvent::Cancelled),
+                    VirtualKeyCode::Back if self.caret > 0 => {
+                        self.caret -= 1;
+                        let index = self.byte_index(self.caret);
+                        self.text.remove(index);
+                    }
+                    VirtualKeyCode::Delete if self.caret < length => {
+                        let index = self.byte_index(self.caret);
+                        self.text.remove(index);
+                    }
+                    VirtualKeyCode::Left if self.caret > 0 => self.caret -= 1,
+                    VirtualKeyCode::Right if self.caret < length => self.caret += 1,
+                    VirtualKeyCode::Home => self.caret = 0,
+                    VirtualKeyCode::End => self.caret = length,
+                    _ => return None,
+                }
+                Some(TextInputEvent::Changed)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, overlay: &mut Overlay) {
+        let layer = overlay.layer(OVERLAY_LAYER);
+        layer.clear();
+        let mut line = format!("{}: ", self.label);
+        for (index, c) in self.text.chars().enumerate() {
+            if index == self.caret {
+                line.push('|');
+            }
+            line.push(c);
+        }
+        if self.caret >= self.text.chars().count() {
+            line.push('|');
+        }
+        layer.text(self.x, self.y, &line, TEXT_COLOR);
+    }
+
+    // removes the field from the overlay.
+    pub fn hide(overlay: &mut Overlay) {
+        overlay.layer(OVERLAY_LAYER).clear();
+    }
+
+    fn accepts(&self, c: char) -> bool {
+        match self.kind {
+            TextKind::Text => true,
+            TextKind::Number => {
+                c.is_ascii_digit()
+                    || (c == '.' && !self.text.contains('.'))
+                    || (c == '-' && self.caret == 0 && !self.text.starts_with('-'))
+            }
+        }
+    }
+
+    fn byte_index(&self, caret: usize) -> usize {
+        self.text.char_indices().nth(caret).map_or(self.text.len(), |(index, _)| index)
+    }
+
+    // pixels from the left of the window to the caret.
+    fn caret_x(&self) -> f32 {
+        self.x + (self.label.chars().count() + 2 + self.caret) as f32 * CHAR_WIDTH
+    }
+}
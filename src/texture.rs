@@ -28,9 +28,66 @@ pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub size: wgpu::Extent3d,
 }
 
 impl Texture {
+    // of the scene's depth buffer, sampled by the lens flares as well (flare.rs).
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    // Depth buffer as big as the surface, with `sample_count` samples like
+    // the color target it's drawn with. Single sampled ones can be read
+    // by later passes too.
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // for shadow like lookups, comparing against the stored depth.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            size,
+        }
+    }
+
     pub fn load<P: AsRef<Path>>(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -55,13 +112,55 @@ impl Texture {
         Self::from_image(device, queue, &img, Some(label))
     }
 
+    // 1x1 texture of a single color.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        label: &str,
+    ) -> Self {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        Self::from_image(device, queue, &img, Some(label))
+            .expect("Unable to create color texture.")
+    }
+
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().unwrap();
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    // For textures that hold data instead of colors, like normal maps:
+    // sampled as they are, without the srgb to linear conversion.
+    pub fn from_image_linear(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    // `format` is one of the rgba8 ones.
+    fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        // jpegs and rgb pngs decode without alpha.
+        let converted;
+        let rgba = match img.as_rgba8() {
+            Some(rgba) => rgba,
+            None => {
+                converted = img.to_rgba8();
+                &converted
+            }
+        };
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -75,8 +174,9 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
         });
 
         queue.write_texture(
@@ -89,8 +189,8 @@ impl Texture {
             rgba,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: core::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: core::num::NonZeroU32::new(dimensions.1),
+                bytes_per_row: Some(4 * dimensions.0),
+                rows_per_image: Some(dimensions.1),
             },
             size,
         );
@@ -110,6 +210,12 @@ impl Texture {
             texture,
             view,
             sampler,
+            size,
         })
     }
+
+    // bytes the texture takes on the gpu (rgba8, no mipmaps).
+    pub fn memory_size(&self) -> u64 {
+        self.size.width as u64 * self.size.height as u64 * self.size.depth_or_array_layers as u64 * 4
+    }
 }
\ No newline at end of file
@@ -0,0 +1,235 @@
+use std::path::Path;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use anyhow::{Context, Result};
+
+use crate::camera;
+use crate::frame::FrameBuffer;
+use crate::model::{self, DrawModel};
+use crate::readback;
+use crate::renderer;
+use crate::vertex::{self, Vertex};
+
+/*
+    Renders models into small images without a window,
+    e.g. for asset browsers built on top of the crate.
+
+    The camera is fitted to the model bounds and the model is
+    shaded by a single key light in front of a neutral gray backdrop.
+*/
+
+const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+const BACKGROUND: wgpu::Color = wgpu::Color {
+    r: 0.18,
+    g: 0.18,
+    b: 0.18,
+    a: 1.0,
+};
+
+// direction (from the model center) the camera looks from.
+const VIEW_DIRECTION: [f32; 3] = [1.0, 0.8, 1.2];
+
+pub struct ThumbnailRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    // the camera bind group has the per frame uniform, it stays at time 0 here.
+    frame: FrameBuffer,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ThumbnailRenderer {
+    // Sets up a device without a surface.
+    // -> reuse the ThumbnailRenderer when rendering many thumbnails.
+    pub fn new() -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            },
+        )).context("No adapter found for offscreen rendering.")?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+                label: Some("Thumbnail Device"),
+            },
+            None,
+        ))?;
+
+        let texture_bind_group_layout = model::Material::bind_group_layout(&device);
+        let camera_bind_group_layout = camera::UniformBuffer::bind_group_layout(&device);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Thumbnail Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("thumbnail_shader.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Thumbnail Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = renderer::create_render_pipeline(
+            &device,
+            &pipeline_layout,
+            COLOR_FORMAT,
+            1,
+            Some(DEPTH_FORMAT),
+            &[vertex::MVertex::desc()],
+            &shader,
+        );
+
+        let frame = FrameBuffer::new(&device);
+
+        Ok(Self {
+            device,
+            queue,
+            texture_bind_group_layout,
+            camera_bind_group_layout,
+            frame,
+            pipeline,
+        })
+    }
+
+    pub fn render<P: AsRef<Path>>(&self, path: P, width: u32, height: u32) -> Result<image::RgbaImage> {
+        let model = model::Model::load(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            path,
+        )?;
+
+        let camera = fit_camera(&model.bounds, width as f32 / height as f32);
+
+        let mut camera_config = camera::UniformBuffer::new();
+        camera_config.update_view_proj(&camera);
+
+        let camera_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Thumbnail Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_config]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let camera_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.camera_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.frame.buffer().as_entire_binding(),
+                },
+            ],
+            label: Some("thumbnail_camera_bind_group"),
+        });
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let color_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Thumbnail Target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: COLOR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let depth_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Thumbnail Depth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Thumbnail Encoder"),
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Thumbnail Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(BACKGROUND),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(1, &camera_bind_group, &[]);
+
+            for mesh in &model.meshes {
+                if let Some(material) = model.materials.get(mesh.material) {
+                    render_pass.set_bind_group(0, &material.bind_group, &[]);
+                    render_pass.draw_mesh(mesh);
+                }
+            }
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        pollster::block_on(readback::read_texture_to_image(
+            &self.device,
+            &self.queue,
+            &color_texture,
+            COLOR_FORMAT,
+            width,
+            height,
+        ))
+        .context("Unable to read back the thumbnail.")
+    }
+}
+
+// Renders a single thumbnail, creating a ThumbnailRenderer for it.
+pub fn render_thumbnail<P: AsRef<Path>>(path: P, width: u32, height: u32) -> Result<image::RgbaImage> {
+    ThumbnailRenderer::new()?.render(path, width, height)
+}
+
+// Places the camera so the bounding sphere of the model fills the view.
+fn fit_camera(bounds: &model::Bounds, aspect: f32) -> camera::Camera {
+    let center: Point3<f32> = bounds.center().into();
+    let radius = bounds.radius().max(0.001);
+
+    let fovy = camera::Camera::looking_at(center, center, aspect).fovy().to_radians();
+    // the narrower of the two fields of view decides the distance.
+    let fovx = 2.0 * ((fovy * 0.5).tan() * aspect).atan();
+    let half_fov = 0.5 * fovy.min(fovx);
+    let distance = radius / half_fov.sin();
+
+    let direction = Vector3::from(VIEW_DIRECTION).normalize();
+    let mut camera = camera::Camera::looking_at(center + direction * distance, center, aspect);
+    camera.set_clip_planes((distance - radius * 1.1).max(distance * 0.01), distance + radius * 1.1);
+    camera
+}
@@ -0,0 +1,93 @@
+// Vertex shader
+
+//
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+}
+
+@group(1) @binding(0) //
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) norm: vec3<f32>,
+    @location(3) color: vec3<f32>,
+    @location(4) uv2: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) color: vec3<f32>,
+    @location(3) uv2: vec2<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+
+    var out: VertexOutput;
+
+    out.uv = model.uv;
+    out.color = model.color;
+    out.uv2 = model.uv2;
+    out.world_position = model.position;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var tex_diffuse: texture_2d<f32>;
+
+@group(0) @binding(1)
+var sampler_diffuse: sampler;
+
+struct MaterialUniform {
+    vertex_colors: u32,
+    lightmap: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> material: MaterialUniform;
+
+@group(0) @binding(3)
+var tex_lightmap: texture_2d<f32>;
+
+@group(0) @binding(4)
+var sampler_lightmap: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(tex_diffuse, sampler_diffuse, in.uv);
+    // same as in basic_shader.wgsl
+    if (material.vertex_colors == 1u) {
+        color = vec4<f32>(color.rgb * in.color, color.a);
+    }
+    if (material.vertex_colors == 2u) {
+        color = vec4<f32>(in.color, color.a);
+    }
+    let light = textureSample(tex_lightmap, sampler_lightmap, in.uv2).rgb;
+    if (material.lightmap == 1u) {
+        color = vec4<f32>(color.rgb * light, color.a);
+    }
+    if (material.lightmap == 2u) {
+        color = vec4<f32>(color.rgb + light, color.a);
+    }
+
+    // flat face normal from the screen space derivatives,
+    // so the thumbnail is shaded even if the mesh has no normals.
+    let normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+
+    // single key light from the upper front.
+    let light_dir = normalize(vec3<f32>(0.5, 1.0, 0.75));
+    let diffuse = abs(dot(normal, light_dir));
+
+    let ambient = 0.35;
+    return vec4<f32>(color.rgb * (ambient + (1.0 - ambient) * diffuse), color.a);
+}
@@ -0,0 +1,75 @@
+use cgmath::{Matrix4, Vector4};
+
+/*
+    Tiled rendering: images bigger than the gpu can draw at once, like
+    a 16k poster, drawn as a grid of tiles and put together on the cpu.
+
+    Every tile is drawn by its own camera, the one of the whole image
+    with the projection narrowed to the tile's part of it (a sub-frustum,
+    see Tile::projection()). So the tiles line up pixel for pixel and the
+    seams can't be seen, as long as nothing depends on the screen: the
+    plugins (the overlay, flares) aren't drawn, and neither is anything
+    that blurs across pixels.
+
+    The tiles are as big as the scene targets of the renderer (the window
+    times the supersampling), the last ones in a row or column are cut
+    off. All tiles are drawn one after the other into the same target,
+    each copied out before the next, and read back in the background;
+    the job pool puts them together once they're all there. The whole
+    image is kept in memory: 4 bytes a pixel, a gigabyte for 16k x 16k.
+
+    usage:
+        renderer.render_tiled(16384, 9216, |_, poster| {
+            if let Err(e) = poster.and_then(|poster| Ok(poster.save("poster.png")?)) {
+                log::error!("{:?}", e);
+            }
+        })?;
+*/
+
+// longest side of a tiled image.
+pub const MAX_TILED_SIZE: u32 = 32768;
+
+// part of a `width` x `height` image, in pixels from the top left.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    // Maps the clip space of the whole `width` x `height` image onto the
+    // tile: multiplied in front of its projection, the tile fills the
+    // clip space, drawn into a viewport of the tile's size.
+    pub fn projection(&self, width: u32, height: u32) -> Matrix4<f32> {
+        let scale_x = width as f32 / self.width as f32;
+        let scale_y = height as f32 / self.height as f32;
+        // center of the tile in the image's ndc, y points up there.
+        let center_x = (2 * self.x + self.width) as f32 / width as f32 - 1.0;
+        let center_y = 1.0 - (2 * self.y + self.height) as f32 / height as f32;
+        Matrix4::from_cols(
+            Vector4::new(scale_x, 0.0, 0.0, 0.0),
+            Vector4::new(0.0, scale_y, 0.0, 0.0),
+            Vector4::new(0.0, 0.0, 1.0, 0.0),
+            Vector4::new(-center_x * scale_x, -center_y * scale_y, 0.0, 1.0),
+        )
+    }
+}
+
+// The tiles of `tile_width` x `tile_height` covering the image, row by row.
+pub fn grid(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Vec<Tile> {
+    let (tile_width, tile_height) = (tile_width.max(1), tile_height.max(1));
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_height as usize) {
+        for x in (0..width).step_by(tile_width as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_width.min(width - x),
+                height: tile_height.min(height - y),
+            });
+        }
+    }
+    tiles
+}
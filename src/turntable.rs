@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+
+use crate::benchmark::CameraPath;
+use crate::camera::Camera;
+use crate::math;
+use crate::model::Bounds;
+
+/*
+    Turntable clips: the camera once around a model or scene, on the
+    orbit of the benchmark (see benchmark.rs), every step captured. For
+    asset catalogs, as an animated gif or a numbered png sequence.
+
+    The frames are drawn like posters (Renderer::render_tiled()), so
+    they can be bigger than the window, and without the plugins. The
+    last frame stops one step short of the first, looped the clip turns
+    on without a jump. Everything is kept in memory until it's saved.
+
+    `--turntable` does it without a window:
+
+        zhneeshyx --turntable chair.obj --seconds 4 --fps 25 --size 512x512 --out chair.gif
+
+    usage:
+        let mut turntable = Turntable::new(&scene_bounds, TurntableSettings::default());
+        while !turntable.is_finished() {
+            renderer.set_camera(turntable.camera());
+            renderer.render_frame()?;
+            let (width, height) = turntable.size();
+            renderer.render_tiled(width, height, move |_, frame| sender.send(frame))?;
+            // poll_completed_jobs() until the frame is there
+            turntable.record(receiver.recv()??);
+        }
+        turntable.save("chair.gif")?;
+*/
+
+// clear color of headless turntables, a neutral gray for catalogs.
+pub const BACKGROUND: wgpu::Color = wgpu::Color { r: 0.5, g: 0.5, b: 0.5, a: 1.0 };
+
+// gif quantization, 1 (best) to 30 (fastest). Turntables have few
+// colors that matter and many frames.
+const GIF_SPEED: i32 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TurntableSettings {
+    // for one turn.
+    pub seconds: f32,
+    pub fps: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for TurntableSettings {
+    fn default() -> Self {
+        // 25 fps, gif delays are in hundredths of a second.
+        Self { seconds: 4.0, fps: 25, width: 512, height: 512 }
+    }
+}
+
+pub struct Turntable {
+    settings: TurntableSettings,
+    path: CameraPath,
+    frames: Vec<RgbaImage>,
+}
+
+impl Turntable {
+    pub fn new(bounds: &Bounds, settings: TurntableSettings) -> Self {
+        Self { settings, path: CameraPath::orbit(bounds), frames: Vec::new() }
+    }
+
+    pub fn num_frames(&self) -> usize {
+        (self.settings.seconds * self.settings.fps as f32).round().max(1.0) as usize
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.settings.width, self.settings.height)
+    }
+
+    // where the camera is for the next frame.
+    pub fn camera(&self) -> Camera {
+        let t = self.frames.len() as f32 / self.num_frames() as f32;
+        let (eye, target) = self.path.at(t);
+        let mut camera = Camera::looking_at(eye, target, math::aspect_ratio(self.settings.width, self.settings.height));
+        camera.set_clip_planes(0.1, self.path.far_plane());
+        camera
+    }
+
+    pub fn record(&mut self, frame: RgbaImage) {
+        self.frames.push(frame);
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.frames.len() as f32 / self.num_frames() as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.len() >= self.num_frames()
+    }
+
+    // a .gif file, or anything else as a directory of frame0000.png, ...
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let gif = path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+        if gif {
+            self.save_gif(path)
+        } else {
+            self.save_frames(path)
+        }
+    }
+
+    fn save_gif(&self, path: &Path) -> Result<()> {
+        let (width, height) = self.size();
+        if width.max(height) > u16::MAX as u32 {
+            bail!("A gif is at most {} pixels wide and high, not {}x{}.", u16::MAX, width, height);
+        }
+        let file = File::create(path).with_context(|| format!("Unable to create {:?}.", path))?;
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let delay = Delay::from_numer_denom_ms(1000, self.settings.fps.max(1));
+        for frame in &self.frames {
+            encoder.encode_frame(Frame::from_parts(frame.clone(), 0, 0, delay))
+                .with_context(|| format!("Unable to write {:?}.", path))?;
+        }
+        Ok(())
+    }
+
+    fn save_frames(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Unable to create {:?}.", dir))?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let path = dir.join(format!("frame{:04}.png", index));
+            frame.save(&path).with_context(|| format!("Unable to save {:?}.", path))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::light::Light;
+use crate::renderer::{RemovedModel, Renderer};
+
+/*
+    Undo / redo for edits of the scene.
+
+    Every edit is a command that knows how to do and undo itself on the
+    renderer. UndoStack::run() applies a command and remembers it,
+    undo() and redo() walk back and forth. A new edit after an undo
+    drops what could have been redone.
+
+    Commands hold the state from before the edit (the old offset, the
+    old light, a removed model with its gpu buffers), so undoing never
+    has to load anything again. Applications can add their own edits
+    by implementing EditCommand.
+
+    usage:
+        let mut undo = UndoStack::new(100);
+        undo.run(&mut renderer, Box::new(MoveMesh::new(&renderer, 0, 2, [1.0, 0.0, 0.0])))?;
+        undo.undo(&mut renderer)?;
+        undo.redo(&mut renderer)?;
+*/
+
+pub trait EditCommand {
+    // what the edit does, e.g. for a menu entry "Undo Move Mesh".
+    fn name(&self) -> &str;
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()>;
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()>;
+}
+
+pub struct UndoStack {
+    done: VecDeque<Box<dyn EditCommand>>,
+    undone: Vec<Box<dyn EditCommand>>,
+    // edits kept, the oldest are forgotten first.
+    limit: usize,
+}
+
+impl UndoStack {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            done: VecDeque::new(),
+            undone: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    // applies `command`, it isn't kept when it fails.
+    pub fn run(&mut self, renderer: &mut Renderer, mut command: Box<dyn EditCommand>) -> Result<()> {
+        command.apply(renderer)?;
+        self.push(command);
+        Ok(())
+    }
+
+    // false when there was nothing to undo.
+    pub fn undo(&mut self, renderer: &mut Renderer) -> Result<bool> {
+        self.undo_with(|command| command.revert(renderer))
+    }
+
+    // false when there was nothing to redo.
+    pub fn redo(&mut self, renderer: &mut Renderer) -> Result<bool> {
+        self.redo_with(|command| command.apply(renderer))
+    }
+
+    // the bookkeeping of run/undo/redo, without the renderer.
+    fn push(&mut self, command: Box<dyn EditCommand>) {
+        self.undone.clear();
+        if self.done.len() == self.limit {
+            self.done.pop_front();
+        }
+        self.done.push_back(command);
+    }
+
+    fn undo_with<F: FnOnce(&mut dyn EditCommand) -> Result<()>>(&mut self, revert: F) -> Result<bool> {
+        let mut command = match self.done.pop_back() {
+            Some(command) => command,
+            None => return Ok(false),
+        };
+        if let Err(e) = revert(command.as_mut()) {
+            self.done.push_back(command);
+            return Err(e);
+        }
+        self.undone.push(command);
+        Ok(true)
+    }
+
+    fn redo_with<F: FnOnce(&mut dyn EditCommand) -> Result<()>>(&mut self, apply: F) -> Result<bool> {
+        let mut command = match self.undone.pop() {
+            Some(command) => command,
+            None => return Ok(false),
+        };
+        if let Err(e) = apply(command.as_mut()) {
+            self.undone.push(command);
+            return Err(e);
+        }
+        self.done.push_back(command);
+        Ok(true)
+    }
+
+    pub fn undo_name(&self) -> Option<&str> {
+        self.done.back().map(|command| command.name())
+    }
+
+    pub fn redo_name(&self) -> Option<&str> {
+        self.undone.last().map(|command| command.name())
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+// the mesh at `model`/`mesh`, or an error naming what's missing.
+fn mesh(renderer: &Renderer, model: usize, mesh: usize) -> Result<&crate::model::Mesh> {
+    renderer.models().get(model)
+        .context("No model with this index.")?
+        .meshes.get(mesh)
+        .context("No mesh with this index.")
+}
+
+// Transform edit: the offset of a mesh.
+pub struct MoveMesh {
+    model: usize,
+    mesh: usize,
+    from: [f32; 3],
+    to: [f32; 3],
+}
+
+impl MoveMesh {
+    pub fn new(renderer: &Renderer, model: usize, mesh: usize, to: [f32; 3]) -> Self {
+        let from = self::mesh(renderer, model, mesh).map_or([0.0; 3], |mesh| mesh.offset);
+        Self { model, mesh, from, to }
+    }
+}
+
+impl EditCommand for MoveMesh {
+    fn name(&self) -> &str {
+        "Move Mesh"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        mesh(renderer, self.model, self.mesh)?;
+        renderer.set_mesh_offset(self.model, self.mesh, self.to);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        mesh(renderer, self.model, self.mesh)?;
+        renderer.set_mesh_offset(self.model, self.mesh, self.from);
+        Ok(())
+    }
+}
+
+// Material edit: another material of the model for one mesh.
+pub struct SetMeshMaterial {
+    model: usize,
+    mesh: usize,
+    from: usize,
+    to: usize,
+}
+
+impl SetMeshMaterial {
+    pub fn new(renderer: &Renderer, model: usize, mesh: usize, to: usize) -> Self {
+        let from = self::mesh(renderer, model, mesh).map_or(0, |mesh| mesh.material);
+        Self { model, mesh, from, to }
+    }
+}
+
+impl EditCommand for SetMeshMaterial {
+    fn name(&self) -> &str {
+        "Change Material"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_mesh_material(self.model, self.mesh, self.to)
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_mesh_material(self.model, self.mesh, self.from)
+    }
+}
+
+// Material edit: the material every mesh is drawn with, see Renderer::set_material_override().
+pub struct SetMaterialOverride {
+    from: Option<usize>,
+    to: Option<usize>,
+}
+
+impl SetMaterialOverride {
+    pub fn new(renderer: &Renderer, to: Option<usize>) -> Self {
+        Self { from: renderer.material_override(), to }
+    }
+}
+
+impl EditCommand for SetMaterialOverride {
+    fn name(&self) -> &str {
+        "Change Material"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_material_override(self.to);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_material_override(self.from);
+        Ok(())
+    }
+}
+
+pub struct SetInstanceMaterial {
+    model: usize,
+    mesh: Option<usize>,
+    from: Option<usize>,
+    to: Option<usize>,
+}
+
+impl SetInstanceMaterial {
+    pub fn new(renderer: &Renderer, model: usize, mesh: Option<usize>, to: Option<usize>) -> Self {
+        Self { model, mesh, from: renderer.instance_material(model, mesh), to }
+    }
+}
+
+impl EditCommand for SetInstanceMaterial {
+    fn name(&self) -> &str {
+        "Change Instance Material"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_instance_material(self.model, self.mesh, self.to)
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_instance_material(self.model, self.mesh, self.from)
+    }
+}
+
+pub struct RenameMesh {
+    model: usize,
+    mesh: usize,
+    from: String,
+    to: String,
+}
+
+impl RenameMesh {
+    pub fn new(renderer: &Renderer, model: usize, mesh: usize, to: &str) -> Self {
+        let from = self::mesh(renderer, model, mesh).map_or(String::new(), |mesh| mesh.name.clone());
+        Self { model, mesh, from, to: to.to_string() }
+    }
+}
+
+impl EditCommand for RenameMesh {
+    fn name(&self) -> &str {
+        "Rename Mesh"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        mesh(renderer, self.model, self.mesh)?;
+        renderer.set_mesh_name(self.model, self.mesh, &self.to);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        mesh(renderer, self.model, self.mesh)?;
+        renderer.set_mesh_name(self.model, self.mesh, &self.from);
+        Ok(())
+    }
+}
+
+pub struct SetMeshVisible {
+    model: usize,
+    mesh: usize,
+    from: bool,
+    to: bool,
+}
+
+impl SetMeshVisible {
+    pub fn new(renderer: &Renderer, model: usize, mesh: usize, to: bool) -> Self {
+        Self { model, mesh, from: renderer.mesh_visible(model, mesh), to }
+    }
+}
+
+impl EditCommand for SetMeshVisible {
+    fn name(&self) -> &str {
+        if self.to { "Show Mesh" } else { "Hide Mesh" }
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_mesh_visible(self.model, self.mesh, self.to);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_mesh_visible(self.model, self.mesh, self.from);
+        Ok(())
+    }
+}
+
+// Node add: loads the model the first time, later redos put the same one back.
+pub struct AddModel {
+    path: PathBuf,
+    index: Option<usize>,
+    removed: Option<RemovedModel>,
+}
+
+impl AddModel {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into(), index: None, removed: None }
+    }
+}
+
+impl EditCommand for AddModel {
+    fn name(&self) -> &str {
+        "Add Model"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let index = match (self.removed.take(), self.index) {
+            (Some(removed), Some(index)) => renderer.insert_model(index, removed)?,
+            _ => renderer.load_model(&self.path)?,
+        };
+        self.index = Some(index);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let index = self.index.context("The model wasn't added.")?;
+        self.removed = Some(renderer.remove_model(index)?);
+        Ok(())
+    }
+}
+
+// Node copy, see Renderer::duplicate_model(). Redos put the same copy back.
+pub struct DuplicateModel {
+    source: usize,
+    offset: [f32; 3],
+    index: Option<usize>,
+    removed: Option<RemovedModel>,
+}
+
+impl DuplicateModel {
+    pub fn new(source: usize, offset: [f32; 3]) -> Self {
+        Self { source, offset, index: None, removed: None }
+    }
+}
+
+impl EditCommand for DuplicateModel {
+    fn name(&self) -> &str {
+        "Duplicate Model"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let index = match (self.removed.take(), self.index) {
+            (Some(removed), Some(index)) => renderer.insert_model(index, removed)?,
+            _ => renderer.duplicate_model(self.source, self.offset)?,
+        };
+        self.index = Some(index);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let index = self.index.context("The model wasn't duplicated.")?;
+        self.removed = Some(renderer.remove_model(index)?);
+        Ok(())
+    }
+}
+
+// Node remove: keeps the model to put it back at the same index.
+pub struct RemoveModel {
+    index: usize,
+    removed: Option<RemovedModel>,
+}
+
+impl RemoveModel {
+    pub fn new(index: usize) -> Self {
+        Self { index, removed: None }
+    }
+}
+
+impl EditCommand for RemoveModel {
+    fn name(&self) -> &str {
+        "Remove Model"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        self.removed = Some(renderer.remove_model(self.index)?);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let removed = self.removed.take().context("The model wasn't removed.")?;
+        renderer.insert_model(self.index, removed)?;
+        Ok(())
+    }
+}
+
+// Light edit: replaces the light at `index`.
+pub struct SetLight {
+    index: usize,
+    from: Option<Light>,
+    to: Light,
+}
+
+impl SetLight {
+    pub fn new(renderer: &Renderer, index: usize, to: Light) -> Self {
+        Self { index, from: renderer.lights().get(index).copied(), to }
+    }
+}
+
+impl EditCommand for SetLight {
+    fn name(&self) -> &str {
+        "Change Light"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        self.from.context("No light with this index.")?;
+        renderer.set_light(self.index, self.to);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        renderer.set_light(self.index, self.from.context("No light with this index.")?);
+        Ok(())
+    }
+}
+
+pub struct AddLight {
+    light: Light,
+    index: Option<usize>,
+}
+
+impl AddLight {
+    pub fn new(light: Light) -> Self {
+        Self { light, index: None }
+    }
+}
+
+impl EditCommand for AddLight {
+    fn name(&self) -> &str {
+        "Add Light"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let index = match self.index {
+            Some(index) => {
+                renderer.insert_light(index, self.light);
+                index
+            }
+            None => renderer.add_light(self.light),
+        };
+        self.index = Some(index);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let index = self.index.context("The light wasn't added.")?;
+        renderer.remove_light(index).context("No light with this index.")?;
+        Ok(())
+    }
+}
+
+pub struct RemoveLight {
+    index: usize,
+    light: Option<Light>,
+}
+
+impl RemoveLight {
+    pub fn new(index: usize) -> Self {
+        Self { index, light: None }
+    }
+}
+
+impl EditCommand for RemoveLight {
+    fn name(&self) -> &str {
+        "Remove Light"
+    }
+
+    fn apply(&mut self, renderer: &mut Renderer) -> Result<()> {
+        self.light = Some(renderer.remove_light(self.index).context("No light with this index.")?);
+        Ok(())
+    }
+
+    fn revert(&mut self, renderer: &mut Renderer) -> Result<()> {
+        let light = self.light.take().context("The light wasn't removed.")?;
+        renderer.insert_light(self.index, light);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an edit that only has a name, the tests stand in for the renderer.
+    struct Named(String);
+
+    impl EditCommand for Named {
+        fn name(&self) -> &str {
+            &self.0
+        }
+
+        fn apply(&mut self, _: &mut Renderer) -> Result<()> {
+            unreachable!()
+        }
+
+        fn revert(&mut self, _: &mut Renderer) -> Result<()> {
+            unreachable!()
+        }
+    }
+
+    fn push(stack: &mut UndoStack, name: &str) {
+        stack.push(Box::new(Named(name.to_string())));
+    }
+
+    fn undo(stack: &mut UndoStack) -> Option<String> {
+        let mut name = None;
+        stack.undo_with(|command| {
+            name = Some(command.name().to_string());
+            Ok(())
+        }).unwrap();
+        name
+    }
+
+    fn redo(stack: &mut UndoStack) -> Option<String> {
+        let mut name = None;
+        stack.redo_with(|command| {
+            name = Some(command.name().to_string());
+            Ok(())
+        }).unwrap();
+        name
+    }
+
+    #[test]
+    fn undo_and_redo_walk_back_and_forth() {
+        let mut stack = UndoStack::new(10);
+        assert_eq!(undo(&mut stack), None);
+        push(&mut stack, "a");
+        push(&mut stack, "b");
+        assert_eq!(stack.undo_name(), Some("b"));
+        assert_eq!(undo(&mut stack).as_deref(), Some("b"));
+        assert_eq!(stack.redo_name(), Some("b"));
+        assert_eq!(undo(&mut stack).as_deref(), Some("a"));
+        assert_eq!(undo(&mut stack), None);
+        assert_eq!(redo(&mut stack).as_deref(), Some("a"));
+        assert_eq!(stack.undo_name(), Some("a"));
+        assert_eq!(stack.redo_name(), Some("b"));
+
+        // a new edit drops what could have been redone.
+        push(&mut stack, "c");
+        assert_eq!(stack.redo_name(), None);
+        assert_eq!(redo(&mut stack), None);
+        assert_eq!(undo(&mut stack).as_deref(), Some("c"));
+        assert_eq!(undo(&mut stack).as_deref(), Some("a"));
+
+        stack.clear();
+        assert_eq!(stack.undo_name(), None);
+        assert_eq!(stack.redo_name(), None);
+    }
+
+    #[test]
+    fn the_oldest_edits_are_forgotten() {
+        let mut stack = UndoStack::new(2);
+        for name in ["a", "b", "c"] {
+            push(&mut stack, name);
+        }
+        assert_eq!(undo(&mut stack).as_deref(), Some("c"));
+        assert_eq!(undo(&mut stack).as_deref(), Some("b"));
+        assert_eq!(undo(&mut stack), None);
+
+        // a limit of 0 still keeps the last edit.
+        let mut stack = UndoStack::new(0);
+        push(&mut stack, "a");
+        assert_eq!(stack.undo_name(), Some("a"));
+    }
+
+    #[test]
+    fn failed_steps_stay_where_they_were() {
+        let mut stack = UndoStack::new(10);
+        push(&mut stack, "a");
+        assert!(stack.undo_with(|_| anyhow::bail!("revert failed")).is_err());
+        assert_eq!(stack.undo_name(), Some("a"));
+        assert_eq!(stack.redo_name(), None);
+
+        undo(&mut stack);
+        assert!(stack.redo_with(|_| anyhow::bail!("apply failed")).is_err());
+        assert_eq!(stack.undo_name(), None);
+        assert_eq!(stack.redo_name(), Some("a"));
+    }
+}
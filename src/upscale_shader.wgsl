@@ -0,0 +1,95 @@
+// Dynamic resolution: the scene was drawn into the top left part of
+// the offscreen target, this stretches that part over the whole window.
+// With supersampling the part is bigger than the window and gets shrunk.
+
+struct UpscaleUniform {
+    // size of the drawn part / size of the target.
+    uv_scale: vec2<f32>,
+    // one texel of the target, in uv.
+    texel: vec2<f32>,
+    // 0..1, only for the sharpening filter.
+    sharpness: f32,
+    // 0 = bilinear, 1 = bilinear + contrast adaptive sharpening.
+    upscale_filter: u32,
+    // one pixel of the window, in uv of the target.
+    footprint: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> upscale: UpscaleUniform;
+
+@group(0) @binding(1)
+var tex_scene: texture_2d<f32>;
+
+@group(0) @binding(2)
+var sampler_scene: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// one triangle covering the screen.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.uv = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+// sample the drawn part only, the rest of the target is stale.
+fn sample_scene(uv: vec2<f32>) -> vec4<f32> {
+    let limit = upscale.uv_scale - upscale.texel * 0.5;
+    let clamped = clamp(uv * upscale.uv_scale, upscale.texel * 0.5, limit);
+    return textureSampleLevel(tex_scene, sampler_scene, clamped, 0.0);
+}
+
+fn scene(uv: vec2<f32>) -> vec3<f32> {
+    return sample_scene(uv).rgb;
+}
+
+// the texels under the pixel at `uv` averaged, four bilinear taps a
+// quarter pixel around the center: exactly the 2x2 texels at 2x, close
+// to a box filter in between.
+fn downsample(uv: vec2<f32>) -> vec4<f32> {
+    let center = clamp(uv * upscale.uv_scale, upscale.texel * 0.5, upscale.uv_scale - upscale.texel * 0.5);
+    let offset = upscale.footprint * 0.25;
+    let a = textureSampleLevel(tex_scene, sampler_scene, center + vec2<f32>(-offset.x, -offset.y), 0.0);
+    let b = textureSampleLevel(tex_scene, sampler_scene, center + vec2<f32>(offset.x, -offset.y), 0.0);
+    let c = textureSampleLevel(tex_scene, sampler_scene, center + vec2<f32>(-offset.x, offset.y), 0.0);
+    let d = textureSampleLevel(tex_scene, sampler_scene, center + vec2<f32>(offset.x, offset.y), 0.0);
+    return (a + b + c + d) * 0.25;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // more than a texel per pixel: supersampled, nothing to sharpen.
+    if (upscale.footprint.x > upscale.texel.x * 1.01) {
+        return downsample(in.uv);
+    }
+
+    // the alpha stays as drawn, for transparent windows.
+    let color = sample_scene(in.uv);
+    let center = color.rgb;
+    if (upscale.upscale_filter == 0u) {
+        return color;
+    }
+
+    // neighbours one scene texel away.
+    let step = upscale.texel / upscale.uv_scale;
+    let north = scene(in.uv - vec2<f32>(0.0, step.y));
+    let south = scene(in.uv + vec2<f32>(0.0, step.y));
+    let west = scene(in.uv - vec2<f32>(step.x, 0.0));
+    let east = scene(in.uv + vec2<f32>(step.x, 0.0));
+
+    // sharpen less where the contrast is high already.
+    let lowest = min(center, min(min(north, south), min(west, east)));
+    let highest = max(center, max(max(north, south), max(west, east)));
+    let amount = sqrt(clamp(min(lowest, vec3<f32>(2.0) - highest) / max(highest, vec3<f32>(0.0001)), vec3<f32>(0.0), vec3<f32>(1.0)));
+    let weight = amount * (-1.0 / mix(8.0, 5.0, upscale.sharpness));
+
+    let sharpened = (center + (north + south + west + east) * weight) / (vec3<f32>(1.0) + weight * 4.0);
+    return vec4<f32>(clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);
+}
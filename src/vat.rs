@@ -0,0 +1,205 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::budget;
+
+/*
+    Vertex animation textures (VAT): simulations baked in a DCC tool
+    (cloth, destruction, fluids) played back without a skeleton.
+
+    The texture holds, for every frame, the offset of every vertex from
+    where the mesh has it (rgb, 32 bit floats). The vertices of a frame
+    are rows of the texture: vertex i of frame f is the texel
+    (i % width, f * rows_per_frame + i / width), so a frame of a mesh
+    with more vertices than the texture is wide takes several rows.
+
+    A material with a vertex animation moves the vertices of every mesh
+    drawn with it in the vertex shader, blending the two frames around
+    the current time (the per frame uniform's, see frame.rs). The
+    vertices are found by their index in the vertex buffer, the mesh has
+    to keep the vertex order it was baked with: no welding on import and
+    no terrain subdivision. The normals aren't animated, and picking and
+    the bounds see the mesh as it is in the file.
+
+    Files are images as the exporters write them, Radiance .hdr (rgb
+    floats) or 8 / 16 bit png, loaded with the number of vertices of the
+    mesh they were baked for. Neither can hold negative values, so the
+    exporters remap the offsets into 0..1 and write the bounds they were
+    remapped from: with `range` (min, max) a texel value of 0 is an
+    offset of min and 1 one of max.
+
+    usage:
+        let range = Some(([-1.2, -0.1, -0.9], [1.3, 2.0, 0.8]));
+        let vat = VertexAnimation::load(renderer.device(), renderer.queue(), "cloth.png", 4096, 30.0, range)?;
+        renderer.set_vertex_animation(model, material, Some(vat))?;
+*/
+
+// the format of the texture, loaded with textureLoad(), it needn't be filterable.
+pub const VAT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+// textures that are created from offsets are this wide at most.
+pub const MAX_WIDTH: u32 = 4096;
+
+pub struct VertexAnimation {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    // of the mesh it was baked for.
+    pub vertices: u32,
+    pub frames: u32,
+    pub fps: f32,
+    // after the last frame it starts again, otherwise it stops there.
+    pub looping: bool,
+    // time of the per frame uniform it starts at, set by Renderer::set_vertex_animation().
+    pub start: f32,
+    width: u32,
+}
+
+impl VertexAnimation {
+    // `offsets` of every vertex, frame by frame.
+    pub fn from_offsets(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        offsets: &[Vec<[f32; 3]>],
+        fps: f32,
+        label: &str,
+    ) -> Result<Self> {
+        let vertices = offsets.first().map_or(0, |frame| frame.len()) as u32;
+        if vertices == 0 {
+            bail!("A vertex animation needs frames with vertices.");
+        }
+        if offsets.iter().any(|frame| frame.len() as u32 != vertices) {
+            bail!("Every frame of a vertex animation needs {} vertices.", vertices);
+        }
+        let width = vertices.min(MAX_WIDTH);
+        let rows = vertices.div_ceil(width);
+        let mut texels = vec![[0.0f32; 4]; (width * rows) as usize * offsets.len()];
+        for (frame, frame_offsets) in offsets.iter().enumerate() {
+            let first = frame * (width * rows) as usize;
+            for (vertex, offset) in frame_offsets.iter().enumerate() {
+                texels[first + vertex] = [offset[0], offset[1], offset[2], 0.0];
+            }
+        }
+        Self::from_texels(device, queue, &texels, width, rows * offsets.len() as u32, vertices, fps, label)
+    }
+
+    // `texels` of a `width` x `height` texture in the layout above.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_texels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texels: &[[f32; 4]],
+        width: u32,
+        height: u32,
+        vertices: u32,
+        fps: f32,
+        label: &str,
+    ) -> Result<Self> {
+        if width == 0 || vertices == 0 || texels.len() != (width * height) as usize {
+            bail!("{} texels are no {} x {} vertex animation.", texels.len(), width, height);
+        }
+        let frames = height / vertices.div_ceil(width);
+        if frames == 0 {
+            bail!("A {} x {} texture holds no frame of {} vertices.", width, height, vertices);
+        }
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: VAT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            texture,
+            view,
+            vertices,
+            frames,
+            fps,
+            looping: true,
+            start: 0.0,
+            width,
+        })
+    }
+
+    // an image baked for a mesh of `vertices` vertices, with the offsets
+    // remapped from `range` if there is one, see above.
+    pub fn load<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+        vertices: u32,
+        fps: f32,
+        range: Option<([f32; 3], [f32; 3])>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let hdr = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        let (width, height, pixels) = if hdr {
+            let file = std::fs::File::open(path).with_context(|| format!("Unable to open vertex animation {:?}.", path))?;
+            let decoder = image::codecs::hdr::HdrDecoder::new(std::io::BufReader::new(file))
+                .with_context(|| format!("{:?} is no .hdr image.", path))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()
+                .with_context(|| format!("Unable to read vertex animation {:?}.", path))?;
+            (metadata.width, metadata.height, pixels.iter().map(|pixel| pixel.0).collect::<Vec<[f32; 3]>>())
+        } else {
+            let image = image::open(path)
+                .with_context(|| format!("Unable to read vertex animation {:?}.", path))?
+                .to_rgb16();
+            let normalize = |value: u16| value as f32 / u16::MAX as f32;
+            let pixels = image.pixels().map(|pixel| [normalize(pixel[0]), normalize(pixel[1]), normalize(pixel[2])]).collect();
+            (image.width(), image.height(), pixels)
+        };
+        let texels: Vec<[f32; 4]> = pixels
+            .iter()
+            .map(|pixel| {
+                let mut texel = [pixel[0], pixel[1], pixel[2], 0.0];
+                if let Some((min, max)) = range {
+                    for axis in 0..3 {
+                        texel[axis] = min[axis] + pixel[axis] * (max[axis] - min[axis]);
+                    }
+                }
+                texel
+            })
+            .collect();
+        Self::from_texels(device, queue, &texels, width, height, vertices, fps, &path.to_string_lossy())
+            .with_context(|| format!("Unable to load vertex animation {:?}.", path))
+    }
+
+    // rows of the texture one frame takes.
+    pub fn rows_per_frame(&self) -> u32 {
+        self.vertices.div_ceil(self.width)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 / self.fps.max(f32::EPSILON)
+    }
+
+    pub fn memory_size(&self) -> u64 {
+        budget::texture_size(&self.texture)
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::Instant;
+
+use crate::particles::{EmitterSettings, ParticleSystem};
+use crate::plugin::{FrameTargets, PluginContext, RenderPlugin};
+
+/*
+    Rain and snow.
+
+    A layer of particles in a box around the camera, the box moves
+    with the camera so it always looks like it's raining/snowing
+    everywhere. Built on the particle system: wind and density
+    map onto the emitter and the system wind.
+
+    Particles fall at `fall_speed` once the drag has caught up:
+    the gravity is set to fall_speed * drag, which makes that the
+    speed where gravity and drag cancel out.
+
+    wetness() rises while it rains and dries up again afterwards.
+    The materials have no roughness to lower yet, so it's up to the
+    application what wet surfaces look like (darker, glossier).
+
+    usage:
+        let mut weather = Weather::new(WeatherSettings::rain());
+        weather.set_wind([2.0, 0.0, 0.5]);
+        renderer.add_plugin(weather);
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WeatherKind {
+    Rain,
+    Snow,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WeatherSettings {
+    pub kind: WeatherKind,
+    // particles per second per square unit of ground.
+    pub density: f32,
+    pub wind: [f32; 3],
+    // half width of the box around the camera and its height.
+    pub radius: f32,
+    pub height: f32,
+    pub fall_speed: f32,
+    // how quickly the particles pick up the wind.
+    pub drag: f32,
+    pub size: f32,
+    pub color: [f32; 4],
+    // seconds to get fully wet while raining / to dry afterwards.
+    pub wetting_time: f32,
+    pub drying_time: f32,
+}
+
+impl WeatherSettings {
+    pub fn rain() -> Self {
+        Self {
+            kind: WeatherKind::Rain,
+            density: 40.0,
+            wind: [0.0; 3],
+            radius: 8.0,
+            height: 8.0,
+            fall_speed: 9.0,
+            drag: 2.0,
+            size: 0.01,
+            color: [0.7, 0.75, 0.85, 0.35],
+            wetting_time: 20.0,
+            drying_time: 120.0,
+        }
+    }
+
+    pub fn snow() -> Self {
+        Self {
+            kind: WeatherKind::Snow,
+            density: 6.0,
+            wind: [0.0; 3],
+            radius: 8.0,
+            height: 6.0,
+            fall_speed: 1.0,
+            drag: 1.5,
+            size: 0.04,
+            color: [1.0, 1.0, 1.0, 0.8],
+            wetting_time: 20.0,
+            drying_time: 120.0,
+        }
+    }
+
+    fn emitter(&self, eye: [f32; 3]) -> EmitterSettings {
+        let rain = self.kind == WeatherKind::Rain;
+        let lifetime = self.height / self.fall_speed.max(0.01);
+        let area = (2.0 * self.radius) * (2.0 * self.radius);
+        let rate = self.density.max(0.0) * area;
+
+        EmitterSettings {
+            // spawning all over the box fills it right away.
+            position: [eye[0], eye[1] + self.height * 0.25, eye[2]],
+            extent: [self.radius, self.height * 0.5, self.radius],
+            rate,
+            lifetime,
+            lifetime_jitter: 0.1,
+            direction: [0.0, -1.0, 0.0],
+            // snow tumbles, rain comes straight down.
+            cone_angle: if rain { 2.0 } else { 40.0 },
+            speed: self.fall_speed,
+            speed_jitter: if rain { 0.1 } else { 0.5 },
+            gravity: [0.0, -self.fall_speed * self.drag, 0.0],
+            start_size: self.size,
+            end_size: self.size,
+            start_color: self.color,
+            end_color: self.color,
+            // drops and flakes stay where they hit the scene.
+            restitution: 0.0,
+            softness: 0.1,
+            stretch: if rain { 0.02 } else { 0.0 },
+            max_particles: ((rate * lifetime * 1.2).ceil() as u32).max(1),
+        }
+    }
+}
+
+pub struct Weather {
+    settings: WeatherSettings,
+    particles: ParticleSystem,
+    enabled: bool,
+    wetness: f32,
+    last_update: Option<Instant>,
+}
+
+impl Weather {
+    pub fn new(settings: WeatherSettings) -> Self {
+        let mut particles = ParticleSystem::new();
+        particles.add_emitter(settings.emitter([0.0; 3]));
+        particles.set_wind(settings.wind, settings.drag);
+        Self {
+            settings,
+            particles,
+            enabled: true,
+            wetness: 0.0,
+            last_update: None,
+        }
+    }
+
+    pub fn settings(&self) -> &WeatherSettings {
+        &self.settings
+    }
+
+    // e.g. switching from rain to snow, takes effect with the next frame.
+    pub fn set_settings(&mut self, settings: WeatherSettings) {
+        self.settings = settings;
+    }
+
+    pub fn set_density(&mut self, density: f32) {
+        self.settings.density = density;
+    }
+
+    pub fn set_wind(&mut self, wind: [f32; 3]) {
+        self.settings.wind = wind;
+    }
+
+    // stops spawning, the particles in the air still fall down.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // 0 (dry) .. 1 (soaked), only rain makes surfaces wet.
+    pub fn wetness(&self) -> f32 {
+        self.wetness
+    }
+}
+
+impl RenderPlugin for Weather {
+    fn name(&self) -> &str {
+        "Weather"
+    }
+
+    fn setup(&mut self, ctx: &PluginContext) {
+        self.particles.setup(ctx);
+    }
+
+    fn resize(&mut self, ctx: &PluginContext, width: u32, height: u32) {
+        self.particles.resize(ctx, width, height);
+    }
+
+    fn update(&mut self, ctx: &PluginContext) {
+        let now = Instant::now();
+        let dt = self.last_update.map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last_update = Some(now);
+
+        let raining = self.enabled && self.settings.kind == WeatherKind::Rain && self.settings.density > 0.0;
+        self.wetness = if raining {
+            self.wetness + dt / self.settings.wetting_time.max(f32::EPSILON)
+        } else {
+            self.wetness - dt / self.settings.drying_time.max(f32::EPSILON)
+        }.clamp(0.0, 1.0);
+
+        let eye = ctx.camera.eye();
+        let mut emitter = self.settings.emitter([eye.x, eye.y, eye.z]);
+        // the ring size still follows the density, so switching
+        // off doesn't drop what is in the air.
+        if !self.enabled {
+            emitter.rate = 0.0;
+        }
+        if let Some(settings) = self.particles.emitter_mut(0) {
+            *settings = emitter;
+        }
+        self.particles.set_wind(self.settings.wind, self.settings.drag);
+        self.particles.update(ctx);
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PluginContext,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &FrameTargets,
+    ) {
+        self.particles.encode(ctx, encoder, targets);
+    }
+}
@@ -0,0 +1,75 @@
+/*
+    Wind sway for vegetation: grass, bushes and trees that move in the
+    wind, done in the vertex shader, without any animation data.
+
+    The wind is global, a direction and a speed in the per frame uniform
+    (see frame.rs, Renderer::set_wind()). A material with a WindSway moves
+    the vertices of its meshes along the wind, as far as its `strength`,
+    swinging back and forth `frequency` times a second. The phase of the
+    swing comes from where the vertex is in the world, so neighbouring
+    plants sway nearly together and the gusts run across a field instead
+    of the whole field moving as one. Every instance of the same mesh
+    (prefab instances, the copies of a scattered field) gets its own
+    phase that way.
+
+    The mask says how much a vertex sways, so the roots stay in the
+    ground:
+    - VertexColor: by the red vertex color, painted 0 at the roots and 1
+      at the tips, as foliage exporters do,
+    - Uv: by 1 - v, for grass cards whose texture has the tips at the top,
+    - Uniform: everything alike, for leaves that float free.
+
+    The normals aren't bent, and picking and the bounds see the plant
+    standing still.
+
+    usage:
+        renderer.set_wind([3.0, 0.0, 1.0]);
+        renderer.set_wind_sway(grass, 0, Some(WindSway::grass()))?;
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindMask {
+    Uniform,
+    VertexColor,
+    Uv,
+}
+
+impl WindMask {
+    // value of the mask in the shader, 0 is no sway.
+    pub fn as_u32(self) -> u32 {
+        match self {
+            WindMask::Uniform => 1,
+            WindMask::VertexColor => 2,
+            WindMask::Uv => 3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WindSway {
+    // how far a fully swaying vertex moves per unit of wind speed.
+    pub strength: f32,
+    // swings per second.
+    pub frequency: f32,
+    pub mask: WindMask,
+}
+
+impl WindSway {
+    // soft, quick blades, the tips painted by the uvs.
+    pub fn grass() -> Self {
+        Self {
+            strength: 0.05,
+            frequency: 1.2,
+            mask: WindMask::Uv,
+        }
+    }
+
+    // stiff and slow, the trunk kept still by the vertex colors.
+    pub fn tree() -> Self {
+        Self {
+            strength: 0.02,
+            frequency: 0.4,
+            mask: WindMask::VertexColor,
+        }
+    }
+}
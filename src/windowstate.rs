@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::{Fullscreen, Window};
+
+/*
+    What the viewer looked like when it was closed, so the next start
+    looks the same: where the window was and how big, on which monitor,
+    maximized or fullscreen, which overlay panels were shown, in which
+    language (see locale.rs), colors (see palette.rs) and size (see
+    overlay.rs) and which models were open.
+
+    It's kept per user, in the config folder (see path()), as the same
+    kind of text as the camera controls:
+
+        # window
+        position 120 80
+        size 1600 900
+        monitor DELL U2719D
+        maximized false
+        fullscreen false
+        stats_hud true
+        locale de
+        palette okabe-ito
+        ui_scale 1.5
+        model /home/me/terrain/rocks.obj
+        model http://assets.local/tree.obj
+
+    The position and size are the ones of the normal window: while it's
+    maximized or fullscreen they aren't updated, so leaving that after a
+    restart goes back to where it was. A monitor that isn't connected any
+    more is ignored and the window stays where the platform puts it.
+
+    usage:
+        let mut state = WindowState::load_or_default();
+        let renderer = RendererBuilder::new().size(...).build(&event_loop)?;
+        state.apply(renderer.window());
+        ...
+        // on exit
+        state.capture(renderer.window());
+        state.save(WindowState::path().unwrap())?;
+*/
+
+// folder in the config folder, and the file in it.
+const CONFIG_FOLDER: &str = "zhneeshyx";
+const FILE_NAME: &str = "window.cfg";
+// models remembered, the most recent first.
+pub const MAX_MODELS: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowState {
+    // outer position and inner size of the normal window, in physical pixels.
+    pub position: Option<[i32; 2]>,
+    pub size: Option<[u32; 2]>,
+    // name of the monitor the window was on.
+    pub monitor: Option<String>,
+    pub maximized: bool,
+    // borderless on `monitor`.
+    pub fullscreen: bool,
+    // the frame time panel (F1), see stats.rs.
+    pub stats_hud: bool,
+    // of the overlay text, a built in language or a locale file.
+    pub locale: Option<String>,
+    // debug colors, one of palette::PRESETS.
+    pub palette: Option<String>,
+    // Overlay::ui_scale(), on top of the scale factor of the monitor.
+    pub ui_scale: Option<f32>,
+    // models open when the viewer was closed, see add_model().
+    pub models: Vec<PathBuf>,
+}
+
+impl WindowState {
+    // $XDG_CONFIG_HOME/zhneeshyx/window.cfg (~/.config without it), or
+    // %APPDATA%\zhneeshyx\window.cfg on windows. None without a home.
+    pub fn path() -> Option<PathBuf> {
+        let env = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let config = if cfg!(windows) {
+            env("APPDATA")
+        } else {
+            env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|home| home.join(".config")))
+        };
+        config.map(|config| config.join(CONFIG_FOLDER).join(FILE_NAME))
+    }
+
+    // the state of the last session, the default one on the first start
+    // or if the file can't be read.
+    pub fn load_or_default() -> Self {
+        match Self::path() {
+            Some(path) if path.is_file() => Self::load(&path).unwrap_or_else(|e| {
+                log::error!("{:?}", e);
+                Self::default()
+            }),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read the window state {:?}.", path))?;
+        Self::parse(&text).with_context(|| format!("Unable to parse the window state {:?}.", path))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(folder)
+                .with_context(|| format!("Unable to create the config folder {:?}.", folder))?;
+        }
+        std::fs::write(path, self.to_text())
+            .with_context(|| format!("Unable to write the window state {:?}.", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut state = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // monitor names and paths may have spaces.
+            let (key, value) = match line.split_once(char::is_whitespace) {
+                Some((key, value)) => (key, value.trim()),
+                None => bail!("Line {}: expected a setting and its value.", number + 1),
+            };
+            let numbers = || -> Result<[i64; 2]> {
+                let values: Vec<i64> = value.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+                match values[..] {
+                    [x, y] => Ok([x, y]),
+                    _ => bail!("Line {}: {} takes two whole numbers.", number + 1, key),
+                }
+            };
+            let flag = || value.parse::<bool>()
+                .ok()
+                .with_context(|| format!("Line {}: {} is true or false.", number + 1, key));
+            match key {
+                "position" => {
+                    let [x, y] = numbers()?;
+                    state.position = Some([x as i32, y as i32]);
+                }
+                "size" => {
+                    let [width, height] = numbers()?;
+                    if width <= 0 || height <= 0 {
+                        bail!("Line {}: the size has to be positive.", number + 1);
+                    }
+                    state.size = Some([width as u32, height as u32]);
+                }
+                "monitor" => state.monitor = Some(value.to_string()),
+                "maximized" => state.maximized = flag()?,
+                "fullscreen" => state.fullscreen = flag()?,
+                "stats_hud" => state.stats_hud = flag()?,
+                "locale" => state.locale = Some(value.to_string()),
+                "palette" => state.palette = Some(value.to_string()),
+                "ui_scale" => {
+                    let ui_scale = value.parse::<f32>()
+                        .with_context(|| format!("Line {}: ui_scale takes a number.", number + 1))?;
+                    state.ui_scale = Some(ui_scale);
+                }
+                "model" => state.models.push(PathBuf::from(value)),
+                _ => bail!("Line {}: unknown setting {:?}.", number + 1, key),
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# window\n");
+        if let Some([x, y]) = self.position {
+            text.push_str(&format!("position {} {}\n", x, y));
+        }
+        if let Some([width, height]) = self.size {
+            text.push_str(&format!("size {} {}\n", width, height));
+        }
+        if let Some(monitor) = &self.monitor {
+            text.push_str(&format!("monitor {}\n", monitor));
+        }
+        text.push_str(&format!(
+            "maximized {}\nfullscreen {}\nstats_hud {}\n",
+            self.maximized, self.fullscreen, self.stats_hud,
+        ));
+        if let Some(locale) = &self.locale {
+            text.push_str(&format!("locale {}\n", locale));
+        }
+        if let Some(palette) = &self.palette {
+            text.push_str(&format!("palette {}\n", palette));
+        }
+        if let Some(ui_scale) = self.ui_scale {
+            text.push_str(&format!("ui_scale {}\n", ui_scale));
+        }
+        for model in &self.models {
+            text.push_str(&format!("model {}\n", model.display()));
+        }
+        text
+    }
+
+    // Remembers `path` as opened, in front of the others.
+    pub fn add_model(&mut self, path: &Path) {
+        self.models.retain(|model| model != path);
+        self.models.insert(0, path.to_path_buf());
+        self.models.truncate(MAX_MODELS);
+    }
+
+    // Takes the geometry of `window`, see above for maximized / fullscreen.
+    pub fn capture(&mut self, window: &Window) {
+        self.maximized = window.is_maximized();
+        self.fullscreen = window.fullscreen().is_some();
+        self.monitor = window.current_monitor().and_then(|monitor| monitor.name()).or_else(|| self.monitor.take());
+        if self.maximized || self.fullscreen || window.is_minimized() == Some(true) {
+            return;
+        }
+        if let Ok(position) = window.outer_position() {
+            self.position = Some([position.x, position.y]);
+        }
+        let size = window.inner_size();
+        if size.width > 0 && size.height > 0 {
+            self.size = Some([size.width, size.height]);
+        }
+    }
+
+    // Puts `window` where it was. The size is best given to the
+    // RendererBuilder already, it's set here as well.
+    pub fn apply(&self, window: &Window) {
+        let monitor = self.monitor.as_ref().and_then(|name| {
+            window.available_monitors().find(|monitor| monitor.name().as_ref() == Some(name))
+        });
+        if let Some([width, height]) = self.size {
+            window.set_inner_size(PhysicalSize::new(width, height));
+        }
+        // only onto a monitor that is still there.
+        if let Some([x, y]) = self.position {
+            let on_screen = window.available_monitors().any(|monitor| {
+                let (origin, size) = (monitor.position(), monitor.size());
+                x >= origin.x && y >= origin.y
+                    && x < origin.x + size.width as i32 && y < origin.y + size.height as i32
+            });
+            if on_screen && (self.monitor.is_none() || monitor.is_some()) {
+                window.set_outer_position(PhysicalPosition::new(x, y));
+            }
+        }
+        if self.maximized {
+            window.set_maximized(true);
+        }
+        if self.fullscreen {
+            window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        }
+    }
+}